@@ -0,0 +1,60 @@
+// Embeds the git commit and the time of the build into the agent, so that the
+// build running on each host can be told apart. The commit is read from the
+// `GIT_COMMIT` environment variable if set, e.g. when building from a source
+// archive, and from git otherwise. The build time honours `SOURCE_DATE_EPOCH`
+// for reproducible builds.
+use std::{
+    env,
+    process::Command,
+    time::{
+        SystemTime,
+        UNIX_EPOCH,
+    },
+};
+
+fn main() {
+    println!("cargo:rerun-if-env-changed=GIT_COMMIT");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/index");
+
+    let git_commit = env::var("GIT_COMMIT").ok().or_else(git_commit);
+    println!(
+        "cargo:rustc-env=PYTH_AGENT_GIT_COMMIT={}",
+        git_commit.as_deref().unwrap_or("unknown")
+    );
+
+    let build_timestamp = env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse::<u64>().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_secs())
+                .unwrap_or_default()
+        });
+    println!(
+        "cargo:rustc-env=PYTH_AGENT_BUILD_TIMESTAMP={}",
+        build_timestamp
+    );
+}
+
+/// The commit checked out, suffixed with "-dirty" if the tree has changes
+fn git_commit() -> Option<String> {
+    let git = |args: &[&str]| {
+        Command::new("git")
+            .args(args)
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    };
+    let commit = git(&["rev-parse", "HEAD"])?;
+    let dirty = git(&["status", "--porcelain", "--untracked-files=no"])
+        .map_or(false, |status| !status.is_empty());
+    Some(if dirty {
+        format!("{}-dirty", commit)
+    } else {
+        commit
+    })
+}
@@ -0,0 +1,370 @@
+// Reference client of the pythd API, and load generator against the agent. It connects
+// to the API over websocket, looks up the price accounts of the symbols to publish with
+// `get_all_products`, and publishes synthetic prices for them with `update_price` at
+// the configured rate, each price following a random walk from its current aggregate.
+// The acceptance latency, between sending an update and receiving its response, is
+// reported periodically and at the end of the run:
+//
+//     cargo run --release --example pyth-agent-bench -- \
+//         --symbols Crypto.BTC/USD,Crypto.ETH/USD --rate 10 --duration 1m
+//
+// Updates are sent without waiting for the responses of the previous ones, as a
+// publisher should, and matched to their responses by their JRPC id.
+use {
+    anyhow::{
+        bail,
+        Context as _,
+        Result,
+    },
+    clap::Parser,
+    pyth_agent::agent::pythd::api::ProductAccount,
+    rand::Rng,
+    serde_json::{
+        json,
+        Value,
+    },
+    solana_sdk::pubkey::Pubkey,
+    std::{
+        collections::HashMap,
+        mem,
+        str::from_utf8,
+        sync::Arc,
+        time::Duration,
+    },
+    tokio::{
+        net::TcpStream,
+        sync::Mutex,
+        time::{
+            self,
+            Instant,
+        },
+    },
+    tokio_util::compat::{
+        Compat,
+        TokioAsyncReadCompatExt,
+    },
+};
+
+#[derive(Parser, Debug)]
+/// Publish synthetic prices to the pythd API of an agent, and report how fast
+/// they are accepted
+struct Arguments {
+    #[clap(long, default_value = "127.0.0.1:8910")]
+    /// Address of the pythd API
+    address: String,
+
+    #[clap(long)]
+    /// Token of the tenant to publish as, if the API requires one
+    token: Option<String>,
+
+    #[clap(long, value_delimiter = ',')]
+    /// Symbols to publish, e.g. Crypto.BTC/USD,Crypto.ETH/USD. Defaults to all
+    /// the symbols of the agent.
+    symbols: Vec<String>,
+
+    #[clap(long)]
+    /// Publish at most this many symbols
+    max_symbols: Option<usize>,
+
+    #[clap(long, default_value_t = 1.0)]
+    /// Updates per second of each symbol
+    rate: f64,
+
+    #[clap(long, default_value_t = 1)]
+    /// Number of connections the symbols are spread over
+    connections: usize,
+
+    #[clap(long, default_value = "1m", value_parser = humantime::parse_duration)]
+    /// How long to publish for
+    duration: Duration,
+
+    #[clap(long, default_value = "10s", value_parser = humantime::parse_duration)]
+    /// Interval at which the latencies are reported
+    report_interval: Duration,
+}
+
+/// A websocket connection to the pythd API
+struct Connection {
+    sender:   soketto::Sender<Compat<TcpStream>>,
+    receiver: soketto::Receiver<Compat<TcpStream>>,
+}
+
+impl Connection {
+    async fn connect(address: &str, token: Option<&str>) -> Result<Self> {
+        let socket = TcpStream::connect(address)
+            .await
+            .with_context(|| format!("connecting to the pythd API at {}", address))?;
+        let path = match token {
+            Some(token) => format!("/?token={}", token),
+            None => "/".to_string(),
+        };
+        let mut client = soketto::handshake::Client::new(socket.compat(), address, &path);
+        if !matches!(
+            client.handshake().await?,
+            soketto::handshake::ServerResponse::Accepted { .. }
+        ) {
+            bail!("websocket handshake rejected by the pythd API");
+        }
+        let (sender, receiver) = client.into_builder().finish();
+        Ok(Connection { sender, receiver })
+    }
+
+    /// Send a request, and return the result of its response
+    async fn request(&mut self, method: &str, params: Value) -> Result<Value> {
+        send(&mut self.sender, 1, method, params).await?;
+        loop {
+            let mut response = receive(&mut self.receiver).await?;
+            if response["id"] != json!(1) {
+                continue;
+            }
+            if !response["error"].is_null() {
+                bail!("{} failed: {}", method, response["error"]);
+            }
+            return Ok(response["result"].take());
+        }
+    }
+}
+
+async fn send(
+    sender: &mut soketto::Sender<Compat<TcpStream>>,
+    id: u64,
+    method: &str,
+    params: Value,
+) -> Result<()> {
+    let request = json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "method": method,
+        "params": params,
+    });
+    sender.send_text(request.to_string()).await?;
+    sender.flush().await?;
+    Ok(())
+}
+
+/// The next message received, skipping those which are not JSON
+async fn receive(receiver: &mut soketto::Receiver<Compat<TcpStream>>) -> Result<Value> {
+    loop {
+        let mut data = vec![];
+        receiver.receive_data(&mut data).await?;
+        if let Ok(message) = serde_json::from_str(from_utf8(&data)?) {
+            return Ok(message);
+        }
+    }
+}
+
+/// A price published by the benchmark
+#[derive(Clone)]
+struct SyntheticPrice {
+    symbol:  String,
+    account: Pubkey,
+    price:   i64,
+}
+
+impl SyntheticPrice {
+    /// Move the price by up to 0.1%, returning the update to publish
+    fn next(&mut self, rng: &mut impl Rng) -> Value {
+        let step = (self.price.abs() / 1000).max(1);
+        self.price = (self.price + rng.gen_range(-step..=step)).max(1);
+        json!({
+            "account": self.account.to_string(),
+            "price": self.price,
+            "conf": (self.price / 1000).max(1),
+            "status": "trading",
+        })
+    }
+}
+
+/// Outcomes of the updates sent within a period
+#[derive(Default)]
+struct Stats {
+    sent:      u64,
+    accepted:  u64,
+    rejected:  u64,
+    /// Acceptance latencies of the accepted updates
+    latencies: Vec<Duration>,
+}
+
+impl Stats {
+    fn merge(&mut self, other: Stats) {
+        self.sent += other.sent;
+        self.accepted += other.accepted;
+        self.rejected += other.rejected;
+        self.latencies.extend(other.latencies);
+    }
+
+    fn report(&mut self, label: &str, period: Duration) {
+        self.latencies.sort();
+        let percentile = |percentile: f64| -> String {
+            if self.latencies.is_empty() {
+                return "-".to_string();
+            }
+            let index = ((self.latencies.len() - 1) as f64 * percentile).round() as usize;
+            format!("{:.2?}", self.latencies[index])
+        };
+        println!(
+            "{}: sent {} ({:.1}/s), accepted {}, rejected {}, latency p50 {} p90 {} p99 {} max {}",
+            label,
+            self.sent,
+            self.sent as f64 / period.as_secs_f64().max(f64::EPSILON),
+            self.accepted,
+            self.rejected,
+            percentile(0.5),
+            percentile(0.9),
+            percentile(0.99),
+            percentile(1.0),
+        );
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Arguments::parse();
+    if args.rate <= 0.0 || args.connections == 0 {
+        bail!("--rate and --connections must be positive");
+    }
+
+    // Look up the price accounts of the symbols
+    let mut connection = Connection::connect(&args.address, args.token.as_deref()).await?;
+    let products: Vec<ProductAccount> =
+        serde_json::from_value(connection.request("get_all_products", json!({})).await?)?;
+    let mut prices: Vec<SyntheticPrice> = products
+        .into_iter()
+        .filter_map(|product| {
+            let symbol = product.attr_dict.get("symbol")?.clone();
+            let price_account = product.price_accounts.into_iter().next()?;
+            Some(SyntheticPrice {
+                symbol,
+                account: price_account.account,
+                // Unpublished prices start from an arbitrary one
+                price: if price_account.price > 0 {
+                    price_account.price
+                } else {
+                    1_000_000
+                },
+            })
+        })
+        .filter(|price| args.symbols.is_empty() || args.symbols.contains(&price.symbol))
+        .collect();
+    prices.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+    prices.truncate(args.max_symbols.unwrap_or(usize::MAX));
+    if prices.is_empty() {
+        bail!("none of the symbols is listed by the agent");
+    }
+    println!(
+        "publishing {} symbols at {}/s each over {} connections for {}",
+        prices.len(),
+        args.rate,
+        args.connections,
+        humantime::format_duration(args.duration),
+    );
+
+    // Spread the symbols over the connections
+    let stats = Arc::new(Mutex::new(Stats::default()));
+    let deadline = Instant::now() + args.duration;
+    let mut jhs = vec![];
+    for index in 0..args.connections.min(prices.len()) {
+        let prices = prices
+            .iter()
+            .skip(index)
+            .step_by(args.connections)
+            .cloned()
+            .collect();
+        let connection = Connection::connect(&args.address, args.token.as_deref()).await?;
+        jhs.push(tokio::spawn(publish(
+            connection,
+            prices,
+            Duration::from_secs_f64(1.0 / args.rate),
+            deadline,
+            stats.clone(),
+        )));
+    }
+
+    // Report the latencies of each period, then of the whole run
+    let start = Instant::now();
+    let mut total = Stats::default();
+    let mut report_interval = time::interval_at(start + args.report_interval, args.report_interval);
+    while Instant::now() < deadline {
+        tokio::select! {
+            _ = report_interval.tick() => {}
+            _ = time::sleep_until(deadline) => break,
+        }
+        let mut period = mem::take(&mut *stats.lock().await);
+        period.report("last period", args.report_interval);
+        total.merge(period);
+    }
+    for jh in jhs {
+        jh.await??;
+    }
+    total.merge(mem::take(&mut *stats.lock().await));
+    total.report("total", start.elapsed());
+    Ok(())
+}
+
+/// Publish the prices on the connection at each tick until the deadline,
+/// recording the outcome of the updates
+async fn publish(
+    connection: Connection,
+    mut prices: Vec<SyntheticPrice>,
+    tick_interval: Duration,
+    deadline: Instant,
+    stats: Arc<Mutex<Stats>>,
+) -> Result<()> {
+    let Connection {
+        mut sender,
+        receiver,
+    } = connection;
+    // Send time of the updates awaiting their response, by their id
+    let pending = Arc::new(Mutex::new(HashMap::new()));
+    let receiver_jh = tokio::spawn(receive_responses(receiver, pending.clone(), stats.clone()));
+
+    let mut next_id = 0;
+    let mut ticks = time::interval(tick_interval);
+    while ticks.tick().await < deadline {
+        for price in &mut prices {
+            next_id += 1;
+            let update = price.next(&mut rand::thread_rng());
+            pending.lock().await.insert(next_id, Instant::now());
+            send(&mut sender, next_id, "update_price", update).await?;
+            stats.lock().await.sent += 1;
+        }
+    }
+
+    // Wait a little for the responses of the last updates
+    let drain_deadline = Instant::now() + Duration::from_secs(5);
+    while !pending.lock().await.is_empty() && Instant::now() < drain_deadline {
+        if receiver_jh.is_finished() {
+            break;
+        }
+        time::sleep(Duration::from_millis(10)).await;
+    }
+    receiver_jh.abort();
+    Ok(())
+}
+
+/// Match the responses to the pending updates, recording their outcome
+async fn receive_responses(
+    mut receiver: soketto::Receiver<Compat<TcpStream>>,
+    pending: Arc<Mutex<HashMap<u64, Instant>>>,
+    stats: Arc<Mutex<Stats>>,
+) -> Result<()> {
+    loop {
+        let message = receive(&mut receiver).await?;
+        // Notifications carry no id
+        let sent_at = match message["id"].as_u64() {
+            Some(id) => match pending.lock().await.remove(&id) {
+                Some(sent_at) => sent_at,
+                None => continue,
+            },
+            None => continue,
+        };
+        let mut stats = stats.lock().await;
+        if message["error"].is_null() {
+            stats.accepted += 1;
+            stats.latencies.push(sent_at.elapsed());
+        } else {
+            stats.rejected += 1;
+            eprintln!("update rejected: {}", message["error"]);
+        }
+    }
+}
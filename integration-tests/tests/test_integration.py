@@ -527,8 +527,13 @@ class TestUpdatePrice(PythTest):
     @pytest.mark.asyncio
     async def test_update_price_simple_with_keypair_hotload(self, client_hotload: PythAgentClient):
 
-        # Hotload the keypair into running agent
-        hl_request = requests.post("http://localhost:9001/primary/load_keypair", json=PUBLISHER_A_KEYPAIR)
+        # Hotload the keypair into running agent. The keypair is rejected
+        # until the agent has read the publish permissions from the network.
+        for _ in range(10):
+            hl_request = requests.post("http://localhost:9001/primary/load_keypair", json=PUBLISHER_A_KEYPAIR)
+            if hl_request.status_code == 200:
+                break
+            time.sleep(1)
 
         # Verify succesful hotload
         assert hl_request.status_code == 200
@@ -40,6 +40,8 @@ Publisher data write path:
 - The Local Store holds the latest price data the user has submitted for each price feed.
 - The Exporters periodically query the Local Store for the latest user-submitted data,
 and send it to the RPC node.
+- An Exporter configured for Pyth Lazer signs the updates of the configured feeds and posts them to the Lazer relayers
+instead.
 
 Publisher data read path:
 - The Oracles continually fetch data from the RPC node, and pass this to the Global Store.
@@ -56,145 +58,806 @@ Remote Keypair Loading:
 Metrics Server:
 - Every update in global and local store is reflected in the metrics
 - Metrics are served using Prometheus
+- A canonical, hashed snapshot of the Global Store is served at /api/snapshot, which the diff-snapshots subcommand
+compares across two agents
+
+Slot Lag:
+- A Slot Tracker per network polls the slot of the cluster tip, which is compared to the latest price account
+and aggregate slots observed by the Oracle
+- The lags are exported as metrics, and shown in the section of each network on the dashboard along with the worst one
+- When configured, the Exporter pauses publishing while the cluster slot has not advanced for a while, as during a
+cluster halt, and resumes once it advances
+
+Publisher Performance:
+- When publisher keys are configured, the Publisher Performance Tracker scores our on-chain components on each new aggregate
+- Uptime, slot hit rate, aggregate inclusion rate and average deviation over rolling windows are exported as metrics,
+shown on the dashboard and served at /api/publisher_performance
+
+Uptime:
+- When publisher keys are configured, the Uptime Tracker counts the slots our components were expected to publish in
+and those they published in, for SLA reporting over windows of up to a month, served at /api/uptime?window=7d
+
+Component Monitor:
+- When configured, the Component Monitor looks up our component in the price accounts updated by each settled
+transaction, and counts the consecutive transactions after which it was missing or lagging behind
+- Miss streaks are exported as metrics, logged once they reach the threshold, and alerted on by the Alerter
+
+Anomaly Detector:
+- When configured, the Anomaly Detector keeps rolling statistics over the recent aggregates of each price observed by
+the Global Store, and flags jumps and confidence blowouts by their z-score, and prices flatlining
+- Flagged anomalies are exported as metrics, shown on the dashboard and alerted on by the Alerter
+
+Kafka Sink:
+- When brokers are configured, every new aggregate and component observed by the Global Store is published to Kafka
+- Messages are encoded as JSON or Avro, and their deliveries are counted per topic
+
+Price Relay:
+- When configured, the Price Relay streams every new aggregate observed by the Global Store to downstream consumers
+over websockets or server-sent events, on its own address, filtered by the symbols each client asks for
+
+Redis Mirror:
+- When a Redis server is configured, the updates accepted by the Local Store and the new aggregates observed by
+the Global Store are published on Redis pub/sub channels keyed by symbol
+
+Price History:
+- When a directory is configured, the Price History Recorder writes each new aggregate observed by the Global Store,
+with its components, to rotating Parquet files
+- When configured, the Price History Backfill replays the recent transactions of the price accounts on startup, and
+hands the aggregates missed while the agent was not running to the Recorder
+
+Reference Prices:
+- When reference price sources are configured, the Reference Price Poller fetches prices from them over HTTP
+- The Local Store flags or rejects updates deviating too far from the reference price of their price
+
+Update Statuses:
+- The API Server assigns a sequence id to each accepted update, returned in the response of update_price
+- The update is followed as the Local Store stores it, and the Exporter of each network packs, submits and sees it land,
+its status being served by the get_update_status method
+
+Alerting:
+- When webhooks are configured, the Alerter evaluates rules against the stores and the networks' RPC nodes
+- Alerts starting to fire or resolving are posted to Slack, PagerDuty or generic webhooks
+
+Shutdown:
+- On SIGTERM or SIGINT, the API server stops accepting updates and the Adapter forwards the ones it received
+- The Exporters then publish the pending updates one last time, and the Local Store persists its contents
+
+Tenancy:
+- When tenants are configured, several publishers share the Oracles and the Global Store of one agent
+- Each tenant's pythd API connections authenticate with its token, and their updates are submitted for its publish key,
+kept apart in the Local Store and batched and signed separately by the Exporters
+
+High Availability:
+- When configured, two agents share a lease file, and only the agent holding it publishes
+- The standby keeps its stores warm, and takes the lease over once it goes without renewal for the failover window
+- When replication is configured, each agent streams the updates accepted by its Local Store to its peer over TCP,
+which only the standby applies, so that it holds the leader's unpublished prices when it takes over
+
+Runtimes:
+- All components run on a single multi-threaded Tokio runtime by default, whose worker threads can be configured
+- A dedicated runtime can be configured for the API Server, Metrics Server and Admin API, so that serving clients
+does not compete with the Oracles and Exporters
+
+Embedding:
+- The agent can run inside a larger Rust service, started with Agent::spawn, which returns a handle through which
+prices are submitted to the Adapter and subscribed to without going through the Pythd JRPC Websocket API
+- An embedded agent leaves the signals to its host, and is shut down through its handle
+
+Startup Gate:
+- When configured, the API Server refuses connections, and the agent is not reported ready, until the first poll of
+the primary Oracle was handed to the Global Store, the timeout elapsed, or the gate was opened through the Admin API
 
 Note that there is an Oracle and Exporter for each network, but only one Local Store and Global Store.
 
 ################################################################################################################################## */
 
+pub mod admin;
+pub mod alerting;
+pub mod anomaly_detector;
+pub mod build_info;
+pub mod channel_monitor;
+pub mod component_monitor;
+pub mod config_check;
+pub mod config_watcher;
 pub mod dashboard;
+pub mod dump;
+pub mod embedded;
+pub mod error;
+pub mod fault_injection;
+pub mod health;
+pub mod high_availability;
+pub mod http_auth;
+pub mod kafka;
+pub mod logging;
 pub mod metrics;
+pub mod price_history;
+pub mod price_relay;
+pub mod publish_latency;
+pub mod publish_pause;
+pub mod publisher_performance;
 pub mod pythd;
+pub mod redis_mirror;
+pub mod reference_prices;
 pub mod remote_keypair_loader;
+pub mod replication;
+pub mod rpc_probe;
+pub mod runtime;
+pub mod shutdown;
+pub mod snapshot_diff;
 pub mod solana;
+pub mod startup_gate;
 pub mod store;
+pub mod telemetry;
+pub mod tenancy;
+#[cfg(feature = "test-support")]
+pub mod test_support;
+pub mod update_status;
+pub mod uptime;
 use {
     self::{
-        config::Config,
-        pythd::api::rpc,
+        config::{
+            Config,
+            ConfigSource,
+        },
+        pythd::{
+            api::rpc,
+            connections::ApiConnections,
+        },
         solana::network,
     },
-    anyhow::Result,
+    anyhow::{
+        anyhow,
+        Result,
+    },
+    config_watcher::ConfigWatcher,
     futures_util::future::join_all,
-    slog::Logger,
-    tokio::sync::{
-        broadcast,
-        mpsc,
+    logging::LogLevel,
+    std::{
+        collections::HashMap,
+        iter,
+    },
+    tokio::{
+        runtime::Handle,
+        sync::{
+            broadcast,
+            mpsc,
+            oneshot,
+            watch,
+        },
     },
+    tracing::Instrument,
 };
 
 pub struct Agent {
-    config: Config,
+    config:        Config,
+    /// Where the config was loaded from, reloaded on SIGHUP. None for an
+    /// embedded agent.
+    config_source: Option<ConfigSource>,
+    /// Minimum level of the logged events, if set from the config
+    log_level:     Option<LogLevel>,
+    /// Dedicated runtime of the API components, which run on the current
+    /// runtime if not set
+    api_runtime:   Option<Handle>,
 }
 
 impl Agent {
-    pub fn new(config: Config) -> Self {
-        Agent { config }
+    pub fn new(config: Config, config_source: ConfigSource, log_level: Option<LogLevel>) -> Self {
+        Agent {
+            config,
+            config_source: Some(config_source),
+            log_level,
+            api_runtime: None,
+        }
+    }
+
+    /// Start an agent embedded in another service on the current runtime,
+    /// returning the handle through which prices are submitted and subscribed
+    /// to once all its components are spawned. The logging is left to the host.
+    pub async fn spawn(config: Config) -> Result<embedded::AgentHandle> {
+        let agent = Agent {
+            config,
+            config_source: None,
+            log_level: None,
+            api_runtime: None,
+        };
+        agent.init();
+        let (started_tx, started_rx) = oneshot::channel();
+        let jh = tokio::spawn(async move {
+            let result = agent.run(Some(started_tx)).await;
+            telemetry::shutdown();
+            result
+        });
+        match started_rx.await {
+            Ok(started) => Ok(embedded::AgentHandle::new(started, jh)),
+            // The agent failed to start, returning why from its task
+            Err(_) => {
+                jh.await??;
+                Err(anyhow!("the agent stopped while starting"))
+            }
+        }
     }
 
-    pub async fn start(&self, logger: Logger) {
-        info!(logger, "starting agent"; "config" => format!("{:?}", self.config));
-        if let Err(err) = self.spawn(logger.clone()).await {
-            error!(logger, "{:#}", err; "error" => format!("{:?}", err));
+    /// Run the API Server, Metrics Server and Admin API on the given runtime
+    pub fn with_api_runtime(mut self, api_runtime: Handle) -> Self {
+        self.api_runtime = Some(api_runtime);
+        self
+    }
+
+    pub async fn start(&self) {
+        self.init();
+        if let Err(err) = self.run(None).await {
+            error!(error = ?err, "{:#}", err);
         };
+        telemetry::shutdown();
     }
 
-    async fn spawn(&self, logger: Logger) -> Result<()> {
+    fn init(&self) {
+        let build = build_info::BuildInfo::current();
+        info!(
+            version = build.version,
+            git_commit = build.git_commit,
+            build_time = %build.build_time,
+            config = ?self.config,
+            "starting agent"
+        );
+        if let Err(err) = telemetry::init(&self.config.telemetry) {
+            error!(error = ?err, "could not set up tracing: {:#}", err);
+        }
+    }
+
+    /// Spawn all components, handing the channels of an embedded agent over
+    /// through `started_tx`, and wait until they complete
+    async fn run(&self, started_tx: Option<oneshot::Sender<embedded::Started>>) -> Result<()> {
         // job handles
         let mut jhs = vec![];
 
+        // Inject faults in the RPC clients, subscribers and channels, if
+        // configured for chaos testing
+        if let Some(config) = &self.config.fault_injection {
+            fault_injection::enable(config.clone())?;
+        }
+
         // Create the channels
-        // TODO: make all components listen to shutdown signal
         let (shutdown_tx, shutdown_rx) =
             broadcast::channel(self.config.channel_capacities.shutdown);
         let (primary_oracle_updates_tx, primary_oracle_updates_rx) =
             mpsc::channel(self.config.channel_capacities.primary_oracle_updates);
         let (secondary_oracle_updates_tx, secondary_oracle_updates_rx) =
             mpsc::channel(self.config.channel_capacities.secondary_oracle_updates);
-        let (global_store_lookup_tx, global_store_lookup_rx) =
-            mpsc::channel(self.config.channel_capacities.global_store_lookup);
+        let global_store_reader = store::global::SnapshotReader::default();
+        let (global_store_events_tx, _) =
+            broadcast::channel(self.config.channel_capacities.global_store_events);
         let (local_store_tx, local_store_rx) =
             mpsc::channel(self.config.channel_capacities.local_store);
+        let (local_store_events_tx, _) =
+            broadcast::channel(self.config.channel_capacities.local_store_events);
+        let (publish_latency_tx, publish_latency_rx) =
+            mpsc::channel(self.config.channel_capacities.publish_latency);
+        let (transactions_store_tx, transactions_store_rx) =
+            mpsc::channel(self.config.channel_capacities.transactions_store);
         let (pythd_adapter_tx, pythd_adapter_rx) =
             mpsc::channel(self.config.channel_capacities.pythd_adapter);
         let (primary_keypair_loader_tx, primary_keypair_loader_rx) = mpsc::channel(10);
         let (secondary_keypair_loader_tx, secondary_keypair_loader_rx) = mpsc::channel(10);
+        let (primary_permissions_tx, primary_permissions_rx) = watch::channel(HashMap::new());
+        let (secondary_permissions_tx, secondary_permissions_rx) = watch::channel(HashMap::new());
+
+        // Reloads the config on SIGHUP, pushing the reloadable settings to the
+        // components subscribed to them
+        let mut config_watcher = ConfigWatcher::new(
+            self.config_source.clone(),
+            self.config.clone(),
+            self.log_level.clone(),
+        );
+
+        // Stops the components in order on SIGTERM or SIGINT
+        let mut shutdown_controller =
+            shutdown::ShutdownController::new(self.config.shutdown.clone());
+
+        // Publishing paused through the Admin API, skipped by the Exporters
+        let publish_pause =
+            publish_pause::PublishPause::new(&mut &mut metrics::PROMETHEUS_REGISTRY.lock().await);
+
+        // Statuses of the updates accepted by the API Server, followed until
+        // they land
+        let update_statuses = update_status::UpdateStatuses::new(&self.config.update_status);
+
+        // Elect the leader of a high-availability pair, the standby not publishing
+        if let Some(config) = &self.config.high_availability {
+            jhs.push(high_availability::spawn_elector(
+                config.clone(),
+                publish_pause.clone(),
+            ));
+        }
+
+        // Connections of the API Server, listed by the Admin API
+        let api_connections = ApiConnections::default();
+
+        // Shared registry of component statuses, served by the metrics server
+        let health = health::HealthReporter::default();
+
+        // Keeps the API Server from accepting connections until the primary
+        // Oracle first polled the accounts, if configured
+        let startup_gate =
+            startup_gate::StartupGate::new(self.config.startup_gate.is_some(), &health);
+        if let Some(config) = &self.config.startup_gate {
+            jhs.push(startup_gate::spawn_timeout(
+                startup_gate.clone(),
+                config.clone(),
+            ));
+        }
+
+        // Slots observed on each network, shown on the dashboard
+        let slot_lags = solana::slot_lag::SlotLagReporter::new().await;
+
+        // Usage of the mapping accounts of each network, shown on the dashboard
+        let mapping_usage = solana::mapping_usage::MappingUsageReporter::new().await;
+
+        // Sample the depth of the channels between the top-level components
+        let channel_monitor =
+            channel_monitor::ChannelMonitor::new(self.config.channel_monitor.clone()).await;
+        let capacities = &self.config.channel_capacities;
+        channel_monitor.register(
+            "primary_oracle_updates",
+            &primary_oracle_updates_tx,
+            capacities.primary_oracle_updates,
+        );
+        channel_monitor.register(
+            "secondary_oracle_updates",
+            &secondary_oracle_updates_tx,
+            capacities.secondary_oracle_updates,
+        );
+        channel_monitor.register("local_store", &local_store_tx, capacities.local_store);
+        channel_monitor.register(
+            "publish_latency",
+            &publish_latency_tx,
+            capacities.publish_latency,
+        );
+        channel_monitor.register(
+            "transactions_store",
+            &transactions_store_tx,
+            capacities.transactions_store,
+        );
+        channel_monitor.register("pythd_adapter", &pythd_adapter_tx, capacities.pythd_adapter);
+
+        // Tenants publishing through this agent, whose keypairs are added to
+        // the key store of each network
+        let tenants = tenancy::Tenants::new(
+            &self.config.tenancy,
+            &self.config.primary_network.key_store.root_path,
+        )?;
 
         // Spawn the primary network
+        let mut primary_network = self.config.primary_network.clone();
+        self.config
+            .tenancy
+            .extend_key_store(&mut primary_network.key_store);
         jhs.extend(network::spawn_network(
-            self.config.primary_network.clone(),
+            primary_network,
+            "primary",
             local_store_tx.clone(),
+            transactions_store_tx.clone(),
+            local_store_events_tx.clone(),
             primary_oracle_updates_tx,
             primary_keypair_loader_tx,
-            logger.new(o!("primary" => true)),
+            primary_permissions_tx,
+            config_watcher.subscribe(|config| config.primary_network.oracle.clone()),
+            config_watcher.subscribe(|config| config.primary_network.exporter.clone()),
+            config_watcher.subscribe(|config| config.primary_network.key_store.clone()),
+            global_store_reader.clone(),
+            publish_pause.clone(),
+            update_statuses.clone(),
+            Some(startup_gate.clone()),
+            &health,
+            &slot_lags,
+            &mapping_usage,
+            &channel_monitor,
+            shutdown_controller.participant(shutdown::Phase::Flush),
         )?);
 
         // Spawn the secondary network, if needed
         if let Some(config) = &self.config.secondary_network {
+            let mut config = config.clone();
+            self.config.tenancy.extend_key_store(&mut config.key_store);
             jhs.extend(network::spawn_network(
-                config.clone(),
+                config,
+                "secondary",
                 local_store_tx.clone(),
+                transactions_store_tx.clone(),
+                local_store_events_tx.clone(),
                 secondary_oracle_updates_tx,
                 secondary_keypair_loader_tx,
-                logger.new(o!("primary" => false)),
+                secondary_permissions_tx,
+                config_watcher.subscribe(|config| {
+                    config
+                        .secondary_network
+                        .as_ref()
+                        .map(|network| network.oracle.clone())
+                        .unwrap_or_default()
+                }),
+                config_watcher.subscribe(|config| {
+                    config
+                        .secondary_network
+                        .as_ref()
+                        .map(|network| network.exporter.clone())
+                        .unwrap_or_default()
+                }),
+                config_watcher.subscribe(|config| {
+                    config
+                        .secondary_network
+                        .as_ref()
+                        .map(|network| network.key_store.clone())
+                        .unwrap_or_default()
+                }),
+                global_store_reader.clone(),
+                publish_pause.clone(),
+                update_statuses.clone(),
+                None,
+                &health,
+                &slot_lags,
+                &mapping_usage,
+                &channel_monitor,
+                shutdown_controller.participant(shutdown::Phase::Flush),
             )?);
         }
 
         // Spawn the Global Store
         jhs.push(store::global::spawn_store(
-            global_store_lookup_rx,
+            self.config.global_store.clone(),
+            global_store_reader.clone(),
             primary_oracle_updates_rx,
             secondary_oracle_updates_rx,
             pythd_adapter_tx.clone(),
-            logger.clone(),
+            global_store_events_tx.clone(),
         ));
 
+        // Spawn the Reference Price Poller, if reference price sources are configured
+        let reference_prices = if !self.config.reference_prices.sources.is_empty() {
+            let reference_prices =
+                reference_prices::ReferencePrices::new(self.config.reference_prices.clone()).await;
+            jhs.push(reference_prices::spawn_poller(
+                reference_prices.clone(),
+                global_store_reader.clone(),
+            )?);
+            Some(reference_prices)
+        } else {
+            None
+        };
+
         // Spawn the Local Store
-        jhs.push(store::local::spawn_store(local_store_rx, logger.clone()));
+        jhs.push(store::local::spawn_store(
+            config_watcher.subscribe(|config| config.local_store.clone()),
+            local_store_rx,
+            publish_latency_tx,
+            local_store_events_tx.clone(),
+            global_store_reader.clone(),
+            reference_prices,
+            update_statuses.clone(),
+            shutdown_controller.participant(shutdown::Phase::Persist),
+        ));
+
+        // Stream the Local Store updates to the peer agent, and follow its
+        // stream, if configured
+        let replication = &self.config.replication;
+        if replication.listen_address.is_some() || replication.peer_address.is_some() {
+            jhs.extend(
+                replication::spawn_replication(
+                    replication.clone(),
+                    local_store_tx.clone(),
+                    local_store_events_tx.subscribe(),
+                    publish_pause.clone(),
+                )
+                .await?,
+            );
+        }
+
+        // Spawn the Publish Latency Tracker, correlating the updates of the
+        // default publisher with the publish keys of the networks
+        let network_publish_keys = iter::once(&self.config.primary_network)
+            .chain(&self.config.secondary_network)
+            .filter_map(|network| match network.publish_key() {
+                Ok(publish_key) => publish_key,
+                Err(err) => {
+                    warn!(error = ?err, "Publish latency: could not read the publish key of a network");
+                    None
+                }
+            })
+            .collect();
+        jhs.push(publish_latency::spawn_tracker(
+            self.config.publish_latency.clone(),
+            network_publish_keys,
+            publish_latency_rx,
+            global_store_events_tx.subscribe(),
+            global_store_reader.clone(),
+        ));
+
+        // Spawn the Publisher Performance Tracker, if publisher keys are configured
+        let publisher_performance_tx =
+            if !self.config.publisher_performance.publisher_keys.is_empty() {
+                let (publisher_performance_tx, publisher_performance_rx) = mpsc::channel(10);
+                jhs.push(publisher_performance::spawn_tracker(
+                    self.config.publisher_performance.clone(),
+                    publisher_performance_rx,
+                    global_store_events_tx.subscribe(),
+                    global_store_reader.clone(),
+                ));
+                Some(publisher_performance_tx)
+            } else {
+                None
+            };
+
+        // Spawn the Uptime Tracker, if publisher keys are configured
+        let uptime_tx = if !self.config.uptime.publisher_keys.is_empty() {
+            let (uptime_tx, uptime_rx) = mpsc::channel(10);
+            jhs.push(uptime::spawn_tracker(
+                self.config.uptime.clone(),
+                uptime_rx,
+                global_store_events_tx.subscribe(),
+                global_store_reader.clone(),
+            ));
+            Some(uptime_tx)
+        } else {
+            None
+        };
+
+        // Spawn the Kafka Sink, if brokers are configured
+        if self.config.kafka.brokers.is_some() {
+            jhs.push(kafka::spawn_sink(
+                self.config.kafka.clone(),
+                global_store_events_tx.subscribe(),
+                global_store_reader.clone(),
+            )?);
+        }
+
+        // Spawn the Price Relay, if enabled
+        if let Some(price_relay_config) = self.config.price_relay.clone() {
+            jhs.push(price_relay::spawn_relay(
+                price_relay_config,
+                global_store_events_tx.subscribe(),
+                global_store_reader.clone(),
+            ));
+        }
 
-        // Spawn the Pythd Adapter
-        jhs.push(pythd::adapter::spawn_adapter(
+        // Spawn the Redis Mirror, if a Redis server is configured
+        if self.config.redis_mirror.url.is_some() {
+            jhs.push(redis_mirror::spawn_mirror(
+                self.config.redis_mirror.clone(),
+                local_store_events_tx.subscribe(),
+                global_store_events_tx.subscribe(),
+                global_store_reader.clone(),
+            )?);
+        }
+
+        // Spawn the Price History Recorder, if a directory is configured
+        if self.config.price_history.directory.is_some() {
+            // The gap in the history is backfilled from the primary network
+            let backfill_network = match &self.config.price_history.backfill {
+                Some(_) => {
+                    let primary_network = &self.config.primary_network;
+                    Some(price_history::backfill::Network {
+                        rpc_url:     primary_network.rpc_url.clone(),
+                        rpc_timeout: primary_network.rpc_timeout,
+                        program_key: solana::key_store::KeyStore::read_program_key(
+                            &primary_network.key_store,
+                        )?,
+                    })
+                }
+                None => None,
+            };
+            jhs.push(price_history::spawn_recorder(
+                self.config.price_history.clone(),
+                global_store_events_tx.subscribe(),
+                global_store_reader.clone(),
+                backfill_network,
+                shutdown_controller.participant(shutdown::Phase::Persist),
+            )?);
+        }
+
+        // Spawn the Transactions Store
+        jhs.push(store::transactions::spawn_store(
+            self.config.transactions_store.clone(),
+            transactions_store_rx,
+        ));
+
+        // Spawn the Pythd Adapter, which the shutdown controller waits for
+        let adapter_jh = pythd::adapter::spawn_adapter(
             self.config.pythd_adapter.clone(),
             pythd_adapter_rx,
-            global_store_lookup_tx.clone(),
+            global_store_reader.clone(),
             local_store_tx.clone(),
+            update_statuses.clone(),
+            channel_monitor.clone(),
             shutdown_tx.subscribe(),
-            logger.clone(),
-        ));
+        );
+
+        // The API components are spawned on their dedicated runtime, if any
+        let api_runtime = self.api_runtime.clone().unwrap_or_else(Handle::current);
 
         // Spawn the Pythd API Server
-        jhs.push(rpc::spawn_server(
-            self.config.pythd_api_server.clone(),
-            pythd_adapter_tx,
-            shutdown_rx,
-            logger.clone(),
-        ));
+        jhs.push({
+            let _api_runtime = api_runtime.enter();
+            rpc::spawn_server(
+                self.config.pythd_api_server.clone(),
+                pythd_adapter_tx.clone(),
+                shutdown_rx,
+                health.component("api_server"),
+                api_connections.clone(),
+                tenants,
+                global_store_reader.clone(),
+                startup_gate.clone(),
+                update_statuses.clone(),
+            )
+        });
+
+        // Spawn the Component Monitor, if configured
+        let component_monitor_tx = match &self.config.component_monitor {
+            Some(config) => {
+                let (component_monitor_tx, component_monitor_rx) = mpsc::channel(10);
+                jhs.push(component_monitor::spawn_monitor(
+                    config.clone(),
+                    component_monitor_rx,
+                    transactions_store_tx.clone(),
+                    global_store_reader.clone(),
+                ));
+                Some(component_monitor_tx)
+            }
+            None => None,
+        };
+
+        // Spawn the Anomaly Detector, if configured
+        let anomaly_detector_tx = match &self.config.anomaly_detector {
+            Some(config) => {
+                let (anomaly_detector_tx, anomaly_detector_rx) = mpsc::channel(10);
+                jhs.push(anomaly_detector::spawn_detector(
+                    config.clone(),
+                    anomaly_detector_rx,
+                    global_store_events_tx.subscribe(),
+                    global_store_reader.clone(),
+                ));
+                Some(anomaly_detector_tx)
+            }
+            None => None,
+        };
+
+        // Spawn the Alerter, if any webhook is configured
+        if !self.config.alerting.webhooks.is_empty() {
+            let mut alerting_networks = vec![alerting::Network {
+                name:   "primary".to_string(),
+                config: self.config.primary_network.clone(),
+            }];
+            if let Some(config) = &self.config.secondary_network {
+                alerting_networks.push(alerting::Network {
+                    name:   "secondary".to_string(),
+                    config: config.clone(),
+                });
+            }
+            jhs.push(alerting::spawn_alerter(
+                self.config.alerting.clone(),
+                alerting_networks,
+                global_store_reader.clone(),
+                local_store_tx.clone(),
+                transactions_store_tx.clone(),
+                component_monitor_tx,
+                anomaly_detector_tx.clone(),
+            )?);
+        }
 
         // Spawn the metrics server
-        jhs.push(tokio::spawn(metrics::MetricsServer::spawn(
-            self.config.metrics_server.bind_address,
-            local_store_tx,
-            global_store_lookup_tx,
-            logger.clone(),
-        )));
-
-        // Spawn the remote keypair loader endpoint for both networks
+        jhs.push(
+            api_runtime.spawn(
+                metrics::MetricsServer::spawn(
+                    self.config.metrics_server.bind_address,
+                    self.config.metrics_server.dashboard_refresh_interval,
+                    self.config.global_store.staleness_threshold,
+                    self.config.metrics_server.dashboard_publisher_keys.clone(),
+                    self.config.metrics_server.auth.as_ref().map(|auth| {
+                        http_auth::Authenticator::new(
+                            Some(auth),
+                            self.config.admin_api.auth_token.as_deref(),
+                        )
+                    }),
+                    local_store_tx.clone(),
+                    transactions_store_tx.clone(),
+                    global_store_reader.clone(),
+                    health,
+                    slot_lags,
+                    mapping_usage,
+                    publish_pause.clone(),
+                    publisher_performance_tx,
+                    uptime_tx,
+                    anomaly_detector_tx,
+                )
+                .instrument(info_span!("metrics_server")),
+            ),
+        );
+
+        // Spawn the StatsD sink, if configured
+        if self.config.metrics_server.statsd.address.is_some() {
+            jhs.push(metrics::statsd::spawn_sink(
+                self.config.metrics_server.statsd.clone(),
+            ));
+        }
+
+        // Spawn the Admin API, if enabled
+        if self.config.admin_api.auth_token.is_some() {
+            let _api_runtime = api_runtime.enter();
+            let dump_sources = dump::DumpSources {
+                config: dump::redact(serde_json::to_value(&self.config)?),
+                local_store_tx,
+                transactions_store_tx,
+                global_store_reader: global_store_reader.clone(),
+                publish_pause: publish_pause.clone(),
+                api_connections: api_connections.clone(),
+            };
+            jhs.push(admin::spawn_server(
+                self.config.admin_api.clone(),
+                self.config.metrics_server.auth.clone(),
+                self.log_level.clone(),
+                publish_pause,
+                api_connections,
+                global_store_reader,
+                dump_sources,
+                startup_gate,
+            ));
+        }
+
+        // Spawn the config watcher, once all components subscribed to it
+        jhs.push(config_watcher::spawn_watcher(config_watcher));
+
+        // Spawn the channel monitor
+        jhs.push(channel_monitor::spawn_monitor(channel_monitor));
+
+        // Spawn the remote keypair loader endpoint for all networks
+        let keypair_loader_config = &self.config.remote_keypair_loader;
+        let mut keypair_loader_networks = vec![remote_keypair_loader::Network {
+            name:                     "primary".to_string(),
+            rpc_url:                  self.config.primary_network.rpc_url.clone(),
+            min_keypair_balance_sol:  keypair_loader_config.primary_min_keypair_balance_sol,
+            requests_rx:              primary_keypair_loader_rx,
+            publisher_permissions_rx: primary_permissions_rx,
+        }];
+        if let Some(config) = &self.config.secondary_network {
+            keypair_loader_networks.push(remote_keypair_loader::Network {
+                name:                     "secondary".to_string(),
+                rpc_url:                  config.rpc_url.clone(),
+                min_keypair_balance_sol:  keypair_loader_config.secondary_min_keypair_balance_sol,
+                requests_rx:              secondary_keypair_loader_rx,
+                publisher_permissions_rx: secondary_permissions_rx,
+            });
+        }
         jhs.append(
             &mut remote_keypair_loader::RemoteKeypairLoader::spawn(
-                primary_keypair_loader_rx,
-                secondary_keypair_loader_rx,
-                self.config.primary_network.rpc_url.clone(),
-                self.config
-                    .secondary_network
-                    .as_ref()
-                    .map(|c| c.rpc_url.clone()),
-                self.config.remote_keypair_loader.clone(),
-                logger,
+                keypair_loader_networks,
+                keypair_loader_config.clone(),
             )
             .await,
         );
 
-        // Wait for all tasks to complete
-        join_all(jhs).await;
+        // Spawn the shutdown controller, once all components took part in it
+        // An embedded agent is shut down through its handle rather than by signals
+        let (shutdown_requested_tx, shutdown_requested_rx) = oneshot::channel();
+        let shutdown_jh = shutdown::spawn_controller(
+            shutdown_controller,
+            shutdown_tx,
+            adapter_jh,
+            started_tx.is_some().then_some(shutdown_requested_rx),
+        );
+
+        // Hand the channels of an embedded agent over to its handle
+        if let Some(started_tx) = started_tx {
+            let _ = started_tx.send(embedded::Started {
+                adapter_tx: pythd_adapter_tx,
+                update_statuses,
+                shutdown_requested_tx,
+                notify_price_tx_buffer: self.config.pythd_api_server.notify_price_tx_buffer,
+            });
+        }
+
+        // Wait for all tasks to complete, or for the agent to be shut down
+        tokio::select! {
+            _ = join_all(jhs) => {}
+            _ = shutdown_jh => {}
+        }
 
         Ok(())
     }
@@ -203,50 +866,450 @@ impl Agent {
 pub mod config {
     use {
         super::{
+            admin,
+            alerting,
+            anomaly_detector,
+            channel_monitor,
+            component_monitor,
+            fault_injection,
+            high_availability,
+            kafka,
+            logging,
             metrics,
+            price_history,
+            price_relay,
+            publish_latency,
+            publisher_performance,
             pythd,
+            redis_mirror,
+            reference_prices,
             remote_keypair_loader,
+            replication,
+            runtime,
+            shutdown,
             solana::network,
+            startup_gate,
+            store,
+            telemetry,
+            tenancy,
+            update_status,
+            uptime,
+        },
+        anyhow::{
+            anyhow,
+            Context,
+            Result,
         },
-        anyhow::Result,
         config as config_rs,
         config_rs::{
             Environment,
             File,
+            Map,
+            Source,
+            Value,
+            ValueKind,
         },
-        serde::Deserialize,
-        std::path::Path,
+        serde::{
+            Deserialize,
+            Serialize,
+        },
+        std::{
+            collections::HashMap,
+            env,
+            fs,
+            path::PathBuf,
+            str::FromStr,
+        },
+        tracing::Level,
     };
 
+    /// Parse a `key=value` config override
+    pub fn parse_override(arg: &str) -> Result<(String, String)> {
+        let (key, value) = arg
+            .split_once('=')
+            .ok_or_else(|| anyhow!("expected KEY=VALUE, got {:?}", arg))?;
+        Ok((key.trim().to_string(), value.to_string()))
+    }
+
+    /// Suffix of the keys whose value is the path of a file holding the value
+    /// of the key without the suffix, to keep secrets out of the config file
+    const FILE_KEY_SUFFIX: &str = "_file";
+
+    /// Prefix of the environment variables overriding any config key, using
+    /// double underscores as separators, e.g. PYTH_AGENT__PRIMARY_NETWORK__RPC_URL
+    /// for primary_network.rpc_url
+    const ENV_OVERRIDE_PREFIX: &str = "pyth_agent";
+    const ENV_OVERRIDE_SEPARATOR: &str = "__";
+
+    /// Log level used when neither the config nor RUST_LOG set one
+    const DEFAULT_LOG_LEVEL: &str = "info";
+
     /// Configuration for all components of the Agent
-    #[derive(Default, Deserialize, Debug)]
-    #[serde(default)]
+    #[derive(Clone, Default, Serialize, Deserialize, Debug, PartialEq)]
+    #[serde(default, deny_unknown_fields)]
     pub struct Config {
+        /// Minimum level of the logged events, "info" if not set. Ignored when
+        /// the RUST_LOG environment variable is set.
+        pub log_level:             Option<String>,
+        /// Format of the logged events, "pretty" or "json"
+        pub log_format:            logging::LogFormat,
+        pub runtime:               runtime::Config,
         pub channel_capacities:    ChannelCapacities,
         pub primary_network:       network::Config,
         pub secondary_network:     Option<network::Config>,
+        pub global_store:          store::global::Config,
+        pub local_store:           store::local::Config,
+        pub transactions_store:    store::transactions::Config,
+        pub publish_latency:       publish_latency::Config,
+        pub publisher_performance: publisher_performance::Config,
+        pub uptime:                uptime::Config,
+        pub update_status:         update_status::Config,
+        /// Checks that our components keep appearing in the price accounts,
+        /// disabled when not set
+        pub component_monitor:     Option<component_monitor::Config>,
+        /// Flags anomalies of the price feeds, disabled when not set
+        pub anomaly_detector:      Option<anomaly_detector::Config>,
+        pub kafka:                 kafka::Config,
+        pub redis_mirror:          redis_mirror::Config,
+        pub price_history:         price_history::Config,
+        /// Streams the observed aggregates to downstream consumers, disabled
+        /// when not set
+        pub price_relay:           Option<price_relay::Config>,
         pub pythd_adapter:         pythd::adapter::Config,
         pub pythd_api_server:      pythd::api::rpc::Config,
         pub metrics_server:        metrics::Config,
         pub remote_keypair_loader: remote_keypair_loader::Config,
+        pub telemetry:             telemetry::Config,
+        pub channel_monitor:       channel_monitor::Config,
+        pub admin_api:             admin::Config,
+        pub shutdown:              shutdown::Config,
+        pub alerting:              alerting::Config,
+        pub reference_prices:      reference_prices::Config,
+        pub tenancy:               tenancy::Config,
+        /// Hot-standby pairing with another agent, disabled when not set
+        pub high_availability:     Option<high_availability::Config>,
+        pub replication:           replication::Config,
+        /// Faults injected for chaos testing, disabled when not set
+        pub fault_injection:       Option<fault_injection::Config>,
+        /// Holds the API Server until the first poll of the primary Oracle,
+        /// disabled when not set
+        pub startup_gate:          Option<startup_gate::Config>,
+    }
+
+    /// Where the config is loaded from
+    #[derive(Clone, Debug)]
+    pub struct ConfigSource {
+        /// Path of the config file
+        pub path:      PathBuf,
+        /// Values overriding those of the file and environment, by their
+        /// dot-separated key, e.g. ("primary_network.rpc_url", "http://...")
+        pub overrides: Vec<(String, String)>,
     }
 
     impl Config {
-        pub fn new(config_file: impl AsRef<Path>) -> Result<Self> {
+        pub fn new(source: &ConfigSource) -> Result<Self> {
             // Build a new configuration object, allowing the default values to be
-            // overridden by those in the config_file or "AGENT_"-prefixed environment
-            // variables.
-            config_rs::Config::builder()
-                .add_source(File::from(config_file.as_ref()))
-                .add_source(Environment::with_prefix("agent"))
-                .build()?
-                .try_deserialize()
-                .map_err(|e| e.into())
+            // overridden by those in the config file, "AGENT_"-prefixed environment
+            // variables for top-level keys, "PYTH_AGENT__"-prefixed environment
+            // variables for any key, and finally the explicit overrides.
+            let mut builder = config_rs::Config::builder()
+                .add_source(File::from(source.path.as_path()))
+                .add_source(Environment::default().source(Some(top_level_env_vars())))
+                .add_source(
+                    Environment::with_prefix(ENV_OVERRIDE_PREFIX)
+                        .prefix_separator(ENV_OVERRIDE_SEPARATOR)
+                        .separator(ENV_OVERRIDE_SEPARATOR),
+                );
+            for (key, value) in &source.overrides {
+                builder = builder
+                    .set_override(key.as_str(), value.as_str())
+                    .with_context(|| format!("overriding {}", key))?;
+            }
+            let mut values = builder.build()?.collect()?;
+
+            // Substitute the referenced environment variables and secret files
+            resolve_table(&mut values, "")?;
+
+            deserialize(values)
+        }
+
+        /// Every config key with its default value, sections which are
+        /// disabled by default being null
+        pub fn schema() -> serde_json::Value {
+            serde_json::to_value(Config::default()).expect("the default config serializes")
+        }
+
+        /// The removed keys which are set in this config. They are ignored, and
+        /// only accepted so that configs written for earlier versions still load.
+        pub fn deprecated_keys(&self) -> Vec<&'static str> {
+            let mut keys = vec![];
+            if self.channel_capacities.global_store_lookup.is_some() {
+                keys.push("channel_capacities.global_store_lookup");
+            }
+            keys
+        }
+
+        pub fn log_level(&self) -> Result<Level> {
+            let log_level = self.log_level.as_deref().unwrap_or(DEFAULT_LOG_LEVEL);
+            Level::from_str(log_level).map_err(|_| anyhow!("invalid log level {:?}", log_level))
+        }
+
+        /// This config, with the settings which can be changed without a restart
+        /// taken from the given config
+        pub fn with_reloadable_from(&self, new: &Config) -> Config {
+            let mut config = self.clone();
+            config.log_level = new.log_level.clone();
+
+            reload_network(&mut config.primary_network, &new.primary_network);
+            if let (Some(network), Some(new_network)) =
+                (&mut config.secondary_network, &new.secondary_network)
+            {
+                reload_network(network, new_network);
+            }
+
+            config.local_store.default_price_bounds = new.local_store.default_price_bounds.clone();
+            config.local_store.price_bounds = new.local_store.price_bounds.clone();
+
+            config
+        }
+
+        /// Names of the top-level sections which differ between the two configs
+        pub fn changed_sections(&self, other: &Config) -> Vec<&'static str> {
+            #[deny(unused_variables)]
+            let Config {
+                log_level,
+                log_format,
+                runtime,
+                channel_capacities,
+                primary_network,
+                secondary_network,
+                global_store,
+                local_store,
+                transactions_store,
+                publish_latency,
+                publisher_performance,
+                uptime,
+                update_status,
+                component_monitor,
+                anomaly_detector,
+                kafka,
+                redis_mirror,
+                price_history,
+                price_relay,
+                pythd_adapter,
+                pythd_api_server,
+                metrics_server,
+                remote_keypair_loader,
+                telemetry,
+                channel_monitor,
+                admin_api,
+                shutdown,
+                alerting,
+                reference_prices,
+                tenancy,
+                high_availability,
+                replication,
+                fault_injection,
+                startup_gate,
+            } = self;
+
+            let sections = [
+                ("log_level", log_level != &other.log_level),
+                ("log_format", log_format != &other.log_format),
+                ("runtime", runtime != &other.runtime),
+                (
+                    "channel_capacities",
+                    channel_capacities != &other.channel_capacities,
+                ),
+                ("primary_network", primary_network != &other.primary_network),
+                (
+                    "secondary_network",
+                    secondary_network != &other.secondary_network,
+                ),
+                ("global_store", global_store != &other.global_store),
+                ("local_store", local_store != &other.local_store),
+                (
+                    "transactions_store",
+                    transactions_store != &other.transactions_store,
+                ),
+                ("publish_latency", publish_latency != &other.publish_latency),
+                (
+                    "publisher_performance",
+                    publisher_performance != &other.publisher_performance,
+                ),
+                ("uptime", uptime != &other.uptime),
+                ("update_status", update_status != &other.update_status),
+                (
+                    "component_monitor",
+                    component_monitor != &other.component_monitor,
+                ),
+                (
+                    "anomaly_detector",
+                    anomaly_detector != &other.anomaly_detector,
+                ),
+                ("kafka", kafka != &other.kafka),
+                ("redis_mirror", redis_mirror != &other.redis_mirror),
+                ("price_history", price_history != &other.price_history),
+                ("price_relay", price_relay != &other.price_relay),
+                ("pythd_adapter", pythd_adapter != &other.pythd_adapter),
+                (
+                    "pythd_api_server",
+                    pythd_api_server != &other.pythd_api_server,
+                ),
+                ("metrics_server", metrics_server != &other.metrics_server),
+                (
+                    "remote_keypair_loader",
+                    remote_keypair_loader != &other.remote_keypair_loader,
+                ),
+                ("telemetry", telemetry != &other.telemetry),
+                ("channel_monitor", channel_monitor != &other.channel_monitor),
+                ("admin_api", admin_api != &other.admin_api),
+                ("shutdown", shutdown != &other.shutdown),
+                ("alerting", alerting != &other.alerting),
+                (
+                    "reference_prices",
+                    reference_prices != &other.reference_prices,
+                ),
+                ("tenancy", tenancy != &other.tenancy),
+                (
+                    "high_availability",
+                    high_availability != &other.high_availability,
+                ),
+                ("replication", replication != &other.replication),
+                ("fault_injection", fault_injection != &other.fault_injection),
+                ("startup_gate", startup_gate != &other.startup_gate),
+            ];
+
+            sections
+                .into_iter()
+                .filter(|(_, changed)| *changed)
+                .map(|(name, _)| name)
+                .collect()
+        }
+    }
+
+    /// Deserialize the config from the resolved values. Unknown keys are
+    /// rejected, reported with their path so that a typo doesn't silently
+    /// fall back to a default. Removed keys are kept as deprecated fields
+    /// instead, listed by `Config::deprecated_keys`.
+    fn deserialize(values: Map<String, Value>) -> Result<Config> {
+        serde_path_to_error::deserialize(Value::new(None, ValueKind::Table(values)))
+            .map_err(|err| anyhow!("{} at {}", err.inner(), err.path()))
+    }
+
+    /// The "AGENT_"-prefixed environment variables naming a top-level key, with
+    /// the prefix stripped. Other variables which happen to share the prefix
+    /// would be rejected as unknown keys.
+    fn top_level_env_vars() -> HashMap<String, String> {
+        let schema = Config::schema();
+        let keys = schema.as_object().expect("the config is a table");
+        env::vars()
+            .filter_map(|(name, value)| {
+                let key = name.to_lowercase().strip_prefix("agent_")?.to_string();
+                keys.contains_key(&key).then(|| (key, value))
+            })
+            .collect()
+    }
+
+    /// Resolve the values of the table and its children, replacing every
+    /// `<key>_file` entry by a `<key>` entry holding the contents of the file
+    fn resolve_table(table: &mut Map<String, Value>, prefix: &str) -> Result<()> {
+        for (key, value) in table.iter_mut() {
+            resolve_value(value, &format!("{}{}", prefix, key))?;
+        }
+
+        let file_keys = table
+            .keys()
+            .filter(|key| key.ends_with(FILE_KEY_SUFFIX))
+            .cloned()
+            .collect::<Vec<_>>();
+        for file_key in file_keys {
+            let key = file_key.trim_end_matches(FILE_KEY_SUFFIX).to_string();
+            if table.contains_key(&key) {
+                return Err(anyhow!(
+                    "only one of {}{} and {}{} may be set",
+                    prefix,
+                    key,
+                    prefix,
+                    file_key
+                ));
+            }
+
+            let path = table
+                .remove(&file_key)
+                .expect("file key is present")
+                .into_string()
+                .with_context(|| format!("{}{} must be a path", prefix, file_key))?;
+            let contents = fs::read_to_string(&path)
+                .with_context(|| format!("reading {}{} from {}", prefix, key, path))?;
+            table.insert(
+                key,
+                Value::new(Some(&path), ValueKind::String(contents.trim().to_string())),
+            );
+        }
+
+        Ok(())
+    }
+
+    fn resolve_value(value: &mut Value, key: &str) -> Result<()> {
+        match &mut value.kind {
+            ValueKind::String(string) => {
+                *string = interpolate_env_vars(string).with_context(|| format!("in {}", key))?;
+            }
+            ValueKind::Table(table) => resolve_table(table, &format!("{}.", key))?,
+            ValueKind::Array(array) => {
+                for (i, value) in array.iter_mut().enumerate() {
+                    resolve_value(value, &format!("{}[{}]", key, i))?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Replace every `${VAR}` in the string by the value of the environment variable
+    fn interpolate_env_vars(string: &str) -> Result<String> {
+        let mut interpolated = String::new();
+        let mut rest = string;
+        while let Some(start) = rest.find("${") {
+            interpolated.push_str(&rest[..start]);
+            let (name, after) = rest[start + 2..]
+                .split_once('}')
+                .ok_or_else(|| anyhow!("unterminated \"${{\" in {:?}", string))?;
+            let value =
+                env::var(name).map_err(|_| anyhow!("environment variable {} is not set", name))?;
+            interpolated.push_str(&value);
+            rest = after;
+        }
+        interpolated.push_str(rest);
+        Ok(interpolated)
+    }
+
+    /// Take the settings of the network which can be changed without a restart
+    /// from the given config
+    fn reload_network(config: &mut network::Config, new: &network::Config) {
+        config.oracle.poll_interval_duration = new.oracle.poll_interval_duration;
+        config.oracle.metadata_poll_interval_duration = new.oracle.metadata_poll_interval_duration;
+        config.key_store.publish_keypair_path = new.key_store.publish_keypair_path.clone();
+
+        let exporter = &mut config.exporter;
+        exporter.publish_interval_duration = new.exporter.publish_interval_duration;
+        exporter.staleness_threshold = new.exporter.staleness_threshold;
+        exporter.unchanged_publish_threshold = new.exporter.unchanged_publish_threshold;
+        exporter.max_batch_size = new.exporter.max_batch_size;
+        exporter.compute_unit_limit = new.exporter.compute_unit_limit;
+        exporter.compute_unit_price_micro_lamports = new.exporter.compute_unit_price_micro_lamports;
+
+        // The symbol filters of the published updates
+        exporter.conf_floor = new.exporter.conf_floor.clone();
+        if let (Some(lazer), Some(new_lazer)) = (&mut exporter.lazer, &new.exporter.lazer) {
+            lazer.feeds = new_lazer.feeds.clone();
         }
     }
 
     /// Capacities of the channels top-level components use to communicate
-    #[derive(Deserialize, Debug)]
+    #[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+    #[serde(default, deny_unknown_fields)]
     pub struct ChannelCapacities {
         /// Capacity of the channel used to broadcast shutdown events to all components
         pub shutdown:                 usize,
@@ -254,16 +1317,27 @@ pub mod config {
         pub primary_oracle_updates:   usize,
         /// Capacity of the channel used to send updates from the secondary Oracle to the Global Store
         pub secondary_oracle_updates: usize,
-        /// Capacity of the channel the Pythd API Adapter uses to send lookup requests to the Global Store
-        pub global_store_lookup:      usize,
         /// Capacity of the channel the Pythd API Adapter uses to communicate with the Local Store
         pub local_store_lookup:       usize,
+        /// Capacity of the channel on which the Global Store broadcasts events to its subscribers
+        pub global_store_events:      usize,
         /// Capacity of the channel on which the Local Store receives messages
         pub local_store:              usize,
+        /// Capacity of the channel on which the Local Store broadcasts accepted updates to its subscribers
+        pub local_store_events:       usize,
+        /// Capacity of the channel on which the Transactions Store receives messages
+        pub transactions_store:       usize,
+        /// Capacity of the channel on which the Publish Latency Tracker receives updates from the Local Store
+        pub publish_latency:          usize,
         /// Capacity of the channel on which the Pythd API Adapter receives messages
         pub pythd_adapter:            usize,
-        /// Capacity of the slog logging channel. Adjust this value if you see complaints about channel capacity from slog
+        /// How many log lines are buffered for output. Lines logged while the buffer is
+        /// full are dropped, so increase this value if the output falls behind bursts of logs.
         pub logger_buffer:            usize,
+        /// Deprecated and ignored: the Global Store is read from a shared snapshot
+        /// rather than over a channel. Still accepted so that existing configs load.
+        #[serde(default, skip_serializing)]
+        pub global_store_lookup:      Option<usize>,
     }
 
     impl Default for ChannelCapacities {
@@ -272,12 +1346,220 @@ pub mod config {
                 shutdown:                 10000,
                 primary_oracle_updates:   10000,
                 secondary_oracle_updates: 10000,
-                global_store_lookup:      10000,
                 local_store_lookup:       10000,
+                global_store_events:      1000,
                 local_store:              10000,
+                local_store_events:       1000,
+                transactions_store:       10000,
+                publish_latency:          10000,
                 pythd_adapter:            10000,
                 logger_buffer:            10000,
+                global_store_lookup:      None,
             }
         }
     }
+
+    #[cfg(test)]
+    mod tests {
+        use {
+            super::{
+                config_rs::{
+                    Map,
+                    Value,
+                    ValueKind,
+                },
+                deserialize,
+                interpolate_env_vars,
+                resolve_table,
+                Config,
+            },
+            crate::agent::solana::exporter::{
+                ConfFloor,
+                ConfFloorConfig,
+            },
+            rand::Rng,
+            std::{
+                collections::HashMap,
+                env,
+                time::Duration,
+            },
+        };
+
+        fn symbol_floors(symbols: &[&str]) -> ConfFloorConfig {
+            ConfFloorConfig {
+                symbol_floors: symbols
+                    .iter()
+                    .enumerate()
+                    .map(|(i, symbol)| {
+                        let floor = ConfFloor {
+                            min_conf:       i as u64 + 1,
+                            min_conf_ratio: 0.0,
+                        };
+                        (symbol.to_string(), floor)
+                    })
+                    .collect::<HashMap<_, _>>(),
+                ..ConfFloorConfig::default()
+            }
+        }
+
+        #[test]
+        fn test_only_the_reloadable_settings_are_reloaded() {
+            let current = Config::default();
+            let mut new = Config::default();
+            new.primary_network.exporter.publish_interval_duration = Duration::from_secs(5);
+            new.primary_network.exporter.conf_floor = Some(symbol_floors(&["Crypto.BTC/USD"]));
+            new.primary_network.rpc_url = "http://rpc.example.com".to_string();
+            new.metrics_server.bind_address = "127.0.0.1:9999".parse().unwrap();
+
+            let effective = current.with_reloadable_from(&new);
+            assert_eq!(
+                effective.primary_network.exporter,
+                new.primary_network.exporter
+            );
+            assert_eq!(
+                effective.primary_network.rpc_url,
+                current.primary_network.rpc_url
+            );
+            assert_eq!(effective.metrics_server, current.metrics_server);
+
+            assert_eq!(
+                current.changed_sections(&effective),
+                vec!["primary_network"]
+            );
+            assert_eq!(
+                effective.changed_sections(&new),
+                vec!["primary_network", "metrics_server"]
+            );
+        }
+
+        #[test]
+        fn test_changed_sections_compare_by_value() {
+            let mut config = Config::default();
+            config.primary_network.exporter.conf_floor = Some(symbol_floors(&[
+                "Crypto.BTC/USD",
+                "Crypto.ETH/USD",
+                "Crypto.SOL/USD",
+            ]));
+            // The same floors, inserted in another order
+            let mut other = config.clone();
+            let floors = config.primary_network.exporter.conf_floor.as_ref().unwrap();
+            other.primary_network.exporter.conf_floor = Some(ConfFloorConfig {
+                symbol_floors: floors
+                    .symbol_floors
+                    .iter()
+                    .rev()
+                    .map(|(symbol, floor)| (symbol.clone(), floor.clone()))
+                    .collect(),
+                ..floors.clone()
+            });
+            assert!(config.changed_sections(&other).is_empty());
+
+            other.pythd_api_server.listen_address = "127.0.0.1:9000".to_string();
+            assert_eq!(config.changed_sections(&other), vec!["pythd_api_server"]);
+        }
+
+        #[test]
+        fn test_unknown_keys_are_rejected_with_their_path() {
+            let table = |entries: Vec<(&str, Value)>| {
+                Value::new(
+                    None,
+                    ValueKind::Table(
+                        entries
+                            .into_iter()
+                            .map(|(key, value)| (key.to_string(), value))
+                            .collect(),
+                    ),
+                )
+            };
+            let values = |key: &str| -> Map<String, Value> {
+                let oracle = table(vec![(key, Value::new(None, "5s"))]);
+                let network = table(vec![("oracle", oracle)]);
+                [("primary_network".to_string(), network)].into()
+            };
+
+            let config = deserialize(values("poll_interval_duration")).unwrap();
+            assert_eq!(
+                config
+                    .primary_network
+                    .oracle
+                    .poll_interval_duration
+                    .as_secs(),
+                5
+            );
+
+            let err = deserialize(values("poll_interval")).unwrap_err();
+            let message = err.to_string();
+            assert!(message.contains("unknown field `poll_interval`"));
+            assert!(message.contains("at primary_network.oracle"));
+        }
+
+        #[test]
+        fn test_removed_channel_capacity_is_accepted() {
+            let channel_capacities = Value::new(
+                None,
+                ValueKind::Table(
+                    [("global_store_lookup".to_string(), Value::new(None, 10000))].into(),
+                ),
+            );
+            let values = [("channel_capacities".to_string(), channel_capacities)].into();
+
+            let config = deserialize(values).unwrap();
+            assert_eq!(config.channel_capacities.global_store_lookup, Some(10000));
+            assert_eq!(
+                config.deprecated_keys(),
+                vec!["channel_capacities.global_store_lookup"]
+            );
+            assert!(Config::default().deprecated_keys().is_empty());
+            assert!(Config::schema()["channel_capacities"]
+                .get("global_store_lookup")
+                .is_none());
+        }
+
+        #[test]
+        fn test_interpolate_env_vars() {
+            env::set_var("PYTH_AGENT_TEST_API_KEY", "secret");
+            assert_eq!(
+                interpolate_env_vars("https://rpc.example.com/${PYTH_AGENT_TEST_API_KEY}/")
+                    .unwrap(),
+                "https://rpc.example.com/secret/"
+            );
+            assert_eq!(
+                interpolate_env_vars("no variables").unwrap(),
+                "no variables"
+            );
+            assert!(interpolate_env_vars("${PYTH_AGENT_TEST_UNSET_VAR}").is_err());
+            assert!(interpolate_env_vars("${PYTH_AGENT_TEST_API_KEY").is_err());
+        }
+
+        #[test]
+        fn test_resolve_secret_file() {
+            let path = env::temp_dir().join(format!(
+                "pyth-agent-secret-{}",
+                rand::thread_rng().gen::<u64>()
+            ));
+            std::fs::write(&path, "https://rpc.example.com/secret\n").unwrap();
+
+            let string = |value: &str| Value::new(None, ValueKind::String(value.to_string()));
+            let mut network = Map::new();
+            network.insert("rpc_url_file".to_string(), string(path.to_str().unwrap()));
+            let mut table = Map::new();
+            table.insert(
+                "primary_network".to_string(),
+                Value::new(None, ValueKind::Table(network)),
+            );
+            resolve_table(&mut table, "").unwrap();
+            std::fs::remove_file(&path).unwrap();
+
+            let network = table
+                .remove("primary_network")
+                .unwrap()
+                .into_table()
+                .unwrap();
+            assert!(!network.contains_key("rpc_url_file"));
+            assert_eq!(
+                network["rpc_url"].clone().into_string().unwrap(),
+                "https://rpc.example.com/secret"
+            );
+        }
+    }
 }
@@ -0,0 +1,375 @@
+// The Admin API lets operators change the behaviour of a running agent without a
+// restart. It is served on a separate address from the metrics server, and every
+// request must carry the configured bearer token; the API is disabled without one.
+// The read-only credentials of the dashboard (`metrics_server.auth`) are also
+// accepted on the GET endpoints, but not on those changing the agent.
+//
+// - GET /log_level returns the global log level and the levels of modules
+//   logging at a different level.
+// - PUT /log_level changes them, e.g. with the body
+//   {"global": "info", "modules": {"pyth_agent::agent::solana::exporter": "debug"}}.
+//   A module set to null follows the global level again.
+// - GET /publish_pause returns whether publishing is paused globally, and the
+//   symbols whose publishing is paused.
+// - POST /publish_pause/pause and POST /publish_pause/resume pause or resume the
+//   symbols of the body, e.g. {"symbols": ["Crypto.BTC/USD"]}, or all publishing
+//   without a body. Resuming all publishing also resumes the paused symbols.
+// - GET /api_connections lists the open connections of the pythd API server, with
+//   their remote address, the publish keys and symbols they updated, when they last
+//   sent a message, their subscription counts and their queued notifications.
+// - GET /dump returns a snapshot of the state of the agent for support tickets,
+//   as described in the dump module.
+// - POST /startup_gate/open opens the startup gate, so that the pythd API server
+//   accepts connections without waiting for the first poll of the oracle.
+use {
+    super::{
+        dump::{
+            redacted,
+            DumpSources,
+        },
+        http_auth::{
+            self,
+            Authenticator,
+            Role,
+        },
+        logging::LogLevel,
+        publish_pause::PublishPause,
+        pythd::connections::{
+            ApiConnections,
+            ConnectionInfo,
+        },
+        startup_gate::StartupGate,
+        store::global,
+    },
+    serde::{
+        Deserialize,
+        Serialize,
+    },
+    std::{
+        collections::{
+            BTreeMap,
+            HashMap,
+        },
+        convert::Infallible,
+        fmt,
+        net::SocketAddr,
+        str::FromStr,
+    },
+    tokio::task::JoinHandle,
+    tracing::Level,
+    warp::{
+        hyper::{
+            body::Bytes,
+            StatusCode,
+        },
+        reply::{
+            self,
+            Reply,
+        },
+        Filter,
+    },
+};
+
+#[derive(Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Address on which the Admin API is served
+    pub bind_address: SocketAddr,
+    /// Bearer token which must be sent with every request. The Admin API is
+    /// disabled when not set.
+    pub auth_token:   Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bind_address: "127.0.0.1:9002".parse().unwrap(),
+            auth_token:   None,
+        }
+    }
+}
+
+impl fmt::Debug for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Config")
+            .field("bind_address", &self.bind_address)
+            .field("auth_token", &redacted(self.auth_token.as_deref()))
+            .finish()
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct LogLevelsResponse {
+    global:  String,
+    modules: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LogLevelsRequest {
+    global:  Option<String>,
+    #[serde(default)]
+    modules: HashMap<String, Option<String>>,
+}
+
+/// A connection of the pythd API server, with its updates by symbol
+#[derive(Debug, Serialize)]
+struct ApiConnectionResponse {
+    #[serde(flatten)]
+    info:    ConnectionInfo,
+    /// Number of updates submitted, by symbol
+    symbols: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct PublishPauseRequest {
+    #[serde(default)]
+    symbols: Vec<String>,
+}
+
+/// Serve the Admin API. `log_level` is None when the log level is set by the
+/// RUST_LOG environment variable, in which case it cannot be changed.
+/// `read_only_credentials` are accepted on the GET endpoints.
+pub fn spawn_server(
+    config: Config,
+    read_only_credentials: Option<http_auth::Credentials>,
+    log_level: Option<LogLevel>,
+    publish_pause: PublishPause,
+    api_connections: ApiConnections,
+    global_store_reader: global::SnapshotReader,
+    dump_sources: DumpSources,
+    startup_gate: StartupGate,
+) -> JoinHandle<()> {
+    // The requests are handled outside of the server's task
+    let span = info_span!("admin_api");
+    tokio::spawn(async move {
+        let auth_token = match config.auth_token {
+            Some(auth_token) => auth_token,
+            None => return,
+        };
+        let authenticator =
+            Authenticator::new(read_only_credentials.as_ref(), Some(auth_token.as_str()));
+        let read_only = authenticator.authorized(Role::ReadOnly);
+        let authorized = authenticator.authorized(Role::Admin);
+
+        let get_log_level = warp::path!("log_level")
+            .and(warp::get())
+            .and(read_only.clone())
+            .map({
+                let log_level = log_level.clone();
+                move |authorized| {
+                    if !authorized {
+                        return unauthorized();
+                    }
+                    match &log_level {
+                        Some(log_level) => log_levels_reply(log_level),
+                        None => set_by_rust_log(),
+                    }
+                }
+            });
+
+        let put_log_level = warp::path!("log_level")
+            .and(warp::put())
+            .and(authorized.clone())
+            .and(warp::body::content_length_limit(16 * 1024))
+            .and(warp::body::json())
+            .map({
+                let span = span.clone();
+                move |authorized, request: LogLevelsRequest| {
+                    let _span = span.enter();
+                    if !authorized {
+                        return unauthorized();
+                    }
+                    let log_level = match &log_level {
+                        Some(log_level) => log_level,
+                        None => return set_by_rust_log(),
+                    };
+
+                    if let Err(message) = set_log_levels(log_level, &request) {
+                        return Box::new(reply::with_status(message, StatusCode::BAD_REQUEST))
+                            as Box<dyn Reply>;
+                    }
+                    info!(?request, "Admin API: log levels changed");
+                    log_levels_reply(log_level)
+                }
+            });
+
+        let get_publish_pause = warp::path!("publish_pause")
+            .and(warp::get())
+            .and(read_only.clone())
+            .map({
+                let publish_pause = publish_pause.clone();
+                move |authorized| {
+                    if !authorized {
+                        return unauthorized();
+                    }
+                    publish_pause_reply(&publish_pause)
+                }
+            });
+
+        let get_api_connections = warp::path!("api_connections")
+            .and(warp::get())
+            .and(read_only.clone())
+            .map(move |authorized| {
+                if !authorized {
+                    return unauthorized();
+                }
+                api_connections_reply(&api_connections, &global_store_reader)
+            });
+
+        let get_dump = warp::path!("dump")
+            .and(warp::get())
+            .and(read_only)
+            .and_then(move |authorized| {
+                let dump_sources = dump_sources.clone();
+                async move {
+                    if !authorized {
+                        return Ok::<_, Infallible>(unauthorized());
+                    }
+                    Ok(match dump_sources.dump().await {
+                        Ok(dump) => Box::new(reply::json(&dump)) as Box<dyn Reply>,
+                        Err(err) => Box::new(reply::with_status(
+                            format!("taking the dump failed: {:#}", err),
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                        )),
+                    })
+                }
+            });
+
+        let post_startup_gate = warp::path!("startup_gate" / "open")
+            .and(warp::post())
+            .and(authorized.clone())
+            .map({
+                let span = span.clone();
+                move |authorized| {
+                    let _span = span.enter();
+                    if !authorized {
+                        return unauthorized();
+                    }
+                    startup_gate.open("opened through the Admin API");
+                    Box::new(reply::json(&serde_json::json!({ "open": true }))) as Box<dyn Reply>
+                }
+            });
+
+        // The body is optional, pausing or resuming all publishing when empty
+        let post_publish_pause = warp::path!("publish_pause" / String)
+            .and(warp::post())
+            .and(authorized)
+            .and(warp::body::content_length_limit(16 * 1024))
+            .and(warp::body::bytes())
+            .map(move |action: String, authorized, body: Bytes| {
+                let _span = span.enter();
+                if !authorized {
+                    return unauthorized();
+                }
+                let request = if body.is_empty() {
+                    PublishPauseRequest::default()
+                } else {
+                    match serde_json::from_slice::<PublishPauseRequest>(&body) {
+                        Ok(request) => request,
+                        Err(err) => {
+                            return Box::new(reply::with_status(
+                                format!("invalid request: {}", err),
+                                StatusCode::BAD_REQUEST,
+                            )) as Box<dyn Reply>
+                        }
+                    }
+                };
+
+                match action.as_str() {
+                    "pause" => publish_pause.pause(&request.symbols),
+                    "resume" => publish_pause.resume(&request.symbols),
+                    _ => {
+                        return Box::new(reply::with_status(
+                            format!("unknown action {:?}", action),
+                            StatusCode::NOT_FOUND,
+                        ))
+                    }
+                }
+                info!(%action, symbols = ?request.symbols, "Admin API: publish pause changed");
+                publish_pause_reply(&publish_pause)
+            });
+
+        warp::serve(
+            get_log_level
+                .or(put_log_level)
+                .or(get_publish_pause)
+                .or(post_publish_pause)
+                .or(get_api_connections)
+                .or(get_dump)
+                .or(post_startup_gate),
+        )
+        .bind(config.bind_address)
+        .await;
+    })
+}
+
+/// Apply the requested log levels, if they are all valid
+fn set_log_levels(log_level: &LogLevel, request: &LogLevelsRequest) -> Result<(), String> {
+    let parse =
+        |level: &str| Level::from_str(level).map_err(|_| format!("invalid log level {:?}", level));
+
+    let global = request.global.as_deref().map(parse).transpose()?;
+    let modules = request
+        .modules
+        .iter()
+        .map(|(module, level)| Ok((module, level.as_deref().map(parse).transpose()?)))
+        .collect::<Result<Vec<_>, String>>()?;
+
+    if let Some(global) = global {
+        log_level.set(global);
+    }
+    for (module, level) in modules {
+        log_level.set_module(module, level);
+    }
+    Ok(())
+}
+
+fn log_levels_reply(log_level: &LogLevel) -> Box<dyn Reply> {
+    Box::new(reply::json(&LogLevelsResponse {
+        global:  log_level.get().as_str().to_lowercase(),
+        modules: log_level
+            .modules()
+            .into_iter()
+            .map(|(module, level)| (module, level.as_str().to_lowercase()))
+            .collect(),
+    }))
+}
+
+fn publish_pause_reply(publish_pause: &PublishPause) -> Box<dyn Reply> {
+    Box::new(reply::json(publish_pause.get().as_ref()))
+}
+
+fn api_connections_reply(
+    api_connections: &ApiConnections,
+    global_store_reader: &global::SnapshotReader,
+) -> Box<dyn Reply> {
+    let snapshot = global_store_reader.load();
+    let symbol_index = &snapshot.account_metadata.symbol_index;
+    let connections = api_connections
+        .list()
+        .into_iter()
+        .map(|info| {
+            let mut symbols = BTreeMap::new();
+            for (price_account, updates) in &info.updates {
+                let symbol = match price_account.parse() {
+                    Ok(price_key) => symbol_index.price_name(&price_key),
+                    Err(_) => price_account.clone(),
+                };
+                *symbols.entry(symbol).or_default() += updates;
+            }
+            ApiConnectionResponse { info, symbols }
+        })
+        .collect::<Vec<_>>();
+    Box::new(reply::json(&connections))
+}
+
+fn unauthorized() -> Box<dyn Reply> {
+    Box::new(reply::with_status("Unauthorized", StatusCode::UNAUTHORIZED))
+}
+
+fn set_by_rust_log() -> Box<dyn Reply> {
+    Box::new(reply::with_status(
+        "The log level is set by the RUST_LOG environment variable",
+        StatusCode::CONFLICT,
+    ))
+}
@@ -0,0 +1,759 @@
+// The Alerter notifies operators of publishing problems directly, without relying on
+// alerts configured elsewhere on top of the metrics. It periodically evaluates the
+// configured rules against the state of the agent:
+// - prices whose on-chain publish time is older than a threshold, from the Global Store
+// - the share of recent transactions which landed, from the Transactions Store
+// - the balance of each network's publish key, from its RPC node
+// - updates rejected by the price bounds or reference prices of the Local Store
+// - our components missing from the price accounts, from the Component Monitor
+// - anomalies of the price feeds, from the Anomaly Detector
+// and posts the alerts which start firing, and those which resolve, to webhooks
+// (Slack, PagerDuty, or any HTTP endpoint). An alert is only notified once while it
+// is firing, and not again if it starts firing again within the cooldown.
+use {
+    crate::agent::{
+        anomaly_detector,
+        component_monitor,
+        dump::redacted,
+        solana::{
+            instrumented_rpc,
+            network,
+        },
+        store::{
+            global,
+            local,
+            transactions::{
+                self,
+                TransactionStatus,
+            },
+        },
+    },
+    anyhow::{
+        anyhow,
+        Context,
+        Result,
+    },
+    chrono::Utc,
+    serde::{
+        Deserialize,
+        Serialize,
+    },
+    serde_json::json,
+    solana_client::nonblocking::rpc_client::RpcClient,
+    solana_sdk::{
+        commitment_config::CommitmentConfig,
+        native_token::LAMPORTS_PER_SOL,
+        pubkey::Pubkey,
+    },
+    std::{
+        collections::{
+            HashMap,
+            HashSet,
+        },
+        fmt,
+        time::Duration,
+    },
+    tokio::{
+        sync::{
+            mpsc,
+            oneshot,
+        },
+        task::JoinHandle,
+        time::{
+            self,
+            Instant,
+            Interval,
+        },
+    },
+    tracing::Instrument,
+};
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Webhooks the alerts are posted to. Alerting is disabled when empty.
+    pub webhooks:                     Vec<WebhookConfig>,
+    /// Duration of the interval at which the rules are evaluated
+    #[serde(with = "humantime_serde")]
+    pub evaluation_interval_duration: Duration,
+    /// An alert which starts firing again within this time of its previous
+    /// notification is not notified again
+    #[serde(with = "humantime_serde")]
+    pub cooldown:                     Duration,
+    /// Timeout of the webhook requests
+    #[serde(with = "humantime_serde")]
+    pub webhook_timeout:              Duration,
+    pub rules:                        Rules,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            webhooks:                     vec![],
+            evaluation_interval_duration: Duration::from_secs(10),
+            cooldown:                     Duration::from_secs(300),
+            webhook_timeout:              Duration::from_secs(10),
+            rules:                        Default::default(),
+        }
+    }
+}
+
+/// The rules evaluated, each disabled when not set
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Rules {
+    /// Alert when the on-chain publish time of a price is older than this
+    #[serde(with = "humantime_serde")]
+    pub stale_threshold:               Option<Duration>,
+    /// Symbols checked for staleness, e.g. "Crypto.BTC/USD". All the prices
+    /// are checked when empty.
+    pub stale_symbols:                 Vec<String>,
+    /// Alert when a lower percentage of a network's recent transactions landed
+    pub min_landing_rate_percent:      Option<f64>,
+    /// Transactions not confirmed within this time of being sent are counted
+    /// as not landed. More recent transactions are not counted yet.
+    #[serde(with = "humantime_serde")]
+    pub landing_timeout:               Duration,
+    /// Fewer counted transactions than this are not enough to evaluate the
+    /// landing rate
+    pub landing_rate_min_transactions: usize,
+    /// Alert when the publish key of a network holds fewer SOL than this
+    pub min_balance_sol:               Option<f64>,
+    /// Alert when the Local Store rejects updates of a price for being
+    /// outside its price bounds or too far from its reference price
+    pub price_bounds_rejections:       bool,
+    /// Alert when the Component Monitor reports one of our components missing
+    /// from a price account, which requires the Component Monitor
+    pub missing_components:            bool,
+    /// Alert when the Anomaly Detector flags an anomaly of a price, which
+    /// requires the Anomaly Detector
+    pub price_anomalies:               bool,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self {
+            stale_threshold:               None,
+            stale_symbols:                 vec![],
+            min_landing_rate_percent:      None,
+            landing_timeout:               Duration::from_secs(30),
+            landing_rate_min_transactions: 10,
+            min_balance_sol:               None,
+            price_bounds_rejections:       false,
+            missing_components:            false,
+            price_anomalies:               false,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookConfig {
+    pub kind:        WebhookKind,
+    /// URL the alerts are posted to, e.g. a Slack incoming webhook, or
+    /// https://events.pagerduty.com/v2/enqueue
+    pub url:         String,
+    /// Routing key of the PagerDuty integration
+    pub routing_key: Option<String>,
+}
+
+/// The URL of a webhook is a secret for Slack, so only its host is shown
+impl fmt::Debug for WebhookConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebhookConfig")
+            .field("kind", &self.kind)
+            .field("url", &instrumented_rpc::endpoint_label(&self.url))
+            .field("routing_key", &redacted(self.routing_key.as_deref()))
+            .finish()
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookKind {
+    /// Posts a message to a Slack incoming webhook
+    Slack,
+    /// Triggers and resolves PagerDuty incidents, through the Events API v2
+    PagerDuty,
+    /// Posts the alert and its state as JSON
+    Generic,
+}
+
+#[derive(Clone, Copy, Serialize, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Rule {
+    StalePrice,
+    LandingRate,
+    LowBalance,
+    PriceBoundsRejections,
+    MissingComponent,
+    PriceAnomaly,
+}
+
+impl Rule {
+    fn name(&self) -> &'static str {
+        match self {
+            Rule::StalePrice => "stale_price",
+            Rule::LandingRate => "landing_rate",
+            Rule::LowBalance => "low_balance",
+            Rule::PriceBoundsRejections => "price_bounds_rejections",
+            Rule::MissingComponent => "missing_component",
+            Rule::PriceAnomaly => "price_anomaly",
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Debug, PartialEq)]
+pub struct Alert {
+    /// Identifies the alert for deduplication, e.g. "stale_price:Crypto.BTC/USD"
+    pub key:     String,
+    pub rule:    Rule,
+    pub summary: String,
+}
+
+impl Alert {
+    fn new(rule: Rule, subject: impl std::fmt::Display, summary: String) -> Self {
+        Alert {
+            key: format!("{}:{}", rule.name(), subject),
+            rule,
+            summary,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertState {
+    Firing,
+    Resolved,
+}
+
+/// A network whose publish key balance and transactions are checked
+pub struct Network {
+    pub name:   String,
+    pub config: network::Config,
+}
+
+/// Firing alerts, deduplicated by key
+#[derive(Default)]
+struct Alerts {
+    /// Firing alerts by key, with whether they were notified when they started firing
+    firing:        HashMap<String, (Alert, bool)>,
+    /// When each alert was last notified as firing
+    last_notified: HashMap<String, Instant>,
+}
+
+impl Alerts {
+    /// Update the firing alerts with the evaluated ones, returning the
+    /// notifications to send. The firing alerts of the rules which could not be
+    /// evaluated keep firing.
+    fn evaluate(
+        &mut self,
+        alerts: Vec<Alert>,
+        unevaluated: &HashSet<Rule>,
+        cooldown: Duration,
+        now: Instant,
+    ) -> Vec<(Alert, AlertState)> {
+        let mut notifications = vec![];
+
+        let keys = alerts
+            .iter()
+            .map(|alert| alert.key.clone())
+            .collect::<HashSet<_>>();
+        self.firing.retain(|key, (alert, notified)| {
+            let firing = keys.contains(key) || unevaluated.contains(&alert.rule);
+            if !firing && *notified {
+                notifications.push((alert.clone(), AlertState::Resolved));
+            }
+            firing
+        });
+
+        for alert in alerts {
+            if let Some((firing_alert, _)) = self.firing.get_mut(&alert.key) {
+                *firing_alert = alert;
+                continue;
+            }
+            let notified = self
+                .last_notified
+                .get(&alert.key)
+                .map_or(true, |last_notified| now - *last_notified >= cooldown);
+            if notified {
+                self.last_notified.insert(alert.key.clone(), now);
+                notifications.push((alert.clone(), AlertState::Firing));
+            }
+            self.firing.insert(alert.key.clone(), (alert, notified));
+        }
+
+        notifications
+    }
+}
+
+pub fn spawn_alerter(
+    config: Config,
+    networks: Vec<Network>,
+    global_store_reader: global::SnapshotReader,
+    local_store_tx: mpsc::Sender<local::Message>,
+    transactions_store_tx: mpsc::Sender<transactions::Message>,
+    component_monitor_tx: Option<mpsc::Sender<component_monitor::Message>>,
+    anomaly_detector_tx: Option<mpsc::Sender<anomaly_detector::Message>>,
+) -> Result<JoinHandle<()>> {
+    let mut alerter = Alerter::new(
+        config,
+        networks,
+        global_store_reader,
+        local_store_tx,
+        transactions_store_tx,
+        component_monitor_tx,
+        anomaly_detector_tx,
+    )?;
+    Ok(tokio::spawn(
+        async move { alerter.run().await }.instrument(info_span!("alerter")),
+    ))
+}
+
+struct Alerter {
+    config:                Config,
+    /// The networks, with the RPC clients their balances are read from
+    networks:              Vec<(Network, RpcClient)>,
+    global_store_reader:   global::SnapshotReader,
+    local_store_tx:        mpsc::Sender<local::Message>,
+    transactions_store_tx: mpsc::Sender<transactions::Message>,
+    component_monitor_tx:  Option<mpsc::Sender<component_monitor::Message>>,
+    anomaly_detector_tx:   Option<mpsc::Sender<anomaly_detector::Message>>,
+    evaluation_interval:   Interval,
+    client:                reqwest::Client,
+    alerts:                Alerts,
+}
+
+impl Alerter {
+    fn new(
+        config: Config,
+        networks: Vec<Network>,
+        global_store_reader: global::SnapshotReader,
+        local_store_tx: mpsc::Sender<local::Message>,
+        transactions_store_tx: mpsc::Sender<transactions::Message>,
+        component_monitor_tx: Option<mpsc::Sender<component_monitor::Message>>,
+        anomaly_detector_tx: Option<mpsc::Sender<anomaly_detector::Message>>,
+    ) -> Result<Self> {
+        for webhook in &config.webhooks {
+            if webhook.kind == WebhookKind::PagerDuty && webhook.routing_key.is_none() {
+                return Err(anyhow!(
+                    "alerting: PagerDuty webhook {} has no routing_key",
+                    webhook.url
+                ));
+            }
+        }
+        if config.rules.missing_components && component_monitor_tx.is_none() {
+            return Err(anyhow!(
+                "alerting: the missing_components rule requires the [component_monitor] section"
+            ));
+        }
+        if config.rules.price_anomalies && anomaly_detector_tx.is_none() {
+            return Err(anyhow!(
+                "alerting: the price_anomalies rule requires the [anomaly_detector] section"
+            ));
+        }
+        let client = reqwest::Client::builder()
+            .timeout(config.webhook_timeout)
+            .build()
+            .context("building alerting HTTP client")?;
+        let networks = networks
+            .into_iter()
+            .map(|network| {
+                let rpc_client = instrumented_rpc::new_rpc_client(
+                    &network.name,
+                    &network.config.rpc_url,
+                    network.config.rpc_timeout,
+                    CommitmentConfig::confirmed(),
+                );
+                (network, rpc_client)
+            })
+            .collect();
+        Ok(Alerter {
+            evaluation_interval: time::interval(config.evaluation_interval_duration),
+            config,
+            networks,
+            global_store_reader,
+            local_store_tx,
+            transactions_store_tx,
+            component_monitor_tx,
+            anomaly_detector_tx,
+            client,
+            alerts: Alerts::default(),
+        })
+    }
+
+    async fn run(&mut self) {
+        loop {
+            self.evaluation_interval.tick().await;
+            self.evaluate().await;
+        }
+    }
+
+    async fn evaluate(&mut self) {
+        let mut alerts = vec![];
+        let mut unevaluated = HashSet::new();
+        let rules = self.config.rules.clone();
+
+        if let Some(stale_threshold) = rules.stale_threshold {
+            alerts.extend(self.stale_prices(stale_threshold, &rules.stale_symbols));
+        }
+        if let Some(min_landing_rate_percent) = rules.min_landing_rate_percent {
+            match self.low_landing_rates(min_landing_rate_percent).await {
+                Ok(low_landing_rates) => alerts.extend(low_landing_rates),
+                Err(err) => {
+                    error!(error = ?err, "alerting: could not evaluate landing rate: {:#}", err);
+                    unevaluated.insert(Rule::LandingRate);
+                }
+            }
+        }
+        if let Some(min_balance_sol) = rules.min_balance_sol {
+            match self.low_balances(min_balance_sol).await {
+                Ok(low_balances) => alerts.extend(low_balances),
+                Err(err) => {
+                    error!(error = ?err, "alerting: could not evaluate balances: {:#}", err);
+                    unevaluated.insert(Rule::LowBalance);
+                }
+            }
+        }
+        if rules.price_bounds_rejections {
+            match self.price_bounds_rejections().await {
+                Ok(rejections) => alerts.extend(rejections),
+                Err(err) => {
+                    error!(error = ?err, "alerting: could not evaluate rejections: {:#}", err);
+                    unevaluated.insert(Rule::PriceBoundsRejections);
+                }
+            }
+        }
+        if rules.missing_components {
+            match self.missing_components().await {
+                Ok(missing_components) => alerts.extend(missing_components),
+                Err(err) => {
+                    error!(error = ?err, "alerting: could not evaluate components: {:#}", err);
+                    unevaluated.insert(Rule::MissingComponent);
+                }
+            }
+        }
+        if rules.price_anomalies {
+            match self.price_anomalies().await {
+                Ok(price_anomalies) => alerts.extend(price_anomalies),
+                Err(err) => {
+                    error!(error = ?err, "alerting: could not evaluate anomalies: {:#}", err);
+                    unevaluated.insert(Rule::PriceAnomaly);
+                }
+            }
+        }
+
+        let notifications =
+            self.alerts
+                .evaluate(alerts, &unevaluated, self.config.cooldown, Instant::now());
+        for (alert, state) in notifications {
+            warn!(key = %alert.key, ?state, summary = %alert.summary, "alert");
+            for webhook in &self.config.webhooks {
+                if let Err(err) = self.notify(webhook, &alert, state).await {
+                    error!(
+                        error = ?err,
+                        kind = ?webhook.kind,
+                        host = %instrumented_rpc::endpoint_label(&webhook.url),
+                        "alerting: could not post alert: {:#}",
+                        err
+                    );
+                }
+            }
+        }
+    }
+
+    fn stale_prices(&self, stale_threshold: Duration, symbols: &[String]) -> Vec<Alert> {
+        let snapshot = self.global_store_reader.load();
+        let now = Utc::now().timestamp();
+        let mut alerts = vec![];
+        for (price_key, price) in &snapshot.account_data.price_accounts {
+            let symbol = snapshot.account_metadata.symbol_index.price_name(price_key);
+            if !symbols.is_empty() && !symbols.contains(&symbol) {
+                continue;
+            }
+            let age = now - price.timestamp;
+            if age > stale_threshold.as_secs() as i64 {
+                alerts.push(Alert::new(
+                    Rule::StalePrice,
+                    &symbol,
+                    format!("{} ({}) last published {}s ago", symbol, price_key, age),
+                ));
+            }
+        }
+        alerts
+    }
+
+    async fn low_landing_rates(&self, min_landing_rate_percent: f64) -> Result<Vec<Alert>> {
+        let (result_tx, result_rx) = oneshot::channel();
+        self.transactions_store_tx
+            .send(transactions::Message::LookupRecent { result_tx })
+            .await?;
+        let transactions = result_rx.await?;
+
+        // Count the transactions old enough to have landed, by network
+        let counted_before =
+            Utc::now().timestamp() - self.config.rules.landing_timeout.as_secs() as i64;
+        let mut counts: HashMap<&str, (usize, usize)> = HashMap::new();
+        for transaction in transactions
+            .iter()
+            .filter(|transaction| transaction.submit_time <= counted_before)
+        {
+            let (landed, total) = counts.entry(transaction.network.as_str()).or_default();
+            if transaction.status == TransactionStatus::Confirmed {
+                *landed += 1;
+            }
+            *total += 1;
+        }
+
+        Ok(counts
+            .into_iter()
+            .filter(|(_, (_, total))| *total >= self.config.rules.landing_rate_min_transactions)
+            .filter_map(|(network, (landed, total))| {
+                let landing_rate_percent = landed as f64 / total as f64 * 100.0;
+                (landing_rate_percent < min_landing_rate_percent).then(|| {
+                    Alert::new(
+                        Rule::LandingRate,
+                        network,
+                        format!(
+                            "{:.1}% of the {} recent transactions on {} landed",
+                            landing_rate_percent, total, network
+                        ),
+                    )
+                })
+            })
+            .collect())
+    }
+
+    async fn low_balances(&self, min_balance_sol: f64) -> Result<Vec<Alert>> {
+        let mut alerts = vec![];
+        for (network, rpc_client) in &self.networks {
+            // Simulated networks have no balance
+            if network.config.simulation.is_some() {
+                continue;
+            }
+            let publish_key = match network.config.publish_key()? {
+                Some(publish_key) => publish_key,
+                None => continue,
+            };
+            let balance_sol = rpc_client
+                .get_balance(&publish_key)
+                .await
+                .with_context(|| format!("getting {} balance of {}", network.name, publish_key))?
+                as f64
+                / LAMPORTS_PER_SOL as f64;
+            if balance_sol < min_balance_sol {
+                alerts.push(Alert::new(
+                    Rule::LowBalance,
+                    &network.name,
+                    format!(
+                        "publish key {} holds {:.3} SOL on {}, below {} SOL",
+                        publish_key, balance_sol, network.name, min_balance_sol
+                    ),
+                ));
+            }
+        }
+        Ok(alerts)
+    }
+
+    async fn price_bounds_rejections(&self) -> Result<Vec<Alert>> {
+        let (result_tx, result_rx) = oneshot::channel();
+        self.local_store_tx
+            .send(local::Message::LookupRejections { result_tx })
+            .await?;
+        let snapshot = self.global_store_reader.load();
+        Ok(result_rx
+            .await?
+            .into_iter()
+            .map(|(price_identifier, rejection)| {
+                let price_key = Pubkey::new_from_array(price_identifier.to_bytes());
+                let symbol = snapshot
+                    .account_metadata
+                    .symbol_index
+                    .price_name(&price_key);
+                Alert::new(
+                    Rule::PriceBoundsRejections,
+                    &symbol,
+                    format!(
+                        "{} updates of {} ({}) rejected by the local store, last: {}",
+                        rejection.count, symbol, price_key, rejection.last_reason
+                    ),
+                )
+            })
+            .collect())
+    }
+
+    async fn missing_components(&self) -> Result<Vec<Alert>> {
+        let component_monitor_tx = match &self.component_monitor_tx {
+            Some(component_monitor_tx) => component_monitor_tx,
+            None => return Ok(vec![]),
+        };
+        let (result_tx, result_rx) = oneshot::channel();
+        component_monitor_tx
+            .send(component_monitor::Message::LookupMissing { result_tx })
+            .await?;
+        Ok(result_rx
+            .await?
+            .into_iter()
+            .map(|streak| {
+                Alert::new(
+                    Rule::MissingComponent,
+                    format!("{}:{}", streak.symbol, streak.publisher),
+                    format!(
+                        "component of {} missing from {} ({}) after {} consecutive transactions",
+                        streak.publisher, streak.symbol, streak.price_account, streak.misses
+                    ),
+                )
+            })
+            .collect())
+    }
+
+    async fn price_anomalies(&self) -> Result<Vec<Alert>> {
+        let anomaly_detector_tx = match &self.anomaly_detector_tx {
+            Some(anomaly_detector_tx) => anomaly_detector_tx,
+            None => return Ok(vec![]),
+        };
+        let (result_tx, result_rx) = oneshot::channel();
+        anomaly_detector_tx
+            .send(anomaly_detector::Message::LookupAnomalies { result_tx })
+            .await?;
+        Ok(result_rx
+            .await?
+            .into_iter()
+            .map(|anomaly| {
+                Alert::new(
+                    Rule::PriceAnomaly,
+                    format!("{}:{}", anomaly.symbol, anomaly.kind.name()),
+                    format!(
+                        "{} anomaly of {} ({}): {}",
+                        anomaly.kind.name(),
+                        anomaly.symbol,
+                        anomaly.price_account,
+                        anomaly.detail
+                    ),
+                )
+            })
+            .collect())
+    }
+
+    async fn notify(
+        &self,
+        webhook: &WebhookConfig,
+        alert: &Alert,
+        state: AlertState,
+    ) -> Result<()> {
+        let body = match webhook.kind {
+            WebhookKind::Slack => json!({
+                "text": format!(
+                    "{} {}",
+                    match state {
+                        AlertState::Firing => ":rotating_light: [FIRING]",
+                        AlertState::Resolved => ":white_check_mark: [RESOLVED]",
+                    },
+                    alert.summary
+                ),
+            }),
+            WebhookKind::PagerDuty => json!({
+                "routing_key": webhook.routing_key,
+                "event_action": match state {
+                    AlertState::Firing => "trigger",
+                    AlertState::Resolved => "resolve",
+                },
+                "dedup_key": alert.key,
+                "payload": {
+                    "summary": alert.summary,
+                    "source": "pyth-agent",
+                    "severity": "critical",
+                },
+            }),
+            WebhookKind::Generic => json!({
+                "state": state,
+                "alert": alert,
+            }),
+        };
+        self.client
+            .post(&webhook.url)
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            Alert,
+            AlertState,
+            Alerts,
+            Rule,
+            WebhookConfig,
+            WebhookKind,
+        },
+        std::{
+            collections::HashSet,
+            time::Duration,
+        },
+        tokio::time::Instant,
+    };
+
+    #[test]
+    fn test_alerts_are_deduplicated_and_cooled_down() {
+        let mut alerts = Alerts::default();
+        let cooldown = Duration::from_secs(300);
+        let start = Instant::now();
+        let stale = Alert::new(Rule::StalePrice, "Crypto.BTC/USD", "stale".to_string());
+        let evaluate = |alerts: &mut Alerts, firing: Vec<Alert>, unevaluated: &[Rule], secs| {
+            alerts.evaluate(
+                firing,
+                &unevaluated.iter().copied().collect::<HashSet<_>>(),
+                cooldown,
+                start + Duration::from_secs(secs),
+            )
+        };
+        assert_eq!(stale.key, "stale_price:Crypto.BTC/USD");
+
+        // Notified once when it starts firing, and when it resolves
+        assert_eq!(
+            evaluate(&mut alerts, vec![stale.clone()], &[], 0),
+            vec![(stale.clone(), AlertState::Firing)]
+        );
+        assert!(evaluate(&mut alerts, vec![stale.clone()], &[], 10).is_empty());
+        assert_eq!(
+            evaluate(&mut alerts, vec![], &[], 20),
+            vec![(stale.clone(), AlertState::Resolved)]
+        );
+
+        // Flapping within the cooldown is not notified
+        assert!(evaluate(&mut alerts, vec![stale.clone()], &[], 30).is_empty());
+        assert!(evaluate(&mut alerts, vec![], &[], 40).is_empty());
+        assert_eq!(
+            evaluate(&mut alerts, vec![stale.clone()], &[], 400),
+            vec![(stale.clone(), AlertState::Firing)]
+        );
+
+        // Alerts of rules which could not be evaluated keep firing
+        assert!(evaluate(&mut alerts, vec![], &[Rule::StalePrice], 410).is_empty());
+        assert_eq!(
+            evaluate(&mut alerts, vec![], &[], 420),
+            vec![(stale, AlertState::Resolved)]
+        );
+    }
+
+    #[test]
+    fn test_secrets_are_redacted_from_debug() {
+        let debug = format!(
+            "{:?}",
+            WebhookConfig {
+                kind:        WebhookKind::PagerDuty,
+                url:         "https://hooks.slack.com/services/T000/B000/slack-secret".to_string(),
+                routing_key: Some("routing-secret".to_string()),
+            }
+        );
+        assert!(!debug.contains("slack-secret"));
+        assert!(!debug.contains("routing-secret"));
+        assert!(debug.contains("hooks.slack.com"));
+    }
+}
@@ -0,0 +1,474 @@
+// The Anomaly Detector flags suspicious behaviour of the price feeds, from the
+// aggregates observed by the Global Store. It keeps rolling statistics over the recent
+// trading aggregates of each price, and flags:
+// - jumps: changes of the aggregate price whose z-score against the recent changes
+//   (as log returns) is too high
+// - confidence blowouts: confidences, relative to the price, whose z-score against the
+//   recent ones is too high
+// - flatlines: trading prices which have not changed for too long
+// An anomaly stays flagged for a while after it was last detected. The flagged
+// anomalies are exported as metrics, shown on the dashboard and alerted on by the
+// Alerter.
+use {
+    crate::agent::{
+        error::Error,
+        metrics::{
+            AnomalyDetectorMetrics,
+            PROMETHEUS_REGISTRY,
+        },
+        solana::oracle::PriceEntry,
+        store::global,
+    },
+    anyhow::{
+        Context as _,
+        Result,
+    },
+    pyth_sdk_solana::state::PriceStatus,
+    serde::{
+        Deserialize,
+        Serialize,
+    },
+    solana_sdk::pubkey::Pubkey,
+    std::{
+        collections::{
+            HashMap,
+            VecDeque,
+        },
+        time::Duration,
+    },
+    tokio::{
+        sync::{
+            broadcast,
+            mpsc,
+            oneshot,
+        },
+        task::JoinHandle,
+        time::{
+            self,
+            Instant,
+            Interval,
+        },
+    },
+    tracing::Instrument,
+};
+
+/// Interval at which the anomalies no longer detected are unflagged
+const UNFLAG_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Number of recent trading aggregates of each price the statistics are
+    /// computed over
+    pub window_size:       usize,
+    /// Jumps and confidence blowouts are only detected once the window holds
+    /// at least this many aggregates
+    pub min_samples:       usize,
+    /// A change of the price whose z-score against the recent changes exceeds
+    /// this, in absolute value, is a jump
+    pub jump_z_score:      f64,
+    /// A confidence, relative to the price, whose z-score against the recent
+    /// ones exceeds this is a blowout
+    pub conf_z_score:      f64,
+    /// A trading price unchanged for at least this long is flatlining
+    #[serde(with = "humantime_serde")]
+    pub flatline_duration: Duration,
+    /// How long an anomaly stays flagged after it was last detected
+    #[serde(with = "humantime_serde")]
+    pub flag_duration:     Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            window_size:       300,
+            min_samples:       30,
+            jump_z_score:      6.0,
+            conf_z_score:      6.0,
+            flatline_duration: Duration::from_secs(10 * 60),
+            flag_duration:     Duration::from_secs(5 * 60),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Message {
+    /// Look up the flagged anomalies
+    LookupAnomalies {
+        result_tx: oneshot::Sender<Vec<Anomaly>>,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnomalyKind {
+    Jump,
+    ConfBlowout,
+    Flatline,
+}
+
+impl AnomalyKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AnomalyKind::Jump => "jump",
+            AnomalyKind::ConfBlowout => "conf_blowout",
+            AnomalyKind::Flatline => "flatline",
+        }
+    }
+}
+
+/// An anomaly flagged on a price
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Anomaly {
+    /// The price account itself if its symbol is not known
+    pub symbol:        String,
+    pub price_account: String,
+    pub kind:          AnomalyKind,
+    /// What was detected, e.g. "price moved +5.000% from 100 to 105"
+    pub detail:        String,
+    /// Publish time of the aggregate it was last detected on, as a Unix
+    /// timestamp
+    pub detected_at:   i64,
+}
+
+pub fn spawn_detector(
+    config: Config,
+    rx: mpsc::Receiver<Message>,
+    global_store_events_rx: broadcast::Receiver<global::Event>,
+    global_store_reader: global::SnapshotReader,
+) -> JoinHandle<()> {
+    tokio::spawn(
+        async move {
+            Detector::new(config, rx, global_store_events_rx, global_store_reader)
+                .await
+                .run()
+                .await
+        }
+        .instrument(info_span!("anomaly_detector")),
+    )
+}
+
+/// The recent trading aggregates of a price
+#[derive(Debug, Default)]
+struct PriceWindow {
+    /// Log returns between consecutive aggregates, oldest first
+    returns:         VecDeque<f64>,
+    /// Confidences of the aggregates relative to their price, oldest first
+    conf_ratios:     VecDeque<f64>,
+    last_price:      Option<i64>,
+    last_slot:       u64,
+    /// Publish time of the first aggregate at the last price
+    unchanged_since: i64,
+}
+
+pub struct Detector {
+    windows:                HashMap<Pubkey, PriceWindow>,
+    /// Flagged anomalies by price account and kind, with when they were last
+    /// detected
+    flagged:                HashMap<(Pubkey, AnomalyKind), (Anomaly, Instant)>,
+    metrics:                AnomalyDetectorMetrics,
+    rx:                     mpsc::Receiver<Message>,
+    global_store_events_rx: broadcast::Receiver<global::Event>,
+    /// Used to look up the symbols of the prices
+    global_store_reader:    global::SnapshotReader,
+    unflag_interval:        Interval,
+    config:                 Config,
+}
+
+impl Detector {
+    pub async fn new(
+        config: Config,
+        rx: mpsc::Receiver<Message>,
+        global_store_events_rx: broadcast::Receiver<global::Event>,
+        global_store_reader: global::SnapshotReader,
+    ) -> Self {
+        Detector {
+            windows: HashMap::new(),
+            flagged: HashMap::new(),
+            metrics: AnomalyDetectorMetrics::new(&mut &mut PROMETHEUS_REGISTRY.lock().await),
+            rx,
+            global_store_events_rx,
+            global_store_reader,
+            unflag_interval: time::interval(UNFLAG_INTERVAL),
+            config,
+        }
+    }
+
+    pub async fn run(&mut self) {
+        loop {
+            tokio::select! {
+                message = self.rx.recv() => match message {
+                    Some(message) => {
+                        if let Err(err) = self.handle(message) {
+                            error!(error = ?err, "{:#}", err)
+                        }
+                    }
+                    None => break,
+                },
+                event = self.global_store_events_rx.recv() => match event {
+                    Ok(global::Event::PriceUpdated { account_key, account, .. }) => {
+                        self.observe(account_key, &account, Instant::now())
+                    }
+                    Ok(global::Event::PriceRemoved { account_key }) => {
+                        self.windows.remove(&account_key);
+                    }
+                    Ok(_) => {}
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!(skipped, "Anomaly detector: missed global store events");
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                _ = self.unflag_interval.tick() => self.unflag(Instant::now()),
+            }
+        }
+    }
+
+    fn handle(&mut self, message: Message) -> Result<()> {
+        match message {
+            Message::LookupAnomalies { result_tx } => result_tx
+                .send(self.anomalies())
+                .map_err(|_| Error::ChannelClosed("requester"))
+                .context("failed to send LookupAnomalies result"),
+        }
+    }
+
+    /// Check the aggregate of the price account against its recent ones, if it
+    /// is new and trading
+    fn observe(&mut self, account_key: Pubkey, account: &PriceEntry, now: Instant) {
+        let agg = &account.agg;
+        if agg.status != PriceStatus::Trading || agg.price <= 0 {
+            return;
+        }
+        let window = self.windows.entry(account_key).or_default();
+        if window.last_price.is_some() && window.last_slot >= agg.pub_slot {
+            return;
+        }
+        window.last_slot = agg.pub_slot;
+
+        let mut detected = vec![];
+        let conf_ratio = agg.conf as f64 / agg.price as f64;
+        if let Some(score) = z_score(&window.conf_ratios, conf_ratio, self.config.min_samples) {
+            if score > self.config.conf_z_score {
+                detected.push((
+                    AnomalyKind::ConfBlowout,
+                    format!(
+                        "confidence of {:.4}% of the price, {:.1} standard deviations above the recent ones",
+                        conf_ratio * 100.0,
+                        score
+                    ),
+                ));
+            }
+        }
+        push_bounded(&mut window.conf_ratios, conf_ratio, self.config.window_size);
+
+        match window.last_price {
+            Some(last_price) => {
+                let log_return = (agg.price as f64 / last_price as f64).ln();
+                if let Some(score) = z_score(&window.returns, log_return, self.config.min_samples) {
+                    if score.abs() > self.config.jump_z_score {
+                        detected.push((
+                            AnomalyKind::Jump,
+                            format!(
+                                "price moved {:+.3}% from {} to {}, {:.1} standard deviations",
+                                (agg.price as f64 / last_price as f64 - 1.0) * 100.0,
+                                last_price,
+                                agg.price,
+                                score
+                            ),
+                        ));
+                    }
+                }
+                push_bounded(&mut window.returns, log_return, self.config.window_size);
+
+                if agg.price != last_price {
+                    window.unchanged_since = account.timestamp;
+                } else if account.timestamp - window.unchanged_since
+                    >= self.config.flatline_duration.as_secs() as i64
+                {
+                    detected.push((
+                        AnomalyKind::Flatline,
+                        format!(
+                            "price unchanged at {} for {}s",
+                            agg.price,
+                            account.timestamp - window.unchanged_since
+                        ),
+                    ));
+                }
+            }
+            None => window.unchanged_since = account.timestamp,
+        }
+        window.last_price = Some(agg.price);
+
+        for (kind, detail) in detected {
+            self.flag(account_key, kind, detail, account.timestamp, now);
+        }
+    }
+
+    fn flag(
+        &mut self,
+        account_key: Pubkey,
+        kind: AnomalyKind,
+        detail: String,
+        detected_at: i64,
+        now: Instant,
+    ) {
+        let symbol = self
+            .global_store_reader
+            .load()
+            .account_metadata
+            .symbol_index
+            .price_name(&account_key);
+        if !self.flagged.contains_key(&(account_key, kind)) {
+            warn!(
+                symbol = %symbol,
+                price_key = %account_key,
+                kind = kind.name(),
+                detail = %detail,
+                "Anomaly detector: anomaly detected"
+            );
+            self.metrics.flagged(&symbol, &account_key, kind.name());
+        }
+        let anomaly = Anomaly {
+            symbol,
+            price_account: account_key.to_string(),
+            kind,
+            detail,
+            detected_at,
+        };
+        self.flagged.insert((account_key, kind), (anomaly, now));
+    }
+
+    /// Unflag the anomalies not detected again within the flag duration
+    fn unflag(&mut self, now: Instant) {
+        let flag_duration = self.config.flag_duration;
+        let metrics = &self.metrics;
+        self.flagged
+            .retain(|(account_key, kind), (anomaly, detected_at)| {
+                let flagged = now.duration_since(*detected_at) < flag_duration;
+                if !flagged {
+                    metrics.unflagged(&anomaly.symbol, account_key, kind.name());
+                }
+                flagged
+            });
+    }
+
+    /// The flagged anomalies, by symbol
+    fn anomalies(&self) -> Vec<Anomaly> {
+        let mut anomalies = self
+            .flagged
+            .values()
+            .map(|(anomaly, _)| anomaly.clone())
+            .collect::<Vec<_>>();
+        anomalies.sort_by(|a, b| (&a.symbol, a.kind).cmp(&(&b.symbol, b.kind)));
+        anomalies
+    }
+}
+
+fn push_bounded(values: &mut VecDeque<f64>, value: f64, size: usize) {
+    values.push_back(value);
+    while values.len() > size {
+        values.pop_front();
+    }
+}
+
+/// The z-score of the value against the values, if there are enough of them
+/// and they vary
+fn z_score(values: &VecDeque<f64>, value: f64, min_samples: usize) -> Option<f64> {
+    if values.is_empty() || values.len() < min_samples {
+        return None;
+    }
+    let count = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / count;
+    let variance = values
+        .iter()
+        .map(|sample| (sample - mean).powi(2))
+        .sum::<f64>()
+        / count;
+    let standard_deviation = variance.sqrt();
+    (standard_deviation > 0.0).then(|| (value - mean) / standard_deviation)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            AnomalyKind,
+            Config,
+            Detector,
+        },
+        crate::agent::{
+            solana::oracle::PriceEntry,
+            store::global,
+        },
+        pyth_sdk_solana::state::PriceStatus,
+        solana_sdk::pubkey::Pubkey,
+        tokio::{
+            sync::{
+                broadcast,
+                mpsc,
+            },
+            time::Instant,
+        },
+    };
+
+    #[tokio::test]
+    async fn test_jumps_conf_blowouts_and_flatlines_are_flagged() {
+        let (_tx, rx) = mpsc::channel(1);
+        let (_events_tx, events_rx) = broadcast::channel(1);
+        let config = Config {
+            window_size: 20,
+            min_samples: 5,
+            ..Default::default()
+        };
+        let flag_duration = config.flag_duration;
+        let mut detector =
+            Detector::new(config, rx, events_rx, global::SnapshotReader::default()).await;
+
+        let account_key = Pubkey::new_unique();
+        let now = Instant::now();
+        let observe = |detector: &mut Detector, slot: u64, timestamp, price, conf| {
+            let mut account = PriceEntry::default();
+            account.agg.status = PriceStatus::Trading;
+            account.agg.pub_slot = slot;
+            account.agg.price = price;
+            account.agg.conf = conf;
+            account.timestamp = timestamp;
+            detector.observe(account_key, &account, now);
+            detector
+                .anomalies()
+                .into_iter()
+                .map(|anomaly| anomaly.kind)
+                .collect::<Vec<_>>()
+        };
+
+        // A price moving back and forth by 0.1% is not anomalous
+        for slot in 0..10 {
+            let anomalies = observe(&mut detector, slot, slot as i64, 1000 + slot as i64 % 2, 1);
+            assert!(anomalies.is_empty());
+        }
+
+        // Moving by 10%, then with a confidence 50 times as wide
+        assert_eq!(
+            observe(&mut detector, 10, 10, 1100, 1),
+            vec![AnomalyKind::Jump]
+        );
+        assert_eq!(
+            observe(&mut detector, 11, 11, 1100, 50),
+            vec![AnomalyKind::Jump, AnomalyKind::ConfBlowout]
+        );
+
+        // Unchanged for the flatline duration since it moved to 1100
+        assert_eq!(
+            observe(&mut detector, 12, 10 + 600, 1100, 1),
+            vec![
+                AnomalyKind::Jump,
+                AnomalyKind::ConfBlowout,
+                AnomalyKind::Flatline
+            ]
+        );
+
+        // The anomalies are unflagged once no longer detected
+        detector.unflag(now + flag_duration);
+        assert!(detector.anomalies().is_empty());
+    }
+}
@@ -0,0 +1,50 @@
+// The version of the agent, the git commit it was built from and the time of the
+// build, embedded by the build script. They are served by the `get_agent_info`
+// method of the pythd API and the "/version" endpoint of the metrics server, logged
+// at startup, and exported as the labels of the `agent_build_info` metric.
+use {
+    chrono::{
+        TimeZone,
+        Utc,
+    },
+    serde::Serialize,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct BuildInfo {
+    pub version:         &'static str,
+    /// Suffixed with "-dirty" if the tree had uncommitted changes
+    pub git_commit:      &'static str,
+    /// Unix timestamp of the build
+    pub build_timestamp: i64,
+    /// Time of the build, in RFC 3339 format
+    pub build_time:      String,
+}
+
+impl BuildInfo {
+    /// The build of the running agent
+    pub fn current() -> Self {
+        let build_timestamp = env!("PYTH_AGENT_BUILD_TIMESTAMP")
+            .parse()
+            .unwrap_or_default();
+        BuildInfo {
+            version: env!("CARGO_PKG_VERSION"),
+            git_commit: env!("PYTH_AGENT_GIT_COMMIT"),
+            build_timestamp,
+            build_time: Utc
+                .timestamp_opt(build_timestamp, 0)
+                .single()
+                .map(|build_time| build_time.to_rfc3339())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Labels of the build info metric
+    pub fn labels(&self) -> Vec<(String, String)> {
+        vec![
+            ("version".to_string(), self.version.to_string()),
+            ("git_commit".to_string(), self.git_commit.to_string()),
+            ("build_time".to_string(), self.build_time.clone()),
+        ]
+    }
+}
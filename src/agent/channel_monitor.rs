@@ -0,0 +1,212 @@
+// The Channel Monitor gives visibility into backpressure between components. It
+// periodically samples the depth of the registered channels, and components send
+// on them through the monitor to count the sends blocked for longer than the
+// send timeout because the channel was full. Components which cannot afford to
+// wait for a full channel send with their own timeout instead, and get the
+// value back once it elapses.
+use {
+    crate::agent::{
+        fault_injection,
+        metrics::{
+            ChannelMetrics,
+            PROMETHEUS_REGISTRY,
+        },
+    },
+    parking_lot::Mutex,
+    serde::{
+        Deserialize,
+        Serialize,
+    },
+    std::{
+        sync::Arc,
+        time::Duration,
+    },
+    tokio::{
+        sync::mpsc::{
+            self,
+            error::{
+                SendError,
+                SendTimeoutError,
+            },
+        },
+        task::JoinHandle,
+        time,
+    },
+};
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Duration of the interval at which the channel depths are sampled
+    #[serde(with = "humantime_serde")]
+    pub sample_interval_duration: Duration,
+    /// Sends which wait longer than this for room in a full channel are counted
+    /// as timed out. They are still completed.
+    #[serde(with = "humantime_serde")]
+    pub send_timeout:             Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            sample_interval_duration: Duration::from_secs(5),
+            send_timeout:             Duration::from_secs(1),
+        }
+    }
+}
+
+/// Returns the current depth of a registered channel, None once it is closed
+type DepthSampler = Box<dyn Fn() -> Option<usize> + Send + Sync>;
+
+/// Shared handle through which channels are registered and sent on
+#[derive(Clone)]
+pub struct ChannelMonitor {
+    channels: Arc<Mutex<Vec<(String, DepthSampler)>>>,
+    metrics:  ChannelMetrics,
+    config:   Config,
+}
+
+impl ChannelMonitor {
+    pub async fn new(config: Config) -> Self {
+        ChannelMonitor {
+            channels: Default::default(),
+            metrics: ChannelMetrics::new(&mut &mut PROMETHEUS_REGISTRY.lock().await),
+            config,
+        }
+    }
+
+    /// Sample the depth of the channel under the given name, until it is
+    /// closed. The monitor only keeps a weak sender, so registered channels
+    /// still close once their senders are dropped.
+    pub fn register<T: Send + 'static>(
+        &self,
+        name: impl Into<String>,
+        tx: &mpsc::Sender<T>,
+        capacity: usize,
+    ) {
+        let name = name.into();
+        self.metrics.set_capacity(&name, capacity);
+
+        let tx = tx.downgrade();
+        self.channels.lock().push((
+            name,
+            Box::new(move || {
+                tx.upgrade()
+                    .map(|tx| capacity.saturating_sub(tx.capacity()))
+            }),
+        ));
+    }
+
+    /// Send on the channel registered under the given name, counting the send as
+    /// timed out if the channel stays full for longer than the send timeout. The
+    /// value may be dropped instead when fault injection is enabled.
+    pub async fn send<T>(
+        &self,
+        name: &str,
+        tx: &mpsc::Sender<T>,
+        value: T,
+    ) -> Result<(), SendError<T>> {
+        if fault_injection::drop_message(name) {
+            return Ok(());
+        }
+
+        let permit = match time::timeout(self.config.send_timeout, tx.reserve()).await {
+            Ok(permit) => permit,
+            Err(_) => {
+                self.metrics.send_timeout(name);
+                tx.reserve().await
+            }
+        };
+
+        match permit {
+            Ok(permit) => {
+                permit.send(value);
+                Ok(())
+            }
+            Err(_) => Err(SendError(value)),
+        }
+    }
+
+    /// Send on the channel registered under the given name, giving the value
+    /// back if the channel stays full for longer than the given timeout, which
+    /// is counted as a send timeout
+    pub async fn send_timeout<T>(
+        &self,
+        name: &str,
+        tx: &mpsc::Sender<T>,
+        value: T,
+        timeout: Duration,
+    ) -> Result<(), SendTimeoutError<T>> {
+        if fault_injection::drop_message(name) {
+            return Ok(());
+        }
+
+        match time::timeout(timeout, tx.reserve()).await {
+            Ok(Ok(permit)) => {
+                permit.send(value);
+                Ok(())
+            }
+            Ok(Err(_)) => Err(SendTimeoutError::Closed(value)),
+            Err(_) => {
+                self.metrics.send_timeout(name);
+                Err(SendTimeoutError::Timeout(value))
+            }
+        }
+    }
+
+    /// Sample the depth of the registered channels, forgetting the closed ones
+    fn sample(&self) {
+        self.channels.lock().retain(|(name, depth)| match depth() {
+            Some(depth) => {
+                self.metrics.set_depth(name, depth);
+                true
+            }
+            None => {
+                self.metrics.set_depth(name, 0);
+                false
+            }
+        });
+    }
+}
+
+pub fn spawn_monitor(monitor: ChannelMonitor) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut sample_interval = time::interval(monitor.config.sample_interval_duration);
+        loop {
+            sample_interval.tick().await;
+            monitor.sample();
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            ChannelMonitor,
+            Config,
+        },
+        tokio::sync::mpsc,
+    };
+
+    #[tokio::test]
+    async fn test_registered_channels_still_close() {
+        let monitor = ChannelMonitor::new(Config::default()).await;
+        let (tx, mut rx) = mpsc::channel(10);
+        monitor.register("test", &tx, 10);
+
+        monitor.send("test", &tx, 1).await.unwrap();
+        monitor.send("test", &tx, 2).await.unwrap();
+        assert_eq!((monitor.channels.lock()[0].1)(), Some(2));
+        monitor.sample();
+        assert_eq!(monitor.channels.lock().len(), 1);
+
+        // Once its senders are dropped, the channel closes and is no longer sampled
+        drop(tx);
+        assert_eq!(rx.recv().await, Some(1));
+        assert_eq!(rx.recv().await, Some(2));
+        assert_eq!(rx.recv().await, None);
+        monitor.sample();
+        assert!(monitor.channels.lock().is_empty());
+    }
+}
@@ -0,0 +1,318 @@
+// The Component Monitor detects our components dropping out of the on-chain price
+// accounts while the Exporters keep publishing them, e.g. when the transactions land
+// but the component of the publish key is not updated. Whenever a transaction sent
+// to the primary network settles, the component of the publish key it was signed
+// with is looked up in each price account it updated, as last observed by the Oracle.
+// A component which is missing, or lags behind the latest slot of its price account by
+// more than the max slot lag, missed. Consecutive misses make up the miss streak of
+// the component, which is exported as a metric, logged when it reaches the threshold,
+// and alerted on by the Alerter.
+use {
+    crate::agent::{
+        error::Error,
+        metrics::{
+            ComponentMonitorMetrics,
+            PROMETHEUS_REGISTRY,
+        },
+        solana::oracle::PriceEntry,
+        store::{
+            global,
+            transactions::{
+                self,
+                TransactionStatus,
+            },
+        },
+    },
+    anyhow::{
+        Context as _,
+        Result,
+    },
+    serde::{
+        Deserialize,
+        Serialize,
+    },
+    solana_sdk::{
+        pubkey::Pubkey,
+        signature::Signature,
+    },
+    std::{
+        collections::{
+            HashMap,
+            HashSet,
+        },
+        iter,
+        time::Duration,
+    },
+    tokio::{
+        sync::{
+            mpsc,
+            oneshot,
+        },
+        task::JoinHandle,
+        time::{
+            self,
+            Interval,
+        },
+    },
+    tracing::Instrument,
+};
+
+/// Network whose transactions are checked, as the Global Store holds its price accounts
+const PRIMARY_NETWORK: &str = "primary";
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Duration of the interval at which the settled transactions are checked
+    #[serde(with = "humantime_serde")]
+    pub check_interval_duration: Duration,
+    /// Slots a component may lag behind the latest slot of its price account
+    /// without missing
+    pub max_slot_lag:            u64,
+    /// Consecutive misses after which a component is reported as missing
+    pub miss_streak_threshold:   u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            check_interval_duration: Duration::from_secs(5),
+            max_slot_lag:            25,
+            miss_streak_threshold:   3,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Message {
+    /// Look up the components whose miss streak reached the threshold
+    LookupMissing {
+        result_tx: oneshot::Sender<Vec<MissStreak>>,
+    },
+}
+
+/// The consecutive misses of one of our components
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct MissStreak {
+    /// The price account itself if its symbol is not known
+    pub symbol:        String,
+    pub price_account: String,
+    pub publisher:     String,
+    pub misses:        u64,
+}
+
+pub fn spawn_monitor(
+    config: Config,
+    rx: mpsc::Receiver<Message>,
+    transactions_store_tx: mpsc::Sender<transactions::Message>,
+    global_store_reader: global::SnapshotReader,
+) -> JoinHandle<()> {
+    tokio::spawn(
+        async move {
+            Monitor::new(config, rx, transactions_store_tx, global_store_reader)
+                .await
+                .run()
+                .await
+        }
+        .instrument(info_span!("component_monitor")),
+    )
+}
+
+pub struct Monitor {
+    /// Miss streaks of our components, by price account and publish key
+    streaks:               HashMap<(Pubkey, Pubkey), u64>,
+    /// Settled transactions which were already checked
+    checked_signatures:    HashSet<Signature>,
+    metrics:               ComponentMonitorMetrics,
+    rx:                    mpsc::Receiver<Message>,
+    transactions_store_tx: mpsc::Sender<transactions::Message>,
+    global_store_reader:   global::SnapshotReader,
+    check_interval:        Interval,
+    config:                Config,
+}
+
+impl Monitor {
+    pub async fn new(
+        config: Config,
+        rx: mpsc::Receiver<Message>,
+        transactions_store_tx: mpsc::Sender<transactions::Message>,
+        global_store_reader: global::SnapshotReader,
+    ) -> Self {
+        Monitor {
+            streaks: HashMap::new(),
+            checked_signatures: HashSet::new(),
+            metrics: ComponentMonitorMetrics::new(&mut &mut PROMETHEUS_REGISTRY.lock().await),
+            rx,
+            transactions_store_tx,
+            global_store_reader,
+            check_interval: time::interval(config.check_interval_duration),
+            config,
+        }
+    }
+
+    pub async fn run(&mut self) {
+        loop {
+            tokio::select! {
+                message = self.rx.recv() => match message {
+                    Some(message) => {
+                        if let Err(err) = self.handle(message) {
+                            error!(error = ?err, "{:#}", err)
+                        }
+                    }
+                    None => break,
+                },
+                _ = self.check_interval.tick() => {
+                    if let Err(err) = self.check().await {
+                        error!(error = ?err, "Component monitor: {:#}", err)
+                    }
+                }
+            }
+        }
+    }
+
+    fn handle(&mut self, message: Message) -> Result<()> {
+        match message {
+            Message::LookupMissing { result_tx } => result_tx
+                .send(self.missing())
+                .map_err(|_| Error::ChannelClosed("requester"))
+                .context("failed to send LookupMissing result"),
+        }
+    }
+
+    /// Check the components updated by the transactions which settled since
+    /// the previous check
+    async fn check(&mut self) -> Result<()> {
+        let (result_tx, result_rx) = oneshot::channel();
+        self.transactions_store_tx
+            .send(transactions::Message::LookupRecent { result_tx })
+            .await
+            .map_err(|_| Error::ChannelClosed("transactions store"))?;
+        let transactions = result_rx
+            .await
+            .context("looking up the transactions store")?;
+
+        let mut settled = HashSet::new();
+        let mut checked_signatures = HashSet::new();
+        for transaction in transactions {
+            if transaction.network != PRIMARY_NETWORK
+                || transaction.status == TransactionStatus::Pending
+            {
+                continue;
+            }
+            checked_signatures.insert(transaction.signature);
+            if self.checked_signatures.contains(&transaction.signature) {
+                continue;
+            }
+            for price_account in transaction.price_accounts {
+                settled.insert((price_account, transaction.publish_key));
+            }
+        }
+        // The transactions forgotten by the store are not seen again
+        self.checked_signatures = checked_signatures;
+
+        let snapshot = self.global_store_reader.load();
+        for (price_key, publish_key) in settled {
+            let price_account = match snapshot.account_data.price_accounts.get(&price_key) {
+                Some(price_account) => price_account,
+                None => continue,
+            };
+            let missed = missed(price_account, &publish_key, self.config.max_slot_lag);
+            let symbol = snapshot
+                .account_metadata
+                .symbol_index
+                .price_name(&price_key);
+            self.record(price_key, publish_key, &symbol, missed);
+        }
+
+        Ok(())
+    }
+
+    fn record(&mut self, price_key: Pubkey, publish_key: Pubkey, symbol: &str, missed: bool) {
+        let threshold = self.config.miss_streak_threshold;
+        let streak = self.streaks.entry((price_key, publish_key)).or_default();
+        if missed {
+            *streak += 1;
+            if *streak == threshold {
+                warn!(
+                    symbol,
+                    price_key = %price_key,
+                    publisher = %publish_key,
+                    misses = *streak,
+                    "Component monitor: our component is missing from the price account"
+                );
+            }
+        } else {
+            if *streak >= threshold {
+                info!(
+                    symbol,
+                    price_key = %price_key,
+                    publisher = %publish_key,
+                    misses = *streak,
+                    "Component monitor: our component is back in the price account"
+                );
+            }
+            *streak = 0;
+        }
+        self.metrics
+            .record(symbol, &price_key, &publish_key, missed, *streak);
+    }
+
+    fn missing(&self) -> Vec<MissStreak> {
+        let snapshot = self.global_store_reader.load();
+        self.streaks
+            .iter()
+            .filter(|(_, misses)| **misses >= self.config.miss_streak_threshold)
+            .map(|((price_key, publish_key), misses)| MissStreak {
+                symbol:        snapshot.account_metadata.symbol_index.price_name(price_key),
+                price_account: price_key.to_string(),
+                publisher:     publish_key.to_string(),
+                misses:        *misses,
+            })
+            .collect()
+    }
+}
+
+/// Whether the component of the publish key is missing from the price account,
+/// or lags behind its latest slot by more than the max slot lag
+fn missed(price_account: &PriceEntry, publish_key: &Pubkey, max_slot_lag: u64) -> bool {
+    let mut components = price_account.comp.iter().take(price_account.num as usize);
+    let latest_slot = components
+        .clone()
+        .map(|component| component.latest.pub_slot)
+        .chain(iter::once(price_account.agg.pub_slot))
+        .max()
+        .unwrap_or_default();
+    components
+        .find(|component| component.publisher == *publish_key)
+        .map_or(true, |component| {
+            latest_slot - component.latest.pub_slot > max_slot_lag
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::missed,
+        crate::agent::solana::oracle::PriceEntry,
+        solana_sdk::pubkey::Pubkey,
+    };
+
+    #[test]
+    fn test_lagging_and_missing_components_missed() {
+        let (publish_key, other_key) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut price_account = PriceEntry::default();
+        price_account.num = 2;
+        price_account.agg.pub_slot = 100;
+        price_account.comp[0].publisher = other_key;
+        price_account.comp[0].latest.pub_slot = 110;
+        price_account.comp[1].publisher = publish_key;
+        price_account.comp[1].latest.pub_slot = 90;
+
+        // Lagging behind the latest component, but within the max slot lag
+        assert!(!missed(&price_account, &publish_key, 25));
+        assert!(missed(&price_account, &publish_key, 10));
+
+        // Not a component of the price account
+        assert!(missed(&price_account, &Pubkey::new_unique(), 25));
+    }
+}
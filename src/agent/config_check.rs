@@ -0,0 +1,282 @@
+// The Config Check validates a config without starting the agent, so that
+// mistakes surface before deployment rather than after. It resolves the key
+// stores and validates the other settings the agent would otherwise only
+// reject (or silently ignore) at runtime. Optionally, it probes the RPC and
+// WSS endpoints of each network and reads the mapping account.
+use {
+    super::{
+        config::{
+            Config,
+            ConfigSource,
+        },
+        solana::{
+            instrumented_rpc,
+            key_store::KeyStore,
+            network,
+        },
+    },
+    pyth_sdk_solana::state::load_mapping_account,
+    serde::Serialize,
+    solana_client::nonblocking::pubsub_client::PubsubClient,
+    solana_sdk::{
+        commitment_config::CommitmentConfig,
+        pubkey::Pubkey,
+    },
+    std::{
+        fmt,
+        str::FromStr,
+    },
+    tokio::time,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Ok,
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Check {
+    pub name:   String,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+/// Outcome of every check, in the order they were run
+#[derive(Debug, Default, Serialize)]
+pub struct Report {
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    fn push(&mut self, name: impl Into<String>, status: CheckStatus, detail: impl Into<String>) {
+        self.checks.push(Check {
+            name: name.into(),
+            status,
+            detail: detail.into(),
+        });
+    }
+
+    pub(crate) fn ok(&mut self, name: impl Into<String>, detail: impl Into<String>) {
+        self.push(name, CheckStatus::Ok, detail);
+    }
+
+    pub(crate) fn warning(&mut self, name: impl Into<String>, detail: impl Into<String>) {
+        self.push(name, CheckStatus::Warning, detail);
+    }
+
+    pub(crate) fn error(&mut self, name: impl Into<String>, detail: impl Into<String>) {
+        self.push(name, CheckStatus::Error, detail);
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.checks
+            .iter()
+            .any(|check| check.status == CheckStatus::Error)
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in &self.checks {
+            let status = match check.status {
+                CheckStatus::Ok => "OK",
+                CheckStatus::Warning => "WARN",
+                CheckStatus::Error => "ERROR",
+            };
+            writeln!(f, "[{:>5}] {}: {}", status, check.name, check.detail)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse and validate the config file, probing the endpoints of the networks
+/// if requested
+pub async fn check_config(source: &ConfigSource, probe: bool) -> Report {
+    let mut report = Report::default();
+
+    let config = match Config::new(source) {
+        Ok(config) => {
+            report.ok("config", format!("parsed {}", source.path.display()));
+            config
+        }
+        Err(err) => {
+            report.error("config", format!("{:#}", err));
+            return report;
+        }
+    };
+
+    for key in config.deprecated_keys() {
+        report.warning(key, "deprecated and ignored, remove it from the config");
+    }
+
+    match config.log_level() {
+        Ok(level) => report.ok("log_level", level.as_str()),
+        Err(err) => report.error("log_level", format!("{:#}", err)),
+    }
+
+    let mut networks = vec![("primary_network", &config.primary_network)];
+    if let Some(secondary_network) = &config.secondary_network {
+        networks.push(("secondary_network", secondary_network));
+    }
+    for (name, network) in networks {
+        let key_store = check_key_store(&mut report, name, network);
+        if let Some(simulation) = &network.simulation {
+            if !(simulation.replay_speed.is_finite() && simulation.replay_speed > 0.0) {
+                report.error(
+                    format!("{}.simulation.replay_speed", name),
+                    format!("{} is not positive", simulation.replay_speed),
+                );
+            }
+        }
+        if probe {
+            probe_network(&mut report, name, network, key_store.as_ref()).await;
+        }
+    }
+
+    for key in &config.publish_latency.publisher_keys {
+        check_pubkey(&mut report, "publish_latency.publisher_keys", key);
+    }
+    for key in &config.metrics_server.dashboard_publisher_keys {
+        check_pubkey(&mut report, "metrics_server.dashboard_publisher_keys", key);
+    }
+
+    if !(0.0..=1.0).contains(&config.telemetry.sample_ratio) {
+        report.error(
+            "telemetry.sample_ratio",
+            format!("{} is not between 0 and 1", config.telemetry.sample_ratio),
+        );
+    }
+
+    report
+}
+
+fn check_key_store(report: &mut Report, name: &str, network: &network::Config) -> Option<KeyStore> {
+    let check_name = format!("{}.key_store", name);
+    let key_store = match KeyStore::new(network.key_store.clone()) {
+        Ok(key_store) => key_store,
+        Err(err) => {
+            report.error(check_name, format!("{:#}", err));
+            return None;
+        }
+    };
+
+    report.ok(
+        &check_name,
+        format!(
+            "program key {}, mapping key {}, {} additional publish keypair(s)",
+            key_store.program_key,
+            key_store.mapping_key,
+            key_store.additional_publish_keypairs.len()
+        ),
+    );
+    let exporter = &network.exporter;
+    let signers = [
+        exporter.remote_signer.is_some(),
+        exporter.kms_signer.is_some(),
+        exporter.ledger_signer.is_some(),
+    ];
+    if signers.iter().filter(|configured| **configured).count() > 1 {
+        report.error(
+            format!("{}.exporter", name),
+            "only one of remote_signer, kms_signer and ledger_signer can be configured",
+        );
+    }
+    if exporter.ledger_signer.is_some() && !cfg!(feature = "ledger") {
+        report.error(
+            format!("{}.exporter.ledger_signer", name),
+            "the agent was built without the ledger feature",
+        );
+    }
+    if let Some(remote_signer) = &exporter.remote_signer {
+        check_pubkey(
+            report,
+            &format!("{}.exporter.remote_signer.publish_key", name),
+            &remote_signer.publish_key,
+        );
+    } else if let Some(kms_signer) = &exporter.kms_signer {
+        check_pubkey(
+            report,
+            &format!("{}.exporter.kms_signer.publish_key", name),
+            &kms_signer.publish_key,
+        );
+    } else if exporter.ledger_signer.is_some() {
+        // The key is only known once the Ledger is connected
+    } else if key_store.publish_keypair.is_none() {
+        report.warning(
+            format!("{}.publish_keypair", check_name),
+            "not found, publishing waits for a keypair from the remote keypair loader",
+        );
+    }
+
+    Some(key_store)
+}
+
+async fn probe_network(
+    report: &mut Report,
+    name: &str,
+    network: &network::Config,
+    key_store: Option<&KeyStore>,
+) {
+    let rpc_client = instrumented_rpc::new_rpc_client(
+        name.trim_end_matches("_network"),
+        &network.rpc_url,
+        network.rpc_timeout,
+        CommitmentConfig {
+            commitment: network.oracle.commitment,
+        },
+    );
+
+    let rpc_check = format!("{}.rpc_url", name);
+    match rpc_client.get_version().await {
+        Ok(version) => report.ok(rpc_check, format!("solana-core {}", version.solana_core)),
+        Err(err) => {
+            report.error(rpc_check, format!("{:#}", err));
+            return;
+        }
+    }
+
+    if let Some(key_store) = key_store {
+        let mapping_check = format!("{}.mapping_account", name);
+        match rpc_client.get_account(&key_store.mapping_key).await {
+            Ok(account) => match load_mapping_account(&account.data) {
+                Ok(mapping_account) if account.owner == key_store.program_key => report.ok(
+                    mapping_check,
+                    format!("{} product(s) listed", mapping_account.num),
+                ),
+                Ok(_) => report.error(
+                    mapping_check,
+                    format!(
+                        "owned by {} rather than the program {}",
+                        account.owner, key_store.program_key
+                    ),
+                ),
+                Err(err) => report.error(mapping_check, format!("not a mapping account: {}", err)),
+            },
+            Err(err) => report.error(mapping_check, format!("{:#}", err)),
+        }
+    }
+
+    if network.oracle.subscriber_enabled {
+        let wss_check = format!("{}.wss_url", name);
+        match time::timeout(network.rpc_timeout, PubsubClient::new(&network.wss_url)).await {
+            Ok(Ok(pubsub_client)) => {
+                let _ = pubsub_client.shutdown().await;
+                report.ok(wss_check, "connected");
+            }
+            Ok(Err(err)) => report.error(wss_check, format!("{:#}", err)),
+            Err(_) => report.error(wss_check, "timed out connecting"),
+        }
+    }
+}
+
+fn check_pubkey(report: &mut Report, name: &str, key: &str) {
+    if let Err(err) = Pubkey::from_str(key) {
+        report.warning(
+            name,
+            format!("{} is not a valid public key and is ignored: {}", key, err),
+        );
+    }
+}
@@ -0,0 +1,228 @@
+// The Config Watcher reloads the config file when the agent receives SIGHUP, so
+// that settings can be changed without dropping publishing for a restart. Only
+// the settings listed in `Config::with_reloadable_from` are applied live. They are
+// pushed to the components which subscribed to them over watch channels. Changes
+// to any other setting are logged as requiring a restart, and otherwise ignored. An
+// agent embedded in another service has no config file, and leaves SIGHUP to its host.
+use {
+    super::{
+        config::{
+            Config,
+            ConfigSource,
+        },
+        logging::LogLevel,
+    },
+    anyhow::{
+        Context,
+        Result,
+    },
+    tokio::{
+        signal::unix::{
+            signal,
+            SignalKind,
+        },
+        sync::watch,
+        task::JoinHandle,
+    },
+    tracing::Instrument,
+};
+
+/// Sends the subscribed section of a reloaded config to its subscriber
+type Subscriber = Box<dyn Fn(&Config) + Send + Sync>;
+
+pub struct ConfigWatcher {
+    /// Where the config is reloaded from on SIGHUP, with the same overrides
+    /// as on startup. None for an embedded agent, which is not reloaded.
+    source:      Option<ConfigSource>,
+    /// The config currently in effect: the config the agent started with, with
+    /// the reloadable settings of the latest reload
+    current:     Config,
+    subscribers: Vec<Subscriber>,
+    /// Minimum level of the logged events. Not reloadable when the level is
+    /// set through the RUST_LOG environment variable.
+    log_level:   Option<LogLevel>,
+}
+
+impl ConfigWatcher {
+    pub fn new(source: Option<ConfigSource>, config: Config, log_level: Option<LogLevel>) -> Self {
+        ConfigWatcher {
+            source,
+            current: config,
+            subscribers: vec![],
+            log_level,
+        }
+    }
+
+    /// Watch the section of the config selected by the given function. The
+    /// section is sent again on every reload, whether it changed or not.
+    pub fn subscribe<T, F>(&mut self, section: F) -> watch::Receiver<T>
+    where
+        T: Send + Sync + 'static,
+        F: Fn(&Config) -> T + Send + Sync + 'static,
+    {
+        let (tx, rx) = watch::channel(section(&self.current));
+        self.subscribers.push(Box::new(move |config| {
+            // The subscriber may have exited
+            let _ = tx.send(section(config));
+        }));
+        rx
+    }
+
+    async fn run(&mut self) -> Result<()> {
+        // The subscriptions are kept open without a config file to reload
+        let source = match self.source.clone() {
+            Some(source) => source,
+            None => return futures_util::future::pending().await,
+        };
+        let mut hangup = signal(SignalKind::hangup()).context("listening for SIGHUP")?;
+        while hangup.recv().await.is_some() {
+            info!(path = %source.path.display(), "received SIGHUP, reloading config");
+            if let Err(err) = self.reload(&source) {
+                error!(
+                    error = ?err,
+                    "could not reload config, keeping the current one: {:#}", err
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn reload(&mut self, source: &ConfigSource) -> Result<()> {
+        let new = Config::new(source)?;
+        for key in new.deprecated_keys() {
+            warn!(
+                key,
+                "config key is deprecated and ignored, remove it from the config"
+            );
+        }
+        let effective = self.current.with_reloadable_from(&new);
+
+        // Validate the new log level before applying anything
+        let log_level = effective.log_level()?;
+
+        for section in effective.changed_sections(&new) {
+            warn!(
+                setting = section,
+                "config setting changed, but requires a restart to take effect"
+            );
+        }
+
+        // Only apply a changed level, to keep a level set through the Admin API
+        match &self.log_level {
+            Some(current_level) if effective.log_level != self.current.log_level => {
+                current_level.set(log_level)
+            }
+            Some(_) => {}
+            None if effective.log_level != self.current.log_level => {
+                warn!("log_level is not reloaded, as it is set by RUST_LOG")
+            }
+            None => {}
+        }
+
+        for subscriber in &self.subscribers {
+            subscriber(&effective);
+        }
+
+        for section in self.current.changed_sections(&effective) {
+            info!(setting = section, "config setting reloaded");
+        }
+        self.current = effective;
+
+        Ok(())
+    }
+}
+
+pub fn spawn_watcher(mut watcher: ConfigWatcher) -> JoinHandle<()> {
+    tokio::spawn(
+        async move {
+            if let Err(err) = watcher.run().await {
+                error!(error = ?err, "{:#}", err);
+            }
+        }
+        .instrument(info_span!("config_watcher")),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::ConfigWatcher,
+        crate::agent::config::{
+            Config,
+            ConfigSource,
+        },
+        rand::Rng,
+        std::{
+            env,
+            fs,
+            time::Duration,
+        },
+    };
+
+    fn write_config(source: &ConfigSource, publish_interval: &str, bind_address: &str) {
+        let contents = format!(
+            "[primary_network.exporter]\n\
+             publish_interval_duration = \"{}\"\n\
+             \n\
+             [metrics_server]\n\
+             bind_address = \"{}\"\n",
+            publish_interval, bind_address
+        );
+        fs::write(&source.path, contents).unwrap();
+    }
+
+    #[test]
+    fn test_reload_applies_only_the_reloadable_settings() {
+        let source = ConfigSource {
+            path:      env::temp_dir().join(format!(
+                "pyth-agent-config-{}.toml",
+                rand::thread_rng().gen::<u64>()
+            )),
+            overrides: vec![],
+        };
+        write_config(&source, "1s", "127.0.0.1:8888");
+        let config = Config::new(&source).unwrap();
+
+        let mut watcher = ConfigWatcher::new(Some(source.clone()), config, None);
+        let mut publish_interval_rx =
+            watcher.subscribe(|config| config.primary_network.exporter.publish_interval_duration);
+
+        write_config(&source, "3s", "127.0.0.1:9999");
+        watcher.reload(&source).unwrap();
+        fs::remove_file(&source.path).unwrap();
+
+        assert!(publish_interval_rx.has_changed().unwrap());
+        assert_eq!(
+            *publish_interval_rx.borrow_and_update(),
+            Duration::from_secs(3)
+        );
+        assert_eq!(
+            watcher.current.metrics_server.bind_address,
+            "127.0.0.1:8888".parse().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_invalid_reload_keeps_the_current_config() {
+        let source = ConfigSource {
+            path:      env::temp_dir().join(format!(
+                "pyth-agent-config-{}.toml",
+                rand::thread_rng().gen::<u64>()
+            )),
+            overrides: vec![],
+        };
+        write_config(&source, "1s", "127.0.0.1:8888");
+        let config = Config::new(&source).unwrap();
+
+        let mut watcher = ConfigWatcher::new(Some(source.clone()), config.clone(), None);
+        let publish_interval_rx =
+            watcher.subscribe(|config| config.primary_network.exporter.publish_interval_duration);
+
+        fs::write(&source.path, "[primary_network.exporter]\nunknown = 1\n").unwrap();
+        assert!(watcher.reload(&source).is_err());
+        fs::remove_file(&source.path).unwrap();
+
+        assert!(!publish_interval_rx.has_changed().unwrap());
+        assert_eq!(watcher.current, config);
+    }
+}
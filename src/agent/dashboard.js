@@ -0,0 +1,73 @@
+// Live updates for the Pyth Agent dashboard. The agent streams a
+// "row" server-sent event whenever the data of a price changes.
+(function () {
+  function formatTimestamp(timestamp) {
+    return new Date(timestamp * 1000).toISOString().replace("T", " ").slice(0, 19);
+  }
+
+  function findOrInsertRow(table, row) {
+    // Rows are identified by their price ID column
+    for (var i = 1; i < table.rows.length; i++) {
+      if (table.rows[i].cells[2].textContent === row.price_id) {
+        return table.rows[i];
+      }
+    }
+
+    var tr = table.insertRow();
+    for (var j = 0; j < 10; j++) {
+      tr.insertCell();
+    }
+    tr.cells[0].textContent = row.symbol;
+    tr.cells[1].textContent = row.product;
+    tr.cells[2].textContent = row.price_id;
+    return tr;
+  }
+
+  function updateRow(row) {
+    var tr = findOrInsertRow(document.querySelector("table"), row);
+    var global = row.global_data;
+
+    tr.cells[3].textContent = global
+      ? (global.price * Math.pow(10, global.expo)).toFixed(2)
+      : "no data";
+    tr.cells[4].textContent = global ? formatTimestamp(global.timestamp) : "no data";
+    tr.cells[5].textContent = row.local_data
+      ? formatTimestamp(row.local_data.timestamp)
+      : "no data";
+
+    // Our publishers' components, one entry per publisher
+    var expo = global ? global.expo : 0;
+    var components = row.components;
+    function joinComponents(format) {
+      return components.length ? components.map(format).join("; ") : "no data";
+    }
+    tr.cells[6].textContent = joinComponents(function (comp) {
+      return (comp.price * Math.pow(10, expo)).toFixed(2);
+    });
+    tr.cells[7].textContent = joinComponents(function (comp) {
+      return comp.slot_lag + " slots";
+    });
+    tr.cells[8].textContent = joinComponents(function (comp) {
+      return comp.deviation === null ? "n/a" : (comp.deviation * 100).toFixed(3) + "%";
+    });
+
+    // Estimate of the next aggregate once our local update lands
+    var preview = row.aggregate_preview;
+    if (!preview) {
+      tr.cells[9].textContent = "no data";
+    } else if (preview.status !== "Trading") {
+      tr.cells[9].textContent = "unknown";
+    } else {
+      tr.cells[9].textContent =
+        (preview.price * Math.pow(10, expo)).toFixed(2) +
+        " \u00b1 " +
+        (preview.conf * Math.pow(10, expo)).toFixed(2);
+    }
+  }
+
+  // Stream only the rows matching the filters of this page
+  var source = new EventSource("/api/dashboard/events" + window.location.search);
+  source.addEventListener("row", function (event) {
+    updateRow(JSON.parse(event.data));
+  });
+})();
@@ -1,26 +1,53 @@
 use {
     super::{
+        anomaly_detector::{
+            self,
+            Anomaly,
+        },
+        publisher_performance::{
+            self,
+            PublisherPerformance,
+        },
         solana::oracle::PriceEntry,
         store::{
             global::{
+                self,
+                AggregatePreview,
                 AllAccountsData,
                 AllAccountsMetadata,
-                Lookup,
                 PriceAccountMetadata,
             },
             local::{
                 Message,
                 PriceInfo,
             },
+            transactions::{
+                self,
+                TransactionStatus,
+            },
+        },
+        uptime::{
+            self,
+            SymbolUptime,
         },
     },
-    crate::agent::metrics::MetricsServer,
-    chrono::NaiveDateTime,
+    crate::agent::{
+        health::ComponentStatus,
+        metrics::MetricsServer,
+    },
+    chrono::{
+        NaiveDateTime,
+        Utc,
+    },
     pyth_sdk::{
         Identifier,
         PriceIdentifier,
     },
-    slog::Logger,
+    pyth_sdk_solana::state::PriceStatus,
+    serde::{
+        Deserialize,
+        Serialize,
+    },
     solana_sdk::pubkey::Pubkey,
     std::{
         collections::{
@@ -29,99 +56,464 @@ use {
             HashMap,
             HashSet,
         },
+        convert::Infallible,
+        sync::Arc,
         time::Duration,
     },
-    tokio::sync::oneshot,
+    tokio::{
+        sync::{
+            mpsc,
+            oneshot,
+            Mutex,
+        },
+        time,
+    },
     typed_html::{
         dom::DOMTree,
         html,
         text,
     },
+    warp::sse,
 };
 
 impl MetricsServer {
-    /// Create an HTML view of store data
-    pub async fn render_dashboard(&self) -> Result<String, Box<dyn std::error::Error>> {
+    /// Gather local and global store data into a single per-symbol
+    /// view, narrowed down by the query. Returns the requested page
+    /// of the view and the total number of pages.
+    async fn fetch_dashboard_data(
+        &self,
+        query: &DashboardQuery,
+    ) -> Result<(BTreeMap<String, DashboardSymbolView>, usize), Box<dyn std::error::Error>> {
         // Prepare response channel for requests
         let (local_tx, local_rx) = oneshot::channel();
-        let (global_data_tx, global_data_rx) = oneshot::channel();
-        let (global_metadata_tx, global_metadata_rx) = oneshot::channel();
 
-        // Request price data from local and global store
+        // Request price data from local store
         self.local_store_tx
             .send(Message::LookupAllPriceInfo {
                 result_tx: local_tx,
             })
             .await?;
 
-        self.global_store_lookup_tx
-            .send(Lookup::LookupAllAccountsData {
-                result_tx: global_data_tx,
-            })
+        // Await the results. Only prices submitted for the default
+        // publisher are shown.
+        let local_data = local_rx.await?.remove(&None).unwrap_or_default();
+        let global_snapshot = self.global_store_reader.load();
+
+        let symbol_view = build_dashboard_data(
+            local_data,
+            global_snapshot.account_data.clone(),
+            global_snapshot.account_metadata.clone(),
+            &self.publisher_keys,
+        );
+
+        Ok(query.apply(symbol_view, self.staleness_threshold))
+    }
+
+    /// Create a JSON-serializable view of store data, along with the
+    /// total number of pages matching the query
+    pub async fn dashboard_json(
+        &self,
+        query: &DashboardQuery,
+    ) -> Result<(BTreeMap<String, DashboardSymbolJson>, usize), Box<dyn std::error::Error>> {
+        let (symbol_view, num_pages) = self.fetch_dashboard_data(query).await?;
+        Ok((
+            symbol_view
+                .into_iter()
+                .map(|(symbol, view)| (symbol, view.into()))
+                .collect(),
+            num_pages,
+        ))
+    }
+
+    /// Gather the recently sent transactions, most recent first. The
+    /// updated price accounts are shown by symbol.
+    async fn fetch_transactions_data(
+        &self,
+    ) -> Result<Vec<DashboardTransactionView>, Box<dyn std::error::Error>> {
+        let (result_tx, result_rx) = oneshot::channel();
+        self.transactions_store_tx
+            .send(transactions::Message::LookupRecent { result_tx })
             .await?;
+        let transactions = result_rx.await?;
+
+        // Resolve price accounts to the symbols of their products
+        let global_snapshot = self.global_store_reader.load();
+        let symbol_index = &global_snapshot.account_metadata.symbol_index;
+
+        Ok(transactions
+            .into_iter()
+            .map(|record| {
+                let symbols = record
+                    .price_accounts
+                    .iter()
+                    .map(|price_key| symbol_index.price_name(price_key))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+
+                let submit_time = match NaiveDateTime::from_timestamp_opt(record.submit_time, 0) {
+                    Some(datetime) => datetime.format("%Y-%m-%d %H:%M:%S").to_string(),
+                    None => format!("Invalid timestamp {}", record.submit_time),
+                };
 
-        self.global_store_lookup_tx
-            .send(Lookup::LookupAllAccountsMetadata {
-                result_tx: global_metadata_tx,
+                let (status, error) = match record.status {
+                    TransactionStatus::Pending => ("pending".to_string(), String::new()),
+                    TransactionStatus::Confirmed => ("confirmed".to_string(), String::new()),
+                    TransactionStatus::Failed(err) => ("failed".to_string(), err),
+                };
+
+                DashboardTransactionView {
+                    network: record.network,
+                    symbols,
+                    signature: record.signature.to_string(),
+                    submit_time,
+                    status,
+                    priority_fee: record
+                        .priority_fee_lamports
+                        .map(|fee| fee.to_string())
+                        .unwrap_or_else(|| "none".to_string()),
+                    error,
+                }
             })
+            .collect())
+    }
+
+    /// Gather the statistics of our publishers, empty if they are not tracked
+    pub async fn fetch_publisher_performance(
+        &self,
+    ) -> Result<Vec<PublisherPerformance>, Box<dyn std::error::Error>> {
+        let publisher_performance_tx = match &self.publisher_performance_tx {
+            Some(publisher_performance_tx) => publisher_performance_tx,
+            None => return Ok(vec![]),
+        };
+        let (result_tx, result_rx) = oneshot::channel();
+        publisher_performance_tx
+            .send(publisher_performance::Message::LookupReport { result_tx })
             .await?;
+        Ok(result_rx.await?)
+    }
 
-        // Await the results
-        let local_data = local_rx.await?;
-        let global_data = global_data_rx.await??;
-        let global_metadata = global_metadata_rx.await??;
+    /// Gather the flagged price anomalies, empty if they are not detected
+    pub async fn fetch_anomalies(&self) -> Result<Vec<Anomaly>, Box<dyn std::error::Error>> {
+        let anomaly_detector_tx = match &self.anomaly_detector_tx {
+            Some(anomaly_detector_tx) => anomaly_detector_tx,
+            None => return Ok(vec![]),
+        };
+        let (result_tx, result_rx) = oneshot::channel();
+        anomaly_detector_tx
+            .send(anomaly_detector::Message::LookupAnomalies { result_tx })
+            .await?;
+        Ok(result_rx.await?)
+    }
 
-        let symbol_view =
-            build_dashboard_data(local_data, global_data, global_metadata, &self.logger);
+    /// Gather the uptime of our publishers over the window, empty if it is not tracked
+    pub async fn fetch_uptime(
+        &self,
+        window: Duration,
+    ) -> Result<Vec<SymbolUptime>, Box<dyn std::error::Error>> {
+        let uptime_tx = match &self.uptime_tx {
+            Some(uptime_tx) => uptime_tx,
+            None => return Ok(vec![]),
+        };
+        let (result_tx, result_rx) = oneshot::channel();
+        uptime_tx
+            .send(uptime::Message::LookupUptime { window, result_tx })
+            .await?;
+        Ok(result_rx.await?)
+    }
 
-        // Note the uptime and adjust to whole seconds for cleaner output
-        let uptime = Duration::from_secs(self.start_time.elapsed().as_secs());
+    /// Create a CSV view of the dashboard table, with a header row
+    pub async fn render_dashboard_csv(
+        &self,
+        query: &DashboardQuery,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let (symbol_view, _num_pages) = self.fetch_dashboard_data(query).await?;
 
-        // Build and collect table rows
-        let mut rows = vec![];
+        let mut csv = String::new();
+        push_csv_record(
+            &mut csv,
+            &[
+                "symbol",
+                "product_id",
+                "price_id",
+                "last_published_price",
+                "last_publish_time",
+                "last_local_update_time",
+                "our_price",
+                "our_slot_lag",
+                "our_deviation",
+                "next_aggregate",
+            ],
+        );
 
         for (symbol, data) in symbol_view {
             for (price_pubkey, price_data) in data.prices {
-                let price_string = if let Some(global_data) = price_data.global_data {
-                    let expo = global_data.expo;
-                    let price_with_expo: f64 = global_data.agg.price as f64 * 10f64.powi(expo);
-                    format!("{:.2}", price_with_expo)
-                } else {
-                    "no data".to_string()
-                };
+                let columns = DashboardPriceColumns::new(&price_data);
+                push_csv_record(
+                    &mut csv,
+                    &[
+                        &symbol,
+                        &data.product.to_string(),
+                        &price_pubkey.to_string(),
+                        &columns.price,
+                        &columns.last_publish,
+                        &columns.last_local_update,
+                        &columns.our_price,
+                        &columns.our_slot_lag,
+                        &columns.our_deviation,
+                        &columns.next_aggregate,
+                    ],
+                );
+            }
+        }
 
-                let last_publish_string = if let Some(global_data) = price_data.global_data {
-                    if let Some(datetime) =
-                        NaiveDateTime::from_timestamp_opt(global_data.timestamp, 0)
-                    {
-                        datetime.format("%Y-%m-%d %H:%M:%S").to_string()
-                    } else {
-                        format!("Invalid timestamp {}", global_data.timestamp)
-                    }
+        Ok(csv)
+    }
+
+    /// Refresh the per-price dashboard gauges from the current store data
+    pub async fn update_dashboard_metrics(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let (symbol_view, _num_pages) = self.fetch_dashboard_data(&DashboardQuery::all()).await?;
+        self.dashboard_metrics
+            .update(&symbol_view, Utc::now().timestamp());
+        Ok(())
+    }
+
+    /// Create an HTML view of store data
+    pub async fn render_dashboard(
+        &self,
+        query: &DashboardQuery,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let (symbol_view, num_pages) = self.fetch_dashboard_data(query).await?;
+
+        // Group the recent transactions by the network they were sent to
+        let mut network_transactions: BTreeMap<String, Vec<DashboardTransactionView>> =
+            BTreeMap::new();
+        for transaction in self.fetch_transactions_data().await? {
+            network_transactions
+                .entry(transaction.network.clone())
+                .or_default()
+                .push(transaction);
+        }
+
+        // Show a section per network, with its slots, the health of its
+        // Oracle and Exporter, and the transactions recently sent to it
+        let components = self.health.components();
+        let format_component = |name: String| match components.get(&name) {
+            Some(ComponentStatus { status, detail, .. }) => {
+                format!("{}: {}", format!("{:?}", status).to_lowercase(), detail)
+            }
+            None => "not running".to_string(),
+        };
+        let format_slots =
+            |slots: Option<u64>| slots.map_or("no data".to_string(), |slots| slots.to_string());
+        let mut mapping_usages = self.mapping_usage.usages();
+        let network_sections = self
+            .slot_lags
+            .lags()
+            .into_iter()
+            .map(|(network, lag)| {
+                let transactions = network_transactions.remove(&network).unwrap_or_default();
+                let count_status = |status: &str| {
+                    transactions
+                        .iter()
+                        .filter(|transaction| transaction.status == status)
+                        .count()
+                };
+                let transaction_stats = format!(
+                    "{} confirmed, {} failed, {} pending",
+                    count_status("confirmed"),
+                    count_status("failed"),
+                    count_status("pending")
+                );
+                let slot_stall = if lag.stalled {
+                    "the cluster slot stopped advancing, publishing paused"
                 } else {
-                    "no data".to_string()
+                    "none"
                 };
+                let transaction_rows = transactions
+                    .into_iter()
+                    .map(|transaction| {
+                        html! {
+                            <tr>
+                                <td>{text!(transaction.symbols)}</td>
+                                <td>{text!(transaction.signature)}</td>
+                                <td>{text!(transaction.submit_time)}</td>
+                                <td>{text!(transaction.status)}</td>
+                                <td>{text!(transaction.priority_fee)}</td>
+                                <td>{text!(transaction.error)}</td>
+                            </tr>
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                // Flag the mapping accounts nearing the number of products they can list
+                let mapping_rows = mapping_usages
+                    .remove(&network)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|(mapping_key, usage)| {
+                        let capacity_status = if usage.nearly_full() {
+                            "nearly full"
+                        } else {
+                            "ok"
+                        };
+                        html! {
+                            <tr>
+                                <td>{text!(mapping_key.to_string())}</td>
+                                <td>{text!("{} / {}", usage.products, usage.capacity)}</td>
+                                <td>{text!(usage.remaining_capacity().to_string())}</td>
+                                <td>{text!(capacity_status)}</td>
+                            </tr>
+                        }
+                    })
+                    .collect::<Vec<_>>();
 
-                let last_local_update_string = if let Some(local_data) = price_data.local_data {
-                    if let Some(datetime) =
-                        NaiveDateTime::from_timestamp_opt(local_data.timestamp, 0)
-                    {
-                        datetime.format("%Y-%m-%d %H:%M:%S").to_string()
-                    } else {
-                        format!("Invalid timestamp {}", local_data.timestamp)
-                    }
-                } else {
-                    "no data".to_string()
+                html! {
+                    <div>
+                        <h3>{text!("Network: {}", network)}</h3>
+                        <table>
+                            <tr><th>"Cluster Slot"</th><td>{text!(format_slots(lag.network_slot))}</td></tr>
+                            <tr><th>"Slot Stall"</th><td>{text!(slot_stall)}</td></tr>
+                            <tr><th>"Oracle Slot Lag"</th><td>{text!(format_slots(lag.oracle_lag))}</td></tr>
+                            <tr><th>"Aggregate Slot Lag"</th><td>{text!(format_slots(lag.aggregate_lag))}</td></tr>
+                            <tr><th>"Oracle RPC"</th><td>{text!(format_component(format!("{}.oracle", network)))}</td></tr>
+                            <tr><th>"Exporter"</th><td>{text!(format_component(format!("{}.exporter", network)))}</td></tr>
+                            <tr><th>"Recent Transactions"</th><td>{text!(transaction_stats)}</td></tr>
+                        </table>
+                        <table>
+                            <tr>
+                                <th>"Mapping Account"</th>
+                                <th>"Products"</th>
+                                <th>"Remaining Capacity"</th>
+                                <th>"Status"</th>
+                            </tr>
+                            { mapping_rows }
+                        </table>
+                        <table>
+                            <tr>
+                                <th>"Symbols"</th>
+                                <th>"Signature"</th>
+                                <th>"Submit Time"</th>
+                                <th>"Status"</th>
+                                <th>"Priority Fee (lamports)"</th>
+                                <th>"Error"</th>
+                            </tr>
+                            { transaction_rows }
+                        </table>
+                    </div>
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let format_rate = |rate: Option<f64>| match rate {
+            Some(rate) => format!("{:.2}%", rate * 100.0),
+            None => "no data".to_string(),
+        };
+        let performance_rows = self
+            .fetch_publisher_performance()
+            .await?
+            .into_iter()
+            .flat_map(|performance| {
+                performance
+                    .windows
+                    .into_iter()
+                    .map(|stats| {
+                        html! {
+                            <tr>
+                                <td>{text!(performance.symbol.clone().unwrap_or_else(|| performance.price_account.clone()))}</td>
+                                <td>{text!(performance.publisher.clone())}</td>
+                                <td>{text!(stats.window)}</td>
+                                <td>{text!(stats.samples.to_string())}</td>
+                                <td>{text!(format_rate(stats.uptime))}</td>
+                                <td>{text!(format_rate(stats.slot_hit_rate))}</td>
+                                <td>{text!(format_rate(stats.inclusion_rate))}</td>
+                                <td>{text!(match stats.average_deviation {
+                                    Some(deviation) => format!("{:.3}%", deviation * 100.0),
+                                    None => "no data".to_string(),
+                                })}</td>
+                            </tr>
+                        }
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let anomaly_rows = self
+            .fetch_anomalies()
+            .await?
+            .into_iter()
+            .map(|anomaly| {
+                let detected_at = match NaiveDateTime::from_timestamp_opt(anomaly.detected_at, 0) {
+                    Some(datetime) => datetime.format("%Y-%m-%d %H:%M:%S").to_string(),
+                    None => format!("Invalid timestamp {}", anomaly.detected_at),
                 };
+                html! {
+                    <tr>
+                        <td>{text!(anomaly.symbol)}</td>
+                        <td>{text!(anomaly.price_account)}</td>
+                        <td>{text!(anomaly.kind.name())}</td>
+                        <td>{text!(detected_at)}</td>
+                        <td>{text!(anomaly.detail)}</td>
+                    </tr>
+                }
+            })
+            .collect::<Vec<_>>();
+
+        // Note the uptime and adjust to whole seconds for cleaner output
+        let uptime = Duration::from_secs(self.start_time.elapsed().as_secs());
+
+        // Show whether publishing was paused through the Admin API, or this
+        // agent is a standby
+        let pause_state = self.publish_pause.get();
+        let publishing = if pause_state.standby {
+            "standby".to_string()
+        } else if pause_state.global {
+            "paused".to_string()
+        } else if pause_state.symbols.is_empty() {
+            "active".to_string()
+        } else {
+            format!(
+                "paused for {}",
+                pause_state
+                    .symbols
+                    .iter()
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        };
+
+        // Show the network whose view lags the most behind its cluster tip
+        let slot_lag = match self.slot_lags.worst() {
+            Some((network, lag)) => format!(
+                "{} slots behind on the {} network (oracle: {}, aggregate: {})",
+                lag.worst().unwrap_or_default(),
+                network,
+                lag.oracle_lag
+                    .map_or("no data".to_string(), |lag| lag.to_string()),
+                lag.aggregate_lag
+                    .map_or("no data".to_string(), |lag| lag.to_string()),
+            ),
+            None => "no data".to_string(),
+        };
+
+        // Build and collect table rows
+        let mut rows = vec![];
+
+        for (symbol, data) in symbol_view {
+            for (price_pubkey, price_data) in data.prices {
+                let columns = DashboardPriceColumns::new(&price_data);
 
                 let row_snippet = html! {
                             <tr>
                                 <td>{text!(symbol.clone())}</td>
                                 <td>{text!(data.product.to_string())}</td>
                 <td>{text!(price_pubkey.to_string())}</td>
-                <td>{text!(price_string)}</td>
-                <td>{text!(last_publish_string)}</td>
-                <td>{text!(last_local_update_string)}</td>
+                <td>{text!(columns.price)}</td>
+                <td>{text!(columns.last_publish)}</td>
+                <td>{text!(columns.last_local_update)}</td>
+                <td>{text!(columns.our_price)}</td>
+                <td>{text!(columns.our_slot_lag)}</td>
+                <td>{text!(columns.our_deviation)}</td>
+                <td>{text!(columns.next_aggregate)}</td>
                             </tr>
                             };
                 rows.push(row_snippet);
@@ -148,7 +540,10 @@ table, th, td {
             <body>
             <h1>{text!(title_string)}</h1>
         {text!("Uptime: {}", humantime::format_duration(uptime))}
+            <p>{text!("Publishing: {}", publishing)}</p>
+            <p>{text!("Worst slot lag: {}", slot_lag)}</p>
             <h2>"State Overview"</h2>
+            <p>{text!("Page {} of {}", query.page(), num_pages)}</p>
             <table>
             <tr>
                 <th>"Symbol"</th>
@@ -157,9 +552,41 @@ table, th, td {
                 <th>"Last Published Price"</th>
         <th>"Last Publish Time"</th>
         <th>"Last Local Update Time"</th>
+        <th>"Our Price"</th>
+        <th>"Our Slot Lag"</th>
+        <th>"Our Deviation"</th>
+        <th>"Next Aggregate (est.)"</th>
             </tr>
             { rows }
         </table>
+            <h2>"Networks"</h2>
+            { network_sections }
+            <h2>"Publisher Performance"</h2>
+            <table>
+            <tr>
+                <th>"Symbol"</th>
+                <th>"Publisher"</th>
+                <th>"Window"</th>
+                <th>"Aggregates"</th>
+                <th>"Uptime"</th>
+                <th>"Slot Hit Rate"</th>
+                <th>"Inclusion Rate"</th>
+                <th>"Average Deviation"</th>
+            </tr>
+            { performance_rows }
+        </table>
+            <h2>"Price Anomalies"</h2>
+            <table>
+            <tr>
+                <th>"Symbol"</th>
+                <th>"Price ID"</th>
+                <th>"Kind"</th>
+                <th>"Last Detected"</th>
+                <th>"Detail"</th>
+            </tr>
+            { anomaly_rows }
+        </table>
+            <script src="/dashboard.js"></script>
             </body>
         </html>
         };
@@ -169,15 +596,449 @@ table, th, td {
 
 #[derive(Debug)]
 pub struct DashboardSymbolView {
-    product: Pubkey,
-    prices:  BTreeMap<Pubkey, DashboardPriceView>,
+    pub product:    Pubkey,
+    pub asset_type: Option<String>,
+    pub prices:     BTreeMap<Pubkey, DashboardPriceView>,
 }
 
 #[derive(Debug)]
 pub struct DashboardPriceView {
-    local_data:      Option<PriceInfo>,
-    global_data:     Option<PriceEntry>,
-    global_metadata: Option<PriceAccountMetadata>,
+    pub local_data:        Option<PriceInfo>,
+    pub global_data:       Option<PriceEntry>,
+    pub global_metadata:   Option<PriceAccountMetadata>,
+    /// On-chain components of our publishers
+    pub components:        Vec<DashboardComponentView>,
+    /// Estimate of the next aggregate once the local update lands
+    pub aggregate_preview: Option<AggregatePreview>,
+}
+
+/// The latest on-chain price of one of our publishers, compared to
+/// the aggregate
+#[derive(Debug, Clone, Serialize)]
+pub struct DashboardComponentView {
+    #[serde(serialize_with = "serialize_pubkey")]
+    pub publisher: Pubkey,
+    pub price:     i64,
+    pub conf:      u64,
+    pub slot:      u64,
+    pub status:    PriceStatus,
+    /// Slots between this component's publish and the aggregate's;
+    /// positive when the component lags behind the aggregate.
+    pub slot_lag:  i64,
+    /// Relative deviation of this component's price from the
+    /// aggregate price. None if the aggregate price is zero.
+    pub deviation: Option<f64>,
+}
+
+impl DashboardComponentView {
+    fn from_price_entry(price_entry: &PriceEntry, publisher_keys: &[Pubkey]) -> Vec<Self> {
+        price_entry
+            .comp
+            .iter()
+            .filter(|comp| publisher_keys.contains(&comp.publisher))
+            .map(|comp| {
+                let agg = &price_entry.agg;
+                DashboardComponentView {
+                    publisher: comp.publisher,
+                    price:     comp.latest.price,
+                    conf:      comp.latest.conf,
+                    slot:      comp.latest.pub_slot,
+                    status:    comp.latest.status,
+                    slot_lag:  agg.pub_slot as i64 - comp.latest.pub_slot as i64,
+                    deviation: if agg.price == 0 {
+                        None
+                    } else {
+                        // Widened, as the difference of the prices may not fit an i64
+                        Some(
+                            (comp.latest.price as i128 - agg.price as i128) as f64
+                                / agg.price.unsigned_abs() as f64,
+                        )
+                    },
+                }
+            })
+            .collect()
+    }
+}
+
+fn serialize_pubkey<S: serde::Serializer>(key: &Pubkey, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&key.to_string())
+}
+
+/// The formatted price columns of a dashboard table row, shared by
+/// the HTML and CSV views
+struct DashboardPriceColumns {
+    price:             String,
+    last_publish:      String,
+    last_local_update: String,
+    our_price:         String,
+    our_slot_lag:      String,
+    our_deviation:     String,
+    next_aggregate:    String,
+}
+
+impl DashboardPriceColumns {
+    fn new(price_data: &DashboardPriceView) -> Self {
+        let price_string = if let Some(global_data) = price_data.global_data {
+            let expo = global_data.expo;
+            let price_with_expo: f64 = global_data.agg.price as f64 * 10f64.powi(expo);
+            format!("{:.2}", price_with_expo)
+        } else {
+            "no data".to_string()
+        };
+
+        let last_publish_string = if let Some(global_data) = price_data.global_data {
+            if let Some(datetime) = NaiveDateTime::from_timestamp_opt(global_data.timestamp, 0) {
+                datetime.format("%Y-%m-%d %H:%M:%S").to_string()
+            } else {
+                format!("Invalid timestamp {}", global_data.timestamp)
+            }
+        } else {
+            "no data".to_string()
+        };
+
+        let last_local_update_string = if let Some(local_data) = &price_data.local_data {
+            if let Some(datetime) = NaiveDateTime::from_timestamp_opt(local_data.timestamp, 0) {
+                datetime.format("%Y-%m-%d %H:%M:%S").to_string()
+            } else {
+                format!("Invalid timestamp {}", local_data.timestamp)
+            }
+        } else {
+            "no data".to_string()
+        };
+
+        let expo = price_data
+            .global_data
+            .map(|global_data| global_data.expo)
+            .unwrap_or_default();
+        let (our_price_string, our_lag_string, our_deviation_string) =
+            if price_data.components.is_empty() {
+                (
+                    "no data".to_string(),
+                    "no data".to_string(),
+                    "no data".to_string(),
+                )
+            } else {
+                let components = &price_data.components;
+                (
+                    components
+                        .iter()
+                        .map(|comp| format!("{:.2}", comp.price as f64 * 10f64.powi(expo)))
+                        .collect::<Vec<_>>()
+                        .join("; "),
+                    components
+                        .iter()
+                        .map(|comp| format!("{} slots", comp.slot_lag))
+                        .collect::<Vec<_>>()
+                        .join("; "),
+                    components
+                        .iter()
+                        .map(|comp| match comp.deviation {
+                            Some(deviation) => format!("{:.3}%", deviation * 100.0),
+                            None => "n/a".to_string(),
+                        })
+                        .collect::<Vec<_>>()
+                        .join("; "),
+                )
+            };
+
+        let next_aggregate_string = match price_data.aggregate_preview {
+            Some(preview) if preview.status == PriceStatus::Trading => format!(
+                "{:.2} ± {:.2}",
+                preview.price as f64 * 10f64.powi(expo),
+                preview.conf as f64 * 10f64.powi(expo)
+            ),
+            Some(_) => "unknown".to_string(),
+            None => "no data".to_string(),
+        };
+
+        DashboardPriceColumns {
+            price:             price_string,
+            last_publish:      last_publish_string,
+            last_local_update: last_local_update_string,
+            our_price:         our_price_string,
+            our_slot_lag:      our_lag_string,
+            our_deviation:     our_deviation_string,
+            next_aggregate:    next_aggregate_string,
+        }
+    }
+}
+
+/// A recently sent transaction, formatted for display
+#[derive(Debug)]
+pub struct DashboardTransactionView {
+    network:      String,
+    /// Symbols of the prices updated by the transaction
+    symbols:      String,
+    signature:    String,
+    submit_time:  String,
+    status:       String,
+    priority_fee: String,
+    error:        String,
+}
+
+/// Default number of symbols shown per dashboard page
+const DASHBOARD_PAGE_SIZE: usize = 100;
+
+/// Query parameters narrowing down the symbols shown on the dashboard
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct DashboardQuery {
+    /// Only show symbols containing this string, ignoring case
+    symbol:     Option<String>,
+    /// Only show products of this asset type, ignoring case
+    asset_type: Option<String>,
+    /// Only show prices without a recent on-chain publish
+    stale_only: bool,
+    /// Page of symbols to show, starting at 1
+    page:       Option<usize>,
+    /// Number of symbols per page
+    page_size:  Option<usize>,
+}
+
+impl DashboardQuery {
+    /// Query matching the whole dashboard, on a single page
+    pub fn all() -> Self {
+        Self {
+            page_size: Some(usize::MAX),
+            ..Default::default()
+        }
+    }
+
+    /// The same query, with all matching symbols on a single page
+    pub fn without_pagination(self) -> Self {
+        Self {
+            page: None,
+            page_size: Some(usize::MAX),
+            ..self
+        }
+    }
+
+    fn page(&self) -> usize {
+        self.page.unwrap_or(1).max(1)
+    }
+
+    fn page_size(&self) -> usize {
+        self.page_size.unwrap_or(DASHBOARD_PAGE_SIZE).max(1)
+    }
+
+    /// Filter the view down to the matching symbols and prices,
+    /// returning the requested page along with the total number of
+    /// pages. Prices are stale if their last on-chain publish is
+    /// older than the staleness threshold, or missing.
+    pub fn apply(
+        &self,
+        symbol_view: BTreeMap<String, DashboardSymbolView>,
+        staleness_threshold: Duration,
+    ) -> (BTreeMap<String, DashboardSymbolView>, usize) {
+        let now = Utc::now().timestamp();
+        let symbol_filter = self.symbol.as_ref().map(|symbol| symbol.to_lowercase());
+
+        let matching = symbol_view
+            .into_iter()
+            .filter(|(symbol, _)| {
+                symbol_filter
+                    .as_ref()
+                    .map_or(true, |filter| symbol.to_lowercase().contains(filter))
+            })
+            .filter(|(_, data)| {
+                self.asset_type.as_ref().map_or(true, |asset_type| {
+                    data.asset_type.as_ref().map_or(false, |data_asset_type| {
+                        data_asset_type.eq_ignore_ascii_case(asset_type)
+                    })
+                })
+            })
+            .filter_map(|(symbol, mut data)| {
+                if self.stale_only {
+                    data.prices.retain(|_, price_data| {
+                        price_data.global_data.map_or(true, |global_data| {
+                            now - global_data.timestamp > staleness_threshold.as_secs() as i64
+                        })
+                    });
+                    if data.prices.is_empty() {
+                        return None;
+                    }
+                }
+                Some((symbol, data))
+            })
+            .collect::<Vec<_>>();
+
+        let page_size = self.page_size();
+        let num_pages =
+            (matching.len() / page_size + (matching.len() % page_size != 0) as usize).max(1);
+        let page = matching
+            .into_iter()
+            .skip((self.page() - 1).saturating_mul(page_size))
+            .take(page_size)
+            .collect();
+
+        (page, num_pages)
+    }
+}
+
+/// JSON representation of a DashboardSymbolView, with public keys
+/// stringified.
+#[derive(Debug, Serialize)]
+pub struct DashboardSymbolJson {
+    product:    String,
+    asset_type: Option<String>,
+    prices:     BTreeMap<String, DashboardPriceJson>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DashboardPriceJson {
+    local_data:        Option<PriceInfo>,
+    global_data:       Option<DashboardGlobalPriceJson>,
+    global_metadata:   Option<DashboardPriceMetadataJson>,
+    components:        Vec<DashboardComponentView>,
+    aggregate_preview: Option<AggregatePreview>,
+}
+
+/// A single dashboard table row, as streamed to live dashboard clients
+#[derive(Debug, Serialize)]
+pub struct DashboardRowJson {
+    symbol:     String,
+    product:    String,
+    price_id:   String,
+    #[serde(flatten)]
+    price_data: DashboardPriceJson,
+}
+
+/// The aggregate on-chain state of a price
+#[derive(Debug, Serialize)]
+pub struct DashboardGlobalPriceJson {
+    price:        i64,
+    conf:         u64,
+    expo:         i32,
+    status:       PriceStatus,
+    publish_slot: u64,
+    timestamp:    i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DashboardPriceMetadataJson {
+    expo: i32,
+}
+
+impl From<DashboardSymbolView> for DashboardSymbolJson {
+    fn from(view: DashboardSymbolView) -> Self {
+        DashboardSymbolJson {
+            product:    view.product.to_string(),
+            asset_type: view.asset_type,
+            prices:     view
+                .prices
+                .into_iter()
+                .map(|(price_key, price_view)| (price_key.to_string(), price_view.into()))
+                .collect(),
+        }
+    }
+}
+
+impl From<DashboardPriceView> for DashboardPriceJson {
+    fn from(view: DashboardPriceView) -> Self {
+        DashboardPriceJson {
+            local_data:        view.local_data,
+            global_data:       view
+                .global_data
+                .map(|global_data| DashboardGlobalPriceJson {
+                    price:        global_data.agg.price,
+                    conf:         global_data.agg.conf,
+                    expo:         global_data.expo,
+                    status:       global_data.agg.status,
+                    publish_slot: global_data.agg.pub_slot,
+                    timestamp:    global_data.timestamp,
+                }),
+            global_metadata:   view
+                .global_metadata
+                .map(|metadata| DashboardPriceMetadataJson {
+                    expo: metadata.expo,
+                }),
+            components:        view.components,
+            aggregate_preview: view.aggregate_preview,
+        }
+    }
+}
+
+/// Append a CSV record to the buffer, quoting fields where necessary
+fn push_csv_record(csv: &mut String, fields: &[&str]) {
+    let fields = fields
+        .iter()
+        .map(|field| {
+            if field.contains(|c: char| matches!(c, ',' | '"' | '\n' | '\r')) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.to_string()
+            }
+        })
+        .collect::<Vec<_>>();
+    csv.push_str(&fields.join(","));
+    csv.push_str("\r\n");
+}
+
+/// Script keeping the rendered dashboard table up to date with the row
+/// events streamed by stream_dashboard_rows().
+pub const DASHBOARD_SCRIPT: &str = include_str!("dashboard.js");
+
+/// Stream dashboard rows to a live dashboard client as server-sent
+/// events, until the client disconnects. The store data is polled at
+/// the dashboard refresh interval, and only rows matching the query
+/// which changed since they were last sent are streamed.
+pub async fn stream_dashboard_rows(
+    shared_state: Arc<Mutex<MetricsServer>>,
+    query: DashboardQuery,
+    events_tx: mpsc::Sender<Result<sse::Event, Infallible>>,
+) {
+    let refresh_interval = shared_state.lock().await.dashboard_refresh_interval;
+    let mut refresh_interval = time::interval(refresh_interval);
+
+    // The last row state sent to the client, by price ID
+    let mut sent_rows: HashMap<String, String> = HashMap::new();
+
+    loop {
+        refresh_interval.tick().await;
+
+        let symbol_view = shared_state
+            .lock()
+            .await
+            .dashboard_json(&query)
+            .await
+            .map_err(|e| e.to_string());
+        let symbol_view = match symbol_view {
+            Ok((symbol_view, _num_pages)) => symbol_view,
+            Err(e) => {
+                error!(error = %e, "Dashboard: Building live update failed");
+                continue;
+            }
+        };
+
+        for (symbol, symbol_data) in symbol_view {
+            for (price_id, price_data) in symbol_data.prices {
+                let row = DashboardRowJson {
+                    symbol: symbol.clone(),
+                    product: symbol_data.product.clone(),
+                    price_id: price_id.clone(),
+                    price_data,
+                };
+                let row_json = match serde_json::to_string(&row) {
+                    Ok(row_json) => row_json,
+                    Err(e) => {
+                        error!(error = %e, "Dashboard: Serializing live update failed");
+                        continue;
+                    }
+                };
+
+                if sent_rows.get(&price_id) == Some(&row_json) {
+                    continue;
+                }
+
+                let event = sse::Event::default().event("row").data(row_json.clone());
+                if events_tx.send(Ok(event)).await.is_err() {
+                    debug!("Dashboard: Live update client disconnected");
+                    return;
+                }
+                sent_rows.insert(price_id, row_json);
+            }
+        }
+    }
 }
 
 /// Turn global/local store state into a single per-symbol view.
@@ -188,21 +1049,26 @@ pub struct DashboardPriceView {
 /// contract).
 ///
 /// The view is indexed by human-readable symbol name or a stringified
-/// public key if symbol name can't be found.
+/// public key if symbol name can't be found. The on-chain components
+/// of the given publishers are included with each price, along with an
+/// estimate of its next aggregate once the local update lands as the
+/// component of the first of them. Without such a component, the local
+/// update is counted as an additional one.
 pub fn build_dashboard_data(
     mut local_data: HashMap<PriceIdentifier, PriceInfo>,
     mut global_data: AllAccountsData,
     mut global_metadata: AllAccountsMetadata,
-    logger: &Logger,
+    publisher_keys: &[Pubkey],
 ) -> BTreeMap<String, DashboardSymbolView> {
     let mut ret = BTreeMap::new();
 
-    debug!(logger, "Building dashboard data";
-      "local_data_len" => local_data.len(),
-      "global_data_products_len" => global_data.product_accounts.len(),
-      "global_data_prices_len" => global_data.price_accounts.len(),
-      "global_metadata_products_len" => global_metadata.product_accounts_metadata.len(),
-      "global_metadata_prices_len" => global_metadata.price_accounts_metadata.len(),
+    debug!(
+        local_data_len = local_data.len(),
+        global_data_products_len = global_data.product_accounts.len(),
+        global_data_prices_len = global_data.price_accounts.len(),
+        global_metadata_products_len = global_metadata.product_accounts_metadata.len(),
+        global_metadata_prices_len = global_metadata.price_accounts_metadata.len(),
+        "Building dashboard data"
     );
 
     // Learn all the product/price keys in the system,
@@ -233,12 +1099,7 @@ pub fn build_dashboard_data(
             .product_accounts_metadata
             .remove(&product_key)
         {
-            let mut symbol_name = product_metadata
-                .attr_dict
-                .get("symbol")
-                .cloned()
-                // Use product key for unnamed products
-                .unwrap_or(format!("unnamed product {}", product_key));
+            let mut symbol_name = global_metadata.symbol_index.product_name(&product_key);
 
             // Sort and deduplicate prices
             let this_product_price_keys_dedup = product_metadata
@@ -250,19 +1111,40 @@ pub fn build_dashboard_data(
 
             // Extract information about each price
             for price_key in this_product_price_keys_dedup {
-                let price_global_data = global_data.price_accounts.remove(&price_key);
+                let price_global_data = global_data
+                    .price_accounts
+                    .remove(&price_key)
+                    .map(|price_account| *price_account);
                 let price_global_metadata =
                     global_metadata.price_accounts_metadata.remove(&price_key);
 
                 let price_identifier = Identifier::new(price_key.clone().to_bytes());
                 let price_local_data = local_data.remove(&price_identifier);
 
+                let aggregate_preview = price_global_data.map(|global_data| {
+                    let publisher = publisher_keys
+                        .iter()
+                        .find(|key| global_data.comp.iter().any(|comp| comp.publisher == **key))
+                        .copied()
+                        .unwrap_or_default();
+                    global::preview_aggregate(&global_data, &publisher, price_local_data.as_ref())
+                });
+
                 prices.insert(
                     price_key,
                     DashboardPriceView {
-                        local_data:      price_local_data,
-                        global_data:     price_global_data,
+                        local_data: price_local_data,
+                        components: price_global_data
+                            .map(|global_data| {
+                                DashboardComponentView::from_price_entry(
+                                    &global_data,
+                                    publisher_keys,
+                                )
+                            })
+                            .unwrap_or_default(),
+                        global_data: price_global_data,
                         global_metadata: price_global_metadata,
+                        aggregate_preview,
                     },
                 );
                 // Mark this price as done
@@ -274,16 +1156,18 @@ pub fn build_dashboard_data(
 
             let symbol_view = DashboardSymbolView {
                 product: product_key,
+                asset_type: product_metadata.attr_dict.get("asset_type").cloned(),
                 prices,
             };
 
             if ret.contains_key(&symbol_name) {
                 let new_symbol_name = format!("{} (duplicate)", symbol_name);
 
-                warn!(logger, "Dashboard: duplicate symbol name detected, renaming";
-                "symbol_name" => &symbol_name,
-                "symbol_renamed_to" => &new_symbol_name,
-                "conflicting_symbol_data" => format!("{:?}", symbol_view),
+                warn!(
+                    %symbol_name,
+                    symbol_renamed_to = %new_symbol_name,
+                    conflicting_symbol_data = ?symbol_view,
+                    "Dashboard: duplicate symbol name detected, renaming"
                 );
 
                 symbol_name = new_symbol_name;
@@ -295,17 +1179,189 @@ pub fn build_dashboard_data(
             // should have found. Missing prices are okay, appearing
             // in cases where no on-chain queries or publishing took
             // place yet.
-            warn!(logger, "Dashboard: Failed to look up product metadata"; "product_id" => product_key.to_string());
+            warn!(product_id = %product_key, "Dashboard: Failed to look up product metadata");
         }
     }
 
     if !(all_price_keys_dedup.is_empty() && remaining_product_keys.is_empty()) {
         let remaining_products: Vec<_> = remaining_product_keys.drain().collect();
         let remaining_prices: Vec<_> = all_price_keys_dedup.drain().collect();
-        warn!(logger, "Dashboard: Orphaned product/price IDs detected";
-	      "remaining_product_ids" => format!("{:?}", remaining_products),
-	      "remaining_price_ids" => format!("{:?}", remaining_prices));
+        warn!(
+            remaining_product_ids = ?remaining_products,
+            remaining_price_ids = ?remaining_prices,
+            "Dashboard: Orphaned product/price IDs detected"
+        );
     }
 
     return ret;
 }
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            DashboardComponentView,
+            DashboardPriceView,
+            DashboardQuery,
+            DashboardSymbolView,
+        },
+        crate::agent::solana::oracle::PriceEntry,
+        chrono::Utc,
+        solana_sdk::pubkey::Pubkey,
+        std::{
+            collections::BTreeMap,
+            time::Duration,
+        },
+    };
+
+    /// A symbol with a single price, last published on-chain at the timestamp
+    fn symbol_view(asset_type: &str, timestamp: Option<i64>) -> DashboardSymbolView {
+        let global_data = timestamp.map(|timestamp| {
+            let mut price_entry = PriceEntry::default();
+            price_entry.timestamp = timestamp;
+            price_entry
+        });
+        DashboardSymbolView {
+            product:    Pubkey::new_unique(),
+            asset_type: Some(asset_type.to_string()),
+            prices:     [(
+                Pubkey::new_unique(),
+                DashboardPriceView {
+                    local_data: None,
+                    global_data,
+                    global_metadata: None,
+                    components: vec![],
+                    aggregate_preview: None,
+                },
+            )]
+            .into_iter()
+            .collect(),
+        }
+    }
+
+    fn symbol_views() -> BTreeMap<String, DashboardSymbolView> {
+        let now = Utc::now().timestamp();
+        [
+            ("Crypto.BTC/USD", symbol_view("Crypto", Some(now))),
+            ("Crypto.ETH/USD", symbol_view("Crypto", Some(now - 3600))),
+            ("Crypto.SOL/USD", symbol_view("Crypto", None)),
+            ("Equity.US.AAPL/USD", symbol_view("Equity", Some(now))),
+            ("FX.EUR/USD", symbol_view("FX", Some(now - 3600))),
+        ]
+        .into_iter()
+        .map(|(symbol, view)| (symbol.to_string(), view))
+        .collect()
+    }
+
+    fn symbols(page: &BTreeMap<String, DashboardSymbolView>) -> Vec<&str> {
+        page.keys().map(String::as_str).collect()
+    }
+
+    #[test]
+    fn test_dashboard_query_filters_the_symbols() {
+        let staleness_threshold = Duration::from_secs(60);
+        let apply = |query: DashboardQuery| query.apply(symbol_views(), staleness_threshold);
+
+        let (page, num_pages) = apply(DashboardQuery::default());
+        assert_eq!(page.len(), 5);
+        assert_eq!(num_pages, 1);
+
+        // Symbols are matched by substring and asset types exactly, ignoring case
+        let (page, _) = apply(DashboardQuery {
+            symbol: Some("usd".to_string()),
+            asset_type: Some("crypto".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(
+            symbols(&page),
+            vec!["Crypto.BTC/USD", "Crypto.ETH/USD", "Crypto.SOL/USD"]
+        );
+        let (page, _) = apply(DashboardQuery {
+            symbol: Some("eur".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(symbols(&page), vec!["FX.EUR/USD"]);
+        let (page, _) = apply(DashboardQuery {
+            asset_type: Some("Crypt".to_string()),
+            ..Default::default()
+        });
+        assert!(page.is_empty());
+
+        // Prices without a recent on-chain publish, or without any, are stale
+        let (page, _) = apply(DashboardQuery {
+            stale_only: true,
+            ..Default::default()
+        });
+        assert_eq!(
+            symbols(&page),
+            vec!["Crypto.ETH/USD", "Crypto.SOL/USD", "FX.EUR/USD"]
+        );
+    }
+
+    #[test]
+    fn test_dashboard_query_paginates_the_symbols() {
+        let staleness_threshold = Duration::from_secs(60);
+        let apply = |query: DashboardQuery| query.apply(symbol_views(), staleness_threshold);
+        let page_of_two = |page: usize| DashboardQuery {
+            page: Some(page),
+            page_size: Some(2),
+            ..Default::default()
+        };
+
+        let (page, num_pages) = apply(page_of_two(1));
+        assert_eq!(symbols(&page), vec!["Crypto.BTC/USD", "Crypto.ETH/USD"]);
+        assert_eq!(num_pages, 3);
+        let (page, num_pages) = apply(page_of_two(3));
+        assert_eq!(symbols(&page), vec!["FX.EUR/USD"]);
+        assert_eq!(num_pages, 3);
+
+        // Pages past the last are empty, and page 0 is the first page
+        assert!(apply(page_of_two(4)).0.is_empty());
+        let (page, _) = apply(page_of_two(0));
+        assert_eq!(symbols(&page), vec!["Crypto.BTC/USD", "Crypto.ETH/USD"]);
+
+        // Only the pages of the matching symbols are counted, at least one
+        let (_, num_pages) = apply(DashboardQuery {
+            asset_type: Some("Equity".to_string()),
+            ..page_of_two(1)
+        });
+        assert_eq!(num_pages, 1);
+        let (page, num_pages) = apply(DashboardQuery {
+            symbol: Some("none".to_string()),
+            ..page_of_two(1)
+        });
+        assert!(page.is_empty());
+        assert_eq!(num_pages, 1);
+
+        // Without pagination, all the matching symbols are on a single page
+        let (page, num_pages) = apply(page_of_two(2).without_pagination());
+        assert_eq!(page.len(), 5);
+        assert_eq!(num_pages, 1);
+        assert_eq!(apply(DashboardQuery::all()).0.len(), 5);
+    }
+
+    #[test]
+    fn test_component_deviation_from_the_aggregate() {
+        let publisher = Pubkey::new_unique();
+        let mut price_entry = PriceEntry::default();
+        price_entry.agg.price = 1000;
+        price_entry.comp[0].publisher = publisher;
+        price_entry.comp[0].latest.price = 1010;
+        price_entry.comp[1].publisher = Pubkey::new_unique();
+
+        // Only the components of our publishers are shown
+        let components = DashboardComponentView::from_price_entry(&price_entry, &[publisher]);
+        assert_eq!(components.len(), 1);
+        assert_eq!(components[0].deviation, Some(0.01));
+
+        // Prices of opposite signs at the ends of the range don't overflow
+        price_entry.agg.price = i64::MIN;
+        price_entry.comp[0].latest.price = i64::MAX;
+        let components = DashboardComponentView::from_price_entry(&price_entry, &[publisher]);
+        assert_eq!(components[0].deviation, Some(2.0));
+
+        price_entry.agg.price = 0;
+        let components = DashboardComponentView::from_price_entry(&price_entry, &[publisher]);
+        assert_eq!(components[0].deviation, None);
+    }
+}
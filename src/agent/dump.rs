@@ -0,0 +1,329 @@
+// The dump is a snapshot of the state of a running agent, served by the Admin API at
+// GET /dump and fetched by the `dump` subcommand, to be attached to support tickets.
+// It bundles the contents of the Local Store and of the Global Store, the transactions
+// recently sent by the Exporters along with their status, the publish pause, the pythd
+// API connections, and the config the agent runs with.
+//
+// The values of the config keys which may hold credentials, such as auth tokens and
+// secret keys, are replaced with "<redacted>". Only the scheme and host of the URLs
+// are kept, as their credentials, path or query may embed an API key, e.g. those of
+// the RPC nodes and webhooks.
+use {
+    super::{
+        publish_pause::{
+            PauseState,
+            PublishPause,
+        },
+        pythd::connections::{
+            ApiConnections,
+            ConnectionInfo,
+        },
+        solana::instrumented_rpc::endpoint_label,
+        store::{
+            global,
+            local::{
+                self,
+                PriceInfo,
+            },
+            transactions::{
+                self,
+                TransactionStatus,
+            },
+        },
+    },
+    crate::agent::error::Error,
+    anyhow::{
+        anyhow,
+        Context,
+        Result,
+    },
+    chrono::Utc,
+    serde::Serialize,
+    serde_json::Value,
+    solana_sdk::pubkey::Pubkey,
+    tokio::sync::{
+        mpsc,
+        oneshot,
+    },
+};
+
+/// Placeholder of the redacted config values
+const REDACTED: &str = "<redacted>";
+
+/// Shown in place of a secret config value by the Debug impls of the configs,
+/// so that logging a config does not leak its credentials. None if the secret
+/// is not set.
+pub fn redacted(secret: Option<&str>) -> Option<&'static str> {
+    secret.filter(|secret| !secret.is_empty()).map(|_| REDACTED)
+}
+
+/// Config keys whose values are redacted, matched as parts of the key
+const SECRET_KEY_PARTS: &[&str] = &["token", "secret", "password", "access_key", "routing_key"];
+
+#[derive(Debug, Serialize)]
+pub struct Dump {
+    /// Unix timestamp at which the dump was taken
+    pub generated_at:    i64,
+    pub config:          Value,
+    pub local_store:     Vec<LocalPrice>,
+    pub global_store:    Vec<GlobalPrice>,
+    /// Transactions recently sent by the Exporters, most recent first
+    pub transactions:    Vec<Transaction>,
+    pub publish_pause:   PauseState,
+    pub api_connections: Vec<ConnectionInfo>,
+}
+
+/// The latest update of a price in the Local Store, for a publisher
+#[derive(Debug, Serialize)]
+pub struct LocalPrice {
+    /// Publish key of the update, None for the default one
+    pub publisher:     Option<String>,
+    pub price_account: String,
+    pub symbol:        Option<String>,
+    #[serde(flatten)]
+    pub price_info:    PriceInfo,
+}
+
+/// The aggregate of a price account in the Global Store
+#[derive(Debug, Serialize)]
+pub struct GlobalPrice {
+    pub price_account: String,
+    pub symbol:        Option<String>,
+    pub price:         i64,
+    pub conf:          u64,
+    pub expo:          i32,
+    pub status:        String,
+    pub pub_slot:      u64,
+    pub components:    usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Transaction {
+    pub network:               String,
+    pub signature:             String,
+    pub symbols:               Vec<String>,
+    pub submit_time:           i64,
+    pub priority_fee_lamports: Option<u64>,
+    pub status:                String,
+}
+
+/// Sources of the state of the agent the dump is taken from
+#[derive(Clone)]
+pub struct DumpSources {
+    /// The config of the agent, already redacted
+    pub config:                Value,
+    pub local_store_tx:        mpsc::Sender<local::Message>,
+    pub transactions_store_tx: mpsc::Sender<transactions::Message>,
+    pub global_store_reader:   global::SnapshotReader,
+    pub publish_pause:         PublishPause,
+    pub api_connections:       ApiConnections,
+}
+
+impl DumpSources {
+    pub async fn dump(&self) -> Result<Dump> {
+        let snapshot = self.global_store_reader.load();
+        let symbol_index = &snapshot.account_metadata.symbol_index;
+
+        let (result_tx, result_rx) = oneshot::channel();
+        self.local_store_tx
+            .send(local::Message::LookupAllPriceInfo { result_tx })
+            .await
+            .map_err(|_| Error::ChannelClosed("local store"))?;
+        let mut local_store = result_rx
+            .await
+            .context("looking up the local store")?
+            .into_iter()
+            .flat_map(|(publisher, price_infos)| {
+                price_infos
+                    .into_iter()
+                    .map(move |(identifier, price_info)| (publisher, identifier, price_info))
+            })
+            .map(|(publisher, identifier, price_info)| LocalPrice {
+                publisher: publisher.map(|publisher| publisher.to_string()),
+                price_account: Pubkey::new_from_array(identifier.to_bytes()).to_string(),
+                symbol: symbol_index
+                    .symbol_of_identifier(&identifier)
+                    .map(str::to_string),
+                price_info,
+            })
+            .collect::<Vec<_>>();
+        local_store.sort_by(|a, b| {
+            (&a.symbol, &a.price_account, &a.publisher).cmp(&(
+                &b.symbol,
+                &b.price_account,
+                &b.publisher,
+            ))
+        });
+
+        let mut global_store = snapshot
+            .account_data
+            .price_accounts
+            .iter()
+            .map(|(price_key, price_account)| GlobalPrice {
+                price_account: price_key.to_string(),
+                symbol:        symbol_index.symbol(price_key).map(str::to_string),
+                price:         price_account.agg.price,
+                conf:          price_account.agg.conf,
+                expo:          price_account.expo,
+                status:        format!("{:?}", price_account.agg.status).to_lowercase(),
+                pub_slot:      price_account.agg.pub_slot,
+                components:    price_account.num as usize,
+            })
+            .collect::<Vec<_>>();
+        global_store
+            .sort_by(|a, b| (&a.symbol, &a.price_account).cmp(&(&b.symbol, &b.price_account)));
+
+        let (result_tx, result_rx) = oneshot::channel();
+        self.transactions_store_tx
+            .send(transactions::Message::LookupRecent { result_tx })
+            .await
+            .map_err(|_| Error::ChannelClosed("transactions store"))?;
+        let transactions = result_rx
+            .await
+            .context("looking up the transactions store")?
+            .into_iter()
+            .map(|record| Transaction {
+                network:               record.network,
+                signature:             record.signature.to_string(),
+                symbols:               record
+                    .price_accounts
+                    .iter()
+                    .map(|price_key| symbol_index.price_name(price_key))
+                    .collect(),
+                submit_time:           record.submit_time,
+                priority_fee_lamports: record.priority_fee_lamports,
+                status:                match record.status {
+                    TransactionStatus::Pending => "pending".to_string(),
+                    TransactionStatus::Confirmed => "confirmed".to_string(),
+                    TransactionStatus::Failed(reason) => format!("failed: {}", reason),
+                },
+            })
+            .collect();
+
+        Ok(Dump {
+            generated_at: Utc::now().timestamp(),
+            config: self.config.clone(),
+            local_store,
+            global_store,
+            transactions,
+            publish_pause: self.publish_pause.get().as_ref().clone(),
+            api_connections: self.api_connections.list(),
+        })
+    }
+}
+
+/// Replace the values of the keys which may hold credentials
+pub fn redact(value: Value) -> Value {
+    match value {
+        Value::Object(object) => Value::Object(
+            object
+                .into_iter()
+                .map(|(key, value)| {
+                    let is_secret = SECRET_KEY_PARTS.iter().any(|part| key.contains(part));
+                    let is_url = key.ends_with("url") || key.ends_with("urls");
+                    let value = match value {
+                        Value::Null => Value::Null,
+                        _ if is_secret => Value::String(REDACTED.to_string()),
+                        value if is_url => redact_urls(value),
+                        value => redact(value),
+                    };
+                    (key, value)
+                })
+                .collect(),
+        ),
+        Value::Array(values) => Value::Array(values.into_iter().map(redact).collect()),
+        value => value,
+    }
+}
+
+/// Replace the credentials, path and query of the URLs, e.g. in
+/// "redis://:password@host:6379", which are kept as "redis://host:6379/<redacted>"
+fn redact_urls(value: Value) -> Value {
+    match value {
+        Value::String(url) => {
+            let scheme = url
+                .split_once("://")
+                .map_or_else(String::new, |(scheme, _)| format!("{}://", scheme));
+            let stripped = format!("{}{}", scheme, endpoint_label(&url));
+            if stripped == url.trim_end_matches('/') {
+                Value::String(url)
+            } else {
+                Value::String(format!("{}/{}", stripped, REDACTED))
+            }
+        }
+        Value::Array(values) => Value::Array(values.into_iter().map(redact_urls).collect()),
+        value => value,
+    }
+}
+
+/// Fetch the dump of the agent whose Admin API is served at the URL
+pub async fn fetch(admin_url: &str, auth_token: &str) -> Result<Value> {
+    let response = reqwest::Client::new()
+        .get(format!("{}/dump", admin_url.trim_end_matches('/')))
+        .bearer_auth(auth_token)
+        .send()
+        .await
+        .with_context(|| format!("requesting the dump from {}", admin_url))?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(anyhow!("the Admin API responded {}: {}", status, body));
+    }
+    response.json().await.context("parsing the dump")
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::redact,
+        serde_json::json,
+    };
+
+    #[test]
+    fn test_credentials_are_redacted() {
+        assert_eq!(
+            redact(json!({
+                "admin_api": {"bind_address": "127.0.0.1:9002", "auth_token": "hunter2"},
+                "tenancy": {"tenants": [{"name": "first", "auth_token": "first-token"}]},
+                "kms_signer": {"secret_access_key": "secret", "access_key_id": "id"},
+                "remote_signer": {"auth_token": null},
+            })),
+            json!({
+                "admin_api": {"bind_address": "127.0.0.1:9002", "auth_token": "<redacted>"},
+                "tenancy": {"tenants": [{"name": "first", "auth_token": "<redacted>"}]},
+                "kms_signer": {"secret_access_key": "<redacted>", "access_key_id": "<redacted>"},
+                "remote_signer": {"auth_token": null},
+            })
+        );
+    }
+
+    #[test]
+    fn test_urls_are_redacted() {
+        assert_eq!(
+            redact(json!({
+                "alerting": {"webhooks": [{
+                    "kind": "pagerduty",
+                    "url": "https://hooks.slack.com/services/T000/B000/secret",
+                    "routing_key": "routing",
+                }]},
+                "redis_mirror": {"url": "redis://:password@redis.internal:6379"},
+                "primary_network": {
+                    "rpc_url": "http://localhost:8899",
+                    "oracle": {"fallback_rpc_urls": ["https://rpc.example.com?api-key=key"]},
+                },
+            })),
+            json!({
+                "alerting": {"webhooks": [{
+                    "kind": "pagerduty",
+                    "url": "https://hooks.slack.com/<redacted>",
+                    "routing_key": "<redacted>",
+                }]},
+                "redis_mirror": {"url": "redis://redis.internal:6379/<redacted>"},
+                "primary_network": {
+                    "rpc_url": "http://localhost:8899",
+                    "oracle": {"fallback_rpc_urls": ["https://rpc.example.com/<redacted>"]},
+                },
+            })
+        );
+    }
+}
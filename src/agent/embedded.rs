@@ -0,0 +1,283 @@
+// An agent embedded in a larger Rust service is started with `Agent::spawn`, which runs
+// the whole pipeline on the current runtime and returns an AgentHandle. Prices are then
+// submitted and subscribed to through the handle, which talks to the Pythd Adapter over
+// its channel as the API Server does, so that the embedding service needs neither a
+// websocket connection nor the JSON encoding of the pythd API. The pythd API is still
+// served as configured, for other publishers.
+//
+// An embedded agent does not reload its config on SIGHUP, nor shut down on SIGTERM or
+// SIGINT, leaving the signals to its host. It is shut down through its handle, in the
+// same phases as on a signal. Only one agent can run per process, as its metrics are
+// registered globally.
+use {
+    super::{
+        error::Error,
+        pythd::{
+            adapter::{
+                self,
+                Adapter,
+            },
+            api::{
+                self,
+                NotifyPrice,
+                ProductAccount,
+            },
+        },
+        telemetry,
+        update_status::{
+            Seq,
+            UpdateStatus,
+            UpdateStatuses,
+        },
+    },
+    anyhow::{
+        Context as _,
+        Result,
+    },
+    opentelemetry::{
+        Context,
+        KeyValue,
+    },
+    pyth_sdk_solana::state::PriceStatus,
+    solana_sdk::pubkey::Pubkey,
+    std::time::Instant,
+    tokio::{
+        sync::{
+            mpsc,
+            oneshot,
+        },
+        task::JoinHandle,
+    },
+};
+
+/// What a started embedded agent hands over to its handle
+pub struct Started {
+    pub adapter_tx:             mpsc::Sender<adapter::Message>,
+    pub update_statuses:        UpdateStatuses,
+    /// Starts the shutdown of the agent
+    pub shutdown_requested_tx:  oneshot::Sender<()>,
+    /// Capacity of the channel of the notifications of each subscription
+    pub notify_price_tx_buffer: usize,
+}
+
+/// An update of a price, submitted as through `update_price`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PriceUpdate {
+    pub account:   Pubkey,
+    pub price:     i64,
+    pub conf:      u64,
+    pub status:    PriceStatus,
+    /// Publish key the update is submitted on behalf of, the default one if None
+    pub publisher: Option<Pubkey>,
+}
+
+/// A new aggregate of a subscribed price, as notified through `notify_price`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AggregateNotification {
+    pub account:    Pubkey,
+    pub price:      i64,
+    pub conf:       u64,
+    pub status:     PriceStatus,
+    pub valid_slot: u64,
+    pub pub_slot:   u64,
+}
+
+/// The aggregates of a subscribed price. The subscription ends once dropped.
+pub struct PriceSubscription {
+    account:         Pubkey,
+    notify_price_rx: mpsc::Receiver<NotifyPrice>,
+}
+
+impl PriceSubscription {
+    /// The next aggregate of the price, None once the agent shut down
+    pub async fn recv(&mut self) -> Option<AggregateNotification> {
+        loop {
+            let notification = self.notify_price_rx.recv().await?;
+            match Adapter::map_status(&notification.result.status) {
+                Ok(status) => {
+                    return Some(AggregateNotification {
+                        account: self.account,
+                        price: notification.result.price,
+                        conf: notification.result.conf,
+                        status,
+                        valid_slot: notification.result.valid_slot,
+                        pub_slot: notification.result.pub_slot,
+                    })
+                }
+                Err(err) => warn!(
+                    account = %self.account,
+                    "Embedded agent: skipping notification: {:#}", err
+                ),
+            }
+        }
+    }
+}
+
+/// Handle of an embedded agent, through which prices are submitted and
+/// subscribed to
+pub struct AgentHandle {
+    adapter_tx:             mpsc::Sender<adapter::Message>,
+    update_statuses:        UpdateStatuses,
+    shutdown_requested_tx:  oneshot::Sender<()>,
+    notify_price_tx_buffer: usize,
+    /// Completes once the agent is shut down
+    jh:                     JoinHandle<Result<()>>,
+}
+
+impl AgentHandle {
+    pub fn new(started: Started, jh: JoinHandle<Result<()>>) -> Self {
+        AgentHandle {
+            adapter_tx: started.adapter_tx,
+            update_statuses: started.update_statuses,
+            shutdown_requested_tx: started.shutdown_requested_tx,
+            notify_price_tx_buffer: started.notify_price_tx_buffer,
+            jh,
+        }
+    }
+
+    /// Submit an update, returning the sequence id its status is followed by
+    pub async fn update_price(&self, update: PriceUpdate) -> Result<Seq> {
+        let received_at = Instant::now();
+        let account = update.account.to_string();
+        let publisher = update.publisher.map(|publisher| publisher.to_string());
+        let seq = self.update_statuses.accept(&account, publisher.as_deref());
+
+        // The update's trace starts here
+        let trace_context = telemetry::start_span(
+            &Context::new(),
+            "embedded.update_price",
+            vec![KeyValue::new("price_account", account.clone())],
+        );
+        let result = self
+            .adapter_tx
+            .send(adapter::Message::UpdatePrice {
+                account,
+                price: update.price,
+                conf: update.conf,
+                status: Adapter::price_status_to_str(update.status),
+                publisher,
+                seq,
+                received_at,
+                trace_context: trace_context.clone(),
+            })
+            .await
+            .map_err(|_| Error::ChannelClosed("adapter"))
+            .context("failed to send update to adapter");
+        telemetry::end_span(&trace_context, &result);
+        if let Err(err) = &result {
+            self.update_statuses.reject(seq, format!("{:#}", err));
+        }
+        result?;
+
+        Ok(seq)
+    }
+
+    /// The status of a submitted update, None once no longer tracked
+    pub fn update_status(&self, seq: Seq) -> Option<UpdateStatus> {
+        self.update_statuses.get(seq)
+    }
+
+    /// Subscribe to the new aggregates of a price
+    pub async fn subscribe_price(&self, account: Pubkey) -> Result<PriceSubscription> {
+        let (notify_price_tx, notify_price_rx) = mpsc::channel(self.notify_price_tx_buffer);
+        let (result_tx, result_rx) = oneshot::channel();
+        self.adapter_tx
+            .send(adapter::Message::SubscribePrice {
+                account: account.to_string(),
+                notify_price_tx,
+                price_changes: false,
+                result_tx,
+            })
+            .await
+            .map_err(|_| Error::ChannelClosed("adapter"))?;
+        result_rx.await??;
+        Ok(PriceSubscription {
+            account,
+            notify_price_rx,
+        })
+    }
+
+    /// All the products, with their prices, as returned by `get_all_products`
+    pub async fn get_all_products(&self) -> Result<Vec<ProductAccount>> {
+        let (result_tx, result_rx) = oneshot::channel();
+        self.adapter_tx
+            .send(adapter::Message::GetAllProducts { result_tx })
+            .await
+            .map_err(|_| Error::ChannelClosed("adapter"))?;
+        result_rx.await?
+    }
+
+    /// The products and the metadata of their prices, as returned by
+    /// `get_product_list`
+    pub async fn get_product_list(&self) -> Result<Vec<api::ProductAccountMetadata>> {
+        let (result_tx, result_rx) = oneshot::channel();
+        self.adapter_tx
+            .send(adapter::Message::GetProductList { result_tx })
+            .await
+            .map_err(|_| Error::ChannelClosed("adapter"))?;
+        result_rx.await?
+    }
+
+    /// Shut the agent down, publishing and persisting the accepted updates, and
+    /// wait until it stopped
+    pub async fn shutdown(self) -> Result<()> {
+        let _ = self.shutdown_requested_tx.send(());
+        self.jh.await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            AggregateNotification,
+            PriceSubscription,
+        },
+        crate::agent::pythd::api::{
+            NotifyPrice,
+            PriceUpdate,
+        },
+        pyth_sdk_solana::state::PriceStatus,
+        solana_sdk::pubkey::Pubkey,
+        tokio::sync::mpsc,
+    };
+
+    #[tokio::test]
+    async fn test_subscription_notifications_are_typed() {
+        let account = Pubkey::new_unique();
+        let (notify_price_tx, notify_price_rx) = mpsc::channel(10);
+        let mut subscription = PriceSubscription {
+            account,
+            notify_price_rx,
+        };
+        let notification = |status: &str| NotifyPrice {
+            subscription: 1,
+            result:       PriceUpdate {
+                price:      42,
+                conf:       2,
+                status:     status.to_string(),
+                valid_slot: 10,
+                pub_slot:   9,
+                changes:    None,
+            },
+        };
+
+        // Notifications with an unknown status are skipped
+        notify_price_tx.send(notification("bogus")).await.unwrap();
+        notify_price_tx.send(notification("trading")).await.unwrap();
+        drop(notify_price_tx);
+
+        assert_eq!(
+            subscription.recv().await,
+            Some(AggregateNotification {
+                account,
+                price: 42,
+                conf: 2,
+                status: PriceStatus::Trading,
+                valid_slot: 10,
+                pub_slot: 9,
+            })
+        );
+        assert_eq!(subscription.recv().await, None);
+    }
+}
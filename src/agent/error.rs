@@ -0,0 +1,131 @@
+// The components of the agent return anyhow errors, whose context chains are what the
+// logs show. Their underlying failures are classified into the kinds below, so that the
+// logs and the `error_count` metric can tell RPC failures from parse failures and closed
+// channels. Failures which don't come from a foreign error type are raised as an Error
+// at their source, and found anywhere in the chain by `ErrorKind::of`.
+use {
+    crate::agent::metrics::ERROR_METRICS,
+    solana_client::client_error::ClientError,
+    std::fmt,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// A request to an RPC node failed
+    #[error("RPC request failed: {0}")]
+    Rpc(#[from] ClientError),
+    /// Data received from an RPC node, a client or the disk could not be parsed
+    #[error("{0}")]
+    Parse(String),
+    /// The channel to another component was closed, or is full
+    #[error("channel to {0} unavailable")]
+    ChannelClosed(&'static str),
+    /// The Local or Global Store refused an update or a lookup
+    #[error("{0}")]
+    Store(String),
+    /// A request to the JSON-RPC API could not be served
+    #[error("{0}")]
+    Api(String),
+}
+
+impl Error {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Rpc(_) => ErrorKind::Rpc,
+            Error::Parse(_) => ErrorKind::Parse,
+            Error::ChannelClosed(_) => ErrorKind::ChannelClosed,
+            Error::Store(_) => ErrorKind::Store,
+            Error::Api(_) => ErrorKind::Api,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    Rpc,
+    Parse,
+    ChannelClosed,
+    Store,
+    Api,
+    /// Not classified
+    Other,
+}
+
+impl ErrorKind {
+    /// The kind of the first classified failure in the chain of the error
+    pub fn of(err: &anyhow::Error) -> Self {
+        if let Some(err) = err.downcast_ref::<Error>() {
+            return err.kind();
+        }
+        err.chain()
+            .find_map(|cause| {
+                if let Some(err) = cause.downcast_ref::<Error>() {
+                    Some(err.kind())
+                } else if cause.is::<ClientError>() {
+                    Some(ErrorKind::Rpc)
+                } else if cause.is::<serde_json::Error>()
+                    || cause.is::<pyth_sdk_solana::PythError>()
+                {
+                    Some(ErrorKind::Parse)
+                } else {
+                    None
+                }
+            })
+            .unwrap_or(ErrorKind::Other)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorKind::Rpc => "rpc",
+            ErrorKind::Parse => "parse",
+            ErrorKind::ChannelClosed => "channel_closed",
+            ErrorKind::Store => "store",
+            ErrorKind::Api => "api",
+            ErrorKind::Other => "other",
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Count the error of the component in the `error_count` metric, returning its
+/// kind for the log
+pub fn record(component: &str, err: &anyhow::Error) -> ErrorKind {
+    let kind = ErrorKind::of(err);
+    ERROR_METRICS.record(component, kind);
+    kind
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            Error,
+            ErrorKind,
+        },
+        anyhow::{
+            anyhow,
+            Context,
+        },
+    };
+
+    #[test]
+    fn test_errors_are_classified_through_their_context() {
+        let closed: anyhow::Result<()> =
+            Err(Error::ChannelClosed("local store")).context("fetching the prices");
+        assert_eq!(
+            ErrorKind::of(&closed.context("publishing").unwrap_err()),
+            ErrorKind::ChannelClosed
+        );
+
+        let parse: anyhow::Result<serde_json::Value> =
+            serde_json::from_str("{").context("reading the file");
+        assert_eq!(ErrorKind::of(&parse.unwrap_err()), ErrorKind::Parse);
+
+        assert_eq!(ErrorKind::of(&anyhow!("unexpected")), ErrorKind::Other);
+    }
+}
@@ -0,0 +1,192 @@
+// Fault injection for chaos testing, to verify the behaviour of the agent under RPC
+// flaps, websocket drops and channel saturation in integration tests and staging.
+// When configured, RPC requests fail or are delayed at random, the account
+// subscribers are disconnected at random intervals, and messages sent through the
+// Channel Monitor are dropped at random. The faults are injected in the layers
+// shared by all components, so the injector is enabled process-wide at startup,
+// like the RPC metrics. It must never be configured in production.
+use {
+    anyhow::{
+        bail,
+        Result,
+    },
+    arc_swap::ArcSwapOption,
+    lazy_static::lazy_static,
+    rand::Rng,
+    serde::{
+        Deserialize,
+        Serialize,
+    },
+    std::{
+        sync::Arc,
+        time::Duration,
+    },
+    tokio::time,
+};
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Probability of an RPC request failing without being sent
+    pub rpc_error_probability:          f64,
+    /// Probability of an RPC request being delayed before it is sent
+    pub rpc_latency_probability:        f64,
+    /// Delay added to the delayed RPC requests
+    #[serde(with = "humantime_serde")]
+    pub rpc_latency:                    Duration,
+    /// Mean interval between forced disconnects of the account subscribers, at
+    /// random. Subscribers are not disconnected when not set.
+    #[serde(with = "humantime_serde")]
+    pub subscriber_disconnect_interval: Option<Duration>,
+    /// Time a disconnected subscriber waits before subscribing again
+    #[serde(with = "humantime_serde")]
+    pub subscriber_reconnect_delay:     Duration,
+    /// Probability of a message sent through the Channel Monitor being dropped
+    pub channel_drop_probability:       f64,
+    /// Channels on which messages are dropped, by their monitored name, e.g.
+    /// "primary_subscriber_updates". Messages are dropped on all the monitored
+    /// channels when empty.
+    pub channels:                       Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            rpc_error_probability:          0.0,
+            rpc_latency_probability:        0.0,
+            rpc_latency:                    Duration::from_secs(1),
+            subscriber_disconnect_interval: None,
+            subscriber_reconnect_delay:     Duration::from_secs(5),
+            channel_drop_probability:       0.0,
+            channels:                       vec![],
+        }
+    }
+}
+
+lazy_static! {
+    static ref FAULT_INJECTOR: ArcSwapOption<FaultInjector> = ArcSwapOption::empty();
+}
+
+/// Enable fault injection for the whole process
+pub fn enable(config: Config) -> Result<()> {
+    let injector = FaultInjector::new(config)?;
+    warn!(config = ?injector.config, "fault injection enabled");
+    FAULT_INJECTOR.store(Some(Arc::new(injector)));
+    Ok(())
+}
+
+/// The injected failure of an RPC request, if any, after the injected delay
+pub async fn rpc_fault(method: &str) -> Option<String> {
+    let injector = FAULT_INJECTOR.load_full()?;
+    injector.rpc_fault(method).await
+}
+
+/// Whether to drop a message sent on the given monitored channel
+pub fn drop_message(channel: &str) -> bool {
+    FAULT_INJECTOR
+        .load()
+        .as_ref()
+        .map_or(false, |injector| injector.drop_message(channel))
+}
+
+/// Completes when a subscriber should be disconnected, never if disconnects are
+/// not injected. Returns how long to stay disconnected.
+pub async fn subscriber_disconnect() -> Duration {
+    let injector = FAULT_INJECTOR.load_full();
+    match injector.as_ref().and_then(|injector| {
+        Some((
+            injector.config.subscriber_disconnect_interval?,
+            injector.config.subscriber_reconnect_delay,
+        ))
+    }) {
+        Some((mean_interval, reconnect_delay)) => {
+            // Exponentially distributed, for disconnects independent of each other
+            let uniform: f64 = rand::thread_rng().gen_range(f64::EPSILON..1.0);
+            time::sleep(mean_interval.mul_f64(-uniform.ln())).await;
+            reconnect_delay
+        }
+        None => futures_util::future::pending().await,
+    }
+}
+
+struct FaultInjector {
+    config: Config,
+}
+
+impl FaultInjector {
+    fn new(config: Config) -> Result<Self> {
+        for (name, probability) in [
+            ("rpc_error_probability", config.rpc_error_probability),
+            ("rpc_latency_probability", config.rpc_latency_probability),
+            ("channel_drop_probability", config.channel_drop_probability),
+        ] {
+            if !(0.0..=1.0).contains(&probability) {
+                bail!(
+                    "fault_injection.{} must be between 0 and 1, got {}",
+                    name,
+                    probability
+                );
+            }
+        }
+        Ok(FaultInjector { config })
+    }
+
+    async fn rpc_fault(&self, method: &str) -> Option<String> {
+        if rand::thread_rng().gen_bool(self.config.rpc_latency_probability) {
+            debug!(method, latency = ?self.config.rpc_latency, "fault injection: delaying RPC request");
+            time::sleep(self.config.rpc_latency).await;
+        }
+        if rand::thread_rng().gen_bool(self.config.rpc_error_probability) {
+            debug!(method, "fault injection: failing RPC request");
+            return Some(format!("fault injection: {} request failed", method));
+        }
+        None
+    }
+
+    fn drop_message(&self, channel: &str) -> bool {
+        let targeted =
+            self.config.channels.is_empty() || self.config.channels.iter().any(|c| c == channel);
+        let dropped = targeted && rand::thread_rng().gen_bool(self.config.channel_drop_probability);
+        if dropped {
+            debug!(channel, "fault injection: dropping message");
+        }
+        dropped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            Config,
+            FaultInjector,
+        },
+        std::time::Duration,
+    };
+
+    #[tokio::test]
+    async fn test_faults_follow_config() {
+        assert!(FaultInjector::new(Config {
+            rpc_error_probability: 1.5,
+            ..Default::default()
+        })
+        .is_err());
+
+        let injector = FaultInjector::new(Config {
+            rpc_error_probability: 1.0,
+            rpc_latency_probability: 1.0,
+            rpc_latency: Duration::from_millis(1),
+            channel_drop_probability: 1.0,
+            channels: vec!["primary_subscriber_updates".to_string()],
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(injector.rpc_fault("getSlot").await.is_some());
+        assert!(injector.drop_message("primary_subscriber_updates"));
+        assert!(!injector.drop_message("local_store"));
+
+        let injector = FaultInjector::new(Default::default()).unwrap();
+        assert!(injector.rpc_fault("getSlot").await.is_none());
+        assert!(!injector.drop_message("local_store"));
+    }
+}
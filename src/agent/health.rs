@@ -0,0 +1,135 @@
+//! Component health reporting. Long-running components report their
+//! status through a ComponentHealth handle, and the MetricsServer
+//! aggregates them on its health endpoints.
+use {
+    chrono::Utc,
+    parking_lot::RwLock,
+    pyth_sdk::UnixTimestamp,
+    serde::Serialize,
+    std::{
+        collections::BTreeMap,
+        sync::Arc,
+    },
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    /// The component has not reported yet
+    Starting,
+    Healthy,
+    Unhealthy,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ComponentStatus {
+    pub status:      Status,
+    /// Human-readable detail of the last report
+    pub detail:      String,
+    /// Time of the last report
+    pub last_update: UnixTimestamp,
+}
+
+/// Overall status along with the status of each component
+#[derive(Clone, Debug, Serialize)]
+pub struct HealthReport {
+    pub status:     Status,
+    pub components: BTreeMap<String, ComponentStatus>,
+}
+
+/// Shared registry of the status of all components
+#[derive(Clone, Default)]
+pub struct HealthReporter {
+    components: Arc<RwLock<BTreeMap<String, ComponentStatus>>>,
+}
+
+impl HealthReporter {
+    /// Register a component, returning the handle it reports its status through
+    pub fn component(&self, name: impl Into<String>) -> ComponentHealth {
+        let name = name.into();
+        self.components.write().insert(
+            name.clone(),
+            ComponentStatus {
+                status:      Status::Starting,
+                detail:      "starting".to_string(),
+                last_update: Utc::now().timestamp(),
+            },
+        );
+
+        ComponentHealth {
+            name,
+            reporter: self.clone(),
+        }
+    }
+
+    /// The latest status of every registered component
+    pub fn components(&self) -> BTreeMap<String, ComponentStatus> {
+        self.components.read().clone()
+    }
+
+    /// Healthy unless a component reported itself unhealthy. Components which
+    /// are still starting do not count against the agent's health.
+    pub fn health(&self) -> HealthReport {
+        let components = self.components();
+        let status = if components
+            .values()
+            .any(|component| component.status == Status::Unhealthy)
+        {
+            Status::Unhealthy
+        } else {
+            Status::Healthy
+        };
+
+        HealthReport { status, components }
+    }
+
+    /// Ready once every component has reported itself healthy
+    pub fn readiness(&self) -> HealthReport {
+        let components = self.components();
+        let status = if components
+            .values()
+            .any(|component| component.status == Status::Unhealthy)
+        {
+            Status::Unhealthy
+        } else if components
+            .values()
+            .all(|component| component.status == Status::Healthy)
+        {
+            Status::Healthy
+        } else {
+            Status::Starting
+        };
+
+        HealthReport { status, components }
+    }
+
+    fn report(&self, name: &str, status: Status, detail: String) {
+        self.components.write().insert(
+            name.to_string(),
+            ComponentStatus {
+                status,
+                detail,
+                last_update: Utc::now().timestamp(),
+            },
+        );
+    }
+}
+
+/// Handle through which a single component reports its status
+#[derive(Clone)]
+pub struct ComponentHealth {
+    name:     String,
+    reporter: HealthReporter,
+}
+
+impl ComponentHealth {
+    pub fn healthy(&self, detail: impl Into<String>) {
+        self.reporter
+            .report(&self.name, Status::Healthy, detail.into());
+    }
+
+    pub fn unhealthy(&self, detail: impl Into<String>) {
+        self.reporter
+            .report(&self.name, Status::Unhealthy, detail.into());
+    }
+}
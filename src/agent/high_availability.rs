@@ -0,0 +1,292 @@
+// Two agents can run as a high-availability pair, of which only the leader publishes.
+// They share a lease file, e.g. on an NFS mount, naming the agent holding it along
+// with a sequence number. The leader increments the sequence on every renewal, and the
+// standby takes the lease over once it has not changed for the failover window, as
+// measured on the standby's own clock so that the clocks of the hosts need not agree.
+// A takeover is only effective if the next read of the lease still names the new
+// holder, so that two agents claiming it at once settle on the last one to write it.
+//
+// The standby runs every component except that its Exporters skip publishing, through
+// the standby flag of the publish pause, so its stores are warm when it takes over. An
+// agent which cannot read or renew the lease stands by, preferring no publishing to
+// double publishing. A leader which stalls for longer than the failover window may
+// still publish for up to one renew interval after resuming, before it reads that the
+// lease was taken over.
+use {
+    crate::agent::{
+        metrics::{
+            HighAvailabilityMetrics,
+            PROMETHEUS_REGISTRY,
+        },
+        publish_pause::PublishPause,
+    },
+    anyhow::{
+        Context,
+        Result,
+    },
+    serde::{
+        Deserialize,
+        Serialize,
+    },
+    std::{
+        io::ErrorKind,
+        path::{
+            Path,
+            PathBuf,
+        },
+        time::Duration,
+    },
+    tokio::{
+        fs,
+        task::JoinHandle,
+        time::{
+            self,
+            Instant,
+        },
+    },
+    tracing::Instrument,
+};
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Path of the lease file shared by the agents of the pair
+    pub lock_path:       PathBuf,
+    /// Identifies this agent in the lease file. A random one is used when
+    /// empty, so a restarted agent waits for the failover window before
+    /// taking over its own lease.
+    pub node_id:         String,
+    /// Duration of the interval at which the lease is read, and renewed by
+    /// the leader
+    #[serde(with = "humantime_serde")]
+    pub renew_interval:  Duration,
+    /// How long the lease must go without renewal before the standby takes
+    /// it over
+    #[serde(with = "humantime_serde")]
+    pub failover_window: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            lock_path:       "agent.lock".into(),
+            node_id:         String::new(),
+            renew_interval:  Duration::from_secs(1),
+            failover_window: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Content of the lease file
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct Lease {
+    holder:   String,
+    sequence: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Role {
+    Standby,
+    /// Claimed the lease, and leads if the next read still names it
+    Candidate,
+    Leader,
+}
+
+/// Decides on the role of this agent from the successive reads of the lease
+struct Election {
+    node_id:         String,
+    failover_window: Duration,
+    role:            Role,
+    /// The lease held by another agent, with when it was first read
+    last_seen:       Option<(Lease, Instant)>,
+}
+
+impl Election {
+    /// Take the role given by the lease just read, returning the lease to
+    /// write if this agent renews or claims it
+    fn step(&mut self, lease: Option<Lease>, now: Instant) -> Option<Lease> {
+        let lease = match lease {
+            Some(lease) if lease.holder == self.node_id => {
+                self.role = Role::Leader;
+                return Some(Lease {
+                    holder:   self.node_id.clone(),
+                    sequence: lease.sequence + 1,
+                });
+            }
+            Some(lease) => lease,
+            None => {
+                self.role = Role::Candidate;
+                return Some(Lease {
+                    holder:   self.node_id.clone(),
+                    sequence: 0,
+                });
+            }
+        };
+
+        let seen_at = match &self.last_seen {
+            Some((seen, seen_at)) if *seen == lease => *seen_at,
+            _ => {
+                self.last_seen = Some((lease.clone(), now));
+                now
+            }
+        };
+        if now.duration_since(seen_at) < self.failover_window {
+            self.role = Role::Standby;
+            return None;
+        }
+
+        self.role = Role::Candidate;
+        Some(Lease {
+            holder:   self.node_id.clone(),
+            sequence: lease.sequence + 1,
+        })
+    }
+}
+
+pub fn spawn_elector(config: Config, publish_pause: PublishPause) -> JoinHandle<()> {
+    // Nothing is published until this agent holds the lease
+    publish_pause.set_standby(true);
+    tokio::spawn(
+        async move {
+            let metrics = HighAvailabilityMetrics::new(&mut &mut PROMETHEUS_REGISTRY.lock().await);
+            let node_id = if config.node_id.is_empty() {
+                format!("{:016x}", rand::random::<u64>())
+            } else {
+                config.node_id.clone()
+            };
+            info!(%node_id, lock_path = %config.lock_path.display(), "starting as standby");
+
+            let mut election = Election {
+                node_id,
+                failover_window: config.failover_window,
+                role: Role::Standby,
+                last_seen: None,
+            };
+            let mut renew_interval = time::interval(config.renew_interval);
+            loop {
+                renew_interval.tick().await;
+
+                let was_leader = election.role == Role::Leader;
+                if let Err(err) = elect(&mut election, &config.lock_path).await {
+                    error!(error = ?err, "High availability: {:#}", err);
+                    election.role = Role::Standby;
+                }
+                let is_leader = election.role == Role::Leader;
+
+                if is_leader != was_leader {
+                    info!(
+                        audit = true,
+                        node_id = %election.node_id,
+                        "High availability: {}",
+                        if is_leader {
+                            "took over as leader"
+                        } else {
+                            "standing by"
+                        }
+                    );
+                    metrics.record_transition(is_leader);
+                }
+                publish_pause.set_standby(!is_leader);
+            }
+        }
+        .instrument(info_span!("high_availability")),
+    )
+}
+
+async fn elect(election: &mut Election, lock_path: &Path) -> Result<()> {
+    let lease = read_lease(lock_path).await?;
+    if let Some(lease) = election.step(lease, Instant::now()) {
+        write_lease(lock_path, &election.node_id, &lease).await?;
+    }
+    Ok(())
+}
+
+async fn read_lease(lock_path: &Path) -> Result<Option<Lease>> {
+    match fs::read(lock_path).await {
+        Ok(contents) => Ok(Some(
+            serde_json::from_slice(&contents).context("parsing the lease")?,
+        )),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err).context("reading the lease"),
+    }
+}
+
+/// Replace the lease file, through a rename so that it is never read partly
+/// written
+async fn write_lease(lock_path: &Path, node_id: &str, lease: &Lease) -> Result<()> {
+    let temp_path = lock_path.with_extension(format!("{}.tmp", node_id));
+    fs::write(&temp_path, serde_json::to_vec(lease)?)
+        .await
+        .context("writing the lease")?;
+    fs::rename(&temp_path, lock_path)
+        .await
+        .context("replacing the lease")
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            Election,
+            Lease,
+            Role,
+        },
+        std::time::Duration,
+        tokio::time::Instant,
+    };
+
+    #[test]
+    fn test_standby_takes_over_once_the_lease_is_not_renewed() {
+        let mut election = Election {
+            node_id:         "standby".to_string(),
+            failover_window: Duration::from_secs(5),
+            role:            Role::Standby,
+            last_seen:       None,
+        };
+        let lease = |holder: &str, sequence| {
+            Some(Lease {
+                holder: holder.to_string(),
+                sequence,
+            })
+        };
+        let start = Instant::now();
+
+        // The leader renews the lease within the failover window
+        assert_eq!(election.step(lease("leader", 1), start), None);
+        assert_eq!(
+            election.step(lease("leader", 2), start + Duration::from_secs(4)),
+            None
+        );
+        assert_eq!(
+            election.step(lease("leader", 2), start + Duration::from_secs(8)),
+            None
+        );
+        assert_eq!(election.role, Role::Standby);
+
+        // The lease is claimed once unchanged for the failover window, and
+        // held if the next read still names this agent
+        assert_eq!(
+            election.step(lease("leader", 2), start + Duration::from_secs(9)),
+            lease("standby", 3)
+        );
+        assert_eq!(election.role, Role::Candidate);
+        assert_eq!(
+            election.step(lease("standby", 3), start + Duration::from_secs(10)),
+            lease("standby", 4)
+        );
+        assert_eq!(election.role, Role::Leader);
+
+        // Another agent taking the lease over makes this one stand by
+        assert_eq!(
+            election.step(lease("leader", 5), start + Duration::from_secs(11)),
+            None
+        );
+        assert_eq!(election.role, Role::Standby);
+
+        // A missing lease is claimed at once
+        assert_eq!(
+            election.step(None, start + Duration::from_secs(12)),
+            lease("standby", 0)
+        );
+    }
+}
@@ -0,0 +1,258 @@
+// The dashboard, its JSON API and the metrics expose operational details of the agent
+// (publish keys, lag, balances), and are open to anyone who can reach the metrics
+// server unless `metrics_server.auth` is set. The credentials set there are read-only:
+// they grant access to the dashboard and to the read-only endpoints of the Admin API,
+// while the endpoints of the Admin API changing the behaviour of the agent require its
+// own bearer token. The Admin API token is also accepted on the dashboard.
+//
+// Credentials are sent in the Authorization header, either as a bearer token or with
+// basic authentication, which browsers prompt for.
+use {
+    super::dump::redacted,
+    serde::{
+        Deserialize,
+        Serialize,
+    },
+    std::{
+        convert::Infallible,
+        fmt,
+    },
+    subtle::ConstantTimeEq,
+    warp::{
+        hyper::{
+            header,
+            StatusCode,
+        },
+        reject::{
+            self,
+            Reject,
+        },
+        reply::{
+            self,
+            Reply,
+        },
+        Filter,
+        Rejection,
+    },
+};
+
+/// Realm of the basic authentication prompt of browsers
+const REALM: &str = "pyth-agent";
+
+#[derive(Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Credentials {
+    /// Bearer token accepted in the Authorization header
+    pub token: Option<String>,
+    /// Username and password accepted with basic authentication
+    pub basic: Option<BasicCredentials>,
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct BasicCredentials {
+    pub username: String,
+    pub password: String,
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("token", &redacted(self.token.as_deref()))
+            .field("basic", &self.basic)
+            .finish()
+    }
+}
+
+impl fmt::Debug for BasicCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BasicCredentials")
+            .field("username", &self.username)
+            .field("password", &redacted(Some(self.password.as_str())))
+            .finish()
+    }
+}
+
+/// What the credentials of a request grant access to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    /// The dashboard and the read-only endpoints of the Admin API
+    ReadOnly,
+    /// Every endpoint, including those changing the behaviour of the agent
+    Admin,
+}
+
+/// Checks the Authorization header of the requests against the configured
+/// credentials
+#[derive(Clone)]
+pub struct Authenticator {
+    /// Authorization headers accepted, with the role they grant. Every request
+    /// is granted the Admin role when None.
+    accepted: Option<Vec<(String, Role)>>,
+    /// Whether basic authentication is accepted, in which case browsers are
+    /// prompted for it
+    basic:    bool,
+}
+
+impl Authenticator {
+    /// Accept every request, as when no credentials are configured
+    pub fn open() -> Self {
+        Authenticator {
+            accepted: None,
+            basic:    false,
+        }
+    }
+
+    pub fn new(read_only: Option<&Credentials>, admin_token: Option<&str>) -> Self {
+        let mut accepted = vec![];
+        if let Some(read_only) = read_only {
+            if let Some(token) = &read_only.token {
+                accepted.push((format!("Bearer {}", token), Role::ReadOnly));
+            }
+            if let Some(basic) = &read_only.basic {
+                let user_pass = format!("{}:{}", basic.username, basic.password);
+                accepted.push((
+                    format!("Basic {}", base64::encode(user_pass)),
+                    Role::ReadOnly,
+                ));
+            }
+        }
+        if let Some(admin_token) = admin_token {
+            accepted.push((format!("Bearer {}", admin_token), Role::Admin));
+        }
+        Authenticator {
+            basic:    read_only.map_or(false, |read_only| read_only.basic.is_some()),
+            accepted: Some(accepted),
+        }
+    }
+
+    /// The role granted by the Authorization header, None if the request is
+    /// not authenticated. The header is compared in constant time to every
+    /// accepted one, so that the time taken does not tell how much of a
+    /// credential was guessed.
+    pub fn role(&self, authorization: Option<&str>) -> Option<Role> {
+        let accepted = match &self.accepted {
+            Some(accepted) => accepted,
+            None => return Some(Role::Admin),
+        };
+        let authorization = authorization?;
+        accepted
+            .iter()
+            .filter(|(expected, _)| bool::from(expected.as_bytes().ct_eq(authorization.as_bytes())))
+            .map(|(_, role)| *role)
+            .max()
+    }
+
+    /// Whether the request is granted the role
+    pub fn authorized(
+        &self,
+        role: Role,
+    ) -> impl Filter<Extract = (bool,), Error = Infallible> + Clone {
+        let authenticator = self.clone();
+        warp::header::optional::<String>("authorization").map(
+            move |authorization: Option<String>| {
+                authenticator
+                    .role(authorization.as_deref())
+                    .map_or(false, |granted| granted >= role)
+            },
+        )
+    }
+
+    /// Reject the requests which are not granted the role, to be recovered
+    /// from with `recover`
+    pub fn require(&self, role: Role) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+        let basic = self.basic;
+        self.authorized(role)
+            .and_then(move |authorized| async move {
+                if authorized {
+                    Ok(())
+                } else {
+                    Err(reject::custom(Unauthorized { basic }))
+                }
+            })
+            .untuple_one()
+    }
+}
+
+#[derive(Debug)]
+struct Unauthorized {
+    basic: bool,
+}
+
+impl Reject for Unauthorized {
+}
+
+/// Reply with 401 to the requests rejected by `require`
+pub async fn recover(rejection: Rejection) -> Result<Box<dyn Reply>, Rejection> {
+    let unauthorized = match rejection.find::<Unauthorized>() {
+        Some(unauthorized) => unauthorized,
+        None => return Err(rejection),
+    };
+    let reply = reply::with_status("Unauthorized", StatusCode::UNAUTHORIZED);
+    Ok(if unauthorized.basic {
+        Box::new(reply::with_header(
+            reply,
+            header::WWW_AUTHENTICATE,
+            format!("Basic realm=\"{}\"", REALM),
+        ))
+    } else {
+        Box::new(reply)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Authenticator,
+        BasicCredentials,
+        Credentials,
+        Role,
+    };
+
+    #[test]
+    fn test_roles_granted_by_the_credentials() {
+        let authenticator = Authenticator::new(
+            Some(&Credentials {
+                token: Some("viewer".to_string()),
+                basic: Some(BasicCredentials {
+                    username: "ops".to_string(),
+                    password: "secret".to_string(),
+                }),
+            }),
+            Some("admin"),
+        );
+
+        assert_eq!(authenticator.role(None), None);
+        assert_eq!(authenticator.role(Some("Bearer wrong")), None);
+        assert_eq!(
+            authenticator.role(Some("Bearer viewer")),
+            Some(Role::ReadOnly)
+        );
+        // base64 of "ops:secret"
+        assert_eq!(
+            authenticator.role(Some("Basic b3BzOnNlY3JldA==")),
+            Some(Role::ReadOnly)
+        );
+        assert_eq!(authenticator.role(Some("Bearer admin")), Some(Role::Admin));
+
+        // Without credentials, every request is granted every role
+        assert_eq!(Authenticator::open().role(None), Some(Role::Admin));
+    }
+
+    #[test]
+    fn test_secrets_are_redacted_from_debug() {
+        let debug = format!(
+            "{:?}",
+            Credentials {
+                token: Some("viewer-token".to_string()),
+                basic: Some(BasicCredentials {
+                    username: "ops".to_string(),
+                    password: "secret".to_string(),
+                }),
+            }
+        );
+        assert!(!debug.contains("viewer-token"));
+        assert!(!debug.contains("secret"));
+        assert!(debug.contains("ops"));
+    }
+}
@@ -0,0 +1,581 @@
+// The Kafka Sink publishes every observed aggregate and component update to Kafka,
+// for downstream research systems. It subscribes to the events of the Global Store,
+// and produces a message for each new aggregate of a price, and for each component
+// published since the previous update of the price account, keyed by price account.
+// Messages are encoded as JSON, or as Avro datums of the schemas below. Batching is
+// left to the producer, and the outcome of each delivery is counted per topic.
+//
+// The producer requires the agent to be built with the "kafka" feature.
+use {
+    crate::agent::{
+        dump::redacted,
+        pythd::adapter::Adapter,
+        solana::oracle::PriceEntry,
+        store::global,
+    },
+    anyhow::Result,
+    serde::{
+        Deserialize,
+        Serialize,
+    },
+    solana_sdk::pubkey::Pubkey,
+    std::{
+        collections::HashMap,
+        fmt,
+        time::Duration,
+    },
+    tokio::{
+        sync::broadcast,
+        task::JoinHandle,
+    },
+};
+
+/// Avro schema of the aggregate update messages
+pub const AGGREGATE_SCHEMA: &str = r#"{
+    "type": "record",
+    "name": "AggregateUpdate",
+    "namespace": "network.pyth.agent",
+    "fields": [
+        {"name": "price_account", "type": "string"},
+        {"name": "product_account", "type": "string"},
+        {"name": "symbol", "type": ["null", "string"]},
+        {"name": "price", "type": "long"},
+        {"name": "conf", "type": "long"},
+        {"name": "expo", "type": "int"},
+        {"name": "status", "type": "string"},
+        {"name": "pub_slot", "type": "long"},
+        {"name": "timestamp", "type": "long"}
+    ]
+}"#;
+
+/// Avro schema of the component update messages
+pub const COMPONENT_SCHEMA: &str = r#"{
+    "type": "record",
+    "name": "ComponentUpdate",
+    "namespace": "network.pyth.agent",
+    "fields": [
+        {"name": "price_account", "type": "string"},
+        {"name": "symbol", "type": ["null", "string"]},
+        {"name": "publisher", "type": "string"},
+        {"name": "price", "type": "long"},
+        {"name": "conf", "type": "long"},
+        {"name": "expo", "type": "int"},
+        {"name": "status", "type": "string"},
+        {"name": "pub_slot", "type": "long"}
+    ]
+}"#;
+
+#[derive(Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Bootstrap servers of the Kafka cluster, e.g. "kafka-1:9092,kafka-2:9092".
+    /// The sink is disabled when not set.
+    pub brokers:             Option<String>,
+    /// Topic the aggregate updates are published to
+    pub aggregate_topic:     String,
+    /// Topic the component updates are published to. Component updates are
+    /// not published when not set.
+    pub component_topic:     Option<String>,
+    /// Encoding of the messages
+    pub format:              Format,
+    /// Maximum number of messages batched in a produce request
+    pub batch_size:          usize,
+    /// Time the producer waits for a batch to fill before sending it
+    #[serde(with = "humantime_serde")]
+    pub linger:              Duration,
+    /// Maximum number of messages awaiting delivery. Further messages are
+    /// dropped, so that a slow cluster never holds up the agent.
+    pub queue_size:          usize,
+    /// Additional librdkafka producer properties, e.g. "security.protocol"
+    pub producer_properties: HashMap<String, String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            brokers:             None,
+            aggregate_topic:     "pyth.aggregates".to_string(),
+            component_topic:     Some("pyth.components".to_string()),
+            format:              Format::Json,
+            batch_size:          1000,
+            linger:              Duration::from_millis(100),
+            queue_size:          100000,
+            producer_properties: HashMap::new(),
+        }
+    }
+}
+
+/// Parts of the keys of the producer properties holding credentials, e.g.
+/// "sasl.password" or "ssl.key.password"
+const SECRET_PROPERTY_PARTS: &[&str] = &["password", "secret"];
+
+impl fmt::Debug for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let producer_properties = self
+            .producer_properties
+            .iter()
+            .map(|(key, value)| {
+                let value = if SECRET_PROPERTY_PARTS.iter().any(|part| key.contains(part)) {
+                    redacted(Some(value.as_str())).unwrap_or_default()
+                } else {
+                    value.as_str()
+                };
+                (key, value)
+            })
+            .collect::<HashMap<_, _>>();
+        f.debug_struct("Config")
+            .field("brokers", &self.brokers)
+            .field("aggregate_topic", &self.aggregate_topic)
+            .field("component_topic", &self.component_topic)
+            .field("format", &self.format)
+            .field("batch_size", &self.batch_size)
+            .field("linger", &self.linger)
+            .field("queue_size", &self.queue_size)
+            .field("producer_properties", &producer_properties)
+            .finish()
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Json,
+    Avro,
+}
+
+/// A new aggregate of a price
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct AggregateUpdate {
+    pub price_account:   String,
+    pub product_account: String,
+    pub symbol:          Option<String>,
+    pub price:           i64,
+    pub conf:            u64,
+    pub expo:            i32,
+    pub status:          String,
+    pub pub_slot:        u64,
+    /// Unix timestamp of the aggregate
+    pub timestamp:       i64,
+}
+
+impl AggregateUpdate {
+    pub fn new(account_key: Pubkey, account: &PriceEntry, symbol: Option<String>) -> Self {
+        AggregateUpdate {
+            price_account: account_key.to_string(),
+            product_account: account.prod.to_string(),
+            symbol,
+            price: account.agg.price,
+            conf: account.agg.conf,
+            expo: account.expo,
+            status: Adapter::price_status_to_str(account.agg.status),
+            pub_slot: account.agg.pub_slot,
+            timestamp: account.timestamp,
+        }
+    }
+}
+
+/// A newly published component of a price
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct ComponentUpdate {
+    pub price_account: String,
+    pub symbol:        Option<String>,
+    pub publisher:     String,
+    pub price:         i64,
+    pub conf:          u64,
+    pub expo:          i32,
+    pub status:        String,
+    pub pub_slot:      u64,
+}
+
+#[cfg(feature = "kafka")]
+pub fn spawn_sink(
+    config: Config,
+    global_store_events_rx: broadcast::Receiver<global::Event>,
+    global_store_reader: global::SnapshotReader,
+) -> Result<JoinHandle<()>> {
+    producer::spawn_sink(config, global_store_events_rx, global_store_reader)
+}
+
+#[cfg(not(feature = "kafka"))]
+pub fn spawn_sink(
+    _config: Config,
+    _global_store_events_rx: broadcast::Receiver<global::Event>,
+    _global_store_reader: global::SnapshotReader,
+) -> Result<JoinHandle<()>> {
+    anyhow::bail!("kafka.brokers is set, but the agent was built without the kafka feature")
+}
+
+/// Extracts the updates of the price accounts which were not published yet
+#[derive(Default)]
+#[cfg_attr(not(feature = "kafka"), allow(dead_code))]
+struct Updates {
+    /// Slot of the last published aggregate of each price account
+    aggregate_slots: HashMap<Pubkey, u64>,
+    /// Slot of the last published component of each price account and publisher
+    component_slots: HashMap<(Pubkey, Pubkey), u64>,
+}
+
+#[cfg_attr(not(feature = "kafka"), allow(dead_code))]
+impl Updates {
+    fn extract(
+        &mut self,
+        account_key: Pubkey,
+        account: &PriceEntry,
+        snapshot: &global::Snapshot,
+    ) -> (Option<AggregateUpdate>, Vec<ComponentUpdate>) {
+        let symbol = snapshot
+            .account_metadata
+            .symbol_index
+            .symbol(&account_key)
+            .map(str::to_string);
+
+        let aggregate = match self
+            .aggregate_slots
+            .insert(account_key, account.agg.pub_slot)
+        {
+            Some(slot) if slot >= account.agg.pub_slot => None,
+            _ => Some(AggregateUpdate::new(account_key, account, symbol.clone())),
+        };
+
+        let components = account
+            .comp
+            .iter()
+            .filter(|component| component.publisher != Pubkey::default())
+            .filter(|component| {
+                match self.component_slots.insert(
+                    (account_key, component.publisher),
+                    component.latest.pub_slot,
+                ) {
+                    Some(slot) => slot < component.latest.pub_slot,
+                    None => true,
+                }
+            })
+            .map(|component| ComponentUpdate {
+                price_account: account_key.to_string(),
+                symbol:        symbol.clone(),
+                publisher:     component.publisher.to_string(),
+                price:         component.latest.price,
+                conf:          component.latest.conf,
+                expo:          account.expo,
+                status:        Adapter::price_status_to_str(component.latest.status),
+                pub_slot:      component.latest.pub_slot,
+            })
+            .collect();
+
+        (aggregate, components)
+    }
+}
+
+#[cfg(feature = "kafka")]
+mod producer {
+    use {
+        super::{
+            Config,
+            Format,
+            Updates,
+            AGGREGATE_SCHEMA,
+            COMPONENT_SCHEMA,
+        },
+        crate::agent::{
+            metrics::{
+                KafkaMetrics,
+                PROMETHEUS_REGISTRY,
+            },
+            store::global,
+        },
+        anyhow::{
+            anyhow,
+            Context,
+            Result,
+        },
+        apache_avro::Schema,
+        futures_util::{
+            stream::FuturesUnordered,
+            StreamExt,
+        },
+        rdkafka::{
+            config::ClientConfig,
+            error::{
+                KafkaError,
+                RDKafkaErrorCode,
+            },
+            producer::{
+                DeliveryFuture,
+                FutureProducer,
+                FutureRecord,
+            },
+        },
+        serde::Serialize,
+        std::future::Future,
+        tokio::{
+            sync::broadcast,
+            task::JoinHandle,
+        },
+        tracing::Instrument,
+    };
+
+    pub fn spawn_sink(
+        config: Config,
+        global_store_events_rx: broadcast::Receiver<global::Event>,
+        global_store_reader: global::SnapshotReader,
+    ) -> Result<JoinHandle<()>> {
+        let brokers = config
+            .brokers
+            .as_ref()
+            .ok_or_else(|| anyhow!("no Kafka brokers configured"))?;
+        let mut client_config = ClientConfig::new();
+        client_config
+            .set("bootstrap.servers", brokers)
+            .set("batch.num.messages", config.batch_size.to_string())
+            .set("linger.ms", config.linger.as_millis().to_string())
+            .set(
+                "queue.buffering.max.messages",
+                config.queue_size.to_string(),
+            );
+        for (key, value) in &config.producer_properties {
+            client_config.set(key, value);
+        }
+        let producer: FutureProducer = client_config.create().context("creating Kafka producer")?;
+
+        let schemas = match config.format {
+            Format::Json => None,
+            Format::Avro => Some((
+                Schema::parse_str(AGGREGATE_SCHEMA)?,
+                Schema::parse_str(COMPONENT_SCHEMA)?,
+            )),
+        };
+
+        Ok(tokio::spawn(
+            async move {
+                let mut sink = Sink {
+                    metrics: KafkaMetrics::new(&mut &mut PROMETHEUS_REGISTRY.lock().await),
+                    config,
+                    producer,
+                    schemas,
+                    updates: Updates::default(),
+                    global_store_events_rx,
+                    global_store_reader,
+                };
+                sink.run().await
+            }
+            .instrument(info_span!("kafka_sink")),
+        ))
+    }
+
+    struct Sink {
+        config:                 Config,
+        producer:               FutureProducer,
+        /// Schemas of the aggregate and component messages, when encoded as Avro
+        schemas:                Option<(Schema, Schema)>,
+        updates:                Updates,
+        metrics:                KafkaMetrics,
+        global_store_events_rx: broadcast::Receiver<global::Event>,
+        global_store_reader:    global::SnapshotReader,
+    }
+
+    impl Sink {
+        async fn run(&mut self) {
+            let mut deliveries = FuturesUnordered::new();
+            loop {
+                tokio::select! {
+                    event = self.global_store_events_rx.recv() => match event {
+                        Ok(global::Event::PriceUpdated { account_key, account, .. }) => {
+                            let (aggregate, components) = self.updates.extract(
+                                account_key,
+                                &account,
+                                &self.global_store_reader.load(),
+                            );
+                            let key = account_key.to_string();
+                            if let Some(aggregate) = aggregate {
+                                let topic = &self.config.aggregate_topic;
+                                let schema = self.schemas.as_ref().map(|(schema, _)| schema);
+                                if let Some(payload) = self.encode(topic, &aggregate, schema) {
+                                    deliveries.extend(self.produce(topic, &key, payload));
+                                }
+                            }
+                            if let Some(topic) = &self.config.component_topic {
+                                let schema = self.schemas.as_ref().map(|(_, schema)| schema);
+                                for component in components {
+                                    if let Some(payload) = self.encode(topic, &component, schema) {
+                                        deliveries.extend(self.produce(topic, &key, payload));
+                                    }
+                                }
+                            }
+                        }
+                        Ok(_) => {}
+                        Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                            warn!(skipped, "Kafka sink: missed global store events");
+                        }
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    },
+                    Some((topic, delivery)) = deliveries.next() => {
+                        match delivery {
+                            Ok(Ok(_)) => self.metrics.delivered(&topic),
+                            Ok(Err((err, _))) => {
+                                self.metrics.failed(&topic);
+                                warn!(error = ?err, %topic, "Kafka sink: delivery failed");
+                            }
+                            Err(_) => self.metrics.failed(&topic),
+                        }
+                    }
+                }
+            }
+        }
+
+        /// Encode a message as Avro if its schema is given, as JSON otherwise.
+        /// Messages which fail to be encoded are dropped.
+        fn encode<T: Serialize>(
+            &self,
+            topic: &str,
+            message: &T,
+            schema: Option<&Schema>,
+        ) -> Option<Vec<u8>> {
+            let encoded = match schema {
+                Some(schema) => apache_avro::to_value(message)
+                    .and_then(|value| value.resolve(schema))
+                    .and_then(|value| apache_avro::to_avro_datum(schema, value))
+                    .map_err(anyhow::Error::from),
+                None => serde_json::to_vec(message).map_err(anyhow::Error::from),
+            };
+            match encoded {
+                Ok(payload) => Some(payload),
+                Err(err) => {
+                    self.metrics.dropped(topic);
+                    error!(error = ?err, %topic, "Kafka sink: could not encode message: {:#}", err);
+                    None
+                }
+            }
+        }
+
+        /// Enqueue a message, returning its pending delivery. Messages which do
+        /// not fit in the queue are dropped.
+        fn produce(
+            &self,
+            topic: &str,
+            key: &str,
+            payload: Vec<u8>,
+        ) -> Option<impl Future<Output = (String, DeliveryResult)>> {
+            match self
+                .producer
+                .send_result(FutureRecord::to(topic).key(key).payload(&payload))
+            {
+                Ok(delivery) => {
+                    let topic = topic.to_string();
+                    Some(async move { (topic, delivery.await) })
+                }
+                Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), _)) => {
+                    self.metrics.dropped(topic);
+                    None
+                }
+                Err((err, _)) => {
+                    self.metrics.dropped(topic);
+                    warn!(error = ?err, %topic, "Kafka sink: could not enqueue message");
+                    None
+                }
+            }
+        }
+    }
+
+    type DeliveryResult = <DeliveryFuture as Future>::Output;
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            AggregateUpdate,
+            Config,
+            Updates,
+        },
+        crate::agent::{
+            solana::oracle::PriceEntry,
+            store::global::{
+                ProductAccountMetadata,
+                Snapshot,
+            },
+        },
+        pyth_sdk_solana::state::PriceStatus,
+        solana_sdk::pubkey::Pubkey,
+    };
+
+    #[test]
+    fn test_secret_properties_are_redacted_from_debug() {
+        let debug = format!(
+            "{:?}",
+            Config {
+                producer_properties: [
+                    ("security.protocol", "SASL_SSL"),
+                    ("sasl.password", "sasl-secret"),
+                    ("ssl.key.password", "key-secret"),
+                ]
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+                ..Default::default()
+            }
+        );
+        assert!(!debug.contains("sasl-secret"));
+        assert!(!debug.contains("key-secret"));
+        assert!(debug.contains("SASL_SSL"));
+    }
+
+    #[test]
+    fn test_updates_are_extracted_once() {
+        let account_key = Pubkey::new_unique();
+        let product_key = Pubkey::new_unique();
+        let publisher = Pubkey::new_unique();
+        let mut snapshot = Snapshot::default();
+        snapshot.account_metadata.insert_product(
+            product_key,
+            ProductAccountMetadata {
+                attr_dict:      [("symbol".to_string(), "Crypto.BTC/USD".to_string())]
+                    .into_iter()
+                    .collect(),
+                price_accounts: vec![account_key],
+            },
+        );
+
+        let mut account = PriceEntry::default();
+        account.prod = product_key;
+        account.expo = -2;
+        account.timestamp = 1_700_000_000;
+        account.agg.price = 100;
+        account.agg.conf = 1;
+        account.agg.status = PriceStatus::Trading;
+        account.agg.pub_slot = 10;
+        account.comp[0].publisher = publisher;
+        account.comp[0].latest.price = 101;
+        account.comp[0].latest.pub_slot = 9;
+
+        let mut updates = Updates::default();
+        let (aggregate, components) = updates.extract(account_key, &account, &snapshot);
+        assert_eq!(
+            aggregate,
+            Some(AggregateUpdate {
+                price_account:   account_key.to_string(),
+                product_account: product_key.to_string(),
+                symbol:          Some("Crypto.BTC/USD".to_string()),
+                price:           100,
+                conf:            1,
+                expo:            -2,
+                status:          "trading".to_string(),
+                pub_slot:        10,
+                timestamp:       1_700_000_000,
+            })
+        );
+        assert_eq!(components.len(), 1);
+        assert_eq!(components[0].publisher, publisher.to_string());
+        assert_eq!(components[0].price, 101);
+
+        // Updates already published are not extracted again
+        let (aggregate, components) = updates.extract(account_key, &account, &snapshot);
+        assert!(aggregate.is_none());
+        assert!(components.is_empty());
+
+        // A newly published component is extracted without the unchanged aggregate
+        account.comp[0].latest.pub_slot = 11;
+        let (aggregate, components) = updates.extract(account_key, &account, &snapshot);
+        assert!(aggregate.is_none());
+        assert_eq!(components.len(), 1);
+    }
+}
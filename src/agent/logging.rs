@@ -0,0 +1,232 @@
+// Logging is done with `tracing`. Each spawned task runs in a span carrying the
+// fields identifying it, such as its network and component, which are added to
+// every event logged within it. Events are written as human-readable lines or as
+// JSON, and counted by level in the metrics. Unless the RUST_LOG environment
+// variable is set, the minimum level of the written events can be changed at
+// runtime, globally or per module.
+use {
+    crate::agent::metrics::LOG_METRICS,
+    arc_swap::ArcSwap,
+    serde::{
+        Deserialize,
+        Serialize,
+    },
+    std::{
+        collections::BTreeMap,
+        env,
+        sync::Arc,
+    },
+    tracing::{
+        subscriber::Interest,
+        Event,
+        Level,
+        Metadata,
+        Subscriber,
+    },
+    tracing_appender::non_blocking::{
+        NonBlockingBuilder,
+        WorkerGuard,
+    },
+    tracing_subscriber::{
+        layer::{
+            Context,
+            SubscriberExt,
+        },
+        registry::LookupSpan,
+        util::SubscriberInitExt,
+        EnvFilter,
+        Layer,
+        Registry,
+    },
+};
+
+/// Format of the written events
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    /// Human-readable lines
+    Pretty,
+    /// One JSON object per line
+    Json,
+}
+
+impl Default for LogFormat {
+    fn default() -> Self {
+        LogFormat::Pretty
+    }
+}
+
+/// Install the global subscriber, writing to stdout, or to stderr if stdout is
+/// taken, from a background thread which buffers at most `buffer_capacity`
+/// lines. Returns the runtime-adjustable log level, unless the level is set by
+/// RUST_LOG, and the guard which flushes the buffered lines when dropped.
+pub fn init(
+    level: Level,
+    format: LogFormat,
+    buffer_capacity: usize,
+    stderr: bool,
+) -> (Option<LogLevel>, WorkerGuard) {
+    let output: Box<dyn std::io::Write + Send + Sync> = if stderr {
+        Box::new(std::io::stderr())
+    } else {
+        Box::new(std::io::stdout())
+    };
+    let (writer, guard) = NonBlockingBuilder::default()
+        .buffered_lines_limit(buffer_capacity)
+        .finish(output);
+
+    // RUST_LOG takes precedence over the log level of the config, which can
+    // then be changed at runtime
+    let (filter, log_level): (Box<dyn Layer<Registry> + Send + Sync>, _) =
+        match env::var(EnvFilter::DEFAULT_ENV) {
+            Ok(directives) => (Box::new(EnvFilter::new(directives)), None),
+            Err(_) => {
+                let log_level = LogLevel::new(level);
+                (Box::new(log_level.clone()), Some(log_level))
+            }
+        };
+
+    let output = tracing_subscriber::fmt::layer().with_writer(writer);
+    let output = match format {
+        LogFormat::Pretty => output.boxed(),
+        LogFormat::Json => output.json().boxed(),
+    };
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(output)
+        .with(MetricsLayer)
+        .init();
+
+    (log_level, guard)
+}
+
+#[derive(Clone, Debug)]
+struct Levels {
+    global:  Level,
+    /// Levels overriding the global one for the events of a module and its
+    /// submodules, by module path, e.g. "pyth_agent::agent::solana::exporter"
+    modules: BTreeMap<String, Level>,
+}
+
+/// Minimum level of the written events, which can be changed at runtime,
+/// globally or for specific modules. Spans are always enabled, so that their
+/// fields are available to the events of any level.
+#[derive(Clone)]
+pub struct LogLevel(Arc<ArcSwap<Levels>>);
+
+impl LogLevel {
+    pub fn new(level: Level) -> Self {
+        LogLevel(Arc::new(ArcSwap::from_pointee(Levels {
+            global:  level,
+            modules: BTreeMap::new(),
+        })))
+    }
+
+    pub fn get(&self) -> Level {
+        self.0.load().global
+    }
+
+    pub fn set(&self, level: Level) {
+        self.0.rcu(|levels| Levels {
+            global:  level,
+            modules: levels.modules.clone(),
+        });
+    }
+
+    pub fn modules(&self) -> BTreeMap<String, Level> {
+        self.0.load().modules.clone()
+    }
+
+    /// Set the level of a module, or make it follow the global level again
+    /// if None
+    pub fn set_module(&self, module: &str, level: Option<Level>) {
+        self.0.rcu(|levels| {
+            let mut levels = levels.as_ref().clone();
+            match level {
+                Some(level) => levels.modules.insert(module.to_string(), level),
+                None => levels.modules.remove(module),
+            };
+            levels
+        });
+    }
+
+    /// Minimum level of the events of the given module: the level of the
+    /// most specific module it is part of, or the global level
+    pub fn get_for_module(&self, module: &str) -> Level {
+        let levels = self.0.load();
+        levels
+            .modules
+            .iter()
+            .filter(|(prefix, _)| {
+                module
+                    .strip_prefix(prefix.as_str())
+                    .map_or(false, |rest| rest.is_empty() || rest.starts_with("::"))
+            })
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map_or(levels.global, |(_, level)| *level)
+    }
+}
+
+impl<S: Subscriber> Layer<S> for LogLevel {
+    fn register_callsite(&self, _metadata: &'static Metadata<'static>) -> Interest {
+        // The level may change, so it is checked for every event
+        Interest::sometimes()
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>, _ctx: Context<'_, S>) -> bool {
+        // More verbose levels compare greater
+        metadata.is_span() || *metadata.level() <= self.get_for_module(metadata.target())
+    }
+}
+
+/// Counts the written events by level
+struct MetricsLayer;
+
+impl<S> Layer<S> for MetricsLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        LOG_METRICS.record(event.metadata().level());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::LogLevel,
+        tracing::Level,
+    };
+
+    #[test]
+    fn test_module_log_level() {
+        let log_level = LogLevel::new(Level::INFO);
+        log_level.set_module("pyth_agent::agent::solana", Some(Level::DEBUG));
+        log_level.set_module("pyth_agent::agent::solana::exporter", Some(Level::TRACE));
+
+        assert_eq!(
+            log_level.get_for_module("pyth_agent::agent::solana::oracle"),
+            Level::DEBUG
+        );
+        assert_eq!(
+            log_level.get_for_module("pyth_agent::agent::solana::exporter"),
+            Level::TRACE
+        );
+        // Only whole path segments match
+        assert_eq!(
+            log_level.get_for_module("pyth_agent::agent::solana_extra"),
+            Level::INFO
+        );
+        assert_eq!(
+            log_level.get_for_module("pyth_agent::agent::store"),
+            Level::INFO
+        );
+
+        log_level.set_module("pyth_agent::agent::solana", None);
+        assert_eq!(
+            log_level.get_for_module("pyth_agent::agent::solana::oracle"),
+            Level::INFO
+        );
+    }
+}
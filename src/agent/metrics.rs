@@ -1,12 +1,65 @@
+pub mod http_cache;
+pub mod statsd;
+
 use {
-    super::store::{
-        global::Lookup,
-        local::Message,
+    self::http_cache::Conditional,
+    super::{
+        anomaly_detector,
+        build_info::BuildInfo,
+        dashboard::{
+            stream_dashboard_rows,
+            DashboardQuery,
+            DashboardSymbolView,
+            DASHBOARD_SCRIPT,
+        },
+        error::ErrorKind,
+        health::{
+            HealthReport,
+            HealthReporter,
+            Status,
+        },
+        http_auth::{
+            self,
+            Authenticator,
+            Role,
+        },
+        publish_pause::{
+            PauseState,
+            PublishPause,
+        },
+        publisher_performance::{
+            self,
+            PublisherPerformance,
+        },
+        pythd::adapter,
+        snapshot_diff::CanonicalSnapshot,
+        store::{
+            global::SnapshotReader,
+            local::Message,
+            transactions,
+        },
+        uptime::{
+            self,
+            UptimeQuery,
+        },
     },
     crate::agent::{
-        solana::oracle::PriceEntry,
+        solana::{
+            mapping_usage::{
+                MappingUsage,
+                MappingUsageReporter,
+            },
+            oracle::PriceEntry,
+            slot_lag::{
+                SlotLag,
+                SlotLagReporter,
+            },
+        },
         store::{
-            local::PriceInfo,
+            local::{
+                PriceInfo,
+                Publisher,
+            },
             PriceIdentifier,
         },
     },
@@ -20,27 +73,45 @@ use {
             counter::Counter,
             family::Family,
             gauge::Gauge,
+            histogram::{
+                exponential_buckets,
+                linear_buckets,
+                Histogram,
+            },
+            info::Info,
         },
         registry::Registry,
     },
-    serde::Deserialize,
-    slog::Logger,
+    serde::{
+        Deserialize,
+        Serialize,
+    },
     solana_sdk::pubkey::Pubkey,
     std::{
+        collections::BTreeMap,
         net::SocketAddr,
+        str::FromStr,
         sync::{
             atomic::AtomicU64,
             Arc,
         },
-        time::Instant,
+        time::{
+            Duration,
+            Instant,
+        },
     },
     tokio::sync::{
         mpsc,
         Mutex,
     },
+    tokio_stream::wrappers::ReceiverStream,
     warp::{
         hyper::StatusCode,
-        reply::{self,},
+        path::Tail,
+        reply::{
+            self,
+        },
+        sse,
         Filter,
         Rejection,
         Reply,
@@ -51,48 +122,198 @@ pub fn default_bind_address() -> SocketAddr {
     "127.0.0.1:8888".parse().unwrap()
 }
 
-#[derive(Deserialize, Debug)]
+pub fn default_dashboard_refresh_interval() -> Duration {
+    Duration::from_secs(1)
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
     #[serde(default = "default_bind_address")]
-    pub bind_address: SocketAddr,
+    pub bind_address:               SocketAddr,
+    /// Interval at which changed rows are pushed to live dashboard clients
+    #[serde(
+        default = "default_dashboard_refresh_interval",
+        with = "humantime_serde"
+    )]
+    pub dashboard_refresh_interval: Duration,
+    /// Publisher keys whose on-chain components are compared to the
+    /// aggregate on the dashboard
+    #[serde(default)]
+    pub dashboard_publisher_keys:   Vec<String>,
+    /// Read-only credentials required to access the dashboard, its JSON API
+    /// and the metrics, which also accept the Admin API token. Open to anyone
+    /// when not set.
+    #[serde(default)]
+    pub auth:                       Option<http_auth::Credentials>,
+    /// Optional sink pushing the metrics to a StatsD agent
+    #[serde(default)]
+    pub statsd:                     statsd::Config,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
-            bind_address: default_bind_address(),
+            bind_address:               default_bind_address(),
+            dashboard_refresh_interval: default_dashboard_refresh_interval(),
+            dashboard_publisher_keys:   vec![],
+            auth:                       None,
+            statsd:                     statsd::Config::default(),
         }
     }
 }
 
+/// Capacity of the channel buffering row events for a single live
+/// dashboard client
+const DASHBOARD_EVENTS_CHANNEL_CAPACITY: usize = 1000;
+
+/// Header of the /api/dashboard responses holding the number of pages of
+/// symbols matching the query
+const PAGE_COUNT_HEADER: &str = "x-page-count";
+
 lazy_static! {
-    pub static ref PROMETHEUS_REGISTRY: Arc<Mutex<Registry>> =
-        Arc::new(Mutex::new(<Registry>::default()));
+    /// Shared by all instrumented RPC clients, which are created outside of
+    /// an async context. Registered along with the registry itself.
+    pub static ref RPC_METRICS: RpcMetrics = RpcMetrics::default();
+    /// Shared by all remote signers, for the same reason
+    pub static ref SIGNER_METRICS: SignerMetrics = SignerMetrics::default();
+    /// Recorded by the logging subscriber, which is installed before the
+    /// registry is first used
+    pub static ref LOG_METRICS: LogMetrics = LogMetrics::default();
+    /// Recorded by the Exporters, which are created before the registry can be locked
+    pub static ref PUBLISH_PERMISSION_METRICS: PublishPermissionMetrics =
+        PublishPermissionMetrics::default();
+    /// Recorded by the Exporters and their Transaction Monitors, for the same reason
+    pub static ref PUBLISH_RETRY_METRICS: PublishRetryMetrics = PublishRetryMetrics::default();
+    /// Recorded by the Exporters in dry run, for the same reason
+    pub static ref DRY_RUN_METRICS: DryRunMetrics = DryRunMetrics::default();
+    /// Recorded by the Exporters applying confidence floors, for the same reason
+    pub static ref CONF_FLOOR_METRICS: ConfFloorMetrics = ConfFloorMetrics::default();
+    /// Recorded by the Pollers of the Oracles, which are created before the registry can be locked
+    pub static ref ACCOUNT_CHECK_METRICS: AccountCheckMetrics = AccountCheckMetrics::default();
+    /// Recorded by the Oracles, for the same reason
+    pub static ref DUPLICATE_UPDATE_METRICS: DuplicateUpdateMetrics =
+        DuplicateUpdateMetrics::default();
+    /// Recorded by the Oracles, which are created before the registry can be locked
+    pub static ref GLOBAL_STORE_BACKPRESSURE_METRICS: GlobalStoreBackpressureMetrics =
+        GlobalStoreBackpressureMetrics::default();
+    /// Recorded by every component on error, wherever it runs
+    pub static ref ERROR_METRICS: ErrorMetrics = ErrorMetrics::default();
+    /// Recorded by the Accumulator Trackers, which are created before the registry can be locked
+    pub static ref ACCUMULATOR_METRICS: AccumulatorMetrics = AccumulatorMetrics::default();
+    /// Recorded by the connections of the pythd API, which may run on another runtime
+    pub static ref TENANT_METRICS: TenantMetrics = TenantMetrics::default();
+    /// Recorded by the connections of the pythd API, for the same reason
+    pub static ref API_METRICS: ApiMetrics = ApiMetrics::default();
+    pub static ref PROMETHEUS_REGISTRY: Arc<Mutex<Registry>> = {
+        let mut registry = <Registry>::default();
+        RPC_METRICS.register(&mut registry);
+        SIGNER_METRICS.register(&mut registry);
+        LOG_METRICS.register(&mut registry);
+        PUBLISH_PERMISSION_METRICS.register(&mut registry);
+        PUBLISH_RETRY_METRICS.register(&mut registry);
+        DRY_RUN_METRICS.register(&mut registry);
+        CONF_FLOOR_METRICS.register(&mut registry);
+        ACCOUNT_CHECK_METRICS.register(&mut registry);
+        DUPLICATE_UPDATE_METRICS.register(&mut registry);
+        GLOBAL_STORE_BACKPRESSURE_METRICS.register(&mut registry);
+        ERROR_METRICS.register(&mut registry);
+        ACCUMULATOR_METRICS.register(&mut registry);
+        TENANT_METRICS.register(&mut registry);
+        API_METRICS.register(&mut registry);
+        registry.register(
+            "agent_build",
+            "Build of the running agent, as the labels of a constant 1",
+            Info::new(BuildInfo::current().labels()),
+        );
+        Arc::new(Mutex::new(registry))
+    };
 }
 
 /// Internal metrics server state, holds state needed for serving
 /// dashboard and metrics.
 pub struct MetricsServer {
     /// Used to pull the state of all symbols in local store
-    pub local_store_tx:         mpsc::Sender<Message>,
-    pub global_store_lookup_tx: mpsc::Sender<Lookup>,
-    pub start_time:             Instant,
-    pub logger:                 Logger,
+    pub local_store_tx:             mpsc::Sender<Message>,
+    /// Used to pull the recently sent transactions
+    pub transactions_store_tx:      mpsc::Sender<transactions::Message>,
+    /// Used to read the latest state of the global store
+    pub global_store_reader:        SnapshotReader,
+    /// Interval at which changed rows are pushed to live dashboard clients
+    pub dashboard_refresh_interval: Duration,
+    /// Age after which a price's last on-chain publish is shown as stale
+    pub staleness_threshold:        Duration,
+    /// Publishers whose components are shown on the dashboard
+    pub publisher_keys:             Vec<Pubkey>,
+    /// Per-price gauges derived from the dashboard data
+    pub dashboard_metrics:          DashboardMetrics,
+    /// Slots observed on each network, shown in the network sections of the dashboard
+    pub slot_lags:                  SlotLagReporter,
+    /// Usage of the mapping accounts of each network, shown in the network
+    /// sections of the dashboard
+    pub mapping_usage:              MappingUsageReporter,
+    /// Statuses of the components, those of the Oracles and Exporters being
+    /// shown in the network sections of the dashboard
+    pub health:                     HealthReporter,
+    /// Publishing paused through the Admin API, shown on the dashboard
+    pub publish_pause:              PublishPause,
+    /// Used to pull the publisher performance statistics, if tracked
+    pub publisher_performance_tx:   Option<mpsc::Sender<publisher_performance::Message>>,
+    /// Used to pull the uptime of our publishers, if tracked
+    pub uptime_tx:                  Option<mpsc::Sender<uptime::Message>>,
+    /// Used to pull the flagged price anomalies, if detected
+    pub anomaly_detector_tx:        Option<mpsc::Sender<anomaly_detector::Message>>,
+    pub start_time:                 Instant,
 }
 
 impl MetricsServer {
     /// Instantiate a metrics API with a dashboard
     pub async fn spawn(
         addr: impl Into<SocketAddr> + 'static,
+        dashboard_refresh_interval: Duration,
+        staleness_threshold: Duration,
+        publisher_keys: Vec<String>,
+        authenticator: Option<Authenticator>,
         local_store_tx: mpsc::Sender<Message>,
-        global_store_lookup_tx: mpsc::Sender<Lookup>,
-        logger: Logger,
+        transactions_store_tx: mpsc::Sender<transactions::Message>,
+        global_store_reader: SnapshotReader,
+        health: HealthReporter,
+        slot_lags: SlotLagReporter,
+        mapping_usage: MappingUsageReporter,
+        publish_pause: PublishPause,
+        publisher_performance_tx: Option<mpsc::Sender<publisher_performance::Message>>,
+        uptime_tx: Option<mpsc::Sender<uptime::Message>>,
+        anomaly_detector_tx: Option<mpsc::Sender<anomaly_detector::Message>>,
     ) {
+        let publisher_keys = publisher_keys
+            .iter()
+            .filter_map(|key| match Pubkey::from_str(key) {
+                Ok(key) => Some(key),
+                Err(e) => {
+                    warn!(%key, error = %e, "Dashboard: ignoring invalid publisher key");
+                    None
+                }
+            })
+            .collect();
+
+        let global_store_reader4api_products = global_store_reader.clone();
+        let global_store_reader4api_snapshot = global_store_reader.clone();
         let server = MetricsServer {
             local_store_tx,
-            global_store_lookup_tx,
+            transactions_store_tx,
+            global_store_reader,
+            dashboard_refresh_interval,
+            staleness_threshold,
+            publisher_keys,
+            dashboard_metrics: DashboardMetrics::new(&mut &mut PROMETHEUS_REGISTRY.lock().await),
+            slot_lags,
+            mapping_usage,
+            health: health.clone(),
+            publish_pause,
+            publisher_performance_tx,
+            uptime_tx,
+            anomaly_detector_tx,
             start_time: Instant::now(),
-            logger,
         };
 
         let shared_state = Arc::new(Mutex::new(server));
@@ -100,52 +321,394 @@ impl MetricsServer {
         let shared_state4dashboard = shared_state.clone();
         let dashboard_route = warp::path("dashboard")
             .or(warp::path::end())
-            .and_then(move |_| {
+            .and(warp::query::<DashboardQuery>())
+            .and(http_cache::conditional())
+            .and_then(move |_, query: DashboardQuery, conditional: Conditional| {
                 let shared_state = shared_state4dashboard.clone();
                 async move {
                     let locked_state = shared_state.lock().await;
                     let response = locked_state
-                        .render_dashboard() // Defined in a separate impl block near dashboard-specific code
+                        .render_dashboard(&query) // Defined in a separate impl block near dashboard-specific code
                         .await
                         .unwrap_or_else(|e| {
                             // Add logging here
-			    error!(locked_state.logger,"Dashboard: Rendering failed"; "error" => e.to_string());
+                            error!(error = %e, "Dashboard: Rendering failed");
 
                             // Withhold failure details from client
                             "Could not render dashboard! See the logs for details".to_owned()
                         });
-                    Result::<Box<dyn Reply>, Rejection>::Ok(Box::new(reply::with_status(
-                        reply::html(response),
-                        StatusCode::OK,
-                    )))
+                    Result::<Box<dyn Reply>, Rejection>::Ok(conditional.reply(
+                        "text/html; charset=utf-8",
+                        response,
+                        None,
+                    ))
                 }
             });
 
         let shared_state4metrics = shared_state.clone();
         let metrics_route = warp::path("metrics")
             .and(warp::path::end())
-            .and_then(move || {
+            .and(http_cache::conditional())
+            .and_then(move |conditional: Conditional| {
                 let shared_state = shared_state4metrics.clone();
                 async move {
-		    let locked_state = shared_state.lock().await;
+                    let locked_state = shared_state.lock().await;
+                    if let Err(e) = locked_state
+                        .update_dashboard_metrics()
+                        .await
+                        .map_err(|e| e.to_string())
+                    {
+                        error!(error = %e, "Metrics: Could not update dashboard metrics");
+                    }
                     let mut buf = String::new();
-                    let response = encode(&mut buf, &&PROMETHEUS_REGISTRY.lock().await).map_err(|e| -> Box<dyn std::error::Error> {e.into()
-		    }).and_then(|_| -> Result<_, Box<dyn std::error::Error>> {
+                    let response = encode(&mut buf, &&PROMETHEUS_REGISTRY.lock().await)
+                        .map_err(|e| -> Box<dyn std::error::Error> { e.into() })
+                        .and_then(|_| -> Result<_, Box<dyn std::error::Error>> {
+                            Ok(conditional.reply("text/plain; charset=utf-8", buf, None))
+                        })
+                        .unwrap_or_else(|e| {
+                            error!(error = %e, "Metrics: Could not gather metrics from registry");
+
+                            Box::new(reply::with_status(
+                                "Could not gather metrics. See logs for details".to_string(),
+                                StatusCode::INTERNAL_SERVER_ERROR,
+                            ))
+                        });
+
+                    Result::<Box<dyn Reply>, Rejection>::Ok(response)
+                }
+            });
+
+        let dashboard_script_route = warp::path!("dashboard.js")
+            .and(http_cache::conditional())
+            .map(|conditional: Conditional| {
+                conditional.reply("application/javascript", DASHBOARD_SCRIPT, None)
+            });
 
-			Ok(Box::new(reply::with_status(buf, StatusCode::OK)))
-		    }).unwrap_or_else(|e| {
-			error!(locked_state.logger, "Metrics: Could not gather metrics from registry"; "error" => e.to_string());
+        let shared_state4csv = shared_state.clone();
+        let dashboard_csv_route = warp::path!("dashboard.csv")
+            .and(warp::query::<DashboardQuery>())
+            .and(http_cache::conditional())
+            .and_then(move |query: DashboardQuery, conditional: Conditional| {
+                let shared_state = shared_state4csv.clone();
+                async move {
+                    let locked_state = shared_state.lock().await;
+                    let response = match locked_state
+                        .render_dashboard_csv(&query.without_pagination())
+                        .await
+                        .map_err(|e| e.to_string())
+                    {
+                        Ok(csv) => conditional.reply("text/csv", csv, None),
+                        Err(e) => Self::api_error_reply(e),
+                    };
+                    Result::<Box<dyn Reply>, Rejection>::Ok(response)
+                }
+            });
+
+        let shared_state4api_events = shared_state.clone();
+        let api_events_route = warp::path!("api" / "dashboard" / "events")
+            .and(warp::query::<DashboardQuery>())
+            .map(move |query: DashboardQuery| {
+                let (events_tx, events_rx) = mpsc::channel(DASHBOARD_EVENTS_CHANNEL_CAPACITY);
+                tokio::spawn(stream_dashboard_rows(
+                    shared_state4api_events.clone(),
+                    query,
+                    events_tx,
+                ));
+                sse::reply(sse::keep_alive().stream(ReceiverStream::new(events_rx)))
+            });
+
+        let shared_state4api_dashboard = shared_state.clone();
+        let api_dashboard_route = warp::path!("api" / "dashboard")
+            .and(warp::query::<DashboardQuery>())
+            .and(http_cache::conditional())
+            .and_then(move |query: DashboardQuery, conditional: Conditional| {
+                let shared_state = shared_state4api_dashboard.clone();
+                async move {
+                    let locked_state = shared_state.lock().await;
+                    // The symbols of the page are returned, and the number of
+                    // pages in a header
+                    let response: Box<dyn Reply> = match locked_state.dashboard_json(&query).await {
+                        Ok((symbol_view, num_pages)) => Box::new(reply::with_header(
+                            Self::json_reply(&conditional, &symbol_view),
+                            PAGE_COUNT_HEADER,
+                            num_pages.to_string(),
+                        )),
+                        Err(e) => Self::api_error_reply(e.to_string()),
+                    };
+                    Result::<Box<dyn Reply>, Rejection>::Ok(response)
+                }
+            });
+
+        // Symbol names may contain slashes (e.g. Crypto.BTC/USD), so
+        // the whole remaining path is taken as the symbol.
+        let shared_state4api_symbol = shared_state.clone();
+        let api_symbol_route = warp::path("api")
+            .and(warp::path("symbols"))
+            .and(warp::path::tail())
+            .and(http_cache::conditional())
+            .and_then(move |symbol: Tail, conditional: Conditional| {
+                let shared_state = shared_state4api_symbol.clone();
+                async move {
+                    let locked_state = shared_state.lock().await;
+                    let response = match locked_state.dashboard_json(&DashboardQuery::all()).await {
+                        Ok((mut symbol_view, _num_pages)) => {
+                            match symbol_view.remove(symbol.as_str()) {
+                                Some(symbol_data) => Self::json_reply(&conditional, &symbol_data),
+                                None => Box::new(reply::with_status(
+                                    format!("Unknown symbol {}", symbol.as_str()),
+                                    StatusCode::NOT_FOUND,
+                                )),
+                            }
+                        }
+                        Err(e) => Self::api_error_reply(e.to_string()),
+                    };
+                    Result::<Box<dyn Reply>, Rejection>::Ok(response)
+                }
+            });
+
+        // Products are filtered by their attributes, e.g.
+        // /api/products?asset_type=Crypto&quote_currency=USD. They only depend on
+        // the Global Store, so they are not listed again while its snapshot is current.
+        let api_products_route = warp::path!("api" / "products")
+            .and(warp::query::<BTreeMap<String, String>>())
+            .and(http_cache::conditional())
+            .map(
+                move |attributes: BTreeMap<String, String>, conditional: Conditional| {
+                    let snapshot = global_store_reader4api_products.load();
+                    let etag = Conditional::snapshot_etag(
+                        snapshot.version,
+                        &format!("products{:?}", attributes),
+                    );
+                    if let Some(not_modified) = conditional.not_modified(&etag) {
+                        return not_modified;
+                    }
+                    let products =
+                        adapter::get_product_metadata(&snapshot.account_metadata, &attributes);
+                    match serde_json::to_vec(&products) {
+                        Ok(body) => conditional.reply("application/json", body, Some(etag)),
+                        Err(e) => Self::api_error_reply(e.to_string()),
+                    }
+                },
+            );
+
+        // The canonical snapshot of the Global Store, compared across agents by
+        // the diff-snapshots subcommand
+        let api_snapshot_route = warp::path!("api" / "snapshot")
+            .and(http_cache::conditional())
+            .map(move |conditional: Conditional| {
+                let snapshot = global_store_reader4api_snapshot.load();
+                let etag = Conditional::snapshot_etag(snapshot.version, "snapshot");
+                if let Some(not_modified) = conditional.not_modified(&etag) {
+                    return not_modified;
+                }
+                match CanonicalSnapshot::new(&snapshot)
+                    .and_then(|canonical| serde_json::to_vec(&canonical).map_err(Into::into))
+                {
+                    Ok(body) => conditional.reply("application/json", body, Some(etag)),
+                    Err(e) => Self::api_error_reply(e.to_string()),
+                }
+            });
 
-			Box::new(reply::with_status("Could not gather metrics. See logs for details".to_string(), StatusCode::INTERNAL_SERVER_ERROR))
-		    });
+        let shared_state4api_performance = shared_state.clone();
+        let api_performance_route = warp::path!("api" / "publisher_performance")
+            .and(http_cache::conditional())
+            .and_then(move |conditional: Conditional| {
+                let shared_state = shared_state4api_performance.clone();
+                async move {
+                    let locked_state = shared_state.lock().await;
+                    let response = match locked_state.fetch_publisher_performance().await {
+                        Ok(report) => Self::json_reply(&conditional, &report),
+                        Err(e) => Self::api_error_reply(e.to_string()),
+                    };
+                    Result::<Box<dyn Reply>, Rejection>::Ok(response)
+                }
+            });
 
-		    Result::<Box<dyn Reply>, Rejection>::Ok(response)
+        let shared_state4api_uptime = shared_state.clone();
+        let api_uptime_route = warp::path!("api" / "uptime")
+            .and(warp::query::<UptimeQuery>())
+            .and(http_cache::conditional())
+            .and_then(move |query: UptimeQuery, conditional: Conditional| {
+                let shared_state = shared_state4api_uptime.clone();
+                async move {
+                    let locked_state = shared_state.lock().await;
+                    let window = query.window.unwrap_or(uptime::DEFAULT_WINDOW);
+                    let response = match locked_state.fetch_uptime(window).await {
+                        Ok(report) => Self::json_reply(&conditional, &report),
+                        Err(e) => Self::api_error_reply(e.to_string()),
+                    };
+                    Result::<Box<dyn Reply>, Rejection>::Ok(response)
                 }
             });
 
-        warp::serve(dashboard_route.or(metrics_route))
-            .bind(addr)
-            .await;
+        // The health endpoints only read the component statuses, so they do
+        // not contend with the dashboard for the shared state.
+        let version_route = warp::path!("version").map(|| reply::json(&BuildInfo::current()));
+
+        let live_route = warp::path!("live")
+            .map(|| reply::with_status(reply::json(&Status::Healthy), StatusCode::OK));
+
+        let health4health = health.clone();
+        let health_route =
+            warp::path!("health").map(move || Self::health_reply(health4health.health()));
+
+        let health4ready = health;
+        let ready_route =
+            warp::path!("ready").map(move || Self::health_reply(health4ready.readiness()));
+
+        // The health and version endpoints stay open to probes, the others
+        // require the configured credentials, if any
+        let authenticator = authenticator.unwrap_or_else(Authenticator::open);
+        warp::serve(
+            live_route
+                .or(version_route)
+                .or(health_route)
+                .or(ready_route)
+                .or(authenticator.require(Role::ReadOnly).and(
+                    dashboard_script_route
+                        .or(dashboard_csv_route)
+                        .or(dashboard_route)
+                        .or(api_events_route)
+                        .or(api_dashboard_route)
+                        .or(api_symbol_route)
+                        .or(api_products_route)
+                        .or(api_snapshot_route)
+                        .or(api_performance_route)
+                        .or(api_uptime_route)
+                        .or(metrics_route),
+                ))
+                .recover(http_auth::recover),
+        )
+        .bind(addr)
+        .await;
+    }
+
+    fn health_reply(report: HealthReport) -> reply::WithStatus<reply::Json> {
+        let status_code = match report.status {
+            Status::Healthy => StatusCode::OK,
+            Status::Starting | Status::Unhealthy => StatusCode::SERVICE_UNAVAILABLE,
+        };
+        reply::with_status(reply::json(&report), status_code)
+    }
+
+    fn json_reply<T: Serialize>(conditional: &Conditional, value: &T) -> Box<dyn Reply> {
+        match serde_json::to_vec(value) {
+            Ok(body) => conditional.reply("application/json", body, None),
+            Err(e) => Self::api_error_reply(e.to_string()),
+        }
+    }
+
+    fn api_error_reply(error: String) -> Box<dyn Reply> {
+        error!(%error, "Dashboard API: Building dashboard data failed");
+
+        // Withhold failure details from client
+        Box::new(reply::with_status(
+            "Could not build dashboard data! See the logs for details",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        ))
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct DashboardPriceLabels {
+    symbol: String,
+    pubkey: String,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct DashboardComponentLabels {
+    symbol:    String,
+    pubkey:    String,
+    publisher: String,
+}
+
+/// Per-price metrics mirroring the dashboard state overview. These are
+/// refreshed from the store data on each scrape.
+#[derive(Default)]
+pub struct DashboardMetrics {
+    /// Seconds since the last on-chain publish of the aggregate price
+    last_publish_age: Family<DashboardPriceLabels, Gauge>,
+    /// Seconds since the last local store update of the price
+    local_update_age: Family<DashboardPriceLabels, Gauge>,
+    /// Relative deviation of our publishers' components from the aggregate price
+    deviation:        Family<DashboardComponentLabels, Gauge<f64, AtomicU64>>,
+}
+
+impl DashboardMetrics {
+    pub fn new(registry: &mut Registry) -> Self {
+        let metrics = Self::default();
+
+        #[deny(unused_variables)]
+        let Self {
+            last_publish_age,
+            local_update_age,
+            deviation,
+        } = &metrics;
+
+        registry.register(
+            "dashboard_last_publish_age_seconds",
+            "Seconds since the last on-chain publish of the aggregate price",
+            last_publish_age.clone(),
+        );
+        registry.register(
+            "dashboard_local_update_age_seconds",
+            "Seconds since the last local store update of the price",
+            local_update_age.clone(),
+        );
+        registry.register(
+            "dashboard_component_deviation",
+            "Relative deviation of our publisher's component from the aggregate price",
+            deviation.clone(),
+        );
+
+        metrics
+    }
+
+    /// Replace the gauge values with those of the given dashboard view.
+    /// Prices no longer in the view are dropped.
+    pub fn update(&self, symbol_view: &BTreeMap<String, DashboardSymbolView>, now: i64) {
+        #[deny(unused_variables)]
+        let Self {
+            last_publish_age,
+            local_update_age,
+            deviation,
+        } = self;
+
+        last_publish_age.clear();
+        local_update_age.clear();
+        deviation.clear();
+
+        for (symbol, data) in symbol_view {
+            for (price_key, price_data) in &data.prices {
+                let labels = DashboardPriceLabels {
+                    symbol: symbol.clone(),
+                    pubkey: price_key.to_string(),
+                };
+
+                if let Some(global_data) = &price_data.global_data {
+                    last_publish_age
+                        .get_or_create(&labels)
+                        .set(now - global_data.timestamp);
+                }
+                if let Some(local_data) = &price_data.local_data {
+                    local_update_age
+                        .get_or_create(&labels)
+                        .set(now - local_data.timestamp);
+                }
+                for component in &price_data.components {
+                    if let Some(component_deviation) = component.deviation {
+                        deviation
+                            .get_or_create(&DashboardComponentLabels {
+                                symbol:    symbol.clone(),
+                                pubkey:    price_key.to_string(),
+                                publisher: component.publisher.to_string(),
+                            })
+                            .set(component_deviation);
+                    }
+                }
+            }
+        }
     }
 }
 
@@ -192,6 +755,19 @@ impl ProductGlobalMetrics {
             })
             .inc();
     }
+
+    /// Stop exporting the metrics of a removed product
+    pub fn remove(&self, product_key: &Pubkey, maybe_symbol: Option<String>) {
+        let symbol_string = maybe_symbol.unwrap_or(format!("unknown_{}", product_key.to_string()));
+
+        #[deny(unused_variables)]
+        let Self { update_count } = self;
+
+        update_count.remove(&ProductGlobalLabels {
+            pubkey: product_key.to_string(),
+            symbol: symbol_string,
+        });
+    }
 }
 
 #[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
@@ -199,6 +775,84 @@ pub struct PriceGlobalLabels {
     pubkey: String,
 }
 
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct PriceAggregationLabels {
+    pubkey: String,
+    /// The price account itself if its symbol is not known
+    symbol: String,
+}
+
+/// Aggregation state of each price in the global store, to alert on feeds
+/// losing their publishers or their trading status
+#[derive(Default)]
+pub struct PriceAggregationMetrics {
+    /// Numeric value of the aggregate's status: 0 unknown, 1 trading,
+    /// 2 halted, 3 auction, 4 ignored
+    status:  Family<PriceAggregationLabels, Gauge>,
+    /// Number of components which contributed to the aggregate
+    num_qt:  Family<PriceAggregationLabels, Gauge>,
+    /// Minimum number of contributing components for the aggregate to trade
+    min_pub: Family<PriceAggregationLabels, Gauge>,
+}
+
+impl PriceAggregationMetrics {
+    pub fn new(registry: &mut Registry) -> Self {
+        let metrics = Self::default();
+
+        #[deny(unused_variables)]
+        let Self {
+            status,
+            num_qt,
+            min_pub,
+        } = &metrics;
+
+        registry.register(
+            "global_price_aggregate_status",
+            "The aggregate status of a price: 0 unknown, 1 trading, 2 halted, 3 auction, 4 ignored",
+            status.clone(),
+        );
+        registry.register(
+            "global_price_num_qt",
+            "The number of publishing components which contributed to the aggregate of a price",
+            num_qt.clone(),
+        );
+        registry.register(
+            "global_price_min_pub",
+            "The minimum number of publishing components for the aggregate of a price to trade",
+            min_pub.clone(),
+        );
+
+        metrics
+    }
+
+    pub fn update(&self, price_key: &Pubkey, symbol: &str, price_account: &PriceEntry) {
+        let labels = PriceAggregationLabels {
+            pubkey: price_key.to_string(),
+            symbol: symbol.to_string(),
+        };
+        self.status
+            .get_or_create(&labels)
+            .set(price_account.agg.status as i64);
+        self.num_qt
+            .get_or_create(&labels)
+            .set(price_account.num_qt as i64);
+        self.min_pub
+            .get_or_create(&labels)
+            .set(price_account.min_pub as i64);
+    }
+
+    /// Stop exporting the metrics of a removed price
+    pub fn remove(&self, price_key: &Pubkey, symbol: &str) {
+        let labels = PriceAggregationLabels {
+            pubkey: price_key.to_string(),
+            symbol: symbol.to_string(),
+        };
+        self.status.remove(&labels);
+        self.num_qt.remove(&labels);
+        self.min_pub.remove(&labels);
+    }
+}
+
 /// Price account global store metrics. Most fields correspond with a subset of PriceEntry fields.
 #[derive(Default)]
 pub struct PriceGlobalMetrics {
@@ -347,11 +1001,40 @@ impl PriceGlobalMetrics {
             })
             .inc();
     }
+
+    /// Stop exporting the metrics of a removed price
+    pub fn remove(&self, price_key: &Pubkey) {
+        #[deny(unused_variables)]
+        let Self {
+            price,
+            expo,
+            conf,
+            timestamp,
+            prev_price,
+            prev_conf,
+            prev_timestamp,
+            update_count,
+        } = self;
+
+        let labels = PriceGlobalLabels {
+            pubkey: price_key.to_string(),
+        };
+        price.remove(&labels);
+        expo.remove(&labels);
+        conf.remove(&labels);
+        timestamp.remove(&labels);
+        prev_price.remove(&labels);
+        prev_conf.remove(&labels);
+        prev_timestamp.remove(&labels);
+        update_count.remove(&labels);
+    }
 }
 
 #[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
 pub struct PriceLocalLabels {
-    pubkey: String,
+    pubkey:    String,
+    /// Set to "default" for updates submitted on behalf of the default publish keypair
+    publisher: String,
 }
 
 /// Metrics exposed to Prometheus by the local store for each price
@@ -365,6 +1048,15 @@ pub struct PriceLocalMetrics {
 
     /// How many times this price was updated in the local store
     update_count: Family<PriceLocalLabels, Counter>,
+
+    /// How many updates of this price were rejected by the local store validation
+    rejected_update_count: Family<PriceLocalLabels, Counter>,
+
+    /// How many updates of this price were dropped as duplicates of the last one
+    duplicate_update_count: Family<PriceLocalLabels, Counter>,
+
+    /// How many queued updates of this price were shed under load
+    shed_update_count: Family<PriceLocalLabels, Counter>,
 }
 impl PriceLocalMetrics {
     pub fn new(registry: &mut Registry) -> Self {
@@ -376,6 +1068,9 @@ impl PriceLocalMetrics {
             conf,
             timestamp,
             update_count,
+            rejected_update_count,
+            duplicate_update_count,
+            shed_update_count,
         } = &metrics;
 
         registry.register(
@@ -398,39 +1093,1793 @@ impl PriceLocalMetrics {
             "How many times we've seen an update for this price in the local store",
             update_count.clone(),
         );
+        registry.register(
+            "local_store_rejected_update_count",
+            "How many updates for this price were rejected by the local store validation",
+            rejected_update_count.clone(),
+        );
+        registry.register(
+            "local_store_duplicate_update_count",
+            "How many updates for this price were dropped as duplicates within the dedup window",
+            duplicate_update_count.clone(),
+        );
+        registry.register(
+            "local_store_shed_update_count",
+            "How many queued updates for this price were shed as superseded while the local store was overloaded",
+            shed_update_count.clone(),
+        );
 
         metrics
     }
 
-    pub fn update(&self, price_id: &PriceIdentifier, price_info: &PriceInfo) {
+    pub fn update(
+        &self,
+        publisher: &Publisher,
+        price_id: &PriceIdentifier,
+        price_info: &PriceInfo,
+    ) {
         #[deny(unused_variables)]
         let Self {
             price,
             conf,
             timestamp,
             update_count,
+            rejected_update_count: _,
+            duplicate_update_count: _,
+            shed_update_count: _,
         } = self;
 
+        let labels = Self::labels(publisher, price_id);
+
+        price.get_or_create(&labels).set(price_info.price);
+        conf.get_or_create(&labels).set(price_info.conf as f64);
+        timestamp.get_or_create(&labels).set(price_info.timestamp);
+        update_count.get_or_create(&labels).inc();
+    }
+
+    pub fn reject(&self, publisher: &Publisher, price_id: &PriceIdentifier) {
+        self.rejected_update_count
+            .get_or_create(&Self::labels(publisher, price_id))
+            .inc();
+    }
+
+    pub fn drop_duplicate(&self, publisher: &Publisher, price_id: &PriceIdentifier) {
+        self.duplicate_update_count
+            .get_or_create(&Self::labels(publisher, price_id))
+            .inc();
+    }
+
+    pub fn shed(&self, publisher: &Publisher, price_id: &PriceIdentifier) {
+        self.shed_update_count
+            .get_or_create(&Self::labels(publisher, price_id))
+            .inc();
+    }
+
+    fn labels(publisher: &Publisher, price_id: &PriceIdentifier) -> PriceLocalLabels {
         let price_key = Pubkey::new(price_id.to_bytes().as_slice());
+        PriceLocalLabels {
+            pubkey:    price_key.to_string(),
+            publisher: publisher
+                .map(|key| key.to_string())
+                .unwrap_or_else(|| "default".to_string()),
+        }
+    }
+}
 
-        price
-            .get_or_create(&PriceLocalLabels {
-                pubkey: price_key.to_string(),
-            })
-            .set(price_info.price);
-        conf.get_or_create(&PriceLocalLabels {
-            pubkey: price_key.to_string(),
-        })
-        .set(price_info.conf as f64);
-        timestamp
-            .get_or_create(&PriceLocalLabels {
-                pubkey: price_key.to_string(),
-            })
-            .set(price_info.timestamp);
-        update_count
-            .get_or_create(&PriceLocalLabels {
-                pubkey: price_key.to_string(),
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct PublishLatencyLabels {
+    /// Set to "unknown" for prices whose product has no asset type
+    asset_type: String,
+}
+
+/// End-to-end latency of our price updates, from their arrival in the local
+/// store to their inclusion in the on-chain aggregate
+pub struct PublishLatencyMetrics {
+    latency: Family<PublishLatencyLabels, Histogram>,
+}
+
+impl PublishLatencyMetrics {
+    pub fn new(registry: &mut Registry) -> Self {
+        // Buckets from 100ms to ~51s
+        let metrics = Self {
+            latency: Family::new_with_constructor(|| {
+                Histogram::new(exponential_buckets(0.1, 2.0, 10))
+            }),
+        };
+
+        #[deny(unused_variables)]
+        let Self { latency } = &metrics;
+
+        registry.register(
+            "publish_latency_seconds",
+            "Seconds from a price update arriving in the local store to its inclusion in the on-chain aggregate",
+            latency.clone(),
+        );
+
+        metrics
+    }
+
+    pub fn observe(&self, asset_type: &Option<String>, latency: Duration) {
+        self.latency
+            .get_or_create(&PublishLatencyLabels {
+                asset_type: asset_type.clone().unwrap_or_else(|| "unknown".to_string()),
             })
-            .inc();
+            .observe(latency.as_secs_f64());
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct RpcLabels {
+    network:  String,
+    /// RPC method, e.g. "getMultipleAccounts"
+    method:   String,
+    /// Host of the RPC endpoint
+    endpoint: String,
+}
+
+/// Latency and errors of the RPC requests made by the Oracles and Exporters,
+/// recorded by their instrumented RPC clients
+pub struct RpcMetrics {
+    latency:     Family<RpcLabels, Histogram>,
+    error_count: Family<RpcLabels, Counter>,
+}
+
+impl Default for RpcMetrics {
+    fn default() -> Self {
+        Self {
+            // Buckets from 10ms to ~10s
+            latency:     Family::new_with_constructor(|| {
+                Histogram::new(exponential_buckets(0.01, 2.0, 11))
+            }),
+            error_count: Family::default(),
+        }
+    }
+}
+
+impl RpcMetrics {
+    pub fn register(&self, registry: &mut Registry) {
+        #[deny(unused_variables)]
+        let Self {
+            latency,
+            error_count,
+        } = self;
+
+        registry.register(
+            "rpc_request_latency_seconds",
+            "Latency of RPC requests, including failed ones",
+            latency.clone(),
+        );
+        registry.register(
+            "rpc_request_error_count",
+            "How many RPC requests failed",
+            error_count.clone(),
+        );
+    }
+
+    pub fn observe(
+        &self,
+        network: &str,
+        method: &str,
+        endpoint: &str,
+        latency: Duration,
+        success: bool,
+    ) {
+        let labels = RpcLabels {
+            network:  network.to_string(),
+            method:   method.to_string(),
+            endpoint: endpoint.to_string(),
+        };
+
+        self.latency
+            .get_or_create(&labels)
+            .observe(latency.as_secs_f64());
+        if !success {
+            self.error_count.get_or_create(&labels).inc();
+        }
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct SignerLabels {
+    network:  String,
+    /// Host of the remote signing service
+    endpoint: String,
+}
+
+/// Latency, retries and errors of the signing requests made by the Exporters
+/// to remote signing services
+pub struct SignerMetrics {
+    latency:     Family<SignerLabels, Histogram>,
+    retry_count: Family<SignerLabels, Counter>,
+    error_count: Family<SignerLabels, Counter>,
+}
+
+impl Default for SignerMetrics {
+    fn default() -> Self {
+        Self {
+            // Buckets from 1ms to ~1s
+            latency:     Family::new_with_constructor(|| {
+                Histogram::new(exponential_buckets(0.001, 2.0, 11))
+            }),
+            retry_count: Family::default(),
+            error_count: Family::default(),
+        }
+    }
+}
+
+impl SignerMetrics {
+    pub fn register(&self, registry: &mut Registry) {
+        #[deny(unused_variables)]
+        let Self {
+            latency,
+            retry_count,
+            error_count,
+        } = self;
+
+        registry.register(
+            "signing_latency_seconds",
+            "Latency of signing a transaction with a remote signer, including retries",
+            latency.clone(),
+        );
+        registry.register(
+            "signing_retry_count",
+            "How many signing requests to a remote signer were retried",
+            retry_count.clone(),
+        );
+        registry.register(
+            "signing_error_count",
+            "How many transactions could not be signed by a remote signer",
+            error_count.clone(),
+        );
+    }
+
+    pub fn observe(&self, network: &str, endpoint: &str, latency: Duration, success: bool) {
+        let labels = SignerLabels {
+            network:  network.to_string(),
+            endpoint: endpoint.to_string(),
+        };
+
+        self.latency
+            .get_or_create(&labels)
+            .observe(latency.as_secs_f64());
+        if !success {
+            self.error_count.get_or_create(&labels).inc();
+        }
+    }
+
+    pub fn retried(&self, network: &str, endpoint: &str) {
+        self.retry_count
+            .get_or_create(&SignerLabels {
+                network:  network.to_string(),
+                endpoint: endpoint.to_string(),
+            })
+            .inc();
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct LogLabels {
+    /// Level of the event, e.g. "warn"
+    level: String,
+}
+
+/// Logged events by level, e.g. to alert on a rising rate of errors
+#[derive(Default)]
+pub struct LogMetrics {
+    event_count: Family<LogLabels, Counter>,
+}
+
+impl LogMetrics {
+    pub fn register(&self, registry: &mut Registry) {
+        #[deny(unused_variables)]
+        let Self { event_count } = self;
+
+        registry.register(
+            "log_event_count",
+            "How many events were logged, by level",
+            event_count.clone(),
+        );
+    }
+
+    pub fn record(&self, level: &tracing::Level) {
+        self.event_count
+            .get_or_create(&LogLabels {
+                level: level.as_str().to_lowercase(),
+            })
+            .inc();
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct ErrorLabels {
+    component: String,
+    /// See `error::ErrorKind`
+    kind:      String,
+}
+
+/// Errors of the components, by kind
+#[derive(Default)]
+pub struct ErrorMetrics {
+    error_count: Family<ErrorLabels, Counter>,
+}
+
+impl ErrorMetrics {
+    pub fn register(&self, registry: &mut Registry) {
+        #[deny(unused_variables)]
+        let Self { error_count } = self;
+
+        registry.register(
+            "error_count",
+            "How many errors each component ran into, by kind",
+            error_count.clone(),
+        );
+    }
+
+    pub fn record(&self, component: &str, kind: ErrorKind) {
+        self.error_count
+            .get_or_create(&ErrorLabels {
+                component: component.to_string(),
+                kind:      kind.as_str().to_string(),
+            })
+            .inc();
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct PublishPermissionLabels {
+    network:     String,
+    publish_key: String,
+    symbol:      String,
+}
+
+/// Prices updated in the local store which the publish keys are not
+/// permissioned to publish, as found by the Exporters' permission checks
+#[derive(Default)]
+pub struct PublishPermissionMetrics {
+    /// Whether the publish key lacks permission to publish the price. Prices
+    /// it was granted permission for are set to 0.
+    missing: Family<PublishPermissionLabels, Gauge>,
+}
+
+impl PublishPermissionMetrics {
+    pub fn register(&self, registry: &mut Registry) {
+        #[deny(unused_variables)]
+        let Self { missing } = self;
+
+        registry.register(
+            "publish_permission_missing",
+            "Whether the publish key lacks permission to publish the price it has updates for",
+            missing.clone(),
+        );
+    }
+
+    pub fn record(&self, network: &str, publish_key: &Pubkey, symbol: &str, missing: bool) {
+        self.missing
+            .get_or_create(&PublishPermissionLabels {
+                network:     network.to_string(),
+                publish_key: publish_key.to_string(),
+                symbol:      symbol.to_string(),
+            })
+            .set(missing as i64);
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct PublishRetryLabels {
+    network: String,
+}
+
+/// Retries of the batches whose transactions did not land in time, with an
+/// escalated compute unit price
+pub struct PublishRetryMetrics {
+    /// Number of attempts it took to land each update
+    attempts_per_landed_update: Family<PublishRetryLabels, Histogram>,
+    /// Number of batches retried
+    retry_count:                Family<PublishRetryLabels, Counter>,
+    /// Number of batches given up on after their last attempt
+    abandoned_count:            Family<PublishRetryLabels, Counter>,
+}
+
+impl Default for PublishRetryMetrics {
+    fn default() -> Self {
+        Self {
+            attempts_per_landed_update: Family::new_with_constructor(|| {
+                Histogram::new(linear_buckets(1.0, 1.0, 10))
+            }),
+            retry_count:                Default::default(),
+            abandoned_count:            Default::default(),
+        }
+    }
+}
+
+impl PublishRetryMetrics {
+    pub fn register(&self, registry: &mut Registry) {
+        #[deny(unused_variables)]
+        let Self {
+            attempts_per_landed_update,
+            retry_count,
+            abandoned_count,
+        } = self;
+
+        registry.register(
+            "attempts_per_landed_update",
+            "Number of attempts it took to land each published update",
+            attempts_per_landed_update.clone(),
+        );
+        registry.register(
+            "publish_retry_count",
+            "Number of batches published again after their transaction did not land",
+            retry_count.clone(),
+        );
+        registry.register(
+            "publish_abandoned_count",
+            "Number of batches given up on after their last attempt did not land",
+            abandoned_count.clone(),
+        );
+    }
+
+    /// Record the updates of a batch which landed on the given attempt
+    pub fn landed(&self, network: &str, attempt: u32, updates: usize) {
+        let histogram = self
+            .attempts_per_landed_update
+            .get_or_create(&PublishRetryLabels {
+                network: network.to_string(),
+            });
+        for _ in 0..updates {
+            histogram.observe(attempt as f64);
+        }
+    }
+
+    pub fn retried(&self, network: &str) {
+        self.retry_count
+            .get_or_create(&PublishRetryLabels {
+                network: network.to_string(),
+            })
+            .inc();
+    }
+
+    pub fn abandoned(&self, network: &str) {
+        self.abandoned_count
+            .get_or_create(&PublishRetryLabels {
+                network: network.to_string(),
+            })
+            .inc();
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct DryRunLabels {
+    network: String,
+}
+
+/// Transactions the Exporters built and signed but did not send, in dry run
+#[derive(Default)]
+pub struct DryRunMetrics {
+    /// Number of transactions not sent
+    transaction_count: Family<DryRunLabels, Counter>,
+    /// Number of price updates in the transactions not sent
+    update_count:      Family<DryRunLabels, Counter>,
+}
+
+impl DryRunMetrics {
+    pub fn register(&self, registry: &mut Registry) {
+        #[deny(unused_variables)]
+        let Self {
+            transaction_count,
+            update_count,
+        } = self;
+
+        registry.register(
+            "dry_run_transaction_count",
+            "Number of transactions built and signed but not sent, in dry run",
+            transaction_count.clone(),
+        );
+        registry.register(
+            "dry_run_update_count",
+            "Number of price updates in the transactions not sent, in dry run",
+            update_count.clone(),
+        );
+    }
+
+    pub fn record(&self, network: &str, updates: usize) {
+        let labels = DryRunLabels {
+            network: network.to_string(),
+        };
+        self.transaction_count.get_or_create(&labels).inc();
+        self.update_count
+            .get_or_create(&labels)
+            .inc_by(updates as u64);
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct AccountCheckLabels {
+    network:  String,
+    /// Host of the RPC endpoint
+    endpoint: String,
+    /// "owner", "magic", "version", "account_type" or "size"
+    reason:   String,
+}
+
+/// Polled accounts which were not the Oracle program accounts expected, each of
+/// which quarantined the RPC endpoint serving it
+#[derive(Default)]
+pub struct AccountCheckMetrics {
+    mismatch_count: Family<AccountCheckLabels, Counter>,
+}
+
+impl AccountCheckMetrics {
+    pub fn register(&self, registry: &mut Registry) {
+        #[deny(unused_variables)]
+        let Self { mismatch_count } = self;
+
+        registry.register(
+            "account_check_mismatch_count",
+            "Number of polls whose accounts were not the oracle program accounts expected, quarantining the RPC endpoint",
+            mismatch_count.clone(),
+        );
+    }
+
+    pub fn record(&self, network: &str, endpoint: &str, reason: &str) {
+        self.mismatch_count
+            .get_or_create(&AccountCheckLabels {
+                network:  network.to_string(),
+                endpoint: endpoint.to_string(),
+                reason:   reason.to_string(),
+            })
+            .inc();
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct DuplicateUpdateLabels {
+    network: String,
+    /// "poll" or "subscription", whichever delivered the duplicate
+    source:  String,
+}
+
+/// Price account updates dropped by the Oracle, as the polls and the
+/// subscription both delivered them
+#[derive(Default)]
+pub struct DuplicateUpdateMetrics {
+    duplicate_count: Family<DuplicateUpdateLabels, Counter>,
+}
+
+impl DuplicateUpdateMetrics {
+    pub fn register(&self, registry: &mut Registry) {
+        #[deny(unused_variables)]
+        let Self { duplicate_count } = self;
+
+        registry.register(
+            "oracle_duplicate_update_count",
+            "Number of price account updates dropped by the oracle, as they were already handed over at the same slot with the same data",
+            duplicate_count.clone(),
+        );
+    }
+
+    pub fn record(&self, network: &str, source: &str) {
+        self.duplicate_count
+            .get_or_create(&DuplicateUpdateLabels {
+                network: network.to_string(),
+                source:  source.to_string(),
+            })
+            .inc();
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct GlobalStoreBackpressureLabels {
+    network: String,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct GlobalStoreDroppedUpdateLabels {
+    network: String,
+    /// "price_account", "product_account" or "listed_accounts"
+    update:  String,
+}
+
+/// Updates the Oracle held back or dropped as the Global Store did not accept
+/// them within the send timeout
+#[derive(Default)]
+pub struct GlobalStoreBackpressureMetrics {
+    held_updates:  Family<GlobalStoreBackpressureLabels, Gauge>,
+    dropped_count: Family<GlobalStoreDroppedUpdateLabels, Counter>,
+}
+
+impl GlobalStoreBackpressureMetrics {
+    pub fn register(&self, registry: &mut Registry) {
+        #[deny(unused_variables)]
+        let Self {
+            held_updates,
+            dropped_count,
+        } = self;
+
+        registry.register(
+            "oracle_global_store_held_updates",
+            "Number of price account updates held back by the oracle until the global store accepts them",
+            held_updates.clone(),
+        );
+        registry.register(
+            "oracle_global_store_dropped_update_count",
+            "Number of updates dropped by the oracle, as the global store did not accept them within the send timeout",
+            dropped_count.clone(),
+        );
+    }
+
+    pub fn set_held_updates(&self, network: &str, held_updates: usize) {
+        self.held_updates
+            .get_or_create(&GlobalStoreBackpressureLabels {
+                network: network.to_string(),
+            })
+            .set(held_updates as i64);
+    }
+
+    pub fn record_dropped(&self, network: &str, update: &str) {
+        self.dropped_count
+            .get_or_create(&GlobalStoreDroppedUpdateLabels {
+                network: network.to_string(),
+                update:  update.to_string(),
+            })
+            .inc();
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct ConfFloorLabels {
+    network: String,
+    symbol:  String,
+    /// "clamped" or "rejected"
+    action:  String,
+}
+
+/// Price updates whose confidence interval was below the floor of their symbol
+#[derive(Default)]
+pub struct ConfFloorMetrics {
+    update_count: Family<ConfFloorLabels, Counter>,
+}
+
+impl ConfFloorMetrics {
+    pub fn register(&self, registry: &mut Registry) {
+        #[deny(unused_variables)]
+        let Self { update_count } = self;
+
+        registry.register(
+            "conf_floor_update_count",
+            "Number of price updates whose confidence interval was raised to the floor of their symbol, or which were not published for being below it",
+            update_count.clone(),
+        );
+    }
+
+    pub fn record(&self, network: &str, symbol: &str, action: &str) {
+        self.update_count
+            .get_or_create(&ConfFloorLabels {
+                network: network.to_string(),
+                symbol:  symbol.to_string(),
+                action:  action.to_string(),
+            })
+            .inc();
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct AccumulatorLabels {
+    network: String,
+}
+
+/// Whether the updates of our publishers make it into the accumulator, on
+/// the networks where it is tracked
+pub struct AccumulatorMetrics {
+    /// Slots between the publish slot of each delivered update and the
+    /// rewrite of its message buffer
+    delivery_slots:  Family<AccumulatorLabels, Histogram>,
+    /// Number of updates whose message buffer was rewritten in time
+    delivered_count: Family<AccumulatorLabels, Counter>,
+    /// Number of updates whose message buffer was not rewritten in time
+    missed_count:    Family<AccumulatorLabels, Counter>,
+}
+
+impl Default for AccumulatorMetrics {
+    fn default() -> Self {
+        Self {
+            delivery_slots:  Family::new_with_constructor(|| {
+                Histogram::new(linear_buckets(0.0, 1.0, 20))
+            }),
+            delivered_count: Default::default(),
+            missed_count:    Default::default(),
+        }
+    }
+}
+
+impl AccumulatorMetrics {
+    pub fn register(&self, registry: &mut Registry) {
+        #[deny(unused_variables)]
+        let Self {
+            delivery_slots,
+            delivered_count,
+            missed_count,
+        } = self;
+
+        registry.register(
+            "accumulator_delivery_slots",
+            "Slots between the publish slot of our updates and the rewrite of their accumulator message buffer",
+            delivery_slots.clone(),
+        );
+        registry.register(
+            "accumulator_delivered_update_count",
+            "Number of our updates whose accumulator message buffer was rewritten within the max delay",
+            delivered_count.clone(),
+        );
+        registry.register(
+            "accumulator_missed_update_count",
+            "Number of our updates whose accumulator message buffer was not rewritten within the max delay",
+            missed_count.clone(),
+        );
+    }
+
+    /// Record the delays of the delivered updates, and the missed updates
+    pub fn record(&self, network: &str, delivered_delays: &[u64], missed: usize) {
+        let labels = AccumulatorLabels {
+            network: network.to_string(),
+        };
+        let histogram = self.delivery_slots.get_or_create(&labels);
+        for delay in delivered_delays {
+            histogram.observe(*delay as f64);
+        }
+        self.delivered_count
+            .get_or_create(&labels)
+            .inc_by(delivered_delays.len() as u64);
+        self.missed_count
+            .get_or_create(&labels)
+            .inc_by(missed as u64);
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct TenantLabels {
+    tenant: String,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct TenantUpdateLabels {
+    tenant: String,
+    /// "accepted", or why the update was rejected
+    result: String,
+}
+
+/// Activity of each tenant on the pythd API
+#[derive(Default)]
+pub struct TenantMetrics {
+    /// Number of open connections authenticated as the tenant
+    connections:  Family<TenantLabels, Gauge>,
+    /// Number of updates submitted by the tenant, by result
+    update_count: Family<TenantUpdateLabels, Counter>,
+}
+
+impl TenantMetrics {
+    pub fn register(&self, registry: &mut Registry) {
+        #[deny(unused_variables)]
+        let Self {
+            connections,
+            update_count,
+        } = self;
+
+        registry.register(
+            "tenant_connections",
+            "Number of open pythd API connections authenticated as the tenant",
+            connections.clone(),
+        );
+        registry.register(
+            "tenant_update_count",
+            "Number of price updates submitted by the tenant, accepted or by reason of rejection",
+            update_count.clone(),
+        );
+    }
+
+    pub fn connected(&self, tenant: &str) {
+        self.connections
+            .get_or_create(&TenantLabels {
+                tenant: tenant.to_string(),
+            })
+            .inc();
+    }
+
+    pub fn disconnected(&self, tenant: &str) {
+        self.connections
+            .get_or_create(&TenantLabels {
+                tenant: tenant.to_string(),
+            })
+            .dec();
+    }
+
+    pub fn record_update(&self, tenant: &str, rejected: Option<&str>) {
+        self.update_count
+            .get_or_create(&TenantUpdateLabels {
+                tenant: tenant.to_string(),
+                result: rejected.unwrap_or("accepted").to_string(),
+            })
+            .inc();
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct ApiLabels {
+    /// JSON-RPC method, e.g. "update_price"
+    method: String,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct ApiRejectionLabels {
+    /// "not_allowed" or "connection_limit"
+    reason: String,
+}
+
+/// Requests handled and notifications sent by the connections of the pythd
+/// API, by method, and the connections admitted or rejected by the listener
+pub struct ApiMetrics {
+    request_count:             Family<ApiLabels, Counter>,
+    error_count:               Family<ApiLabels, Counter>,
+    latency:                   Family<ApiLabels, Histogram>,
+    notification_count:        Family<ApiLabels, Counter>,
+    open_connections:          Gauge,
+    rejected_connection_count: Family<ApiRejectionLabels, Counter>,
+    /// Bytes of the messages sent over compressed connections, before compression
+    compression_input_bytes:   Counter,
+    /// Bytes saved by compressing them, negative while compression grew them
+    compression_saved_bytes:   Gauge,
+}
+
+impl Default for ApiMetrics {
+    fn default() -> Self {
+        Self {
+            request_count:             Family::default(),
+            error_count:               Family::default(),
+            // Buckets from 0.1ms to ~100ms
+            latency:                   Family::new_with_constructor(|| {
+                Histogram::new(exponential_buckets(0.0001, 2.0, 11))
+            }),
+            notification_count:        Family::default(),
+            open_connections:          Gauge::default(),
+            rejected_connection_count: Family::default(),
+            compression_input_bytes:   Counter::default(),
+            compression_saved_bytes:   Gauge::default(),
+        }
+    }
+}
+
+impl ApiMetrics {
+    pub fn register(&self, registry: &mut Registry) {
+        #[deny(unused_variables)]
+        let Self {
+            request_count,
+            error_count,
+            latency,
+            notification_count,
+            open_connections,
+            rejected_connection_count,
+            compression_input_bytes,
+            compression_saved_bytes,
+        } = self;
+
+        registry.register(
+            "api_request_count",
+            "Number of pythd API requests handled, including failed ones",
+            request_count.clone(),
+        );
+        registry.register(
+            "api_request_error_count",
+            "Number of pythd API requests which failed",
+            error_count.clone(),
+        );
+        registry.register(
+            "api_request_latency_seconds",
+            "Time taken to handle pythd API requests, including failed ones",
+            latency.clone(),
+        );
+        registry.register(
+            "api_notification_count",
+            "Number of pythd API notifications sent",
+            notification_count.clone(),
+        );
+        registry.register(
+            "api_open_connections",
+            "Number of open websocket connections admitted by the pythd API listener",
+            open_connections.clone(),
+        );
+        registry.register(
+            "api_rejected_connection_count",
+            "Number of connections rejected by the pythd API listener, by reason",
+            rejected_connection_count.clone(),
+        );
+        registry.register(
+            "api_compression_input_bytes",
+            "Bytes of the pythd API messages sent with permessage-deflate, before compression",
+            compression_input_bytes.clone(),
+        );
+        registry.register(
+            "api_compression_saved_bytes",
+            "Bytes saved by compressing the pythd API messages sent with permessage-deflate",
+            compression_saved_bytes.clone(),
+        );
+    }
+
+    pub fn observe_request(&self, method: &str, latency: Duration, success: bool) {
+        let labels = ApiLabels {
+            method: method.to_string(),
+        };
+
+        self.request_count.get_or_create(&labels).inc();
+        self.latency
+            .get_or_create(&labels)
+            .observe(latency.as_secs_f64());
+        if !success {
+            self.error_count.get_or_create(&labels).inc();
+        }
+    }
+
+    pub fn record_notification(&self, method: &str) {
+        self.notification_count
+            .get_or_create(&ApiLabels {
+                method: method.to_string(),
+            })
+            .inc();
+    }
+
+    pub fn connection_opened(&self) {
+        self.open_connections.inc();
+    }
+
+    pub fn connection_closed(&self) {
+        self.open_connections.dec();
+    }
+
+    pub fn record_compression(&self, input_len: usize, output_len: usize) {
+        self.compression_input_bytes.inc_by(input_len as u64);
+        self.compression_saved_bytes
+            .inc_by(input_len as i64 - output_len as i64);
+    }
+
+    pub fn record_rejected_connection(&self, reason: &str) {
+        self.rejected_connection_count
+            .get_or_create(&ApiRejectionLabels {
+                reason: reason.to_string(),
+            })
+            .inc();
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct KeypairLoaderLabels {
+    network: String,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct KeypairLoadLabels {
+    network: String,
+    /// "accepted" or "rejected"
+    result:  String,
+}
+
+/// Load status of the remote keypair loader
+#[derive(Default)]
+pub struct KeypairLoaderMetrics {
+    /// Whether a keypair is currently loaded, per network
+    loaded:     Family<KeypairLoaderLabels, Gauge>,
+    /// How many keypairs were uploaded, by validation result
+    load_count: Family<KeypairLoadLabels, Counter>,
+}
+
+impl KeypairLoaderMetrics {
+    pub fn new(registry: &mut Registry) -> Self {
+        let metrics = Self::default();
+
+        #[deny(unused_variables)]
+        let Self { loaded, load_count } = &metrics;
+
+        registry.register(
+            "remote_keypair_loaded",
+            "Whether a keypair was loaded through the remote keypair loader",
+            loaded.clone(),
+        );
+        registry.register(
+            "remote_keypair_load_count",
+            "How many keypairs were uploaded to the remote keypair loader, by validation result",
+            load_count.clone(),
+        );
+
+        metrics
+    }
+
+    pub fn set_loaded(&self, network: &str, loaded: bool) {
+        self.loaded
+            .get_or_create(&KeypairLoaderLabels {
+                network: network.to_string(),
+            })
+            .set(loaded as i64);
+    }
+
+    pub fn load_attempted(&self, network: &str, accepted: bool) {
+        self.load_count
+            .get_or_create(&KeypairLoadLabels {
+                network: network.to_string(),
+                result:  if accepted { "accepted" } else { "rejected" }.to_string(),
+            })
+            .inc();
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct ChannelLabels {
+    channel: String,
+}
+
+/// Depth and backpressure of the internal channels, recorded by the channel monitor
+#[derive(Default, Clone)]
+pub struct ChannelMetrics {
+    /// Number of messages waiting in the channel
+    depth:              Family<ChannelLabels, Gauge>,
+    /// Maximum number of messages the channel can hold
+    capacity:           Family<ChannelLabels, Gauge>,
+    /// How many sends waited longer than the send timeout for room in the channel
+    send_timeout_count: Family<ChannelLabels, Counter>,
+}
+
+impl ChannelMetrics {
+    pub fn new(registry: &mut Registry) -> Self {
+        let metrics = Self::default();
+
+        #[deny(unused_variables)]
+        let Self {
+            depth,
+            capacity,
+            send_timeout_count,
+        } = &metrics;
+
+        registry.register(
+            "channel_depth",
+            "Number of messages waiting in the channel",
+            depth.clone(),
+        );
+        registry.register(
+            "channel_capacity",
+            "Maximum number of messages the channel can hold",
+            capacity.clone(),
+        );
+        registry.register(
+            "channel_send_timeout_count",
+            "How many sends waited longer than the send timeout for room in the channel",
+            send_timeout_count.clone(),
+        );
+
+        metrics
+    }
+
+    pub fn set_depth(&self, channel: &str, depth: usize) {
+        self.depth
+            .get_or_create(&Self::labels(channel))
+            .set(depth as i64);
+    }
+
+    pub fn set_capacity(&self, channel: &str, capacity: usize) {
+        self.capacity
+            .get_or_create(&Self::labels(channel))
+            .set(capacity as i64);
+    }
+
+    pub fn send_timeout(&self, channel: &str) {
+        self.send_timeout_count
+            .get_or_create(&Self::labels(channel))
+            .inc();
+    }
+
+    fn labels(channel: &str) -> ChannelLabels {
+        ChannelLabels {
+            channel: channel.to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct PublishPauseLabels {
+    symbol: String,
+}
+
+/// Publishing paused through the Admin API
+#[derive(Default)]
+pub struct PublishPauseMetrics {
+    /// Whether all publishing is paused
+    paused_global: Gauge,
+    /// Whether publishing is paused, by symbol. Resumed symbols are set to 0.
+    paused_symbol: Family<PublishPauseLabels, Gauge>,
+}
+
+impl PublishPauseMetrics {
+    pub fn new(registry: &mut Registry) -> Self {
+        let metrics = Self::default();
+
+        #[deny(unused_variables)]
+        let Self {
+            paused_global,
+            paused_symbol,
+        } = &metrics;
+
+        registry.register(
+            "publish_paused",
+            "Whether all publishing is paused",
+            paused_global.clone(),
+        );
+        registry.register(
+            "publish_paused_symbol",
+            "Whether publishing is paused for the symbol",
+            paused_symbol.clone(),
+        );
+
+        metrics
+    }
+
+    /// Record the pause state, after the given symbols were paused or resumed
+    pub fn record(&self, state: &PauseState, symbols: &[String]) {
+        self.paused_global.set(state.global as i64);
+        for symbol in symbols {
+            self.paused_symbol
+                .get_or_create(&PublishPauseLabels {
+                    symbol: symbol.clone(),
+                })
+                .set(state.symbols.contains(symbol) as i64);
+        }
+    }
+}
+
+/// Role of this agent in a high-availability pair
+#[derive(Default)]
+pub struct HighAvailabilityMetrics {
+    /// Whether this agent is the leader
+    leader:           Gauge,
+    /// Number of times this agent took over as leader or stood by
+    transition_count: Counter,
+}
+
+impl HighAvailabilityMetrics {
+    pub fn new(registry: &mut Registry) -> Self {
+        let metrics = Self::default();
+
+        #[deny(unused_variables)]
+        let Self {
+            leader,
+            transition_count,
+        } = &metrics;
+
+        registry.register(
+            "high_availability_leader",
+            "Whether this agent is the leader of its high-availability pair, and publishes",
+            leader.clone(),
+        );
+        registry.register(
+            "high_availability_transition_count",
+            "Number of times this agent took over as leader or stood by",
+            transition_count.clone(),
+        );
+
+        metrics
+    }
+
+    pub fn record_transition(&self, leader: bool) {
+        self.leader.set(leader as i64);
+        self.transition_count.inc();
+    }
+}
+
+/// The replication stream between the agents of a high-availability pair
+#[derive(Default)]
+pub struct ReplicationMetrics {
+    /// Number of followers of this agent's stream
+    followers:      Gauge,
+    /// Whether this agent follows the stream of its peer
+    following:      Gauge,
+    /// Number of updates of the peer applied to the Local Store
+    applied_count:  Counter,
+    /// Number of gaps detected in the stream of the peer
+    gap_count:      Counter,
+    /// Number of updates of the peer missed in the gaps
+    missed_count:   Counter,
+    /// Number of followers rejected, from outside of the allowed networks or
+    /// without the auth token
+    rejected_count: Counter,
+}
+
+impl ReplicationMetrics {
+    pub fn new(registry: &mut Registry) -> Self {
+        let metrics = Self::default();
+
+        #[deny(unused_variables)]
+        let Self {
+            followers,
+            following,
+            applied_count,
+            gap_count,
+            missed_count,
+            rejected_count,
+        } = &metrics;
+
+        registry.register(
+            "replication_followers",
+            "Number of agents following the replication stream of this agent",
+            followers.clone(),
+        );
+        registry.register(
+            "replication_following",
+            "Whether this agent follows the replication stream of its peer",
+            following.clone(),
+        );
+        registry.register(
+            "replication_applied_update_count",
+            "Number of updates of the peer applied to the local store",
+            applied_count.clone(),
+        );
+        registry.register(
+            "replication_gap_count",
+            "Number of gaps detected in the replication stream of the peer",
+            gap_count.clone(),
+        );
+        registry.register(
+            "replication_missed_update_count",
+            "Number of updates of the peer missed in the gaps of its replication stream",
+            missed_count.clone(),
+        );
+        registry.register(
+            "replication_rejected_follower_count",
+            "Number of followers rejected, from outside of the allowed networks or without the auth token",
+            rejected_count.clone(),
+        );
+
+        metrics
+    }
+
+    pub fn follower_connected(&self) {
+        self.followers.inc();
+    }
+
+    pub fn follower_disconnected(&self) {
+        self.followers.dec();
+    }
+
+    pub fn set_following(&self, following: bool) {
+        self.following.set(following as i64);
+    }
+
+    pub fn record_applied(&self) {
+        self.applied_count.inc();
+    }
+
+    pub fn record_gap(&self, missed: u64) {
+        self.gap_count.inc();
+        self.missed_count.inc_by(missed);
+    }
+
+    pub fn record_rejected_follower(&self) {
+        self.rejected_count.inc();
+    }
+}
+
+/// Cross-checks of the local store updates against the reference prices
+#[derive(Default)]
+pub struct ReferencePriceMetrics {
+    /// Relative deviation of the latest checked update from the reference price
+    deviation:     Family<PriceLocalLabels, Gauge<f64, AtomicU64>>,
+    /// How many updates deviated more than the flag threshold
+    flagged_count: Family<PriceLocalLabels, Counter>,
+    /// How many updates deviated more than the block threshold, and were rejected
+    blocked_count: Family<PriceLocalLabels, Counter>,
+}
+
+impl ReferencePriceMetrics {
+    pub fn new(registry: &mut Registry) -> Self {
+        let metrics = Self::default();
+
+        #[deny(unused_variables)]
+        let Self {
+            deviation,
+            flagged_count,
+            blocked_count,
+        } = &metrics;
+
+        registry.register(
+            "reference_price_deviation",
+            "Relative deviation of the latest local store update from the reference price",
+            deviation.clone(),
+        );
+        registry.register(
+            "reference_price_flagged_update_count",
+            "How many local store updates deviated from the reference price beyond the flag threshold",
+            flagged_count.clone(),
+        );
+        registry.register(
+            "reference_price_blocked_update_count",
+            "How many local store updates were rejected for deviating from the reference price",
+            blocked_count.clone(),
+        );
+
+        metrics
+    }
+
+    pub fn check(&self, publisher: &Publisher, price_id: &PriceIdentifier, deviation: f64) {
+        self.deviation
+            .get_or_create(&PriceLocalMetrics::labels(publisher, price_id))
+            .set(deviation);
+    }
+
+    pub fn flag(&self, publisher: &Publisher, price_id: &PriceIdentifier) {
+        self.flagged_count
+            .get_or_create(&PriceLocalMetrics::labels(publisher, price_id))
+            .inc();
+    }
+
+    pub fn block(&self, publisher: &Publisher, price_id: &PriceIdentifier) {
+        self.blocked_count
+            .get_or_create(&PriceLocalMetrics::labels(publisher, price_id))
+            .inc();
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct PublisherPerformanceLabels {
+    /// Set to "unknown_<pubkey>" if not found in the attribute set
+    symbol:    String,
+    pubkey:    String,
+    publisher: String,
+    window:    String,
+}
+
+/// Statistics of our publishers' components over rolling windows. Refreshed
+/// by the Publisher Performance Tracker.
+#[derive(Default)]
+pub struct PublisherPerformanceMetrics {
+    /// Fraction of the aggregates for which the component was quoting
+    uptime:            Family<PublisherPerformanceLabels, Gauge<f64, AtomicU64>>,
+    /// Fraction of the aggregates computed from the slot the component was published in
+    slot_hit_rate:     Family<PublisherPerformanceLabels, Gauge<f64, AtomicU64>>,
+    /// Fraction of the aggregates the component contributed to
+    inclusion_rate:    Family<PublisherPerformanceLabels, Gauge<f64, AtomicU64>>,
+    /// Mean relative deviation of the contributed prices from the aggregate price
+    average_deviation: Family<PublisherPerformanceLabels, Gauge<f64, AtomicU64>>,
+}
+
+impl PublisherPerformanceMetrics {
+    pub fn new(registry: &mut Registry) -> Self {
+        let metrics = Self::default();
+
+        #[deny(unused_variables)]
+        let Self {
+            uptime,
+            slot_hit_rate,
+            inclusion_rate,
+            average_deviation,
+        } = &metrics;
+
+        registry.register(
+            "publisher_uptime_ratio",
+            "Fraction of the aggregates for which our publisher's component was quoting",
+            uptime.clone(),
+        );
+        registry.register(
+            "publisher_slot_hit_rate",
+            "Fraction of the aggregates computed from the slot our publisher's component was published in",
+            slot_hit_rate.clone(),
+        );
+        registry.register(
+            "publisher_aggregate_inclusion_rate",
+            "Fraction of the aggregates our publisher's component contributed to",
+            inclusion_rate.clone(),
+        );
+        registry.register(
+            "publisher_average_deviation",
+            "Mean relative deviation of our publisher's contributed prices from the aggregate price",
+            average_deviation.clone(),
+        );
+
+        metrics
+    }
+
+    /// Replace the gauge values with those of the given report. Windows
+    /// without samples are dropped.
+    pub fn update(&self, report: &[PublisherPerformance]) {
+        #[deny(unused_variables)]
+        let Self {
+            uptime,
+            slot_hit_rate,
+            inclusion_rate,
+            average_deviation,
+        } = self;
+
+        uptime.clear();
+        slot_hit_rate.clear();
+        inclusion_rate.clear();
+        average_deviation.clear();
+
+        for performance in report {
+            for stats in &performance.windows {
+                let labels = PublisherPerformanceLabels {
+                    symbol:    performance
+                        .symbol
+                        .clone()
+                        .unwrap_or_else(|| format!("unknown_{}", performance.price_account)),
+                    pubkey:    performance.price_account.clone(),
+                    publisher: performance.publisher.clone(),
+                    window:    stats.window.clone(),
+                };
+                for (family, value) in [
+                    (uptime, stats.uptime),
+                    (slot_hit_rate, stats.slot_hit_rate),
+                    (inclusion_rate, stats.inclusion_rate),
+                    (average_deviation, stats.average_deviation),
+                ] {
+                    if let Some(value) = value {
+                        family.get_or_create(&labels).set(value);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct ComponentMonitorLabels {
+    /// The price account itself if its symbol is not known
+    symbol:    String,
+    pubkey:    String,
+    publisher: String,
+}
+
+/// Misses of our components in the price accounts updated by our transactions,
+/// counted by the Component Monitor
+#[derive(Default)]
+pub struct ComponentMonitorMetrics {
+    /// Consecutive checks the component missed
+    miss_streak: Family<ComponentMonitorLabels, Gauge>,
+    miss_count:  Family<ComponentMonitorLabels, Counter>,
+}
+
+impl ComponentMonitorMetrics {
+    pub fn new(registry: &mut Registry) -> Self {
+        let metrics = Self::default();
+
+        #[deny(unused_variables)]
+        let Self {
+            miss_streak,
+            miss_count,
+        } = &metrics;
+
+        registry.register(
+            "component_miss_streak",
+            "Consecutive settled transactions after which our component was missing from the price account or lagging behind it",
+            miss_streak.clone(),
+        );
+        registry.register(
+            "component_miss_count",
+            "Settled transactions after which our component was missing from the price account or lagging behind it",
+            miss_count.clone(),
+        );
+
+        metrics
+    }
+
+    pub fn record(
+        &self,
+        symbol: &str,
+        price_key: &Pubkey,
+        publisher: &Pubkey,
+        missed: bool,
+        streak: u64,
+    ) {
+        let labels = ComponentMonitorLabels {
+            symbol:    symbol.to_string(),
+            pubkey:    price_key.to_string(),
+            publisher: publisher.to_string(),
+        };
+        if missed {
+            self.miss_count.get_or_create(&labels).inc();
+        }
+        self.miss_streak.get_or_create(&labels).set(streak as i64);
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct AnomalyLabels {
+    /// The price account itself if its symbol is not known
+    symbol: String,
+    pubkey: String,
+    /// "jump", "conf_blowout" or "flatline"
+    kind:   String,
+}
+
+/// Anomalies of the price feeds flagged by the Anomaly Detector
+#[derive(Default)]
+pub struct AnomalyDetectorMetrics {
+    /// 1 while the anomaly is flagged
+    flagged:       Family<AnomalyLabels, Gauge>,
+    /// Number of times the anomaly started being flagged
+    flagged_count: Family<AnomalyLabels, Counter>,
+}
+
+impl AnomalyDetectorMetrics {
+    pub fn new(registry: &mut Registry) -> Self {
+        let metrics = Self::default();
+
+        #[deny(unused_variables)]
+        let Self {
+            flagged,
+            flagged_count,
+        } = &metrics;
+
+        registry.register(
+            "price_anomaly_flagged",
+            "Whether an anomaly of the kind is flagged on the price by the anomaly detector",
+            flagged.clone(),
+        );
+        registry.register(
+            "price_anomaly_count",
+            "Number of times an anomaly of the kind started being flagged on the price",
+            flagged_count.clone(),
+        );
+
+        metrics
+    }
+
+    pub fn flagged(&self, symbol: &str, price_key: &Pubkey, kind: &str) {
+        let labels = AnomalyLabels {
+            symbol: symbol.to_string(),
+            pubkey: price_key.to_string(),
+            kind:   kind.to_string(),
+        };
+        self.flagged.get_or_create(&labels).set(1);
+        self.flagged_count.get_or_create(&labels).inc();
+    }
+
+    pub fn unflagged(&self, symbol: &str, price_key: &Pubkey, kind: &str) {
+        self.flagged.remove(&AnomalyLabels {
+            symbol: symbol.to_string(),
+            pubkey: price_key.to_string(),
+            kind:   kind.to_string(),
+        });
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct KafkaLabels {
+    topic: String,
+}
+
+/// Deliveries of the Kafka sink
+#[derive(Default)]
+pub struct KafkaMetrics {
+    /// How many messages were acknowledged by the cluster
+    delivered_count: Family<KafkaLabels, Counter>,
+    /// How many messages failed to be delivered
+    failed_count:    Family<KafkaLabels, Counter>,
+    /// How many messages were dropped without being sent, as the producer
+    /// queue was full or they could not be encoded
+    dropped_count:   Family<KafkaLabels, Counter>,
+}
+
+impl KafkaMetrics {
+    pub fn new(registry: &mut Registry) -> Self {
+        let metrics = Self::default();
+
+        #[deny(unused_variables)]
+        let Self {
+            delivered_count,
+            failed_count,
+            dropped_count,
+        } = &metrics;
+
+        registry.register(
+            "kafka_delivered_message_count",
+            "How many messages of the Kafka sink were acknowledged by the cluster",
+            delivered_count.clone(),
+        );
+        registry.register(
+            "kafka_failed_message_count",
+            "How many messages of the Kafka sink failed to be delivered",
+            failed_count.clone(),
+        );
+        registry.register(
+            "kafka_dropped_message_count",
+            "How many messages of the Kafka sink were dropped without being sent",
+            dropped_count.clone(),
+        );
+
+        metrics
+    }
+
+    pub fn delivered(&self, topic: &str) {
+        self.delivered_count
+            .get_or_create(&Self::labels(topic))
+            .inc();
+    }
+
+    pub fn failed(&self, topic: &str) {
+        self.failed_count.get_or_create(&Self::labels(topic)).inc();
+    }
+
+    pub fn dropped(&self, topic: &str) {
+        self.dropped_count.get_or_create(&Self::labels(topic)).inc();
+    }
+
+    fn labels(topic: &str) -> KafkaLabels {
+        KafkaLabels {
+            topic: topic.to_string(),
+        }
+    }
+}
+
+/// Updates mirrored to Redis
+#[derive(Default)]
+pub struct RedisMirrorMetrics {
+    /// How many updates were published
+    published_count: Counter,
+    /// How many updates were dropped, as Redis was unreachable
+    dropped_count:   Counter,
+}
+
+impl RedisMirrorMetrics {
+    pub fn new(registry: &mut Registry) -> Self {
+        let metrics = Self::default();
+
+        #[deny(unused_variables)]
+        let Self {
+            published_count,
+            dropped_count,
+        } = &metrics;
+
+        registry.register(
+            "redis_mirror_published_update_count",
+            "How many updates were published to Redis",
+            published_count.clone(),
+        );
+        registry.register(
+            "redis_mirror_dropped_update_count",
+            "How many updates were dropped as Redis was unreachable",
+            dropped_count.clone(),
+        );
+
+        metrics
+    }
+
+    pub fn published(&self) {
+        self.published_count.inc();
+    }
+
+    pub fn dropped(&self) {
+        self.dropped_count.inc();
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct SlotLagLabels {
+    network: String,
+}
+
+/// How far behind the cluster tip the agent's view of each network is
+#[derive(Default)]
+pub struct SlotLagMetrics {
+    /// Latest slot of the cluster tip
+    network_slot:         Family<SlotLagLabels, Gauge>,
+    /// Slots between the cluster tip and the latest price account update
+    oracle_slot_lag:      Family<SlotLagLabels, Gauge>,
+    /// Slots between the cluster tip and the latest aggregate price
+    aggregate_slot_lag:   Family<SlotLagLabels, Gauge>,
+    /// 1 while publishing is paused as the network slot stalled
+    network_slot_stalled: Family<SlotLagLabels, Gauge>,
+}
+
+impl SlotLagMetrics {
+    pub fn new(registry: &mut Registry) -> Self {
+        let metrics = Self::default();
+
+        #[deny(unused_variables)]
+        let Self {
+            network_slot,
+            oracle_slot_lag,
+            aggregate_slot_lag,
+            network_slot_stalled,
+        } = &metrics;
+
+        registry.register(
+            "network_slot",
+            "Latest slot of the cluster tip, per network",
+            network_slot.clone(),
+        );
+        registry.register(
+            "oracle_slot_lag",
+            "Slots between the cluster tip and the latest price account update observed by the oracle",
+            oracle_slot_lag.clone(),
+        );
+        registry.register(
+            "aggregate_slot_lag",
+            "Slots between the cluster tip and the latest aggregate price observed by the oracle",
+            aggregate_slot_lag.clone(),
+        );
+        registry.register(
+            "network_slot_stalled",
+            "Whether publishing is paused as the network slot stopped advancing, per network",
+            network_slot_stalled.clone(),
+        );
+
+        metrics
+    }
+
+    pub fn update(&self, network: &str, lag: &SlotLag) {
+        let labels = SlotLagLabels {
+            network: network.to_string(),
+        };
+        if let Some(slot) = lag.network_slot {
+            self.network_slot.get_or_create(&labels).set(slot as i64);
+        }
+        if let Some(oracle_lag) = lag.oracle_lag {
+            self.oracle_slot_lag
+                .get_or_create(&labels)
+                .set(oracle_lag as i64);
+        }
+        if let Some(aggregate_lag) = lag.aggregate_lag {
+            self.aggregate_slot_lag
+                .get_or_create(&labels)
+                .set(aggregate_lag as i64);
+        }
+        self.network_slot_stalled
+            .get_or_create(&labels)
+            .set(lag.stalled as i64);
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct MappingUsageLabels {
+    network:         String,
+    mapping_account: String,
+}
+
+/// How full the mapping accounts of each network are
+#[derive(Default)]
+pub struct MappingUsageMetrics {
+    /// Number of products listed by the mapping account
+    mapping_product_count:      Family<MappingUsageLabels, Gauge>,
+    /// Number of products the mapping account can still list
+    mapping_remaining_capacity: Family<MappingUsageLabels, Gauge>,
+}
+
+impl MappingUsageMetrics {
+    pub fn new(registry: &mut Registry) -> Self {
+        let metrics = Self::default();
+
+        #[deny(unused_variables)]
+        let Self {
+            mapping_product_count,
+            mapping_remaining_capacity,
+        } = &metrics;
+
+        registry.register(
+            "mapping_product_count",
+            "Number of products listed by each mapping account polled by the oracle",
+            mapping_product_count.clone(),
+        );
+        registry.register(
+            "mapping_remaining_capacity",
+            "Number of products each mapping account polled by the oracle can still list",
+            mapping_remaining_capacity.clone(),
+        );
+
+        metrics
+    }
+
+    pub fn update(&self, network: &str, mapping_key: &Pubkey, usage: &MappingUsage) {
+        let labels = MappingUsageLabels {
+            network:         network.to_string(),
+            mapping_account: mapping_key.to_string(),
+        };
+        self.mapping_product_count
+            .get_or_create(&labels)
+            .set(usage.products as i64);
+        self.mapping_remaining_capacity
+            .get_or_create(&labels)
+            .set(usage.remaining_capacity() as i64);
     }
 }
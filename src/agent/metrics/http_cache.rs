@@ -0,0 +1,208 @@
+// The dashboard of an agent publishing thousands of symbols is several megabytes,
+// fetched again on every refresh. The responses of the MetricsServer are compressed
+// with gzip or deflate when the client accepts either, and carry an ETag: a client
+// sending it back in If-None-Match gets an empty 304 Not Modified response while the
+// content is unchanged. The responses rendered from the Global Store alone are tagged
+// with the version of its snapshot, and are not rendered again while it is current.
+// Those also showing the Local Store, or ages relative to the current time, are
+// tagged with a digest of their content.
+use {
+    flate2::{
+        write::{
+            DeflateEncoder,
+            GzEncoder,
+        },
+        Compression,
+    },
+    sha2::{
+        Digest,
+        Sha256,
+    },
+    std::io::Write,
+    warp::{
+        http::{
+            header,
+            Response,
+            StatusCode,
+        },
+        hyper::Body,
+        Filter,
+        Rejection,
+        Reply,
+    },
+};
+
+/// Bodies smaller than this are sent uncompressed, as compression would save little
+const MIN_COMPRESSED_SIZE: usize = 1024;
+
+/// The responses are cached by the clients, but validated on every use
+const CACHE_CONTROL: &str = "no-cache";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Gzip,
+    Deflate,
+}
+
+impl Encoding {
+    fn name(self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        }
+    }
+
+    fn encode(self, body: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self {
+            Encoding::Gzip => {
+                let mut encoder = GzEncoder::new(vec![], Compression::default());
+                encoder.write_all(body)?;
+                encoder.finish()
+            }
+            Encoding::Deflate => {
+                let mut encoder = DeflateEncoder::new(vec![], Compression::default());
+                encoder.write_all(body)?;
+                encoder.finish()
+            }
+        }
+    }
+}
+
+/// The caching and compression headers of a request
+#[derive(Clone, Debug, Default)]
+pub struct Conditional {
+    accept_encoding: Option<String>,
+    if_none_match:   Option<String>,
+}
+
+/// Extract the caching and compression headers of the request
+pub fn conditional() -> impl Filter<Extract = (Conditional,), Error = Rejection> + Clone {
+    warp::header::optional::<String>("accept-encoding")
+        .and(warp::header::optional::<String>("if-none-match"))
+        .map(|accept_encoding, if_none_match| Conditional {
+            accept_encoding,
+            if_none_match,
+        })
+}
+
+impl Conditional {
+    /// The ETag of the responses rendered from the given Global Store snapshot
+    pub fn snapshot_etag(version: u64, variant: &str) -> String {
+        let digest = Sha256::digest(variant.as_bytes());
+        format!("\"s{}-{}\"", version, hex::encode(&digest[..8]))
+    }
+
+    /// The not modified response, if the client holds the response with the ETag
+    pub fn not_modified(&self, etag: &str) -> Option<Box<dyn Reply>> {
+        let if_none_match = self.if_none_match.as_deref()?;
+        let matches = if_none_match
+            .split(',')
+            .map(|tag| tag.trim().trim_start_matches("W/"))
+            .any(|tag| tag == "*" || tag == etag);
+        matches.then(|| {
+            let response = Response::builder()
+                .status(StatusCode::NOT_MODIFIED)
+                .header(header::ETAG, etag)
+                .header(header::CACHE_CONTROL, CACHE_CONTROL)
+                .body(Body::empty());
+            Box::new(response) as Box<dyn Reply>
+        })
+    }
+
+    /// Reply with the body tagged with the given ETag, or with a digest of
+    /// the body if none is given
+    pub fn reply(
+        &self,
+        content_type: &str,
+        body: impl Into<Vec<u8>>,
+        etag: Option<String>,
+    ) -> Box<dyn Reply> {
+        let body = body.into();
+        let etag = etag.unwrap_or_else(|| {
+            let digest = Sha256::digest(&body);
+            format!("\"{}\"", hex::encode(&digest[..16]))
+        });
+        if let Some(not_modified) = self.not_modified(&etag) {
+            return not_modified;
+        }
+
+        let response = Response::builder()
+            .header(header::CONTENT_TYPE, content_type)
+            .header(header::ETAG, &etag)
+            .header(header::CACHE_CONTROL, CACHE_CONTROL)
+            .header(header::VARY, "accept-encoding");
+        let encoding = self
+            .accept_encoding
+            .as_deref()
+            .and_then(negotiate)
+            .filter(|_| body.len() >= MIN_COMPRESSED_SIZE);
+        let response = match encoding.map(|encoding| (encoding, encoding.encode(&body))) {
+            Some((encoding, Ok(encoded))) => response
+                .header(header::CONTENT_ENCODING, encoding.name())
+                .body(Body::from(encoded)),
+            Some((encoding, Err(err))) => {
+                warn!(error = %err, encoding = encoding.name(), "Metrics server: could not compress the response");
+                response.body(Body::from(body))
+            }
+            None => response.body(Body::from(body)),
+        };
+        Box::new(response)
+    }
+}
+
+/// The preferred of the supported encodings accepted by the client, per the
+/// Accept-Encoding header of its request
+pub fn negotiate(accept_encoding: &str) -> Option<Encoding> {
+    let accepted = |name: &str| {
+        accept_encoding.split(',').any(|coding| {
+            let mut parts = coding.split(';');
+            let coding = parts.next().unwrap_or_default().trim();
+            let quality = parts
+                .find_map(|param| param.trim().strip_prefix("q="))
+                .and_then(|quality| quality.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            (coding.eq_ignore_ascii_case(name) || coding == "*") && quality > 0.0
+        })
+    };
+    [Encoding::Gzip, Encoding::Deflate]
+        .into_iter()
+        .find(|encoding| accepted(encoding.name()))
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            negotiate,
+            Conditional,
+            Encoding,
+        },
+        warp::Reply,
+    };
+
+    #[test]
+    fn test_encoding_is_negotiated_and_etags_validated() {
+        assert_eq!(negotiate("gzip, deflate, br"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("gzip;q=0, deflate"), Some(Encoding::Deflate));
+        assert_eq!(negotiate("br"), None);
+        assert_eq!(negotiate("identity, *;q=0.1"), Some(Encoding::Gzip));
+
+        let etag = Conditional::snapshot_etag(42, "page=1");
+        assert_ne!(etag, Conditional::snapshot_etag(43, "page=1"));
+        assert_ne!(etag, Conditional::snapshot_etag(42, "page=2"));
+
+        let conditional = Conditional {
+            accept_encoding: None,
+            if_none_match:   Some(format!("\"other\", W/{}", etag)),
+        };
+        assert!(conditional.not_modified(&etag).is_some());
+        assert!(conditional.not_modified("\"other-etag\"").is_none());
+        assert_eq!(
+            conditional
+                .reply("text/plain", "body", Some(etag))
+                .into_response()
+                .status(),
+            304
+        );
+    }
+}
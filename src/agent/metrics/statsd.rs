@@ -0,0 +1,390 @@
+// The StatsD sink periodically pushes the metrics of the Prometheus registry to a
+// StatsD or DogStatsD agent, for deployments where scraping the metrics endpoint
+// is impractical. Gauges are sent as gauges. Counters, and the sum, count and
+// cumulative bucket counts of histograms, are sent as counters incremented by
+// their change since the previous push.
+use {
+    super::PROMETHEUS_REGISTRY,
+    anyhow::{
+        anyhow,
+        Context,
+        Result,
+    },
+    prometheus_client::encoding::text::encode,
+    serde::{
+        Deserialize,
+        Serialize,
+    },
+    std::{
+        collections::HashMap,
+        time::Duration,
+    },
+    tokio::{
+        net::UdpSocket,
+        task::JoinHandle,
+        time,
+    },
+    tracing::Instrument,
+};
+
+/// Maximum size of a datagram, staying below the MTU of common networks
+const MAX_DATAGRAM_SIZE: usize = 1432;
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Address of the StatsD agent, e.g. "127.0.0.1:8125". The sink is
+    /// disabled when not set.
+    pub address:                Option<String>,
+    /// Prefix of the metric names, separated from them by a dot
+    pub prefix:                 String,
+    /// Tags added to every metric, e.g. "env:prod". Only sent to DogStatsD.
+    pub tags:                   Vec<String>,
+    /// Whether the agent is DogStatsD, which supports tags. Metric labels are
+    /// sent as tags to DogStatsD, and appended to the metric name otherwise.
+    pub dogstatsd:              bool,
+    /// Duration of the interval at which the metrics are pushed
+    #[serde(with = "humantime_serde")]
+    pub push_interval_duration: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            address:                None,
+            prefix:                 "pyth_agent".to_string(),
+            tags:                   vec![],
+            dogstatsd:              true,
+            push_interval_duration: Duration::from_secs(10),
+        }
+    }
+}
+
+pub fn spawn_sink(config: Config) -> JoinHandle<()> {
+    tokio::spawn(
+        async move {
+            if let Err(err) = Sink::new(config).run().await {
+                error!(error = ?err, "StatsD sink stopped: {:#}", err);
+            }
+        }
+        .instrument(info_span!("statsd_sink")),
+    )
+}
+
+/// A sample of the Prometheus text exposition
+#[derive(Debug, PartialEq)]
+struct Sample {
+    name:   String,
+    labels: Vec<(String, String)>,
+    value:  f64,
+}
+
+#[derive(Debug, PartialEq)]
+enum StatsdType {
+    Counter,
+    Gauge,
+}
+
+struct Sink {
+    config:   Config,
+    /// Values of the counters at the previous push, keyed by name and labels
+    counters: HashMap<String, f64>,
+    /// Socket connected to the agent, connected again after a failed push
+    socket:   Option<UdpSocket>,
+}
+
+impl Sink {
+    fn new(config: Config) -> Self {
+        Sink {
+            config,
+            counters: HashMap::new(),
+            socket: None,
+        }
+    }
+
+    async fn run(&mut self) -> Result<()> {
+        let address = self
+            .config
+            .address
+            .clone()
+            .ok_or_else(|| anyhow!("no StatsD address configured"))?;
+
+        let mut push_interval = time::interval(self.config.push_interval_duration);
+        loop {
+            push_interval.tick().await;
+            if let Err(err) = self.push(&address).await {
+                // Connect again on the next push
+                self.socket = None;
+                warn!(error = ?err, "StatsD push failed: {:#}", err);
+            }
+        }
+    }
+
+    async fn push(&mut self, address: &str) -> Result<()> {
+        let mut exposition = String::new();
+        encode(&mut exposition, &PROMETHEUS_REGISTRY.lock().await)?;
+        let lines = self.statsd_lines(&exposition);
+
+        if self.socket.is_none() {
+            let socket = UdpSocket::bind("0.0.0.0:0").await?;
+            socket
+                .connect(address)
+                .await
+                .with_context(|| format!("failed to connect to StatsD agent at {}", address))?;
+            self.socket = Some(socket);
+        }
+        let socket = self.socket.as_ref().unwrap();
+
+        for datagram in datagrams(&lines) {
+            socket.send(datagram.as_bytes()).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Translate the Prometheus text exposition into StatsD lines, updating the
+    /// last seen values of the counters
+    fn statsd_lines(&mut self, exposition: &str) -> Vec<String> {
+        let mut types = HashMap::new();
+        let mut lines = vec![];
+        for line in exposition.lines() {
+            if let Some(type_line) = line.strip_prefix("# TYPE ") {
+                if let Some((name, metric_type)) = type_line.split_once(' ') {
+                    types.insert(name.to_string(), metric_type.to_string());
+                }
+                continue;
+            }
+            if line.starts_with('#') || line.is_empty() {
+                continue;
+            }
+
+            let sample = match parse_sample(line) {
+                Some(sample) => sample,
+                None => {
+                    debug!(line, "StatsD: skipping unparseable sample");
+                    continue;
+                }
+            };
+
+            let statsd_type = match statsd_type(&types, &sample.name) {
+                Some(statsd_type) => statsd_type,
+                None => continue,
+            };
+
+            let value = match statsd_type {
+                StatsdType::Gauge => sample.value,
+                StatsdType::Counter => {
+                    let key = format!("{}{:?}", sample.name, sample.labels);
+                    let previous = self.counters.insert(key, sample.value).unwrap_or(0.0);
+                    let delta = sample.value - previous;
+                    if delta <= 0.0 {
+                        continue;
+                    }
+                    delta
+                }
+            };
+
+            lines.push(self.format_line(&sample, value, statsd_type));
+        }
+        lines
+    }
+
+    fn format_line(&self, sample: &Sample, value: f64, statsd_type: StatsdType) -> String {
+        let mut name = sanitize(&sample.name);
+        if !self.config.prefix.is_empty() {
+            name = format!("{}.{}", sanitize(&self.config.prefix), name);
+        }
+        if !self.config.dogstatsd {
+            for (_, label_value) in &sample.labels {
+                name.push('.');
+                name.push_str(&sanitize(label_value));
+            }
+        }
+
+        let suffix = match statsd_type {
+            StatsdType::Counter => "c",
+            StatsdType::Gauge => "g",
+        };
+        let mut line = format!("{}:{}|{}", name, value, suffix);
+
+        if self.config.dogstatsd {
+            let tags = self
+                .config
+                .tags
+                .iter()
+                .cloned()
+                .chain(
+                    sample
+                        .labels
+                        .iter()
+                        .map(|(key, value)| format!("{}:{}", key, sanitize_tag(value))),
+                )
+                .collect::<Vec<_>>();
+            if !tags.is_empty() {
+                line.push_str("|#");
+                line.push_str(&tags.join(","));
+            }
+        }
+
+        line
+    }
+}
+
+/// The StatsD type of a sample, given the types of the metric families
+fn statsd_type(types: &HashMap<String, String>, sample_name: &str) -> Option<StatsdType> {
+    let family_type = |suffix: &str| {
+        sample_name
+            .strip_suffix(suffix)
+            .and_then(|family| types.get(family))
+            .map(String::as_str)
+    };
+
+    if types.get(sample_name).map(String::as_str) == Some("gauge") {
+        return Some(StatsdType::Gauge);
+    }
+    if family_type("_total") == Some("counter") {
+        return Some(StatsdType::Counter);
+    }
+    if ["_sum", "_count", "_bucket"]
+        .iter()
+        .any(|suffix| family_type(suffix) == Some("histogram"))
+    {
+        return Some(StatsdType::Counter);
+    }
+    None
+}
+
+/// Parse a sample line of the form `name{key="value",...} value`, ignoring any
+/// exemplar following the value
+fn parse_sample(line: &str) -> Option<Sample> {
+    let name_end = line.find(|c| c == '{' || c == ' ')?;
+    let name = line[..name_end].to_string();
+
+    let mut labels = vec![];
+    let mut rest = &line[name_end..];
+    if let Some(label_set) = rest.strip_prefix('{') {
+        rest = label_set;
+        loop {
+            if let Some(after) = rest.strip_prefix('}') {
+                rest = after;
+                break;
+            }
+            let (key, after_key) = rest.split_once("=\"")?;
+            let mut value = String::new();
+            let mut chars = after_key.char_indices();
+            let value_end = loop {
+                match chars.next()? {
+                    (_, '\\') => match chars.next()?.1 {
+                        'n' => value.push('\n'),
+                        c => value.push(c),
+                    },
+                    (i, '"') => break i,
+                    (_, c) => value.push(c),
+                }
+            };
+            labels.push((key.to_string(), value));
+            rest = &after_key[value_end + 1..];
+            rest = rest.strip_prefix(',').unwrap_or(rest);
+        }
+    }
+
+    let value = rest.trim_start().split(' ').next()?;
+    let value = match value {
+        "+Inf" => f64::INFINITY,
+        "-Inf" => f64::NEG_INFINITY,
+        value => value.parse().ok()?,
+    };
+
+    Some(Sample {
+        name,
+        labels,
+        value,
+    })
+}
+
+/// Replace the characters with a meaning in the StatsD protocol
+fn sanitize(name: &str) -> String {
+    name.replace(
+        |c: char| matches!(c, ':' | '|' | '@' | '#' | ',' | '\n'),
+        "_",
+    )
+}
+
+/// Tag values may contain colons, but not the tag separators
+fn sanitize_tag(value: &str) -> String {
+    value.replace(|c: char| matches!(c, '|' | '@' | '#' | ',' | '\n'), "_")
+}
+
+/// Pack the lines into newline-separated datagrams of at most the maximum size
+fn datagrams(lines: &[String]) -> Vec<String> {
+    let mut datagrams = vec![];
+    let mut current = String::new();
+    for line in lines {
+        if !current.is_empty() && current.len() + 1 + line.len() > MAX_DATAGRAM_SIZE {
+            datagrams.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push('\n');
+        }
+        current.push_str(line);
+    }
+    if !current.is_empty() {
+        datagrams.push(current);
+    }
+    datagrams
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Config,
+        Sink,
+    };
+
+    #[test]
+    fn test_statsd_lines() {
+        let mut sink = Sink::new(Config {
+            tags: vec!["env:test".to_string()],
+            ..Default::default()
+        });
+
+        let exposition = |count: u64| {
+            format!(
+                r#"# HELP price_count Number of prices
+# TYPE price_count gauge
+price_count{{symbol="Crypto.BTC/USD"}} 3
+# HELP update_count Number of updates
+# TYPE update_count counter
+update_count_total {}
+# HELP latency_seconds Latency
+# TYPE latency_seconds histogram
+latency_seconds_sum 1.5
+latency_seconds_count 2
+latency_seconds_bucket{{le="+Inf"}} 2
+# EOF
+"#,
+                count
+            )
+        };
+
+        assert_eq!(
+            sink.statsd_lines(&exposition(5)),
+            vec![
+                "pyth_agent.price_count:3|g|#env:test,symbol:Crypto.BTC/USD",
+                "pyth_agent.update_count_total:5|c|#env:test",
+                "pyth_agent.latency_seconds_sum:1.5|c|#env:test",
+                "pyth_agent.latency_seconds_count:2|c|#env:test",
+                "pyth_agent.latency_seconds_bucket:2|c|#env:test,le:+Inf",
+            ]
+        );
+
+        // Counters are sent as their change since the previous push
+        assert_eq!(
+            sink.statsd_lines(&exposition(8)),
+            vec![
+                "pyth_agent.price_count:3|g|#env:test,symbol:Crypto.BTC/USD",
+                "pyth_agent.update_count_total:3|c|#env:test",
+            ]
+        );
+    }
+}
@@ -0,0 +1,676 @@
+// The Price History Recorder keeps a durable, analyzable history of the prices
+// observed by the Global Store, without a separate indexer. Each new aggregate of a
+// price is buffered as a row holding its slot, publish time, price, confidence,
+// status and components, and the buffered rows are periodically written to Parquet
+// files in the configured directory. A file is written under an ".inprogress" name
+// and renamed once complete, when it exceeds the rotation size or age, so that
+// readers only ever see complete files. The rows are encoded and written on a blocking
+// thread, so that the recorder keeps receiving the aggregates meanwhile. The recorder
+// completes its file on shutdown.
+// When configured, the aggregates missed while the agent was not running are
+// backfilled from the transaction history of the price accounts.
+pub mod backfill;
+
+use {
+    crate::agent::{
+        pythd::adapter::Adapter,
+        shutdown,
+        solana::oracle::PriceEntry,
+        store::global,
+    },
+    anyhow::{
+        Context,
+        Result,
+    },
+    arrow_array::{
+        builder::{
+            Int64Builder,
+            ListBuilder,
+            StringBuilder,
+            StructBuilder,
+            UInt64Builder,
+        },
+        ArrayRef,
+        Int32Array,
+        Int64Array,
+        RecordBatch,
+        StringArray,
+        UInt64Array,
+    },
+    arrow_schema::{
+        DataType,
+        Field,
+        Fields,
+        Schema,
+        SchemaRef,
+    },
+    chrono::Utc,
+    futures_util::future,
+    parquet::{
+        arrow::ArrowWriter,
+        basic::{
+            Compression as ParquetCompression,
+            GzipLevel,
+            ZstdLevel,
+        },
+        file::properties::WriterProperties,
+    },
+    serde::{
+        Deserialize,
+        Serialize,
+    },
+    solana_sdk::pubkey::Pubkey,
+    std::{
+        collections::HashMap,
+        fs::{
+            self,
+            File,
+        },
+        mem,
+        path::PathBuf,
+        sync::Arc,
+        time::Duration,
+    },
+    tokio::{
+        sync::{
+            broadcast,
+            mpsc,
+        },
+        task::{
+            self,
+            JoinError,
+            JoinHandle,
+        },
+        time::{
+            self,
+            Instant,
+            Interval,
+        },
+    },
+    tracing::Instrument,
+};
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Directory the Parquet files are written to. The recorder is disabled
+    /// when not set.
+    pub directory:               Option<PathBuf>,
+    /// A file is completed once it is older than this
+    #[serde(with = "humantime_serde")]
+    pub rotation_interval:       Duration,
+    /// A file is completed once it is larger than this, in bytes
+    pub rotation_size:           u64,
+    /// Duration of the interval at which the buffered rows are written
+    #[serde(with = "humantime_serde")]
+    pub flush_interval_duration: Duration,
+    /// Compression of the files
+    pub compression:             Compression,
+    /// Backfills the recent aggregates from the transaction history of the
+    /// price accounts on startup. Disabled when not set.
+    pub backfill:                Option<backfill::Config>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            directory:               None,
+            rotation_interval:       Duration::from_secs(60 * 60),
+            rotation_size:           256 * 1024 * 1024,
+            flush_interval_duration: Duration::from_secs(10),
+            compression:             Compression::Snappy,
+            backfill:                None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    None,
+    Snappy,
+    Gzip,
+    Lz4,
+    Zstd,
+}
+
+impl From<Compression> for ParquetCompression {
+    fn from(compression: Compression) -> Self {
+        match compression {
+            Compression::None => ParquetCompression::UNCOMPRESSED,
+            Compression::Snappy => ParquetCompression::SNAPPY,
+            Compression::Gzip => ParquetCompression::GZIP(GzipLevel::default()),
+            Compression::Lz4 => ParquetCompression::LZ4,
+            Compression::Zstd => ParquetCompression::ZSTD(ZstdLevel::default()),
+        }
+    }
+}
+
+pub fn spawn_recorder(
+    config: Config,
+    global_store_events_rx: broadcast::Receiver<global::Event>,
+    global_store_reader: global::SnapshotReader,
+    backfill_network: Option<backfill::Network>,
+    shutdown: shutdown::Participant,
+) -> Result<JoinHandle<()>> {
+    let directory = config
+        .directory
+        .clone()
+        .context("no price history directory configured")?;
+    fs::create_dir_all(&directory)
+        .with_context(|| format!("creating price history directory {}", directory.display()))?;
+
+    // The backfill hands its rows to the recorder
+    let backfill_rx = match (config.backfill.clone(), backfill_network) {
+        (Some(backfill_config), Some(network)) => {
+            let (backfill_tx, backfill_rx) = mpsc::channel(BACKFILL_CHANNEL_CAPACITY);
+            backfill::spawn_backfill(
+                backfill_config,
+                network,
+                global_store_reader.clone(),
+                backfill_tx,
+            );
+            Some(backfill_rx)
+        }
+        _ => None,
+    };
+
+    Ok(tokio::spawn(
+        async move {
+            Recorder::new(
+                config,
+                directory,
+                global_store_events_rx,
+                global_store_reader,
+                backfill_rx,
+            )
+            .run(shutdown)
+            .await
+        }
+        .instrument(info_span!("price_history_recorder")),
+    ))
+}
+
+/// Capacity of the channel the backfilled rows are handed to the recorder on,
+/// in batches of the rows of a price account
+const BACKFILL_CHANNEL_CAPACITY: usize = 16;
+
+/// A new aggregate of a price
+#[derive(Clone, Debug, PartialEq)]
+struct Row {
+    price_account: Pubkey,
+    symbol:        Option<String>,
+    slot:          u64,
+    publish_time:  i64,
+    price:         i64,
+    conf:          u64,
+    expo:          i32,
+    status:        String,
+    components:    Vec<ComponentRow>,
+}
+
+impl Row {
+    fn new(price_account: Pubkey, symbol: Option<String>, account: &PriceEntry) -> Self {
+        Row {
+            price_account,
+            symbol,
+            slot: account.agg.pub_slot,
+            publish_time: account.timestamp,
+            price: account.agg.price,
+            conf: account.agg.conf,
+            expo: account.expo,
+            status: Adapter::price_status_to_str(account.agg.status),
+            components: account
+                .comp
+                .iter()
+                .filter(|component| component.publisher != Pubkey::default())
+                .map(|component| ComponentRow {
+                    publisher: component.publisher,
+                    price:     component.latest.price,
+                    conf:      component.latest.conf,
+                    status:    Adapter::price_status_to_str(component.latest.status),
+                    pub_slot:  component.latest.pub_slot,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The latest price of a publisher, at the time of the aggregate
+#[derive(Clone, Debug, PartialEq)]
+struct ComponentRow {
+    publisher: Pubkey,
+    price:     i64,
+    conf:      u64,
+    status:    String,
+    pub_slot:  u64,
+}
+
+/// The file being written
+struct OpenFile {
+    writer:    ArrowWriter<File>,
+    path:      PathBuf,
+    opened_at: Instant,
+}
+
+/// Writes the rows to the Parquet files, on a blocking thread
+struct Writer {
+    directory: PathBuf,
+    schema:    SchemaRef,
+    file:      Option<OpenFile>,
+    config:    Config,
+}
+
+/// A write of the buffered rows in progress, handing back the writer and the
+/// rows along with the result
+type Written = (Writer, Vec<Row>, Result<()>);
+
+struct Recorder {
+    directory:              PathBuf,
+    /// Rows not written yet, oldest first
+    buffer:                 Vec<Row>,
+    /// None while a write is in progress
+    writer:                 Option<Writer>,
+    writing:                Option<JoinHandle<Written>>,
+    /// Slot of the last recorded aggregate of each price account
+    aggregate_slots:        HashMap<Pubkey, u64>,
+    global_store_events_rx: broadcast::Receiver<global::Event>,
+    /// Used to look up the symbols of the prices
+    global_store_reader:    global::SnapshotReader,
+    /// Rows of the aggregates backfilled from the transaction history, if
+    /// the backfill is running
+    backfill_rx:            Option<mpsc::Receiver<Vec<Row>>>,
+    flush_interval:         Interval,
+    config:                 Config,
+}
+
+impl Recorder {
+    fn new(
+        config: Config,
+        directory: PathBuf,
+        global_store_events_rx: broadcast::Receiver<global::Event>,
+        global_store_reader: global::SnapshotReader,
+        backfill_rx: Option<mpsc::Receiver<Vec<Row>>>,
+    ) -> Self {
+        Recorder {
+            writer: Some(Writer::new(config.clone(), directory.clone())),
+            directory,
+            buffer: vec![],
+            writing: None,
+            aggregate_slots: HashMap::new(),
+            global_store_events_rx,
+            global_store_reader,
+            backfill_rx,
+            flush_interval: time::interval(config.flush_interval_duration),
+            config,
+        }
+    }
+
+    async fn run(&mut self, mut shutdown: shutdown::Participant) {
+        loop {
+            tokio::select! {
+                event = self.global_store_events_rx.recv() => match event {
+                    Ok(global::Event::PriceUpdated { account_key, account, .. }) => {
+                        self.record(account_key, &account)
+                    }
+                    Ok(_) => {}
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!(skipped, "Price history: missed global store events");
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                rows = Self::next_backfilled_rows(&mut self.backfill_rx) => match rows {
+                    Some(rows) => self.buffer.extend(rows),
+                    None => self.backfill_rx = None,
+                },
+                _ = self.flush_interval.tick() => self.start_write(),
+                written = Self::next_write(&mut self.writing) => self.finish_write(written),
+                _ = shutdown.wait() => break,
+            }
+        }
+
+        // Complete the file, so that the history recorded so far is readable
+        self.flush().await;
+        if let Some(mut writer) = self.writer.take() {
+            let result = task::spawn_blocking(move || writer.complete_file())
+                .await
+                .map_err(anyhow::Error::from)
+                .and_then(|result| result);
+            if let Err(err) = result {
+                error!(error = ?err, "Price history: could not complete file: {:#}", err);
+            }
+        }
+    }
+
+    /// Write the buffered rows, after the write in progress if any
+    async fn flush(&mut self) {
+        if self.writing.is_some() {
+            let written = Self::next_write(&mut self.writing).await;
+            self.finish_write(written);
+        }
+        self.start_write();
+        let written = Self::next_write(&mut self.writing).await;
+        self.finish_write(written);
+    }
+
+    /// Hand the buffered rows over to the writer on a blocking thread, unless
+    /// a write is already in progress
+    fn start_write(&mut self) {
+        let mut writer = match self.writer.take() {
+            Some(writer) => writer,
+            None => return,
+        };
+        let rows = mem::take(&mut self.buffer);
+        self.writing = Some(task::spawn_blocking(move || {
+            let result = writer.write(&rows);
+            (writer, rows, result)
+        }));
+    }
+
+    /// The write in progress once it completes, never if there is none
+    async fn next_write(writing: &mut Option<JoinHandle<Written>>) -> Result<Written, JoinError> {
+        match writing {
+            Some(handle) => {
+                let written = handle.await;
+                *writing = None;
+                written
+            }
+            None => future::pending().await,
+        }
+    }
+
+    /// Take the writer back, buffering the rows again ahead of the newer ones
+    /// if they could not be written
+    fn finish_write(&mut self, written: Result<Written, JoinError>) {
+        match written {
+            Ok((writer, rows, result)) => {
+                self.writer = Some(writer);
+                if let Err(err) = result {
+                    error!(error = ?err, "Price history: could not write rows: {:#}", err);
+                    self.buffer.splice(0..0, rows);
+                }
+            }
+            Err(err) => {
+                // The file being written is left in progress
+                error!(error = ?err, "Price history: writer failed: {:#}", err);
+                self.writer = Some(Writer::new(self.config.clone(), self.directory.clone()));
+            }
+        }
+    }
+
+    /// The rows of the next backfilled price account, None once the backfill completed
+    async fn next_backfilled_rows(
+        backfill_rx: &mut Option<mpsc::Receiver<Vec<Row>>>,
+    ) -> Option<Vec<Row>> {
+        match backfill_rx {
+            Some(backfill_rx) => backfill_rx.recv().await,
+            None => future::pending().await,
+        }
+    }
+
+    /// Buffer a row for the price account, if its aggregate is new
+    fn record(&mut self, account_key: Pubkey, account: &PriceEntry) {
+        match self
+            .aggregate_slots
+            .insert(account_key, account.agg.pub_slot)
+        {
+            Some(slot) if slot >= account.agg.pub_slot => return,
+            _ => {}
+        }
+
+        let symbol = self
+            .global_store_reader
+            .load()
+            .account_metadata
+            .symbol_index
+            .symbol(&account_key)
+            .map(str::to_string);
+        self.buffer.push(Row::new(account_key, symbol, account));
+    }
+}
+
+impl Writer {
+    fn new(config: Config, directory: PathBuf) -> Self {
+        Writer {
+            directory,
+            schema: Arc::new(schema()),
+            file: None,
+            config,
+        }
+    }
+
+    /// Write the rows, completing the file if it is due for rotation
+    fn write(&mut self, rows: &[Row]) -> Result<()> {
+        if !rows.is_empty() {
+            let batch = record_batch(&self.schema, rows)?;
+            let file = self.open_file()?;
+            file.writer.write(&batch)?;
+            // Write the rows as a row group, so that the file size reflects them
+            file.writer.flush()?;
+        }
+
+        let rotate = match &self.file {
+            Some(file) => {
+                file.opened_at.elapsed() >= self.config.rotation_interval
+                    || fs::metadata(&file.path)?.len() >= self.config.rotation_size
+            }
+            None => false,
+        };
+        if rotate {
+            self.complete_file()?;
+        }
+        Ok(())
+    }
+
+    fn open_file(&mut self) -> Result<&mut OpenFile> {
+        if self.file.is_none() {
+            let path = self.directory.join(format!(
+                "prices-{}.parquet.inprogress",
+                Utc::now().format("%Y%m%dT%H%M%S%.3f")
+            ));
+            let properties = WriterProperties::builder()
+                .set_compression(self.config.compression.into())
+                .build();
+            let writer = ArrowWriter::try_new(
+                File::create(&path)
+                    .with_context(|| format!("creating price history file {}", path.display()))?,
+                self.schema.clone(),
+                Some(properties),
+            )?;
+            info!(path = %path.display(), "Price history: writing new file");
+            self.file = Some(OpenFile {
+                writer,
+                path,
+                opened_at: Instant::now(),
+            });
+        }
+        Ok(self.file.as_mut().unwrap())
+    }
+
+    /// Write the footer of the current file, and rename it to its final name
+    fn complete_file(&mut self) -> Result<()> {
+        if let Some(file) = self.file.take() {
+            file.writer.close()?;
+            let path = file.path.with_extension("");
+            fs::rename(&file.path, &path)
+                .with_context(|| format!("renaming price history file {}", path.display()))?;
+            info!(path = %path.display(), "Price history: completed file");
+        }
+        Ok(())
+    }
+}
+
+fn component_fields() -> Fields {
+    Fields::from(vec![
+        Field::new("publisher", DataType::Utf8, false),
+        Field::new("price", DataType::Int64, false),
+        Field::new("conf", DataType::UInt64, false),
+        Field::new("status", DataType::Utf8, false),
+        Field::new("pub_slot", DataType::UInt64, false),
+    ])
+}
+
+fn schema() -> Schema {
+    Schema::new(vec![
+        Field::new("price_account", DataType::Utf8, false),
+        Field::new("symbol", DataType::Utf8, true),
+        Field::new("slot", DataType::UInt64, false),
+        Field::new("publish_time", DataType::Int64, false),
+        Field::new("price", DataType::Int64, false),
+        Field::new("conf", DataType::UInt64, false),
+        Field::new("expo", DataType::Int32, false),
+        Field::new("status", DataType::Utf8, false),
+        Field::new(
+            "components",
+            DataType::List(Arc::new(Field::new(
+                "item",
+                DataType::Struct(component_fields()),
+                true,
+            ))),
+            true,
+        ),
+    ])
+}
+
+fn record_batch(schema: &SchemaRef, rows: &[Row]) -> Result<RecordBatch> {
+    let mut components = ListBuilder::new(StructBuilder::new(
+        component_fields(),
+        vec![
+            Box::new(StringBuilder::new()),
+            Box::new(Int64Builder::new()),
+            Box::new(UInt64Builder::new()),
+            Box::new(StringBuilder::new()),
+            Box::new(UInt64Builder::new()),
+        ],
+    ));
+    for row in rows {
+        let builder = components.values();
+        for component in &row.components {
+            builder
+                .field_builder::<StringBuilder>(0)
+                .unwrap()
+                .append_value(component.publisher.to_string());
+            builder
+                .field_builder::<Int64Builder>(1)
+                .unwrap()
+                .append_value(component.price);
+            builder
+                .field_builder::<UInt64Builder>(2)
+                .unwrap()
+                .append_value(component.conf);
+            builder
+                .field_builder::<StringBuilder>(3)
+                .unwrap()
+                .append_value(&component.status);
+            builder
+                .field_builder::<UInt64Builder>(4)
+                .unwrap()
+                .append_value(component.pub_slot);
+            builder.append(true);
+        }
+        components.append(true);
+    }
+
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(StringArray::from_iter_values(
+            rows.iter().map(|row| row.price_account.to_string()),
+        )),
+        Arc::new(StringArray::from_iter(
+            rows.iter().map(|row| row.symbol.as_deref()),
+        )),
+        Arc::new(UInt64Array::from_iter_values(
+            rows.iter().map(|row| row.slot),
+        )),
+        Arc::new(Int64Array::from_iter_values(
+            rows.iter().map(|row| row.publish_time),
+        )),
+        Arc::new(Int64Array::from_iter_values(
+            rows.iter().map(|row| row.price),
+        )),
+        Arc::new(UInt64Array::from_iter_values(
+            rows.iter().map(|row| row.conf),
+        )),
+        Arc::new(Int32Array::from_iter_values(
+            rows.iter().map(|row| row.expo),
+        )),
+        Arc::new(StringArray::from_iter_values(
+            rows.iter().map(|row| row.status.as_str()),
+        )),
+        Arc::new(components.finish()),
+    ];
+    Ok(RecordBatch::try_new(schema.clone(), columns)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            Config,
+            Recorder,
+        },
+        crate::agent::{
+            solana::oracle::PriceEntry,
+            store::global,
+        },
+        parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder,
+        pyth_sdk_solana::state::PriceStatus,
+        solana_sdk::pubkey::Pubkey,
+        std::fs::{
+            self,
+            File,
+        },
+        tokio::sync::broadcast,
+    };
+
+    #[tokio::test]
+    async fn test_aggregates_are_written_to_parquet() {
+        let directory =
+            std::env::temp_dir().join(format!("price-history-{}", Pubkey::new_unique()));
+        fs::create_dir_all(&directory).unwrap();
+        let mut recorder = Recorder::new(
+            Config {
+                directory: Some(directory.clone()),
+                ..Default::default()
+            },
+            directory.clone(),
+            broadcast::channel(1).1,
+            global::SnapshotReader::default(),
+            None,
+        );
+
+        let account_key = Pubkey::new_unique();
+        let mut account = PriceEntry::default();
+        account.agg.price = 100;
+        account.agg.status = PriceStatus::Trading;
+        account.agg.pub_slot = 10;
+        account.comp[0].publisher = Pubkey::new_unique();
+        account.comp[0].latest.price = 101;
+        recorder.record(account_key, &account);
+        // Aggregates already recorded are not recorded again
+        recorder.record(account_key, &account);
+        account.agg.pub_slot = 11;
+        recorder.record(account_key, &account);
+        assert_eq!(recorder.buffer.len(), 2);
+
+        recorder.flush().await;
+        assert!(recorder.buffer.is_empty());
+        recorder.writer.as_mut().unwrap().complete_file().unwrap();
+
+        // Only the completed file remains, holding the rows
+        let paths = fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect::<Vec<_>>();
+        assert_eq!(paths.len(), 1);
+        assert_eq!(paths[0].extension().unwrap(), "parquet");
+        let rows = ParquetRecordBatchReaderBuilder::try_new(File::open(&paths[0]).unwrap())
+            .unwrap()
+            .build()
+            .unwrap()
+            .map(|batch| batch.unwrap().num_rows())
+            .sum::<usize>();
+        assert_eq!(rows, 2);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,496 @@
+// The Price History Backfill fills the gap the agent leaves in the price history
+// while it is not running. Once the first poll of the primary Oracle reached the
+// Global Store, the recent transactions of each price account are fetched with
+// getSignaturesForAddress and getTransaction, back to the max age and up to the slot
+// of the current aggregate, and the upd_price instructions they hold are replayed in
+// slot order onto the components of the price. After the updates of each slot, the
+// aggregation of the Oracle program is replayed on the components, as for the
+// aggregate preview, and the resulting aggregate is handed to the recorder as a row.
+//
+// The components which were not updated within the backfilled window are missing
+// from its first aggregates, and the prices submitted to the price store program are
+// not backfilled, as their transactions don't reference the price accounts.
+use {
+    super::Row,
+    crate::agent::{
+        solana::{
+            instrumented_rpc,
+            oracle::PriceEntry,
+        },
+        store::global,
+    },
+    anyhow::Result,
+    chrono::Utc,
+    futures_util::future::join_all,
+    pyth_sdk_solana::state::PriceStatus,
+    serde::{
+        Deserialize,
+        Serialize,
+    },
+    solana_client::{
+        nonblocking::rpc_client::RpcClient,
+        rpc_client::GetConfirmedSignaturesForAddress2Config,
+        rpc_config::RpcTransactionConfig,
+    },
+    solana_sdk::{
+        commitment_config::CommitmentConfig,
+        pubkey::Pubkey,
+        signature::Signature,
+        transaction::Transaction,
+    },
+    solana_transaction_status::{
+        EncodedTransaction,
+        TransactionBinaryEncoding,
+        UiTransactionEncoding,
+    },
+    std::{
+        str::FromStr,
+        time::Duration,
+    },
+    tokio::{
+        sync::mpsc,
+        task::JoinHandle,
+        time,
+    },
+    tracing::Instrument,
+};
+
+/// Version of the Oracle program instructions
+const ORACLE_VERSION: u32 = 2;
+
+/// Commands of the upd_price instruction, failing on error or not
+const UPD_PRICE_COMMANDS: [u32; 2] = [7, 13];
+
+/// Size of the data of an upd_price instruction: its version, command, status,
+/// padding, price, confidence and publish slot
+const UPD_PRICE_SIZE: usize = 40;
+
+/// Most signatures a getSignaturesForAddress request returns
+const MAX_SIGNATURES_PER_REQUEST: usize = 1000;
+
+/// Interval at which the Global Store is checked for the first poll of the Oracle
+const GLOBAL_STORE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Transactions older than this are not backfilled
+    #[serde(with = "humantime_serde")]
+    pub max_age:                 Duration,
+    /// Most recent transactions fetched per price account
+    pub max_transactions:        usize,
+    /// Symbols whose prices are backfilled. All the prices are when empty.
+    pub symbols:                 Vec<String>,
+    /// Transactions fetched at once
+    pub max_concurrent_requests: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            max_age:                 Duration::from_secs(60 * 60),
+            max_transactions:        1000,
+            symbols:                 vec![],
+            max_concurrent_requests: 8,
+        }
+    }
+}
+
+/// The network the transaction history is fetched from
+#[derive(Clone, Debug)]
+pub struct Network {
+    pub rpc_url:     String,
+    pub rpc_timeout: Duration,
+    /// Key of the Oracle program, whose upd_price instructions are replayed
+    pub program_key: Pubkey,
+}
+
+/// A component update of an upd_price instruction, in the slot it landed in
+#[derive(Clone, Debug, PartialEq)]
+struct Update {
+    slot:       u64,
+    block_time: i64,
+    publisher:  Pubkey,
+    price:      i64,
+    conf:       u64,
+    status:     PriceStatus,
+    pub_slot:   u64,
+}
+
+pub fn spawn_backfill(
+    config: Config,
+    network: Network,
+    global_store_reader: global::SnapshotReader,
+    rows_tx: mpsc::Sender<Vec<Row>>,
+) -> JoinHandle<()> {
+    tokio::spawn(
+        backfill(config, network, global_store_reader, rows_tx)
+            .instrument(info_span!("price_history_backfill")),
+    )
+}
+
+async fn backfill(
+    config: Config,
+    network: Network,
+    global_store_reader: global::SnapshotReader,
+    rows_tx: mpsc::Sender<Vec<Row>>,
+) {
+    let rpc_client = instrumented_rpc::new_rpc_client(
+        "primary",
+        &network.rpc_url,
+        network.rpc_timeout,
+        CommitmentConfig::confirmed(),
+    );
+
+    // The price accounts are known once the Oracle first polled them
+    let snapshot = loop {
+        let snapshot = global_store_reader.load();
+        if !snapshot.account_data.price_accounts.is_empty() {
+            break snapshot;
+        }
+        time::sleep(GLOBAL_STORE_POLL_INTERVAL).await;
+    };
+    let symbol_index = &snapshot.account_metadata.symbol_index;
+    let price_accounts = snapshot
+        .account_data
+        .price_accounts
+        .iter()
+        .map(|(price_key, price_account)| {
+            let symbol = symbol_index.symbol(price_key).map(str::to_string);
+            (price_key, price_account, symbol)
+        })
+        .filter(|(_, _, symbol)| {
+            config.symbols.is_empty()
+                || symbol
+                    .as_ref()
+                    .map_or(false, |symbol| config.symbols.contains(symbol))
+        })
+        .collect::<Vec<_>>();
+
+    let since = Utc::now().timestamp() - config.max_age.as_secs() as i64;
+    info!(
+        price_accounts = price_accounts.len(),
+        max_age = ?config.max_age,
+        "Price history: backfilling from the transaction history"
+    );
+    let mut backfilled_rows = 0;
+    for (price_key, price_account, symbol) in price_accounts {
+        let updates = match fetch_updates(
+            &rpc_client,
+            &config,
+            &network.program_key,
+            price_key,
+            price_account.agg.pub_slot,
+            since,
+        )
+        .await
+        {
+            Ok(updates) => updates,
+            Err(err) => {
+                warn!(
+                    price_key = %price_key,
+                    error = ?err,
+                    "Price history: could not backfill the price: {:#}",
+                    err
+                );
+                continue;
+            }
+        };
+        let rows = replay(*price_key, symbol, price_account, updates);
+        backfilled_rows += rows.len();
+        if !rows.is_empty() && rows_tx.send(rows).await.is_err() {
+            // The recorder stopped
+            return;
+        }
+    }
+    info!(rows = backfilled_rows, "Price history: backfill complete");
+}
+
+/// The component updates of the recent transactions of the price account which
+/// landed before the given slot, oldest first
+async fn fetch_updates(
+    rpc_client: &RpcClient,
+    config: &Config,
+    program_key: &Pubkey,
+    price_key: &Pubkey,
+    until_slot: u64,
+    since: i64,
+) -> Result<Vec<Update>> {
+    // The signatures are returned most recent first
+    let mut signatures = vec![];
+    let mut fetched = 0;
+    let mut before = None;
+    'pages: while fetched < config.max_transactions {
+        let limit = MAX_SIGNATURES_PER_REQUEST.min(config.max_transactions - fetched);
+        let page = rpc_client
+            .get_signatures_for_address_with_config(
+                price_key,
+                GetConfirmedSignaturesForAddress2Config {
+                    before,
+                    until: None,
+                    limit: Some(limit),
+                    commitment: Some(CommitmentConfig::confirmed()),
+                },
+            )
+            .await?;
+        fetched += page.len();
+        for status in &page {
+            if status
+                .block_time
+                .map_or(false, |block_time| block_time < since)
+            {
+                break 'pages;
+            }
+            if status.err.is_none() && status.slot < until_slot {
+                signatures.push(Signature::from_str(&status.signature)?);
+            }
+        }
+        match page.last() {
+            Some(status) if page.len() == limit => {
+                before = Some(Signature::from_str(&status.signature)?)
+            }
+            _ => break,
+        }
+    }
+    signatures.reverse();
+
+    let mut updates = vec![];
+    for signatures in signatures.chunks(config.max_concurrent_requests.max(1)) {
+        let transactions = join_all(signatures.iter().map(|signature| {
+            rpc_client.get_transaction_with_config(
+                signature,
+                RpcTransactionConfig {
+                    encoding: Some(UiTransactionEncoding::Base64),
+                    commitment: Some(CommitmentConfig::confirmed()),
+                    ..RpcTransactionConfig::default()
+                },
+            )
+        }))
+        .await;
+        for (signature, transaction) in signatures.iter().zip(transactions) {
+            let transaction = match transaction {
+                Ok(transaction) => transaction,
+                Err(err) => {
+                    debug!(%signature, error = %err, "Price history: could not fetch a transaction");
+                    continue;
+                }
+            };
+            if let Some(decoded) = decode(&transaction.transaction.transaction) {
+                updates.extend(parse_updates(
+                    &decoded,
+                    program_key,
+                    price_key,
+                    transaction.slot,
+                    transaction.block_time.unwrap_or_default(),
+                ));
+            }
+        }
+    }
+    Ok(updates)
+}
+
+/// The transaction, if it is a legacy transaction encoded in base64
+fn decode(encoded: &EncodedTransaction) -> Option<Transaction> {
+    match encoded {
+        EncodedTransaction::Binary(blob, TransactionBinaryEncoding::Base64) => {
+            bincode::deserialize(&base64::decode(blob).ok()?).ok()
+        }
+        _ => None,
+    }
+}
+
+/// The component updates of the upd_price instructions of the transaction for the price account
+fn parse_updates(
+    transaction: &Transaction,
+    program_key: &Pubkey,
+    price_key: &Pubkey,
+    slot: u64,
+    block_time: i64,
+) -> Vec<Update> {
+    let account_keys = &transaction.message.account_keys;
+    let account = |index: Option<&u8>| index.and_then(|index| account_keys.get(*index as usize));
+    transaction
+        .message
+        .instructions
+        .iter()
+        .filter(|instruction| {
+            account(Some(&instruction.program_id_index)) == Some(program_key)
+                && account(instruction.accounts.get(1)) == Some(price_key)
+                && instruction.data.len() >= UPD_PRICE_SIZE
+        })
+        .filter_map(|instruction| {
+            let data = &instruction.data;
+            let u32_at = |offset: usize| {
+                let mut bytes = [0; 4];
+                bytes.copy_from_slice(&data[offset..offset + 4]);
+                u32::from_le_bytes(bytes)
+            };
+            let u64_at = |offset: usize| {
+                let mut bytes = [0; 8];
+                bytes.copy_from_slice(&data[offset..offset + 8]);
+                u64::from_le_bytes(bytes)
+            };
+            if u32_at(0) != ORACLE_VERSION || !UPD_PRICE_COMMANDS.contains(&u32_at(4)) {
+                return None;
+            }
+            Some(Update {
+                slot,
+                block_time,
+                publisher: *account(instruction.accounts.first())?,
+                price: u64_at(16) as i64,
+                conf: u64_at(24),
+                status: match u32_at(8) {
+                    1 => PriceStatus::Trading,
+                    2 => PriceStatus::Halted,
+                    3 => PriceStatus::Auction,
+                    4 => PriceStatus::Ignored,
+                    _ => PriceStatus::Unknown,
+                },
+                pub_slot: u64_at(32),
+            })
+        })
+        .collect()
+}
+
+/// Replay the updates onto the components of the price, returning a row for
+/// the aggregate after the updates of each slot
+fn replay(
+    price_key: Pubkey,
+    symbol: Option<String>,
+    price_account: &PriceEntry,
+    updates: Vec<Update>,
+) -> Vec<Row> {
+    let mut account = PriceEntry::default();
+    account.expo = price_account.expo;
+    account.min_pub = price_account.min_pub;
+
+    let mut rows = vec![];
+    let mut updates = updates.into_iter().peekable();
+    while let Some(update) = updates.next() {
+        let num = account.num as usize;
+        let index = match account.comp[..num]
+            .iter()
+            .position(|component| component.publisher == update.publisher)
+        {
+            Some(index) => index,
+            None if num < account.comp.len() => {
+                account.comp[num].publisher = update.publisher;
+                account.num += 1;
+                num
+            }
+            None => continue,
+        };
+        let latest = &mut account.comp[index].latest;
+        latest.price = update.price;
+        latest.conf = update.conf;
+        latest.status = update.status;
+        latest.pub_slot = update.pub_slot;
+
+        // Aggregate once all the updates of the slot were applied
+        if updates.peek().map_or(true, |next| next.slot != update.slot) {
+            account.agg.pub_slot = update.slot;
+            let aggregate = global::preview_aggregate(&account, &Pubkey::default(), None);
+            account.agg.price = aggregate.price;
+            account.agg.conf = aggregate.conf;
+            account.agg.status = aggregate.status;
+            account.timestamp = update.block_time;
+            rows.push(Row::new(price_key, symbol.clone(), &account));
+        }
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            parse_updates,
+            replay,
+            Update,
+        },
+        crate::agent::solana::oracle::PriceEntry,
+        pyth_sdk_solana::state::PriceStatus,
+        solana_sdk::{
+            instruction::{
+                AccountMeta,
+                Instruction,
+            },
+            pubkey::Pubkey,
+            transaction::Transaction,
+        },
+    };
+
+    fn upd_price(
+        program_key: Pubkey,
+        publisher: Pubkey,
+        price_key: Pubkey,
+        price: i64,
+    ) -> Instruction {
+        let mut data = vec![];
+        for field in [2u32, 13, 1, 0] {
+            data.extend(field.to_le_bytes());
+        }
+        for field in [price as u64, 10, 99] {
+            data.extend(field.to_le_bytes());
+        }
+        Instruction {
+            program_id: program_key,
+            accounts: vec![
+                AccountMeta::new(publisher, true),
+                AccountMeta::new(price_key, false),
+            ],
+            data,
+        }
+    }
+
+    #[test]
+    fn test_aggregates_are_replayed_from_the_updates() {
+        let (program_key, price_key) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let publishers = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let transaction = Transaction::new_with_payer(
+            &[
+                upd_price(program_key, publishers[0], price_key, 1000),
+                upd_price(program_key, publishers[0], Pubkey::new_unique(), 2000),
+                upd_price(Pubkey::new_unique(), publishers[0], price_key, 3000),
+            ],
+            Some(&publishers[0]),
+        );
+        let updates = parse_updates(&transaction, &program_key, &price_key, 100, 1234);
+        assert_eq!(
+            updates,
+            vec![Update {
+                slot:       100,
+                block_time: 1234,
+                publisher:  publishers[0],
+                price:      1000,
+                conf:       10,
+                status:     PriceStatus::Trading,
+                pub_slot:   99,
+            }]
+        );
+
+        // A second publisher joins in the next slot
+        let mut second = updates[0].clone();
+        second.slot = 101;
+        second.publisher = publishers[1];
+        second.price = 1100;
+        let mut price_account = PriceEntry::default();
+        price_account.min_pub = 1;
+        let rows = replay(
+            price_key,
+            None,
+            &price_account,
+            vec![updates[0].clone(), second],
+        );
+        assert_eq!(rows.len(), 2);
+        assert_eq!(
+            (rows[0].slot, rows[0].price, rows[0].components.len()),
+            (100, 1000, 1)
+        );
+        assert_eq!(
+            (rows[1].slot, rows[1].price, rows[1].components.len()),
+            (101, 1050, 2)
+        );
+        assert_eq!(rows[1].status, "trading");
+    }
+}
@@ -0,0 +1,305 @@
+// The Price Relay streams the aggregates observed by the agent to internal consumers,
+// which then need no access to a Solana RPC node. It is read-only and served on its
+// own address, independently of the pythd API. The relay subscribes to the events of
+// the Global Store, and broadcasts each new aggregate of a price to the connected
+// clients, encoded as the JSON aggregate update of the Kafka sink:
+//
+// - GET /ws upgrades to a websocket, on which each update is a text frame.
+// - GET /sse streams each update as a server-sent event named "aggregate".
+//
+// Both take an optional `symbols` query parameter, the comma-separated symbols whose
+// updates the client receives, e.g. /ws?symbols=Crypto.BTC/USD,Crypto.ETH/USD, all of
+// them being sent without it. Clients too slow to keep up miss the updates they fell
+// behind on.
+use {
+    super::{
+        kafka::AggregateUpdate,
+        store::global,
+    },
+    futures_util::{
+        SinkExt,
+        StreamExt,
+    },
+    serde::{
+        Deserialize,
+        Serialize,
+    },
+    solana_sdk::pubkey::Pubkey,
+    std::{
+        collections::{
+            HashMap,
+            HashSet,
+        },
+        convert::Infallible,
+        net::SocketAddr,
+        sync::Arc,
+    },
+    tokio::{
+        sync::{
+            broadcast::{
+                self,
+                error::RecvError,
+            },
+            mpsc,
+        },
+        task::JoinHandle,
+    },
+    tokio_stream::wrappers::ReceiverStream,
+    tracing::Instrument,
+    warp::{
+        sse,
+        ws::{
+            Message,
+            WebSocket,
+            Ws,
+        },
+        Filter,
+    },
+};
+
+/// Capacity of the channel of the server-sent events of each client
+const SSE_CHANNEL_CAPACITY: usize = 100;
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Address on which the Price Relay is served
+    pub bind_address:       SocketAddr,
+    /// Number of updates buffered for the clients. A client further behind
+    /// misses the updates it fell behind on.
+    pub client_buffer_size: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bind_address:       "127.0.0.1:9003".parse().unwrap(),
+            client_buffer_size: 1000,
+        }
+    }
+}
+
+/// An aggregate update, encoded once for all the clients
+struct Frame {
+    symbol: Option<String>,
+    json:   String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct RelayQuery {
+    symbols: Option<String>,
+}
+
+/// The symbols a client receives the updates of, all of them if None
+#[derive(Debug, Clone, PartialEq)]
+struct SymbolFilter(Option<HashSet<String>>);
+
+impl SymbolFilter {
+    fn new(query: &RelayQuery) -> Self {
+        SymbolFilter(query.symbols.as_ref().map(|symbols| {
+            symbols
+                .split(',')
+                .map(str::trim)
+                .filter(|symbol| !symbol.is_empty())
+                .map(str::to_string)
+                .collect()
+        }))
+    }
+
+    fn matches(&self, frame: &Frame) -> bool {
+        match (&self.0, &frame.symbol) {
+            (None, _) => true,
+            (Some(symbols), Some(symbol)) => symbols.contains(symbol),
+            (Some(_), None) => false,
+        }
+    }
+}
+
+pub fn spawn_relay(
+    config: Config,
+    global_store_events_rx: broadcast::Receiver<global::Event>,
+    global_store_reader: global::SnapshotReader,
+) -> JoinHandle<()> {
+    let (frames_tx, _) = broadcast::channel(config.client_buffer_size);
+    tokio::spawn(
+        async move {
+            tokio::join!(
+                relay_aggregates(
+                    global_store_events_rx,
+                    global_store_reader,
+                    frames_tx.clone()
+                ),
+                serve(config.bind_address, frames_tx),
+            );
+        }
+        .instrument(info_span!("price_relay")),
+    )
+}
+
+/// Broadcast the new aggregates of the prices updated in the Global Store
+async fn relay_aggregates(
+    mut global_store_events_rx: broadcast::Receiver<global::Event>,
+    global_store_reader: global::SnapshotReader,
+    frames_tx: broadcast::Sender<Arc<Frame>>,
+) {
+    // Slot of the last relayed aggregate of each price account
+    let mut aggregate_slots: HashMap<Pubkey, u64> = HashMap::new();
+    loop {
+        let (account_key, account) = match global_store_events_rx.recv().await {
+            Ok(global::Event::PriceUpdated {
+                account_key,
+                account,
+                ..
+            }) => (account_key, account),
+            Ok(_) => continue,
+            Err(RecvError::Lagged(skipped)) => {
+                warn!(
+                    skipped,
+                    "Price relay: fell behind the Global Store, skipping updates"
+                );
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+        match aggregate_slots.insert(account_key, account.agg.pub_slot) {
+            Some(slot) if slot >= account.agg.pub_slot => continue,
+            _ => {}
+        }
+        // Nobody is listening
+        if frames_tx.receiver_count() == 0 {
+            continue;
+        }
+
+        let symbol = global_store_reader
+            .load()
+            .account_metadata
+            .symbol_index
+            .symbol(&account_key)
+            .map(str::to_string);
+        let update = AggregateUpdate::new(account_key, &account, symbol.clone());
+        match serde_json::to_string(&update) {
+            Ok(json) => {
+                let _ = frames_tx.send(Arc::new(Frame { symbol, json }));
+            }
+            Err(err) => error!(error = %err, "Price relay: Serializing update failed"),
+        }
+    }
+}
+
+async fn serve(bind_address: SocketAddr, frames_tx: broadcast::Sender<Arc<Frame>>) {
+    let ws_frames_tx = frames_tx.clone();
+    let ws_route = warp::path!("ws")
+        .and(warp::ws())
+        .and(warp::query::<RelayQuery>())
+        .map(move |ws: Ws, query: RelayQuery| {
+            let frames_rx = ws_frames_tx.subscribe();
+            let filter = SymbolFilter::new(&query);
+            ws.on_upgrade(move |websocket| stream_websocket(websocket, frames_rx, filter))
+        });
+
+    let sse_route =
+        warp::path!("sse")
+            .and(warp::query::<RelayQuery>())
+            .map(move |query: RelayQuery| {
+                let (events_tx, events_rx) = mpsc::channel(SSE_CHANNEL_CAPACITY);
+                tokio::spawn(stream_events(
+                    frames_tx.subscribe(),
+                    SymbolFilter::new(&query),
+                    events_tx,
+                ));
+                sse::reply(sse::keep_alive().stream(ReceiverStream::new(events_rx)))
+            });
+
+    info!(%bind_address, "Price relay: serving");
+    warp::serve(ws_route.or(sse_route)).bind(bind_address).await;
+}
+
+/// Send the updates to a websocket client, until it disconnects
+async fn stream_websocket(
+    websocket: WebSocket,
+    mut frames_rx: broadcast::Receiver<Arc<Frame>>,
+    filter: SymbolFilter,
+) {
+    debug!("Price relay: websocket client connected");
+    let (mut ws_tx, mut ws_rx) = websocket.split();
+    loop {
+        tokio::select! {
+            frame = frames_rx.recv() => match frame {
+                Ok(frame) if filter.matches(&frame) => {
+                    if ws_tx.send(Message::text(frame.json.clone())).await.is_err() {
+                        break;
+                    }
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(skipped)) => {
+                    debug!(skipped, "Price relay: websocket client fell behind, skipping updates");
+                }
+                Err(RecvError::Closed) => break,
+            },
+            // The messages of the client are ignored, until it closes the connection
+            message = ws_rx.next() => match message {
+                Some(Ok(message)) if !message.is_close() => {}
+                _ => break,
+            },
+        }
+    }
+    debug!("Price relay: websocket client disconnected");
+}
+
+/// Send the updates to a server-sent events client, until it disconnects
+async fn stream_events(
+    mut frames_rx: broadcast::Receiver<Arc<Frame>>,
+    filter: SymbolFilter,
+    events_tx: mpsc::Sender<Result<sse::Event, Infallible>>,
+) {
+    loop {
+        match frames_rx.recv().await {
+            Ok(frame) if filter.matches(&frame) => {
+                let event = sse::Event::default()
+                    .event("aggregate")
+                    .data(frame.json.clone());
+                if events_tx.send(Ok(event)).await.is_err() {
+                    debug!("Price relay: server-sent events client disconnected");
+                    return;
+                }
+            }
+            Ok(_) => {}
+            Err(RecvError::Lagged(skipped)) => {
+                debug!(
+                    skipped,
+                    "Price relay: server-sent events client fell behind, skipping updates"
+                );
+            }
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Frame,
+        RelayQuery,
+        SymbolFilter,
+    };
+
+    #[test]
+    fn test_symbol_filter() {
+        let frame = |symbol: Option<&str>| Frame {
+            symbol: symbol.map(str::to_string),
+            json:   "{}".to_string(),
+        };
+
+        let all = SymbolFilter::new(&RelayQuery { symbols: None });
+        assert!(all.matches(&frame(Some("Crypto.BTC/USD"))));
+        assert!(all.matches(&frame(None)));
+
+        let some = SymbolFilter::new(&RelayQuery {
+            symbols: Some("Crypto.BTC/USD, Crypto.ETH/USD,".to_string()),
+        });
+        assert!(some.matches(&frame(Some("Crypto.BTC/USD"))));
+        assert!(some.matches(&frame(Some("Crypto.ETH/USD"))));
+        assert!(!some.matches(&frame(Some("Equity.US.AAPL/USD"))));
+        assert!(!some.matches(&frame(None)));
+    }
+}
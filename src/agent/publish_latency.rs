@@ -0,0 +1,416 @@
+// The Publish Latency Tracker measures the end-to-end latency of our price updates:
+// the time from an update arriving at the agent to the update being included in
+// the on-chain aggregate, as observed by the Global Store. Only the components of
+// our own publish keys are correlated with our updates. Latencies are exported as
+// Prometheus histograms per asset type.
+use {
+    crate::agent::{
+        metrics::{
+            PublishLatencyMetrics,
+            PROMETHEUS_REGISTRY,
+        },
+        store::{
+            global,
+            local::{
+                PriceInfo,
+                Publisher,
+            },
+            PriceIdentifier,
+        },
+        telemetry,
+    },
+    opentelemetry::{
+        Context,
+        KeyValue,
+    },
+    serde::{
+        Deserialize,
+        Serialize,
+    },
+    solana_sdk::pubkey::Pubkey,
+    std::{
+        collections::{
+            HashMap,
+            HashSet,
+            VecDeque,
+        },
+        str::FromStr,
+        time::{
+            Duration,
+            Instant,
+            SystemTime,
+        },
+    },
+    tokio::{
+        sync::{
+            broadcast,
+            mpsc,
+        },
+        task::JoinHandle,
+        time::{
+            self,
+            Interval,
+        },
+    },
+    tracing::Instrument,
+};
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Updates not observed on-chain within this duration are forgotten
+    #[serde(with = "humantime_serde")]
+    pub max_pending_age: Duration,
+    /// Publish keys whose on-chain components are correlated with the updates
+    /// submitted on behalf of the default publisher. Defaults to the publish
+    /// keys of the networks when empty.
+    pub publisher_keys:  Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            max_pending_age: Duration::from_secs(60),
+            publisher_keys:  vec![],
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Message {
+    /// An update was accepted into the Local Store, having arrived at the agent
+    /// at `received_at`
+    LocalUpdate {
+        publisher:        Publisher,
+        price_identifier: PriceIdentifier,
+        price_info:       PriceInfo,
+        received_at:      Instant,
+        trace_context:    Context,
+    },
+}
+
+pub fn spawn_tracker(
+    config: Config,
+    network_publish_keys: Vec<Pubkey>,
+    rx: mpsc::Receiver<Message>,
+    global_store_events_rx: broadcast::Receiver<global::Event>,
+    global_store_reader: global::SnapshotReader,
+) -> JoinHandle<()> {
+    tokio::spawn(
+        async move {
+            Tracker::new(
+                config,
+                network_publish_keys,
+                rx,
+                global_store_events_rx,
+                global_store_reader,
+            )
+            .await
+            .run()
+            .await
+        }
+        .instrument(info_span!("publish_latency_tracker")),
+    )
+}
+
+/// A local update which has not been observed on-chain yet
+struct PendingUpdate {
+    price_info:    PriceInfo,
+    received_at:   Instant,
+    trace_context: Context,
+}
+
+pub struct Tracker {
+    /// Updates awaiting their inclusion in the aggregate, oldest first
+    pending:                HashMap<PriceIdentifier, HashMap<Publisher, VecDeque<PendingUpdate>>>,
+    /// Publish keys of the default publisher, parsed from the config or else
+    /// those of the networks
+    publisher_keys:         HashSet<Pubkey>,
+    metrics:                PublishLatencyMetrics,
+    rx:                     mpsc::Receiver<Message>,
+    global_store_events_rx: broadcast::Receiver<global::Event>,
+    /// Used to look up the asset type of the observed prices
+    global_store_reader:    global::SnapshotReader,
+    /// Interval at which updates older than the max pending age are forgotten
+    cleanup_interval:       Interval,
+    config:                 Config,
+}
+
+impl Tracker {
+    pub async fn new(
+        config: Config,
+        network_publish_keys: Vec<Pubkey>,
+        rx: mpsc::Receiver<Message>,
+        global_store_events_rx: broadcast::Receiver<global::Event>,
+        global_store_reader: global::SnapshotReader,
+    ) -> Self {
+        let publisher_keys = if config.publisher_keys.is_empty() {
+            network_publish_keys.into_iter().collect()
+        } else {
+            config
+                .publisher_keys
+                .iter()
+                .filter_map(|key| match Pubkey::from_str(key) {
+                    Ok(key) => Some(key),
+                    Err(err) => {
+                        error!(%key, error = %err, "Publish latency: ignoring invalid publisher key");
+                        None
+                    }
+                })
+                .collect()
+        };
+        if publisher_keys.is_empty() {
+            warn!("Publish latency: no publish keys known, updates of the default publisher are not tracked");
+        }
+
+        Tracker {
+            pending: HashMap::new(),
+            publisher_keys,
+            metrics: PublishLatencyMetrics::new(&mut &mut PROMETHEUS_REGISTRY.lock().await),
+            rx,
+            global_store_events_rx,
+            global_store_reader,
+            cleanup_interval: time::interval(config.max_pending_age),
+            config,
+        }
+    }
+
+    pub async fn run(&mut self) {
+        loop {
+            tokio::select! {
+                message = self.rx.recv() => match message {
+                    Some(message) => self.handle(message),
+                    None => break,
+                },
+                event = self.global_store_events_rx.recv() => match event {
+                    Ok(event) => self.handle_global_store_event(event),
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!(skipped, "Publish latency: missed global store events");
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                _ = self.cleanup_interval.tick() => self.forget_old_updates(),
+            }
+        }
+    }
+
+    fn handle(&mut self, message: Message) {
+        match message {
+            Message::LocalUpdate {
+                publisher,
+                price_identifier,
+                price_info,
+                received_at,
+                trace_context,
+            } => {
+                let pending = self
+                    .pending
+                    .entry(price_identifier)
+                    .or_default()
+                    .entry(publisher)
+                    .or_default();
+
+                // Repeated submissions of the same price are not new updates, the
+                // latency is measured from the first one.
+                if let Some(last) = pending.back() {
+                    if last.price_info.cmp_no_timestamp(&price_info) {
+                        return;
+                    }
+                }
+
+                pending.push_back(PendingUpdate {
+                    price_info,
+                    received_at,
+                    trace_context,
+                });
+            }
+        }
+    }
+
+    fn handle_global_store_event(&mut self, event: global::Event) {
+        let (account_key, account, global_trace_context) = match event {
+            global::Event::PriceUpdated {
+                account_key,
+                account,
+                trace_context,
+            } => (account_key, account, trace_context),
+            _ => return,
+        };
+
+        let price_identifier = PriceIdentifier::new(account_key.to_bytes());
+        let pending_by_publisher = match self.pending.get_mut(&price_identifier) {
+            Some(pending_by_publisher) => pending_by_publisher,
+            None => return,
+        };
+
+        let now = Instant::now();
+        let system_now = SystemTime::now();
+        let mut latencies = vec![];
+        for (publisher, pending) in pending_by_publisher.iter_mut() {
+            // Components of the given publisher are candidates, or those of our
+            // publish keys for the default publisher.
+            let is_candidate = |component_publisher: &Pubkey| match publisher {
+                Some(publisher) => publisher == component_publisher,
+                None => self.publisher_keys.contains(component_publisher),
+            };
+
+            // The component's aggregated price is the one which went into the aggregate
+            let included = account
+                .comp
+                .iter()
+                .filter(|component| is_candidate(&component.publisher))
+                .filter_map(|component| {
+                    pending.iter().position(|update| {
+                        update.price_info.status == component.agg.status
+                            && update.price_info.price == component.agg.price
+                            && update.price_info.conf == component.agg.conf
+                    })
+                })
+                .max();
+
+            // Earlier updates were superseded by the included one
+            if let Some(position) = included {
+                let update = pending
+                    .drain(..=position)
+                    .last()
+                    .expect("drained range is not empty");
+                let latency = now.duration_since(update.received_at);
+                latencies.push(latency);
+
+                // Close the update's trace with the time it took to reach the
+                // aggregate, linked to the trace of the observing Global Store update
+                telemetry::record_linked_span_since(
+                    &update.trace_context,
+                    "publish_latency.aggregate_inclusion",
+                    system_now - latency,
+                    vec![KeyValue::new("latency_seconds", latency.as_secs_f64())],
+                    &global_trace_context,
+                );
+            }
+        }
+
+        if latencies.is_empty() {
+            return;
+        }
+
+        let asset_type = self
+            .global_store_reader
+            .load()
+            .account_metadata
+            .product_accounts_metadata
+            .get(&account.prod)
+            .and_then(|metadata| metadata.attr_dict.get("asset_type").cloned());
+        for latency in latencies {
+            self.metrics.observe(&asset_type, latency);
+        }
+    }
+
+    fn forget_old_updates(&mut self) {
+        let max_pending_age = self.config.max_pending_age;
+        self.pending.retain(|_, pending_by_publisher| {
+            pending_by_publisher.retain(|_, pending| {
+                pending.retain(|update| update.received_at.elapsed() <= max_pending_age);
+                !pending.is_empty()
+            });
+            !pending_by_publisher.is_empty()
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            Config,
+            Message,
+            Tracker,
+        },
+        crate::agent::{
+            solana::oracle::PriceEntry,
+            store::{
+                global,
+                local::PriceInfo,
+                PriceIdentifier,
+            },
+        },
+        opentelemetry::Context,
+        pyth_sdk_solana::state::PriceStatus,
+        solana_sdk::pubkey::Pubkey,
+        std::{
+            sync::Arc,
+            time::Instant,
+        },
+        tokio::sync::{
+            broadcast,
+            mpsc,
+        },
+    };
+
+    fn local_update(price_identifier: PriceIdentifier, price: i64) -> Message {
+        Message::LocalUpdate {
+            publisher: None,
+            price_identifier,
+            price_info: PriceInfo {
+                status: PriceStatus::Trading,
+                price,
+                conf: 1,
+                timestamp: 0,
+            },
+            received_at: Instant::now(),
+            trace_context: Context::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_correlate_included_update() {
+        let (_tx, rx) = mpsc::channel(1);
+        let (_events_tx, events_rx) = broadcast::channel(1);
+        let publish_key = Pubkey::new_unique();
+        let mut tracker = Tracker::new(
+            Config::default(),
+            vec![publish_key],
+            rx,
+            events_rx,
+            global::SnapshotReader::default(),
+        )
+        .await;
+
+        let account_key = Pubkey::new_unique();
+        let price_identifier = PriceIdentifier::new(account_key.to_bytes());
+        tracker.handle(local_update(price_identifier, 10));
+        tracker.handle(local_update(price_identifier, 10));
+        tracker.handle(local_update(price_identifier, 20));
+        tracker.handle(local_update(price_identifier, 30));
+
+        // Repeated submissions of the same price are tracked once
+        let pending = |tracker: &Tracker| {
+            tracker.pending[&price_identifier][&None]
+                .iter()
+                .map(|update| update.price_info.price)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(pending(&tracker), vec![10, 20, 30]);
+
+        // The same price published by another publisher is not ours
+        let mut account = PriceEntry::default();
+        account.comp[0].publisher = Pubkey::new_unique();
+        account.comp[0].agg.status = PriceStatus::Trading;
+        account.comp[0].agg.price = 20;
+        account.comp[0].agg.conf = 1;
+        tracker.handle_global_store_event(global::Event::PriceUpdated {
+            account_key,
+            account: Arc::new(account),
+            trace_context: Context::new(),
+        });
+        assert_eq!(pending(&tracker), vec![10, 20, 30]);
+
+        // Including the second update in the aggregate supersedes the first
+        account.comp[0].publisher = publish_key;
+        tracker.handle_global_store_event(global::Event::PriceUpdated {
+            account_key,
+            account: Arc::new(account),
+            trace_context: Context::new(),
+        });
+        assert_eq!(pending(&tracker), vec![30]);
+    }
+}
@@ -0,0 +1,136 @@
+// Publishing can be paused through the Admin API, globally or for specific symbols,
+// e.g. to halt publishing during an incident without stopping the agent. While
+// paused, updates are still accepted and stored, but the Exporters skip them. The
+// pause is not persisted, so a restarted agent publishes again.
+//
+// The standby of a high-availability pair is paused the same way, independently of
+// the pauses of the Admin API.
+use {
+    crate::agent::metrics::PublishPauseMetrics,
+    arc_swap::ArcSwap,
+    prometheus_client::registry::Registry,
+    serde::Serialize,
+    std::{
+        collections::BTreeSet,
+        sync::Arc,
+    },
+};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct PauseState {
+    /// Whether all publishing is paused
+    pub global:  bool,
+    /// Symbols whose publishing is paused, e.g. "Crypto.BTC/USD"
+    pub symbols: BTreeSet<String>,
+    /// Whether this agent is the standby of a high-availability pair
+    pub standby: bool,
+}
+
+impl PauseState {
+    /// Whether all publishing is paused, globally or by standing by
+    pub fn all_paused(&self) -> bool {
+        self.global || self.standby
+    }
+
+    pub fn is_paused(&self, symbol: &str) -> bool {
+        self.all_paused() || self.symbols.contains(symbol)
+    }
+}
+
+/// Pause state shared between the Admin API, the Exporters and the dashboard
+#[derive(Clone)]
+pub struct PublishPause {
+    state:   Arc<ArcSwap<PauseState>>,
+    metrics: Arc<PublishPauseMetrics>,
+}
+
+impl PublishPause {
+    pub fn new(registry: &mut Registry) -> Self {
+        PublishPause {
+            state:   Default::default(),
+            metrics: Arc::new(PublishPauseMetrics::new(registry)),
+        }
+    }
+
+    pub fn get(&self) -> Arc<PauseState> {
+        self.state.load_full()
+    }
+
+    /// Pause the given symbols, or all publishing if there are none
+    pub fn pause(&self, symbols: &[String]) {
+        self.state.rcu(|state| {
+            let mut state = state.as_ref().clone();
+            if symbols.is_empty() {
+                state.global = true;
+            }
+            state.symbols.extend(symbols.iter().cloned());
+            state
+        });
+        self.metrics.record(&self.get(), symbols);
+    }
+
+    /// Resume the given symbols, or all publishing, including the paused
+    /// symbols, if there are none
+    pub fn resume(&self, symbols: &[String]) {
+        let previous = self.get();
+        self.state.rcu(|state| {
+            let mut state = state.as_ref().clone();
+            if symbols.is_empty() {
+                state = PauseState {
+                    standby: state.standby,
+                    ..Default::default()
+                };
+            }
+            for symbol in symbols {
+                state.symbols.remove(symbol);
+            }
+            state
+        });
+        let changed = if symbols.is_empty() {
+            previous.symbols.iter().cloned().collect::<Vec<_>>()
+        } else {
+            symbols.to_vec()
+        };
+        self.metrics.record(&self.get(), &changed);
+    }
+
+    /// Stand by, or resume publishing as the leader of a high-availability
+    /// pair
+    pub fn set_standby(&self, standby: bool) {
+        self.state.rcu(|state| PauseState {
+            standby,
+            ..state.as_ref().clone()
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::PublishPause,
+        prometheus_client::registry::Registry,
+    };
+
+    #[test]
+    fn test_pause_and_resume() {
+        let publish_pause = PublishPause::new(&mut Registry::default());
+        let btc = "Crypto.BTC/USD".to_string();
+        let eth = "Crypto.ETH/USD".to_string();
+
+        publish_pause.pause(&[btc.clone(), eth.clone()]);
+        assert!(publish_pause.get().is_paused(&btc));
+        assert!(!publish_pause.get().is_paused("Crypto.SOL/USD"));
+
+        publish_pause.resume(&[btc.clone()]);
+        assert!(!publish_pause.get().is_paused(&btc));
+        assert!(publish_pause.get().is_paused(&eth));
+
+        // A global pause covers every symbol, and a global resume clears the
+        // symbols paused individually too
+        publish_pause.pause(&[]);
+        assert!(publish_pause.get().is_paused("Crypto.SOL/USD"));
+        publish_pause.resume(&[]);
+        assert!(!publish_pause.get().global);
+        assert!(publish_pause.get().symbols.is_empty());
+    }
+}
@@ -0,0 +1,449 @@
+// The Publisher Performance Tracker scores our publishers the way the network does,
+// from the on-chain components of our publish keys observed by the Global Store.
+// Each new aggregate of a price is a sample, for which every one of our components
+// is scored on:
+// - uptime: whether it was quoting, i.e. trading and published recently enough to
+//   be eligible for the aggregate
+// - slot hit: whether it was published in the slot the aggregate was computed from
+// - aggregate inclusion: whether it contributed to the aggregate
+// - deviation: how far its contributed price was from the aggregate price
+// Samples are counted in fixed-duration buckets, from which the statistics are
+// computed over rolling windows. They are exported as Prometheus gauges, shown on
+// the dashboard and served as JSON by the metrics server.
+use {
+    crate::agent::{
+        metrics::{
+            PublisherPerformanceMetrics,
+            PROMETHEUS_REGISTRY,
+        },
+        solana::oracle::PriceEntry,
+        store::global,
+    },
+    anyhow::{
+        anyhow,
+        Result,
+    },
+    pyth_sdk_solana::state::PriceStatus,
+    serde::{
+        Deserialize,
+        Serialize,
+    },
+    solana_sdk::pubkey::Pubkey,
+    std::{
+        collections::{
+            HashMap,
+            HashSet,
+            VecDeque,
+        },
+        str::FromStr,
+        time::Duration,
+    },
+    tokio::{
+        sync::{
+            broadcast,
+            mpsc,
+            oneshot,
+        },
+        task::JoinHandle,
+        time::{
+            self,
+            Instant,
+            Interval,
+        },
+    },
+    tracing::Instrument,
+};
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Publish keys whose on-chain components are scored. The tracker is
+    /// disabled when empty.
+    pub publisher_keys:  Vec<String>,
+    /// Rolling windows over which the statistics are computed
+    pub windows:         Vec<humantime_serde::Serde<Duration>>,
+    /// Granularity of the rolling windows. The statistics exported as metrics
+    /// are refreshed at this interval.
+    #[serde(with = "humantime_serde")]
+    pub bucket_duration: Duration,
+    /// Components published more slots than this before the aggregate are
+    /// not counted as up, as they are too old to be included in it
+    pub max_slot_lag:    u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            publisher_keys:  vec![],
+            windows:         vec![
+                Duration::from_secs(5 * 60).into(),
+                Duration::from_secs(60 * 60).into(),
+                Duration::from_secs(24 * 60 * 60).into(),
+            ],
+            bucket_duration: Duration::from_secs(60),
+            max_slot_lag:    25,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Message {
+    /// Look up the current statistics of all our components
+    LookupReport {
+        result_tx: oneshot::Sender<Vec<PublisherPerformance>>,
+    },
+}
+
+/// The statistics of one of our publishers for a price
+#[derive(Clone, Debug, Serialize)]
+pub struct PublisherPerformance {
+    pub symbol:        Option<String>,
+    pub price_account: String,
+    pub publisher:     String,
+    pub windows:       Vec<WindowStats>,
+}
+
+/// The statistics of a component over a rolling window. The rates are
+/// fractions of the aggregates observed in the window, and are not set when
+/// none was observed.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct WindowStats {
+    /// The window, e.g. "1h"
+    pub window:            String,
+    /// Number of aggregates observed in the window
+    pub samples:           u64,
+    pub uptime:            Option<f64>,
+    pub slot_hit_rate:     Option<f64>,
+    pub inclusion_rate:    Option<f64>,
+    /// Mean relative deviation of the included prices from the aggregate price
+    pub average_deviation: Option<f64>,
+}
+
+pub fn spawn_tracker(
+    config: Config,
+    rx: mpsc::Receiver<Message>,
+    global_store_events_rx: broadcast::Receiver<global::Event>,
+    global_store_reader: global::SnapshotReader,
+) -> JoinHandle<()> {
+    tokio::spawn(
+        async move {
+            Tracker::new(config, rx, global_store_events_rx, global_store_reader)
+                .await
+                .run()
+                .await
+        }
+        .instrument(info_span!("publisher_performance_tracker")),
+    )
+}
+
+/// The samples of a component observed during a bucket
+#[derive(Clone, Debug, Default)]
+struct Bucket {
+    started_at:        Option<Instant>,
+    samples:           u64,
+    up:                u64,
+    hits:              u64,
+    included:          u64,
+    deviation_sum:     f64,
+    deviation_samples: u64,
+}
+
+pub struct Tracker {
+    /// Buckets of each of our components, by price account and publisher, oldest first
+    buckets:                HashMap<(Pubkey, Pubkey), VecDeque<Bucket>>,
+    /// Slot of the last aggregate observed for each price account
+    last_aggregate_slots:   HashMap<Pubkey, u64>,
+    /// Publish keys parsed from the config
+    publisher_keys:         HashSet<Pubkey>,
+    /// Start of the current bucket
+    bucket_started_at:      Instant,
+    metrics:                PublisherPerformanceMetrics,
+    rx:                     mpsc::Receiver<Message>,
+    global_store_events_rx: broadcast::Receiver<global::Event>,
+    /// Used to look up the symbols of the prices
+    global_store_reader:    global::SnapshotReader,
+    bucket_interval:        Interval,
+    config:                 Config,
+}
+
+impl Tracker {
+    pub async fn new(
+        config: Config,
+        rx: mpsc::Receiver<Message>,
+        global_store_events_rx: broadcast::Receiver<global::Event>,
+        global_store_reader: global::SnapshotReader,
+    ) -> Self {
+        let publisher_keys = config
+            .publisher_keys
+            .iter()
+            .filter_map(|key| match Pubkey::from_str(key) {
+                Ok(key) => Some(key),
+                Err(err) => {
+                    error!(%key, error = %err, "Publisher performance: ignoring invalid publisher key");
+                    None
+                }
+            })
+            .collect();
+
+        Tracker {
+            buckets: HashMap::new(),
+            last_aggregate_slots: HashMap::new(),
+            publisher_keys,
+            bucket_started_at: Instant::now(),
+            metrics: PublisherPerformanceMetrics::new(&mut &mut PROMETHEUS_REGISTRY.lock().await),
+            rx,
+            global_store_events_rx,
+            global_store_reader,
+            bucket_interval: time::interval(config.bucket_duration),
+            config,
+        }
+    }
+
+    pub async fn run(&mut self) {
+        loop {
+            tokio::select! {
+                message = self.rx.recv() => match message {
+                    Some(message) => {
+                        if let Err(err) = self.handle(message) {
+                            error!(error = ?err, "{:#}", err)
+                        }
+                    }
+                    None => break,
+                },
+                event = self.global_store_events_rx.recv() => match event {
+                    Ok(global::Event::PriceUpdated { account_key, account, .. }) => {
+                        self.observe(account_key, &account)
+                    }
+                    Ok(_) => {}
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!(skipped, "Publisher performance: missed global store events");
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                _ = self.bucket_interval.tick() => self.start_bucket(),
+            }
+        }
+    }
+
+    fn handle(&mut self, message: Message) -> Result<()> {
+        match message {
+            Message::LookupReport { result_tx } => result_tx
+                .send(self.report())
+                .map_err(|_| anyhow!("failed to send LookupReport result")),
+        }
+    }
+
+    /// Score our components of the price account, if its aggregate is new
+    fn observe(&mut self, account_key: Pubkey, account: &PriceEntry) {
+        let agg = &account.agg;
+        match self.last_aggregate_slots.insert(account_key, agg.pub_slot) {
+            Some(last_slot) if last_slot >= agg.pub_slot => return,
+            _ => {}
+        }
+
+        for component in account
+            .comp
+            .iter()
+            .filter(|component| self.publisher_keys.contains(&component.publisher))
+        {
+            let buckets = self
+                .buckets
+                .entry((account_key, component.publisher))
+                .or_default();
+            if buckets.back().and_then(|bucket| bucket.started_at) != Some(self.bucket_started_at) {
+                buckets.push_back(Bucket {
+                    started_at: Some(self.bucket_started_at),
+                    ..Default::default()
+                });
+            }
+            let bucket = buckets.back_mut().expect("a bucket was just pushed");
+
+            let slot_lag = agg.pub_slot.saturating_sub(component.latest.pub_slot);
+            bucket.samples += 1;
+            if component.latest.status == PriceStatus::Trading
+                && slot_lag <= self.config.max_slot_lag
+            {
+                bucket.up += 1;
+            }
+            if slot_lag <= 1 {
+                bucket.hits += 1;
+            }
+            if component.agg.status == PriceStatus::Trading {
+                bucket.included += 1;
+                if agg.price != 0 {
+                    // Widened, as the difference of the prices may not fit an i64
+                    bucket.deviation_sum += (component.agg.price as i128 - agg.price as i128)
+                        .unsigned_abs() as f64
+                        / agg.price.unsigned_abs() as f64;
+                    bucket.deviation_samples += 1;
+                }
+            }
+        }
+    }
+
+    /// Start a new bucket, forgetting those older than the longest window, and
+    /// refresh the metrics
+    fn start_bucket(&mut self) {
+        self.bucket_started_at = Instant::now();
+
+        let max_window = self
+            .config
+            .windows
+            .iter()
+            .map(|window| **window)
+            .max()
+            .unwrap_or_default();
+        let bucket_duration = self.config.bucket_duration;
+        let now = self.bucket_started_at;
+        self.buckets.retain(|_, buckets| {
+            buckets.retain(|bucket| {
+                bucket.started_at.map_or(false, |started_at| {
+                    now.duration_since(started_at) < max_window + bucket_duration
+                })
+            });
+            !buckets.is_empty()
+        });
+
+        self.metrics.update(&self.report());
+    }
+
+    /// The statistics of all our components, by symbol
+    fn report(&self) -> Vec<PublisherPerformance> {
+        let snapshot = self.global_store_reader.load();
+        let now = Instant::now();
+        let mut report = self
+            .buckets
+            .iter()
+            .map(|((price_key, publisher), buckets)| PublisherPerformance {
+                symbol:        snapshot
+                    .account_metadata
+                    .symbol_index
+                    .symbol(price_key)
+                    .map(str::to_string),
+                price_account: price_key.to_string(),
+                publisher:     publisher.to_string(),
+                windows:       self
+                    .config
+                    .windows
+                    .iter()
+                    .map(|window| window_stats(buckets, **window, self.config.bucket_duration, now))
+                    .collect(),
+            })
+            .collect::<Vec<_>>();
+        report.sort_by(|a, b| {
+            (&a.symbol, &a.price_account, &a.publisher).cmp(&(
+                &b.symbol,
+                &b.price_account,
+                &b.publisher,
+            ))
+        });
+        report
+    }
+}
+
+/// The statistics of the buckets overlapping the window ending now
+fn window_stats(
+    buckets: &VecDeque<Bucket>,
+    window: Duration,
+    bucket_duration: Duration,
+    now: Instant,
+) -> WindowStats {
+    let mut total = Bucket::default();
+    for bucket in buckets.iter().filter(|bucket| {
+        bucket.started_at.map_or(false, |started_at| {
+            now.duration_since(started_at) < window + bucket_duration
+        })
+    }) {
+        total.samples += bucket.samples;
+        total.up += bucket.up;
+        total.hits += bucket.hits;
+        total.included += bucket.included;
+        total.deviation_sum += bucket.deviation_sum;
+        total.deviation_samples += bucket.deviation_samples;
+    }
+
+    let rate = |count: u64| (total.samples > 0).then(|| count as f64 / total.samples as f64);
+    WindowStats {
+        window:            humantime::format_duration(window).to_string(),
+        samples:           total.samples,
+        uptime:            rate(total.up),
+        slot_hit_rate:     rate(total.hits),
+        inclusion_rate:    rate(total.included),
+        average_deviation: (total.deviation_samples > 0)
+            .then(|| total.deviation_sum / total.deviation_samples as f64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            Config,
+            Tracker,
+        },
+        crate::agent::{
+            solana::oracle::PriceEntry,
+            store::global,
+        },
+        pyth_sdk_solana::state::PriceStatus,
+        solana_sdk::pubkey::Pubkey,
+        std::time::Duration,
+        tokio::sync::{
+            broadcast,
+            mpsc,
+        },
+    };
+
+    #[tokio::test]
+    async fn test_components_are_scored_per_aggregate() {
+        let publisher = Pubkey::new_unique();
+        let (_tx, rx) = mpsc::channel(1);
+        let (_events_tx, events_rx) = broadcast::channel(1);
+        let mut tracker = Tracker::new(
+            Config {
+                publisher_keys: vec![publisher.to_string()],
+                windows: vec![Duration::from_secs(60).into()],
+                ..Default::default()
+            },
+            rx,
+            events_rx,
+            global::SnapshotReader::default(),
+        )
+        .await;
+
+        let account_key = Pubkey::new_unique();
+        let aggregate = |slot, published_slot, included| {
+            let mut account = PriceEntry::default();
+            account.agg.pub_slot = slot;
+            account.agg.price = 100;
+            account.comp[0].publisher = publisher;
+            account.comp[0].latest.status = PriceStatus::Trading;
+            account.comp[0].latest.pub_slot = published_slot;
+            if included {
+                account.comp[0].agg.status = PriceStatus::Trading;
+                account.comp[0].agg.price = 102;
+            }
+            // Components of other publishers are not scored
+            account.comp[1].publisher = Pubkey::new_unique();
+            account
+        };
+
+        tracker.observe(account_key, &aggregate(10, 9, true));
+        // Repeated observations of the same aggregate are not new samples
+        tracker.observe(account_key, &aggregate(10, 9, true));
+        tracker.observe(account_key, &aggregate(20, 15, true));
+        tracker.observe(account_key, &aggregate(60, 15, false));
+        tracker.observe(account_key, &aggregate(61, 60, false));
+
+        let report = tracker.report();
+        assert_eq!(report.len(), 1);
+        assert_eq!(report[0].publisher, publisher.to_string());
+        let stats = &report[0].windows[0];
+        assert_eq!(stats.window, "1m");
+        assert_eq!(stats.samples, 4);
+        assert_eq!(stats.uptime, Some(0.75));
+        assert_eq!(stats.slot_hit_rate, Some(0.5));
+        assert_eq!(stats.inclusion_rate, Some(0.5));
+        assert_eq!(stats.average_deviation, Some(0.02));
+    }
+}
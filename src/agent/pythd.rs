@@ -1,2 +1,7 @@
 pub mod adapter;
+pub mod admission;
 pub mod api;
+pub mod compression;
+pub mod connections;
+pub mod exponent_check;
+pub mod wire_schema;
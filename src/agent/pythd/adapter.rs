@@ -10,23 +10,42 @@ use {
         },
         api::{
             self,
+            AggregatePreview,
             Conf,
             NotifyPrice,
             NotifyPriceSched,
             Price,
             PriceAccountMetadata,
+            PriceChanges,
             PriceUpdate,
             ProductAccount,
             ProductAccountMetadata,
             SubscriptionID,
         },
     },
-    crate::agent::store::global::AllAccountsData,
+    crate::agent::{
+        channel_monitor::ChannelMonitor,
+        error::{
+            self,
+            Error,
+        },
+        store::global::AllAccountsData,
+        telemetry,
+        update_status::{
+            Seq,
+            UpdateStatuses,
+        },
+    },
     anyhow::{
         anyhow,
+        Context as _,
         Result,
     },
     chrono::Utc,
+    opentelemetry::{
+        Context,
+        KeyValue,
+    },
     pyth_sdk::Identifier,
     pyth_sdk_solana::state::{
         PriceComp,
@@ -36,10 +55,12 @@ use {
         Deserialize,
         Serialize,
     },
-    slog::Logger,
     std::{
         collections::HashMap,
-        time::Duration,
+        time::{
+            Duration,
+            Instant,
+        },
     },
     tokio::{
         sync::{
@@ -53,10 +74,11 @@ use {
             Interval,
         },
     },
+    tracing::Instrument,
 };
 
-#[derive(Clone, Serialize, Deserialize, Debug)]
-#[serde(default)]
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
 pub struct Config {
     /// The duration of the interval at which `notify_price_sched` notifications
     /// will be sent.
@@ -88,20 +110,28 @@ pub struct Adapter {
     // Notify Price Subscriptions
     notify_price_subscriptions: HashMap<PriceIdentifier, Vec<NotifyPriceSubscription>>,
 
+    /// The previous update of each price, from which the changes sent to the
+    /// subscriptions with the price changes capability are computed
+    last_price_updates: HashMap<PriceIdentifier, PriceUpdate>,
+
     /// The fixed interval at which Notify Price Sched notifications are sent
     notify_price_sched_interval: Interval,
 
-    /// Channel on which to communicate with the global store
-    global_store_lookup_tx: mpsc::Sender<global::Lookup>,
+    /// Reader for the latest snapshot of the global store
+    global_store_reader: global::SnapshotReader,
 
     /// Channel on which to communicate with the local store
     local_store_tx: mpsc::Sender<local::Message>,
 
+    /// Statuses of the updates, which fail if they cannot be handed to the
+    /// local store
+    update_statuses: UpdateStatuses,
+
+    /// Monitors the backpressure of price updates sent to the local store
+    channel_monitor: ChannelMonitor,
+
     /// Channel on which the shutdown is broadcast
     shutdown_rx: broadcast::Receiver<()>,
-
-    /// The logger
-    logger: Logger,
 }
 
 /// Represents a single Notify Price Sched subscription
@@ -118,6 +148,8 @@ struct NotifyPriceSubscription {
     subscription_id: SubscriptionID,
     /// Channel notifications are sent on
     notify_price_tx: mpsc::Sender<NotifyPrice>,
+    /// Whether the notifications include the changes since the previous update
+    price_changes:   bool,
 }
 
 #[derive(Debug)]
@@ -133,6 +165,11 @@ pub enum Message {
     GetProductList {
         result_tx: oneshot::Sender<Result<Vec<ProductAccountMetadata>>>,
     },
+    GetProductMetadata {
+        /// Attributes the products must have, e.g. the symbol or asset type
+        attributes: api::Attrs,
+        result_tx:  oneshot::Sender<Result<Vec<ProductAccountMetadata>>>,
+    },
     GetProduct {
         account:   api::Pubkey,
         result_tx: oneshot::Sender<Result<ProductAccount>>,
@@ -140,9 +177,17 @@ pub enum Message {
     GetAllProducts {
         result_tx: oneshot::Sender<Result<Vec<ProductAccount>>>,
     },
+    GetAggregatePreview {
+        account:   api::Pubkey,
+        /// Publish key whose pending update is previewed
+        publisher: api::Pubkey,
+        result_tx: oneshot::Sender<Result<AggregatePreview>>,
+    },
     SubscribePrice {
         account:         api::Pubkey,
         notify_price_tx: mpsc::Sender<NotifyPrice>,
+        /// Whether the price changes capability was negotiated
+        price_changes:   bool,
         result_tx:       oneshot::Sender<Result<SubscriptionID>>,
     },
     SubscribePriceSched {
@@ -151,56 +196,121 @@ pub enum Message {
         result_tx:             oneshot::Sender<Result<SubscriptionID>>,
     },
     UpdatePrice {
-        account: api::Pubkey,
-        price:   Price,
-        conf:    Conf,
-        status:  String,
+        account:       api::Pubkey,
+        price:         Price,
+        conf:          Conf,
+        status:        String,
+        /// Publish key the update is submitted on behalf of, `None` for the default one
+        publisher:     Option<api::Pubkey>,
+        /// Sequence id the API assigned to the update
+        seq:           Seq,
+        /// When the update arrived at the API
+        received_at:   Instant,
+        trace_context: Context,
     },
 }
 
+/// The API metadata of the products having all the given attributes, read from
+/// the Global Store metadata
+pub fn get_product_metadata(
+    all_accounts_metadata: &global::AllAccountsMetadata,
+    attributes: &api::Attrs,
+) -> Vec<ProductAccountMetadata> {
+    all_accounts_metadata
+        .products_with_attributes(attributes)
+        .into_iter()
+        .map(|(product_account_key, product_account)| {
+            to_api_product_account_metadata(
+                all_accounts_metadata,
+                product_account_key,
+                product_account,
+            )
+        })
+        .collect()
+}
+
+fn to_api_product_account_metadata(
+    all_accounts_metadata: &global::AllAccountsMetadata,
+    product_account_key: &solana_sdk::pubkey::Pubkey,
+    product_account: &global::ProductAccountMetadata,
+) -> ProductAccountMetadata {
+    // Transform the price accounts into the API PriceAccountMetadata structs
+    // the API uses.
+    let price_accounts_metadata = product_account
+        .price_accounts
+        .iter()
+        .filter_map(|price_account_key| {
+            all_accounts_metadata
+                .price_accounts_metadata
+                .get(price_account_key)
+                .map(|acc| (price_account_key, acc))
+        })
+        .map(|(price_account_key, price_account)| PriceAccountMetadata {
+            account:        price_account_key.to_string(),
+            price_type:     "price".to_owned(),
+            price_exponent: price_account.expo as i64,
+        })
+        .collect();
+
+    // Create the product account metadata struct
+    ProductAccountMetadata {
+        account:   product_account_key.to_string(),
+        attr_dict: product_account.attr_dict.clone(),
+        price:     price_accounts_metadata,
+    }
+}
+
 pub fn spawn_adapter(
     config: Config,
     message_rx: mpsc::Receiver<Message>,
-    global_store_lookup_tx: mpsc::Sender<global::Lookup>,
+    global_store_reader: global::SnapshotReader,
     local_store_tx: mpsc::Sender<local::Message>,
+    update_statuses: UpdateStatuses,
+    channel_monitor: ChannelMonitor,
     shutdown_rx: broadcast::Receiver<()>,
-    logger: Logger,
 ) -> JoinHandle<()> {
-    tokio::spawn(async move {
-        Adapter::new(
-            config,
-            message_rx,
-            global_store_lookup_tx,
-            local_store_tx,
-            shutdown_rx,
-            logger,
-        )
-        .run()
-        .await
-    })
+    tokio::spawn(
+        async move {
+            Adapter::new(
+                config,
+                message_rx,
+                global_store_reader,
+                local_store_tx,
+                update_statuses,
+                channel_monitor,
+                shutdown_rx,
+            )
+            .run()
+            .await
+        }
+        .instrument(info_span!("pythd_adapter")),
+    )
 }
 
 impl Adapter {
     pub fn new(
         config: Config,
         message_rx: mpsc::Receiver<Message>,
-        global_store_lookup_tx: mpsc::Sender<global::Lookup>,
+        global_store_reader: global::SnapshotReader,
         local_store_tx: mpsc::Sender<local::Message>,
+        update_statuses: UpdateStatuses,
+        channel_monitor: ChannelMonitor,
         shutdown_rx: broadcast::Receiver<()>,
-        logger: Logger,
     ) -> Self {
         Adapter {
             message_rx,
             subscription_id_count: 0,
             notify_price_sched_subscriptions: HashMap::new(),
             notify_price_subscriptions: HashMap::new(),
+            last_price_updates: HashMap::new(),
             notify_price_sched_interval: time::interval(
                 config.notify_price_sched_interval_duration,
             ),
-            global_store_lookup_tx,
+            global_store_reader,
             local_store_tx,
+            update_statuses,
+            channel_monitor,
             shutdown_rx,
-            logger,
         }
     }
 
@@ -210,40 +320,70 @@ impl Adapter {
             tokio::select! {
                 Some(message) = self.message_rx.recv() => {
                     if let Err(err) = self.handle_message(message).await {
-                        error!(self.logger, "{:#}", err; "error" => format!("{:?}", err))
+                        error!(error = ?err, kind = %error::record("adapter", &err), "{:#}", err)
                     }
                 }
                 _ = self.shutdown_rx.recv() => {
-                    info!(self.logger, "shutdown signal received");
+                    info!("shutdown signal received");
+                    self.drain_messages().await;
                     return;
                 }
                 _ = self.notify_price_sched_interval.tick() => {
                     if let Err(err) = self.send_notify_price_sched().await {
-                        error!(self.logger, "{:#}", err; "error" => format!("{:?}", err))
+                        error!(error = ?err, kind = %error::record("adapter", &err), "{:#}", err)
                     }
                 }
             }
         }
     }
 
+    /// Stop receiving messages and handle the ones already queued, so that the
+    /// accepted updates reach the Local Store before it is persisted
+    async fn drain_messages(&mut self) {
+        self.message_rx.close();
+        while let Some(message) = self.message_rx.recv().await {
+            if let Err(err) = self.handle_message(message).await {
+                error!(error = ?err, kind = %error::record("adapter", &err), "{:#}", err)
+            }
+        }
+    }
+
     async fn handle_message(&mut self, message: Message) -> Result<()> {
         match message {
             Message::GetProductList { result_tx } => {
                 self.send(result_tx, self.handle_get_product_list().await)
             }
+            Message::GetProductMetadata {
+                attributes,
+                result_tx,
+            } => self.send(
+                result_tx,
+                self.handle_get_product_metadata(&attributes).await,
+            ),
             Message::GetProduct { account, result_tx } => {
                 self.send(result_tx, self.handle_get_product(&account.parse()?).await)
             }
             Message::GetAllProducts { result_tx } => {
                 self.send(result_tx, self.handle_get_all_products().await)
             }
+            Message::GetAggregatePreview {
+                account,
+                publisher,
+                result_tx,
+            } => {
+                let preview = self
+                    .handle_get_aggregate_preview(&account.parse()?, &publisher.parse()?)
+                    .await;
+                self.send(result_tx, preview)
+            }
             Message::SubscribePrice {
                 account,
                 notify_price_tx,
+                price_changes,
                 result_tx,
             } => {
                 let subscription_id = self
-                    .handle_subscribe_price(&account.parse()?, notify_price_tx)
+                    .handle_subscribe_price(&account.parse()?, notify_price_tx, price_changes)
                     .await;
                 self.send(result_tx, Ok(subscription_id))
             }
@@ -263,9 +403,33 @@ impl Adapter {
                 price,
                 conf,
                 status,
+                publisher,
+                seq,
+                received_at,
+                trace_context,
             } => {
-                self.handle_update_price(&account.parse()?, price, conf, status)
-                    .await
+                let trace_context = telemetry::start_span(
+                    &trace_context,
+                    "adapter.update_price",
+                    vec![KeyValue::new("price_account", account.clone())],
+                );
+                let result = self
+                    .handle_update_price(
+                        account,
+                        price,
+                        conf,
+                        status,
+                        publisher,
+                        seq,
+                        received_at,
+                        &trace_context,
+                    )
+                    .await;
+                telemetry::end_span(&trace_context, &result);
+                if let Err(err) = &result {
+                    self.update_statuses.reject(seq, format!("{:#}", err));
+                }
+                result
             }
             Message::GlobalStoreUpdate {
                 price_identifier,
@@ -293,58 +457,41 @@ impl Adapter {
     }
 
     async fn handle_get_product_list(&self) -> Result<Vec<ProductAccountMetadata>> {
-        let all_accounts_metadata = self.lookup_all_accounts_metadata().await?;
-
-        let mut result = Vec::new();
-        for (product_account_key, product_account) in
-            all_accounts_metadata.product_accounts_metadata
-        {
-            // Transform the price accounts into the API PriceAccountMetadata structs
-            // the API uses.
-            let price_accounts_metadata = product_account
-                .price_accounts
-                .iter()
-                .filter_map(|price_account_key| {
-                    all_accounts_metadata
-                        .price_accounts_metadata
-                        .get(price_account_key)
-                        .map(|acc| (price_account_key, acc))
-                })
-                .map(|(price_account_key, price_account)| PriceAccountMetadata {
-                    account:        price_account_key.to_string(),
-                    price_type:     "price".to_owned(),
-                    price_exponent: price_account.expo as i64,
-                })
-                .collect();
+        let snapshot = self.global_store_reader.load();
+        let all_accounts_metadata = &snapshot.account_metadata;
 
-            // Create the product account metadata struct
-            result.push(ProductAccountMetadata {
-                account:   product_account_key.to_string(),
-                attr_dict: product_account.attr_dict,
-                price:     price_accounts_metadata,
+        Ok(all_accounts_metadata
+            .product_accounts_metadata
+            .iter()
+            .map(|(product_account_key, product_account)| {
+                to_api_product_account_metadata(
+                    all_accounts_metadata,
+                    product_account_key,
+                    product_account,
+                )
             })
-        }
-
-        Ok(result)
+            .collect())
     }
 
-    // Fetches the Solana-specific Oracle data from the global store
-    async fn lookup_all_accounts_metadata(&self) -> Result<global::AllAccountsMetadata> {
-        let (result_tx, result_rx) = oneshot::channel();
-        self.global_store_lookup_tx
-            .send(global::Lookup::LookupAllAccountsMetadata { result_tx })
-            .await?;
-        result_rx.await?
+    async fn handle_get_product_metadata(
+        &self,
+        attributes: &api::Attrs,
+    ) -> Result<Vec<ProductAccountMetadata>> {
+        Ok(get_product_metadata(
+            &self.global_store_reader.load().account_metadata,
+            attributes,
+        ))
     }
 
     async fn handle_get_all_products(&self) -> Result<Vec<ProductAccount>> {
-        let solana_data = self.lookup_all_accounts_data().await?;
+        let snapshot = self.global_store_reader.load();
+        let solana_data = &snapshot.account_data;
 
         let mut result = Vec::new();
         for (product_account_key, product_account) in &solana_data.product_accounts {
             let product_account_api = Self::solana_product_account_to_pythd_api_product_account(
                 product_account,
-                &solana_data,
+                solana_data,
                 product_account_key,
             );
 
@@ -354,14 +501,6 @@ impl Adapter {
         Ok(result)
     }
 
-    async fn lookup_all_accounts_data(&self) -> Result<AllAccountsData> {
-        let (result_tx, result_rx) = oneshot::channel();
-        self.global_store_lookup_tx
-            .send(global::Lookup::LookupAllAccountsData { result_tx })
-            .await?;
-        result_rx.await?
-    }
-
     fn solana_product_account_to_pythd_api_product_account(
         product_account: &solana::oracle::ProductEntry,
         all_accounts_data: &AllAccountsData,
@@ -432,7 +571,7 @@ impl Adapter {
     }
 
     // TODO: implement Display on PriceStatus and then just call PriceStatus::to_string
-    fn price_status_to_str(price_status: PriceStatus) -> String {
+    pub fn price_status_to_str(price_status: PriceStatus) -> String {
         match price_status {
             PriceStatus::Unknown => "unknown",
             PriceStatus::Trading => "trading",
@@ -447,21 +586,63 @@ impl Adapter {
         &self,
         product_account_key: &solana_sdk::pubkey::Pubkey,
     ) -> Result<ProductAccount> {
-        let all_accounts_data = self.lookup_all_accounts_data().await?;
+        let snapshot = self.global_store_reader.load();
+        let all_accounts_data = &snapshot.account_data;
 
         // Look up the product account
         let product_account = all_accounts_data
             .product_accounts
             .get(product_account_key)
-            .ok_or_else(|| anyhow!("product account not found"))?;
+            .ok_or_else(|| Error::Store("product account not found".to_string()))?;
 
         Ok(Self::solana_product_account_to_pythd_api_product_account(
             product_account,
-            &all_accounts_data,
+            all_accounts_data,
             product_account_key,
         ))
     }
 
+    async fn handle_get_aggregate_preview(
+        &self,
+        account: &solana_sdk::pubkey::Pubkey,
+        publisher: &solana_sdk::pubkey::Pubkey,
+    ) -> Result<AggregatePreview> {
+        let snapshot = self.global_store_reader.load();
+        let price_account = snapshot
+            .account_data
+            .price_accounts
+            .get(account)
+            .ok_or_else(|| Error::Store("price account not found".to_string()))?;
+
+        let (result_tx, result_rx) = oneshot::channel();
+        self.local_store_tx
+            .send(local::Message::LookupAllPriceInfo { result_tx })
+            .await
+            .map_err(|_| Error::ChannelClosed("local store"))?;
+        let mut all_price_info = result_rx.await.context("looking up the local store")?;
+
+        // Updates submitted without a publish key are published with the
+        // default one, whose key is not known here
+        let price_identifier = Identifier::new(account.to_bytes());
+        let pending_update = [Some(*publisher), None]
+            .iter()
+            .find_map(|key| all_price_info.get_mut(key)?.remove(&price_identifier));
+
+        let preview = global::preview_aggregate(price_account, publisher, pending_update.as_ref());
+        Ok(AggregatePreview {
+            account:        account.to_string(),
+            publisher:      publisher.to_string(),
+            pending_update: pending_update.is_some(),
+            price:          preview.price,
+            conf:           preview.conf,
+            status:         Self::price_status_to_str(preview.status),
+            num_publishers: preview.num_publishers as u64,
+            current_price:  price_account.agg.price,
+            current_conf:   price_account.agg.conf,
+            current_status: Self::price_status_to_str(price_account.agg.status),
+        })
+    }
+
     async fn handle_subscribe_price_sched(
         &mut self,
         account_pubkey: &solana_sdk::pubkey::Pubkey,
@@ -487,6 +668,7 @@ impl Adapter {
         &mut self,
         account: &solana_sdk::pubkey::Pubkey,
         notify_price_tx: mpsc::Sender<NotifyPrice>,
+        price_changes: bool,
     ) -> SubscriptionID {
         let subscription_id = self.next_subscription_id();
         self.notify_price_subscriptions
@@ -495,6 +677,7 @@ impl Adapter {
             .push(NotifyPriceSubscription {
                 subscription_id,
                 notify_price_tx,
+                price_changes,
             });
         subscription_id
     }
@@ -524,39 +707,54 @@ impl Adapter {
 
     async fn handle_update_price(
         &self,
-        account: &solana_sdk::pubkey::Pubkey,
+        account: api::Pubkey,
         price: Price,
         conf: Conf,
         status: String,
+        publisher: Option<api::Pubkey>,
+        seq: Seq,
+        received_at: Instant,
+        trace_context: &Context,
     ) -> Result<()> {
-        self.local_store_tx
-            .send(local::Message::Update {
-                price_identifier: pyth_sdk::Identifier::new(account.to_bytes()),
-                price_info:       local::PriceInfo {
-                    status: Adapter::map_status(&status)?,
-                    price,
-                    conf,
-                    timestamp: Utc::now().timestamp(),
-                },
-            })
+        let account = account.parse::<solana_sdk::pubkey::Pubkey>()?;
+        let publisher = publisher
+            .map(|key| key.parse::<solana_sdk::pubkey::Pubkey>())
+            .transpose()?;
+        let update = local::Message::Update {
+            publisher,
+            price_identifier: pyth_sdk::Identifier::new(account.to_bytes()),
+            price_info: local::PriceInfo {
+                status: Adapter::map_status(&status)?,
+                price,
+                conf,
+                timestamp: Utc::now().timestamp(),
+            },
+            seq: Some(seq),
+            received_at,
+            replicated: false,
+            trace_context: trace_context.clone(),
+        };
+        self.channel_monitor
+            .send("local_store", &self.local_store_tx, update)
             .await
-            .map_err(|_| anyhow!("failed to send update to local store"))
+            .map_err(|_| Error::ChannelClosed("local store"))
+            .context("failed to send update to local store")
     }
 
     // TODO: implement FromStr method on PriceStatus
-    fn map_status(status: &str) -> Result<PriceStatus> {
+    pub fn map_status(status: &str) -> Result<PriceStatus> {
         match status {
             "unknown" => Ok(PriceStatus::Unknown),
             "trading" => Ok(PriceStatus::Trading),
             "halted" => Ok(PriceStatus::Halted),
             "auction" => Ok(PriceStatus::Auction),
             "ignored" => Ok(PriceStatus::Ignored),
-            _ => Err(anyhow!("invalid price status: {:#?}", status)),
+            _ => Err(Error::Api(format!("invalid price status: {:#?}", status)).into()),
         }
     }
 
     async fn handle_global_store_update(
-        &self,
+        &mut self,
         price_identifier: PriceIdentifier,
         price: i64,
         conf: u64,
@@ -564,6 +762,18 @@ impl Adapter {
         valid_slot: u64,
         pub_slot: u64,
     ) -> Result<()> {
+        let update = PriceUpdate {
+            price,
+            conf,
+            status: Self::price_status_to_str(status),
+            valid_slot,
+            pub_slot,
+            changes: None,
+        };
+        let changes = Self::price_changes(self.last_price_updates.get(&price_identifier), &update);
+        self.last_price_updates
+            .insert(price_identifier, update.clone());
+
         // Look up any subcriptions associated with the price identifier
         let empty = Vec::new();
         let subscriptions = self
@@ -578,11 +788,8 @@ impl Adapter {
                 .send(NotifyPrice {
                     subscription: subscription.subscription_id,
                     result:       PriceUpdate {
-                        price,
-                        conf,
-                        status: Self::price_status_to_str(status),
-                        valid_slot,
-                        pub_slot,
+                        changes: subscription.price_changes.then(|| changes.clone()),
+                        ..update.clone()
                     },
                 })
                 .await?;
@@ -590,6 +797,25 @@ impl Adapter {
 
         Ok(())
     }
+
+    /// The changes of the update since the previous update of the same price
+    fn price_changes(previous: Option<&PriceUpdate>, update: &PriceUpdate) -> PriceChanges {
+        match previous {
+            Some(previous) => PriceChanges {
+                prev_price: Some(previous.price),
+                prev_conf:  Some(previous.conf),
+                slot_delta: Some(update.pub_slot.saturating_sub(previous.pub_slot)),
+                changed:    (previous.price, previous.conf, &previous.status)
+                    != (update.price, update.conf, &update.status),
+            },
+            None => PriceChanges {
+                prev_price: None,
+                prev_conf:  None,
+                slot_delta: None,
+                changed:    true,
+            },
+        }
+    }
 }
 
 #[cfg(test)]
@@ -607,6 +833,7 @@ mod tests {
                     NotifyPrice,
                     NotifyPriceSched,
                     PriceAccountMetadata,
+                    PriceChanges,
                     PriceUpdate,
                     ProductAccount,
                     ProductAccountMetadata,
@@ -620,7 +847,7 @@ mod tests {
                 local,
             },
         },
-        iobuffer::IoBuffer,
+        opentelemetry::Context,
         pyth_sdk::Identifier,
         pyth_sdk_solana::state::{
             PriceAccount,
@@ -630,13 +857,13 @@ mod tests {
             PriceType,
             Rational,
         },
-        slog_extlog::slog_test,
         std::{
             collections::{
                 BTreeMap,
                 HashMap,
             },
             str::FromStr,
+            sync::Arc,
             time::Duration,
         },
         tokio::{
@@ -650,11 +877,10 @@ mod tests {
     };
 
     struct TestAdapter {
-        message_tx:             mpsc::Sender<Message>,
-        shutdown_tx:            broadcast::Sender<()>,
-        global_store_lookup_rx: mpsc::Receiver<global::Lookup>,
-        local_store_rx:         mpsc::Receiver<local::Message>,
-        jh:                     JoinHandle<()>,
+        message_tx:     mpsc::Sender<Message>,
+        shutdown_tx:    broadcast::Sender<()>,
+        local_store_rx: mpsc::Receiver<local::Message>,
+        jh:             JoinHandle<()>,
     }
 
     impl Drop for TestAdapter {
@@ -665,12 +891,15 @@ mod tests {
     }
 
     async fn setup() -> TestAdapter {
+        setup_with_global_store(Default::default()).await
+    }
+
+    async fn setup_with_global_store(global_store_snapshot: global::Snapshot) -> TestAdapter {
         // Create and spawn an adapter
         let (adapter_tx, adapter_rx) = mpsc::channel(100);
-        let (global_store_lookup_tx, global_store_lookup_rx) = mpsc::channel(1000);
+        let global_store_reader = global::SnapshotReader::new(global_store_snapshot);
         let (local_store_tx, local_store_rx) = mpsc::channel(1000);
         let notify_price_sched_interval_duration = Duration::from_nanos(10);
-        let logger = slog_test::new_test_logger(IoBuffer::new());
         let (shutdown_tx, shutdown_rx) = broadcast::channel(10);
         let config = Config {
             notify_price_sched_interval_duration,
@@ -678,16 +907,16 @@ mod tests {
         let mut adapter = Adapter::new(
             config,
             adapter_rx,
-            global_store_lookup_tx,
+            global_store_reader,
             local_store_tx,
+            Default::default(),
+            ChannelMonitor::new(Default::default()).await,
             shutdown_rx,
-            logger,
         );
         let jh = tokio::spawn(async move { adapter.run().await });
 
         TestAdapter {
             message_tx: adapter_tx,
-            global_store_lookup_rx,
             local_store_rx,
             shutdown_tx,
             jh,
@@ -726,7 +955,7 @@ mod tests {
     }
 
     fn get_test_all_accounts_metadata() -> global::AllAccountsMetadata {
-        global::AllAccountsMetadata {
+        let metadata = global::AllAccountsMetadata {
             product_accounts_metadata: HashMap::from([
                 (
                     solana_sdk::pubkey::Pubkey::from_str(
@@ -839,13 +1068,95 @@ mod tests {
                     global::PriceAccountMetadata { expo: 2 },
                 ),
             ]),
+            symbol_index:              Default::default(),
+        };
+
+        // Index the symbols of the products, as the Global Store does
+        let mut indexed_metadata = global::AllAccountsMetadata {
+            price_accounts_metadata: metadata.price_accounts_metadata,
+            ..Default::default()
+        };
+        for (product_key, product_metadata) in metadata.product_accounts_metadata {
+            indexed_metadata.insert_product(product_key, product_metadata);
         }
+        indexed_metadata
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_get_product_metadata() {
+        let test_adapter = setup_with_global_store(global::Snapshot {
+            account_metadata: get_test_all_accounts_metadata(),
+            ..Default::default()
+        })
+        .await;
+        let get_product_metadata = |attributes: &[(&str, &str)]| {
+            let message_tx = test_adapter.message_tx.clone();
+            let attributes = attributes
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect();
+            async move {
+                let (result_tx, result_rx) = oneshot::channel();
+                message_tx
+                    .send(Message::GetProductMetadata {
+                        attributes,
+                        result_tx,
+                    })
+                    .await
+                    .unwrap();
+                let mut result = result_rx.await.unwrap().unwrap();
+                result.sort();
+                result
+            }
+        };
+        let accounts = |products: Vec<ProductAccountMetadata>| {
+            products
+                .into_iter()
+                .map(|product| product.account)
+                .collect::<Vec<_>>()
+        };
+
+        // A single symbol is resolved with its price accounts
+        let result = get_product_metadata(&[("symbol", "Crypto.LTC/USD")]).await;
+        assert_eq!(
+            accounts(result.clone()),
+            vec!["CkMrDWtmFJZcmAUC11qNaWymbXQKvnRx4cq1QudLav7t".to_string()]
+        );
+        assert_eq!(result[0].price.len(), 3);
+
+        // Products must have all the requested attributes
+        assert_eq!(
+            accounts(
+                get_product_metadata(&[("asset_type", "Crypto"), ("quote_currency", "USD")]).await
+            ),
+            vec![
+                "BjHoZWRxo9dgbR1NQhPyTiUs6xFiX6mGS4TMYvy3b2yc".to_string(),
+                "CkMrDWtmFJZcmAUC11qNaWymbXQKvnRx4cq1QudLav7t".to_string(),
+            ]
+        );
+        assert_eq!(
+            accounts(get_product_metadata(&[("symbol", "Crypto.ETH/USD"), ("base", "ETH")]).await),
+            vec!["BjHoZWRxo9dgbR1NQhPyTiUs6xFiX6mGS4TMYvy3b2yc".to_string()]
+        );
+        assert!(
+            get_product_metadata(&[("symbol", "Crypto.ETH/USD"), ("base", "LTC")])
+                .await
+                .is_empty()
+        );
+        assert!(get_product_metadata(&[("country", "US")]).await.is_empty());
+        assert!(get_product_metadata(&[("symbol", "Equity.US.AAPL/USD")])
+            .await
+            .is_empty());
     }
 
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
     async fn test_get_product_list() {
-        // Start the test adapter
-        let mut test_adapter = setup().await;
+        // Start the test adapter, with the product list in the global store
+        let test_adapter = setup_with_global_store(global::Snapshot {
+            account_metadata: get_test_all_accounts_metadata(),
+            ..Default::default()
+        })
+        .await;
 
         // Send a Get Product List message
         let (result_tx, result_rx) = oneshot::channel();
@@ -855,14 +1166,6 @@ mod tests {
             .await
             .unwrap();
 
-        // Return the product list to the adapter, from the global store
-        match test_adapter.global_store_lookup_rx.recv().await.unwrap() {
-            global::Lookup::LookupAllAccountsMetadata { result_tx } => result_tx
-                .send(Ok(get_test_all_accounts_metadata()))
-                .unwrap(),
-            _ => panic!("Uexpected message received from adapter"),
-        };
-
         // Check that the result is what we expected
         let expected = vec![
             ProductAccountMetadata {
@@ -1067,7 +1370,7 @@ mod tests {
                         "GVXRSBjFk6e6J3NbVPXohDJetcTjaeeuykUpbQF8UoMU",
                     )
                     .unwrap(),
-                    PriceAccount {
+                    Arc::new(PriceAccount {
                         magic:          0xa1b2c3d4,
                         ver:            7,
                         atype:          9,
@@ -1113,14 +1416,14 @@ mod tests {
                             pub_slot: 7262746,
                         },
                         comp:           [PriceComp::default(); 32],
-                    },
+                    }),
                 ),
                 (
                     solana_sdk::pubkey::Pubkey::from_str(
                         "3VQwtcntVQN1mj1MybQw8qK7Li3KNrrgNskSQwZAPGNr",
                     )
                     .unwrap(),
-                    PriceAccount {
+                    Arc::new(PriceAccount {
                         magic:          0xa1b2c3d4,
                         ver:            6,
                         atype:          4,
@@ -1185,14 +1488,14 @@ mod tests {
                                 pub_slot: 4368,
                             },
                         }]),
-                    },
+                    }),
                 ),
                 (
                     solana_sdk::pubkey::Pubkey::from_str(
                         "2V7t5NaKY7aGkwytCWQgvUYZfEr9XMwNChhJEakTExk6",
                     )
                     .unwrap(),
-                    PriceAccount {
+                    Arc::new(PriceAccount {
                         magic:          0xa1b2c3d4,
                         ver:            7,
                         atype:          6,
@@ -1279,14 +1582,14 @@ mod tests {
                                 },
                             },
                         ]),
-                    },
+                    }),
                 ),
                 (
                     solana_sdk::pubkey::Pubkey::from_str(
                         "GG3FTE7xhc9Diy7dn9P6BWzoCrAEE4D3p5NBYrDAm5DD",
                     )
                     .unwrap(),
-                    PriceAccount {
+                    Arc::new(PriceAccount {
                         magic:          0xa1b2c3d4,
                         ver:            6,
                         atype:          6,
@@ -1370,14 +1673,14 @@ mod tests {
                                 },
                             },
                         ]),
-                    },
+                    }),
                 ),
                 (
                     solana_sdk::pubkey::Pubkey::from_str(
                         "fTNjSfj5uW9e4CAMHzUcm65ftRNBxCN1gG5GS1mYfid",
                     )
                     .unwrap(),
-                    PriceAccount {
+                    Arc::new(PriceAccount {
                         magic:          0xa1b2c3d4,
                         ver:            8,
                         atype:          4,
@@ -1464,14 +1767,14 @@ mod tests {
                                 },
                             },
                         ]),
-                    },
+                    }),
                 ),
                 (
                     solana_sdk::pubkey::Pubkey::from_str(
                         "GKNcUmNacSJo4S2Kq3DuYRYRGw3sNUfJ4tyqd198t6vQ",
                     )
                     .unwrap(),
-                    PriceAccount {
+                    Arc::new(PriceAccount {
                         magic:          0xa1b2c3d4,
                         ver:            6,
                         atype:          3,
@@ -1533,7 +1836,7 @@ mod tests {
                                 pub_slot: 7101326,
                             },
                         }]),
-                    },
+                    }),
                 ),
             ]),
         }
@@ -1541,8 +1844,12 @@ mod tests {
 
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
     async fn test_get_all_products() {
-        // Start the test adapter
-        let mut test_adapter = setup().await;
+        // Start the test adapter, with the account data in the global store
+        let test_adapter = setup_with_global_store(global::Snapshot {
+            account_data: get_all_accounts_data(),
+            ..Default::default()
+        })
+        .await;
 
         // Send a Get All Products message
         let (result_tx, result_rx) = oneshot::channel();
@@ -1552,14 +1859,6 @@ mod tests {
             .await
             .unwrap();
 
-        // Return the account data to the adapter, from the global store
-        match test_adapter.global_store_lookup_rx.recv().await.unwrap() {
-            global::Lookup::LookupAllAccountsData { result_tx } => {
-                result_tx.send(Ok(get_all_accounts_data())).unwrap()
-            }
-            _ => panic!("Uexpected message received from adapter"),
-        };
-
         // Check that the result of the conversion to the Pythd API format is what we expected
         let expected = vec![
             api::ProductAccount {
@@ -1762,8 +2061,12 @@ mod tests {
 
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
     async fn test_get_product() {
-        // Start the test adapter
-        let mut test_adapter = setup().await;
+        // Start the test adapter, with the account data in the global store
+        let test_adapter = setup_with_global_store(global::Snapshot {
+            account_data: get_all_accounts_data(),
+            ..Default::default()
+        })
+        .await;
 
         // Send a Get Product message
         let account = "CkMrDWtmFJZcmAUC11qNaWymbXQKvnRx4cq1QudLav7t".to_string();
@@ -1777,14 +2080,6 @@ mod tests {
             .await
             .unwrap();
 
-        // Return the account data to the adapter, from the global store
-        match test_adapter.global_store_lookup_rx.recv().await.unwrap() {
-            global::Lookup::LookupAllAccountsData { result_tx } => {
-                result_tx.send(Ok(get_all_accounts_data())).unwrap()
-            }
-            _ => panic!("Uexpected message received from adapter"),
-        };
-
         // Check that the result of the conversion to the Pythd API format is what we expected
         let expected = ProductAccount {
             account,
@@ -1892,6 +2187,10 @@ mod tests {
                 price,
                 conf,
                 status: "trading".to_string(),
+                publisher: None,
+                seq: 1,
+                received_at: Instant::now(),
+                trace_context: Context::new(),
             })
             .await
             .unwrap();
@@ -1899,9 +2198,12 @@ mod tests {
         // Check that the local store indeed received the correct update
         match test_adapter.local_store_rx.recv().await.unwrap() {
             local::Message::Update {
+                publisher,
                 price_identifier,
                 price_info,
+                ..
             } => {
+                assert_eq!(publisher, None);
                 assert_eq!(
                     price_identifier,
                     Identifier::new(
@@ -1933,6 +2235,7 @@ mod tests {
             .send(Message::SubscribePrice {
                 account: account.clone(),
                 notify_price_tx,
+                price_changes: false,
                 result_tx,
             })
             .await
@@ -1974,9 +2277,82 @@ mod tests {
                     conf,
                     status: "trading".to_string(),
                     valid_slot,
-                    pub_slot
+                    pub_slot,
+                    changes: None,
                 },
             }
         )
     }
+
+    #[tokio::test]
+    async fn test_subscribe_notify_price_changes() {
+        // Start the test adapter
+        let test_adapter = setup().await;
+
+        // Subscribe to the price with the price changes capability
+        let account = "2wrWGm63xWubz7ue4iYR3qvBbaUJhZVi4eSpNuU8k8iF".to_string();
+        let (notify_price_tx, mut notify_price_rx) = mpsc::channel(1000);
+        let (result_tx, result_rx) = oneshot::channel();
+        test_adapter
+            .message_tx
+            .send(Message::SubscribePrice {
+                account: account.clone(),
+                notify_price_tx,
+                price_changes: true,
+                result_tx,
+            })
+            .await
+            .unwrap();
+        result_rx.await.unwrap().unwrap();
+
+        let price_identifier = Identifier::new(
+            account
+                .parse::<solana_sdk::pubkey::Pubkey>()
+                .unwrap()
+                .to_bytes(),
+        );
+        let update = |price, pub_slot| Message::GlobalStoreUpdate {
+            price_identifier,
+            price,
+            conf: 10,
+            status: PriceStatus::Trading,
+            valid_slot: pub_slot,
+            pub_slot,
+        };
+
+        // The first update has no previous value
+        test_adapter.message_tx.send(update(100, 5)).await.unwrap();
+        assert_eq!(
+            notify_price_rx.recv().await.unwrap().result.changes,
+            Some(PriceChanges {
+                prev_price: None,
+                prev_conf:  None,
+                slot_delta: None,
+                changed:    true,
+            })
+        );
+
+        // Repeated values are flagged as unchanged
+        test_adapter.message_tx.send(update(100, 7)).await.unwrap();
+        assert_eq!(
+            notify_price_rx.recv().await.unwrap().result.changes,
+            Some(PriceChanges {
+                prev_price: Some(100),
+                prev_conf:  Some(10),
+                slot_delta: Some(2),
+                changed:    false,
+            })
+        );
+
+        test_adapter.message_tx.send(update(101, 8)).await.unwrap();
+        assert_eq!(
+            notify_price_rx.recv().await.unwrap().result.changes,
+            Some(PriceChanges {
+                prev_price: Some(100),
+                prev_conf:  Some(10),
+                slot_delta: Some(1),
+                changed:    true,
+            })
+        );
+    }
 }
@@ -0,0 +1,185 @@
+// The pythd API listener can be hardened beyond the firewall in front of it. Connections
+// from addresses outside of the allowed networks, and connections beyond the maximum
+// number of concurrent ones, are rejected as they connect, before their websocket
+// handshake, with 403 and 503 respectively. The rejections are counted by reason in the
+// api_rejected_connection_count metric, and the admitted connections still open in the
+// api_open_connections gauge. The stdio connection is always admitted.
+use {
+    crate::agent::metrics::API_METRICS,
+    ipnet::IpNet,
+    serde::{
+        Deserialize,
+        Serialize,
+    },
+    std::{
+        net::{
+            IpAddr,
+            SocketAddr,
+        },
+        sync::Arc,
+    },
+    tokio::sync::{
+        OwnedSemaphorePermit,
+        Semaphore,
+    },
+};
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Networks the connections are accepted from, in CIDR notation, e.g.
+    /// "10.0.0.0/8". Connections from any address are accepted when empty.
+    pub allowed_networks: Vec<IpNet>,
+    /// Maximum number of concurrent websocket connections. Unlimited when not
+    /// set.
+    pub max_connections:  Option<usize>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            allowed_networks: vec![],
+            max_connections:  None,
+        }
+    }
+}
+
+/// Why a connection was rejected
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rejection {
+    /// The remote address is outside of the allowed networks
+    NotAllowed,
+    /// The maximum number of concurrent connections is reached
+    ConnectionLimit,
+}
+
+impl Rejection {
+    /// Label of the rejection in the metrics
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Rejection::NotAllowed => "not_allowed",
+            Rejection::ConnectionLimit => "connection_limit",
+        }
+    }
+}
+
+/// Decides which connections the listener admits
+#[derive(Clone)]
+pub struct Admission {
+    allowed_networks:   Vec<IpNet>,
+    /// A permit is held by each open connection, None when unlimited
+    connection_permits: Option<Arc<Semaphore>>,
+}
+
+impl Admission {
+    pub fn new(config: &Config) -> Self {
+        Admission {
+            allowed_networks:   config.allowed_networks.clone(),
+            connection_permits: config
+                .max_connections
+                .map(|max_connections| Arc::new(Semaphore::new(max_connections))),
+        }
+    }
+
+    /// Admit a connection from the remote address, returning the guard which
+    /// it holds until it closes
+    pub fn admit(
+        &self,
+        remote_address: Option<SocketAddr>,
+    ) -> Result<AdmittedConnection, Rejection> {
+        let result = self.check(remote_address);
+        match &result {
+            Ok(_) => API_METRICS.connection_opened(),
+            Err(rejection) => API_METRICS.record_rejected_connection(rejection.reason()),
+        }
+        result
+    }
+
+    fn check(&self, remote_address: Option<SocketAddr>) -> Result<AdmittedConnection, Rejection> {
+        if !self.allowed_networks.is_empty() {
+            let ip = remote_address
+                .map(|address| canonical_ip(address.ip()))
+                .ok_or(Rejection::NotAllowed)?;
+            if !self
+                .allowed_networks
+                .iter()
+                .any(|network| network.contains(&ip))
+            {
+                return Err(Rejection::NotAllowed);
+            }
+        }
+
+        let permit = match &self.connection_permits {
+            Some(connection_permits) => Some(
+                connection_permits
+                    .clone()
+                    .try_acquire_owned()
+                    .map_err(|_| Rejection::ConnectionLimit)?,
+            ),
+            None => None,
+        };
+        Ok(AdmittedConnection { _permit: permit })
+    }
+}
+
+/// The IPv4 address of an IPv4-mapped IPv6 address, as IPv4 clients of a
+/// listener bound to an IPv6 address connect from
+fn canonical_ip(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(ipv6) => ipv6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+        IpAddr::V4(_) => ip,
+    }
+}
+
+/// Held by an admitted connection until it closes
+pub struct AdmittedConnection {
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl Drop for AdmittedConnection {
+    fn drop(&mut self) {
+        API_METRICS.connection_closed();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Admission,
+        Config,
+        Rejection,
+    };
+
+    #[test]
+    fn test_connections_are_admitted_from_the_allowed_networks_up_to_the_limit() {
+        let admission = Admission::new(&Config {
+            allowed_networks: vec!["10.0.0.0/8".parse().unwrap()],
+            max_connections:  Some(1),
+        });
+
+        assert_eq!(
+            admission
+                .admit(Some("192.168.1.1:1234".parse().unwrap()))
+                .err(),
+            Some(Rejection::NotAllowed)
+        );
+        assert_eq!(admission.admit(None).err(), Some(Rejection::NotAllowed));
+
+        // IPv4 clients of an IPv6 listener connect from IPv4-mapped addresses
+        let admitted = admission
+            .admit(Some("[::ffff:10.1.2.3]:1234".parse().unwrap()))
+            .unwrap();
+        assert_eq!(
+            admission
+                .admit(Some("10.1.2.4:1234".parse().unwrap()))
+                .err(),
+            Some(Rejection::ConnectionLimit)
+        );
+
+        // The slot of a connection is freed once it closes
+        drop(admitted);
+        assert!(admission
+            .admit(Some("10.1.2.4:1234".parse().unwrap()))
+            .is_ok());
+    }
+}
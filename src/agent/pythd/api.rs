@@ -68,6 +68,25 @@ pub struct PublisherAccount {
     pub slot:    Slot,
 }
 
+/// The next aggregate of a price account expected once the pending update of a
+/// publisher lands, next to its current aggregate
+#[derive(Serialize, Deserialize, Debug, Clone, Ord, PartialOrd, PartialEq, Eq)]
+pub struct AggregatePreview {
+    pub account:        Pubkey,
+    pub publisher:      Pubkey,
+    /// Whether the publisher has an update pending in the agent. Without one,
+    /// the latest components are aggregated as they are.
+    pub pending_update: bool,
+    pub price:          Price,
+    pub conf:           Conf,
+    pub status:         String,
+    /// Number of publishers whose components are expected to be aggregated
+    pub num_publishers: u64,
+    pub current_price:  Price,
+    pub current_conf:   Conf,
+    pub current_status: String,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Ord, PartialOrd, PartialEq, Eq)]
 pub struct NotifyPrice {
     pub subscription: SubscriptionID,
@@ -81,6 +100,10 @@ pub struct NotifyPriceSched {
 
 pub type SubscriptionID = i64;
 
+/// Capability of `subscribe_price` with which the notifications include the
+/// changes since the previous price update
+pub const PRICE_CHANGES_CAPABILITY: &str = "price_changes";
+
 #[derive(Serialize, Deserialize, Debug, Clone, Ord, PartialOrd, PartialEq, Eq)]
 pub struct PriceUpdate {
     pub price:      Price,
@@ -88,29 +111,93 @@ pub struct PriceUpdate {
     pub status:     String,
     pub valid_slot: Slot,
     pub pub_slot:   Slot,
+    /// Only set for subscriptions which negotiated the price changes capability
+    #[serde(flatten)]
+    pub changes:    Option<PriceChanges>,
+}
+
+/// Changes of a price update since the previous one of the same price
+#[derive(Serialize, Deserialize, Debug, Clone, Ord, PartialOrd, PartialEq, Eq)]
+pub struct PriceChanges {
+    /// Not set for the first update of the price seen by the agent
+    pub prev_price: Option<Price>,
+    pub prev_conf:  Option<Conf>,
+    /// Slots between the publish slots of the previous update and this one
+    pub slot_delta: Option<Slot>,
+    /// Whether the price, confidence or status differ from the previous update
+    pub changed:    bool,
 }
 
 pub mod rpc {
     use {
         super::{
-            super::adapter,
+            super::{
+                adapter,
+                admission::{
+                    self,
+                    Admission,
+                    Rejection,
+                },
+                compression::{
+                    self,
+                    PendingUpgrade,
+                },
+                connections::{
+                    ApiConnections,
+                    ConnectionHandle,
+                },
+                exponent_check,
+                wire_schema::{
+                    self,
+                    LegacyTranslation,
+                    WireSchema,
+                },
+            },
+            Attrs,
             Conf,
             NotifyPrice,
             NotifyPriceSched,
             Price,
             Pubkey,
             SubscriptionID,
+            PRICE_CHANGES_CAPABILITY,
+        },
+        crate::agent::{
+            build_info::BuildInfo,
+            error::{
+                self,
+                Error,
+            },
+            health::ComponentHealth,
+            metrics::{
+                API_METRICS,
+                TENANT_METRICS,
+            },
+            startup_gate::StartupGate,
+            store::global,
+            telemetry,
+            tenancy::{
+                Tenant,
+                Tenants,
+            },
+            update_status::{
+                Seq,
+                UpdateStatuses,
+            },
         },
         anyhow::{
-            anyhow,
+            Context as _,
             Result,
         },
         futures_util::{
+            future,
+            sink,
             stream::{
-                SplitSink,
-                SplitStream,
+                self,
+                Stream,
                 StreamExt,
             },
+            Sink,
             SinkExt,
         },
         jrpc::{
@@ -122,6 +209,10 @@ pub mod rpc {
             Response,
             Value,
         },
+        opentelemetry::{
+            Context,
+            KeyValue,
+        },
         serde::{
             de::DeserializeOwned,
             Deserialize,
@@ -131,12 +222,24 @@ pub mod rpc {
             as_i64,
             as_u64,
         },
-        slog::Logger,
         std::{
+            collections::HashMap,
+            convert::Infallible,
             fmt::Debug,
             net::SocketAddr,
+            pin::Pin,
+            time::{
+                Duration,
+                Instant,
+            },
         },
         tokio::{
+            io::{
+                self as tokio_io,
+                AsyncBufReadExt,
+                AsyncWriteExt,
+                BufReader,
+            },
             sync::{
                 broadcast,
                 mpsc,
@@ -144,7 +247,27 @@ pub mod rpc {
             },
             task::JoinHandle,
         },
+        tracing::{
+            Instrument,
+            Span,
+        },
         warp::{
+            hyper::{
+                self,
+                server::conn::AddrStream,
+                service::{
+                    make_service_fn,
+                    service_fn,
+                    Service,
+                },
+                Body,
+                Request as HttpRequest,
+                StatusCode,
+            },
+            reply::{
+                self,
+                Reply,
+            },
             ws::{
                 Message,
                 WebSocket,
@@ -160,11 +283,35 @@ pub mod rpc {
         GetProductList,
         GetProduct,
         GetAllProducts,
+        GetProductMetadata,
+        GetAggregatePreview,
+        GetAgentInfo,
         SubscribePrice,
         NotifyPrice,
         SubscribePriceSched,
         NotifyPriceSched,
         UpdatePrice,
+        GetUpdateStatus,
+    }
+
+    impl Method {
+        /// Name of the method on the wire, used to label its metrics
+        fn name(&self) -> &'static str {
+            match self {
+                Method::GetProductList => "get_product_list",
+                Method::GetProduct => "get_product",
+                Method::GetAllProducts => "get_all_products",
+                Method::GetProductMetadata => "get_product_metadata",
+                Method::GetAggregatePreview => "get_aggregate_preview",
+                Method::GetAgentInfo => "get_agent_info",
+                Method::SubscribePrice => "subscribe_price",
+                Method::NotifyPrice => "notify_price",
+                Method::SubscribePriceSched => "subscribe_price_sched",
+                Method::NotifyPriceSched => "notify_price_sched",
+                Method::UpdatePrice => "update_price",
+                Method::GetUpdateStatus => "get_update_status",
+            }
+        }
     }
 
     #[derive(Serialize, Deserialize, Debug)]
@@ -172,9 +319,30 @@ pub mod rpc {
         account: Pubkey,
     }
 
+    /// Attributes the products must have, e.g. `{"asset_type": "Crypto"}`. All
+    /// products are returned when omitted.
+    #[derive(Serialize, Deserialize, Debug, Default)]
+    struct GetProductMetadataParams {
+        #[serde(flatten)]
+        attributes: Attrs,
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    struct GetAggregatePreviewParams {
+        account:   Pubkey,
+        /// Publish key whose pending update is previewed. Defaults to the
+        /// publish key of the tenant the connection authenticated as.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        publisher: Option<Pubkey>,
+    }
+
     #[derive(Serialize, Deserialize, Debug)]
     struct SubscribePriceParams {
-        account: Pubkey,
+        account:      Pubkey,
+        /// Optional capabilities requested for the subscription, e.g.
+        /// `["price_changes"]`. Those the agent does not support are ignored.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        capabilities: Vec<String>,
     }
 
     #[derive(Serialize, Deserialize, Debug)]
@@ -184,32 +352,93 @@ pub mod rpc {
 
     #[derive(Serialize, Deserialize, Debug, Clone)]
     struct UpdatePriceParams {
-        account: Pubkey,
+        account:   Pubkey,
         #[serde(deserialize_with = "as_i64")]
-        price:   Price,
+        price:     Price,
         #[serde(deserialize_with = "as_u64")]
-        conf:    Conf,
-        status:  String,
+        conf:      Conf,
+        status:    String,
+        /// Publish key to submit the update on behalf of. The default
+        /// publish keypair is used when omitted.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        publisher: Option<Pubkey>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    struct GetUpdateStatusParams {
+        /// Sequence id returned by `update_price`
+        seq: Seq,
     }
 
     #[derive(Serialize, Deserialize, Debug, PartialEq)]
     struct SubscribeResult {
         subscription: SubscriptionID,
+        /// The requested capabilities which were granted
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        capabilities: Vec<String>,
     }
 
     #[derive(thiserror::Error, Debug)]
     enum ConnectionError {
-        #[error("websocket connection closed")]
-        WebsocketConnectionClosed,
+        #[error("connection closed")]
+        ConnectionClosed,
+    }
+
+    /// Text messages sent to the client, over the transport of the connection
+    pub(crate) type MessageSink = Pin<Box<dyn Sink<String, Error = anyhow::Error> + Send>>;
+
+    /// Messages received from the client, None for those which are not text
+    pub(crate) type MessageStream = Pin<Box<dyn Stream<Item = Result<Option<String>>> + Send>>;
+
+    /// Address of the client of a request, put in its extensions by the server
+    #[derive(Clone, Copy, Debug)]
+    struct RemoteAddress(SocketAddr);
+
+    /// The text messages of a websocket
+    fn websocket_transport(ws_conn: WebSocket) -> (MessageSink, MessageStream) {
+        let (ws_tx, ws_rx) = ws_conn.split();
+        let tx = ws_tx.with(|text: String| future::ok::<_, anyhow::Error>(Message::text(text)));
+        let rx = ws_rx.map(|message| {
+            message
+                .map(|message| message.to_str().ok().map(str::to_string))
+                .map_err(Into::into)
+        });
+        (Box::pin(tx), Box::pin(rx))
+    }
+
+    /// The lines of stdin and stdout, each message being a single line. Blank
+    /// lines are skipped.
+    fn stdio_transport() -> (MessageSink, MessageStream) {
+        let tx = sink::unfold(tokio_io::stdout(), |mut stdout, text: String| async move {
+            stdout.write_all(text.as_bytes()).await?;
+            stdout.write_all(b"\n").await?;
+            stdout.flush().await?;
+            Ok::<_, anyhow::Error>(stdout)
+        });
+        let rx = stream::unfold(
+            BufReader::new(tokio_io::stdin()).lines(),
+            |mut lines| async move {
+                match lines.next_line().await {
+                    Ok(Some(line)) => {
+                        let line = Some(line).filter(|line| !line.trim().is_empty());
+                        Some((Ok(line), lines))
+                    }
+                    Ok(None) => None,
+                    Err(err) => Some((Err(err.into()), lines)),
+                }
+            },
+        );
+        (Box::pin(tx), Box::pin(rx))
     }
 
     struct Connection {
         // Channel for communicating with the adapter
         adapter_tx: mpsc::Sender<adapter::Message>,
 
-        // Channel Websocket messages are sent and received on
-        ws_tx: SplitSink<WebSocket, Message>,
-        ws_rx: SplitStream<WebSocket>,
+        // Channel the text messages of the client are sent and received on,
+        // over a websocket or stdio
+        tx: MessageSink,
+        rx: MessageStream,
 
         // Channel NotifyPrice events are sent and received on
         notify_price_tx: mpsc::Sender<NotifyPrice>,
@@ -219,57 +448,111 @@ pub mod rpc {
         notify_price_sched_tx: mpsc::Sender<NotifyPriceSched>,
         notify_price_sched_rx: mpsc::Receiver<NotifyPriceSched>,
 
-        logger: Logger,
+        // Reports the activity of the connection to the Admin API
+        handle: ConnectionHandle,
+
+        // Tenant the connection authenticated as, whose publish key its
+        // updates are submitted for
+        tenant: Option<Tenant>,
+
+        // Used to look up the symbols the tenant updates
+        global_store_reader: global::SnapshotReader,
+
+        // Translation of the messages of the connection, if it speaks the
+        // legacy wire schema
+        legacy: Option<LegacyTranslation>,
+
+        // Fractions of the requests and notifications which are logged
+        request_log_sample_rate:      f64,
+        notification_log_sample_rate: f64,
+
+        // Accounts subscribed to, by subscription, to name the symbols of
+        // the logged notifications
+        subscriptions: HashMap<SubscriptionID, Pubkey>,
+
+        // Rejects the updates which look scaled with the wrong exponent, if
+        // enabled
+        exponent_check: Option<exponent_check::Config>,
+
+        // Assigns the sequence ids of the updates, and serves their statuses
+        update_statuses: UpdateStatuses,
+    }
+
+    impl Drop for Connection {
+        fn drop(&mut self) {
+            if let Some(tenant) = &self.tenant {
+                TENANT_METRICS.disconnected(&tenant.name);
+            }
+        }
     }
 
     impl Connection {
         fn new(
-            ws_conn: WebSocket,
+            (tx, rx): (MessageSink, MessageStream),
             adapter_tx: mpsc::Sender<adapter::Message>,
-            notify_price_tx_buffer: usize,
-            notify_price_sched_tx_buffer: usize,
-            logger: Logger,
+            config: &Config,
+            remote_address: Option<SocketAddr>,
+            connections: &ApiConnections,
+            tenant: Option<Tenant>,
+            global_store_reader: global::SnapshotReader,
+            legacy: Option<LegacyTranslation>,
+            update_statuses: UpdateStatuses,
         ) -> Self {
             // Create the channels
-            let (ws_tx, ws_rx) = ws_conn.split();
-            let (notify_price_tx, notify_price_rx) = mpsc::channel(notify_price_tx_buffer);
+            let (notify_price_tx, notify_price_rx) = mpsc::channel(config.notify_price_tx_buffer);
             let (notify_price_sched_tx, notify_price_sched_rx) =
-                mpsc::channel(notify_price_sched_tx_buffer);
+                mpsc::channel(config.notify_price_sched_tx_buffer);
+            let handle = connections.register(
+                remote_address,
+                (&notify_price_tx, config.notify_price_tx_buffer),
+                (&notify_price_sched_tx, config.notify_price_sched_tx_buffer),
+            );
+            if let Some(tenant) = &tenant {
+                TENANT_METRICS.connected(&tenant.name);
+            }
 
             // Create the new connection object
             Connection {
                 adapter_tx,
-                ws_tx,
-                ws_rx,
+                tx,
+                rx,
                 notify_price_tx,
                 notify_price_rx,
                 notify_price_sched_tx,
                 notify_price_sched_rx,
-                logger,
+                handle,
+                tenant,
+                global_store_reader,
+                legacy,
+                request_log_sample_rate: config.request_log_sample_rate,
+                notification_log_sample_rate: config.notification_log_sample_rate,
+                subscriptions: HashMap::new(),
+                exponent_check: config.exponent_check.clone(),
+                update_statuses,
             }
         }
 
         async fn consume(&mut self) {
             loop {
                 if let Err(err) = self.handle_next().await {
-                    if let Some(ConnectionError::WebsocketConnectionClosed) =
+                    if let Some(ConnectionError::ConnectionClosed) =
                         err.downcast_ref::<ConnectionError>()
                     {
-                        info!(self.logger, "websocket connection closed");
+                        info!("connection closed");
                         return;
                     }
 
-                    error!(self.logger, "{:#}", err; "error" => format!("{:?}", err))
+                    error!(error = ?err, kind = %error::record("api", &err), "{:#}", err)
                 }
             }
         }
 
         async fn handle_next(&mut self) -> Result<()> {
             tokio::select! {
-                msg = self.ws_rx.next() => {
+                msg = self.rx.next() => {
                     match msg {
-                        Some(body) => self.handle_ws_rx(body).await,
-                        None => Err(ConnectionError::WebsocketConnectionClosed)?,
+                        Some(body) => self.handle_rx(body).await,
+                        None => Err(ConnectionError::ConnectionClosed)?,
                     }
                 }
                 Some(notify_price) = self.notify_price_rx.recv() => {
@@ -281,14 +564,26 @@ pub mod rpc {
             }
         }
 
-        async fn handle_ws_rx(&mut self, body: Result<Message, warp::Error>) -> Result<()> {
+        async fn handle_rx(&mut self, body: Result<Option<String>>) -> Result<()> {
             match body {
                 Ok(msg) => self.handle(msg).await,
-                Err(e) => self.send_error(e.into(), None).await,
+                Err(e) => self.send_error(e, None).await,
             }
         }
 
         async fn handle_notify_price(&mut self, notify_price: NotifyPrice) -> Result<()> {
+            API_METRICS.record_notification(Method::NotifyPrice.name());
+            if sampled(self.notification_log_sample_rate) {
+                info!(
+                    method = Method::NotifyPrice.name(),
+                    symbol = self.subscription_symbol(notify_price.subscription).as_deref(),
+                    subscription = notify_price.subscription,
+                    price = notify_price.result.price,
+                    conf = notify_price.result.conf,
+                    status = %notify_price.result.status,
+                    "JSON RPC API: sending notification"
+                );
+            }
             self.send_notification(Method::NotifyPrice, Some(notify_price))
                 .await
         }
@@ -297,19 +592,35 @@ pub mod rpc {
             &mut self,
             notify_price_sched: NotifyPriceSched,
         ) -> Result<()> {
+            API_METRICS.record_notification(Method::NotifyPriceSched.name());
+            if sampled(self.notification_log_sample_rate) {
+                info!(
+                    method = Method::NotifyPriceSched.name(),
+                    symbol = self
+                        .subscription_symbol(notify_price_sched.subscription)
+                        .as_deref(),
+                    subscription = notify_price_sched.subscription,
+                    "JSON RPC API: sending notification"
+                );
+            }
             self.send_notification(Method::NotifyPriceSched, Some(notify_price_sched))
                 .await
         }
 
-        async fn handle(&mut self, msg: Message) -> Result<()> {
+        async fn handle(&mut self, msg: Option<String>) -> Result<()> {
+            self.handle.record_message();
+
             // Ignore control and binary messages
-            if !msg.is_text() {
-                debug!(self.logger, "JSON RPC API: skipped non-text message");
-                return Ok(());
-            }
+            let msg = match msg {
+                Some(msg) => msg,
+                None => {
+                    debug!("JSON RPC API: skipped non-text message");
+                    return Ok(());
+                }
+            };
 
             // Parse and dispatch the message
-            match self.parse(msg).await {
+            match self.parse(&msg).await {
                 Ok((requests, is_batch)) => {
                     let mut responses = Vec::with_capacity(requests.len());
 
@@ -343,12 +654,11 @@ pub mod rpc {
         /// sending unexpected
         /// `[{<just one response, but request was not array>}]`
         /// array payloads.
-        async fn parse(&mut self, msg: Message) -> Result<(Vec<Request<Method, Value>>, bool)> {
-            let s = msg
-                .to_str()
-                .map_err(|_| anyhow!("Could not parse message as text"))?;
-
-            let json_value: Value = serde_json::from_str(s)?;
+        async fn parse(&mut self, msg: &str) -> Result<(Vec<Request<Method, Value>>, bool)> {
+            let mut json_value: Value = serde_json::from_str(msg)?;
+            if let Some(legacy) = &self.legacy {
+                json_value = legacy.from_legacy(json_value);
+            }
             if let Some(array) = json_value.as_array() {
                 // Interpret request as JSON-RPC 2.0 batch if value is an array
                 let mut requests = Vec::with_capacity(array.len());
@@ -357,17 +667,17 @@ pub mod rpc {
                     // jrpc parsing function available and it's taking
                     // &str.
                     let maybe_request_string = serde_json::to_string(maybe_request)?;
-                    requests
-                        .push(parse_request::<Method>(&maybe_request_string).map_err(|e| {
-                            anyhow!("Could not parse message: {}", e.error.message)
-                        })?);
+                    requests.push(parse_request::<Method>(&maybe_request_string).map_err(|e| {
+                        Error::Parse(format!("Could not parse message: {}", e.error.message))
+                    })?);
                 }
 
                 Ok((requests, true))
             } else {
                 // Base single request case
-                let single = parse_request::<Method>(s)
-                    .map_err(|e| anyhow!("Could not parse message: {}", e.error.message))?;
+                let single = parse_request::<Method>(&json_value.to_string()).map_err(|e| {
+                    Error::Parse(format!("Could not parse message: {}", e.error.message))
+                })?;
                 Ok((vec![single], false))
             }
         }
@@ -376,34 +686,44 @@ pub mod rpc {
             &mut self,
             request: &Request<Method, Value>,
         ) -> Response<serde_json::Value> {
-            debug!(self.logger,
-             "JSON RPC API: handling request";
-            "method" => format!("{:?}", request.method),
-                );
+            debug!(method = ?request.method, "JSON RPC API: handling request");
+            let started_at = Instant::now();
             let result = match request.method {
                 Method::GetProductList => self.get_product_list().await,
                 Method::GetProduct => self.get_product(request).await,
                 Method::GetAllProducts => self.get_all_products().await,
+                Method::GetProductMetadata => self.get_product_metadata(request).await,
+                Method::GetAggregatePreview => self.get_aggregate_preview(request).await,
+                Method::GetAgentInfo => Ok(serde_json::to_value(BuildInfo::current())?),
                 Method::SubscribePrice => self.subscribe_price(request).await,
                 Method::SubscribePriceSched => self.subscribe_price_sched(request).await,
                 Method::UpdatePrice => self.update_price(request).await,
+                Method::GetUpdateStatus => self.get_update_status(request),
                 Method::NotifyPrice | Method::NotifyPriceSched => {
-                    Err(anyhow!("unsupported method: {:?}", request.method))
+                    Err(Error::Api(format!("unsupported method: {:?}", request.method)).into())
                 }
             };
 
+            let latency = started_at.elapsed();
+            API_METRICS.observe_request(request.method.name(), latency, result.is_ok());
+            if sampled(self.request_log_sample_rate) {
+                info!(
+                    method = request.method.name(),
+                    symbol = self.request_symbol(request).as_deref(),
+                    params = %request.params.as_ref().map(Value::to_string).unwrap_or_default(),
+                    latency_ms = latency.as_secs_f64() * 1000.0,
+                    error = result.as_ref().err().map(|e| format!("{:#}", e)).as_deref(),
+                    "JSON RPC API: handled request"
+                );
+            }
+
             // Consider errors internal, print details to logs.
             match result {
                 Ok(payload) => {
                     Response::success(request.id.clone().to_id().unwrap_or(Id::from(0)), payload)
                 }
                 Err(e) => {
-                    warn!(
-                    self.logger,
-                      "Error handling JSON RPC request";
-                    "request" => format!("{:?}", request),
-                    "error" => format!("{}", e.to_string()),
-                    );
+                    warn!(?request, error = %e, "Error handling JSON RPC request");
                     Response::error(
                         request.id.clone().to_id().unwrap_or(Id::from(0)),
                         ErrorCode::InternalError,
@@ -449,23 +769,82 @@ pub mod rpc {
             Ok(serde_json::to_value(result_rx.await??)?)
         }
 
+        async fn get_product_metadata(
+            &mut self,
+            request: &Request<Method, Value>,
+        ) -> Result<serde_json::Value> {
+            let params: GetProductMetadataParams = match request.params.clone() {
+                Some(params) => self.deserialize_params(Some(params))?,
+                None => GetProductMetadataParams::default(),
+            };
+
+            let (result_tx, result_rx) = oneshot::channel();
+            self.adapter_tx
+                .send(adapter::Message::GetProductMetadata {
+                    attributes: params.attributes,
+                    result_tx,
+                })
+                .await?;
+
+            Ok(serde_json::to_value(result_rx.await??)?)
+        }
+
+        async fn get_aggregate_preview(
+            &mut self,
+            request: &Request<Method, Value>,
+        ) -> Result<serde_json::Value> {
+            let params: GetAggregatePreviewParams =
+                self.deserialize_params(request.params.clone())?;
+            let publisher = params
+                .publisher
+                .or_else(|| {
+                    self.tenant
+                        .as_ref()
+                        .map(|tenant| tenant.publish_key.to_string())
+                })
+                .ok_or_else(|| {
+                    Error::Api("the publish key to preview the update of is required".to_string())
+                })?;
+
+            let (result_tx, result_rx) = oneshot::channel();
+            self.adapter_tx
+                .send(adapter::Message::GetAggregatePreview {
+                    account: params.account,
+                    publisher,
+                    result_tx,
+                })
+                .await?;
+
+            Ok(serde_json::to_value(result_rx.await??)?)
+        }
+
         async fn subscribe_price(
             &mut self,
             request: &Request<Method, Value>,
         ) -> Result<serde_json::Value> {
             let params: SubscribePriceParams = self.deserialize_params(request.params.clone())?;
+            let capabilities: Vec<String> = params
+                .capabilities
+                .into_iter()
+                .filter(|capability| capability == PRICE_CHANGES_CAPABILITY)
+                .collect();
 
             let (result_tx, result_rx) = oneshot::channel();
             self.adapter_tx
                 .send(adapter::Message::SubscribePrice {
                     result_tx,
-                    account: params.account,
+                    account: params.account.clone(),
                     notify_price_tx: self.notify_price_tx.clone(),
+                    price_changes: !capabilities.is_empty(),
                 })
                 .await?;
 
+            let subscription = result_rx.await??;
+            self.handle.record_price_subscription();
+            self.subscriptions.insert(subscription, params.account);
             Ok(serde_json::to_value(SubscribeResult {
-                subscription: result_rx.await??,
+                subscription,
+                capabilities,
             })?)
         }
 
@@ -480,13 +859,17 @@ pub mod rpc {
             self.adapter_tx
                 .send(adapter::Message::SubscribePriceSched {
                     result_tx,
-                    account: params.account,
+                    account: params.account.clone(),
                     notify_price_sched_tx: self.notify_price_sched_tx.clone(),
                 })
                 .await?;
 
+            let subscription = result_rx.await??;
+            self.handle.record_price_sched_subscription();
+            self.subscriptions.insert(subscription, params.account);
             Ok(serde_json::to_value(SubscribeResult {
-                subscription: result_rx.await??,
+                subscription,
+                capabilities: vec![],
             })?)
         }
 
@@ -494,26 +877,183 @@ pub mod rpc {
             &mut self,
             request: &Request<Method, Value>,
         ) -> Result<serde_json::Value> {
-            let params: UpdatePriceParams = self.deserialize_params(request.params.clone())?;
-
-            self.adapter_tx
+            // The publish latency of the update is measured from its arrival
+            let received_at = Instant::now();
+            let mut params: UpdatePriceParams = self.deserialize_params(request.params.clone())?;
+            self.attribute_to_tenant(&mut params)?;
+            self.check_exponent(&params)?;
+            self.handle
+                .record_update(&params.account, params.publisher.as_deref());
+            let seq = self
+                .update_statuses
+                .accept(&params.account, params.publisher.as_deref());
+
+            // The update's trace starts here
+            let trace_context = telemetry::start_span(
+                &Context::new(),
+                "api.update_price",
+                vec![KeyValue::new("price_account", params.account.clone())],
+            );
+            let result = self
+                .adapter_tx
                 .send(adapter::Message::UpdatePrice {
                     account: params.account,
-                    price:   params.price,
-                    conf:    params.conf,
-                    status:  params.status,
+                    price: params.price,
+                    conf: params.conf,
+                    status: params.status,
+                    publisher: params.publisher,
+                    seq,
+                    received_at,
+                    trace_context: trace_context.clone(),
                 })
-                .await?;
+                .await
+                .map_err(|_| Error::ChannelClosed("adapter"))
+                .context("failed to send update to adapter");
+            telemetry::end_span(&trace_context, &result);
+            if let Err(err) = &result {
+                self.update_statuses.reject(seq, format!("{:#}", err));
+            }
+            result?;
+
+            Ok(serde_json::to_value(seq)?)
+        }
+
+        /// The status of an update, which tenants may only look up for their
+        /// own updates
+        fn get_update_status(&self, request: &Request<Method, Value>) -> Result<serde_json::Value> {
+            let params: GetUpdateStatusParams = self.deserialize_params(request.params.clone())?;
+            let status = self
+                .update_statuses
+                .get(params.seq)
+                .filter(|status| {
+                    self.tenant.as_ref().map_or(true, |tenant| {
+                        status.publisher.as_deref() == Some(&tenant.publish_key.to_string())
+                    })
+                })
+                .ok_or_else(|| Error::Api(format!("unknown update {}", params.seq)))?;
+            Ok(serde_json::to_value(status)?)
+        }
+
+        /// Submit the update for the publish key of the tenant the connection
+        /// authenticated as, if any, unless the tenant may not publish it
+        fn attribute_to_tenant(&self, params: &mut UpdatePriceParams) -> Result<()> {
+            let tenant = match &self.tenant {
+                Some(tenant) => tenant,
+                None => return Ok(()),
+            };
+
+            let publish_key = tenant.publish_key.to_string();
+            if params
+                .publisher
+                .as_ref()
+                .map_or(false, |publisher| *publisher != publish_key)
+            {
+                tenant.record_update(Some("publisher"));
+                return Err(Error::Api(format!(
+                    "tenant {} may only publish on behalf of {}",
+                    tenant.name, publish_key
+                ))
+                .into());
+            }
+
+            let account = params
+                .account
+                .parse()
+                .map_err(|_| Error::Api(format!("invalid price account {}", params.account)))?;
+            let symbol = self
+                .global_store_reader
+                .load()
+                .account_metadata
+                .symbol_index
+                .price_name(&account);
+            if !tenant.may_publish(&symbol) {
+                tenant.record_update(Some("symbol"));
+                return Err(Error::Api(format!(
+                    "tenant {} may not publish {}",
+                    tenant.name, symbol
+                ))
+                .into());
+            }
+
+            tenant.record_update(None);
+            params.publisher = Some(publish_key);
+            Ok(())
+        }
+
+        /// Reject a trading update whose price is off from the aggregate of
+        /// its price account by a power of ten, naming the exponent it looks
+        /// scaled with
+        fn check_exponent(&self, params: &UpdatePriceParams) -> Result<()> {
+            let exponent_check = match &self.exponent_check {
+                Some(exponent_check) if params.status == "trading" => exponent_check,
+                _ => return Ok(()),
+            };
+            let account = params
+                .account
+                .parse()
+                .map_err(|_| Error::Api(format!("invalid price account {}", params.account)))?;
+            let snapshot = self.global_store_reader.load();
+            let mismatch = snapshot
+                .account_data
+                .price_accounts
+                .get(&account)
+                .and_then(|price_account| exponent_check.check(params.price, price_account));
+            match mismatch {
+                Some(mismatch) => {
+                    let symbol = snapshot.account_metadata.symbol_index.price_name(&account);
+                    warn!(
+                        %symbol,
+                        price = params.price,
+                        expo = mismatch.expo,
+                        suspected_expo = mismatch.suspected_expo,
+                        "Rejected an update which looks scaled with the wrong exponent"
+                    );
+                    Err(Error::Api(format!(
+                        "price {} of {} looks scaled with exponent {} rather than {}",
+                        params.price, symbol, mismatch.suspected_expo, mismatch.expo
+                    ))
+                    .into())
+                }
+                None => Ok(()),
+            }
+        }
+
+        /// Symbol of the price or product account the request is about, if
+        /// it names one
+        fn request_symbol(&self, request: &Request<Method, Value>) -> Option<String> {
+            let account = request
+                .params
+                .as_ref()?
+                .get("account")?
+                .as_str()?
+                .parse()
+                .ok()?;
+            let snapshot = self.global_store_reader.load();
+            let symbol_index = &snapshot.account_metadata.symbol_index;
+            symbol_index
+                .symbol(&account)
+                .or_else(|| symbol_index.product_symbol(&account))
+                .map(str::to_string)
+        }
 
-            Ok(serde_json::to_value(0)?)
+        fn subscription_symbol(&self, subscription: SubscriptionID) -> Option<String> {
+            let account = self.subscriptions.get(&subscription)?.parse().ok()?;
+            self.global_store_reader
+                .load()
+                .account_metadata
+                .symbol_index
+                .symbol(&account)
+                .map(str::to_string)
         }
 
         fn deserialize_params<T>(&self, value: Option<Value>) -> Result<T>
         where
             T: DeserializeOwned,
         {
-            serde_json::from_value::<T>(value.ok_or_else(|| anyhow!("Missing request parameters"))?)
-                .map_err(|e| e.into())
+            serde_json::from_value::<T>(
+                value.ok_or_else(|| Error::Api("Missing request parameters".to_string()))?,
+            )
+            .map_err(|e| e.into())
         }
 
         async fn send_error(&mut self, error: anyhow::Error, id: Option<Id>) -> Result<()> {
@@ -548,20 +1088,16 @@ pub mod rpc {
         }
 
         async fn send_text(&mut self, msg: &str) -> Result<()> {
-            self.ws_tx
-                .send(Message::text(msg.to_string()))
-                .await
-                .map_err(|e| e.into())
+            let msg = match &self.legacy {
+                Some(legacy) => legacy.to_legacy(serde_json::from_str(msg)?).to_string(),
+                None => msg.to_string(),
+            };
+            self.tx.send(msg).await
         }
     }
 
-    #[derive(Clone)]
-    struct WithLogger {
-        logger: Logger,
-    }
-
-    #[derive(Clone, Debug, Serialize, Deserialize)]
-    #[serde(default)]
+    #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+    #[serde(default, deny_unknown_fields)]
     pub struct Config {
         /// The address which the websocket API server will listen on.
         pub listen_address:               String,
@@ -571,6 +1107,27 @@ pub mod rpc {
         /// Size of the buffer of each Server's channel on which `notify_price_sched` events are
         /// received from the Adapter.
         pub notify_price_sched_tx_buffer: usize,
+        /// Wire schemas spoken by the connections, for legacy clients
+        pub wire_schema:                  wire_schema::Config,
+        /// Fraction of the requests logged with their params, symbol and
+        /// latency, from 0 (none) to 1 (all)
+        pub request_log_sample_rate:      f64,
+        /// Fraction of the notifications logged with their symbol, from 0
+        /// (none) to 1 (all)
+        pub notification_log_sample_rate: f64,
+        /// Whether the API is also served over stdin and stdout, one message
+        /// per line, the logs then being written to stderr. Read at startup
+        /// only.
+        pub stdio:                        bool,
+        /// Rejects the trading updates whose price is off from the aggregate
+        /// of their price account by a power of ten. Disabled when not set.
+        pub exponent_check:               Option<exponent_check::Config>,
+        /// Networks the connections are accepted from, and the maximum number
+        /// of concurrent connections
+        pub admission:                    admission::Config,
+        /// Compression of the websocket messages with permessage-deflate, for
+        /// the clients offering it. Disabled when not set.
+        pub compression:                  Option<compression::Config>,
     }
 
     impl Default for Config {
@@ -579,91 +1136,317 @@ pub mod rpc {
                 listen_address:               "127.0.0.1:8910".to_string(),
                 notify_price_tx_buffer:       10000,
                 notify_price_sched_tx_buffer: 10000,
+                wire_schema:                  wire_schema::Config::default(),
+                request_log_sample_rate:      0.0,
+                notification_log_sample_rate: 0.0,
+                stdio:                        false,
+                exponent_check:               None,
+                admission:                    admission::Config::default(),
+                compression:                  None,
             }
         }
     }
 
+    /// Interval at which the stdio connection checks whether the startup gate
+    /// opened
+    const STARTUP_GATE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+    /// Whether to log a message, logging the given fraction of them
+    fn sampled(sample_rate: f64) -> bool {
+        sample_rate > 0.0 && rand::random::<f64>() < sample_rate
+    }
+
     pub fn spawn_server(
         config: Config,
         adapter_tx: mpsc::Sender<adapter::Message>,
         shutdown_rx: broadcast::Receiver<()>,
-        logger: Logger,
+        health: ComponentHealth,
+        connections: ApiConnections,
+        tenants: Tenants,
+        global_store_reader: global::SnapshotReader,
+        startup_gate: StartupGate,
+        update_statuses: UpdateStatuses,
     ) -> JoinHandle<()> {
-        tokio::spawn(async move {
-            Server::new(adapter_tx, config, logger)
+        tokio::spawn(
+            async move {
+                Server::new(
+                    adapter_tx,
+                    config,
+                    health,
+                    connections,
+                    tenants,
+                    global_store_reader,
+                    startup_gate,
+                    update_statuses,
+                )
                 .run(shutdown_rx)
                 .await
-        })
+            }
+            .instrument(info_span!("api_server")),
+        )
     }
 
     pub struct Server {
-        adapter_tx: mpsc::Sender<adapter::Message>,
-        config:     Config,
-        logger:     Logger,
+        adapter_tx:          mpsc::Sender<adapter::Message>,
+        config:              Config,
+        health:              ComponentHealth,
+        /// The open connections, listed by the Admin API
+        connections:         ApiConnections,
+        /// Tenants the connections must authenticate as, if any
+        tenants:             Tenants,
+        /// Used by the connections of tenants to look up symbols
+        global_store_reader: global::SnapshotReader,
+        /// Connections are refused until it opens
+        startup_gate:        StartupGate,
+        /// Statuses of the updates accepted by the connections
+        update_statuses:     UpdateStatuses,
     }
 
     impl Server {
         pub fn new(
             adapter_tx: mpsc::Sender<adapter::Message>,
             config: Config,
-            logger: Logger,
+            health: ComponentHealth,
+            connections: ApiConnections,
+            tenants: Tenants,
+            global_store_reader: global::SnapshotReader,
+            startup_gate: StartupGate,
+            update_statuses: UpdateStatuses,
         ) -> Self {
             Server {
                 adapter_tx,
                 config,
-                logger,
+                health,
+                connections,
+                tenants,
+                global_store_reader,
+                startup_gate,
+                update_statuses,
             }
         }
 
         pub async fn run(&self, shutdown_rx: broadcast::Receiver<()>) {
-            if let Err(err) = self.serve(shutdown_rx).await {
-                error!(self.logger, "{:#}", err; "error" => format!("{:?}", err))
+            match self.serve(shutdown_rx).await {
+                Ok(()) => self.health.unhealthy("api server stopped"),
+                Err(err) => {
+                    error!(error = ?err, kind = %error::record("api", &err), "{:#}", err);
+                    self.health
+                        .unhealthy(format!("api server failed: {:#}", err));
+                }
             }
         }
 
         async fn serve(&self, mut shutdown_rx: broadcast::Receiver<()>) -> Result<()> {
             let adapter_tx = self.adapter_tx.clone();
             let config = self.config.clone();
-            let with_logger = WithLogger {
-                logger: self.logger.clone(),
-            };
+            let connections = self.connections.clone();
+            let tenants = self.tenants.clone();
+            let global_store_reader = self.global_store_reader.clone();
+            let startup_gate = self.startup_gate.clone();
+            let update_statuses = self.update_statuses.clone();
+            let legacy_translation = LegacyTranslation::new(&self.config.wire_schema);
+            let admission = Admission::new(&self.config.admission);
+            // The connections are handled outside of the server's task, so
+            // their spans are explicitly made children of its span
+            let server_span = Span::current();
 
             let index = warp::path::end()
                 .and(warp::ws())
+                .and(
+                    warp::ext::optional::<RemoteAddress>()
+                        .map(|remote_address: Option<RemoteAddress>| {
+                            remote_address.map(|RemoteAddress(address)| address)
+                        }),
+                )
+                .and(warp::ext::optional::<PendingUpgrade>())
                 .and(warp::any().map(move || adapter_tx.clone()))
-                .and(warp::any().map(move || with_logger.clone()))
                 .and(warp::any().map(move || config.clone()))
+                .and(warp::any().map(move || connections.clone()))
+                .and(warp::header::optional::<String>("authorization"))
+                .and(warp::query::<HashMap<String, String>>())
                 .map(
-                    |ws: Ws,
-                     adapter_tx: mpsc::Sender<adapter::Message>,
-                     with_logger: WithLogger,
-                     config: Config| {
-                        ws.on_upgrade(move |conn| async move {
-                            info!(with_logger.logger, "websocket user connected");
-
-                            Connection::new(
-                                conn,
-                                adapter_tx,
-                                config.notify_price_tx_buffer,
-                                config.notify_price_sched_tx_buffer,
-                                with_logger.logger,
-                            )
-                            .consume()
-                            .await
-                        })
+                    move |ws: Ws,
+                          remote_address: Option<SocketAddr>,
+                          pending_upgrade: Option<PendingUpgrade>,
+                          adapter_tx: mpsc::Sender<adapter::Message>,
+                          config: Config,
+                          connections: ApiConnections,
+                          authorization: Option<String>,
+                          query: HashMap<String, String>| {
+                        // Connections are admitted before anything else is done for them
+                        let admitted = match admission.admit(remote_address) {
+                            Ok(admitted) => admitted,
+                            Err(rejection) => {
+                                warn!(
+                                    remote_address = ?remote_address,
+                                    reason = rejection.reason(),
+                                    "rejected websocket connection"
+                                );
+                                let status = match rejection {
+                                    Rejection::NotAllowed => StatusCode::FORBIDDEN,
+                                    Rejection::ConnectionLimit => StatusCode::SERVICE_UNAVAILABLE,
+                                };
+                                return Box::new(reply::with_status(rejection.reason(), status))
+                                    as Box<dyn Reply>;
+                            }
+                        };
+
+                        // Clients are asked to retry until the products are loaded
+                        if !startup_gate.is_open() {
+                            return Box::new(reply::with_status(
+                                "waiting for the first poll of the oracle",
+                                StatusCode::SERVICE_UNAVAILABLE,
+                            )) as Box<dyn Reply>;
+                        }
+
+                        // With tenants, connections authenticate as one of them
+                        let token = authorization
+                            .as_deref()
+                            .and_then(|authorization| authorization.strip_prefix("Bearer "))
+                            .or_else(|| query.get("token").map(String::as_str));
+                        let tenant = token.and_then(|token| tenants.authenticate(token));
+                        if tenants.enabled() && tenant.is_none() {
+                            warn!(remote_address = ?remote_address, "rejected unauthenticated websocket connection");
+                            return Box::new(reply::with_status(
+                                "missing or unknown tenant token",
+                                StatusCode::UNAUTHORIZED,
+                            )) as Box<dyn Reply>;
+                        }
+                        let schema = match query.get("schema") {
+                            Some(schema) => match WireSchema::from_query(schema) {
+                                Some(schema) => schema,
+                                None => {
+                                    return Box::new(reply::with_status(
+                                        "unknown wire schema, expected current or legacy",
+                                        StatusCode::BAD_REQUEST,
+                                    )) as Box<dyn Reply>;
+                                }
+                            },
+                            None => config.wire_schema.default_schema,
+                        };
+                        let legacy = (schema == WireSchema::Legacy)
+                            .then(|| legacy_translation.clone());
+                        let global_store_reader = global_store_reader.clone();
+                        let update_statuses = update_statuses.clone();
+
+                        let connection_span = info_span!(
+                            parent: &server_span,
+                            "connection",
+                            remote_address = ?remote_address,
+                            ?schema,
+                            tenant = ?tenant.as_ref().map(|tenant| &tenant.name)
+                        );
+                        let compressed = pending_upgrade.is_some();
+                        let on_connection = move |transport: (MessageSink, MessageStream)| {
+                            async move {
+                                // Held until the connection closes
+                                let _admitted = admitted;
+                                info!(compressed, "websocket user connected");
+
+                                Connection::new(
+                                    transport,
+                                    adapter_tx,
+                                    &config,
+                                    remote_address,
+                                    &connections,
+                                    tenant,
+                                    global_store_reader,
+                                    legacy,
+                                    update_statuses,
+                                )
+                                .consume()
+                                .await
+                            }
+                            .instrument(connection_span)
+                        };
+                        // Clients offering permessage-deflate are framed with
+                        // compression, rather than by the websocket of warp
+                        match &pending_upgrade {
+                            Some(pending_upgrade) => match pending_upgrade.accept(on_connection) {
+                                Some(response) => Box::new(response) as Box<dyn Reply>,
+                                None => Box::new(reply::with_status(
+                                    "the connection was already upgraded",
+                                    StatusCode::BAD_REQUEST,
+                                )),
+                            },
+                            None => Box::new(
+                                ws.on_upgrade(move |conn| on_connection(websocket_transport(conn))),
+                            ),
+                        }
                     },
                 );
 
-            let (_, serve) = warp::serve(index).bind_with_graceful_shutdown(
-                self.config.listen_address.as_str().parse::<SocketAddr>()?,
-                async move {
-                    let _ = shutdown_rx.recv().await;
-                },
+            // The requests are served by hyper rather than warp, so that the
+            // upgrade of the connections offering permessage-deflate is taken
+            // out of their request before warp handles it
+            let compression = self.config.compression.clone();
+            let service = warp::service(index);
+            let make_service = make_service_fn(move |conn: &AddrStream| {
+                let remote_address = RemoteAddress(conn.remote_addr());
+                let service = service.clone();
+                let compression = compression.clone();
+                future::ok::<_, Infallible>(service_fn(move |mut request: HttpRequest<Body>| {
+                    request.extensions_mut().insert(remote_address);
+                    if let Some(compression) = &compression {
+                        PendingUpgrade::intercept(&mut request, compression);
+                    }
+                    service.clone().call(request)
+                }))
+            });
+            let serve = hyper::Server::try_bind(
+                &self.config.listen_address.as_str().parse::<SocketAddr>()?,
+            )?
+            .serve(make_service)
+            .with_graceful_shutdown(async move {
+                let _ = shutdown_rx.recv().await;
+            });
+
+            info!(
+                listen_address = %self.config.listen_address,
+                "starting api server"
             );
+            if self.config.stdio {
+                self.spawn_stdio_connection();
+            }
+            self.health
+                .healthy(format!("listening on {}", self.config.listen_address));
+
+            tokio::task::spawn(serve).await?.context("serving the api")
+        }
 
-            info!(self.logger, "starting api server"; "listen address" => self.config.listen_address.clone());
+        /// Serve the API over stdin and stdout, once the startup gate opened
+        fn spawn_stdio_connection(&self) -> JoinHandle<()> {
+            let adapter_tx = self.adapter_tx.clone();
+            let config = self.config.clone();
+            let connections = self.connections.clone();
+            let global_store_reader = self.global_store_reader.clone();
+            let startup_gate = self.startup_gate.clone();
+            let update_statuses = self.update_statuses.clone();
+            let legacy = (config.wire_schema.default_schema == WireSchema::Legacy)
+                .then(|| LegacyTranslation::new(&config.wire_schema));
+            tokio::spawn(
+                async move {
+                    while !startup_gate.is_open() {
+                        tokio::time::sleep(STARTUP_GATE_POLL_INTERVAL).await;
+                    }
+                    info!("stdio connection opened");
 
-            tokio::task::spawn(serve).await.map_err(|e| e.into())
+                    Connection::new(
+                        stdio_transport(),
+                        adapter_tx,
+                        &config,
+                        None,
+                        &connections,
+                        None,
+                        global_store_reader,
+                        legacy,
+                        update_statuses,
+                    )
+                    .consume()
+                    .await
+                }
+                .instrument(info_span!("connection", transport = "stdio")),
+            )
         }
     }
 
@@ -685,21 +1468,24 @@ pub mod rpc {
                 Config,
                 Server,
             },
-            crate::agent::pythd::{
-                adapter,
-                api::{
-                    rpc::{
-                        SubscribePriceParams,
-                        SubscribePriceSchedParams,
-                        UpdatePriceParams,
+            crate::agent::{
+                health::HealthReporter,
+                pythd::{
+                    adapter,
+                    api::{
+                        rpc::{
+                            SubscribePriceParams,
+                            SubscribePriceSchedParams,
+                            UpdatePriceParams,
+                        },
+                        NotifyPrice,
+                        NotifyPriceSched,
+                        PriceUpdate,
                     },
-                    NotifyPrice,
-                    NotifyPriceSched,
-                    PriceUpdate,
                 },
+                startup_gate::StartupGate,
             },
             anyhow::anyhow,
-            iobuffer::IoBuffer,
             jrpc::{
                 Id,
                 Request,
@@ -709,7 +1495,6 @@ pub mod rpc {
                 de::DeserializeOwned,
                 Serialize,
             },
-            slog_extlog::slog_test,
             soketto::handshake::{
                 Client,
                 ServerResponse,
@@ -802,7 +1587,7 @@ pub mod rpc {
             }
         }
 
-        async fn start_server() -> (TestServer, TestClient, TestAdapter, IoBuffer) {
+        async fn start_server() -> (TestServer, TestClient, TestAdapter) {
             let listen_port = portpicker::pick_unused_port().unwrap();
 
             // Create the test adapter
@@ -811,13 +1596,20 @@ pub mod rpc {
 
             // Create and spawn a server (the SUT)
             let (shutdown_tx, shutdown_rx) = broadcast::channel(10);
-            let mut log_buffer = IoBuffer::new();
-            let logger = slog_test::new_test_logger(log_buffer.clone());
             let config = Config {
                 listen_address: format!("127.0.0.1:{:}", listen_port),
                 ..Default::default()
             };
-            let server = Server::new(adapter_tx, config, logger);
+            let server = Server::new(
+                adapter_tx,
+                config,
+                HealthReporter::default().component("api_server"),
+                Default::default(),
+                Default::default(),
+                Default::default(),
+                StartupGate::new(false, &HealthReporter::default()),
+                Default::default(),
+            );
             let jh = tokio::spawn(async move {
                 server.run(shutdown_rx).await;
             });
@@ -826,13 +1618,13 @@ pub mod rpc {
             // Create a test client to interact with the server
             let test_client = TestClient::new(listen_port).await;
 
-            (test_server, test_client, test_adapter, log_buffer)
+            (test_server, test_client, test_adapter)
         }
 
         #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
         async fn json_get_product_success_test() {
             // Start and connect to the JRPC server
-            let (_test_server, mut test_client, mut test_adapter, _) = start_server().await;
+            let (_test_server, mut test_client, mut test_adapter) = start_server().await;
 
             // Make a binary request, which should be safely ignored
             let random_bytes = rand::thread_rng().gen::<[u8; 32]>();
@@ -961,7 +1753,7 @@ pub mod rpc {
         #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
         async fn json_unknown_method_error_test() {
             // Start and connect to the JRPC server
-            let (_test_server, mut test_client, _, _) = start_server().await;
+            let (_test_server, mut test_client, _) = start_server().await;
 
             // Make a request with an unknown methid
             test_client
@@ -978,14 +1770,14 @@ pub mod rpc {
             let received_json = test_client.recv_json().await;
 
             // Check that the result is what we expect
-            let expected_json = r#"{"jsonrpc":"2.0","error":{"code":-32603,"message":"Could not parse message: unknown variant `wrong_method`, expected one of `get_product_list`, `get_product`, `get_all_products`, `subscribe_price`, `notify_price`, `subscribe_price_sched`, `notify_price_sched`, `update_price`","data":null},"id":0}"#;
+            let expected_json = r#"{"jsonrpc":"2.0","error":{"code":-32603,"message":"Could not parse message: unknown variant `wrong_method`, expected one of `get_product_list`, `get_product`, `get_all_products`, `get_product_metadata`, `get_aggregate_preview`, `get_agent_info`, `subscribe_price`, `notify_price`, `subscribe_price_sched`, `notify_price_sched`, `update_price`, `get_update_status`","data":null},"id":0}"#;
             assert_eq!(received_json, expected_json);
         }
 
         #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
         async fn json_missing_request_parameters_test() {
             // Start and connect to the JRPC server
-            let (_test_server, mut test_client, _, _) = start_server().await;
+            let (_test_server, mut test_client, _) = start_server().await;
 
             // Make a request with missing parameters
             test_client
@@ -1003,7 +1795,7 @@ pub mod rpc {
         #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
         async fn json_internal_error() {
             // Start and connect to the JRPC server
-            let (_test_server, mut test_client, mut test_adapter, _) = start_server().await;
+            let (_test_server, mut test_client, mut test_adapter) = start_server().await;
 
             // Make a request
             test_client
@@ -1032,15 +1824,16 @@ pub mod rpc {
         #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
         async fn json_update_price_success() {
             // Start and connect to the JRPC server
-            let (_test_server, mut test_client, mut test_adapter, _) = start_server().await;
+            let (_test_server, mut test_client, mut test_adapter) = start_server().await;
 
             // Make a request to update the price
             let status = "trading";
             let params = UpdatePriceParams {
-                account: Pubkey::from("some_price_account"),
-                price:   7467,
-                conf:    892,
-                status:  status.to_string(),
+                account:   Pubkey::from("some_price_account"),
+                price:     7467,
+                conf:      892,
+                status:    status.to_string(),
+                publisher: None,
             };
             test_client
                 .send(Request::with_params(
@@ -1057,22 +1850,36 @@ pub mod rpc {
                     account,
                     price,
                     conf,
-                    status
-                } if account == params.account && price == params.price && conf == params.conf && status == params.status
+                    status,
+                    publisher,
+                    seq,
+                    ..
+                } if account == params.account && price == params.price && conf == params.conf && status == params.status && publisher == params.publisher && seq == 1
             ));
 
             // Get the result back
             let received_json = test_client.recv_json().await;
 
-            // Assert that the result is what we expect
-            let expected_json = r#"{"jsonrpc":"2.0","result":0,"id":15}"#;
+            // Assert that the result is the sequence id of the update
+            let expected_json = r#"{"jsonrpc":"2.0","result":1,"id":15}"#;
             assert_eq!(received_json, expected_json);
+
+            // The update is accepted, but not stored nor packed by the test adapter
+            test_client
+                .send(Request::with_params(
+                    Id::from(16),
+                    "get_update_status".to_string(),
+                    serde_json::json!({"seq": 1}),
+                ))
+                .await;
+            let expected_json = r#"{"jsonrpc":"2.0","result":{"seq":1,"account":"some_price_account","status":"accepted","networks":{}},"id":16}"#;
+            assert_eq!(test_client.recv_json().await, expected_json);
         }
 
         #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
         async fn get_product_list_success_test() {
             // Start and connect to the JRPC server
-            let (_test_server, mut test_client, mut test_adapter, _) = start_server().await;
+            let (_test_server, mut test_client, mut test_adapter) = start_server().await;
 
             // Define the data we are working with
             let product_account = Pubkey::from("some_product_account");
@@ -1124,7 +1931,7 @@ pub mod rpc {
         #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
         async fn get_all_products_success() {
             // Start and connect to the JRPC server
-            let (_test_server, mut test_client, mut test_adapter, _) = start_server().await;
+            let (_test_server, mut test_client, mut test_adapter) = start_server().await;
 
             // Define the data we are working with
             let data = vec![ProductAccount {
@@ -1194,7 +2001,7 @@ pub mod rpc {
         #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
         async fn subscribe_price_success() {
             // Start and connect to the JRPC server
-            let (_test_server, mut test_client, mut test_adapter, _) = start_server().await;
+            let (_test_server, mut test_client, mut test_adapter) = start_server().await;
 
             // Make a SubscribePrice request
             let price_account = Pubkey::from("some_price_account");
@@ -1203,7 +2010,8 @@ pub mod rpc {
                     Id::from(13),
                     "subscribe_price".to_string(),
                     SubscribePriceParams {
-                        account: price_account,
+                        account:      price_account,
+                        capabilities: vec![],
                     },
                 ))
                 .await;
@@ -1215,7 +2023,10 @@ pub mod rpc {
                     account: _,
                     notify_price_tx,
                     result_tx,
+                    price_changes,
                 } => {
+                    assert!(!price_changes);
+
                     // Send the subscription ID from the adapter to the server
                     let subscription_id = SubscriptionID::from(16);
                     result_tx.send(Ok(subscription_id)).unwrap();
@@ -1235,6 +2046,7 @@ pub mod rpc {
                             status:     "trading".to_string(),
                             valid_slot: 6786,
                             pub_slot:   9897,
+                            changes:    None,
                         },
                     };
                     notify_price_tx.send(notify_price_update).await.unwrap();
@@ -1253,7 +2065,7 @@ pub mod rpc {
         #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
         async fn subscribe_price_sched_success() {
             // Start and connect to the JRPC server
-            let (_test_server, mut test_client, mut test_adapter, _) = start_server().await;
+            let (_test_server, mut test_client, mut test_adapter) = start_server().await;
 
             // Make a SubscribePriceSched request
             let price_account = Pubkey::from("some_price_account");
@@ -1309,8 +2121,7 @@ pub mod rpc {
         #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
         async fn batch_request_partial_failure() {
             // Start and connect to the JRPC server
-            let (_test_server, mut test_client, mut test_adapter, mut log_buffer) =
-                start_server().await;
+            let (_test_server, mut test_client, mut test_adapter) = start_server().await;
 
             let product_account_key = "some_product_account".to_string();
             let valid_params = GetProductParams {
@@ -1452,11 +2263,6 @@ pub mod rpc {
                 ]
                 );
 
-            println!("Log contents:");
-            for line in log_buffer.lines() {
-                println!("{}", String::from_utf8(line).unwrap());
-            }
-
             assert_eq!(received, expected);
         }
     }
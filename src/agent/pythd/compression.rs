@@ -0,0 +1,643 @@
+// The websocket messages of the pythd API can be compressed with the permessage-deflate
+// extension (RFC 7692), which saves most of the bandwidth of the JSON notifications of
+// clients subscribing to many symbols. The websocket server of warp supports no
+// extensions, so the connections of the clients offering permessage-deflate are upgraded
+// and framed here instead, once the API server admitted them. The others keep the
+// websocket of warp.
+//
+// Both directions keep their compression context across messages unless the client
+// asks otherwise. Offers restricting the window of the server are declined, as the
+// compressor always uses the full window, and the connection is then not compressed.
+// The bytes saved are counted in the api_compression_saved_bytes metric.
+use {
+    super::api::rpc::{
+        MessageSink,
+        MessageStream,
+    },
+    crate::agent::metrics::API_METRICS,
+    anyhow::{
+        anyhow,
+        Result,
+    },
+    flate2::{
+        Compress,
+        Compression,
+        Decompress,
+        FlushCompress,
+        FlushDecompress,
+        Status,
+    },
+    futures_util::{
+        sink,
+        stream,
+    },
+    parking_lot::Mutex as SyncMutex,
+    serde::{
+        Deserialize,
+        Serialize,
+    },
+    sha1::{
+        Digest,
+        Sha1,
+    },
+    std::{
+        future::Future,
+        sync::Arc,
+    },
+    tokio::{
+        io::{
+            AsyncRead,
+            AsyncReadExt,
+            AsyncWrite,
+            AsyncWriteExt,
+            ReadHalf,
+            WriteHalf,
+        },
+        sync::Mutex,
+    },
+    warp::hyper::{
+        header,
+        upgrade::OnUpgrade,
+        Body,
+        Request,
+        Response,
+        StatusCode,
+    },
+};
+
+/// Name of the extension in the Sec-WebSocket-Extensions headers
+const EXTENSION_NAME: &str = "permessage-deflate";
+
+/// Appended to the handshake key of the client to derive the accept key
+const HANDSHAKE_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// The empty block ending each compressed message, which is left out on the
+/// wire
+const DEFLATE_TRAILER: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+
+/// Largest message accepted from a client, after decompression
+const MAX_MESSAGE_SIZE: usize = 64 << 20;
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_BINARY: u8 = 0x2;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xa;
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Compression level, from 0 (none, fastest) to 9 (best, slowest)
+    pub level: u32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self { level: 6 }
+    }
+}
+
+/// The parameters of permessage-deflate agreed on with a client
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Agreement {
+    /// The compression context of the server is reset after each message
+    server_no_context_takeover: bool,
+    /// The client resets its compression context after each message
+    client_no_context_takeover: bool,
+}
+
+impl Agreement {
+    /// Agree on the first offer of permessage-deflate in the
+    /// Sec-WebSocket-Extensions header of a client which can be accepted
+    pub fn negotiate(extensions: &str) -> Option<Self> {
+        extensions.split(',').find_map(|offer| {
+            let mut params = offer.split(';').map(str::trim);
+            if params.next()? != EXTENSION_NAME {
+                return None;
+            }
+
+            let mut agreement = Agreement::default();
+            for param in params.filter(|param| !param.is_empty()) {
+                let (name, value) = match param.split_once('=') {
+                    Some((name, value)) => (name.trim(), Some(value.trim().trim_matches('"'))),
+                    None => (param, None),
+                };
+                match (name, value) {
+                    ("server_no_context_takeover", None) => {
+                        agreement.server_no_context_takeover = true
+                    }
+                    ("client_no_context_takeover", None) => {
+                        agreement.client_no_context_takeover = true
+                    }
+                    // The compressor uses the full window
+                    ("server_max_window_bits", Some("15")) => {}
+                    // Any window of the client fits in the full window of the
+                    // decompressor, so the parameter is left out of the response
+                    ("client_max_window_bits", _) => {}
+                    _ => return None,
+                }
+            }
+            Some(agreement)
+        })
+    }
+
+    /// The Sec-WebSocket-Extensions header of the response
+    pub fn response_header(&self) -> String {
+        let mut header = EXTENSION_NAME.to_string();
+        if self.server_no_context_takeover {
+            header.push_str("; server_no_context_takeover");
+        }
+        if self.client_no_context_takeover {
+            header.push_str("; client_no_context_takeover");
+        }
+        header
+    }
+}
+
+/// The upgrade of a connection whose client offered permessage-deflate, taken
+/// out of its request before warp handles the request
+#[derive(Clone)]
+pub struct PendingUpgrade(Arc<SyncMutex<Option<Upgrade>>>);
+
+struct Upgrade {
+    on_upgrade: OnUpgrade,
+    agreement:  Agreement,
+    accept_key: String,
+    level:      u32,
+}
+
+impl PendingUpgrade {
+    /// Take the upgrade out of a websocket request offering an acceptable
+    /// permessage-deflate, and put a pending upgrade in the extensions of the
+    /// request in its place
+    pub fn intercept(request: &mut Request<Body>, config: &Config) {
+        let header = |name| {
+            request
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+        };
+        let agreement =
+            match header(header::SEC_WEBSOCKET_EXTENSIONS).and_then(Agreement::negotiate) {
+                Some(agreement) => agreement,
+                None => return,
+            };
+        let accept_key = match header(header::SEC_WEBSOCKET_KEY) {
+            Some(key) => accept_key(key),
+            None => return,
+        };
+        let on_upgrade = match request.extensions_mut().remove::<OnUpgrade>() {
+            Some(on_upgrade) => on_upgrade,
+            None => return,
+        };
+        request
+            .extensions_mut()
+            .insert(PendingUpgrade(Arc::new(SyncMutex::new(Some(Upgrade {
+                on_upgrade,
+                agreement,
+                accept_key,
+                level: config.level,
+            })))));
+    }
+
+    /// Accept the upgrade, returning the response switching protocols. Once
+    /// upgraded, the connection is handed over to the given function.
+    pub fn accept<F, U>(&self, on_connection: F) -> Option<Response<Body>>
+    where
+        F: FnOnce((MessageSink, MessageStream)) -> U + Send + 'static,
+        U: Future<Output = ()> + Send + 'static,
+    {
+        let upgrade = self.0.lock().take()?;
+        let response = Response::builder()
+            .status(StatusCode::SWITCHING_PROTOCOLS)
+            .header(header::CONNECTION, "upgrade")
+            .header(header::UPGRADE, "websocket")
+            .header(header::SEC_WEBSOCKET_ACCEPT, &upgrade.accept_key)
+            .header(
+                header::SEC_WEBSOCKET_EXTENSIONS,
+                upgrade.agreement.response_header(),
+            )
+            .body(Body::empty())
+            .expect("the headers are valid");
+
+        tokio::spawn(async move {
+            match upgrade.on_upgrade.await {
+                Ok(upgraded) => {
+                    on_connection(transport(upgraded, upgrade.level, &upgrade.agreement)).await
+                }
+                Err(err) => warn!(error = ?err, "compressed websocket upgrade failed: {:#}", err),
+            }
+        });
+        Some(response)
+    }
+}
+
+/// The Sec-WebSocket-Accept header of the response to the handshake key of a
+/// client
+pub fn accept_key(key: &str) -> String {
+    let mut sha1 = Sha1::new();
+    sha1.update(key.as_bytes());
+    sha1.update(HANDSHAKE_GUID.as_bytes());
+    base64::encode(sha1.finalize())
+}
+
+/// Compresses the messages sent to the client
+struct Deflater {
+    compress:            Compress,
+    no_context_takeover: bool,
+}
+
+impl Deflater {
+    fn new(level: u32, agreement: &Agreement) -> Self {
+        Deflater {
+            compress:            Compress::new(Compression::new(level.min(9)), false),
+            no_context_takeover: agreement.server_no_context_takeover,
+        }
+    }
+
+    fn deflate(&mut self, message: &[u8]) -> Result<Vec<u8>> {
+        let start = self.compress.total_in();
+        let mut output = Vec::with_capacity(message.len() / 2 + 64);
+        loop {
+            let consumed = (self.compress.total_in() - start) as usize;
+            self.compress
+                .compress_vec(&message[consumed..], &mut output, FlushCompress::Sync)?;
+            // The flush is complete once the output no longer fills the buffer
+            let consumed = (self.compress.total_in() - start) as usize;
+            if consumed == message.len() && output.len() < output.capacity() {
+                break;
+            }
+            output.reserve(output.capacity().max(64));
+        }
+        if output.ends_with(&DEFLATE_TRAILER) {
+            output.truncate(output.len() - DEFLATE_TRAILER.len());
+        }
+        if self.no_context_takeover {
+            self.compress.reset();
+        }
+        Ok(output)
+    }
+}
+
+/// Decompresses the messages received from the client
+struct Inflater {
+    decompress:          Decompress,
+    no_context_takeover: bool,
+}
+
+impl Inflater {
+    fn new(agreement: &Agreement) -> Self {
+        Inflater {
+            decompress:          Decompress::new(false),
+            no_context_takeover: agreement.client_no_context_takeover,
+        }
+    }
+
+    fn inflate(&mut self, payload: &[u8]) -> Result<Vec<u8>> {
+        let input = [payload, &DEFLATE_TRAILER].concat();
+        let start = self.decompress.total_in();
+        let mut output = Vec::with_capacity(input.len() * 4);
+        loop {
+            let consumed = (self.decompress.total_in() - start) as usize;
+            let status = self.decompress.decompress_vec(
+                &input[consumed..],
+                &mut output,
+                FlushDecompress::Sync,
+            )?;
+            let consumed = (self.decompress.total_in() - start) as usize;
+            if consumed == input.len() && output.len() < output.capacity() {
+                break;
+            }
+            if status == Status::BufError && output.len() < output.capacity() {
+                return Err(anyhow!("truncated compressed message"));
+            }
+            if output.len() > MAX_MESSAGE_SIZE {
+                return Err(anyhow!("message larger than {} bytes", MAX_MESSAGE_SIZE));
+            }
+            output.reserve(output.capacity());
+        }
+        if self.no_context_takeover {
+            self.decompress.reset(false);
+        }
+        Ok(output)
+    }
+}
+
+/// A frame received from the client, unmasked
+struct Frame {
+    fin:        bool,
+    compressed: bool,
+    opcode:     u8,
+    payload:    Vec<u8>,
+}
+
+async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Frame> {
+    let mut header = [0; 2];
+    reader.read_exact(&mut header).await?;
+    let fin = header[0] & 0x80 != 0;
+    let compressed = header[0] & 0x40 != 0;
+    if header[0] & 0x30 != 0 {
+        return Err(anyhow!("reserved bits set in a frame"));
+    }
+    let opcode = header[0] & 0x0f;
+    if header[1] & 0x80 == 0 {
+        return Err(anyhow!("unmasked frame from the client"));
+    }
+    let len = match header[1] & 0x7f {
+        126 => reader.read_u16().await? as u64,
+        127 => reader.read_u64().await?,
+        len => len as u64,
+    };
+    if len > MAX_MESSAGE_SIZE as u64 {
+        return Err(anyhow!("frame larger than {} bytes", MAX_MESSAGE_SIZE));
+    }
+    let mut mask = [0; 4];
+    reader.read_exact(&mut mask).await?;
+    let mut payload = vec![0; len as usize];
+    reader.read_exact(&mut payload).await?;
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+    Ok(Frame {
+        fin,
+        compressed,
+        opcode,
+        payload,
+    })
+}
+
+async fn write_frame<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    opcode: u8,
+    compressed: bool,
+    payload: &[u8],
+) -> Result<()> {
+    let mut frame = Vec::with_capacity(payload.len() + 10);
+    frame.push(0x80 | if compressed { 0x40 } else { 0 } | opcode);
+    match payload.len() {
+        len if len < 126 => frame.push(len as u8),
+        len if len <= u16::MAX as usize => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    writer.write_all(&frame).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// The text messages of a websocket compressed with permessage-deflate, over
+/// the upgraded connection of a client
+fn transport<S>(io: S, level: u32, agreement: &Agreement) -> (MessageSink, MessageStream)
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let (reader, writer) = tokio::io::split(io);
+    // Shared with the stream, which answers the pings and closes of the client
+    let writer = Arc::new(Mutex::new(writer));
+
+    let tx = sink::unfold(
+        (writer.clone(), Deflater::new(level, agreement)),
+        |(writer, mut deflater), text: String| async move {
+            let payload = deflater.deflate(text.as_bytes())?;
+            API_METRICS.record_compression(text.len(), payload.len());
+            write_frame(&mut *writer.lock().await, OPCODE_TEXT, true, &payload).await?;
+            Ok::<_, anyhow::Error>((writer, deflater))
+        },
+    );
+
+    let rx = stream::unfold(
+        Some((reader, writer, Inflater::new(agreement))),
+        |state| async move {
+            let (mut reader, writer, mut inflater) = state?;
+            match receive_message(&mut reader, &writer, &mut inflater).await {
+                Ok(Some(message)) => Some((Ok(message), Some((reader, writer, inflater)))),
+                // The client closed the connection
+                Ok(None) => None,
+                Err(err) => Some((Err(err), None)),
+            }
+        },
+    );
+
+    (Box::pin(tx), Box::pin(rx))
+}
+
+/// Receive the next data message of the client, answering the control frames
+/// received before it. Returns None once the client closed the connection, and
+/// Some(None) for binary messages.
+async fn receive_message<S: AsyncRead + AsyncWrite>(
+    reader: &mut ReadHalf<S>,
+    writer: &Mutex<WriteHalf<S>>,
+    inflater: &mut Inflater,
+) -> Result<Option<Option<String>>> {
+    let mut message: Option<(u8, bool, Vec<u8>)> = None;
+    loop {
+        let frame = read_frame(reader).await?;
+        match frame.opcode {
+            OPCODE_PING => {
+                write_frame(
+                    &mut *writer.lock().await,
+                    OPCODE_PONG,
+                    false,
+                    &frame.payload,
+                )
+                .await?;
+                continue;
+            }
+            OPCODE_PONG => continue,
+            OPCODE_CLOSE => {
+                write_frame(
+                    &mut *writer.lock().await,
+                    OPCODE_CLOSE,
+                    false,
+                    &frame.payload,
+                )
+                .await?;
+                return Ok(None);
+            }
+            OPCODE_TEXT | OPCODE_BINARY if message.is_none() => {
+                message = Some((frame.opcode, frame.compressed, frame.payload));
+            }
+            OPCODE_CONTINUATION if !frame.compressed => match &mut message {
+                Some((_, _, payload))
+                    if payload.len() + frame.payload.len() <= MAX_MESSAGE_SIZE =>
+                {
+                    payload.extend_from_slice(&frame.payload)
+                }
+                Some(_) => return Err(anyhow!("message larger than {} bytes", MAX_MESSAGE_SIZE)),
+                None => return Err(anyhow!("continuation frame without a message")),
+            },
+            opcode => return Err(anyhow!("unexpected frame with opcode {:#x}", opcode)),
+        }
+        if !frame.fin {
+            continue;
+        }
+
+        let (opcode, compressed, payload) = message.take().expect("a message was started");
+        let payload = if compressed {
+            inflater.inflate(&payload)?
+        } else {
+            payload
+        };
+        return Ok(Some(match opcode {
+            OPCODE_TEXT => Some(String::from_utf8(payload)?),
+            _ => None,
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            accept_key,
+            read_frame,
+            transport,
+            write_frame,
+            Agreement,
+            Deflater,
+            Frame,
+            Inflater,
+            OPCODE_TEXT,
+        },
+        futures_util::{
+            SinkExt,
+            StreamExt,
+        },
+        tokio::io::{
+            self,
+            AsyncRead,
+            AsyncReadExt,
+            AsyncWriteExt,
+        },
+    };
+
+    /// Read a short frame sent by the server, which is not masked
+    async fn read_server_frame<R: AsyncRead + Unpin>(reader: &mut R) -> Frame {
+        let mut header = [0; 2];
+        reader.read_exact(&mut header).await.unwrap();
+        let mut payload = vec![0; (header[1] & 0x7f) as usize];
+        reader.read_exact(&mut payload).await.unwrap();
+
+        // A zero mask is spliced in, as read_frame expects masked frames
+        let mut masked = vec![header[0], header[1] | 0x80, 0, 0, 0, 0];
+        masked.extend(payload);
+        read_frame(&mut &masked[..]).await.unwrap()
+    }
+
+    #[test]
+    fn test_accept_key() {
+        // The example of RFC 6455
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[test]
+    fn test_the_first_acceptable_offer_is_agreed_on() {
+        assert_eq!(Agreement::negotiate("x-webkit-deflate-frame"), None);
+        assert_eq!(
+            Agreement::negotiate("permessage-deflate; client_max_window_bits"),
+            Some(Agreement::default())
+        );
+        assert_eq!(
+            Agreement::negotiate(
+                "permessage-deflate; server_max_window_bits=10, \
+                 permessage-deflate; server_no_context_takeover"
+            ),
+            Some(Agreement {
+                server_no_context_takeover: true,
+                client_no_context_takeover: false,
+            })
+        );
+        assert_eq!(
+            Agreement::negotiate("permessage-deflate; server_max_window_bits=10"),
+            None
+        );
+        assert_eq!(
+            Agreement {
+                server_no_context_takeover: true,
+                client_no_context_takeover: true,
+            }
+            .response_header(),
+            "permessage-deflate; server_no_context_takeover; client_no_context_takeover"
+        );
+    }
+
+    #[test]
+    fn test_messages_are_inflated_across_the_compression_context() {
+        let agreement = Agreement::default();
+        let mut deflater = Deflater::new(6, &agreement);
+        let mut inflater = Inflater::new(&agreement);
+
+        let message = br#"{"jsonrpc":"2.0","method":"notify_price","params":{}}"#.repeat(100);
+        let first = deflater.deflate(&message).unwrap();
+        assert!(first.len() < message.len() / 10);
+        // The second message refers back to the first one
+        let second = deflater.deflate(&message).unwrap();
+        assert!(second.len() < first.len());
+
+        assert_eq!(inflater.inflate(&first).unwrap(), message);
+        assert_eq!(inflater.inflate(&second).unwrap(), message);
+    }
+
+    #[tokio::test]
+    async fn test_transport_exchanges_compressed_text_messages() {
+        let (server, client) = io::duplex(1 << 16);
+        let agreement = Agreement::default();
+        let (mut tx, mut rx) = transport(server, 6, &agreement);
+        let (mut client_reader, mut client_writer) = io::split(client);
+
+        // Masked frames from the client, the request being compressed and
+        // fragmented, around a ping
+        let request = br#"{"jsonrpc":"2.0","method":"get_product_list","id":1}"#;
+        let compressed = Deflater::new(6, &agreement).deflate(request).unwrap();
+        let (first, rest) = compressed.split_at(compressed.len() / 2);
+        let mask = [1, 2, 3, 4];
+        for (header, payload) in [(0x41, first), (0x89, &b"ping"[..]), (0x80, rest)] {
+            let mut frame = vec![header, 0x80 | payload.len() as u8];
+            frame.extend_from_slice(&mask);
+            frame.extend(
+                payload
+                    .iter()
+                    .enumerate()
+                    .map(|(i, byte)| byte ^ mask[i % 4]),
+            );
+            client_writer.write_all(&frame).await.unwrap();
+        }
+        assert_eq!(
+            rx.next().await.unwrap().unwrap().as_deref(),
+            Some(std::str::from_utf8(request).unwrap())
+        );
+
+        // Unmasked frames from the server, after the pong
+        let pong = read_server_frame(&mut client_reader).await;
+        assert_eq!((pong.opcode, pong.payload), (0xa, b"ping".to_vec()));
+
+        tx.send("{\"result\":[]}".to_string()).await.unwrap();
+        let response = read_server_frame(&mut client_reader).await;
+        assert!(response.fin && response.compressed);
+        assert_eq!(response.opcode, OPCODE_TEXT);
+        assert_eq!(
+            Inflater::new(&agreement)
+                .inflate(&response.payload)
+                .unwrap(),
+            b"{\"result\":[]}"
+        );
+
+        // The stream ends once the client closes the connection
+        let mut close = Vec::new();
+        write_frame(&mut close, 0x8, false, &[]).await.unwrap();
+        close[1] |= 0x80;
+        close.extend_from_slice(&[0, 0, 0, 0]);
+        client_writer.write_all(&close).await.unwrap();
+        assert!(rx.next().await.is_none());
+    }
+}
@@ -0,0 +1,197 @@
+// The connections of the pythd API server are registered here for the Admin API, so
+// that operators can tell whether a client whose publishing stopped is even connected.
+// Each connection reports the messages it receives, the prices it updates and its
+// subscriptions, and is removed from the registry when it closes.
+//
+// The pythd API is unauthenticated, so the identity of a connection is the publish keys
+// it submitted updates on behalf of, with the default publish key shown as "default".
+use {
+    chrono::Utc,
+    parking_lot::Mutex,
+    serde::Serialize,
+    std::{
+        collections::{
+            BTreeMap,
+            BTreeSet,
+        },
+        net::SocketAddr,
+        sync::{
+            atomic::{
+                AtomicU64,
+                Ordering,
+            },
+            Arc,
+        },
+    },
+    tokio::sync::mpsc,
+};
+
+/// Name of the publish key updates without a publisher are submitted for
+const DEFAULT_PUBLISHER: &str = "default";
+
+/// Returns the number of notifications queued for a connection
+type DepthSampler = Box<dyn Fn() -> usize + Send + Sync>;
+
+/// State of a connection as shown by the Admin API
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct ConnectionInfo {
+    pub id:                        u64,
+    pub remote_address:            Option<String>,
+    /// Unix timestamps of the connection and of the last message received
+    pub connected_at:              i64,
+    pub last_message_at:           Option<i64>,
+    /// Publish keys the connection submitted updates on behalf of
+    pub publishers:                BTreeSet<String>,
+    /// Number of updates submitted, by price account
+    pub updates:                   BTreeMap<String, u64>,
+    pub price_subscriptions:       usize,
+    pub price_sched_subscriptions: usize,
+    /// Notifications queued for the connection and not yet sent
+    pub queue_depth:               usize,
+}
+
+struct ConnectionState {
+    info:        ConnectionInfo,
+    queue_depth: DepthSampler,
+}
+
+/// Registry of the open connections, shared between the API server and the
+/// Admin API
+#[derive(Clone, Default)]
+pub struct ApiConnections {
+    connections: Arc<Mutex<BTreeMap<u64, ConnectionState>>>,
+    next_id:     Arc<AtomicU64>,
+}
+
+impl ApiConnections {
+    /// Register a new connection, whose queue depth is sampled from the
+    /// senders of its notification channels
+    pub fn register<P: Send + 'static, S: Send + 'static>(
+        &self,
+        remote_address: Option<SocketAddr>,
+        notify_price_tx: (&mpsc::Sender<P>, usize),
+        notify_price_sched_tx: (&mpsc::Sender<S>, usize),
+    ) -> ConnectionHandle {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let ((price_tx, price_capacity), (sched_tx, sched_capacity)) = (
+            (notify_price_tx.0.clone(), notify_price_tx.1),
+            (notify_price_sched_tx.0.clone(), notify_price_sched_tx.1),
+        );
+        self.connections.lock().insert(
+            id,
+            ConnectionState {
+                info:        ConnectionInfo {
+                    id,
+                    remote_address: remote_address.map(|address| address.to_string()),
+                    connected_at: Utc::now().timestamp(),
+                    ..Default::default()
+                },
+                queue_depth: Box::new(move || {
+                    price_capacity.saturating_sub(price_tx.capacity())
+                        + sched_capacity.saturating_sub(sched_tx.capacity())
+                }),
+            },
+        );
+        ConnectionHandle {
+            id,
+            connections: self.clone(),
+        }
+    }
+
+    /// The open connections, oldest first
+    pub fn list(&self) -> Vec<ConnectionInfo> {
+        self.connections
+            .lock()
+            .values()
+            .map(|state| ConnectionInfo {
+                queue_depth: (state.queue_depth)(),
+                ..state.info.clone()
+            })
+            .collect()
+    }
+
+    fn update(&self, id: u64, f: impl FnOnce(&mut ConnectionInfo)) {
+        if let Some(state) = self.connections.lock().get_mut(&id) {
+            f(&mut state.info)
+        }
+    }
+}
+
+/// Handle through which a connection reports its activity. The connection is
+/// removed from the registry when the handle is dropped.
+pub struct ConnectionHandle {
+    id:          u64,
+    connections: ApiConnections,
+}
+
+impl ConnectionHandle {
+    pub fn record_message(&self) {
+        self.connections.update(self.id, |info| {
+            info.last_message_at = Some(Utc::now().timestamp())
+        });
+    }
+
+    pub fn record_update(&self, price_account: &str, publisher: Option<&str>) {
+        self.connections.update(self.id, |info| {
+            *info.updates.entry(price_account.to_string()).or_default() += 1;
+            info.publishers
+                .insert(publisher.unwrap_or(DEFAULT_PUBLISHER).to_string());
+        });
+    }
+
+    pub fn record_price_subscription(&self) {
+        self.connections
+            .update(self.id, |info| info.price_subscriptions += 1);
+    }
+
+    pub fn record_price_sched_subscription(&self) {
+        self.connections
+            .update(self.id, |info| info.price_sched_subscriptions += 1);
+    }
+}
+
+impl Drop for ConnectionHandle {
+    fn drop(&mut self) {
+        self.connections.connections.lock().remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::ApiConnections,
+        tokio::sync::mpsc,
+    };
+
+    #[test]
+    fn test_connections_report_their_activity_until_closed() {
+        let connections = ApiConnections::default();
+        let (notify_price_tx, _notify_price_rx) = mpsc::channel(10);
+        let (notify_price_sched_tx, _notify_price_sched_rx) = mpsc::channel::<()>(10);
+        let connection = connections.register(
+            Some("127.0.0.1:5000".parse().unwrap()),
+            (&notify_price_tx, 10),
+            (&notify_price_sched_tx, 10),
+        );
+
+        connection.record_message();
+        connection.record_update("price", None);
+        connection.record_update("price", Some("publisher"));
+        connection.record_price_subscription();
+        notify_price_tx.try_send(()).unwrap();
+
+        let info = connections.list().pop().unwrap();
+        assert_eq!(info.remote_address.as_deref(), Some("127.0.0.1:5000"));
+        assert!(info.last_message_at.is_some());
+        assert_eq!(info.updates.get("price"), Some(&2));
+        assert_eq!(
+            info.publishers.into_iter().collect::<Vec<_>>(),
+            vec!["default", "publisher"]
+        );
+        assert_eq!(info.price_subscriptions, 1);
+        assert_eq!(info.queue_depth, 1);
+
+        drop(connection);
+        assert!(connections.list().is_empty());
+    }
+}
@@ -0,0 +1,118 @@
+// Clients sometimes scale their prices with the wrong exponent, e.g. sending a price in
+// units of 1e-6 for a price account whose exponent is -8. Such updates pass the price
+// bounds of the Local Store unless they are set tightly, and are published as prices
+// off by a power of ten. The pythd API compares the trading price updates with the most
+// recent trading aggregate of their price account, and rejects those whose ratio to it
+// is close to a power of ten far from one, reporting the suspected exponent to the
+// client. Prices which moved away from the aggregate by other factors are accepted.
+use {
+    crate::agent::solana::oracle::PriceEntry,
+    pyth_sdk_solana::state::PriceStatus,
+    serde::{
+        Deserialize,
+        Serialize,
+    },
+};
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Updates are rejected when off from the aggregate by at least this
+    /// many powers of ten
+    pub min_powers_of_ten: u32,
+    /// How far, in powers of ten, the ratio of the update to the aggregate may
+    /// be from a whole power of ten to count as off by it
+    pub tolerance:         f64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            min_powers_of_ten: 2,
+            tolerance:         0.2,
+        }
+    }
+}
+
+/// An update which looks scaled with the wrong exponent
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExponentMismatch {
+    /// Exponent of the price account
+    pub expo:           i32,
+    /// Exponent the update looks scaled with
+    pub suspected_expo: i32,
+}
+
+impl Config {
+    /// Compare the price of a trading update with the aggregate of its price
+    /// account, if it is trading
+    pub fn check(&self, price: i64, price_account: &PriceEntry) -> Option<ExponentMismatch> {
+        let aggregate = price_account.agg.price;
+        if price_account.agg.status != PriceStatus::Trading || aggregate == 0 || price == 0 {
+            return None;
+        }
+
+        let powers_of_ten = (price.unsigned_abs() as f64 / aggregate.unsigned_abs() as f64).log10();
+        let rounded = powers_of_ten.round();
+        if rounded.abs() < self.min_powers_of_ten as f64
+            || (powers_of_ten - rounded).abs() > self.tolerance
+        {
+            return None;
+        }
+
+        // A price 100 times the aggregate was scaled with an exponent lower by 2
+        Some(ExponentMismatch {
+            expo:           price_account.expo,
+            suspected_expo: price_account.expo - rounded as i32,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            Config,
+            ExponentMismatch,
+        },
+        crate::agent::solana::oracle::PriceEntry,
+        pyth_sdk_solana::state::PriceStatus,
+    };
+
+    #[test]
+    fn test_updates_off_by_powers_of_ten_are_detected() {
+        let mut price_account = PriceEntry::default();
+        price_account.expo = -8;
+        price_account.agg.price = 60_000 * 10_i64.pow(8);
+        price_account.agg.status = PriceStatus::Trading;
+        let config = Config::default();
+
+        // Prices near the aggregate, or far from it by other factors, pass
+        assert_eq!(config.check(61_000 * 10_i64.pow(8), &price_account), None);
+        assert_eq!(
+            config.check(60_000 * 10_i64.pow(8) * 5, &price_account),
+            None
+        );
+        assert_eq!(config.check(60_000 * 10_i64.pow(9), &price_account), None);
+
+        // Prices scaled with exponents -6 and -10
+        assert_eq!(
+            config.check(60_500 * 10_i64.pow(6), &price_account),
+            Some(ExponentMismatch {
+                expo:           -8,
+                suspected_expo: -6,
+            })
+        );
+        assert_eq!(
+            config.check(59_800 * 10_i64.pow(10), &price_account),
+            Some(ExponentMismatch {
+                expo:           -8,
+                suspected_expo: -10,
+            })
+        );
+
+        // Nothing is checked against an aggregate which is not trading
+        price_account.agg.status = PriceStatus::Unknown;
+        assert_eq!(config.check(60_000 * 10_i64.pow(6), &price_account), None);
+    }
+}
@@ -0,0 +1,223 @@
+// The pythd API speaks one of two wire schemas. The current schema is the one the
+// methods of the API are defined with. The legacy schema is the dialect of older
+// publishing clients of the pyth-client daemon, which name some methods and fields
+// differently, so that those clients connect unchanged.
+//
+// A connection speaks the schema given by its `schema` query parameter, e.g.
+// `ws://127.0.0.1:8910/?schema=legacy`, or the default schema of the server otherwise.
+// The legacy names are configured as aliases of the current ones: the method names and
+// object keys of the requests of legacy connections are translated to the current names
+// before they are parsed, and those of their responses and notifications back to the
+// legacy names before they are sent. Names without an alias are the same in both.
+use {
+    serde::{
+        Deserialize,
+        Serialize,
+    },
+    serde_json::{
+        Map,
+        Value,
+    },
+    std::{
+        collections::HashMap,
+        sync::Arc,
+    },
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WireSchema {
+    Current,
+    Legacy,
+}
+
+impl WireSchema {
+    /// The schema named by the `schema` query parameter of a connection
+    pub fn from_query(value: &str) -> Option<Self> {
+        match value {
+            "current" => Some(WireSchema::Current),
+            "legacy" => Some(WireSchema::Legacy),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Schema of the connections which don't select one
+    pub default_schema: WireSchema,
+    /// Legacy names of the methods, by their current name, e.g.
+    /// {update_price = "upd_price"}
+    pub method_aliases: HashMap<String, String>,
+    /// Legacy names of the object keys, by their current name, e.g.
+    /// {price_sched = "sched"}
+    pub field_aliases:  HashMap<String, String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            default_schema: WireSchema::Current,
+            method_aliases: HashMap::new(),
+            field_aliases:  HashMap::new(),
+        }
+    }
+}
+
+/// Translates the messages of legacy connections, shared between them
+#[derive(Clone, Debug, Default)]
+pub struct LegacyTranslation {
+    inner: Arc<Aliases>,
+}
+
+#[derive(Debug, Default)]
+struct Aliases {
+    /// Current names by legacy name
+    methods_from_legacy: HashMap<String, String>,
+    fields_from_legacy:  HashMap<String, String>,
+    /// Legacy names by current name
+    methods_to_legacy:   HashMap<String, String>,
+    fields_to_legacy:    HashMap<String, String>,
+}
+
+impl LegacyTranslation {
+    pub fn new(config: &Config) -> Self {
+        let reverse = |aliases: &HashMap<String, String>| {
+            aliases
+                .iter()
+                .map(|(current, legacy)| (legacy.clone(), current.clone()))
+                .collect()
+        };
+        LegacyTranslation {
+            inner: Arc::new(Aliases {
+                methods_from_legacy: reverse(&config.method_aliases),
+                fields_from_legacy:  reverse(&config.field_aliases),
+                methods_to_legacy:   config.method_aliases.clone(),
+                fields_to_legacy:    config.field_aliases.clone(),
+            }),
+        }
+    }
+
+    /// Translate a request, or a batch of them, from the legacy schema
+    pub fn from_legacy(&self, value: Value) -> Value {
+        translate_message(
+            value,
+            &self.inner.methods_from_legacy,
+            &self.inner.fields_from_legacy,
+        )
+    }
+
+    /// Translate a response, a notification, or a batch of them, to the
+    /// legacy schema
+    pub fn to_legacy(&self, value: Value) -> Value {
+        translate_message(
+            value,
+            &self.inner.methods_to_legacy,
+            &self.inner.fields_to_legacy,
+        )
+    }
+}
+
+/// Rename the method of a JSON-RPC message and the keys of its params or
+/// result. The keys of the envelope itself are the same in both schemas.
+fn translate_message(
+    value: Value,
+    methods: &HashMap<String, String>,
+    fields: &HashMap<String, String>,
+) -> Value {
+    match value {
+        Value::Array(messages) => Value::Array(
+            messages
+                .into_iter()
+                .map(|message| translate_message(message, methods, fields))
+                .collect(),
+        ),
+        Value::Object(message) => Value::Object(
+            message
+                .into_iter()
+                .map(|(key, value)| {
+                    let value = match (key.as_str(), value) {
+                        ("method", Value::String(method)) => {
+                            Value::String(methods.get(&method).cloned().unwrap_or(method))
+                        }
+                        ("params" | "result", value) => translate_fields(value, fields),
+                        (_, value) => value,
+                    };
+                    (key, value)
+                })
+                .collect(),
+        ),
+        value => value,
+    }
+}
+
+fn translate_fields(value: Value, fields: &HashMap<String, String>) -> Value {
+    match value {
+        Value::Array(values) => Value::Array(
+            values
+                .into_iter()
+                .map(|value| translate_fields(value, fields))
+                .collect(),
+        ),
+        Value::Object(object) => Value::Object(
+            object
+                .into_iter()
+                .map(|(key, value)| {
+                    (
+                        fields.get(&key).cloned().unwrap_or(key),
+                        translate_fields(value, fields),
+                    )
+                })
+                .collect::<Map<_, _>>(),
+        ),
+        value => value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            Config,
+            LegacyTranslation,
+        },
+        serde_json::json,
+    };
+
+    #[test]
+    fn test_legacy_names_are_translated_both_ways() {
+        let translation = LegacyTranslation::new(&Config {
+            method_aliases: [("update_price".to_string(), "upd_price".to_string())].into(),
+            field_aliases: [("account".to_string(), "price_account".to_string())].into(),
+            ..Default::default()
+        });
+
+        assert_eq!(
+            translation.from_legacy(json!({
+                "jsonrpc": "2.0",
+                "method": "upd_price",
+                "params": {"price_account": "key", "price": 42},
+                "id": 1,
+            })),
+            json!({
+                "jsonrpc": "2.0",
+                "method": "update_price",
+                "params": {"account": "key", "price": 42},
+                "id": 1,
+            })
+        );
+
+        // Results are translated however deeply their keys are nested
+        assert_eq!(
+            translation.to_legacy(json!([
+                {"jsonrpc": "2.0", "result": [{"account": "key", "price": [{"account": "k"}]}], "id": 1},
+                {"jsonrpc": "2.0", "method": "notify_price", "params": {"subscription": 1}},
+            ])),
+            json!([
+                {"jsonrpc": "2.0", "result": [{"price_account": "key", "price": [{"price_account": "k"}]}], "id": 1},
+                {"jsonrpc": "2.0", "method": "notify_price", "params": {"subscription": 1}},
+            ])
+        );
+    }
+}
@@ -0,0 +1,456 @@
+// The Redis Mirror publishes the price updates accepted by the Local Store and the
+// new aggregates observed by the Global Store on Redis pub/sub channels keyed by
+// symbol, so that internal consumers can subscribe to them instead of polling the
+// dashboard. Local updates are published on "<prefix>.local.<symbol>" and
+// aggregates on "<prefix>.global.<symbol>", as JSON. Prices whose symbol is not
+// known yet are keyed by their price account instead.
+//
+// Updates are mirrored on a best-effort basis: while Redis is unreachable they are
+// dropped, and the connection is attempted again with an exponential backoff.
+use {
+    crate::agent::{
+        dump::redacted,
+        metrics::{
+            RedisMirrorMetrics,
+            PROMETHEUS_REGISTRY,
+        },
+        pythd::adapter::Adapter,
+        solana::oracle::PriceEntry,
+        store::{
+            global,
+            local,
+        },
+    },
+    anyhow::{
+        Context,
+        Result,
+    },
+    redis::AsyncCommands,
+    serde::{
+        Deserialize,
+        Serialize,
+    },
+    solana_sdk::pubkey::Pubkey,
+    std::{
+        collections::HashMap,
+        fmt,
+        time::Duration,
+    },
+    tokio::{
+        sync::broadcast,
+        task::JoinHandle,
+        time::{
+            self,
+            Instant,
+        },
+    },
+    tracing::Instrument,
+};
+
+#[derive(Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// URL of the Redis server, e.g. "redis://127.0.0.1:6379". The mirror is
+    /// disabled when not set.
+    pub url:                 Option<String>,
+    /// Prefix of the channel names
+    pub channel_prefix:      String,
+    /// Whether to mirror the updates accepted by the Local Store
+    pub local_updates:       bool,
+    /// Whether to mirror the new aggregates observed by the Global Store
+    pub global_updates:      bool,
+    /// Timeout of the connection attempts and of the publish commands
+    #[serde(with = "humantime_serde")]
+    pub timeout:             Duration,
+    /// Delay before the first attempt to reconnect, doubled after each failed
+    /// attempt up to `max_reconnect_delay`
+    #[serde(with = "humantime_serde")]
+    pub reconnect_delay:     Duration,
+    #[serde(with = "humantime_serde")]
+    pub max_reconnect_delay: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            url:                 None,
+            channel_prefix:      "pyth".to_string(),
+            local_updates:       true,
+            global_updates:      true,
+            timeout:             Duration::from_secs(1),
+            reconnect_delay:     Duration::from_secs(1),
+            max_reconnect_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl fmt::Debug for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Config")
+            .field("url", &self.url.as_deref().map(redact_credentials))
+            .field("channel_prefix", &self.channel_prefix)
+            .field("local_updates", &self.local_updates)
+            .field("global_updates", &self.global_updates)
+            .field("timeout", &self.timeout)
+            .field("reconnect_delay", &self.reconnect_delay)
+            .field("max_reconnect_delay", &self.max_reconnect_delay)
+            .finish()
+    }
+}
+
+/// The URL with its credentials redacted, e.g. "redis://:password@host:6379"
+/// is shown as "redis://<redacted>@host:6379"
+fn redact_credentials(url: &str) -> String {
+    let authority_start = url.find("://").map_or(0, |scheme_end| scheme_end + 3);
+    let authority_end = url[authority_start..]
+        .find(|c: char| c == '/' || c == '?')
+        .map_or(url.len(), |end| authority_start + end);
+    match url[authority_start..authority_end].rfind('@') {
+        Some(at) => format!(
+            "{}{}{}",
+            &url[..authority_start],
+            redacted(Some(&url[authority_start..authority_start + at])).unwrap_or_default(),
+            &url[authority_start + at..]
+        ),
+        None => url.to_string(),
+    }
+}
+
+/// An update accepted by the Local Store
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct LocalUpdate {
+    pub symbol:        Option<String>,
+    pub price_account: String,
+    /// The publisher the update was submitted for, if not the default one
+    pub publisher:     Option<String>,
+    pub price:         i64,
+    pub conf:          u64,
+    pub status:        String,
+    pub timestamp:     i64,
+}
+
+/// A new aggregate observed by the Global Store
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct GlobalUpdate {
+    pub symbol:        Option<String>,
+    pub price_account: String,
+    pub price:         i64,
+    pub conf:          u64,
+    pub expo:          i32,
+    pub status:        String,
+    pub pub_slot:      u64,
+    pub timestamp:     i64,
+}
+
+pub fn spawn_mirror(
+    config: Config,
+    local_store_events_rx: broadcast::Receiver<local::Event>,
+    global_store_events_rx: broadcast::Receiver<global::Event>,
+    global_store_reader: global::SnapshotReader,
+) -> Result<JoinHandle<()>> {
+    let client =
+        redis::Client::open(config.url.clone().unwrap_or_default()).context("invalid Redis URL")?;
+    Ok(tokio::spawn(
+        async move {
+            Mirror::new(
+                config,
+                client,
+                local_store_events_rx,
+                global_store_events_rx,
+                global_store_reader,
+            )
+            .await
+            .run()
+            .await
+        }
+        .instrument(info_span!("redis_mirror")),
+    ))
+}
+
+struct Mirror {
+    client:                 redis::Client,
+    /// Connection to the server, dropped when a command fails
+    connection:             Option<redis::aio::Connection>,
+    /// Earliest time of the next connection attempt
+    next_attempt_at:        Instant,
+    /// Delay before the next connection attempt, if it fails
+    reconnect_delay:        Duration,
+    /// Slot of the last mirrored aggregate of each price account
+    aggregate_slots:        HashMap<Pubkey, u64>,
+    metrics:                RedisMirrorMetrics,
+    local_store_events_rx:  broadcast::Receiver<local::Event>,
+    global_store_events_rx: broadcast::Receiver<global::Event>,
+    global_store_reader:    global::SnapshotReader,
+    config:                 Config,
+}
+
+impl Mirror {
+    async fn new(
+        config: Config,
+        client: redis::Client,
+        local_store_events_rx: broadcast::Receiver<local::Event>,
+        global_store_events_rx: broadcast::Receiver<global::Event>,
+        global_store_reader: global::SnapshotReader,
+    ) -> Self {
+        Mirror {
+            client,
+            connection: None,
+            next_attempt_at: Instant::now(),
+            reconnect_delay: config.reconnect_delay,
+            aggregate_slots: HashMap::new(),
+            metrics: RedisMirrorMetrics::new(&mut &mut PROMETHEUS_REGISTRY.lock().await),
+            local_store_events_rx,
+            global_store_events_rx,
+            global_store_reader,
+            config,
+        }
+    }
+
+    async fn run(&mut self) {
+        loop {
+            let (channel, payload) = tokio::select! {
+                event = self.local_store_events_rx.recv() => match event {
+                    Ok(local::Event::PriceUpdated { publisher, price_identifier, price_info, .. }) => {
+                        if !self.config.local_updates {
+                            continue;
+                        }
+                        let price_key = Pubkey::new_from_array(price_identifier.to_bytes());
+                        let update = LocalUpdate {
+                            symbol:        self.symbol(&price_key),
+                            price_account: price_key.to_string(),
+                            publisher:     publisher.map(|publisher| publisher.to_string()),
+                            price:         price_info.price,
+                            conf:          price_info.conf,
+                            status:        Adapter::price_status_to_str(price_info.status),
+                            timestamp:     price_info.timestamp,
+                        };
+                        (self.channel("local", &update.symbol, &price_key), serde_json::to_string(&update))
+                    }
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!(skipped, "Redis mirror: missed local store events");
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                event = self.global_store_events_rx.recv() => match event {
+                    Ok(global::Event::PriceUpdated { account_key, account, .. }) => {
+                        if !self.config.global_updates {
+                            continue;
+                        }
+                        match self.global_update(account_key, &account) {
+                            Some(update) => (
+                                self.channel("global", &update.symbol, &account_key),
+                                serde_json::to_string(&update),
+                            ),
+                            None => continue,
+                        }
+                    }
+                    Ok(_) => continue,
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!(skipped, "Redis mirror: missed global store events");
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+            };
+
+            match payload {
+                Ok(payload) => self.publish(channel, payload).await,
+                Err(err) => {
+                    error!(error = ?err, "Redis mirror: could not encode update: {:#}", err)
+                }
+            }
+        }
+    }
+
+    /// The update of the price account, if its aggregate was not mirrored yet
+    fn global_update(&mut self, account_key: Pubkey, account: &PriceEntry) -> Option<GlobalUpdate> {
+        match self
+            .aggregate_slots
+            .insert(account_key, account.agg.pub_slot)
+        {
+            Some(slot) if slot >= account.agg.pub_slot => None,
+            _ => Some(GlobalUpdate {
+                symbol:        self.symbol(&account_key),
+                price_account: account_key.to_string(),
+                price:         account.agg.price,
+                conf:          account.agg.conf,
+                expo:          account.expo,
+                status:        Adapter::price_status_to_str(account.agg.status),
+                pub_slot:      account.agg.pub_slot,
+                timestamp:     account.timestamp,
+            }),
+        }
+    }
+
+    /// The symbol of the price account, if known
+    fn symbol(&self, price_key: &Pubkey) -> Option<String> {
+        self.global_store_reader
+            .load()
+            .account_metadata
+            .symbol_index
+            .symbol(price_key)
+            .map(str::to_string)
+    }
+
+    fn channel(&self, kind: &str, symbol: &Option<String>, price_key: &Pubkey) -> String {
+        format!(
+            "{}.{}.{}",
+            self.config.channel_prefix,
+            kind,
+            symbol.clone().unwrap_or_else(|| price_key.to_string())
+        )
+    }
+
+    /// Publish the payload on the channel, dropping it if Redis is unreachable
+    async fn publish(&mut self, channel: String, payload: String) {
+        let timeout = self.config.timeout;
+        let connection = match self.connection().await {
+            Some(connection) => connection,
+            None => {
+                self.metrics.dropped();
+                return;
+            }
+        };
+
+        match time::timeout(timeout, connection.publish::<_, _, ()>(&channel, payload)).await {
+            Ok(Ok(())) => self.metrics.published(),
+            Ok(Err(err)) => self.disconnect(err.into(), &channel),
+            Err(err) => self.disconnect(err.into(), &channel),
+        }
+    }
+
+    /// The connection to the server, connecting again if the backoff elapsed
+    async fn connection(&mut self) -> Option<&mut redis::aio::Connection> {
+        if self.connection.is_none() && Instant::now() >= self.next_attempt_at {
+            let result = match time::timeout(
+                self.config.timeout,
+                self.client.get_async_connection(),
+            )
+            .await
+            {
+                Ok(result) => result.map_err(anyhow::Error::from),
+                Err(err) => Err(err.into()),
+            };
+            match result {
+                Ok(connection) => {
+                    info!("Redis mirror: connected");
+                    self.connection = Some(connection);
+                    self.reconnect_delay = self.config.reconnect_delay;
+                }
+                Err(err) => {
+                    warn!(
+                        error = ?err,
+                        retry_in = ?self.reconnect_delay,
+                        "Redis mirror: could not connect: {:#}",
+                        err
+                    );
+                    self.next_attempt_at = Instant::now() + self.reconnect_delay;
+                    self.reconnect_delay =
+                        (self.reconnect_delay * 2).min(self.config.max_reconnect_delay);
+                }
+            }
+        }
+        self.connection.as_mut()
+    }
+
+    fn disconnect(&mut self, err: anyhow::Error, channel: &str) {
+        warn!(error = ?err, %channel, "Redis mirror: publish failed, reconnecting: {:#}", err);
+        self.metrics.dropped();
+        self.connection = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            Config,
+            GlobalUpdate,
+            Mirror,
+        },
+        crate::agent::{
+            solana::oracle::PriceEntry,
+            store::global::{
+                ProductAccountMetadata,
+                Snapshot,
+                SnapshotReader,
+            },
+        },
+        pyth_sdk_solana::state::PriceStatus,
+        solana_sdk::pubkey::Pubkey,
+        tokio::sync::broadcast,
+    };
+
+    #[test]
+    fn test_password_is_redacted_from_debug() {
+        let debug = format!(
+            "{:?}",
+            Config {
+                url: Some("redis://:hunter2@redis.internal:6379/0".to_string()),
+                ..Default::default()
+            }
+        );
+        assert!(!debug.contains("hunter2"));
+        assert!(debug.contains("redis://<redacted>@redis.internal:6379/0"));
+    }
+
+    #[tokio::test]
+    async fn test_new_aggregates_are_mirrored_by_symbol() {
+        let price_key = Pubkey::new_unique();
+        let mut snapshot = Snapshot::default();
+        snapshot.account_metadata.insert_product(
+            Pubkey::new_unique(),
+            ProductAccountMetadata {
+                attr_dict:      [("symbol".to_string(), "Crypto.BTC/USD".to_string())]
+                    .into_iter()
+                    .collect(),
+                price_accounts: vec![price_key],
+            },
+        );
+        let mut mirror = Mirror::new(
+            Config::default(),
+            redis::Client::open("redis://127.0.0.1:6379").unwrap(),
+            broadcast::channel(1).1,
+            broadcast::channel(1).1,
+            SnapshotReader::new(snapshot),
+        )
+        .await;
+
+        let mut account = PriceEntry::default();
+        account.expo = -2;
+        account.agg.price = 100;
+        account.agg.conf = 1;
+        account.agg.status = PriceStatus::Trading;
+        account.agg.pub_slot = 10;
+        let update = mirror.global_update(price_key, &account).unwrap();
+        assert_eq!(
+            update,
+            GlobalUpdate {
+                symbol:        Some("Crypto.BTC/USD".to_string()),
+                price_account: price_key.to_string(),
+                price:         100,
+                conf:          1,
+                expo:          -2,
+                status:        "trading".to_string(),
+                pub_slot:      10,
+                timestamp:     0,
+            }
+        );
+        assert_eq!(
+            mirror.channel("global", &update.symbol, &price_key),
+            "pyth.global.Crypto.BTC/USD"
+        );
+
+        // Aggregates already mirrored are not mirrored again
+        assert!(mirror.global_update(price_key, &account).is_none());
+
+        // Prices of unknown symbols are keyed by price account
+        let unknown_key = Pubkey::new_unique();
+        let update = mirror.global_update(unknown_key, &account).unwrap();
+        assert_eq!(
+            mirror.channel("local", &update.symbol, &unknown_key),
+            format!("pyth.local.{}", unknown_key)
+        );
+    }
+}
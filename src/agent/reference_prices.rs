@@ -0,0 +1,427 @@
+// Reference prices cross-check the updates submitted to the Local Store, so that a
+// publisher feeding bad data is caught before it reaches the chain. The Reference
+// Price Poller periodically fetches prices from external HTTP sources, e.g. an
+// internal pricing service, and resolves their symbols to price accounts through
+// the Global Store. The Local Store compares each trading update against the median
+// of the reference prices of its price account: updates deviating beyond the flag
+// threshold are logged and counted, and those deviating beyond the block threshold
+// are rejected. Updates without a fresh reference price are accepted.
+use {
+    crate::agent::{
+        dump::redacted,
+        metrics::{
+            ReferencePriceMetrics,
+            PROMETHEUS_REGISTRY,
+        },
+        store::{
+            global,
+            local::{
+                PriceInfo,
+                Publisher,
+            },
+            PriceIdentifier,
+        },
+    },
+    anyhow::{
+        anyhow,
+        Context,
+        Result,
+    },
+    arc_swap::ArcSwap,
+    pyth_sdk_solana::state::PriceStatus,
+    serde::{
+        Deserialize,
+        Serialize,
+    },
+    std::{
+        collections::HashMap,
+        fmt,
+        sync::Arc,
+        time::Duration,
+    },
+    tokio::{
+        task::JoinHandle,
+        time::{
+            self,
+            Instant,
+        },
+    },
+    tracing::Instrument,
+};
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// HTTP sources of the reference prices. Cross-checking is disabled when empty.
+    pub sources:                Vec<SourceConfig>,
+    /// Duration of the interval at which the sources are polled
+    #[serde(with = "humantime_serde")]
+    pub poll_interval_duration: Duration,
+    /// Timeout of the requests to the sources
+    #[serde(with = "humantime_serde")]
+    pub timeout:                Duration,
+    /// Reference prices older than this are not used, e.g. when the sources
+    /// are unreachable
+    #[serde(with = "humantime_serde")]
+    pub max_age:                Duration,
+    /// Updates whose relative deviation from the reference price exceeds this,
+    /// e.g. 0.01 for 1%, are logged and counted
+    pub flag_deviation:         Option<f64>,
+    /// Updates whose relative deviation from the reference price exceeds this
+    /// are rejected
+    pub block_deviation:        Option<f64>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            sources:                vec![],
+            poll_interval_duration: Duration::from_secs(5),
+            timeout:                Duration::from_secs(5),
+            max_age:                Duration::from_secs(30),
+            flag_deviation:         None,
+            block_deviation:        None,
+        }
+    }
+}
+
+/// A source answering GET requests with a JSON object of prices by symbol,
+/// e.g. {"Crypto.BTC/USD": 65000.12}
+#[derive(Clone, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SourceConfig {
+    pub url:        String,
+    /// Bearer token sent with the requests, if any
+    pub auth_token: Option<String>,
+}
+
+impl fmt::Debug for SourceConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SourceConfig")
+            .field("url", &self.url)
+            .field("auth_token", &redacted(self.auth_token.as_deref()))
+            .finish()
+    }
+}
+
+/// A reference price, in the exponent-scaled units of the updates of its price
+#[derive(Clone, Copy, Debug)]
+struct ReferencePrice {
+    price:      i64,
+    fetched_at: Instant,
+}
+
+/// Latest reference prices, shared between the poller and the Local Store
+#[derive(Clone)]
+pub struct ReferencePrices {
+    config:  Config,
+    prices:  Arc<ArcSwap<HashMap<PriceIdentifier, ReferencePrice>>>,
+    metrics: Arc<ReferencePriceMetrics>,
+}
+
+impl ReferencePrices {
+    pub async fn new(config: Config) -> Self {
+        ReferencePrices {
+            config,
+            prices: Default::default(),
+            metrics: Arc::new(ReferencePriceMetrics::new(
+                &mut &mut PROMETHEUS_REGISTRY.lock().await,
+            )),
+        }
+    }
+
+    /// Check a trading update against the reference price of its price,
+    /// failing if it deviates beyond the block threshold
+    pub fn check(
+        &self,
+        publisher: &Publisher,
+        price_identifier: &PriceIdentifier,
+        price_info: &PriceInfo,
+    ) -> Result<()> {
+        if price_info.status != PriceStatus::Trading {
+            return Ok(());
+        }
+        let reference = match self.prices.load().get(price_identifier) {
+            Some(reference) if reference.fetched_at.elapsed() <= self.config.max_age => *reference,
+            _ => return Ok(()),
+        };
+        if reference.price == 0 {
+            return Ok(());
+        }
+
+        let deviation = (price_info.price as f64 - reference.price as f64).abs()
+            / (reference.price as f64).abs();
+        self.metrics.check(publisher, price_identifier, deviation);
+
+        if let Some(block_deviation) = self.config.block_deviation {
+            if deviation > block_deviation {
+                self.metrics.block(publisher, price_identifier);
+                return Err(anyhow!(
+                    "price {} deviates {:.2}% from reference price {} (max {:.2}%)",
+                    price_info.price,
+                    deviation * 100.0,
+                    reference.price,
+                    block_deviation * 100.0
+                ));
+            }
+        }
+        if let Some(flag_deviation) = self.config.flag_deviation {
+            if deviation > flag_deviation {
+                self.metrics.flag(publisher, price_identifier);
+                warn!(
+                    identifier = %price_identifier,
+                    ?publisher,
+                    price = price_info.price,
+                    reference_price = reference.price,
+                    deviation = %format!("{:.2}%", deviation * 100.0),
+                    "Reference prices: update deviates from reference price"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn set_prices(&self, prices: HashMap<PriceIdentifier, i64>) {
+        let fetched_at = Instant::now();
+        self.prices.store(Arc::new(
+            prices
+                .into_iter()
+                .map(|(price_identifier, price)| {
+                    (price_identifier, ReferencePrice { price, fetched_at })
+                })
+                .collect(),
+        ));
+    }
+}
+
+pub fn spawn_poller(
+    reference_prices: ReferencePrices,
+    global_store_reader: global::SnapshotReader,
+) -> Result<JoinHandle<()>> {
+    let mut poller = Poller::new(reference_prices, global_store_reader)?;
+    Ok(tokio::spawn(
+        async move { poller.run().await }.instrument(info_span!("reference_price_poller")),
+    ))
+}
+
+/// Fetches the reference prices from the sources
+struct Poller {
+    reference_prices:    ReferencePrices,
+    global_store_reader: global::SnapshotReader,
+    client:              reqwest::Client,
+    poll_interval:       time::Interval,
+}
+
+impl Poller {
+    fn new(
+        reference_prices: ReferencePrices,
+        global_store_reader: global::SnapshotReader,
+    ) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(reference_prices.config.timeout)
+            .build()
+            .context("building reference prices HTTP client")?;
+        Ok(Poller {
+            poll_interval: time::interval(reference_prices.config.poll_interval_duration),
+            reference_prices,
+            global_store_reader,
+            client,
+        })
+    }
+
+    async fn run(&mut self) {
+        loop {
+            self.poll_interval.tick().await;
+            self.poll().await;
+        }
+    }
+
+    /// Fetch the prices of every source, keeping the previous reference prices
+    /// if none answered
+    async fn poll(&self) {
+        let mut prices_by_symbol: HashMap<String, Vec<f64>> = HashMap::new();
+        for source in &self.reference_prices.config.sources {
+            match self.fetch(source).await {
+                Ok(prices) => {
+                    for (symbol, price) in prices {
+                        prices_by_symbol.entry(symbol).or_default().push(price);
+                    }
+                }
+                Err(err) => error!(
+                    error = ?err,
+                    url = %source.url,
+                    "Reference prices: could not fetch prices: {:#}",
+                    err
+                ),
+            }
+        }
+        if prices_by_symbol.is_empty() {
+            return;
+        }
+
+        let medians = prices_by_symbol
+            .into_iter()
+            .filter_map(|(symbol, mut prices)| Some((symbol, median(&mut prices)?)))
+            .collect();
+        self.reference_prices
+            .set_prices(resolve_prices(&self.global_store_reader.load(), &medians));
+    }
+
+    async fn fetch(&self, source: &SourceConfig) -> Result<HashMap<String, f64>> {
+        let mut request = self.client.get(&source.url);
+        if let Some(auth_token) = &source.auth_token {
+            request = request.bearer_auth(auth_token);
+        }
+        Ok(request
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<HashMap<String, f64>>()
+            .await?)
+    }
+}
+
+/// The median of the given prices, ignoring those which are not finite
+fn median(prices: &mut Vec<f64>) -> Option<f64> {
+    prices.retain(|price| price.is_finite());
+    prices.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let middle = prices.len() / 2;
+    match prices.len() {
+        0 => None,
+        len if len % 2 == 0 => Some((prices[middle - 1] + prices[middle]) / 2.0),
+        _ => Some(prices[middle]),
+    }
+}
+
+/// Resolve the reference prices of the symbols to the price accounts of their
+/// products, in the exponent-scaled units of each price account
+fn resolve_prices(
+    snapshot: &global::Snapshot,
+    prices: &HashMap<String, f64>,
+) -> HashMap<PriceIdentifier, i64> {
+    let metadata = &snapshot.account_metadata;
+    let mut resolved = HashMap::new();
+    for (symbol, price) in prices {
+        for price_key in metadata.symbol_index.price_accounts(symbol) {
+            if let Some(price_metadata) = metadata.price_accounts_metadata.get(price_key) {
+                let scaled = price * 10f64.powi(-price_metadata.expo);
+                resolved.insert(
+                    PriceIdentifier::new(price_key.to_bytes()),
+                    scaled.round() as i64,
+                );
+            }
+        }
+    }
+    resolved
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            median,
+            resolve_prices,
+            Config,
+            ReferencePrices,
+            SourceConfig,
+        },
+        crate::agent::store::{
+            global::{
+                PriceAccountMetadata,
+                ProductAccountMetadata,
+                Snapshot,
+            },
+            local::PriceInfo,
+            PriceIdentifier,
+        },
+        pyth_sdk_solana::state::PriceStatus,
+        solana_sdk::pubkey::Pubkey,
+        std::collections::HashMap,
+    };
+
+    #[test]
+    fn test_auth_token_is_redacted_from_debug() {
+        let source = SourceConfig {
+            url:        "https://prices.example.com".to_string(),
+            auth_token: Some("secret".to_string()),
+        };
+        let debug = format!("{:?}", source);
+        assert!(debug.contains("https://prices.example.com"));
+        assert!(!debug.contains("secret"));
+    }
+
+    #[tokio::test]
+    async fn test_updates_are_checked_against_reference_prices() {
+        assert_eq!(median(&mut vec![3.0, f64::NAN, 1.0, 2.0]), Some(2.0));
+        assert_eq!(median(&mut vec![4.0, 1.0]), Some(2.5));
+        assert_eq!(median(&mut vec![]), None);
+
+        // Reference prices are scaled by the exponent of their price account
+        let product_key = Pubkey::new_unique();
+        let price_key = Pubkey::new_unique();
+        let mut snapshot = Snapshot::default();
+        snapshot.account_metadata.insert_product(
+            product_key,
+            ProductAccountMetadata {
+                attr_dict:      [("symbol".to_string(), "Crypto.BTC/USD".to_string())]
+                    .into_iter()
+                    .collect(),
+                price_accounts: vec![price_key],
+            },
+        );
+        snapshot
+            .account_metadata
+            .price_accounts_metadata
+            .insert(price_key, PriceAccountMetadata { expo: -2 });
+        let identifier = PriceIdentifier::new(price_key.to_bytes());
+        let resolved = resolve_prices(
+            &snapshot,
+            &[("Crypto.BTC/USD".to_string(), 1000.0)]
+                .into_iter()
+                .collect(),
+        );
+        assert_eq!(resolved, HashMap::from([(identifier, 100_000)]));
+
+        let reference_prices = ReferencePrices::new(Config {
+            flag_deviation: Some(0.01),
+            block_deviation: Some(0.05),
+            ..Default::default()
+        })
+        .await;
+        reference_prices.set_prices(resolved);
+        let price_info = |status, price| PriceInfo {
+            status,
+            price,
+            conf: 1,
+            timestamp: 0,
+        };
+
+        // Flagged updates are accepted, blocked ones are rejected
+        assert!(reference_prices
+            .check(
+                &None,
+                &identifier,
+                &price_info(PriceStatus::Trading, 103_000)
+            )
+            .is_ok());
+        assert!(reference_prices
+            .check(
+                &None,
+                &identifier,
+                &price_info(PriceStatus::Trading, 94_000)
+            )
+            .is_err());
+        assert!(reference_prices
+            .check(&None, &identifier, &price_info(PriceStatus::Unknown, 0))
+            .is_ok());
+
+        // Prices without a reference price are accepted
+        assert!(reference_prices
+            .check(
+                &None,
+                &PriceIdentifier::new([1; 32]),
+                &price_info(PriceStatus::Trading, 1)
+            )
+            .is_ok());
+    }
+}
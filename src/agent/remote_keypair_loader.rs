@@ -1,20 +1,35 @@
 //! Remote keypair loading endpoint. Lets you hotload a keypair in
-//! runtime for publishing to the given network.
+//! runtime for publishing to the given network, under
+//! "/<network>/load_keypair". A keypair is only accepted once it is
+//! validated against the network: it must hold the minimum balance,
+//! and, if enabled, be permissioned to publish at least one price.
 //!
 use {
+    crate::agent::metrics::{
+        KeypairLoaderMetrics,
+        PROMETHEUS_REGISTRY,
+    },
     anyhow::{
+        anyhow,
         Context,
         Result,
     },
-    serde::Deserialize,
-    slog::Logger,
+    serde::{
+        Deserialize,
+        Serialize,
+    },
     solana_client::nonblocking::rpc_client::RpcClient,
     solana_sdk::{
         commitment_config::CommitmentConfig,
+        pubkey::Pubkey,
         signature::Keypair,
         signer::Signer,
     },
     std::{
+        collections::{
+            HashMap,
+            HashSet,
+        },
         net::SocketAddr,
         sync::Arc,
         time::Duration,
@@ -23,10 +38,12 @@ use {
         sync::{
             mpsc,
             oneshot,
+            watch,
             Mutex,
         },
         task::JoinHandle,
     },
+    tracing::Instrument,
     warp::{
         hyper::StatusCode,
         reply::{
@@ -48,12 +65,16 @@ pub fn default_bind_address() -> SocketAddr {
         .expect("INTERNAL: Could not build default remote keypair loader bind address")
 }
 
-#[derive(Clone, Debug, Deserialize)]
-#[serde(default)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
 pub struct Config {
-    primary_min_keypair_balance_sol:   u64,
-    secondary_min_keypair_balance_sol: u64,
-    bind_address:                      SocketAddr,
+    pub primary_min_keypair_balance_sol:   u64,
+    pub secondary_min_keypair_balance_sol: u64,
+    pub bind_address:                      SocketAddr,
+    /// Whether to reject keypairs which are not permissioned to publish
+    /// any price on the network. Keypairs are rejected until the Oracle
+    /// first read the permissions from the network.
+    pub check_publish_permission:          bool,
 }
 
 impl Default for Config {
@@ -62,6 +83,7 @@ impl Default for Config {
             primary_min_keypair_balance_sol:   default_min_keypair_balance_sol(),
             secondary_min_keypair_balance_sol: default_min_keypair_balance_sol(),
             bind_address:                      default_bind_address(),
+            check_publish_permission:          false,
         }
     }
 }
@@ -72,198 +94,180 @@ pub struct KeypairRequest {
     response_tx: oneshot::Sender<Keypair>,
 }
 
+/// A network to which keypairs can be loaded
+pub struct Network {
+    /// Name of the network, under which its keypair is loaded
+    pub name:                     String,
+    pub rpc_url:                  String,
+    /// How much whole SOL a keypair must hold to be accepted. Disabled with 0.
+    pub min_keypair_balance_sol:  u64,
+    /// Keypair requests of the network's Exporter
+    pub requests_rx:              mpsc::Receiver<KeypairRequest>,
+    /// Price accounts each publisher is permissioned to publish, as last read
+    /// from the chain by the network's Oracle
+    pub publisher_permissions_rx: watch::Receiver<HashMap<Pubkey, HashSet<Pubkey>>>,
+}
+
+/// Validation state and currently loaded keypair of a network
+struct NetworkState {
+    rpc_url:                  String,
+    min_keypair_balance_sol:  u64,
+    publisher_permissions_rx: watch::Receiver<HashMap<Pubkey, HashSet<Pubkey>>>,
+    current_keypair:          Option<Keypair>,
+}
+
 pub struct RemoteKeypairLoader {
-    primary_current_keypair:   Option<Keypair>,
-    secondary_current_keypair: Option<Keypair>,
-    primary_rpc_url:           String,
-    secondary_rpc_url:         Option<String>,
-    config:                    Config,
+    /// Loadable networks, by name
+    networks: HashMap<String, NetworkState>,
+    config:   Config,
+    metrics:  KeypairLoaderMetrics,
 }
 
 impl RemoteKeypairLoader {
-    pub async fn spawn(
-        primary_requests_rx: mpsc::Receiver<KeypairRequest>,
-        secondary_requests_rx: mpsc::Receiver<KeypairRequest>,
-        primary_rpc_url: String,
-        secondary_rpc_url: Option<String>,
-        config: Config,
-        logger: Logger,
-    ) -> Vec<JoinHandle<()>> {
+    pub async fn spawn(networks: Vec<Network>, config: Config) -> Vec<JoinHandle<()>> {
         let bind_address = config.bind_address.clone();
 
         let ip = bind_address.ip();
 
         if !ip.is_loopback() {
-            warn!(logger, "Remote key loader: bind address is not localhost. Make sure the access on the selected address is secure."; "bind_address" => bind_address,);
+            warn!(
+                %bind_address,
+                "Remote key loader: bind address is not localhost. Make sure the access on the selected address is secure."
+            );
+        }
+
+        let metrics = KeypairLoaderMetrics::new(&mut &mut PROMETHEUS_REGISTRY.lock().await);
+        let mut requests_rxs = vec![];
+        let mut network_states = HashMap::new();
+        for network in networks {
+            metrics.set_loaded(&network.name, false);
+            requests_rxs.push((network.name.clone(), network.requests_rx));
+            network_states.insert(
+                network.name,
+                NetworkState {
+                    rpc_url:                  network.rpc_url,
+                    min_keypair_balance_sol:  network.min_keypair_balance_sol,
+                    publisher_permissions_rx: network.publisher_permissions_rx,
+                    current_keypair:          None,
+                },
+            );
         }
 
         let shared_state = Arc::new(Mutex::new(Self {
-            primary_current_keypair: None,
-            secondary_current_keypair: None,
-            primary_rpc_url,
-            secondary_rpc_url,
+            networks: network_states,
             config,
+            metrics,
         }));
 
-        let request_handler_jh = tokio::spawn(handle_key_requests(
-            primary_requests_rx,
-            secondary_requests_rx,
-            shared_state.clone(),
-            logger.clone(),
-        ));
-
-        let logger4primary = logger.clone();
-        let shared_state4primary = shared_state.clone();
-        let primary_upload_route = warp::path!("primary" / "load_keypair")
-            .and(warp::post())
-            .and(warp::body::content_length_limit(1024))
-            .and(warp::body::json())
-            .and(warp::path::end())
-            .and_then(move |kp: Vec<u8>| {
-                let shared_state = shared_state4primary.clone();
-                let logger = logger4primary.clone();
-                async move {
-                    let mut locked_state = shared_state.lock().await;
-
-                    let min_balance = locked_state.config.primary_min_keypair_balance_sol;
-                    let rpc_url = locked_state.primary_rpc_url.clone();
-
-                    let response = Self::handle_new_keypair(
-                        &mut (locked_state.primary_current_keypair),
-                        kp,
-                        min_balance,
-                        rpc_url,
-                        "primary",
-                        logger,
-                    )
-                    .await;
+        let request_handler_jh = tokio::spawn(
+            handle_key_requests(requests_rxs, shared_state.clone())
+                .instrument(info_span!("remote_keypair_loader")),
+        );
 
-                    Result::<WithStatus<_>, Rejection>::Ok(response)
-                }
-            });
+        // The requests are handled outside of the loader's tasks
+        let request_span = info_span!("remote_keypair_loader");
 
-        let secondary_upload_route = warp::path!("secondary" / "load_keypair")
+        let upload_route = warp::path!(String / "load_keypair")
             .and(warp::post())
             .and(warp::body::content_length_limit(1024))
             .and(warp::body::json())
             .and(warp::path::end())
-            .and_then(move |kp: Vec<u8>| {
+            .and_then(move |network_name: String, kp: Vec<u8>| {
                 let shared_state = shared_state.clone();
-                let logger = logger.clone();
                 async move {
                     let mut locked_state = shared_state.lock().await;
+                    let response = locked_state.handle_new_keypair(&network_name, kp).await;
 
-                    if let Some(rpc_url) = locked_state.secondary_rpc_url.clone() {
-                        let min_balance = locked_state.config.secondary_min_keypair_balance_sol;
-
-                        let response = Self::handle_new_keypair(
-                            &mut (locked_state.secondary_current_keypair),
-                            kp,
-                            min_balance,
-                            rpc_url,
-                            "secondary",
-                            logger,
-                        )
-                        .await;
-
-                        Result::<WithStatus<_>, Rejection>::Ok(response)
-                    } else {
-                        Result::<WithStatus<_>, Rejection>::Ok(reply::with_status(
-                            "Secondary network is not active",
-                            StatusCode::SERVICE_UNAVAILABLE,
-                        ))
-                    }
+                    Result::<WithStatus<_>, Rejection>::Ok(response)
                 }
+                .instrument(request_span.clone())
             });
 
-        let http_api_jh = tokio::spawn(
-            warp::serve(primary_upload_route.or(secondary_upload_route)).bind(bind_address),
-        );
+        let http_api_jh = tokio::spawn(warp::serve(upload_route).bind(bind_address));
 
         // WARNING: All jobs spawned here must report their join handles in this vec
         return vec![request_handler_jh, http_api_jh];
     }
 
-    /// Validate and apply a keypair to the specified mut reference,
-    /// hiding errors in logs.
+    /// Validate and apply a keypair to the specified network, hiding
+    /// errors in logs.
     ///
     /// Returns the appropriate HTTP response depending on checks success.
-    ///
-    /// NOTE(2023-03-22): Lifetime bounds are currently necessary
-    /// because of https://github.com/rust-lang/rust/issues/63033
-    async fn handle_new_keypair<'a, 'b: 'a>(
-        keypair_slot: &'a mut Option<Keypair>,
+    async fn handle_new_keypair(
+        &mut self,
+        network_name: &str,
         new_keypair_bytes: Vec<u8>,
-        min_keypair_balance_sol: u64,
-        rpc_url: String,
-        network_name: &'b str,
-        logger: Logger,
     ) -> WithStatus<&'static str> {
-        let mut upload_ok = true;
+        let check_publish_permission = self.config.check_publish_permission;
+        let network = match self.networks.get_mut(network_name) {
+            Some(network) => network,
+            None => {
+                return reply::with_status("Network is not active", StatusCode::SERVICE_UNAVAILABLE)
+            }
+        };
+
+        let result = match Keypair::from_bytes(&new_keypair_bytes) {
+            Ok(kp) => Self::validate_keypair(&kp, network, check_publish_permission)
+                .await
+                .map(|()| kp),
+            Err(e) => Err(anyhow!(e).context("Could not parse keypair")),
+        };
 
-        match Keypair::from_bytes(&new_keypair_bytes) {
+        match result {
             Ok(kp) => {
-                match Self::validate_keypair(&kp, min_keypair_balance_sol, rpc_url.clone()).await {
-                    Ok(()) => {
-                        *keypair_slot = Some(kp);
-                    }
-                    Err(e) => {
-                        warn!(logger, "Remote keypair loader: Keypair failed validation";
-                        "network" => network_name,
-                        "error" => e.to_string(),
-                        );
-                        upload_ok = false;
-                    }
-                }
+                info!(
+                    network = network_name,
+                    pubkey = %kp.pubkey(),
+                    "Remote keypair loader: Keypair loaded"
+                );
+                self.metrics.set_loaded(network_name, true);
+                self.metrics.load_attempted(network_name, true);
+                network.current_keypair = Some(kp);
+                reply::with_status("keypair upload OK", StatusCode::OK)
             }
             Err(e) => {
-                warn!(logger, "Remote keypair loader: Keypair failed validation";
-                "network" => network_name,
-                "error" => e.to_string(),
+                warn!(
+                    network = network_name,
+                    error = %format!("{:#}", e),
+                    "Remote keypair loader: Keypair failed validation"
                 );
-                upload_ok = false;
+                self.metrics.load_attempted(network_name, false);
+                reply::with_status(
+                    "Could not upload keypair. See logs for details.",
+                    StatusCode::BAD_REQUEST,
+                )
             }
         }
-
-        if upload_ok {
-            reply::with_status("keypair upload OK", StatusCode::OK)
-        } else {
-            reply::with_status(
-                "Could not upload keypair. See logs for details.",
-                StatusCode::BAD_REQUEST,
-            )
-        }
     }
 
-    /// Validate keypair balance before using it in transactions.
-    pub async fn validate_keypair(
+    /// Validate keypair balance and publish permission before using it in
+    /// transactions.
+    async fn validate_keypair(
         kp: &Keypair,
-        min_keypair_balance_sol: u64,
-        rpc_url: String,
+        network: &NetworkState,
+        check_publish_permission: bool,
     ) -> Result<()> {
-        let c = RpcClient::new_with_commitment(rpc_url, CommitmentConfig::confirmed());
+        if check_publish_permission {
+            check_permission(&kp.pubkey(), &network.publisher_permissions_rx.borrow())?;
+        }
+
+        let c =
+            RpcClient::new_with_commitment(network.rpc_url.clone(), CommitmentConfig::confirmed());
 
         let balance_lamports = c
             .get_balance(&kp.pubkey())
             .await
             .context("Could not check keypair's balance")?;
 
-        let lamports_in_sol = 1_000_000_000;
-
-        if balance_lamports > min_keypair_balance_sol * lamports_in_sol {
-            Ok(())
-        } else {
-            Err(anyhow::anyhow!(format!(
-                "Keypair {} balance of {} SOL below threshold of {} SOL",
-                kp.pubkey(),
-                balance_lamports as f64 / lamports_in_sol as f64,
-                min_keypair_balance_sol
-            )))
-        }
+        check_balance(
+            &kp.pubkey(),
+            balance_lamports,
+            network.min_keypair_balance_sol,
+        )
     }
 
     /// Get a keypair using the specified request
-    /// sender. primary/secondary is decided by the channel the tx
+    /// sender. The network is decided by the channel the tx
     /// that request_tx comes from.
     pub async fn request_keypair(request_tx: &mpsc::Sender<KeypairRequest>) -> Result<Keypair> {
         let (tx, rx) = oneshot::channel();
@@ -274,47 +278,77 @@ impl RemoteKeypairLoader {
     }
 }
 
+/// Check that the publisher is permissioned to publish at least one price
+fn check_permission(
+    publisher: &Pubkey,
+    publisher_permissions: &HashMap<Pubkey, HashSet<Pubkey>>,
+) -> Result<()> {
+    if publisher_permissions.is_empty() {
+        return Err(anyhow!(
+            "Publish permissions not read from the network yet, try again later"
+        ));
+    }
+    if publisher_permissions
+        .get(publisher)
+        .map_or(true, HashSet::is_empty)
+    {
+        return Err(anyhow!(
+            "Keypair {} is not permissioned to publish any price",
+            publisher
+        ));
+    }
+    Ok(())
+}
+
+/// Check that the publisher holds at least the minimum balance
+fn check_balance(publisher: &Pubkey, balance_lamports: u64, min_balance_sol: u64) -> Result<()> {
+    let lamports_in_sol = 1_000_000_000;
+
+    if balance_lamports >= min_balance_sol * lamports_in_sol {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!(format!(
+            "Keypair {} balance of {} SOL below threshold of {} SOL",
+            publisher,
+            balance_lamports as f64 / lamports_in_sol as f64,
+            min_balance_sol
+        )))
+    }
+}
+
 /// Query channel receivers indefinitely, sending back the requested
 /// keypair if available.
 async fn handle_key_requests(
-    mut primary_rx: mpsc::Receiver<KeypairRequest>,
-    mut secondary_rx: mpsc::Receiver<KeypairRequest>,
+    mut requests_rxs: Vec<(String, mpsc::Receiver<KeypairRequest>)>,
     shared_state: Arc<Mutex<RemoteKeypairLoader>>,
-    logger: Logger,
 ) {
     loop {
         let locked_state = shared_state.lock().await;
 
         // Only handle requests for defined keypairs. The possibility
         // of missing keypair is the reason we are not
-        // tokio::select!()-ing on the two channel receivers.
-
-        if let Some(primary_keypair) = locked_state.primary_current_keypair.as_ref() {
-            // Drain all primary keypair requests
-            while let Ok(KeypairRequest { response_tx }) = primary_rx.try_recv() {
-                let copied_keypair = Keypair::from_bytes(&primary_keypair.to_bytes())
+        // tokio::select!()-ing on the channel receivers.
+        for (network_name, requests_rx) in requests_rxs.iter_mut() {
+            let keypair = match locked_state
+                .networks
+                .get(network_name)
+                .and_then(|network| network.current_keypair.as_ref())
+            {
+                Some(keypair) => keypair,
+                None => continue,
+            };
+
+            // Drain all keypair requests of the network
+            while let Ok(KeypairRequest { response_tx }) = requests_rx.try_recv() {
+                let copied_keypair = Keypair::from_bytes(&keypair.to_bytes())
                     .expect("INTERNAL: could not convert Keypair to bytes and back");
 
                 match response_tx.send(copied_keypair) {
                     Ok(()) => {}
                     Err(_e) => {
-                        warn!(logger, "remote_keypair_loader: Could not send back primary keypair to channel";
-                        );
-                    }
-                }
-            }
-        }
-
-        if let Some(secondary_keypair) = locked_state.secondary_current_keypair.as_ref() {
-            // Drain all secondary keypair requests
-            while let Ok(KeypairRequest { response_tx }) = secondary_rx.try_recv() {
-                let copied_keypair = Keypair::from_bytes(&secondary_keypair.to_bytes())
-                    .expect("INTERNAL: could not convert Keypair to bytes and back");
-
-                match response_tx.send(copied_keypair) {
-                    Ok(()) => {}
-                    Err(_e) => {
-                        warn!(logger, "remote_keypair_loader: Could not send back secondary keypair to channel";
+                        warn!(
+                            network = network_name.as_str(),
+                            "remote_keypair_loader: Could not send back keypair to channel"
                         );
                     }
                 }
@@ -327,3 +361,52 @@ async fn handle_key_requests(
         tokio::time::sleep(Duration::from_millis(500)).await;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            check_balance,
+            check_permission,
+            Config,
+        },
+        solana_sdk::pubkey::Pubkey,
+        std::collections::{
+            HashMap,
+            HashSet,
+        },
+    };
+
+    #[test]
+    fn test_publish_permission_is_not_checked_by_default() {
+        assert!(!Config::default().check_publish_permission);
+    }
+
+    #[test]
+    fn test_check_permission() {
+        let publisher = Pubkey::new_unique();
+        let other_publisher = Pubkey::new_unique();
+
+        // Permissions not read yet
+        assert!(check_permission(&publisher, &HashMap::new()).is_err());
+
+        let permissions = HashMap::from([
+            (publisher, HashSet::from([Pubkey::new_unique()])),
+            (other_publisher, HashSet::new()),
+        ]);
+        assert!(check_permission(&publisher, &permissions).is_ok());
+        // Permissioned to no price
+        assert!(check_permission(&other_publisher, &permissions).is_err());
+        // Unknown publisher
+        assert!(check_permission(&Pubkey::new_unique(), &permissions).is_err());
+    }
+
+    #[test]
+    fn test_check_balance() {
+        let publisher = Pubkey::new_unique();
+        assert!(check_balance(&publisher, 1_000_000_000, 1).is_ok());
+        assert!(check_balance(&publisher, 999_999_999, 1).is_err());
+        // Disabled with 0
+        assert!(check_balance(&publisher, 0, 0).is_ok());
+    }
+}
@@ -0,0 +1,526 @@
+// Replication streams the updates accepted by the Local Store of an agent to the other
+// agent of a high-availability pair, so that the standby holds the latest prices of
+// the leader, including those not published yet, when it takes over.
+//
+// Each agent serves a stream of its accepted updates over TCP, and follows the stream
+// of its peer. A follower first receives a snapshot of the Local Store contents, then
+// every update accepted since, as newline-delimited JSON frames numbered in sequence.
+// Only the standby applies the updates it receives to its Local Store, so the stream
+// of the leader is applied by the standby, and the stream of the standby is ignored by
+// the leader. The applied updates are tagged as replicated, and are not streamed back,
+// so that updates don't bounce between two standbys. A gap in the sequence, e.g. when
+// the follower falls behind, is counted and the follower reconnects to start again from
+// a new snapshot.
+//
+// The stream carries unpublished prices, so followers can be restricted to the allowed
+// networks, and required to present the auth token shared by the pair in the hello
+// line they send on connecting.
+use {
+    crate::agent::{
+        dump::redacted,
+        error::Error,
+        metrics::{
+            ReplicationMetrics,
+            PROMETHEUS_REGISTRY,
+        },
+        publish_pause::PublishPause,
+        store::local::{
+            self,
+            PersistedPriceInfo,
+        },
+    },
+    anyhow::{
+        anyhow,
+        Context as _,
+        Result,
+    },
+    ipnet::IpNet,
+    opentelemetry::Context,
+    serde::{
+        Deserialize,
+        Serialize,
+    },
+    std::{
+        fmt,
+        net::{
+            IpAddr,
+            SocketAddr,
+        },
+        sync::Arc,
+        time::{
+            Duration,
+            Instant,
+        },
+    },
+    subtle::ConstantTimeEq,
+    tokio::{
+        io::{
+            AsyncBufReadExt,
+            AsyncWriteExt,
+            BufReader,
+        },
+        net::{
+            TcpListener,
+            TcpStream,
+        },
+        sync::{
+            broadcast,
+            mpsc,
+            oneshot,
+        },
+        task::JoinHandle,
+        time,
+    },
+    tracing::Instrument,
+};
+
+/// How long a follower has to send its hello line after connecting
+const HELLO_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Address on which the updates accepted by the Local Store are streamed.
+    /// Not served when not set.
+    pub listen_address:   Option<SocketAddr>,
+    /// Address of the peer agent whose stream is followed, e.g.
+    /// "10.0.0.2:8920". Not followed when not set.
+    pub peer_address:     Option<String>,
+    /// Delay before following the peer again after the stream broke
+    #[serde(with = "humantime_serde")]
+    pub reconnect_delay:  Duration,
+    /// Frames buffered for each follower, which falls behind when more are
+    /// pending
+    pub follower_buffer:  usize,
+    /// Networks the followers are accepted from, in CIDR notation, e.g.
+    /// "10.0.0.0/8". Followers from any address are accepted when empty.
+    pub allowed_networks: Vec<IpNet>,
+    /// Token shared by the agents of the pair. When set, the followers must
+    /// present it to be streamed the updates, and it is presented to the peer.
+    /// Can be read from a file with `auth_token_file`.
+    pub auth_token:       Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            listen_address:   None,
+            peer_address:     None,
+            reconnect_delay:  Duration::from_secs(1),
+            follower_buffer:  10000,
+            allowed_networks: vec![],
+            auth_token:       None,
+        }
+    }
+}
+
+impl fmt::Debug for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Config")
+            .field("listen_address", &self.listen_address)
+            .field("peer_address", &self.peer_address)
+            .field("reconnect_delay", &self.reconnect_delay)
+            .field("follower_buffer", &self.follower_buffer)
+            .field("allowed_networks", &self.allowed_networks)
+            .field("auth_token", &redacted(self.auth_token.as_deref()))
+            .finish()
+    }
+}
+
+/// Sent by a follower as the first line of the stream
+#[derive(Debug, Serialize, Deserialize)]
+struct Hello {
+    /// The auth token of the pair, if one is set
+    token: Option<String>,
+}
+
+/// A frame of the stream, sent as a line of JSON
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Frame {
+    /// The contents of the Local Store when the follower connected
+    Snapshot { entries: Vec<PersistedPriceInfo> },
+    /// An update accepted by the Local Store
+    Update {
+        sequence: u64,
+        entry:    PersistedPriceInfo,
+    },
+}
+
+/// Checks that the updates follow each other in sequence
+#[derive(Debug, Default)]
+struct SequenceTracker {
+    last_sequence: Option<u64>,
+}
+
+impl SequenceTracker {
+    /// Record the sequence of an update, returning how many updates were
+    /// missed before it
+    fn observe(&mut self, sequence: u64) -> u64 {
+        let missed = match self.last_sequence {
+            Some(last_sequence) => sequence.saturating_sub(last_sequence + 1),
+            None => 0,
+        };
+        self.last_sequence = Some(sequence);
+        missed
+    }
+}
+
+pub async fn spawn_replication(
+    config: Config,
+    local_store_tx: mpsc::Sender<local::Message>,
+    local_store_events_rx: broadcast::Receiver<local::Event>,
+    publish_pause: PublishPause,
+) -> Result<Vec<JoinHandle<()>>> {
+    let metrics = Arc::new(ReplicationMetrics::new(
+        &mut &mut PROMETHEUS_REGISTRY.lock().await,
+    ));
+    let mut jhs = vec![];
+
+    if let Some(listen_address) = config.listen_address {
+        if config.allowed_networks.is_empty() && config.auth_token.is_none() {
+            warn!(
+                %listen_address,
+                "Replication: the unpublished prices are streamed to any follower, set \
+                 replication.allowed_networks or replication.auth_token to restrict them"
+            );
+        }
+        let listener = TcpListener::bind(listen_address)
+            .await
+            .with_context(|| format!("binding the replication stream to {}", listen_address))?;
+        let (frames_tx, _) = broadcast::channel(config.follower_buffer);
+        jhs.push(tokio::spawn(
+            number_updates(local_store_events_rx, frames_tx.clone())
+                .instrument(info_span!("replication_sequencer")),
+        ));
+        jhs.push(tokio::spawn(
+            serve(
+                listener,
+                Followers::new(&config),
+                frames_tx,
+                local_store_tx.clone(),
+                metrics.clone(),
+            )
+            .instrument(info_span!("replication_server", %listen_address)),
+        ));
+    }
+
+    if let Some(peer_address) = config.peer_address.clone() {
+        let hello = Hello {
+            token: config.auth_token.clone(),
+        };
+        jhs.push(tokio::spawn(
+            async move {
+                loop {
+                    if let Err(err) = follow(
+                        &peer_address,
+                        &hello,
+                        &local_store_tx,
+                        &publish_pause,
+                        &metrics,
+                    )
+                    .await
+                    {
+                        warn!(error = ?err, "Replication: {:#}", err);
+                    }
+                    metrics.set_following(false);
+                    time::sleep(config.reconnect_delay).await;
+                }
+            }
+            .instrument(info_span!("replication_follower")),
+        ));
+    }
+
+    Ok(jhs)
+}
+
+/// Number the accepted updates in sequence, leaving out those replicated from
+/// the peer. Updates the sequencer missed still take a number, so that the
+/// followers detect the gap.
+async fn number_updates(
+    mut events_rx: broadcast::Receiver<local::Event>,
+    frames_tx: broadcast::Sender<Arc<Frame>>,
+) {
+    let mut sequence = 0;
+    loop {
+        match events_rx.recv().await {
+            Ok(local::Event::PriceUpdated {
+                replicated: true, ..
+            }) => {}
+            Ok(local::Event::PriceUpdated {
+                publisher,
+                price_identifier,
+                price_info,
+                replicated: false,
+            }) => {
+                sequence += 1;
+                let _ = frames_tx.send(Arc::new(Frame::Update {
+                    sequence,
+                    entry: PersistedPriceInfo::new(&publisher, &price_identifier, &price_info),
+                }));
+            }
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                warn!(skipped, "Replication: missed local store events");
+                sequence += skipped;
+            }
+            Err(broadcast::error::RecvError::Closed) => break,
+        }
+    }
+}
+
+/// Decides which followers are streamed the updates
+#[derive(Clone)]
+struct Followers {
+    allowed_networks: Vec<IpNet>,
+    auth_token:       Option<String>,
+}
+
+impl Followers {
+    fn new(config: &Config) -> Self {
+        Followers {
+            allowed_networks: config.allowed_networks.clone(),
+            auth_token:       config.auth_token.clone(),
+        }
+    }
+
+    fn is_allowed(&self, remote_address: SocketAddr) -> bool {
+        // IPv4 followers of a listener bound to an IPv6 address connect from
+        // IPv4-mapped addresses
+        let ip = match remote_address.ip() {
+            IpAddr::V6(ipv6) => ipv6
+                .to_ipv4_mapped()
+                .map_or(remote_address.ip(), IpAddr::V4),
+            ip => ip,
+        };
+        self.allowed_networks.is_empty()
+            || self
+                .allowed_networks
+                .iter()
+                .any(|network| network.contains(&ip))
+    }
+
+    fn is_authenticated(&self, hello: &Hello) -> bool {
+        match (&self.auth_token, &hello.token) {
+            (None, _) => true,
+            (Some(expected), Some(token)) => {
+                bool::from(expected.as_bytes().ct_eq(token.as_bytes()))
+            }
+            (Some(_), None) => false,
+        }
+    }
+
+    /// Read the hello line of the follower, and check its token
+    async fn authenticate(&self, stream: &mut TcpStream) -> Result<()> {
+        let mut line = String::new();
+        time::timeout(HELLO_TIMEOUT, BufReader::new(stream).read_line(&mut line))
+            .await
+            .map_err(|_| anyhow!("no hello from the follower"))?
+            .context("reading the hello of the follower")?;
+        let hello = serde_json::from_str::<Hello>(&line).context("parsing the hello")?;
+        if !self.is_authenticated(&hello) {
+            return Err(anyhow!("invalid auth token"));
+        }
+        Ok(())
+    }
+}
+
+async fn serve(
+    listener: TcpListener,
+    followers: Followers,
+    frames_tx: broadcast::Sender<Arc<Frame>>,
+    local_store_tx: mpsc::Sender<local::Message>,
+    metrics: Arc<ReplicationMetrics>,
+) {
+    loop {
+        let (stream, remote_address) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(err) => {
+                error!(error = ?err, "Replication: accepting a follower: {:#}", err);
+                continue;
+            }
+        };
+        if !followers.is_allowed(remote_address) {
+            warn!(
+                %remote_address,
+                "Replication: rejected a follower outside of the allowed networks"
+            );
+            metrics.record_rejected_follower();
+            continue;
+        }
+        info!(%remote_address, "Replication: follower connected");
+
+        let followers = followers.clone();
+        let frames_tx = frames_tx.clone();
+        let local_store_tx = local_store_tx.clone();
+        let metrics = metrics.clone();
+        tokio::spawn(
+            async move {
+                let mut stream = stream;
+                if let Err(err) = followers.authenticate(&mut stream).await {
+                    warn!(error = ?err, "Replication: rejected a follower: {:#}", err);
+                    metrics.record_rejected_follower();
+                    return;
+                }
+
+                // Subscribe before the snapshot is taken, so that no update falls
+                // between the two
+                let frames_rx = frames_tx.subscribe();
+                metrics.follower_connected();
+                if let Err(err) = stream_to_follower(stream, frames_rx, &local_store_tx).await {
+                    info!(error = ?err, "Replication: follower disconnected: {:#}", err);
+                }
+                metrics.follower_disconnected();
+            }
+            .instrument(info_span!("replication_follower_stream", %remote_address)),
+        );
+    }
+}
+
+async fn stream_to_follower(
+    mut stream: TcpStream,
+    mut frames_rx: broadcast::Receiver<Arc<Frame>>,
+    local_store_tx: &mpsc::Sender<local::Message>,
+) -> Result<()> {
+    let (result_tx, result_rx) = oneshot::channel();
+    local_store_tx
+        .send(local::Message::LookupAllPriceInfo { result_tx })
+        .await
+        .map_err(|_| Error::ChannelClosed("local store"))?;
+    let entries = result_rx
+        .await?
+        .iter()
+        .flat_map(|(publisher, prices)| {
+            prices
+                .iter()
+                .map(|(identifier, info)| PersistedPriceInfo::new(publisher, identifier, info))
+        })
+        .collect();
+    write_frame(&mut stream, &Frame::Snapshot { entries }).await?;
+
+    loop {
+        match frames_rx.recv().await {
+            Ok(frame) => write_frame(&mut stream, &frame).await?,
+            // The follower sees the gap in the sequence
+            Err(broadcast::error::RecvError::Lagged(_)) => {}
+            Err(broadcast::error::RecvError::Closed) => return Ok(()),
+        }
+    }
+}
+
+async fn write_frame(stream: &mut TcpStream, frame: &Frame) -> Result<()> {
+    let mut line = serde_json::to_vec(frame)?;
+    line.push(b'\n');
+    stream
+        .write_all(&line)
+        .await
+        .context("writing to the follower")
+}
+
+/// Follow the stream of the peer until it breaks, applying its updates to the
+/// Local Store while this agent is the standby
+async fn follow(
+    peer_address: &str,
+    hello: &Hello,
+    local_store_tx: &mpsc::Sender<local::Message>,
+    publish_pause: &PublishPause,
+    metrics: &ReplicationMetrics,
+) -> Result<()> {
+    let mut stream = TcpStream::connect(peer_address)
+        .await
+        .with_context(|| format!("connecting to {}", peer_address))?;
+    let mut line = serde_json::to_vec(hello)?;
+    line.push(b'\n');
+    stream
+        .write_all(&line)
+        .await
+        .context("sending the hello to the peer")?;
+    info!(peer_address, "Replication: following the peer");
+    metrics.set_following(true);
+
+    let mut lines = BufReader::new(stream).lines();
+    let mut sequence_tracker = SequenceTracker::default();
+    while let Some(line) = lines.next_line().await.context("reading from the peer")? {
+        let entries = match serde_json::from_str::<Frame>(&line).context("parsing a frame")? {
+            Frame::Snapshot { entries } => entries,
+            Frame::Update { sequence, entry } => {
+                let missed = sequence_tracker.observe(sequence);
+                if missed > 0 {
+                    metrics.record_gap(missed);
+                    return Err(anyhow!(
+                        "missed {} updates of the peer, following it again from a snapshot",
+                        missed
+                    ));
+                }
+                vec![entry]
+            }
+        };
+
+        if !publish_pause.get().standby {
+            continue;
+        }
+        for entry in entries {
+            let (publisher, price_identifier, price_info) = entry.parse()?;
+            local_store_tx
+                .send(local::Message::Update {
+                    publisher,
+                    price_identifier,
+                    price_info,
+                    seq: None,
+                    received_at: Instant::now(),
+                    replicated: true,
+                    trace_context: Context::new(),
+                })
+                .await
+                .map_err(|_| Error::ChannelClosed("local store"))?;
+            metrics.record_applied();
+        }
+    }
+
+    Err(anyhow!("the peer closed the stream"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Config,
+        Followers,
+        Hello,
+        SequenceTracker,
+    };
+
+    #[test]
+    fn test_gaps_in_the_sequence_are_detected() {
+        let mut tracker = SequenceTracker::default();
+
+        // The first update sets where the sequence starts from
+        assert_eq!(tracker.observe(41), 0);
+        assert_eq!(tracker.observe(42), 0);
+        assert_eq!(tracker.observe(45), 2);
+        assert_eq!(tracker.observe(46), 0);
+    }
+
+    #[test]
+    fn test_followers_are_restricted_to_the_allowed_networks_and_token() {
+        let followers = Followers::new(&Config {
+            allowed_networks: vec!["10.0.0.0/8".parse().unwrap()],
+            auth_token: Some("secret".to_string()),
+            ..Config::default()
+        });
+
+        assert!(followers.is_allowed("10.1.2.3:1234".parse().unwrap()));
+        assert!(followers.is_allowed("[::ffff:10.1.2.3]:1234".parse().unwrap()));
+        assert!(!followers.is_allowed("192.168.1.1:1234".parse().unwrap()));
+
+        let hello = |token: Option<&str>| Hello {
+            token: token.map(str::to_string),
+        };
+        assert!(followers.is_authenticated(&hello(Some("secret"))));
+        assert!(!followers.is_authenticated(&hello(Some("wrong"))));
+        assert!(!followers.is_authenticated(&hello(None)));
+
+        // Without restrictions, any follower is streamed the updates
+        let followers = Followers::new(&Config::default());
+        assert!(followers.is_allowed("192.168.1.1:1234".parse().unwrap()));
+        assert!(followers.is_authenticated(&hello(None)));
+    }
+}
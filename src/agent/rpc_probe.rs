@@ -0,0 +1,237 @@
+// The RPC Probe validates an RPC and WSS endpoint pair before the agent is pointed at
+// it, printing a scorecard in the format of the Config Check report. It measures:
+// - the latency of getAccountInfo requests, over a number of samples
+// - the time taken to connect to the websocket endpoint and receive the first slot
+//   notification of a subscription
+// - the slot lag of the node, as how far its processed slot is behind the highest slot
+//   it received shreds for
+// - whether the node serves getRecentPrioritizationFees, used to price transactions,
+//   and base64+zstd account encoding, which the Oracle may be configured to poll with
+use {
+    super::{
+        config_check::Report,
+        solana::instrumented_rpc,
+    },
+    anyhow::{
+        anyhow,
+        Result,
+    },
+    futures_util::StreamExt,
+    serde_json::{
+        json,
+        Value,
+    },
+    solana_account_decoder::UiAccountEncoding,
+    solana_client::{
+        nonblocking::pubsub_client::PubsubClient,
+        rpc_config::RpcAccountInfoConfig,
+        rpc_request::RpcRequest,
+    },
+    solana_sdk::{
+        commitment_config::CommitmentConfig,
+        pubkey::Pubkey,
+    },
+    std::time::Duration,
+    tokio::time::{
+        self,
+        Instant,
+    },
+};
+
+/// Median getAccountInfo latency above which the endpoint is flagged
+const ACCOUNT_LATENCY_WARNING: Duration = Duration::from_millis(500);
+
+/// Time to the first slot notification above which the endpoint is flagged
+const SUBSCRIPTION_LATENCY_WARNING: Duration = Duration::from_secs(2);
+
+/// Slot lag above which the node is flagged
+const SLOT_LAG_WARNING: u64 = 10;
+
+/// The endpoint pair to probe
+#[derive(Clone, Debug)]
+pub struct Target {
+    pub rpc_url: String,
+    pub wss_url: String,
+    /// Account read to measure the latency, which must exist
+    pub account: Pubkey,
+    /// Number of getAccountInfo requests the latency is measured over
+    pub samples: usize,
+    /// Timeout of every request, and of the websocket subscription
+    pub timeout: Duration,
+}
+
+pub async fn probe(target: &Target) -> Report {
+    let mut report = Report::default();
+    let rpc_client = instrumented_rpc::new_rpc_client(
+        "probe",
+        &target.rpc_url,
+        target.timeout,
+        CommitmentConfig::confirmed(),
+    );
+
+    match rpc_client.get_version().await {
+        Ok(version) => report.ok("rpc", format!("solana-core {}", version.solana_core)),
+        Err(err) => {
+            report.error("rpc", format!("{:#}", err));
+            return report;
+        }
+    }
+
+    let mut latencies = Vec::with_capacity(target.samples);
+    let mut latency_error = None;
+    for _ in 0..target.samples {
+        let started_at = Instant::now();
+        match rpc_client.get_account(&target.account).await {
+            Ok(_) => latencies.push(started_at.elapsed()),
+            Err(err) => {
+                latency_error = Some(err);
+                break;
+            }
+        }
+    }
+    match (latency_error, median_and_max(latencies)) {
+        (Some(err), _) => report.error("account_latency", format!("{:#}", err)),
+        (None, Some((median, max))) => {
+            let detail = format!(
+                "median {} ms, max {} ms over {} request(s)",
+                median.as_millis(),
+                max.as_millis(),
+                target.samples
+            );
+            if median > ACCOUNT_LATENCY_WARNING {
+                report.warning("account_latency", detail);
+            } else {
+                report.ok("account_latency", detail);
+            }
+        }
+        (None, None) => {}
+    }
+
+    match time::timeout(target.timeout, first_slot_notification(&target.wss_url)).await {
+        Ok(Ok((connected_in, notified_in))) => {
+            let detail = format!(
+                "connected in {} ms, first slot notification after {} ms",
+                connected_in.as_millis(),
+                notified_in.as_millis()
+            );
+            if notified_in > SUBSCRIPTION_LATENCY_WARNING {
+                report.warning("subscription_latency", detail);
+            } else {
+                report.ok("subscription_latency", detail);
+            }
+        }
+        Ok(Err(err)) => report.error("subscription_latency", format!("{:#}", err)),
+        Err(_) => report.error("subscription_latency", "timed out waiting for a slot"),
+    }
+
+    let slots = futures_util::future::join(
+        rpc_client.get_slot_with_commitment(CommitmentConfig::processed()),
+        rpc_client.get_max_shred_insert_slot(),
+    )
+    .await;
+    match slots {
+        (Ok(slot), Ok(max_shred_insert_slot)) => {
+            let lag = max_shred_insert_slot.saturating_sub(slot);
+            let detail = format!(
+                "{} slot(s) behind the highest slot received, at slot {}",
+                lag, slot
+            );
+            if lag > SLOT_LAG_WARNING {
+                report.warning("slot_lag", detail);
+            } else {
+                report.ok("slot_lag", detail);
+            }
+        }
+        (Err(err), _) | (_, Err(err)) => report.error("slot_lag", format!("{:#}", err)),
+    }
+
+    // Not part of the RPC client of the Solana version the agent is built with
+    match rpc_client
+        .send::<Value>(
+            RpcRequest::Custom {
+                method: "getRecentPrioritizationFees",
+            },
+            json!([]),
+        )
+        .await
+    {
+        Ok(_) => report.ok("priority_fee_api", "supported"),
+        Err(err) => report.warning(
+            "priority_fee_api",
+            format!(
+                "not supported, compute unit prices must be set in the config: {:#}",
+                err
+            ),
+        ),
+    }
+
+    // The client decompresses the account, so an unsupported encoding fails
+    match rpc_client
+        .get_account_with_config(
+            &target.account,
+            RpcAccountInfoConfig {
+                encoding: Some(UiAccountEncoding::Base64Zstd),
+                ..RpcAccountInfoConfig::default()
+            },
+        )
+        .await
+    {
+        Ok(_) => report.ok(
+            "zstd_encoding",
+            "supported, oracle.account_encoding = \"base64+zstd\" cuts the transfer size of the polls",
+        ),
+        Err(err) => report.warning(
+            "zstd_encoding",
+            format!(
+                "not supported, keep oracle.account_encoding = \"base64\": {:#}",
+                err
+            ),
+        ),
+    }
+
+    report
+}
+
+/// Connect to the websocket endpoint and subscribe to the slots, returning
+/// the time taken to connect and to receive the first notification
+async fn first_slot_notification(wss_url: &str) -> Result<(Duration, Duration)> {
+    let started_at = Instant::now();
+    let pubsub_client = PubsubClient::new(wss_url).await?;
+    let connected_in = started_at.elapsed();
+
+    let (mut slots, unsubscribe) = pubsub_client.slot_subscribe().await?;
+    let first_slot = slots.next().await;
+    let notified_in = started_at.elapsed();
+    drop(slots);
+    unsubscribe().await;
+    let _ = pubsub_client.shutdown().await;
+
+    match first_slot {
+        Some(_) => Ok((connected_in, notified_in)),
+        None => Err(anyhow!("the slot subscription closed")),
+    }
+}
+
+fn median_and_max(mut latencies: Vec<Duration>) -> Option<(Duration, Duration)> {
+    latencies.sort();
+    let max = *latencies.last()?;
+    Some((latencies[latencies.len() / 2], max))
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::median_and_max,
+        std::time::Duration,
+    };
+
+    #[test]
+    fn test_latencies_are_summarized() {
+        let ms = Duration::from_millis;
+        assert_eq!(median_and_max(vec![]), None);
+        assert_eq!(
+            median_and_max(vec![ms(30), ms(10), ms(250), ms(20), ms(40)]),
+            Some((ms(30), ms(250)))
+        );
+    }
+}
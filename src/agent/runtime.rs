@@ -0,0 +1,151 @@
+// The agent runs its components on Tokio runtimes built from this config. By default
+// a single multi-threaded runtime runs everything. The Pythd API Server, the Metrics
+// Server with its dashboard and the Admin API can instead run on a dedicated runtime,
+// so that serving clients does not compete with the Oracles and Exporters for worker
+// threads.
+use {
+    anyhow::{
+        anyhow,
+        Context,
+        Result,
+    },
+    serde::{
+        Deserialize,
+        Serialize,
+    },
+    std::future::Future,
+    tokio::runtime::{
+        self,
+        Handle,
+        Runtime,
+    },
+};
+
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Worker threads of the main runtime, which runs the Oracles, Exporters and
+    /// stores. The number of CPU cores if not set.
+    pub worker_threads:       Option<usize>,
+    /// Maximum number of threads of the main runtime running blocking operations,
+    /// Tokio's default if not set
+    pub max_blocking_threads: Option<usize>,
+    /// Dedicated runtime of the API Server, Metrics Server and Admin API. They
+    /// run on the main runtime if not set.
+    pub api:                  Option<ApiConfig>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ApiConfig {
+    /// Worker threads of the dedicated API runtime
+    pub worker_threads: usize,
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        Self { worker_threads: 2 }
+    }
+}
+
+/// The runtimes of the agent, built once the config is loaded
+pub struct Runtimes {
+    main: Runtime,
+    api:  Option<Runtime>,
+}
+
+impl Runtimes {
+    pub fn new(config: &Config) -> Result<Self> {
+        let mut main = runtime::Builder::new_multi_thread();
+        main.enable_all().thread_name("agent-main");
+        if let Some(worker_threads) = config.worker_threads {
+            main.worker_threads(positive(worker_threads, "runtime.worker_threads")?);
+        }
+        if let Some(max_blocking_threads) = config.max_blocking_threads {
+            main.max_blocking_threads(positive(
+                max_blocking_threads,
+                "runtime.max_blocking_threads",
+            )?);
+        }
+
+        let api = match &config.api {
+            Some(api_config) => Some(
+                runtime::Builder::new_multi_thread()
+                    .enable_all()
+                    .thread_name("agent-api")
+                    .worker_threads(positive(
+                        api_config.worker_threads,
+                        "runtime.api.worker_threads",
+                    )?)
+                    .build()
+                    .context("building the API runtime")?,
+            ),
+            None => None,
+        };
+
+        Ok(Runtimes {
+            main: main.build().context("building the main runtime")?,
+            api,
+        })
+    }
+
+    /// Handle of the dedicated API runtime, if configured
+    pub fn api_handle(&self) -> Option<Handle> {
+        self.api.as_ref().map(|api| api.handle().clone())
+    }
+
+    /// Run the future to completion on the main runtime
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.main.block_on(future)
+    }
+}
+
+fn positive(threads: usize, key: &str) -> Result<usize> {
+    if threads == 0 {
+        return Err(anyhow!("{} must be at least 1", key));
+    }
+    Ok(threads)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        ApiConfig,
+        Config,
+        Runtimes,
+    };
+
+    #[test]
+    fn test_api_components_run_on_the_dedicated_runtime() {
+        let thread_name = || std::thread::current().name().map(str::to_string);
+
+        let runtimes = Runtimes::new(&Config {
+            worker_threads: Some(1),
+            api: Some(ApiConfig { worker_threads: 1 }),
+            ..Default::default()
+        })
+        .unwrap();
+        let api_handle = runtimes.api_handle().unwrap();
+        let (main_thread, api_thread) = runtimes.block_on(async move {
+            let main_thread = tokio::spawn(async move { thread_name() }).await.unwrap();
+            let api_thread = api_handle
+                .spawn(async move { thread_name() })
+                .await
+                .unwrap();
+            (main_thread, api_thread)
+        });
+        assert_eq!(main_thread.as_deref(), Some("agent-main"));
+        assert_eq!(api_thread.as_deref(), Some("agent-api"));
+
+        // The API components share the main runtime by default
+        assert!(Runtimes::new(&Config::default())
+            .unwrap()
+            .api_handle()
+            .is_none());
+        assert!(Runtimes::new(&Config {
+            worker_threads: Some(0),
+            ..Default::default()
+        })
+        .is_err());
+    }
+}
@@ -0,0 +1,255 @@
+// The Shutdown Controller stops the agent in phases on SIGTERM or SIGINT, so that
+// the updates accepted over the API are published and persisted before exiting:
+// 1. The API server stops accepting connections, and the Pythd Adapter forwards
+//    the updates it already received to the Local Store.
+// 2. The Exporters publish the pending updates one last time.
+// 3. The Local Store persists its contents, and the Price History Recorder
+//    completes its file.
+// Each phase is bounded by a deadline, after which the next one starts anyway. An
+// agent embedded in another service leaves the signals to its host, and is shut down
+// through its handle instead.
+use {
+    anyhow::{
+        Context,
+        Result,
+    },
+    serde::{
+        Deserialize,
+        Serialize,
+    },
+    std::{
+        collections::HashMap,
+        time::Duration,
+    },
+    tokio::{
+        signal::unix::{
+            signal,
+            SignalKind,
+        },
+        sync::{
+            broadcast,
+            mpsc,
+            oneshot,
+            watch,
+        },
+        task::JoinHandle,
+        time::{
+            self,
+            Instant,
+        },
+    },
+    tracing::Instrument,
+};
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Time allowed for the accepted updates to be forwarded to the Local Store
+    /// and published by the Exporters, once the shutdown is requested
+    #[serde(with = "humantime_serde")]
+    pub flush_timeout:   Duration,
+    /// Time allowed for the Local Store to persist its contents, once the
+    /// updates are flushed
+    #[serde(with = "humantime_serde")]
+    pub persist_timeout: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            flush_timeout:   Duration::from_secs(10),
+            persist_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Phase of the shutdown, in the order they are entered
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Phase {
+    Running,
+    /// The Exporters publish the pending updates and exit
+    Flush,
+    /// The Local Store persists its contents, the Price History Recorder
+    /// completes its file, and they exit
+    Persist,
+}
+
+/// Held by a component taking part in a phase of the shutdown. The phase is
+/// complete once all its participants are dropped.
+#[derive(Clone)]
+pub struct Participant {
+    phase:    Phase,
+    phase_rx: watch::Receiver<Phase>,
+    _done_tx: mpsc::Sender<()>,
+}
+
+impl Participant {
+    /// Wait until the phase of this participant is entered. Never returns if
+    /// the controller is gone.
+    pub async fn wait(&mut self) {
+        loop {
+            if *self.phase_rx.borrow_and_update() >= self.phase {
+                return;
+            }
+            if self.phase_rx.changed().await.is_err() {
+                futures_util::future::pending::<()>().await;
+            }
+        }
+    }
+}
+
+pub struct ShutdownController {
+    config:   Config,
+    phase_tx: watch::Sender<Phase>,
+    /// Cloned into the participants of each phase, and dropped by the
+    /// controller once the shutdown starts
+    done_txs: HashMap<Phase, mpsc::Sender<()>>,
+    /// Closed once all the participants of each phase are dropped
+    done_rxs: HashMap<Phase, mpsc::Receiver<()>>,
+}
+
+impl ShutdownController {
+    pub fn new(config: Config) -> Self {
+        ShutdownController {
+            config,
+            phase_tx: watch::channel(Phase::Running).0,
+            done_txs: HashMap::new(),
+            done_rxs: HashMap::new(),
+        }
+    }
+
+    /// Register a component taking part in the given phase
+    pub fn participant(&mut self, phase: Phase) -> Participant {
+        let done_rxs = &mut self.done_rxs;
+        let done_tx = self.done_txs.entry(phase).or_insert_with(|| {
+            let (done_tx, done_rx) = mpsc::channel(1);
+            done_rxs.insert(phase, done_rx);
+            done_tx
+        });
+        Participant {
+            phase,
+            phase_rx: self.phase_tx.subscribe(),
+            _done_tx: done_tx.clone(),
+        }
+    }
+
+    /// Wait for SIGTERM or SIGINT, or for the request of an embedded agent's
+    /// handle, then stop the API server and the Pythd Adapter through
+    /// `shutdown_tx` and run the shutdown phases
+    async fn run(
+        mut self,
+        shutdown_tx: broadcast::Sender<()>,
+        adapter_jh: JoinHandle<()>,
+        requested_rx: Option<oneshot::Receiver<()>>,
+    ) -> Result<()> {
+        match requested_rx {
+            Some(requested_rx) => {
+                // The agent keeps running if its handle is dropped
+                if requested_rx.await.is_err() {
+                    futures_util::future::pending::<()>().await;
+                }
+                info!("shutdown requested, shutting down");
+            }
+            None => {
+                let mut sigterm =
+                    signal(SignalKind::terminate()).context("listening for SIGTERM")?;
+                let mut sigint = signal(SignalKind::interrupt()).context("listening for SIGINT")?;
+                tokio::select! {
+                    _ = sigterm.recv() => info!("received SIGTERM, shutting down"),
+                    _ = sigint.recv() => info!("received SIGINT, shutting down"),
+                }
+            }
+        }
+
+        // Only the participants hold the senders from now on
+        self.done_txs.clear();
+
+        // Stop accepting updates, and wait for the Adapter to forward the ones
+        // it received
+        let flush_deadline = Instant::now() + self.config.flush_timeout;
+        let _ = shutdown_tx.send(());
+        if time::timeout_at(flush_deadline, adapter_jh).await.is_err() {
+            warn!("pythd adapter did not forward the received updates before the flush deadline");
+        }
+
+        self.enter(Phase::Flush, flush_deadline).await;
+        self.enter(Phase::Persist, Instant::now() + self.config.persist_timeout)
+            .await;
+
+        info!("shutdown complete");
+        Ok(())
+    }
+
+    /// Enter the given phase, and wait until its participants are done or the deadline passes
+    async fn enter(&mut self, phase: Phase, deadline: Instant) {
+        debug!(?phase, "entering shutdown phase");
+        let _ = self.phase_tx.send(phase);
+        if let Some(done_rx) = self.done_rxs.get_mut(&phase) {
+            if time::timeout_at(deadline, done_rx.recv()).await.is_err() {
+                warn!(
+                    ?phase,
+                    "shutdown phase did not complete before its deadline"
+                );
+            }
+        }
+    }
+}
+
+/// Spawn the controller, whose task completes once the agent is shut down. An
+/// embedded agent is shut down through `requested_rx` rather than by signals.
+pub fn spawn_controller(
+    controller: ShutdownController,
+    shutdown_tx: broadcast::Sender<()>,
+    adapter_jh: JoinHandle<()>,
+    requested_rx: Option<oneshot::Receiver<()>>,
+) -> JoinHandle<()> {
+    tokio::spawn(
+        async move {
+            if let Err(err) = controller.run(shutdown_tx, adapter_jh, requested_rx).await {
+                // Keep the agent running without graceful shutdown
+                error!(error = ?err, "{:#}", err);
+                futures_util::future::pending::<()>().await;
+            }
+        }
+        .instrument(info_span!("shutdown_controller")),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            Phase,
+            ShutdownController,
+        },
+        std::time::Duration,
+        tokio::time,
+    };
+
+    #[tokio::test]
+    async fn test_participants_wait_for_their_phase() {
+        let mut controller = ShutdownController::new(Default::default());
+        let mut flush = controller.participant(Phase::Flush);
+        let mut persist = controller.participant(Phase::Persist);
+        controller.done_txs.clear();
+
+        // Nothing happens while running
+        assert!(time::timeout(Duration::from_millis(10), flush.wait())
+            .await
+            .is_err());
+
+        // Entering a phase releases its participants only, and completes once
+        // they are dropped
+        let deadline = time::Instant::now() + Duration::from_secs(5);
+        let flushed = tokio::spawn(async move { flush.wait().await });
+        controller.enter(Phase::Flush, deadline).await;
+        flushed.await.unwrap();
+        assert!(time::timeout(Duration::from_millis(10), persist.wait())
+            .await
+            .is_err());
+
+        let persisted = tokio::spawn(async move { persist.wait().await });
+        controller.enter(Phase::Persist, deadline).await;
+        persisted.await.unwrap();
+    }
+}
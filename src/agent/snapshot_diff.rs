@@ -0,0 +1,299 @@
+// Redundant agents should observe the same prices. To compare them, each agent serves
+// a canonical snapshot of its Global Store at /api/snapshot on the metrics server: the
+// slot, price, confidence interval and status of the aggregate of every symbol, in the
+// order of the symbols, along with the hash of their JSON encoding. Two agents whose
+// snapshots have the same hash observe the same state. The `diff-snapshots` subcommand
+// fetches the snapshots of two agents and prints the symbols which only one of them
+// knows of, and the fields which differ between them.
+//
+// Agents polling at different times may be a few slots apart, so the slots are allowed
+// to differ by a tolerance, in which case the other fields of the symbol are not compared.
+use {
+    super::store::global,
+    anyhow::{
+        anyhow,
+        Context,
+        Result,
+    },
+    serde::{
+        Deserialize,
+        Serialize,
+    },
+    solana_sdk::hash,
+    std::{
+        collections::BTreeMap,
+        fmt,
+    },
+};
+
+/// The aggregates of the Global Store, in a canonical form
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CanonicalSnapshot {
+    /// Hash of the JSON encoding of the symbols
+    pub hash:    String,
+    /// The aggregates by symbol. The price accounts with no symbol are keyed by
+    /// themselves, and those of a symbol with several price accounts by the
+    /// symbol followed by the price account.
+    pub symbols: BTreeMap<String, SymbolState>,
+}
+
+/// The aggregate of the price account of a symbol
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SymbolState {
+    pub price_account: String,
+    pub slot:          u64,
+    pub price:         i64,
+    pub conf:          u64,
+    pub status:        String,
+}
+
+impl CanonicalSnapshot {
+    pub fn new(snapshot: &global::Snapshot) -> Result<Self> {
+        let symbol_index = &snapshot.account_metadata.symbol_index;
+        let symbols = snapshot
+            .account_data
+            .price_accounts
+            .iter()
+            .map(|(price_key, price_account)| {
+                let key = match symbol_index.symbol(price_key) {
+                    Some(symbol) if symbol_index.price_accounts(symbol).len() > 1 => {
+                        format!("{} {}", symbol, price_key)
+                    }
+                    _ => symbol_index.price_name(price_key),
+                };
+                (
+                    key,
+                    SymbolState {
+                        price_account: price_key.to_string(),
+                        slot:          price_account.agg.pub_slot,
+                        price:         price_account.agg.price,
+                        conf:          price_account.agg.conf,
+                        status:        format!("{:?}", price_account.agg.status).to_lowercase(),
+                    },
+                )
+            })
+            .collect();
+        Self::from_symbols(symbols)
+    }
+
+    fn from_symbols(symbols: BTreeMap<String, SymbolState>) -> Result<Self> {
+        let encoded = serde_json::to_vec(&symbols)?;
+        Ok(CanonicalSnapshot {
+            hash: hash::hash(&encoded).to_string(),
+            symbols,
+        })
+    }
+}
+
+/// The differences between the snapshots of two agents
+#[derive(Debug, Default, Serialize)]
+pub struct SnapshotDiff {
+    pub left_hash:  String,
+    pub right_hash: String,
+    /// Symbols only the left agent knows of
+    pub only_left:  Vec<String>,
+    /// Symbols only the right agent knows of
+    pub only_right: Vec<String>,
+    /// Fields of the symbols known to both agents which differ
+    pub differing:  Vec<FieldDiff>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct FieldDiff {
+    pub symbol: String,
+    pub field:  &'static str,
+    pub left:   String,
+    pub right:  String,
+}
+
+impl SnapshotDiff {
+    /// Compare the snapshots, the slots of a symbol being allowed to differ
+    /// by up to slot_tolerance
+    pub fn new(left: &CanonicalSnapshot, right: &CanonicalSnapshot, slot_tolerance: u64) -> Self {
+        let mut diff = SnapshotDiff {
+            left_hash: left.hash.clone(),
+            right_hash: right.hash.clone(),
+            ..Default::default()
+        };
+        for (symbol, left_state) in &left.symbols {
+            let right_state = match right.symbols.get(symbol) {
+                Some(right_state) => right_state,
+                None => {
+                    diff.only_left.push(symbol.clone());
+                    continue;
+                }
+            };
+            let mut differs = |field: &'static str, left: String, right: String| {
+                if left != right {
+                    diff.differing.push(FieldDiff {
+                        symbol: symbol.clone(),
+                        field,
+                        left,
+                        right,
+                    });
+                }
+            };
+            differs(
+                "price_account",
+                left_state.price_account.clone(),
+                right_state.price_account.clone(),
+            );
+            if left_state.slot != right_state.slot {
+                if left_state.slot.abs_diff(right_state.slot) > slot_tolerance {
+                    differs(
+                        "slot",
+                        left_state.slot.to_string(),
+                        right_state.slot.to_string(),
+                    );
+                }
+                // The aggregates of different slots are not comparable
+                continue;
+            }
+            differs(
+                "price",
+                left_state.price.to_string(),
+                right_state.price.to_string(),
+            );
+            differs(
+                "conf",
+                left_state.conf.to_string(),
+                right_state.conf.to_string(),
+            );
+            differs(
+                "status",
+                left_state.status.clone(),
+                right_state.status.clone(),
+            );
+        }
+        diff.only_right = right
+            .symbols
+            .keys()
+            .filter(|symbol| !left.symbols.contains_key(*symbol))
+            .cloned()
+            .collect();
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.only_left.is_empty() && self.only_right.is_empty() && self.differing.is_empty()
+    }
+}
+
+impl fmt::Display for SnapshotDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "left:  {}", self.left_hash)?;
+        writeln!(f, "right: {}", self.right_hash)?;
+        if self.is_empty() {
+            return writeln!(f, "the snapshots match");
+        }
+        for symbol in &self.only_left {
+            writeln!(f, "[ LEFT] {}: only known to the left agent", symbol)?;
+        }
+        for symbol in &self.only_right {
+            writeln!(f, "[RIGHT] {}: only known to the right agent", symbol)?;
+        }
+        for field_diff in &self.differing {
+            writeln!(
+                f,
+                "[ DIFF] {}: {} {} != {}",
+                field_diff.symbol, field_diff.field, field_diff.left, field_diff.right
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Fetch the snapshot of the agent whose metrics server is served at the URL
+pub async fn fetch(metrics_url: &str, auth_token: Option<&str>) -> Result<CanonicalSnapshot> {
+    let mut request = reqwest::Client::new().get(format!(
+        "{}/api/snapshot",
+        metrics_url.trim_end_matches('/')
+    ));
+    if let Some(auth_token) = auth_token {
+        request = request.bearer_auth(auth_token);
+    }
+    let response = request
+        .send()
+        .await
+        .with_context(|| format!("requesting the snapshot from {}", metrics_url))?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(anyhow!("the metrics server responded {}: {}", status, body));
+    }
+    response.json().await.context("parsing the snapshot")
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            CanonicalSnapshot,
+            FieldDiff,
+            SnapshotDiff,
+            SymbolState,
+        },
+        std::collections::BTreeMap,
+    };
+
+    fn state(slot: u64, price: i64) -> SymbolState {
+        SymbolState {
+            price_account: "price".to_string(),
+            slot,
+            price,
+            conf: 1,
+            status: "trading".to_string(),
+        }
+    }
+
+    fn snapshot(symbols: &[(&str, SymbolState)]) -> CanonicalSnapshot {
+        CanonicalSnapshot::from_symbols(
+            symbols
+                .iter()
+                .map(|(symbol, state)| (symbol.to_string(), state.clone()))
+                .collect::<BTreeMap<_, _>>(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_snapshots_are_diffed_by_symbol() {
+        let left = snapshot(&[
+            ("Crypto.BTC/USD", state(100, 42)),
+            ("Crypto.ETH/USD", state(100, 7)),
+            ("Crypto.SOL/USD", state(100, 3)),
+        ]);
+        let right = snapshot(&[
+            ("Crypto.BTC/USD", state(100, 43)),
+            ("Crypto.ETH/USD", state(102, 8)),
+            ("Equity.US.AAPL/USD", state(100, 5)),
+        ]);
+
+        // Identical snapshots have the same hash
+        assert_eq!(
+            left.hash,
+            CanonicalSnapshot::from_symbols(left.symbols.clone())
+                .unwrap()
+                .hash
+        );
+        assert_ne!(left.hash, right.hash);
+
+        let diff = SnapshotDiff::new(&left, &right, 2);
+        assert_eq!(diff.only_left, vec!["Crypto.SOL/USD".to_string()]);
+        assert_eq!(diff.only_right, vec!["Equity.US.AAPL/USD".to_string()]);
+        // The prices of slots within the tolerance are not compared
+        assert_eq!(
+            diff.differing,
+            vec![FieldDiff {
+                symbol: "Crypto.BTC/USD".to_string(),
+                field:  "price",
+                left:   "42".to_string(),
+                right:  "43".to_string(),
+            }]
+        );
+
+        // Beyond the tolerance, the slots differ
+        let diff = SnapshotDiff::new(&left, &right, 1);
+        assert_eq!(diff.differing[1].field, "slot");
+    }
+}
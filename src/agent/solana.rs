@@ -1,9 +1,20 @@
+pub mod account_check;
+pub mod accumulator;
 pub mod exporter;
+pub mod instrumented_rpc;
+pub mod leader_schedule;
+pub mod mapping_usage;
 pub mod oracle;
+pub mod recording;
+pub mod signer;
+pub mod simulation;
+pub mod slot_lag;
 
 /// This module encapsulates all the interaction with a single Solana network:
 /// - The Oracle, which reads data from the network
 /// - The Exporter, which publishes data to the network
+///
+/// In simulation, the network is replaced by a simulated cluster.
 pub mod network {
 
     use {
@@ -13,32 +24,63 @@ pub mod network {
                 store::global,
             },
             exporter,
+            instrumented_rpc::endpoint_label,
             key_store::{
                 self,
                 KeyStore,
             },
+            mapping_usage::MappingUsageReporter,
             oracle,
+            simulation::{
+                self,
+                SimulatedCluster,
+            },
+            slot_lag::SlotLagReporter,
+        },
+        crate::agent::{
+            channel_monitor::ChannelMonitor,
+            health::HealthReporter,
+            publish_pause::PublishPause,
+            remote_keypair_loader::KeypairRequest,
+            shutdown,
+            startup_gate::StartupGate,
+            update_status::UpdateStatuses,
         },
-        crate::agent::remote_keypair_loader::KeypairRequest,
         anyhow::Result,
         serde::{
             Deserialize,
             Serialize,
         },
-        slog::Logger,
-        std::time::Duration,
+        solana_sdk::{
+            pubkey::Pubkey,
+            signer::Signer,
+        },
+        std::{
+            collections::{
+                HashMap,
+                HashSet,
+            },
+            fmt,
+            str::FromStr,
+            sync::Arc,
+            time::Duration,
+        },
         tokio::{
             sync::{
-                mpsc,
-                mpsc::Sender,
+                broadcast,
+                mpsc::{
+                    self,
+                    Sender,
+                },
+                watch,
             },
             task::JoinHandle,
         },
     };
 
     /// Configuration for a network
-    #[derive(Clone, Serialize, Deserialize, Debug)]
-    #[serde(default)]
+    #[derive(Clone, Serialize, Deserialize, PartialEq)]
+    #[serde(default, deny_unknown_fields)]
     pub struct Config {
         /// HTTP RPC endpoint
         pub rpc_url:     String,
@@ -53,6 +95,9 @@ pub mod network {
         pub oracle:      oracle::Config,
         /// Configuration for the Exporter publishing data to this network
         pub exporter:    exporter::Config,
+        /// Replaces the network with a simulated cluster, for offline
+        /// development, if set
+        pub simulation:  Option<simulation::Config>,
     }
 
     impl Default for Config {
@@ -64,54 +109,167 @@ pub mod network {
                 key_store:   Default::default(),
                 oracle:      Default::default(),
                 exporter:    Default::default(),
+                simulation:  None,
             }
         }
     }
 
+    /// The RPC endpoints can hold API keys in their path or query, so only
+    /// their hosts are shown
+    impl fmt::Debug for Config {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("Config")
+                .field("rpc_url", &endpoint_label(&self.rpc_url))
+                .field("wss_url", &endpoint_label(&self.wss_url))
+                .field("rpc_timeout", &self.rpc_timeout)
+                .field("key_store", &self.key_store)
+                .field("oracle", &self.oracle)
+                .field("exporter", &self.exporter)
+                .field("simulation", &self.simulation)
+                .finish()
+        }
+    }
+
+    impl Config {
+        /// The key the network publishes with, if known without waiting for a
+        /// remote keypair
+        pub fn publish_key(&self) -> Result<Option<Pubkey>> {
+            let signer_key = self
+                .exporter
+                .remote_signer
+                .as_ref()
+                .map(|signer| &signer.publish_key)
+                .or_else(|| {
+                    self.exporter
+                        .kms_signer
+                        .as_ref()
+                        .map(|signer| &signer.publish_key)
+                });
+            if let Some(signer_key) = signer_key {
+                return Ok(Some(Pubkey::from_str(signer_key)?));
+            }
+            Ok(KeyStore::new(self.key_store.clone())?
+                .publish_keypair
+                .map(|keypair| keypair.pubkey()))
+        }
+    }
+
     pub fn spawn_network(
         config: Config,
+        network_name: &str,
         local_store_tx: Sender<store::local::Message>,
+        transactions_store_tx: Sender<store::transactions::Message>,
+        local_store_events_tx: broadcast::Sender<store::local::Event>,
         global_store_update_tx: mpsc::Sender<global::Update>,
         keypair_request_tx: mpsc::Sender<KeypairRequest>,
-        logger: Logger,
+        keypair_loader_permissions_tx: watch::Sender<HashMap<Pubkey, HashSet<Pubkey>>>,
+        oracle_config_rx: watch::Receiver<oracle::Config>,
+        exporter_config_rx: watch::Receiver<exporter::Config>,
+        key_store_config_rx: watch::Receiver<key_store::Config>,
+        global_store_reader: global::SnapshotReader,
+        publish_pause: PublishPause,
+        update_statuses: UpdateStatuses,
+        startup_gate: Option<StartupGate>,
+        health: &HealthReporter,
+        slot_lags: &SlotLagReporter,
+        mapping_usage: &MappingUsageReporter,
+        channel_monitor: &ChannelMonitor,
+        shutdown: shutdown::Participant,
     ) -> Result<Vec<JoinHandle<()>>> {
+        // The spans of the network's tasks are created within this one, so
+        // that all their events carry the network name
+        let _span = info_span!("network", network = network_name).entered();
+
+        // Slots of the network, tracked by the Oracle and watched by the Exporter
+        let slots = slot_lags.network(network_name);
+
         // Publisher permissions updates between oracle and exporter
         let (publisher_permissions_tx, publisher_permissions_rx) =
             mpsc::channel(config.oracle.updates_channel_capacity);
 
+        // In simulation, the Oracle reads the simulated cluster, and the
+        // Exporter logs its transactions instead of sending them
+        let oracle_key_store = KeyStore::new(config.key_store.clone())?;
+        let simulated_cluster = config
+            .simulation
+            .as_ref()
+            .map(|simulation| SimulatedCluster::new(simulation, &oracle_key_store))
+            .transpose()?
+            .map(Arc::new);
+
         // Spawn the Oracle
         let mut jhs = oracle::spawn_oracle(
-            config.oracle.clone(),
+            oracle_config_rx,
+            network_name,
             &config.rpc_url,
             &config.wss_url,
             config.rpc_timeout,
             global_store_update_tx.clone(),
             publisher_permissions_tx,
-            KeyStore::new(config.key_store.clone(), &logger)?,
-            logger.clone(),
+            keypair_loader_permissions_tx,
+            oracle_key_store,
+            simulated_cluster,
+            startup_gate,
+            health,
+            channel_monitor,
+            slots.clone(),
+            mapping_usage.network(network_name),
         );
 
         // Spawn the Exporter
         let exporter_jhs = exporter::spawn_exporter(
-            config.exporter,
+            exporter_config_rx,
+            network_name,
             &config.rpc_url,
+            &config.wss_url,
             config.rpc_timeout,
             publisher_permissions_rx,
-            KeyStore::new(config.key_store.clone(), &logger)?,
+            KeyStore::new(config.key_store.clone())?,
+            key_store_config_rx,
             local_store_tx,
+            transactions_store_tx,
+            local_store_events_tx,
             keypair_request_tx,
-            logger,
+            global_store_reader,
+            publish_pause,
+            update_statuses,
+            slots,
+            config.simulation.is_some(),
+            health,
+            channel_monitor,
+            shutdown,
         )?;
         jhs.extend(exporter_jhs);
 
         Ok(jhs)
     }
+
+    #[cfg(test)]
+    mod tests {
+        use super::Config;
+
+        #[test]
+        fn test_rpc_api_keys_are_redacted_from_debug() {
+            let debug = format!(
+                "{:?}",
+                Config {
+                    rpc_url: "https://pyth.rpcpool.com/rpc-secret".to_string(),
+                    wss_url: "wss://pyth.rpcpool.com/ws?api-key=wss-secret".to_string(),
+                    ..Default::default()
+                }
+            );
+            assert!(!debug.contains("rpc-secret"));
+            assert!(!debug.contains("wss-secret"));
+            assert!(debug.contains("pyth.rpcpool.com"));
+        }
+    }
 }
 
 /// The key_store module is responsible for parsing the pythd key store.
-mod key_store {
+pub mod key_store {
     use {
         anyhow::{
+            anyhow,
             Context,
             Result,
         },
@@ -119,13 +277,16 @@ mod key_store {
             Deserialize,
             Serialize,
         },
-        slog::Logger,
         solana_sdk::{
             pubkey::Pubkey,
             signature::Keypair,
-            signer::keypair,
+            signer::{
+                keypair,
+                Signer,
+            },
         },
         std::{
+            collections::HashMap,
             fs,
             path::{
                 Path,
@@ -135,33 +296,42 @@ mod key_store {
         },
     };
 
-    #[derive(Clone, Serialize, Deserialize, Debug)]
-    #[serde(default)]
+    #[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+    #[serde(default, deny_unknown_fields)]
     pub struct Config {
         /// Root directory of the KeyStore
-        pub root_path:            PathBuf,
+        pub root_path:                        PathBuf,
         /// Path to the keypair used to publish price updates,
         /// relative to the root. If set to a non-existent file path,
         /// the system expects a keypair to be loaded via the remote
         /// keypair loader. If the path is valid, the remote keypair
         /// loading is disabled.
-        pub publish_keypair_path: PathBuf,
+        pub publish_keypair_path:             PathBuf,
         /// Path to the public key of the Oracle program, relative to the root
-        pub program_key_path:     PathBuf,
+        pub program_key_path:                 PathBuf,
         /// Path to the public key of the root mapping account, relative to the root
-        pub mapping_key_path:     PathBuf,
+        pub mapping_key_path:                 PathBuf,
         /// Path to the public key of the accumulator program, relative to the root.
-        pub accumulator_key_path: Option<PathBuf>,
+        pub accumulator_key_path:             Option<PathBuf>,
+        /// Path to the public key of the price store program, relative to the
+        /// root. Required by the price store exporter backend.
+        pub price_store_key_path:             Option<PathBuf>,
+        /// Paths to keypairs of additional publishers, relative to the
+        /// root. Prices submitted over the API on behalf of one of these
+        /// publishers are signed with the corresponding keypair.
+        pub additional_publish_keypair_paths: Vec<PathBuf>,
     }
 
     impl Default for Config {
         fn default() -> Self {
             Self {
-                root_path:            Default::default(),
-                publish_keypair_path: "publish_key_pair.json".into(),
-                program_key_path:     "program_key.json".into(),
-                mapping_key_path:     "mapping_key.json".into(),
-                accumulator_key_path: None,
+                root_path:                        Default::default(),
+                publish_keypair_path:             "publish_key_pair.json".into(),
+                program_key_path:                 "program_key.json".into(),
+                mapping_key_path:                 "mapping_key.json".into(),
+                accumulator_key_path:             None,
+                price_store_key_path:             None,
+                additional_publish_keypair_paths: vec![],
             }
         }
     }
@@ -170,25 +340,28 @@ mod key_store {
         /// The keypair used to publish price updates. When None,
         /// publishing will not start until a new keypair is supplied
         /// via the remote loading endpoint
-        pub publish_keypair: Option<Keypair>,
+        pub publish_keypair:             Option<Keypair>,
         /// Public key of the Oracle program
-        pub program_key:     Pubkey,
+        pub program_key:                 Pubkey,
         /// Public key of the root mapping account
-        pub mapping_key:     Pubkey,
+        pub mapping_key:                 Pubkey,
         /// Public key of the accumulator program (if provided)
-        pub accumulator_key: Option<Pubkey>,
+        pub accumulator_key:             Option<Pubkey>,
+        /// Public key of the price store program (if provided)
+        pub price_store_key:             Option<Pubkey>,
+        /// Keypairs of additional publishers, by their public key
+        pub additional_publish_keypairs: HashMap<Pubkey, Keypair>,
     }
 
     impl KeyStore {
-        pub fn new(config: Config, logger: &Logger) -> Result<Self> {
-            let full_keypair_path = config.root_path.join(config.publish_keypair_path);
-
-            let publish_keypair = match keypair::read_keypair_file(&full_keypair_path) {
+        pub fn new(config: Config) -> Result<Self> {
+            let publish_keypair = match Self::read_publish_keypair(&config) {
                 Ok(k) => Some(k),
                 Err(e) => {
-                    warn!(logger,
-			  "Reading publish keypair returned an error. Waiting for a remote-loaded key before publishing.";
-			  "full_keypair_path" => full_keypair_path.to_str(), "error" => e.to_string());
+                    warn!(
+                        error = %format!("{:#}", e),
+                        "Reading publish keypair returned an error. Waiting for a remote-loaded key before publishing."
+                    );
                     None
                 }
             };
@@ -203,6 +376,27 @@ mod key_store {
                     None
                 };
 
+            let price_store_key = config
+                .price_store_key_path
+                .map(|key_path| {
+                    Self::pubkey_from_path(config.root_path.join(key_path))
+                        .context("reading price store key")
+                })
+                .transpose()?;
+
+            let mut additional_publish_keypairs = HashMap::new();
+            for path in config.additional_publish_keypair_paths {
+                let full_path = config.root_path.join(path);
+                let keypair = keypair::read_keypair_file(&full_path).map_err(|e| {
+                    anyhow!(
+                        "Reading additional publish keypair {}: {}",
+                        full_path.display(),
+                        e
+                    )
+                })?;
+                additional_publish_keypairs.insert(keypair.pubkey(), keypair);
+            }
+
             Ok(KeyStore {
                 publish_keypair,
                 program_key: Self::pubkey_from_path(config.root_path.join(config.program_key_path))
@@ -210,9 +404,29 @@ mod key_store {
                 mapping_key: Self::pubkey_from_path(config.root_path.join(config.mapping_key_path))
                     .context("reading mapping key")?,
                 accumulator_key,
+                price_store_key,
+                additional_publish_keypairs,
             })
         }
 
+        /// Read the keypair used to publish price updates
+        pub fn read_publish_keypair(config: &Config) -> Result<Keypair> {
+            let full_keypair_path = config.root_path.join(&config.publish_keypair_path);
+            keypair::read_keypair_file(&full_keypair_path).map_err(|e| {
+                anyhow!(
+                    "Reading publish keypair {}: {}",
+                    full_keypair_path.display(),
+                    e
+                )
+            })
+        }
+
+        /// Read the key of the Oracle program, without loading the keypairs
+        pub fn read_program_key(config: &Config) -> Result<Pubkey> {
+            Self::pubkey_from_path(config.root_path.join(&config.program_key_path))
+                .context("reading program key")
+        }
+
         fn pubkey_from_path(path: impl AsRef<Path>) -> Result<Pubkey> {
             let contents = fs::read_to_string(path)?;
             Pubkey::from_str(contents.trim()).map_err(|e| e.into())
@@ -0,0 +1,190 @@
+// RPC providers have been seen to return truncated account data, or the data of
+// other accounts. The accounts polled by the Oracle are checked before they are
+// parsed: they must be owned by the Oracle program, start with the header of the
+// Oracle program accounts with the expected account type, and be large enough to
+// hold both that type and the size given in their header. The Poller counts the
+// mismatches per RPC endpoint, and quarantines the endpoints serving them.
+use {
+    pyth_sdk_solana::state::{
+        AccountType,
+        MappingAccount,
+        PriceAccount,
+        ProductAccount,
+        MAGIC,
+        VERSION_2,
+    },
+    solana_sdk::{
+        account::Account,
+        pubkey::Pubkey,
+    },
+    std::{
+        fmt,
+        mem::size_of,
+    },
+};
+
+/// Size of the header of the Oracle program accounts: their magic number,
+/// version, account type and size
+const HEADER_SIZE: usize = 16;
+
+/// Kind of Oracle program account a polled account is expected to be
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccountKind {
+    Mapping,
+    Product,
+    Price,
+}
+
+impl AccountKind {
+    fn account_type(self) -> u32 {
+        match self {
+            AccountKind::Mapping => AccountType::Mapping as u32,
+            AccountKind::Product => AccountType::Product as u32,
+            AccountKind::Price => AccountType::Price as u32,
+        }
+    }
+
+    /// Smallest account the kind can be parsed from
+    fn min_size(self) -> usize {
+        match self {
+            AccountKind::Mapping => size_of::<MappingAccount>(),
+            AccountKind::Product => size_of::<ProductAccount>(),
+            AccountKind::Price => size_of::<PriceAccount>(),
+        }
+    }
+}
+
+/// Why an account failed its check
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mismatch {
+    Owner,
+    Magic,
+    Version,
+    AccountType,
+    Size,
+}
+
+impl Mismatch {
+    /// Label of the mismatch in the metrics
+    pub fn label(self) -> &'static str {
+        match self {
+            Mismatch::Owner => "owner",
+            Mismatch::Magic => "magic",
+            Mismatch::Version => "version",
+            Mismatch::AccountType => "account_type",
+            Mismatch::Size => "size",
+        }
+    }
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Mismatch::Owner => write!(f, "not owned by the oracle program"),
+            Mismatch::Magic => write!(f, "wrong magic number"),
+            Mismatch::Version => write!(f, "unsupported version"),
+            Mismatch::AccountType => write!(f, "wrong account type"),
+            Mismatch::Size => write!(f, "truncated data"),
+        }
+    }
+}
+
+/// Check that the account is an Oracle program account of the expected kind
+pub fn check(account: &Account, program_key: &Pubkey, kind: AccountKind) -> Result<(), Mismatch> {
+    if account.owner != *program_key {
+        return Err(Mismatch::Owner);
+    }
+    if account.data.len() < HEADER_SIZE {
+        return Err(Mismatch::Size);
+    }
+
+    let header_field = |index: usize| {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(&account.data[index * 4..index * 4 + 4]);
+        u32::from_le_bytes(bytes)
+    };
+    if header_field(0) != MAGIC {
+        return Err(Mismatch::Magic);
+    }
+    if header_field(1) != VERSION_2 {
+        return Err(Mismatch::Version);
+    }
+    if header_field(2) != kind.account_type() {
+        return Err(Mismatch::AccountType);
+    }
+    if account.data.len() < kind.min_size() || account.data.len() < header_field(3) as usize {
+        return Err(Mismatch::Size);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            check,
+            AccountKind,
+            Mismatch,
+        },
+        bytemuck::Zeroable,
+        pyth_sdk_solana::state::{
+            AccountType,
+            PriceAccount,
+            MAGIC,
+            VERSION_2,
+        },
+        solana_sdk::{
+            account::Account,
+            pubkey::Pubkey,
+        },
+        std::mem::size_of,
+    };
+
+    #[test]
+    fn test_spurious_accounts_are_detected() {
+        let program_key = Pubkey::new_unique();
+        let mut price = PriceAccount::zeroed();
+        price.magic = MAGIC;
+        price.ver = VERSION_2;
+        price.atype = AccountType::Price as u32;
+        price.size = size_of::<PriceAccount>() as u32;
+        let account = Account {
+            owner: program_key,
+            data: bytemuck::bytes_of(&price).to_vec(),
+            ..Account::default()
+        };
+        assert_eq!(check(&account, &program_key, AccountKind::Price), Ok(()));
+
+        // Another program's account, or the wrong kind of account
+        assert_eq!(
+            check(&account, &Pubkey::new_unique(), AccountKind::Price),
+            Err(Mismatch::Owner)
+        );
+        assert_eq!(
+            check(&account, &program_key, AccountKind::Product),
+            Err(Mismatch::AccountType)
+        );
+
+        // Truncated data
+        let mut truncated = account.clone();
+        truncated.data.truncate(1000);
+        assert_eq!(
+            check(&truncated, &program_key, AccountKind::Price),
+            Err(Mismatch::Size)
+        );
+        truncated.data.truncate(8);
+        assert_eq!(
+            check(&truncated, &program_key, AccountKind::Price),
+            Err(Mismatch::Size)
+        );
+
+        // Data which is not an Oracle program account
+        let mut garbage = account;
+        garbage.data[0] ^= 0xff;
+        assert_eq!(
+            check(&garbage, &program_key, AccountKind::Price),
+            Err(Mismatch::Magic)
+        );
+    }
+}
@@ -0,0 +1,330 @@
+// The Accumulator Tracker follows whether the updates of our publishers make it into the
+// accumulator on Pythnet, from which they are delivered cross-chain. When the Oracle
+// program aggregates a price, it writes the price's message to a message buffer account
+// of the accumulator program. The Oracle reports the price accounts it observes, and for
+// the prices our publish keys are components of, the tracker polls their message buffers.
+// An update is counted as delivered when the message buffer of its price is rewritten
+// within `max_delay_slots` of the update's publish slot, and as missed otherwise.
+//
+// A message buffer is taken as rewritten at the slot of the first poll which saw its new
+// content, so the poll interval should be well under the max delay.
+use {
+    super::{
+        instrumented_rpc,
+        key_store::KeyStore,
+        oracle::PriceEntry,
+    },
+    crate::agent::{
+        error,
+        metrics::ACCUMULATOR_METRICS,
+    },
+    anyhow::{
+        Context as _,
+        Result,
+    },
+    parking_lot::Mutex,
+    serde::{
+        Deserialize,
+        Serialize,
+    },
+    solana_client::nonblocking::rpc_client::RpcClient,
+    solana_sdk::{
+        commitment_config::{
+            CommitmentConfig,
+            CommitmentLevel,
+        },
+        pubkey::Pubkey,
+        signer::Signer as _,
+    },
+    std::{
+        collections::{
+            HashMap,
+            HashSet,
+            VecDeque,
+        },
+        str::FromStr,
+        sync::Arc,
+        time::Duration,
+    },
+    tokio::{
+        task::JoinHandle,
+        time,
+    },
+    tracing::Instrument,
+};
+
+/// Message buffers looked up in a single request
+const MAX_LOOKUP_BATCH_SIZE: usize = 100;
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Publish keys whose updates are tracked, in addition to those of the
+    /// key store, e.g. when signing with a remote signer
+    pub publisher_keys:         Vec<String>,
+    /// Duration of the interval at which the message buffers are polled
+    #[serde(with = "humantime_serde")]
+    pub poll_interval_duration: Duration,
+    /// Updates whose message buffer is not rewritten within this many slots
+    /// of their publish slot are counted as missed
+    pub max_delay_slots:        u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            publisher_keys:         vec![],
+            poll_interval_duration: Duration::from_millis(400),
+            max_delay_slots:        10,
+        }
+    }
+}
+
+/// The message buffer of a price, with the updates of our publishers waiting
+/// to be seen in it
+#[derive(Debug)]
+struct TrackedPrice {
+    message_buffer_key: Pubkey,
+    /// Content of the message buffer as of the last poll, not known until
+    /// the first one
+    last_data:          Option<Vec<u8>>,
+    /// Latest publish slot observed of each of our publishers
+    last_pub_slots:     HashMap<Pubkey, u64>,
+    /// Publish slots of the updates not yet seen in the message buffer,
+    /// oldest first
+    pending_slots:      VecDeque<u64>,
+}
+
+impl TrackedPrice {
+    /// Resolve the pending updates given that the message buffer was
+    /// rewritten at the slot, if it was, and that the network reached the
+    /// current slot. Returns the delays of the delivered updates, and the
+    /// number of updates missed.
+    fn resolve(
+        &mut self,
+        written_slot: Option<u64>,
+        current_slot: u64,
+        max_delay_slots: u64,
+    ) -> (Vec<u64>, usize) {
+        let mut delivered = vec![];
+        let mut missed = 0;
+        while let Some(pub_slot) = self.pending_slots.front().copied() {
+            match written_slot {
+                Some(written_slot) if written_slot >= pub_slot => {
+                    let delay = written_slot - pub_slot;
+                    if delay <= max_delay_slots {
+                        delivered.push(delay);
+                    } else {
+                        missed += 1;
+                    }
+                }
+                _ if current_slot > pub_slot + max_delay_slots => missed += 1,
+                _ => break,
+            }
+            self.pending_slots.pop_front();
+        }
+        (delivered, missed)
+    }
+}
+
+/// Handle through which the Oracle reports the price accounts it observes
+#[derive(Clone)]
+pub struct AccumulatorUpdates {
+    publisher_keys:  Arc<HashSet<Pubkey>>,
+    oracle_auth_key: Pubkey,
+    accumulator_key: Pubkey,
+    prices:          Arc<Mutex<HashMap<Pubkey, TrackedPrice>>>,
+}
+
+impl AccumulatorUpdates {
+    pub fn new(
+        publisher_keys: HashSet<Pubkey>,
+        program_key: Pubkey,
+        accumulator_key: Pubkey,
+    ) -> Self {
+        let (oracle_auth_key, _) = Pubkey::find_program_address(
+            &[b"upd_price_write", &accumulator_key.to_bytes()],
+            &program_key,
+        );
+        AccumulatorUpdates {
+            publisher_keys: Arc::new(publisher_keys),
+            oracle_auth_key,
+            accumulator_key,
+            prices: Default::default(),
+        }
+    }
+
+    /// Record the new updates of our publishers in the price account
+    pub fn observe_price_account(&self, price_key: &Pubkey, price_account: &PriceEntry) {
+        let mut prices = self.prices.lock();
+        for component in price_account
+            .comp
+            .iter()
+            .filter(|component| self.publisher_keys.contains(&component.publisher))
+        {
+            let tracked = prices.entry(*price_key).or_insert_with(|| TrackedPrice {
+                message_buffer_key: Pubkey::find_program_address(
+                    &[
+                        &self.oracle_auth_key.to_bytes(),
+                        b"message",
+                        &price_key.to_bytes(),
+                    ],
+                    &self.accumulator_key,
+                )
+                .0,
+                last_data:          None,
+                last_pub_slots:     HashMap::new(),
+                pending_slots:      VecDeque::new(),
+            });
+            let pub_slot = component.latest.pub_slot;
+            let last_pub_slot = tracked.last_pub_slots.insert(component.publisher, pub_slot);
+            // The first update observed may predate the tracking, and only
+            // sets where the new updates start from
+            if last_pub_slot.map_or(false, |last_pub_slot| pub_slot > last_pub_slot) {
+                let index = tracked
+                    .pending_slots
+                    .partition_point(|pending_slot| *pending_slot < pub_slot);
+                if tracked.pending_slots.get(index) != Some(&pub_slot) {
+                    tracked.pending_slots.insert(index, pub_slot);
+                }
+            }
+        }
+    }
+}
+
+/// Create the handle the Oracle reports to, and spawn the tracker polling
+/// the message buffers of our prices
+pub fn spawn_tracker(
+    config: Config,
+    network_name: &str,
+    rpc_url: &str,
+    rpc_timeout: Duration,
+    commitment: CommitmentLevel,
+    key_store: &KeyStore,
+    accumulator_key: Pubkey,
+) -> (AccumulatorUpdates, JoinHandle<()>) {
+    let publisher_keys = config
+        .publisher_keys
+        .iter()
+        .filter_map(|key| match Pubkey::from_str(key) {
+            Ok(key) => Some(key),
+            Err(err) => {
+                error!(%key, error = %err, "Accumulator tracker: ignoring invalid publisher key");
+                None
+            }
+        })
+        .chain(
+            key_store
+                .publish_keypair
+                .iter()
+                .map(|keypair| keypair.pubkey()),
+        )
+        .chain(key_store.additional_publish_keypairs.keys().copied())
+        .collect();
+    let updates = AccumulatorUpdates::new(publisher_keys, key_store.program_key, accumulator_key);
+
+    let rpc_client = instrumented_rpc::new_rpc_client(
+        network_name,
+        rpc_url,
+        rpc_timeout,
+        CommitmentConfig { commitment },
+    );
+    let network_name = network_name.to_string();
+    let tracked = updates.clone();
+    let jh = tokio::spawn(
+        async move {
+            let mut poll_interval = time::interval(config.poll_interval_duration);
+            loop {
+                poll_interval.tick().await;
+                if let Err(err) = poll_message_buffers(
+                    &rpc_client,
+                    &tracked,
+                    config.max_delay_slots,
+                    &network_name,
+                )
+                .await
+                {
+                    error!(error = ?err, kind = %error::record("accumulator", &err), "{:#}", err);
+                }
+            }
+        }
+        .instrument(info_span!("accumulator_tracker")),
+    );
+    (updates, jh)
+}
+
+async fn poll_message_buffers(
+    rpc_client: &RpcClient,
+    updates: &AccumulatorUpdates,
+    max_delay_slots: u64,
+    network_name: &str,
+) -> Result<()> {
+    let tracked_keys = updates
+        .prices
+        .lock()
+        .iter()
+        .map(|(price_key, tracked)| (*price_key, tracked.message_buffer_key))
+        .collect::<Vec<_>>();
+
+    for batch in tracked_keys.chunks(MAX_LOOKUP_BATCH_SIZE) {
+        let message_buffer_keys = batch
+            .iter()
+            .map(|(_, message_buffer_key)| *message_buffer_key)
+            .collect::<Vec<_>>();
+        let response = rpc_client
+            .get_multiple_accounts_with_commitment(&message_buffer_keys, rpc_client.commitment())
+            .await
+            .context("fetching the message buffers")?;
+        let current_slot = response.context.slot;
+
+        let mut prices = updates.prices.lock();
+        for ((price_key, _), account) in batch.iter().zip(response.value) {
+            let tracked = match prices.get_mut(price_key) {
+                Some(tracked) => tracked,
+                None => continue,
+            };
+            let data = account.map(|account| account.data);
+            let written_slot = match (&tracked.last_data, &data) {
+                (Some(last_data), Some(data)) if last_data != data => Some(current_slot),
+                _ => None,
+            };
+            if data.is_some() {
+                tracked.last_data = data;
+            }
+
+            let (delivered, missed) = tracked.resolve(written_slot, current_slot, max_delay_slots);
+            ACCUMULATOR_METRICS.record(network_name, &delivered, missed);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::TrackedPrice,
+        solana_sdk::pubkey::Pubkey,
+        std::collections::HashMap,
+    };
+
+    #[test]
+    fn test_updates_are_delivered_when_the_message_buffer_is_rewritten_in_time() {
+        let mut tracked = TrackedPrice {
+            message_buffer_key: Pubkey::new_unique(),
+            last_data:          Some(vec![]),
+            last_pub_slots:     HashMap::new(),
+            pending_slots:      [100, 102, 110].into(),
+        };
+
+        // Nothing is resolved before the buffer is rewritten or the max delay passes
+        assert_eq!(tracked.resolve(None, 105, 10), (vec![], 0));
+
+        // The buffer rewritten at slot 104 includes the updates published before it
+        assert_eq!(tracked.resolve(Some(104), 105, 10), (vec![4, 2], 0));
+
+        // The last update is missed once the max delay passed without a rewrite
+        assert_eq!(tracked.resolve(None, 121, 10), (vec![], 1));
+        assert!(tracked.pending_slots.is_empty());
+    }
+}
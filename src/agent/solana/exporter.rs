@@ -1,42 +1,123 @@
+pub mod destination;
+pub mod lazer;
+pub mod slot_clock;
+
 use {
-    self::transaction_monitor::TransactionMonitor,
+    self::{
+        lazer::LazerExporter,
+        slot_clock::{
+            SlotBoundary,
+            SlotClock,
+        },
+        transaction_monitor::{
+            SentBatch,
+            SentTransaction,
+            TransactionMonitor,
+        },
+    },
     super::{
         super::store::{
             self,
-            local::PriceInfo,
+            global,
+            local::{
+                self,
+                AllPriceInfo,
+                AllTraceContexts,
+                PriceInfo,
+                Publisher,
+            },
+            symbol_index::SymbolIndex,
+            transactions::{
+                self,
+                TransactionRecord,
+                TransactionStatus,
+            },
             PriceIdentifier,
         },
+        instrumented_rpc,
         key_store,
+        leader_schedule::{
+            self,
+            LeaderSchedule,
+            LeaderTracker,
+        },
+        signer::{
+            self,
+            kms::{
+                self,
+                KmsSigner,
+            },
+            ledger,
+            KeypairSigner,
+            RemoteSigner,
+        },
+        slot_lag::NetworkSlots,
     },
-    crate::agent::remote_keypair_loader::{
-        KeypairRequest,
-        RemoteKeypairLoader,
+    crate::agent::{
+        channel_monitor::ChannelMonitor,
+        error::{
+            self,
+            Error,
+        },
+        health::HealthReporter,
+        metrics::{
+            CONF_FLOOR_METRICS,
+            DRY_RUN_METRICS,
+            PUBLISH_PERMISSION_METRICS,
+            PUBLISH_RETRY_METRICS,
+        },
+        publish_pause::{
+            PauseState,
+            PublishPause,
+        },
+        remote_keypair_loader::{
+            KeypairRequest,
+            RemoteKeypairLoader,
+        },
+        shutdown,
+        telemetry,
+        update_status::UpdateStatuses,
     },
     anyhow::{
         anyhow,
-        Context,
+        Context as _,
         Result,
     },
+    async_trait::async_trait,
     bincode::Options,
     chrono::Utc,
-    futures_util::future::{
-        self,
-        join_all,
+    futures_util::{
+        future::{
+            self,
+            join_all,
+        },
+        StreamExt,
     },
     key_store::KeyStore,
-    pyth_sdk::Identifier,
+    opentelemetry::{
+        trace::TraceContextExt,
+        Context,
+        KeyValue,
+    },
+    parking_lot::RwLock,
     pyth_sdk_solana::state::PriceStatus,
     serde::{
         Deserialize,
         Serialize,
     },
-    slog::Logger,
     solana_client::{
-        nonblocking::rpc_client::RpcClient,
+        nonblocking::{
+            pubsub_client::PubsubClient,
+            rpc_client::RpcClient,
+        },
         rpc_config::RpcSendTransactionConfig,
     },
     solana_sdk::{
         bs58,
+        clock::{
+            DEFAULT_MS_PER_SLOT,
+            MAX_PROCESSING_AGE,
+        },
         commitment_config::CommitmentConfig,
         compute_budget::ComputeBudgetInstruction,
         hash::Hash,
@@ -44,12 +125,13 @@ use {
             AccountMeta,
             Instruction,
         },
+        message::Message,
         pubkey::Pubkey,
         signature::{
             Keypair,
             Signature,
         },
-        signer::Signer,
+        signer::Signer as _,
         sysvar::clock,
         transaction::Transaction,
     },
@@ -57,13 +139,20 @@ use {
         collections::{
             HashMap,
             HashSet,
+            VecDeque,
+        },
+        sync::Arc,
+        time::{
+            Duration,
+            Instant,
         },
-        time::Duration,
     },
     tokio::{
+        net::UdpSocket,
         sync::{
-            mpsc,
+            broadcast,
             mpsc::{
+                self,
                 error::TryRecvError,
                 Sender,
             },
@@ -76,9 +165,14 @@ use {
             Interval,
         },
     },
+    tracing::Instrument,
 };
 
 const PYTH_ORACLE_VERSION: u32 = 2;
+
+/// Time after which a submission can no longer land, once its blockhash expired
+const SUBMISSION_EXPIRY: Duration =
+    Duration::from_millis(MAX_PROCESSING_AGE as u64 * DEFAULT_MS_PER_SLOT);
 const UPDATE_PRICE_NO_FAIL_ON_ERROR: i32 = 13;
 // const UPDATE_PRICE: i32 = 7; // Useful for making tx errors more visible in place of UPDATE_PRICE_NO_FAIL_ON_ERROR
 
@@ -94,14 +188,72 @@ struct UpdPriceCmd {
     pub_slot: u64,
 }
 
-#[derive(Clone, Serialize, Deserialize, Debug)]
-#[serde(default)]
+/// Instruction of the price store program which submits prices to the
+/// buffer account of a publisher
+const PRICE_STORE_SUBMIT_PRICES: u8 = 1;
+/// Seed of the publisher config PDA of the price store program
+const PRICE_STORE_PUBLISHER_CONFIG_SEED: &[u8] = b"PUBLISHER_CONFIG";
+/// Offset of the buffer account in the publisher config account, after its
+/// format (u32) and publisher (Pubkey)
+const PRICE_STORE_BUFFER_KEY_OFFSET: usize = 36;
+/// The feed index takes the low 28 bits of a buffered price, and the trading
+/// status the high 4 bits
+const PRICE_STORE_MAX_FEED_INDEX: u32 = (1 << 28) - 1;
+
+/// A price submitted to the buffer account of a publisher, following the
+/// `submit_prices` instruction header
+#[repr(C)]
+#[derive(Serialize, PartialEq, Debug, Clone)]
+struct BufferedPrice {
+    trading_status_and_feed_index: u32,
+    price:                         i64,
+    conf:                          u64,
+}
+
+impl BufferedPrice {
+    fn new(feed_index: u32, price_info: &PriceInfo) -> Option<Self> {
+        (feed_index <= PRICE_STORE_MAX_FEED_INDEX).then(|| BufferedPrice {
+            trading_status_and_feed_index: ((price_info.status as u32) << 28) | feed_index,
+            price:                         price_info.price,
+            conf:                          price_info.conf,
+        })
+    }
+}
+
+/// Program the updates are submitted to
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Backend {
+    /// One `upd_price` instruction of the Oracle program per update
+    Oracle,
+    /// A single `submit_prices` instruction of the price store program per
+    /// batch, written to the publisher's buffer account. Prices which have no
+    /// feed index yet are still published with `upd_price`.
+    PriceStore,
+}
+
+impl Default for Backend {
+    fn default() -> Self {
+        Backend::Oracle
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
 pub struct Config {
     /// Duration of the interval at which to refresh the cached network state (current slot and blockhash).
     /// It is recommended to set this to slightly less than the network's block time,
     /// as the slot fetched will be used as the time of the price update.
     #[serde(with = "humantime_serde")]
     pub refresh_network_state_interval_duration: Duration,
+    /// Also refresh the cached network state on every new slot notified over the
+    /// network's websocket endpoint, keeping the blockhash as fresh as possible.
+    /// The interval keeps refreshing it if the subscription fails.
+    pub refresh_network_state_on_new_slot:       bool,
+    /// Batches are not published while the cached blockhash is older than this,
+    /// as the transactions would likely expire before landing
+    #[serde(with = "humantime_serde")]
+    pub max_blockhash_age:                       Duration,
     /// Duration of the interval at which to publish updates
     #[serde(with = "humantime_serde")]
     pub publish_interval_duration:               Duration,
@@ -114,6 +266,10 @@ pub struct Config {
     pub unchanged_publish_threshold:             Duration,
     /// Maximum size of a batch
     pub max_batch_size:                          usize,
+    /// Duration of the interval at which the publish keys' permissions to
+    /// publish the prices updated in the local store are checked
+    #[serde(with = "humantime_serde")]
+    pub permission_check_interval_duration:      Duration,
     /// Capacity of the channel between the Exporter and the Transaction Monitor
     pub inflight_transactions_channel_capacity:  usize,
     /// Configuration for the Transaction Monitor
@@ -124,154 +280,926 @@ pub struct Config {
     pub compute_unit_limit:                      u32,
     /// Price per compute unit offered for update_price transactions
     pub compute_unit_price_micro_lamports:       Option<u64>,
+    /// Program the updates are submitted to. The price store backend requires
+    /// the key store's price store program key.
+    pub backend:                                 Backend,
+    /// Publishes the batches whose transactions did not land in time again,
+    /// offering a higher compute unit price on each attempt. Disabled when not set.
+    pub retry:                                   Option<RetryConfig>,
+    /// Remote signing service holding the publish keypair. When set, the updates
+    /// which were not submitted on behalf of a specific publisher are signed by
+    /// it rather than with the publish keypair of the key store.
+    pub remote_signer:                           Option<signer::Config>,
+    /// Cloud KMS holding the publish keypair, used in the same way as the
+    /// remote signer. At most one of the signers can be configured.
+    pub kms_signer:                              Option<kms::Config>,
+    /// Ledger hardware wallet holding the publish keypair, used in the same way
+    /// as the remote signer. Each transaction may need to be approved on the
+    /// device, so it only suits low publish rates. Requires the ledger feature.
+    pub ledger_signer:                           Option<ledger::Config>,
+    /// Times the transactions around leader rotations, and sends them to the
+    /// TPUs of the upcoming leaders, using the network's leader schedule.
+    /// Disabled when not set.
+    pub leader_schedule:                         Option<leader_schedule::Config>,
+    /// Build and sign the transactions, then log and count them in the
+    /// `dry_run_transaction_count` metric instead of sending them. Unlike in
+    /// simulation, the network state is fetched from the RPC node.
+    pub dry_run:                                 bool,
+    /// Minimum confidence intervals of the published trading prices, by
+    /// symbol or asset class. Disabled when not set.
+    pub conf_floor:                              Option<ConfFloorConfig>,
+    /// Publishes the updates as soon as enough of them are pending, or as soon
+    /// as the oldest of them has waited long enough, rather than on the next
+    /// tick of the publish interval. Disabled when not set.
+    pub micro_batching:                          Option<MicroBatchingConfig>,
+    /// Pauses publishing while the network slot is not advancing, as during a
+    /// cluster halt, and resumes it once the slot advances. Disabled when not set.
+    pub slot_stall:                              Option<SlotStallConfig>,
+    /// Delays each tick of the publish interval until an offset into the next
+    /// slot of the network, as notified over its websocket endpoint, for the
+    /// updates to land within that slot. Disabled when not set.
+    pub slot_alignment:                          Option<slot_clock::Config>,
+    /// Publishes the updates of the configured feeds to Pyth Lazer instead of
+    /// sending transactions to the network, which is still read by the Oracle.
+    /// Disabled when not set.
+    pub lazer:                                   Option<lazer::Config>,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             refresh_network_state_interval_duration: Duration::from_millis(200),
+            refresh_network_state_on_new_slot:       false,
+            max_blockhash_age:                       Duration::from_secs(30),
             publish_interval_duration:               Duration::from_secs(1),
             staleness_threshold:                     Duration::from_secs(5),
             unchanged_publish_threshold:             Duration::from_secs(5),
             max_batch_size:                          12,
+            permission_check_interval_duration:      Duration::from_secs(60),
             inflight_transactions_channel_capacity:  10000,
             transaction_monitor:                     Default::default(),
             // The largest transactions appear to be about ~12000 CUs. We leave ourselves some breathing room.
             compute_unit_limit:                      40000,
             compute_unit_price_micro_lamports:       None,
+            backend:                                 Backend::Oracle,
+            retry:                                   None,
+            remote_signer:                           None,
+            kms_signer:                              None,
+            ledger_signer:                           None,
+            leader_schedule:                         None,
+            dry_run:                                 false,
+            conf_floor:                              None,
+            micro_batching:                          None,
+            slot_stall:                              None,
+            slot_alignment:                          None,
+            lazer:                                   None,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct MicroBatchingConfig {
+    /// The updates are published as soon as this many are pending
+    pub max_pending_updates: usize,
+    /// The updates are published at the latest this long after the oldest
+    /// of them was accepted by the Local Store
+    #[serde(with = "humantime_serde")]
+    pub max_delay:           Duration,
+}
+
+impl Default for MicroBatchingConfig {
+    fn default() -> Self {
+        Self {
+            max_pending_updates: 12,
+            max_delay:           Duration::from_millis(100),
+        }
+    }
+}
+
+/// Updates accepted by the Local Store since the last publish
+#[derive(Debug, Default)]
+struct PendingUpdates {
+    count:       usize,
+    /// When the oldest of them was accepted
+    oldest_time: Option<Instant>,
+}
+
+impl PendingUpdates {
+    fn record(&mut self, count: usize) {
+        self.count += count;
+        self.oldest_time.get_or_insert_with(Instant::now);
+    }
+
+    fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Time at which the pending updates are due to be published
+    fn deadline(&self, config: &MicroBatchingConfig) -> Option<Instant> {
+        self.oldest_time
+            .map(|oldest_time| oldest_time + config.max_delay)
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct SlotStallConfig {
+    /// Publishing is paused once the network slot has not advanced for this long
+    #[serde(with = "humantime_serde")]
+    pub threshold: Duration,
+}
+
+impl Default for SlotStallConfig {
+    fn default() -> Self {
+        Self {
+            threshold: Duration::from_secs(10),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetryConfig {
+    /// A batch is published again when its transaction is not confirmed within
+    /// this many slots of being sent
+    pub confirmation_timeout_slots:                u64,
+    /// Maximum number of attempts to publish a batch, including the first one
+    pub max_attempts:                              u32,
+    /// Compute unit price the retries escalate from, when
+    /// exporter.compute_unit_price_micro_lamports is not set
+    pub initial_compute_unit_price_micro_lamports: u64,
+    /// Each attempt offers this many times the compute unit price of the previous one
+    pub fee_multiplier:                            f64,
+    /// Highest compute unit price offered by the retries
+    pub max_compute_unit_price_micro_lamports:     u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            confirmation_timeout_slots:                20,
+            max_attempts:                              3,
+            initial_compute_unit_price_micro_lamports: 1000,
+            fee_multiplier:                            2.0,
+            max_compute_unit_price_micro_lamports:     100000,
+        }
+    }
+}
+
+/// What is done with the updates whose confidence interval is below their floor
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfFloorAction {
+    /// The confidence interval is raised to the floor
+    Clamp,
+    /// The update is not published
+    Reject,
+}
+
+/// Floor of the confidence interval of a price, the highest of its absolute
+/// and relative bounds
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfFloor {
+    /// Lowest confidence interval, in the exponent-scaled units of the price
+    pub min_conf:       u64,
+    /// Lowest ratio of confidence interval to absolute price
+    pub min_conf_ratio: f64,
+}
+
+impl ConfFloor {
+    fn min_conf(&self, price: i64) -> u64 {
+        let relative = (price.unsigned_abs() as f64 * self.min_conf_ratio).ceil() as u64;
+        self.min_conf.max(relative)
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfFloorConfig {
+    /// Whether updates below their floor are clamped to it or rejected
+    pub action:             ConfFloorAction,
+    /// Floor of the prices without one for their symbol or asset class
+    pub default_floor:      ConfFloor,
+    /// Floors by asset class, the part of the symbol before the first dot,
+    /// e.g. "Equity"
+    pub asset_class_floors: HashMap<String, ConfFloor>,
+    /// Floors by symbol, e.g. "Crypto.BTC/USD", replacing the floor of their
+    /// asset class
+    pub symbol_floors:      HashMap<String, ConfFloor>,
+}
+
+impl Default for ConfFloorConfig {
+    fn default() -> Self {
+        Self {
+            action:             ConfFloorAction::Clamp,
+            default_floor:      ConfFloor::default(),
+            asset_class_floors: HashMap::new(),
+            symbol_floors:      HashMap::new(),
+        }
+    }
+}
+
+impl ConfFloorConfig {
+    fn floor(&self, symbol: Option<&str>) -> &ConfFloor {
+        symbol
+            .and_then(|symbol| {
+                self.symbol_floors.get(symbol).or_else(|| {
+                    let (asset_class, _) = symbol.split_once('.')?;
+                    self.asset_class_floors.get(asset_class)
+                })
+            })
+            .unwrap_or(&self.default_floor)
+    }
+}
+
+impl RetryConfig {
+    /// Compute unit price offered by the given attempt, escalated from the base
+    /// price on each retry up to the cap. Retries never offer less than the base
+    /// price, whatever the multiplier.
+    fn compute_unit_price(&self, base_price: Option<u64>, attempt: u32) -> Option<u64> {
+        if attempt <= 1 {
+            return base_price;
         }
+        let base_price = base_price.unwrap_or(self.initial_compute_unit_price_micro_lamports);
+        let price = (base_price as f64 * self.fee_multiplier.powf((attempt - 1) as f64))
+            .max(base_price as f64);
+        Some((price as u64).min(self.max_compute_unit_price_micro_lamports))
     }
 }
 
 pub fn spawn_exporter(
-    config: Config,
+    config_rx: watch::Receiver<Config>,
+    network_name: &str,
     rpc_url: &str,
+    wss_url: &str,
     rpc_timeout: Duration,
     publisher_permissions_rx: mpsc::Receiver<HashMap<Pubkey, HashSet<Pubkey>>>,
     key_store: KeyStore,
+    key_store_config_rx: watch::Receiver<key_store::Config>,
     local_store_tx: Sender<store::local::Message>,
+    transactions_store_tx: Sender<transactions::Message>,
+    local_store_events_tx: broadcast::Sender<local::Event>,
     keypair_request_tx: mpsc::Sender<KeypairRequest>,
-    logger: Logger,
+    global_store_reader: global::SnapshotReader,
+    publish_pause: PublishPause,
+    update_statuses: UpdateStatuses,
+    slots: NetworkSlots,
+    simulated: bool,
+    health: &HealthReporter,
+    channel_monitor: &ChannelMonitor,
+    shutdown: shutdown::Participant,
 ) -> Result<Vec<JoinHandle<()>>> {
-    // Create and spawn the network state querier
+    let config = config_rx.borrow().clone();
+
+    let publish_signer: Option<Arc<dyn signer::Signer>> = match (
+        config.remote_signer.clone(),
+        config.kms_signer.clone(),
+        config.ledger_signer.clone(),
+    ) {
+        (Some(signer_config), None, None) => {
+            Some(Arc::new(RemoteSigner::new(signer_config, network_name)?))
+        }
+        (None, Some(signer_config), None) => {
+            Some(Arc::new(KmsSigner::new(signer_config, network_name)?))
+        }
+        (None, None, Some(signer_config)) => Some(ledger::new_signer(signer_config)?),
+        (None, None, None) => None,
+        _ => {
+            return Err(anyhow!(
+                "only one of exporter.remote_signer, exporter.kms_signer and exporter.ledger_signer can be configured"
+            ))
+        }
+    };
+
+    // Publish to Pyth Lazer instead of the network, if configured
+    if let Some(lazer_config) = config.lazer.clone() {
+        let destination = Arc::new(LazerExporter::new(
+            lazer_config,
+            config_rx.clone(),
+            network_name,
+            global_store_reader.clone(),
+            simulated,
+        )?);
+        return Ok(spawn_publishing(
+            destination,
+            config_rx,
+            network_name,
+            rpc_url,
+            rpc_timeout,
+            publisher_permissions_rx,
+            key_store,
+            key_store_config_rx,
+            local_store_tx,
+            transactions_store_tx,
+            local_store_events_tx,
+            keypair_request_tx,
+            publish_signer,
+            global_store_reader,
+            publish_pause,
+            update_statuses,
+            slots,
+            None,
+            simulated,
+            health,
+            channel_monitor,
+            shutdown,
+        ));
+    }
+
+    let mut jhs = vec![];
+
+    // Create and spawn the network state querier. In simulation, the
+    // transactions are built with the default network state.
     let (network_state_tx, network_state_rx) = watch::channel(Default::default());
     let mut network_state_querier = NetworkStateQuerier::new(
+        network_name,
         rpc_url,
+        config
+            .refresh_network_state_on_new_slot
+            .then(|| wss_url.to_string()),
         rpc_timeout,
         time::interval(config.refresh_network_state_interval_duration),
         network_state_tx,
-        logger.clone(),
     );
-    let network_state_querier_jh = tokio::spawn(async move { network_state_querier.run().await });
+    if !simulated {
+        jhs.push(tokio::spawn(
+            async move { network_state_querier.run().await }
+                .instrument(info_span!("network_state_querier")),
+        ));
+    }
+
+    // Create and spawn the leader tracker, if enabled. The simulated cluster
+    // has no leaders.
+    let leader_schedule_rx = match config.leader_schedule.clone() {
+        Some(leader_schedule_config) if !simulated => {
+            let (leader_schedule_tx, leader_schedule_rx) = watch::channel(Default::default());
+            let mut leader_tracker = LeaderTracker::new(
+                network_name,
+                rpc_url,
+                rpc_timeout,
+                leader_schedule_config,
+                leader_schedule_tx,
+            );
+            jhs.push(tokio::spawn(
+                async move { leader_tracker.run().await }.instrument(info_span!("leader_tracker")),
+            ));
+            Some(leader_schedule_rx)
+        }
+        _ => None,
+    };
+
+    // Create and spawn the slot clock the publish interval is aligned to, if
+    // enabled. The simulated cluster has no websocket endpoint.
+    let slot_boundary_rx = match config.slot_alignment.clone() {
+        Some(slot_alignment_config) if !simulated => {
+            let (slot_boundary_tx, slot_boundary_rx) = watch::channel(None);
+            let mut slot_clock = SlotClock::new(
+                network_name,
+                wss_url,
+                slot_alignment_config,
+                slot_boundary_tx,
+            );
+            jhs.push(tokio::spawn(
+                async move { slot_clock.run().await }.instrument(info_span!("slot_clock")),
+            ));
+            Some(slot_boundary_rx)
+        }
+        _ => None,
+    };
+
+    // Create the destination the batches are sent to
+    let destination = Arc::new(SolanaExporter::new(
+        config_rx.clone(),
+        network_name,
+        rpc_url,
+        rpc_timeout,
+        &key_store,
+        network_state_rx,
+        global_store_reader.clone(),
+        leader_schedule_rx,
+        simulated,
+    )?);
+
+    jhs.extend(spawn_publishing(
+        destination,
+        config_rx,
+        network_name,
+        rpc_url,
+        rpc_timeout,
+        publisher_permissions_rx,
+        key_store,
+        key_store_config_rx,
+        local_store_tx,
+        transactions_store_tx,
+        local_store_events_tx,
+        keypair_request_tx,
+        publish_signer,
+        global_store_reader,
+        publish_pause,
+        update_statuses,
+        slots,
+        slot_boundary_rx,
+        simulated,
+        health,
+        channel_monitor,
+        shutdown,
+    ));
+
+    Ok(jhs)
+}
 
-    // Create and spawn the transaction monitor
+/// Spawn the Exporter publishing to the destination, and the transaction
+/// monitor confirming its batches
+fn spawn_publishing<D: destination::Exporter>(
+    destination: Arc<D>,
+    config_rx: watch::Receiver<Config>,
+    network_name: &str,
+    rpc_url: &str,
+    rpc_timeout: Duration,
+    publisher_permissions_rx: mpsc::Receiver<HashMap<Pubkey, HashSet<Pubkey>>>,
+    key_store: KeyStore,
+    key_store_config_rx: watch::Receiver<key_store::Config>,
+    local_store_tx: Sender<store::local::Message>,
+    transactions_store_tx: Sender<transactions::Message>,
+    local_store_events_tx: broadcast::Sender<local::Event>,
+    keypair_request_tx: mpsc::Sender<KeypairRequest>,
+    publish_signer: Option<Arc<dyn signer::Signer>>,
+    global_store_reader: global::SnapshotReader,
+    publish_pause: PublishPause,
+    update_statuses: UpdateStatuses,
+    slots: NetworkSlots,
+    slot_boundary_rx: Option<watch::Receiver<Option<SlotBoundary>>>,
+    simulated: bool,
+    health: &HealthReporter,
+    channel_monitor: &ChannelMonitor,
+    shutdown: shutdown::Participant,
+) -> Vec<JoinHandle<()>> {
+    let config = config_rx.borrow().clone();
+    let mut jhs = vec![];
+
+    // Create and spawn the transaction monitor, which has nothing to monitor
+    // in simulation. It hands the batches which did not land back to the
+    // exporter, if retries are enabled.
+    let (retry_tx, retry_rx) = mpsc::channel(config.inflight_transactions_channel_capacity);
     let (transactions_tx, transactions_rx) =
         mpsc::channel(config.inflight_transactions_channel_capacity);
+    let inflight_transactions_channel = format!("{}_inflight_transactions", network_name);
+    channel_monitor.register(
+        inflight_transactions_channel.clone(),
+        &transactions_tx,
+        config.inflight_transactions_channel_capacity,
+    );
     let mut transaction_monitor = TransactionMonitor::new(
         config.transaction_monitor.clone(),
+        config_rx.clone(),
+        network_name,
         rpc_url,
         rpc_timeout,
+        destination.clone(),
         transactions_rx,
-        logger.clone(),
+        retry_tx,
+        transactions_store_tx.clone(),
+        update_statuses.clone(),
+        health.component(format!("{}.exporter", network_name)),
     );
-    let transaction_monitor_jh = tokio::spawn(async move { transaction_monitor.run().await });
+    if !simulated {
+        jhs.push(tokio::spawn(
+            async move { transaction_monitor.run().await }
+                .instrument(info_span!("transaction_monitor")),
+        ));
+    }
 
     // Create and spawn the exporter
     let mut exporter = Exporter::new(
-        config,
-        rpc_url,
-        rpc_timeout,
+        config_rx,
+        network_name,
+        destination,
         key_store,
+        key_store_config_rx,
         local_store_tx,
-        network_state_rx,
+        transactions_store_tx,
+        local_store_events_tx,
         transactions_tx,
+        inflight_transactions_channel,
+        retry_rx,
+        channel_monitor.clone(),
         publisher_permissions_rx,
         keypair_request_tx,
-        logger,
+        publish_signer,
+        global_store_reader,
+        publish_pause,
+        update_statuses,
+        slots,
+        slot_boundary_rx,
     );
-    let exporter_jh = tokio::spawn(async move { exporter.run().await });
+    jhs.push(tokio::spawn(
+        async move { exporter.run(shutdown).await }.instrument(info_span!("exporter")),
+    ));
+
+    jhs
+}
 
-    Ok(vec![
-        network_state_querier_jh,
-        transaction_monitor_jh,
-        exporter_jh,
-    ])
+/// A batch prepared and handed to the destination
+struct PublishedBatch {
+    /// Signature of the submission, if it was actually sent
+    signature: Option<Signature>,
+    /// The refreshed updates the batch was prepared with
+    prices:    Vec<(PriceIdentifier, PriceInfo)>,
 }
 
 /// Exporter is responsible for exporting data held in the local store
 /// to the global Pyth Network.
-pub struct Exporter {
-    rpc_client: RpcClient,
-
+pub struct Exporter<D> {
     config: Config,
 
+    /// Watched for the settings reloaded on SIGHUP
+    config_rx: watch::Receiver<Config>,
+
+    /// Name of the network this Exporter publishes to
+    network_name: String,
+
+    /// Destination the batches are prepared for and submitted to
+    destination: Arc<D>,
+
     /// Interval at which to publish updates
     publish_interval: Interval,
 
     /// The Key Store
     key_store: KeyStore,
 
+    /// Watched for a new publish keypair on SIGHUP
+    key_store_config_rx: watch::Receiver<key_store::Config>,
+
     /// Channel on which to communicate with the local store
     local_store_tx: Sender<store::local::Message>,
 
-    /// The last state published for each price identifier. Used to
-    /// rule out stale data and prevent repetitive publishing of
-    /// unchanged prices.
-    last_published_state: HashMap<PriceIdentifier, PriceInfo>,
+    /// Channel on which to record sent transactions in the transactions store
+    transactions_store_tx: Sender<transactions::Message>,
 
-    /// Watch receiver channel to access the current network state
-    network_state_rx: watch::Receiver<NetworkState>,
+    /// Subscribed to for the updates accepted by the Local Store while
+    /// micro-batching is enabled
+    local_store_events_tx: broadcast::Sender<local::Event>,
+
+    /// Updates accepted by the Local Store, counted by the micro-batching.
+    /// Only subscribed to while it is enabled.
+    local_store_events_rx: Option<broadcast::Receiver<local::Event>>,
+
+    /// Updates accepted since the last publish, if micro-batching is enabled
+    pending_updates: PendingUpdates,
+
+    /// The last state published for each price identifier, per
+    /// publisher. Used to rule out stale data and prevent repetitive
+    /// publishing of unchanged prices.
+    last_published_state: HashMap<(Publisher, PriceIdentifier), PriceInfo>,
+
+    /// Signatures of the submissions sent until they can no longer land, with
+    /// the key they were signed with and when they were sent
+    sent_signatures: VecDeque<(Pubkey, Signature, Instant)>,
+
+    /// Publish keypair switched over to once the submissions signed with the
+    /// current one have landed or expired
+    next_publish_keypair: Option<Keypair>,
 
     // Channel on which to send inflight transactions to the transaction monitor
-    inflight_transactions_tx: Sender<Signature>,
+    inflight_transactions_tx: Sender<SentTransaction>,
+
+    /// Name under which the inflight transactions channel is monitored
+    inflight_transactions_channel: String,
+
+    /// Batches whose transactions did not land in time, to be published again
+    retry_rx: mpsc::Receiver<SentBatch>,
+
+    /// Counts the sends blocked on a full channel
+    channel_monitor: ChannelMonitor,
 
     /// Permissioned symbols as read by the oracle module
     publisher_permissions_rx: mpsc::Receiver<HashMap<Pubkey, HashSet<Pubkey>>>,
 
-    /// Currently known permissioned prices of this publisher
-    our_prices: HashSet<Pubkey>,
+    /// Currently known permissioned prices of every publisher
+    publisher_permissions: HashMap<Pubkey, HashSet<Pubkey>>,
+
+    /// Interval at which to check the publish permissions of the updated prices
+    permission_check_interval: Interval,
+
+    /// Whether the publish permissions were checked since they were first read
+    publish_permissions_checked: bool,
+
+    /// Prices the publish keys lack permission for, as of the last check
+    missing_permissions: HashSet<(Pubkey, Pubkey)>,
 
     keypair_request_tx: Sender<KeypairRequest>,
 
-    logger: Logger,
+    /// Signs the updates not submitted on behalf of a specific publisher, in
+    /// place of the publish keypair, if a remote or KMS signer is configured
+    publish_signer: Option<Arc<dyn signer::Signer>>,
+
+    /// Used to resolve the symbols whose publishing is paused to their price
+    /// accounts
+    global_store_reader: global::SnapshotReader,
+
+    /// Publishing paused through the Admin API
+    publish_pause: PublishPause,
+
+    /// Statuses of the updates accepted by the API, reported as they are
+    /// packed and submitted
+    update_statuses: UpdateStatuses,
+
+    /// Slots of the network, watched for a stall of the cluster
+    slots: NetworkSlots,
+
+    /// Start of the latest slot of the network, which the publish interval is
+    /// aligned to if slot alignment is enabled
+    slot_boundary_rx: Option<watch::Receiver<Option<SlotBoundary>>>,
 }
 
-impl Exporter {
+impl<D: destination::Exporter> Exporter<D> {
     pub fn new(
-        config: Config,
-        rpc_url: &str,
-        rpc_timeout: Duration,
+        config_rx: watch::Receiver<Config>,
+        network_name: &str,
+        destination: Arc<D>,
         key_store: KeyStore,
+        key_store_config_rx: watch::Receiver<key_store::Config>,
         local_store_tx: Sender<store::local::Message>,
-        network_state_rx: watch::Receiver<NetworkState>,
-        inflight_transactions_tx: Sender<Signature>,
+        transactions_store_tx: Sender<transactions::Message>,
+        local_store_events_tx: broadcast::Sender<local::Event>,
+        inflight_transactions_tx: Sender<SentTransaction>,
+        inflight_transactions_channel: String,
+        retry_rx: mpsc::Receiver<SentBatch>,
+        channel_monitor: ChannelMonitor,
         publisher_permissions_rx: mpsc::Receiver<HashMap<Pubkey, HashSet<Pubkey>>>,
         keypair_request_tx: mpsc::Sender<KeypairRequest>,
-        logger: Logger,
+        publish_signer: Option<Arc<dyn signer::Signer>>,
+        global_store_reader: global::SnapshotReader,
+        publish_pause: PublishPause,
+        update_statuses: UpdateStatuses,
+        slots: NetworkSlots,
+        slot_boundary_rx: Option<watch::Receiver<Option<SlotBoundary>>>,
     ) -> Self {
+        let config = config_rx.borrow().clone();
         let publish_interval = time::interval(config.publish_interval_duration);
+        let permission_check_interval = time::interval(config.permission_check_interval_duration);
+        let local_store_events_rx = config
+            .micro_batching
+            .as_ref()
+            .map(|_| local_store_events_tx.subscribe());
         Exporter {
-            rpc_client: RpcClient::new_with_timeout(rpc_url.to_string(), rpc_timeout),
             config,
+            config_rx,
+            network_name: network_name.to_string(),
+            destination,
             publish_interval,
             key_store,
+            key_store_config_rx,
             local_store_tx,
+            transactions_store_tx,
+            local_store_events_tx,
+            local_store_events_rx,
+            pending_updates: PendingUpdates::default(),
             last_published_state: HashMap::new(),
-            network_state_rx,
+            sent_signatures: VecDeque::new(),
+            next_publish_keypair: None,
             inflight_transactions_tx,
+            inflight_transactions_channel,
+            retry_rx,
+            channel_monitor,
             publisher_permissions_rx,
-            our_prices: HashSet::new(),
+            publisher_permissions: HashMap::new(),
+            permission_check_interval,
+            publish_permissions_checked: false,
+            missing_permissions: HashSet::new(),
             keypair_request_tx,
-            logger,
+            publish_signer,
+            global_store_reader,
+            publish_pause,
+            update_statuses,
+            slots,
+            slot_boundary_rx,
         }
     }
 
-    pub async fn run(&mut self) {
+    /// Publish the updates until the shutdown flushes them one last time
+    pub async fn run(&mut self, mut shutdown: shutdown::Participant) {
         loop {
-            self.publish_interval.tick().await;
-            if let Err(err) = self.publish_updates().await {
-                error!(self.logger, "{:#}", err; "error" => format!("{:?}", err));
+            let flush_deadline = self
+                .config
+                .micro_batching
+                .as_ref()
+                .and_then(|config| self.pending_updates.deadline(config))
+                .map(time::Instant::from_std);
+            tokio::select! {
+                _ = self.publish_interval.tick() => {
+                    self.align_to_slot().await;
+                    self.pending_updates.clear();
+                    if let Err(err) = self.publish_updates().await {
+                        error!(error = ?err, kind = %error::record("exporter", &err), "{:#}", err);
+                    }
+                }
+                event = Self::next_local_store_event(&mut self.local_store_events_rx) => {
+                    let count = match event {
+                        Ok(local::Event::PriceUpdated { .. }) => 1,
+                        Err(broadcast::error::RecvError::Lagged(skipped)) => skipped as usize,
+                        Err(broadcast::error::RecvError::Closed) => {
+                            self.local_store_events_rx = None;
+                            continue;
+                        }
+                    };
+                    if let Some(config) = &self.config.micro_batching {
+                        self.pending_updates.record(count);
+                        if self.pending_updates.count >= config.max_pending_updates {
+                            self.flush_pending_updates("max_pending_updates").await;
+                        }
+                    }
+                }
+                _ = time::sleep_until(flush_deadline.unwrap_or_else(time::Instant::now)), if flush_deadline.is_some() => {
+                    self.flush_pending_updates("max_delay").await;
+                }
+                _ = self.permission_check_interval.tick() => {
+                    if let Err(err) = self.check_publish_permissions().await {
+                        error!(error = ?err, kind = %error::record("exporter", &err), "{:#}", err);
+                    }
+                }
+                Some(batch) = self.retry_rx.recv() => {
+                    if let Err(err) = self.retry_batch(batch).await {
+                        error!(error = ?err, kind = %error::record("exporter", &err), "Exporter: failed to retry batch: {:#}", err);
+                    }
+                }
+                Ok(()) = self.config_rx.changed() => self.reload_config(),
+                Ok(()) = self.key_store_config_rx.changed() => self.reload_publish_keypair(),
+                _ = shutdown.wait() => {
+                    info!("flushing pending updates before shutdown");
+                    if let Err(err) = self.publish_updates().await {
+                        error!(error = ?err, kind = %error::record("exporter", &err), "{:#}", err);
+                    }
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Wait until the offset into the next slot, if slot alignment is enabled
+    /// and the slots are being notified
+    async fn align_to_slot(&self) {
+        let (config, slot_boundary_rx) = match (&self.config.slot_alignment, &self.slot_boundary_rx)
+        {
+            (Some(config), Some(slot_boundary_rx)) => (config, slot_boundary_rx),
+            _ => return,
+        };
+        let slot_boundary = *slot_boundary_rx.borrow();
+        let publish_time = slot_boundary
+            .and_then(|slot_boundary| slot_boundary.next_publish_time(config, Instant::now()));
+        match publish_time {
+            Some(publish_time) => time::sleep_until(time::Instant::from_std(publish_time)).await,
+            None => debug!("Exporter: the slots are not notified, publishing unaligned"),
+        }
+    }
+
+    /// Receive the next update accepted by the Local Store, or never while
+    /// micro-batching is disabled
+    async fn next_local_store_event(
+        local_store_events_rx: &mut Option<broadcast::Receiver<local::Event>>,
+    ) -> Result<local::Event, broadcast::error::RecvError> {
+        match local_store_events_rx {
+            Some(local_store_events_rx) => local_store_events_rx.recv().await,
+            None => future::pending().await,
+        }
+    }
+
+    /// Publish the pending updates ahead of the next tick of the publish
+    /// interval, which then restarts from now
+    async fn flush_pending_updates(&mut self, reason: &'static str) {
+        debug!(
+            pending_updates = self.pending_updates.count,
+            reason, "Exporter: flushing micro-batch"
+        );
+        self.pending_updates.clear();
+        if let Err(err) = self.publish_updates().await {
+            error!(error = ?err, kind = %error::record("exporter", &err), "{:#}", err);
+        }
+        self.publish_interval.reset();
+    }
+
+    /// Apply the reloaded config, restarting the publish interval if its duration changed
+    fn reload_config(&mut self) {
+        let config = self.config_rx.borrow().clone();
+        if config.publish_interval_duration != self.config.publish_interval_duration {
+            self.publish_interval = time::interval(config.publish_interval_duration);
+        }
+        if config.permission_check_interval_duration
+            != self.config.permission_check_interval_duration
+        {
+            self.permission_check_interval =
+                time::interval(config.permission_check_interval_duration);
+        }
+        if config.micro_batching.is_some() != self.config.micro_batching.is_some() {
+            self.local_store_events_rx = config
+                .micro_batching
+                .as_ref()
+                .map(|_| self.local_store_events_tx.subscribe());
+            self.pending_updates.clear();
+        }
+        info!(?config, "Exporter: config reloaded");
+        self.config = config;
+    }
+
+    /// Read the publish keypair again, switching over to it if it is a new one.
+    /// The switch waits for the submissions signed with the old key to land or
+    /// expire, holding back the publishing meanwhile, so that the updates of the
+    /// two keys never race each other.
+    fn reload_publish_keypair(&mut self) {
+        let config = self.key_store_config_rx.borrow().clone();
+        let new_keypair = match KeyStore::read_publish_keypair(&config) {
+            Ok(keypair) => keypair,
+            Err(err) => {
+                // The keypair may have been loaded remotely
+                warn!(
+                    "Exporter: could not reload publish keypair, keeping the current one: {:#}",
+                    err
+                );
+                return;
+            }
+        };
+
+        let old_key = self.key_store.publish_keypair.as_ref().map(Keypair::pubkey);
+        if old_key == Some(new_keypair.pubkey()) {
+            self.next_publish_keypair = None;
+            return;
+        }
+        info!(
+            old_publish_key = %old_key.map_or_else(|| "none".to_string(), |key| key.to_string()),
+            new_publish_key = %new_keypair.pubkey(),
+            "Exporter: rotating the publish keypair once the transactions signed with the old one landed"
+        );
+        self.next_publish_keypair = Some(new_keypair);
+    }
+
+    /// Switch over to the next publish keypair, if any, once none of the
+    /// submissions signed with the current one can still land. Returns whether
+    /// the switch is still waiting for them.
+    async fn rotate_publish_keypair(&mut self) -> Result<bool> {
+        if self.next_publish_keypair.is_none() {
+            return Ok(false);
+        }
+        self.forget_expired_signatures();
+
+        let old_key = self.key_store.publish_keypair.as_ref().map(Keypair::pubkey);
+        let inflight = self
+            .sent_signatures
+            .iter()
+            .filter(|(key, ..)| Some(*key) == old_key)
+            .map(|(_, signature, _)| *signature)
+            .collect::<Vec<_>>();
+        if !inflight.is_empty() {
+            let statuses = self.destination.confirm(&inflight).await?;
+            let settled = inflight
+                .iter()
+                .zip(statuses)
+                .filter(|(_, status)| !matches!(status, None | Some(TransactionStatus::Pending)))
+                .map(|(signature, _)| *signature)
+                .collect::<HashSet<_>>();
+            self.sent_signatures
+                .retain(|(_, signature, _)| !settled.contains(signature));
+            if settled.len() < inflight.len() {
+                debug!(
+                    inflight = inflight.len() - settled.len(),
+                    "Exporter: waiting for the transactions of the old publish keypair"
+                );
+                return Ok(true);
+            }
+        }
+
+        let new_keypair = self
+            .next_publish_keypair
+            .take()
+            .expect("next publish keypair is set");
+        info!(
+            audit = true,
+            old_publish_key = %old_key.map_or_else(|| "none".to_string(), |key| key.to_string()),
+            new_publish_key = %new_keypair.pubkey(),
+            "Exporter: publish keypair rotated"
+        );
+        self.key_store.publish_keypair = Some(new_keypair);
+        Ok(false)
+    }
+
+    /// Remember the signatures of the sent submissions
+    fn record_sent_signatures(&mut self, sent: impl IntoIterator<Item = (Pubkey, Signature)>) {
+        let now = Instant::now();
+        self.sent_signatures.extend(
+            sent.into_iter()
+                .map(|(key, signature)| (key, signature, now)),
+        );
+        self.forget_expired_signatures();
+    }
+
+    /// Forget the submissions whose blockhash expired, which can no longer land
+    fn forget_expired_signatures(&mut self) {
+        while let Some((_, _, sent_at)) = self.sent_signatures.front() {
+            if sent_at.elapsed() < SUBMISSION_EXPIRY {
+                break;
             }
+            self.sent_signatures.pop_front();
         }
     }
 
@@ -279,9 +1207,10 @@ impl Exporter {
     ///
     /// The strategy used to do this is as follows:
     /// - Fetch all the price updates currently present in the local store
-    /// - Filter out price updates we have previously attempted to publish, or which are
-    ///   too old to publish.
-    /// - Collect the price updates into batches.
+    /// - Resolve the signer of each publisher the updates were submitted for
+    /// - Filter out price updates we have previously attempted to publish, which are
+    ///   too old to publish, or which the publisher is not permissioned to update.
+    /// - Collect the price updates of each publisher into batches.
     /// - Publish all the batches, staggering them evenly over the interval at which this method is called.
     ///
     /// This design is intended to:
@@ -291,140 +1220,461 @@ impl Exporter {
     ///   time to respond, no internal queues grow unboundedly. At any single point in time there are at most
     ///   (n / batch_size) requests in flight.
     async fn publish_updates(&mut self) -> Result<()> {
+        let pause_state = self.publish_pause.get();
+        if pause_state.all_paused() {
+            debug!("Exporter: Publishing is paused, skipping updates");
+            return Ok(());
+        }
+        if self.slot_stalled() {
+            debug!("Exporter: The network slot stalled, skipping updates");
+            return Ok(());
+        }
+        if self.rotate_publish_keypair().await? {
+            debug!("Exporter: The publish keypair is rotating, skipping updates");
+            return Ok(());
+        }
+        let paused_prices = self.paused_prices(&pause_state);
+
         let local_store_contents = self.fetch_local_store_contents().await?;
 
         let now = Utc::now().timestamp();
 
-        // Filter the contents to only include information we haven't already sent,
-        // and to ignore stale information.
-        let fresh_updates = local_store_contents
-            .iter()
-            .filter(|(identifier, info)| {
-                // Filter out timestamps older than what we already published
-                if let Some(last_info) = self.last_published_state.get(identifier) {
-                    last_info.timestamp < info.timestamp
-                } else {
-                    true // No prior data found, letting the price through
-                }
-            })
+        self.update_publisher_permissions();
+
+        // Check the permissions as soon as the Oracle first read them
+        if !self.publish_permissions_checked {
+            self.check_publish_permissions().await?;
+        }
+
+        // Resolve the signer of the updates of each publisher,
+        // keeping only the updates which should be published.
+        let mut publisher_updates = vec![];
+        for (publisher, price_infos) in local_store_contents {
+            let publish_signer = match self.resolve_signer(&publisher).await? {
+                Some(publish_signer) => publish_signer,
+                None => continue,
+            };
+
+            let updates = self.filter_updates(
+                &publisher,
+                &publish_signer.pubkey(),
+                price_infos,
+                &paused_prices,
+                now,
+            );
+            if !updates.is_empty() {
+                publisher_updates.push((publisher, publish_signer, updates));
+            }
+        }
+
+        // Split the updates up into batches, each signed by a single publisher
+        let max_batch_size = self.config.max_batch_size;
+        let batches = publisher_updates
+            .iter()
+            .flat_map(|(publisher, publish_signer, updates)| {
+                updates
+                    .chunks(max_batch_size)
+                    .map(move |batch| (publisher, publish_signer, batch))
+            })
+            .collect::<Vec<_>>();
+
+        if batches.is_empty() {
+            return Ok(());
+        }
+
+        // Publish all the batches, staggering the requests over the publish interval
+        let num_batches = batches.len();
+        let mut batch_send_interval = time::interval(
+            self.config
+                .publish_interval_duration
+                .div_f64(num_batches as f64),
+        );
+        let mut batch_futures = vec![];
+        let mut batch_keys = vec![];
+        for (publisher, publish_signer, batch) in batches {
+            batch_futures.push(self.publish_batch(*publisher, batch, publish_signer.as_ref(), 1));
+            batch_keys.push((*publisher, publish_signer.pubkey()));
+
+            batch_send_interval.tick().await;
+        }
+
+        // Wait for all the update requests to complete. Note that this doesn't wait for the
+        // transactions themselves to be processed or confirmed, just the RPC requests to return.
+        let results = join_all(batch_futures).await;
+        self.record_sent_signatures(
+            batch_keys
+                .iter()
+                .zip(&results)
+                .filter_map(|((_, key), result)| Some((*key, result.as_ref().ok()?.signature?))),
+        );
+        let published_batches = results.into_iter().collect::<Result<Vec<_>>>()?;
+
+        // Record the updates the batches were actually prepared with
+        for ((publisher, _), published) in batch_keys.into_iter().zip(published_batches) {
+            self.last_published_state.extend(
+                published
+                    .prices
+                    .into_iter()
+                    .map(|(identifier, info)| ((publisher, identifier), info)),
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Whether publishing is paused as the network slot has not advanced for
+    /// the stall threshold, logging when it pauses and resumes
+    fn slot_stalled(&self) -> bool {
+        let stalled_for = self.slots.stalled_for();
+        let stalled = match (&self.config.slot_stall, stalled_for) {
+            (Some(slot_stall), Some(stalled_for)) => stalled_for >= slot_stall.threshold,
+            _ => false,
+        };
+        match (self.slots.set_stalled(stalled), stalled) {
+            (false, true) => warn!(
+                stalled_for = ?stalled_for.unwrap_or_default(),
+                "Exporter: the network slot stopped advancing, pausing publishing"
+            ),
+            (true, false) => info!("Exporter: the network slot is advancing, resuming publishing"),
+            _ => {}
+        }
+        stalled
+    }
+
+    /// Publish again the prices of a batch whose transaction did not land in
+    /// time, with their latest updates and a higher compute unit price. The
+    /// prices published again since the batch was sent are left out, as they
+    /// are in a newer transaction which may still land.
+    async fn retry_batch(&mut self, unlanded: SentBatch) -> Result<()> {
+        let pause_state = self.publish_pause.get();
+        if pause_state.all_paused() || self.slot_stalled() || self.rotate_publish_keypair().await? {
+            return Ok(());
+        }
+        let paused_prices = self.paused_prices(&pause_state);
+
+        let publisher_contents = self
+            .fetch_local_store_contents()
+            .await?
+            .remove(&unlanded.publisher)
+            .unwrap_or_default();
+        let now = Utc::now().timestamp();
+        let snapshot = self.global_store_reader.load();
+        let symbol_index = &snapshot.account_metadata.symbol_index;
+        let batch = unlanded
+            .prices
+            .iter()
+            .filter(|(identifier, sent_timestamp)| {
+                self.last_published_state
+                    .get(&(unlanded.publisher, *identifier))
+                    .map_or(false, |info| info.timestamp == *sent_timestamp)
+                    && !paused_prices.contains(&Pubkey::new(&identifier.to_bytes()))
+            })
+            .filter_map(|(identifier, _)| {
+                let info = publisher_contents.get(identifier)?;
+                let fresh =
+                    (now - info.timestamp) <= self.config.staleness_threshold.as_secs() as i64;
+                fresh.then(|| (*identifier, info.clone()))
+            })
+            .filter_map(|(identifier, info)| {
+                let info = self.apply_conf_floor(symbol_index, &identifier, info)?;
+                Some((identifier, info))
+            })
+            .collect::<Vec<_>>();
+        if batch.is_empty() {
+            return Ok(());
+        }
+
+        let publish_signer = match self.resolve_signer(&unlanded.publisher).await? {
+            Some(publish_signer) => publish_signer,
+            None => return Ok(()),
+        };
+        let attempt = unlanded.attempt + 1;
+        info!(
+            attempt,
+            prices = batch.len(),
+            "Exporter: Publishing again a batch which did not land"
+        );
+        PUBLISH_RETRY_METRICS.retried(&self.network_name);
+        let published = self
+            .publish_batch(unlanded.publisher, &batch, publish_signer.as_ref(), attempt)
+            .await?;
+        self.record_sent_signatures(
+            published
+                .signature
+                .map(|signature| (publish_signer.pubkey(), signature)),
+        );
+
+        self.last_published_state.extend(
+            published
+                .prices
+                .into_iter()
+                .map(|(identifier, info)| ((unlanded.publisher, identifier), info)),
+        );
+        Ok(())
+    }
+
+    /// Filter the price updates of a single publisher to only include
+    /// information we haven't already sent, to ignore stale
+    /// information, and to drop prices the publish key is not
+    /// permissioned to update or whose publishing is paused.
+    fn filter_updates(
+        &self,
+        publisher: &Publisher,
+        publish_pubkey: &Pubkey,
+        price_infos: HashMap<PriceIdentifier, PriceInfo>,
+        paused_prices: &HashSet<Pubkey>,
+        now: i64,
+    ) -> Vec<(PriceIdentifier, PriceInfo)> {
+        let permissioned_prices = self.publisher_permissions.get(publish_pubkey);
+        if permissioned_prices.is_none() {
+            debug!(
+                %publish_pubkey,
+                "Exporter: No permissioned prices are known for the publishing keypair"
+            );
+        }
+
+        let snapshot = self.global_store_reader.load();
+        let symbol_index = &snapshot.account_metadata.symbol_index;
+
+        price_infos
+            .into_iter()
+            .filter(|(identifier, info)| {
+                // Filter out timestamps older than what we already published
+                if let Some(last_info) = self.last_published_state.get(&(*publisher, *identifier)) {
+                    last_info.timestamp < info.timestamp
+                } else {
+                    true // No prior data found, letting the price through
+                }
+            })
             .filter(|(_identifier, info)| {
                 // Filter out timestamps that are old
                 (now - info.timestamp) < self.config.staleness_threshold.as_secs() as i64
             })
+            // Applied before comparing with the last published state, which
+            // holds the clamped confidence intervals
+            .filter_map(|(identifier, info)| {
+                let info = self.apply_conf_floor(symbol_index, &identifier, info)?;
+                Some((identifier, info))
+            })
             .filter(|(identifier, info)| {
                 // Filter out unchanged price data if the max delay wasn't reached
 
-                if let Some(last_info) = self.last_published_state.get(identifier) {
+                if let Some(last_info) = self.last_published_state.get(&(*publisher, *identifier)) {
                     if (info.timestamp - last_info.timestamp)
                         > self.config.unchanged_publish_threshold.as_secs() as i64
                     {
                         true // max delay since last published state reached, we publish anyway
                     } else {
-                        !last_info.cmp_no_timestamp(*info) // Filter out if data is unchanged
+                        !last_info.cmp_no_timestamp(info) // Filter out if data is unchanged
                     }
                 } else {
                     true // No prior data found, letting the price through
                 }
             })
-            .collect::<Vec<_>>();
-
-        let publish_keypair = if let Some(kp) = self.key_store.publish_keypair.as_ref() {
-            // It's impossible to sanely return a &Keypair in the
-            // other if branch, so we clone the reference.
-            Keypair::from_bytes(&kp.to_bytes())
-                .context("INTERNAL: Could not convert keypair to bytes and back")?
-        } else {
-            // Request the keypair from remote keypair loader.  Doing
-            // this here guarantees that the up to date loaded keypair
-            // is being used.
-            //
-            // Currently, we're guaranteed not to clog memory or block
-            // the keypair loader under the following assumptions:
-            // - The Exporter publishing loop waits for a publish
-            //   attempt to finish before beginning the next
-            //   one. Currently realized in run()
-            // - The Remote Key Loader does not read channels for
-            //   keypairs it does not have. Currently expressed in
-            //   handle_key_requests() in remote_keypair_loader.rs
-
-            debug!(
-                self.logger,
-                "Exporter: Publish keypair is None, requesting remote loaded key"
-            );
-            let kp = RemoteKeypairLoader::request_keypair(&self.keypair_request_tx).await?;
-            debug!(self.logger, "Exporter: Keypair received");
-            kp
-        };
-
-        self.update_our_prices(&publish_keypair.pubkey());
-
-        debug!(self.logger, "Exporter: filtering prices permissioned to us";
-               "our_prices" => format!("{:?}", self.our_prices),
-               "publish_pubkey" => publish_keypair.pubkey().to_string(),
-        );
-
-        // Filter out price accounts we're not permissioned to update
-        let permissioned_updates = fresh_updates
-            .into_iter()
-            .filter(|(id, _data)| {
-                let key_from_id = Pubkey::new(id.clone().to_bytes().as_slice());
-                if self.our_prices.contains(&key_from_id) {
+            .filter(|(identifier, _info)| {
+                // Filter out price accounts we're not permissioned to update
+                let key_from_id = Pubkey::new(identifier.to_bytes().as_slice());
+                if permissioned_prices.map_or(false, |prices| prices.contains(&key_from_id)) {
                     true
                 } else {
                     // Note: This message is not an error. Some
                     // publishers have different permissions on
                     // primary/secondary networks
                     debug!(
-                    self.logger,
-                    "Exporter: Attempted to publish a price without permission, skipping";
-                    "unpermissioned_price_account" => key_from_id.to_string(),
-                    "permissioned_accounts" => format!("{:?}", self.our_prices)
-                            );
+                        unpermissioned_price_account = %key_from_id,
+                        %publish_pubkey,
+                        "Exporter: Attempted to publish a price without permission, skipping"
+                    );
                     false
                 }
             })
-            .collect::<Vec<_>>();
+            .filter(|(identifier, _info)| {
+                // Filter out prices whose publishing is paused
+                !paused_prices.contains(&Pubkey::new(identifier.to_bytes().as_slice()))
+            })
+            .collect()
+    }
 
-        if permissioned_updates.is_empty() {
+    /// Raise the confidence interval of a trading price to the floor of its
+    /// symbol, or drop the update, as configured. Updates are left as they
+    /// are without floors.
+    fn apply_conf_floor(
+        &self,
+        symbol_index: &SymbolIndex,
+        identifier: &PriceIdentifier,
+        mut info: PriceInfo,
+    ) -> Option<PriceInfo> {
+        let conf_floor = match &self.config.conf_floor {
+            Some(conf_floor) if info.status == PriceStatus::Trading => conf_floor,
+            _ => return Some(info),
+        };
+        let symbol = symbol_index.symbol_of_identifier(identifier);
+        let min_conf = conf_floor.floor(symbol).min_conf(info.price);
+        if info.conf >= min_conf {
+            return Some(info);
+        }
+
+        let symbol = symbol
+            .map(str::to_string)
+            .unwrap_or_else(|| Pubkey::new_from_array(identifier.to_bytes()).to_string());
+        match conf_floor.action {
+            ConfFloorAction::Clamp => {
+                debug!(%symbol, conf = info.conf, min_conf, "Exporter: Raising the confidence interval to its floor");
+                CONF_FLOOR_METRICS.record(&self.network_name, &symbol, "clamped");
+                info.conf = min_conf;
+                Some(info)
+            }
+            ConfFloorAction::Reject => {
+                debug!(%symbol, conf = info.conf, min_conf, "Exporter: Confidence interval below its floor, skipping the update");
+                CONF_FLOOR_METRICS.record(&self.network_name, &symbol, "rejected");
+                None
+            }
+        }
+    }
+
+    /// Check the permissions of the publish keys to publish the prices updated
+    /// in the local store, reporting the prices they lack permission for, which
+    /// are skipped when publishing, and those they were granted permission for.
+    async fn check_publish_permissions(&mut self) -> Result<()> {
+        self.update_publisher_permissions();
+        // The Oracle has not read the permissions from the network yet
+        if self.publisher_permissions.is_empty() {
             return Ok(());
         }
+        self.publish_permissions_checked = true;
+
+        let mut updated_prices: HashMap<Pubkey, HashSet<Pubkey>> = HashMap::new();
+        for (publisher, price_infos) in self.fetch_local_store_contents().await? {
+            if let Some(publish_pubkey) = self.publish_pubkey(&publisher) {
+                updated_prices.entry(publish_pubkey).or_default().extend(
+                    price_infos
+                        .keys()
+                        .map(|identifier| Pubkey::new_from_array(identifier.to_bytes())),
+                );
+            }
+        }
+        let missing_permissions = missing_permissions(&updated_prices, &self.publisher_permissions);
 
-        // Split the updates up into batches
-        let batches = permissioned_updates.chunks(self.config.max_batch_size);
+        let snapshot = self.global_store_reader.load();
+        let symbol_index = &snapshot.account_metadata.symbol_index;
+        for (publish_pubkey, price_key) in missing_permissions.difference(&self.missing_permissions)
+        {
+            let symbol = symbol_index.price_name(price_key);
+            warn!(
+                %publish_pubkey,
+                price_account = %price_key,
+                %symbol,
+                "Exporter: Publish key is not permissioned to publish the price, skipping its updates"
+            );
+            PUBLISH_PERMISSION_METRICS.record(&self.network_name, publish_pubkey, &symbol, true);
+        }
+        for (publish_pubkey, price_key) in self.missing_permissions.difference(&missing_permissions)
+        {
+            let symbol = symbol_index.price_name(price_key);
+            info!(
+                %publish_pubkey,
+                price_account = %price_key,
+                %symbol,
+                "Exporter: Publish key no longer lacks permission to publish the price"
+            );
+            PUBLISH_PERMISSION_METRICS.record(&self.network_name, publish_pubkey, &symbol, false);
+        }
+        self.missing_permissions = missing_permissions;
 
-        // Publish all the batches, staggering the requests over the publish interval
-        let num_batches = batches.len();
-        let mut batch_send_interval = time::interval(
-            self.config
-                .publish_interval_duration
-                .div_f64(num_batches as f64),
-        );
-        let mut batch_state = HashMap::new();
-        let mut batch_futures = vec![];
-        for batch in batches {
-            batch_futures.push(self.publish_batch(batch, &publish_keypair));
+        Ok(())
+    }
 
-            for (identifier, info) in batch {
-                batch_state.insert(**identifier, (**info).clone());
-            }
+    /// The key the updates of the publisher are published with, if known
+    /// without waiting for a remote keypair
+    fn publish_pubkey(&self, publisher: &Publisher) -> Option<Pubkey> {
+        match publisher {
+            None => self
+                .publish_signer
+                .as_ref()
+                .map(|publish_signer| publish_signer.pubkey())
+                .or_else(|| self.key_store.publish_keypair.as_ref().map(Keypair::pubkey)),
+            Some(publisher_key) => self
+                .key_store
+                .additional_publish_keypairs
+                .get(publisher_key)
+                .map(Keypair::pubkey),
+        }
+    }
 
-            batch_send_interval.tick().await;
+    /// Price accounts of the symbols whose publishing is paused
+    fn paused_prices(&self, pause_state: &PauseState) -> HashSet<Pubkey> {
+        if pause_state.symbols.is_empty() {
+            return HashSet::new();
         }
+        let snapshot = self.global_store_reader.load();
+        pause_state
+            .symbols
+            .iter()
+            .flat_map(|symbol| {
+                snapshot
+                    .account_metadata
+                    .symbol_index
+                    .price_accounts(symbol)
+            })
+            .copied()
+            .collect()
+    }
 
-        // Wait for all the update requests to complete. Note that this doesn't wait for the
-        // transactions themselves to be processed or confirmed, just the RPC requests to return.
-        join_all(batch_futures)
-            .await
-            .into_iter()
-            .collect::<Result<Vec<_>>>()?;
+    /// Get the signer of the publisher's updates, or None if no keypair is
+    /// configured for it
+    async fn resolve_signer(
+        &self,
+        publisher: &Publisher,
+    ) -> Result<Option<Arc<dyn signer::Signer>>> {
+        match publisher {
+            None => Ok(Some(self.get_default_signer().await?)),
+            Some(publisher_key) => match self
+                .key_store
+                .additional_publish_keypairs
+                .get(publisher_key)
+            {
+                Some(kp) => Ok(Some(Arc::new(KeypairSigner::new(kp)?))),
+                None => {
+                    warn!(
+                        publisher = %publisher_key,
+                        "Exporter: No keypair configured for publisher, skipping its updates"
+                    );
+                    Ok(None)
+                }
+            },
+        }
+    }
 
-        self.last_published_state.extend(batch_state);
+    /// Get the signer used to publish updates which were not
+    /// submitted on behalf of a specific publisher.
+    async fn get_default_signer(&self) -> Result<Arc<dyn signer::Signer>> {
+        if let Some(publish_signer) = self.publish_signer.as_ref() {
+            Ok(publish_signer.clone())
+        } else if let Some(kp) = self.key_store.publish_keypair.as_ref() {
+            Ok(Arc::new(KeypairSigner::new(kp)?))
+        } else {
+            // Request the keypair from remote keypair loader.  Doing
+            // this here guarantees that the up to date loaded keypair
+            // is being used.
+            //
+            // Currently, we're guaranteed not to clog memory or block
+            // the keypair loader under the following assumptions:
+            // - The Exporter publishing loop waits for a publish
+            //   attempt to finish before beginning the next
+            //   one. Currently realized in run()
+            // - The Remote Key Loader does not read channels for
+            //   keypairs it does not have. Currently expressed in
+            //   handle_key_requests() in remote_keypair_loader.rs
 
-        Ok(())
+            debug!("Exporter: Publish keypair is None, requesting remote loaded key");
+            let kp = RemoteKeypairLoader::request_keypair(&self.keypair_request_tx).await?;
+            debug!("Exporter: Keypair received");
+            Ok(Arc::new(KeypairSigner::new(&kp)?))
+        }
     }
 
-    /// Update permissioned prices of this publisher from oracle using
-    /// the publisher permissions channel.
+    /// Update the publisher permissions from oracle using the
+    /// publisher permissions channel.
     ///
     /// The loop ensures that we clear the channel and use
     /// only the final, latest message; try_recv() is
@@ -435,41 +1685,30 @@ impl Exporter {
     /// because its internal RwLock would complain about not being
     /// Send with the HashMap<HashSet<Pubkey>> inside.
     /// TODO(2023-05-05): Debug the watch::channel() compilation errors
-    fn update_our_prices(&mut self, publish_pubkey: &Pubkey) {
+    fn update_publisher_permissions(&mut self) {
         loop {
             match self.publisher_permissions_rx.try_recv() {
                 Ok(publisher_permissions) => {
-                    self.our_prices = publisher_permissions.get(publish_pubkey) .cloned()
-            .unwrap_or_else( || {
-		warn!(
-		    self.logger,
-		    "Exporter: No permissioned prices were found for the publishing keypair on-chain. This is expected only on startup.";
-		    "publish_pubkey" => publish_pubkey.to_string(),
-		);
-		HashSet::new()
-	    });
+                    self.publisher_permissions = publisher_permissions;
                     trace!(
-                        self.logger,
-                        "Exporter: read permissioned price accounts from channel";
-                        "new_value" => format!("{:?}", self.our_prices),
+                        new_value = ?self.publisher_permissions,
+                        "Exporter: read publisher permissions from channel"
                     );
                 }
                 // Expected failures when channel is empty
                 Err(TryRecvError::Empty) => {
                     trace!(
-                        self.logger,
-                        "Exporter: No more permissioned price accounts in channel, using cached value";
-                        "cached_value" => format!("{:?}", self.our_prices),
+                        cached_value = ?self.publisher_permissions,
+                        "Exporter: No more publisher permissions in channel, using cached value"
                     );
                     break;
                 }
                 // Unexpected failures (channel closed, internal errors etc.)
                 Err(other) => {
                     warn!(
-                        self.logger,
-                        "Exporter: Updating permissioned price accounts failed unexpectedly, using cached value";
-                        "cached_value" => format!("{:?}", self.our_prices),
-                        "error" => other.to_string(),
+                        cached_value = ?self.publisher_permissions,
+                        error = %other,
+                        "Exporter: Updating publisher permissions failed unexpectedly, using cached value"
                     );
                     break;
                 }
@@ -477,105 +1716,364 @@ impl Exporter {
         }
     }
 
-    async fn fetch_local_store_contents(&self) -> Result<HashMap<PriceIdentifier, PriceInfo>> {
+    async fn fetch_local_store_contents(&self) -> Result<AllPriceInfo> {
         let (result_tx, result_rx) = oneshot::channel();
         self.local_store_tx
             .send(store::local::Message::LookupAllPriceInfo { result_tx })
             .await
-            .map_err(|_| anyhow!("failed to send lookup price info message to local store"))?;
+            .map_err(|_| Error::ChannelClosed("local store"))
+            .context("failed to send lookup price info message to local store")?;
         result_rx
             .await
-            .map_err(|_| anyhow!("failed to fetch from local store"))
+            .map_err(|_| Error::ChannelClosed("local store"))
+            .context("failed to fetch from local store")
+    }
+
+    async fn fetch_local_store_trace_contexts(&self) -> Result<AllTraceContexts> {
+        let (result_tx, result_rx) = oneshot::channel();
+        self.local_store_tx
+            .send(store::local::Message::LookupAllTraceContexts { result_tx })
+            .await
+            .map_err(|_| Error::ChannelClosed("local store"))
+            .context("failed to send lookup trace contexts message to local store")?;
+        result_rx
+            .await
+            .map_err(|_| Error::ChannelClosed("local store"))
+            .context("failed to fetch trace contexts from local store")
     }
 
     async fn publish_batch(
         &self,
-        batch: &[(&Identifier, &PriceInfo)],
-        publish_keypair: &Keypair,
-    ) -> Result<()> {
-        let mut instructions = Vec::new();
-
-        // Refresh the data in the batch
-        let local_store_contents = self.fetch_local_store_contents().await?;
-        let refreshed_batch = batch.iter().map(|(identifier, _)| {
-            (
-                identifier,
-                local_store_contents
-                    .get(identifier)
-                    .ok_or_else(|| anyhow!("price identifier not found in local store"))
-                    .with_context(|| identifier.to_string()),
-            )
-        });
-        let price_accounts = refreshed_batch
-            .clone()
-            .map(|(identifier, _)| bs58::encode(identifier.to_bytes()).into_string())
-            .collect::<Vec<_>>();
+        publisher: Publisher,
+        batch: &[(PriceIdentifier, PriceInfo)],
+        publish_signer: &dyn signer::Signer,
+        attempt: u32,
+    ) -> Result<PublishedBatch> {
+        // The batch combines the traces of the updates it publishes
+        let trace_contexts = self.fetch_local_store_trace_contexts().await?;
+        let trace_context = telemetry::start_linked_span(
+            "exporter.publish_batch",
+            vec![
+                KeyValue::new("network", self.network_name.clone()),
+                KeyValue::new("publish_key", publish_signer.pubkey().to_string()),
+                KeyValue::new("prices", batch.len() as i64),
+                KeyValue::new("attempt", attempt as i64),
+            ],
+            batch
+                .iter()
+                .filter_map(|(identifier, _)| trace_contexts.get(&(publisher, *identifier))),
+        );
 
-        let network_state = *self.network_state_rx.borrow();
-        for (identifier, price_info_result) in refreshed_batch {
-            let price_info = price_info_result?;
+        let result = self
+            .send_batch(publisher, batch, publish_signer, attempt, &trace_context)
+            .await;
+        telemetry::end_span(&trace_context, &result);
+        result
+    }
 
+    /// Prepare the batch, refreshed with the latest updates in the Local Store,
+    /// and submit it, handing it to the transaction monitor. Returns the
+    /// signature of the submission, if it was actually sent, along with the
+    /// updates it was prepared with.
+    async fn send_batch(
+        &self,
+        publisher: Publisher,
+        batch: &[(PriceIdentifier, PriceInfo)],
+        publish_signer: &dyn signer::Signer,
+        attempt: u32,
+        trace_context: &Context,
+    ) -> Result<PublishedBatch> {
+        // Refresh the data in the batch, leaving the stale prices out, and
+        // applying the confidence interval floors to the refreshed updates
+        let local_store_contents = self.fetch_local_store_contents().await?;
+        let publisher_contents = local_store_contents.get(&publisher);
+        let snapshot = self.global_store_reader.load();
+        let symbol_index = &snapshot.account_metadata.symbol_index;
+        let mut refreshed_batch = vec![];
+        for (identifier, _) in batch {
+            let price_info = publisher_contents
+                .and_then(|contents| contents.get(identifier))
+                .ok_or_else(|| anyhow!("price identifier not found in local store"))
+                .with_context(|| identifier.to_string())?;
             let stale_price = (Utc::now().timestamp() - price_info.timestamp)
                 > self.config.staleness_threshold.as_secs() as i64;
             if stale_price {
                 continue;
             }
-
-            let instruction = if let Some(accumulator_program_key) = self.key_store.accumulator_key
+            if let Some(price_info) =
+                self.apply_conf_floor(symbol_index, identifier, price_info.clone())
             {
-                self.create_instruction_with_accumulator(
-                    publish_keypair.pubkey(),
-                    Pubkey::new(&identifier.to_bytes()),
-                    &price_info,
-                    network_state.current_slot,
-                    accumulator_program_key,
-                )?
-            } else {
-                self.create_instruction_without_accumulator(
-                    publish_keypair.pubkey(),
-                    Pubkey::new(&identifier.to_bytes()),
-                    &price_info,
-                    network_state.current_slot,
-                )?
-            };
+                refreshed_batch.push((*identifier, price_info));
+            }
+        }
 
-            instructions.push(instruction);
+        let prepared = self
+            .destination
+            .prepare(&destination::Batch {
+                publisher,
+                prices: &refreshed_batch,
+                signer: publish_signer,
+                attempt,
+            })
+            .await?;
+        let seqs = self
+            .update_statuses
+            .packed(&self.network_name, publisher, &prepared.prices);
+
+        if !self.destination.submit(&prepared).await? {
+            return Ok(PublishedBatch {
+                signature: None,
+                prices:    refreshed_batch,
+            });
         }
+        let signature = prepared.signature;
+
+        self.update_statuses
+            .submitted(&self.network_name, &seqs, &signature);
+        trace_context
+            .span()
+            .set_attribute(KeyValue::new("signature", signature.to_string()));
+        self.channel_monitor
+            .send(
+                &self.inflight_transactions_channel,
+                &self.inflight_transactions_tx,
+                SentTransaction {
+                    signature,
+                    trace_context: trace_context.clone(),
+                    batch: SentBatch {
+                        publisher,
+                        // The prices actually sent, with the timestamps of
+                        // their refreshed updates
+                        prices: prepared.prices.clone(),
+                        attempt,
+                        slot: prepared.slot,
+                        seqs,
+                    },
+                },
+            )
+            .await
+            .map_err(|_| Error::ChannelClosed("transaction monitor"))
+            .context("failed to send transaction to transaction monitor")?;
+
+        // Record the transaction for the dashboard
+        self.channel_monitor
+            .send(
+                "transactions_store",
+                &self.transactions_store_tx,
+                transactions::Message::Sent(TransactionRecord {
+                    network: self.network_name.clone(),
+                    signature,
+                    publish_key: publish_signer.pubkey(),
+                    price_accounts: prepared
+                        .prices
+                        .iter()
+                        .map(|(identifier, _)| Pubkey::new(&identifier.to_bytes()))
+                        .collect(),
+                    submit_time: Utc::now().timestamp(),
+                    priority_fee_lamports: prepared.priority_fee_lamports,
+                    status: TransactionStatus::Pending,
+                }),
+            )
+            .await
+            .map_err(|_| Error::ChannelClosed("transactions store"))
+            .context("failed to send transaction record to transactions store")?;
 
-        // Pay priority fees, if configured
-        instructions.push(ComputeBudgetInstruction::set_compute_unit_limit(
-            self.config.compute_unit_limit * instructions.len() as u32,
-        ));
-        if let Some(compute_unit_price_micro_lamports) =
-            self.config.compute_unit_price_micro_lamports
-        {
-            instructions.push(ComputeBudgetInstruction::set_compute_unit_price(
-                compute_unit_price_micro_lamports,
-            ));
+        Ok(PublishedBatch {
+            signature: Some(signature),
+            prices:    refreshed_batch,
+        })
+    }
+}
+
+/// A batch signed into a transaction by the Solana exporter
+pub struct SignedTransaction {
+    transaction:                       Transaction,
+    /// Number of prices the transaction updates
+    update_count:                      usize,
+    compute_unit_limit:                u32,
+    compute_unit_price_micro_lamports: Option<u64>,
+    attempt:                           u32,
+}
+
+/// Sends the batches in transactions to the Oracle program of the network,
+/// or to the price store program with the price store backend
+pub struct SolanaExporter {
+    rpc_client: RpcClient,
+
+    /// Watched for the settings reloaded on SIGHUP
+    config_rx: watch::Receiver<Config>,
+
+    /// Name of the network the transactions are sent to
+    network_name: String,
+
+    /// The Oracle program
+    program_key: Pubkey,
+
+    /// The accumulator program the Oracle program writes the updates to, if any
+    accumulator_key: Option<Pubkey>,
+
+    /// The price store program, required by the price store backend
+    price_store_key: Option<Pubkey>,
+
+    /// Watch receiver channel to access the current network state
+    network_state_rx: watch::Receiver<NetworkState>,
+
+    /// Used to resolve the feed indexes of the prices
+    global_store_reader: global::SnapshotReader,
+
+    /// Buffer accounts of the publish keys in the price store program, as
+    /// read from their publisher config accounts
+    publisher_buffer_keys: RwLock<HashMap<Pubkey, Pubkey>>,
+
+    /// Leaders of the upcoming slots, if leader-aware submission is enabled
+    leader_schedule_rx: Option<watch::Receiver<LeaderSchedule>>,
+
+    /// Socket the transactions are sent to the TPUs of the upcoming leaders
+    /// on, if enabled
+    tpu_socket: Option<UdpSocket>,
+
+    /// Whether the transactions are logged instead of sent, in simulation
+    simulated: bool,
+}
+
+impl SolanaExporter {
+    pub fn new(
+        config_rx: watch::Receiver<Config>,
+        network_name: &str,
+        rpc_url: &str,
+        rpc_timeout: Duration,
+        key_store: &KeyStore,
+        network_state_rx: watch::Receiver<NetworkState>,
+        global_store_reader: global::SnapshotReader,
+        leader_schedule_rx: Option<watch::Receiver<LeaderSchedule>>,
+        simulated: bool,
+    ) -> Result<Self> {
+        let tpu_socket = match &config_rx.borrow().leader_schedule {
+            Some(leader_schedule_config)
+                if leader_schedule_config.tpu_fanout_slots > 0 && leader_schedule_rx.is_some() =>
+            {
+                let socket =
+                    std::net::UdpSocket::bind("0.0.0.0:0").context("binding the TPU socket")?;
+                socket.set_nonblocking(true)?;
+                Some(UdpSocket::from_std(socket)?)
+            }
+            _ => None,
+        };
+        Ok(SolanaExporter {
+            rpc_client: instrumented_rpc::new_rpc_client(
+                network_name,
+                rpc_url,
+                rpc_timeout,
+                CommitmentConfig::default(),
+            ),
+            config_rx,
+            network_name: network_name.to_string(),
+            program_key: key_store.program_key,
+            accumulator_key: key_store.accumulator_key,
+            price_store_key: key_store.price_store_key,
+            network_state_rx,
+            global_store_reader,
+            publisher_buffer_keys: RwLock::new(HashMap::new()),
+            leader_schedule_rx,
+            tpu_socket,
+            simulated,
+        })
+    }
+
+    /// The price store program and the buffer account of the publish key,
+    /// reading the buffer account from the publisher config account once
+    async fn price_store_accounts(&self, publish_pubkey: Pubkey) -> Result<(Pubkey, Pubkey)> {
+        let price_store_program_key = self
+            .price_store_key
+            .context("the price store backend requires key_store.price_store_key_path")?;
+        if let Some(buffer_key) = self.publisher_buffer_keys.read().get(&publish_pubkey) {
+            return Ok((price_store_program_key, *buffer_key));
         }
 
-        let transaction = Transaction::new_signed_with_payer(
-            &instructions,
-            Some(&publish_keypair.pubkey()),
-            &vec![publish_keypair],
-            network_state.blockhash,
+        let (publisher_config_key, _) = Pubkey::find_program_address(
+            &[
+                PRICE_STORE_PUBLISHER_CONFIG_SEED,
+                &publish_pubkey.to_bytes(),
+            ],
+            &price_store_program_key,
         );
-
-        let signature = self
+        let data = self
             .rpc_client
-            .send_transaction_with_config(
-                &transaction,
-                RpcSendTransactionConfig {
-                    skip_preflight: true,
-                    ..RpcSendTransactionConfig::default()
-                },
-            )
-            .await?;
-        debug!(self.logger, "sent upd_price transaction"; "signature" => signature.to_string(), "instructions" => instructions.len(), "price_accounts" => format!("{:?}", price_accounts));
+            .get_account_data(&publisher_config_key)
+            .await
+            .with_context(|| format!("fetching the publisher config of {}", publish_pubkey))?;
+        let buffer_key = data
+            .get(PRICE_STORE_BUFFER_KEY_OFFSET..PRICE_STORE_BUFFER_KEY_OFFSET + 32)
+            .map(Pubkey::new)
+            .ok_or_else(|| {
+                Error::Parse(format!(
+                    "publisher config {} is too short",
+                    publisher_config_key
+                ))
+            })?;
+        self.publisher_buffer_keys
+            .write()
+            .insert(publish_pubkey, buffer_key);
+        Ok((price_store_program_key, buffer_key))
+    }
+
+    /// Compute unit price offered by the given attempt to publish a batch
+    fn compute_unit_price(config: &Config, attempt: u32) -> Option<u64> {
+        match &config.retry {
+            Some(retry_config) => {
+                retry_config.compute_unit_price(config.compute_unit_price_micro_lamports, attempt)
+            }
+            None => config.compute_unit_price_micro_lamports,
+        }
+    }
 
-        self.inflight_transactions_tx.send(signature).await?;
+    /// Hold the transaction back while the current leader is in its last slot,
+    /// so that it reaches the next leader in time, then send it to the TPUs of
+    /// the upcoming leaders, if enabled. Nothing is done without a leader
+    /// schedule.
+    async fn time_submission(&self, transaction: &Transaction) {
+        let leader_schedule_config = self.config_rx.borrow().leader_schedule.clone();
+        let (leader_schedule_rx, leader_schedule_config) =
+            match (&self.leader_schedule_rx, &leader_schedule_config) {
+                (Some(leader_schedule_rx), Some(leader_schedule_config)) => {
+                    (leader_schedule_rx, leader_schedule_config)
+                }
+                _ => return,
+            };
 
-        Ok(())
+        let delay = leader_schedule_rx.borrow().submission_delay(
+            time::Instant::now(),
+            leader_schedule_config.max_submission_delay,
+        );
+        if !delay.is_zero() {
+            debug!(
+                ?delay,
+                "Exporter: holding the transaction back until the next leader"
+            );
+            time::sleep(delay).await;
+        }
+
+        let tpu_socket = match &self.tpu_socket {
+            Some(tpu_socket) => tpu_socket,
+            None => return,
+        };
+        let tpu_addresses = leader_schedule_rx.borrow().upcoming_tpu_addresses(
+            time::Instant::now(),
+            leader_schedule_config.tpu_fanout_slots,
+        );
+        let wire_transaction = match bincode::serialize(transaction) {
+            Ok(wire_transaction) => wire_transaction,
+            Err(err) => {
+                warn!(error = ?err, "Exporter: could not serialize the transaction for the TPUs");
+                return;
+            }
+        };
+        // The RPC node still sends the transaction if the TPUs can't be reached
+        for tpu_address in tpu_addresses {
+            if let Err(err) = tpu_socket.send_to(&wire_transaction, tpu_address).await {
+                debug!(%tpu_address, error = %err, "Exporter: could not send the transaction to a TPU");
+            }
+        }
     }
 
     fn create_instruction_without_accumulator(
@@ -586,7 +2084,7 @@ impl Exporter {
         current_slot: u64,
     ) -> Result<Instruction> {
         Ok(Instruction {
-            program_id: self.key_store.program_key,
+            program_id: self.program_key,
             accounts:   vec![
                 AccountMeta {
                     pubkey:      publish_pubkey,
@@ -621,6 +2119,51 @@ impl Exporter {
         })
     }
 
+    fn create_submit_prices_instruction(
+        publish_pubkey: Pubkey,
+        price_store_program_key: Pubkey,
+        publisher_buffer_key: Pubkey,
+        prices: &[BufferedPrice],
+    ) -> Result<Instruction> {
+        let (publisher_config_key, publisher_config_bump) = Pubkey::find_program_address(
+            &[
+                PRICE_STORE_PUBLISHER_CONFIG_SEED,
+                &publish_pubkey.to_bytes(),
+            ],
+            &price_store_program_key,
+        );
+
+        let mut data = vec![PRICE_STORE_SUBMIT_PRICES, publisher_config_bump];
+        let options = bincode::DefaultOptions::new()
+            .with_little_endian()
+            .with_fixint_encoding();
+        for price in prices {
+            data.extend(options.serialize(price)?);
+        }
+
+        Ok(Instruction {
+            program_id: price_store_program_key,
+            accounts: vec![
+                AccountMeta {
+                    pubkey:      publish_pubkey,
+                    is_signer:   true,
+                    is_writable: true,
+                },
+                AccountMeta {
+                    pubkey:      publisher_config_key,
+                    is_signer:   false,
+                    is_writable: false,
+                },
+                AccountMeta {
+                    pubkey:      publisher_buffer_key,
+                    is_signer:   false,
+                    is_writable: true,
+                },
+            ],
+            data,
+        })
+    }
+
     fn create_instruction_with_accumulator(
         &self,
         publish_pubkey: Pubkey,
@@ -636,7 +2179,7 @@ impl Exporter {
 
         let (oracle_auth_pda, _) = Pubkey::find_program_address(
             &[b"upd_price_write", &accumulator_program_key.to_bytes()],
-            &self.key_store.program_key,
+            &self.program_key,
         );
 
         let (accumulator_data_pubkey, _accumulator_data_pubkey) = Pubkey::find_program_address(
@@ -649,7 +2192,7 @@ impl Exporter {
         );
 
         Ok(Instruction {
-            program_id: self.key_store.program_key,
+            program_id: self.program_key,
             accounts:   vec![
                 AccountMeta {
                     pubkey:      publish_pubkey,
@@ -709,51 +2252,330 @@ impl Exporter {
     }
 }
 
+#[async_trait]
+impl destination::Exporter for SolanaExporter {
+    type Submission = SignedTransaction;
+
+    async fn prepare(
+        &self,
+        batch: &destination::Batch<'_>,
+    ) -> Result<destination::Prepared<SignedTransaction>> {
+        let config = self.config_rx.borrow().clone();
+        let publish_pubkey = batch.signer.pubkey();
+        let mut instructions = Vec::new();
+
+        // Transactions built with an old blockhash would likely expire before
+        // landing. In simulation, they are built with the default network state.
+        let network_state = *self.network_state_rx.borrow();
+        if !self.simulated {
+            match network_state.blockhash_age() {
+                Some(age) if age <= config.max_blockhash_age => {}
+                Some(age) => {
+                    return Err(anyhow!(
+                        "cached blockhash is {}s old, not publishing the batch",
+                        age.as_secs()
+                    ))
+                }
+                None => {
+                    return Err(anyhow!(
+                        "no blockhash fetched yet, not publishing the batch"
+                    ))
+                }
+            }
+        }
+        // With the price store backend, the prices which have a feed index are
+        // submitted together to the publisher's buffer account
+        let price_store = match config.backend {
+            Backend::PriceStore => Some(self.price_store_accounts(publish_pubkey).await?),
+            Backend::Oracle => None,
+        };
+        let snapshot = self.global_store_reader.load();
+        let mut buffered_prices = Vec::new();
+
+        for (identifier, price_info) in batch.prices {
+            // The feed index the Oracle program assigns to the prices migrated
+            // to the price store is held in the drv4 field of the price account
+            let buffered_price = price_store
+                .and_then(|_| {
+                    snapshot
+                        .account_data
+                        .price_accounts
+                        .get(&Pubkey::new(&identifier.to_bytes()))
+                })
+                .map(|price_account| price_account.drv4)
+                .filter(|feed_index| *feed_index != 0)
+                .and_then(|feed_index| BufferedPrice::new(feed_index, price_info));
+            if let Some(buffered_price) = buffered_price {
+                buffered_prices.push(buffered_price);
+                continue;
+            }
+
+            let instruction = if let Some(accumulator_program_key) = self.accumulator_key {
+                self.create_instruction_with_accumulator(
+                    publish_pubkey,
+                    Pubkey::new(&identifier.to_bytes()),
+                    price_info,
+                    network_state.current_slot,
+                    accumulator_program_key,
+                )?
+            } else {
+                self.create_instruction_without_accumulator(
+                    publish_pubkey,
+                    Pubkey::new(&identifier.to_bytes()),
+                    price_info,
+                    network_state.current_slot,
+                )?
+            };
+
+            instructions.push(instruction);
+        }
+
+        // Pay priority fees, if configured
+        let update_count = instructions.len() + buffered_prices.len();
+        if let Some((price_store_program_key, publisher_buffer_key)) = price_store {
+            if !buffered_prices.is_empty() {
+                instructions.push(Self::create_submit_prices_instruction(
+                    publish_pubkey,
+                    price_store_program_key,
+                    publisher_buffer_key,
+                    &buffered_prices,
+                )?);
+            }
+        }
+        let compute_unit_limit = config.compute_unit_limit * update_count as u32;
+        instructions.push(ComputeBudgetInstruction::set_compute_unit_limit(
+            compute_unit_limit,
+        ));
+        let compute_unit_price_micro_lamports = Self::compute_unit_price(&config, batch.attempt);
+        if let Some(compute_unit_price_micro_lamports) = compute_unit_price_micro_lamports {
+            instructions.push(ComputeBudgetInstruction::set_compute_unit_price(
+                compute_unit_price_micro_lamports,
+            ));
+        }
+
+        let message = Message::new_with_blockhash(
+            &instructions,
+            Some(&publish_pubkey),
+            &network_state.blockhash,
+        );
+        let transaction = Transaction {
+            signatures: vec![batch.signer.sign_message(&message.serialize()).await?],
+            message,
+        };
+
+        Ok(destination::Prepared {
+            signature:             transaction.signatures[0],
+            prices:                batch
+                .prices
+                .iter()
+                .map(|(identifier, info)| (*identifier, info.timestamp))
+                .collect(),
+            slot:                  network_state.current_slot,
+            priority_fee_lamports: compute_unit_price_micro_lamports
+                .map(|price| price * compute_unit_limit as u64 / 1_000_000),
+            submission:            SignedTransaction {
+                transaction,
+                update_count,
+                compute_unit_limit,
+                compute_unit_price_micro_lamports,
+                attempt: batch.attempt,
+            },
+        })
+    }
+
+    async fn submit(&self, prepared: &destination::Prepared<SignedTransaction>) -> Result<bool> {
+        let SignedTransaction {
+            transaction,
+            update_count,
+            compute_unit_limit,
+            compute_unit_price_micro_lamports,
+            attempt,
+        } = &prepared.submission;
+        let instructions = transaction.message.instructions.len();
+        let price_accounts = prepared
+            .prices
+            .iter()
+            .map(|(identifier, _)| bs58::encode(identifier.to_bytes()).into_string())
+            .collect::<Vec<_>>();
+
+        if self.simulated {
+            info!(
+                signature = %prepared.signature,
+                instructions,
+                ?price_accounts,
+                "simulation: upd_price transaction not sent"
+            );
+            return Ok(false);
+        }
+
+        if self.config_rx.borrow().dry_run {
+            info!(
+                signature = %prepared.signature,
+                instructions,
+                compute_unit_limit,
+                compute_unit_price_micro_lamports,
+                attempt,
+                ?price_accounts,
+                "Exporter: dry run, upd_price transaction not sent"
+            );
+            DRY_RUN_METRICS.record(&self.network_name, *update_count);
+            return Ok(false);
+        }
+
+        self.time_submission(transaction).await;
+
+        let signature = self
+            .rpc_client
+            .send_transaction_with_config(
+                transaction,
+                RpcSendTransactionConfig {
+                    skip_preflight: true,
+                    ..RpcSendTransactionConfig::default()
+                },
+            )
+            .await?;
+        debug!(
+            %signature,
+            instructions,
+            ?price_accounts,
+            "sent upd_price transaction"
+        );
+        Ok(true)
+    }
+
+    async fn confirm(&self, signatures: &[Signature]) -> Result<Vec<Option<TransactionStatus>>> {
+        // Poll the status of each transaction, in a single RPC request
+        let statuses = self
+            .rpc_client
+            .get_signature_statuses(signatures)
+            .await?
+            .value;
+
+        debug!(?statuses, "Processing Signature Statuses");
+
+        Ok(statuses
+            .into_iter()
+            .map(|status| {
+                status.map(|status| match status.err {
+                    Some(err) => TransactionStatus::Failed(err.to_string()),
+                    None if status.satisfies_commitment(CommitmentConfig::confirmed()) => {
+                        TransactionStatus::Confirmed
+                    }
+                    None => TransactionStatus::Pending,
+                })
+            })
+            .collect())
+    }
+}
+
 #[derive(Debug, Clone, Copy, Default)]
 pub struct NetworkState {
-    blockhash:    Hash,
-    current_slot: u64,
+    blockhash:            Hash,
+    current_slot:         u64,
+    /// When the blockhash was fetched, `None` until it first was
+    blockhash_fetched_at: Option<Instant>,
+}
+
+impl NetworkState {
+    /// Time since the cached blockhash was fetched
+    pub fn blockhash_age(&self) -> Option<Duration> {
+        self.blockhash_fetched_at
+            .map(|fetched_at| fetched_at.elapsed())
+    }
 }
 
+/// Delay before subscribing to the slots again after the subscription failed,
+/// during which the network state is only refreshed on the interval
+const SLOT_RESUBSCRIBE_DELAY: Duration = Duration::from_secs(10);
+
 /// NetworkStateQuerier periodically queries the current state of the network,
-/// fetching the blockhash and slot number.
+/// fetching the blockhash and slot number. It is the blockhash cache shared by
+/// the Exporter's batches, so that building a transaction never waits on the RPC.
 struct NetworkStateQuerier {
     /// The RPC client
     rpc_client: RpcClient,
 
+    /// Websocket endpoint notifying the new slots the network state is also
+    /// refreshed on, if enabled
+    wss_url: Option<String>,
+
     /// The interval with which to query the network state
     query_interval: Interval,
 
     /// Channel the current network state is sent on
     network_state_tx: watch::Sender<NetworkState>,
-
-    /// Logger
-    logger: Logger,
 }
 
 impl NetworkStateQuerier {
     pub fn new(
+        network_name: &str,
         rpc_endpoint: &str,
+        wss_url: Option<String>,
         rpc_timeout: Duration,
         query_interval: Interval,
         network_state_tx: watch::Sender<NetworkState>,
-        logger: Logger,
     ) -> Self {
         NetworkStateQuerier {
-            rpc_client: RpcClient::new_with_timeout(rpc_endpoint.to_string(), rpc_timeout),
+            rpc_client: instrumented_rpc::new_rpc_client(
+                network_name,
+                rpc_endpoint,
+                rpc_timeout,
+                CommitmentConfig::default(),
+            ),
+            wss_url,
             query_interval,
             network_state_tx,
-            logger,
         }
     }
 
     pub async fn run(&mut self) {
         loop {
-            self.query_interval.tick().await;
+            if let Some(wss_url) = self.wss_url.clone() {
+                if let Err(err) = self.run_on_new_slots(&wss_url).await {
+                    error!(
+                        error = ?err,
+                        "Network state querier: slot subscription failed, refreshing on the interval only: {:#}",
+                        err
+                    );
+                }
+            }
+
+            // Refresh on the interval alone until subscribing again, if enabled
+            let resubscribe_at = Instant::now() + SLOT_RESUBSCRIBE_DELAY;
+            while self.wss_url.is_none() || Instant::now() < resubscribe_at {
+                self.query_interval.tick().await;
+                if let Err(err) = self.query_network_state().await {
+                    error!(error = ?err, kind = %error::record("exporter", &err), "{:#}", err);
+                }
+            }
+        }
+    }
+
+    /// Refresh the network state on every new slot, and on the interval in
+    /// between, until the subscription ends
+    async fn run_on_new_slots(&mut self, wss_url: &str) -> Result<()> {
+        let pubsub_client = PubsubClient::new(wss_url)
+            .await
+            .context("connecting to the websocket endpoint")?;
+        let (mut slots, unsubscribe) = pubsub_client
+            .slot_subscribe()
+            .await
+            .context("subscribing to the slots")?;
+        loop {
+            tokio::select! {
+                _ = self.query_interval.tick() => {}
+                slot = slots.next() => {
+                    if slot.is_none() {
+                        break;
+                    }
+                }
+            }
             if let Err(err) = self.query_network_state().await {
-                error!(self.logger, "{:#}", err; "error" => format!("{:?}", err));
+                error!(error = ?err, kind = %error::record("exporter", &err), "{:#}", err);
             }
         }
+        unsubscribe().await;
+        Err(anyhow!("slot subscription closed"))
     }
 
     async fn query_network_state(&mut self) -> Result<()> {
@@ -768,33 +2590,97 @@ impl NetworkStateQuerier {
 
         // Send the result on the channel
         self.network_state_tx.send(NetworkState {
-            blockhash:    latest_blockhash_result?,
-            current_slot: current_slot_result?,
+            blockhash:            latest_blockhash_result?,
+            current_slot:         current_slot_result?,
+            blockhash_fetched_at: Some(Instant::now()),
         })?;
 
         Ok(())
     }
 }
 
+/// The prices updated for each publish key which it is not permissioned to
+/// publish, as (publish key, price account) pairs
+fn missing_permissions(
+    updated_prices: &HashMap<Pubkey, HashSet<Pubkey>>,
+    publisher_permissions: &HashMap<Pubkey, HashSet<Pubkey>>,
+) -> HashSet<(Pubkey, Pubkey)> {
+    updated_prices
+        .iter()
+        .flat_map(|(publish_pubkey, price_keys)| {
+            let permissioned_prices = publisher_permissions.get(publish_pubkey);
+            price_keys
+                .iter()
+                .filter(move |price_key| {
+                    !permissioned_prices.map_or(false, |prices| prices.contains(*price_key))
+                })
+                .map(move |price_key| (*publish_pubkey, *price_key))
+        })
+        .collect()
+}
+
 mod transaction_monitor {
     use {
-        anyhow::Result,
+        super::{
+            super::instrumented_rpc,
+            destination,
+            RetryConfig,
+        },
+        crate::agent::{
+            error::{
+                self,
+                Error,
+            },
+            health::ComponentHealth,
+            metrics::PUBLISH_RETRY_METRICS,
+            store::{
+                local::Publisher,
+                transactions::{
+                    self,
+                    TransactionStatus,
+                },
+                PriceIdentifier,
+            },
+            telemetry,
+            update_status::{
+                Seq,
+                UpdateStatuses,
+            },
+        },
+        anyhow::{
+            Context as _,
+            Result,
+        },
+        opentelemetry::{
+            Context,
+            KeyValue,
+        },
+        pyth_sdk::UnixTimestamp,
         serde::{
             Deserialize,
             Serialize,
         },
-        slog::Logger,
         solana_client::nonblocking::rpc_client::RpcClient,
         solana_sdk::{
             commitment_config::CommitmentConfig,
             signature::Signature,
         },
         std::{
-            collections::VecDeque,
-            time::Duration,
+            collections::{
+                HashMap,
+                VecDeque,
+            },
+            sync::Arc,
+            time::{
+                Duration,
+                SystemTime,
+            },
         },
         tokio::{
-            sync::mpsc,
+            sync::{
+                mpsc,
+                watch,
+            },
             time::{
                 self,
                 Interval,
@@ -802,8 +2688,31 @@ mod transaction_monitor {
         },
     };
 
-    #[derive(Clone, Serialize, Deserialize, Debug)]
-    #[serde(default)]
+    /// A batch published by the Exporter
+    #[derive(Clone, Debug)]
+    pub struct SentBatch {
+        pub publisher: Publisher,
+        /// Prices of the batch, with the timestamp of the updates they were
+        /// published with
+        pub prices:    Vec<(PriceIdentifier, UnixTimestamp)>,
+        /// Attempt to publish the batch, starting from 1
+        pub attempt:   u32,
+        /// Slot the batch was sent in
+        pub slot:      u64,
+        /// Sequence ids of the updates the batch publishes
+        pub seqs:      Vec<Seq>,
+    }
+
+    /// A transaction sent by the Exporter, along with the trace context and
+    /// contents of the batch it published
+    pub struct SentTransaction {
+        pub signature:     Signature,
+        pub trace_context: Context,
+        pub batch:         SentBatch,
+    }
+
+    #[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+    #[serde(default, deny_unknown_fields)]
     pub struct Config {
         /// Duration of the interval with which to poll the status of transactions.
         /// It is recommended to set this to a value close to the Exporter's publish_interval.
@@ -826,56 +2735,103 @@ mod transaction_monitor {
 
     /// TransactionMonitor monitors the percentage of recently sent transactions that
     /// have been successfully confirmed.
-    pub struct TransactionMonitor {
+    pub struct TransactionMonitor<D> {
         config: Config,
 
         /// The RPC client
         rpc_client: RpcClient,
 
-        /// Channel the signatures of transactions we have sent are received.
-        transactions_rx: mpsc::Receiver<Signature>,
+        /// Destination the statuses of the transactions are polled from
+        destination: Arc<D>,
+
+        /// Watched for the retry policy of the Exporter
+        exporter_config_rx: watch::Receiver<super::Config>,
+
+        /// Name of the network the transactions are sent to
+        network_name: String,
+
+        /// Channel the signatures of transactions we have sent are received,
+        /// along with the trace context of the batch they published.
+        transactions_rx: mpsc::Receiver<SentTransaction>,
+
+        /// Batches of the sent transactions which have not settled yet
+        batches: HashMap<Signature, SentBatch>,
+
+        /// Channel on which the batches which did not land in time are handed
+        /// back to the Exporter
+        retry_tx: mpsc::Sender<SentBatch>,
 
         /// Vector storing the signatures of transactions we have sent
         sent_transactions: VecDeque<Signature>,
 
+        /// Trace contexts of the sent transactions which have not settled yet,
+        /// along with the time they were received
+        trace_contexts: HashMap<Signature, (Context, SystemTime)>,
+
         /// Interval with which to poll the status of transactions
         poll_interval: Interval,
 
-        logger: Logger,
+        /// Channel on which to report transaction statuses to the transactions store
+        transactions_store_tx: mpsc::Sender<transactions::Message>,
+
+        /// Statuses of the updates, reported as their transactions settle
+        update_statuses: UpdateStatuses,
+
+        /// Reports whether recent transactions are landing
+        health: ComponentHealth,
     }
 
-    impl TransactionMonitor {
+    impl<D: destination::Exporter> TransactionMonitor<D> {
         pub fn new(
             config: Config,
+            exporter_config_rx: watch::Receiver<super::Config>,
+            network_name: &str,
             rpc_url: &str,
             rpc_timeout: Duration,
-            transactions_rx: mpsc::Receiver<Signature>,
-            logger: Logger,
+            destination: Arc<D>,
+            transactions_rx: mpsc::Receiver<SentTransaction>,
+            retry_tx: mpsc::Sender<SentBatch>,
+            transactions_store_tx: mpsc::Sender<transactions::Message>,
+            update_statuses: UpdateStatuses,
+            health: ComponentHealth,
         ) -> Self {
             let poll_interval = time::interval(config.poll_interval_duration);
-            let rpc_client = RpcClient::new_with_timeout(rpc_url.to_string(), rpc_timeout);
+            let rpc_client = instrumented_rpc::new_rpc_client(
+                network_name,
+                rpc_url,
+                rpc_timeout,
+                CommitmentConfig::default(),
+            );
             TransactionMonitor {
                 config,
+                exporter_config_rx,
+                network_name: network_name.to_string(),
                 rpc_client,
+                destination,
                 sent_transactions: VecDeque::new(),
+                trace_contexts: HashMap::new(),
                 transactions_rx,
+                batches: HashMap::new(),
+                retry_tx,
                 poll_interval,
-                logger,
+                transactions_store_tx,
+                update_statuses,
+                health,
             }
         }
 
         pub async fn run(&mut self) {
             loop {
                 if let Err(err) = self.handle_next().await {
-                    error!(self.logger, "{:#}", err; "error" => format!("{:?}", err));
+                    error!(error = ?err, kind = %error::record("exporter", &err), "{:#}", err);
                 }
             }
         }
 
         async fn handle_next(&mut self) -> Result<()> {
             tokio::select! {
-                Some(signature) = self.transactions_rx.recv() => {
-                    self.add_transaction(signature);
+                Some(transaction) = self.transactions_rx.recv() => {
+                    self.add_transaction(transaction);
                     Ok(())
                 }
                 _ = self.poll_interval.tick() => {
@@ -884,57 +2840,1054 @@ mod transaction_monitor {
             }
         }
 
-        fn add_transaction(&mut self, signature: Signature) {
-            debug!(self.logger, "monitoring new transaction"; "signature" => signature.to_string());
+        fn add_transaction(&mut self, transaction: SentTransaction) {
+            let SentTransaction {
+                signature,
+                trace_context,
+                batch,
+            } = transaction;
+            debug!(%signature, attempt = batch.attempt, "monitoring new transaction");
 
             // Add the new transaction to the list
             self.sent_transactions.push_back(signature);
+            self.trace_contexts
+                .insert(signature, (trace_context, SystemTime::now()));
+            self.batches.insert(signature, batch);
 
             // Pop off the oldest transaction if necessary
             if self.sent_transactions.len() > self.config.max_transactions {
-                self.sent_transactions.pop_front();
+                if let Some(signature) = self.sent_transactions.pop_front() {
+                    self.trace_contexts.remove(&signature);
+                    self.batches.remove(&signature);
+                }
+            }
+        }
+
+        /// Hand the batches whose transactions were not seen within the
+        /// confirmation timeout back to the Exporter, until their last attempt
+        async fn retry_unlanded_batches(
+            &mut self,
+            retry_config: &RetryConfig,
+            unseen: Vec<Signature>,
+        ) -> Result<()> {
+            let current_slot = self
+                .rpc_client
+                .get_slot_with_commitment(CommitmentConfig::confirmed())
+                .await?;
+            for signature in unseen {
+                let timed_out = self.batches.get(&signature).map_or(false, |batch| {
+                    batch.slot + retry_config.confirmation_timeout_slots < current_slot
+                });
+                if !timed_out {
+                    continue;
+                }
+                let batch = match self.batches.remove(&signature) {
+                    Some(batch) => batch,
+                    None => continue,
+                };
+
+                if batch.attempt >= retry_config.max_attempts {
+                    warn!(
+                        %signature,
+                        attempt = batch.attempt,
+                        "Transaction monitor: batch did not land after its last attempt"
+                    );
+                    PUBLISH_RETRY_METRICS.abandoned(&self.network_name);
+                    self.update_statuses.failed(
+                        &self.network_name,
+                        &batch.seqs,
+                        format!("did not land after {} attempts", batch.attempt),
+                    );
+                } else if let Err(err) = self.retry_tx.try_send(batch) {
+                    warn!(%signature, "Transaction monitor: could not hand back batch for retry: {}", err);
+                }
             }
+            Ok(())
         }
 
         async fn poll_transactions_status(&mut self) -> Result<()> {
             if self.sent_transactions.is_empty() {
+                self.health.healthy("no recent transactions");
                 return Ok(());
             }
 
             let signatures_contiguous = self.sent_transactions.make_contiguous();
 
-            // Poll the status of each transaction, in a single RPC request
-            let statuses = self
-                .rpc_client
-                .get_signature_statuses(signatures_contiguous)
-                .await?
-                .value;
+            let statuses = self.destination.confirm(signatures_contiguous).await?;
+
+            // Report the settled transactions to the transactions store
+            for (status, signature) in statuses.iter().zip(signatures_contiguous.iter()) {
+                let status = match status {
+                    Some(TransactionStatus::Pending) | None => continue,
+                    Some(status) => status.clone(),
+                };
+
+                // Record the attempts it took to land the batch's updates, and
+                // the statuses of the updates
+                if let Some(batch) = self.batches.remove(signature) {
+                    match &status {
+                        TransactionStatus::Confirmed => {
+                            PUBLISH_RETRY_METRICS.landed(
+                                &self.network_name,
+                                batch.attempt,
+                                batch.prices.len(),
+                            );
+                            self.update_statuses.confirmed(
+                                &self.network_name,
+                                &batch.seqs,
+                                signature,
+                            );
+                        }
+                        TransactionStatus::Failed(reason) => self.update_statuses.failed(
+                            &self.network_name,
+                            &batch.seqs,
+                            reason.clone(),
+                        ),
+                        TransactionStatus::Pending => {}
+                    }
+                }
+
+                // Close the batch's trace with its settlement, the first time it is seen
+                if let Some((trace_context, sent_time)) = self.trace_contexts.remove(signature) {
+                    telemetry::record_span_since(
+                        &trace_context,
+                        "transaction_monitor.settled",
+                        sent_time,
+                        vec![KeyValue::new("status", format!("{:?}", status))],
+                    );
+                }
+
+                self.transactions_store_tx
+                    .send(transactions::Message::StatusUpdate {
+                        signature: *signature,
+                        status,
+                    })
+                    .await
+                    .map_err(|_| Error::ChannelClosed("transactions store"))
+                    .context("failed to send transaction status to transactions store")?;
+            }
 
-            debug!(self.logger, "Processing Signature Statuses"; "statuses" => format!("{:?}", statuses));
+            // The transactions not seen by the network yet
+            let unseen = statuses
+                .iter()
+                .zip(signatures_contiguous.iter())
+                .filter(|(status, _)| status.is_none())
+                .map(|(_, signature)| *signature)
+                .collect();
 
-            // Determine the percentage of the recently sent transactions that have successfully been committed
+            // Determine the percentage of the recently sent transactions that have landed
             // TODO: expose as metric
             let confirmed = statuses
                 .into_iter()
                 .zip(signatures_contiguous)
-                .map(|(status, sig)| status.map(|some_status| (some_status, sig))) // Collate Some() statuses with their tx signatures before flatten()
-                .flatten()
-                .filter(|(status, sig)| {
-                    if let Some(err) = status.err.as_ref() {
-                        warn!(self.logger, "TX status has err value";
-                        "error" => err.to_string(),
-                        "tx_signature" => sig.to_string(),
-                                          )
+                .filter(|(status, sig)| match status {
+                    Some(TransactionStatus::Failed(err)) => {
+                        warn!(error = %err, tx_signature = %sig, "TX status has err value");
+                        true
                     }
-
-                    status.satisfies_commitment(CommitmentConfig::confirmed())
+                    Some(TransactionStatus::Confirmed) => true,
+                    _ => false,
                 })
                 .count();
             let percentage_confirmed =
                 ((confirmed as f64) / (self.sent_transactions.len() as f64)) * 100.0;
-            info!(self.logger, "monitoring transaction hit rate"; "percentage confirmed" => format!("{:.}", percentage_confirmed));
+            info!(
+                percentage_confirmed = %format!("{:.}", percentage_confirmed),
+                "monitoring transaction hit rate"
+            );
+
+            let health_detail = format!(
+                "{:.} percent of the {} recent transactions confirmed",
+                percentage_confirmed,
+                self.sent_transactions.len()
+            );
+            if confirmed > 0 {
+                self.health.healthy(health_detail);
+            } else {
+                self.health.unhealthy(health_detail);
+            }
+
+            // Retry the batches of the transactions not seen in time, if enabled
+            let retry_config = self.exporter_config_rx.borrow().retry.clone();
+            if let Some(retry_config) = retry_config {
+                self.retry_unlanded_batches(&retry_config, unseen).await?;
+            }
 
             Ok(())
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            destination::{
+                self,
+                Exporter as _,
+            },
+            transaction_monitor::SentTransaction,
+            Backend,
+            BufferedPrice,
+            ConfFloor,
+            ConfFloorAction,
+            ConfFloorConfig,
+            Config,
+            Exporter,
+            MicroBatchingConfig,
+            NetworkState,
+            PendingUpdates,
+            RetryConfig,
+            SolanaExporter,
+            PRICE_STORE_PUBLISHER_CONFIG_SEED,
+        },
+        crate::agent::{
+            channel_monitor::{
+                self,
+                ChannelMonitor,
+            },
+            publish_pause::PublishPause,
+            remote_keypair_loader::KeypairRequest,
+            solana::{
+                key_store::{
+                    self,
+                    KeyStore,
+                },
+                oracle::PriceEntry,
+                signer::KeypairSigner,
+                slot_lag::SlotLagReporter,
+            },
+            store::{
+                global::{
+                    self,
+                    ProductAccountMetadata,
+                },
+                local::{
+                    self,
+                    AllPriceInfo,
+                    PriceInfo,
+                },
+                transactions::{
+                    self,
+                    TransactionStatus,
+                },
+                PriceIdentifier,
+            },
+            update_status::UpdateStatuses,
+        },
+        anyhow::Result,
+        async_trait::async_trait,
+        chrono::Utc,
+        parking_lot::Mutex,
+        prometheus_client::registry::Registry,
+        pyth_sdk_solana::state::PriceStatus,
+        solana_sdk::{
+            hash::Hash,
+            pubkey::Pubkey,
+            signature::{
+                write_keypair_file,
+                Keypair,
+                Signature,
+            },
+            signer::Signer as _,
+        },
+        std::{
+            collections::{
+                HashMap,
+                HashSet,
+            },
+            env,
+            sync::Arc,
+            time::{
+                Duration,
+                Instant,
+            },
+        },
+        tokio::sync::{
+            broadcast,
+            mpsc,
+            watch,
+        },
+    };
+
+    /// Destination recording the batches it is handed, with the key signing
+    /// them, and confirming the submissions given a status
+    #[derive(Default)]
+    struct TestDestination {
+        batches:  Mutex<Vec<(Pubkey, Vec<(PriceIdentifier, PriceInfo)>)>>,
+        statuses: Mutex<HashMap<Signature, TransactionStatus>>,
+        /// Whether the submissions are not actually sent, as in a dry run
+        dry_run:  Mutex<bool>,
+    }
+
+    #[async_trait]
+    impl destination::Exporter for TestDestination {
+        type Submission = ();
+
+        async fn prepare(
+            &self,
+            batch: &destination::Batch<'_>,
+        ) -> Result<destination::Prepared<Self::Submission>> {
+            self.batches
+                .lock()
+                .push((batch.signer.pubkey(), batch.prices.to_vec()));
+            Ok(destination::Prepared {
+                signature:             Signature::new_unique(),
+                prices:                batch
+                    .prices
+                    .iter()
+                    .map(|(identifier, info)| (*identifier, info.timestamp))
+                    .collect(),
+                slot:                  0,
+                priority_fee_lamports: None,
+                submission:            (),
+            })
+        }
+
+        async fn submit(
+            &self,
+            _prepared: &destination::Prepared<Self::Submission>,
+        ) -> Result<bool> {
+            Ok(!*self.dry_run.lock())
+        }
+
+        async fn confirm(
+            &self,
+            signatures: &[Signature],
+        ) -> Result<Vec<Option<TransactionStatus>>> {
+            let statuses = self.statuses.lock();
+            Ok(signatures
+                .iter()
+                .map(|signature| statuses.get(signature).cloned())
+                .collect())
+        }
+    }
+
+    /// An Exporter publishing the contents of a fake Local Store to a test
+    /// destination, with the other ends of its channels
+    struct Harness {
+        exporter:                 Exporter<TestDestination>,
+        destination:              Arc<TestDestination>,
+        local_store:              Arc<Mutex<AllPriceInfo>>,
+        config_tx:                watch::Sender<Config>,
+        key_store_config_tx:      watch::Sender<key_store::Config>,
+        local_store_events_tx:    broadcast::Sender<local::Event>,
+        publisher_permissions_tx: mpsc::Sender<HashMap<Pubkey, HashSet<Pubkey>>>,
+        inflight_transactions_rx: mpsc::Receiver<SentTransaction>,
+        _transactions_store_rx:   mpsc::Receiver<transactions::Message>,
+        _keypair_request_rx:      mpsc::Receiver<KeypairRequest>,
+    }
+
+    impl Harness {
+        async fn new(config: Config) -> Self {
+            let (config_tx, config_rx) = watch::channel(config);
+            let (key_store_config_tx, key_store_config_rx) = watch::channel(Default::default());
+            let (local_store_tx, mut local_store_rx) = mpsc::channel(100);
+            let (transactions_store_tx, _transactions_store_rx) = mpsc::channel(100);
+            let (local_store_events_tx, _) = broadcast::channel(100);
+            let (inflight_transactions_tx, inflight_transactions_rx) = mpsc::channel(100);
+            let (_retry_tx, retry_rx) = mpsc::channel(100);
+            let (publisher_permissions_tx, publisher_permissions_rx) = mpsc::channel(100);
+            let (keypair_request_tx, _keypair_request_rx) = mpsc::channel(100);
+
+            // The Local Store only answers the lookups of the Exporter
+            let local_store = Arc::new(Mutex::new(AllPriceInfo::new()));
+            let contents = local_store.clone();
+            tokio::spawn(async move {
+                while let Some(message) = local_store_rx.recv().await {
+                    match message {
+                        local::Message::LookupAllPriceInfo { result_tx } => {
+                            let _ = result_tx.send(contents.lock().clone());
+                        }
+                        local::Message::LookupAllTraceContexts { result_tx } => {
+                            let _ = result_tx.send(HashMap::new());
+                        }
+                        _ => {}
+                    }
+                }
+            });
+
+            let destination = Arc::new(TestDestination::default());
+            let exporter = Exporter::new(
+                config_rx,
+                "test",
+                destination.clone(),
+                KeyStore {
+                    publish_keypair:             Some(Keypair::new()),
+                    program_key:                 Pubkey::new_unique(),
+                    mapping_key:                 Pubkey::new_unique(),
+                    accumulator_key:             None,
+                    price_store_key:             None,
+                    additional_publish_keypairs: HashMap::new(),
+                },
+                key_store_config_rx,
+                local_store_tx,
+                transactions_store_tx,
+                local_store_events_tx.clone(),
+                inflight_transactions_tx,
+                "test_inflight_transactions".to_string(),
+                retry_rx,
+                ChannelMonitor::new(channel_monitor::Config::default()).await,
+                publisher_permissions_rx,
+                keypair_request_tx,
+                None,
+                global::SnapshotReader::default(),
+                PublishPause::new(&mut Registry::default()),
+                UpdateStatuses::default(),
+                SlotLagReporter::new().await.network("test"),
+                None,
+            );
+
+            Harness {
+                exporter,
+                destination,
+                local_store,
+                config_tx,
+                key_store_config_tx,
+                local_store_events_tx,
+                publisher_permissions_tx,
+                inflight_transactions_rx,
+                _transactions_store_rx,
+                _keypair_request_rx,
+            }
+        }
+
+        /// Key the updates of the default publisher are signed with
+        fn publish_key(&self) -> Pubkey {
+            self.exporter
+                .key_store
+                .publish_keypair
+                .as_ref()
+                .unwrap()
+                .pubkey()
+        }
+
+        /// Permission the publish key to publish the given prices
+        async fn permit(&self, identifiers: &[PriceIdentifier]) {
+            let prices = identifiers
+                .iter()
+                .map(|identifier| Pubkey::new_from_array(identifier.to_bytes()))
+                .collect();
+            self.publisher_permissions_tx
+                .send(HashMap::from([(self.publish_key(), prices)]))
+                .await
+                .unwrap();
+        }
+
+        /// Accept an update of the default publisher into the Local Store
+        fn update(&self, identifier: PriceIdentifier, info: PriceInfo) {
+            self.local_store
+                .lock()
+                .entry(None)
+                .or_default()
+                .insert(identifier, info);
+        }
+
+        /// The prices and confidence intervals of the batches handed to the
+        /// destination since the previous call, with the key signing them
+        fn published(&self) -> Vec<(Pubkey, Vec<(PriceIdentifier, i64, u64)>)> {
+            std::mem::take(&mut *self.destination.batches.lock())
+                .into_iter()
+                .map(|(signer, prices)| {
+                    let prices = prices
+                        .into_iter()
+                        .map(|(identifier, info)| (identifier, info.price, info.conf))
+                        .collect();
+                    (signer, prices)
+                })
+                .collect()
+        }
+    }
+
+    /// A fresh trading price
+    fn price_info(price: i64, conf: u64) -> PriceInfo {
+        PriceInfo {
+            status: PriceStatus::Trading,
+            price,
+            conf,
+            timestamp: Utc::now().timestamp(),
+        }
+    }
+
+    #[test]
+    fn test_pending_updates_are_due_after_the_max_delay() {
+        let config = MicroBatchingConfig {
+            max_delay: Duration::from_millis(100),
+            ..Default::default()
+        };
+        let mut pending_updates = PendingUpdates::default();
+        assert_eq!(pending_updates.deadline(&config), None);
+
+        // The deadline is measured from the oldest of the pending updates
+        let before = Instant::now();
+        pending_updates.record(1);
+        let deadline = pending_updates.deadline(&config).unwrap();
+        assert!(deadline >= before + config.max_delay);
+        pending_updates.record(2);
+        assert_eq!(pending_updates.count, 3);
+        assert_eq!(pending_updates.deadline(&config), Some(deadline));
+
+        pending_updates.clear();
+        assert_eq!(pending_updates.count, 0);
+        assert_eq!(pending_updates.deadline(&config), None);
+    }
+
+    #[tokio::test]
+    async fn test_flushing_publishes_the_pending_updates() {
+        let mut harness = Harness::new(Config {
+            micro_batching: Some(MicroBatchingConfig::default()),
+            ..Default::default()
+        })
+        .await;
+        let identifier = PriceIdentifier::new(Pubkey::new_unique().to_bytes());
+        harness.permit(&[identifier]).await;
+        harness.update(identifier, price_info(10, 1));
+        harness.exporter.pending_updates.record(1);
+
+        harness.exporter.flush_pending_updates("test").await;
+
+        assert_eq!(
+            harness.published(),
+            vec![(harness.publish_key(), vec![(identifier, 10, 1)])]
+        );
+        assert_eq!(harness.exporter.pending_updates.count, 0);
+        assert!(harness.inflight_transactions_rx.try_recv().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_local_store_events_are_only_received_with_micro_batching() {
+        let mut harness = Harness::new(Config::default()).await;
+        assert!(harness.exporter.local_store_events_rx.is_none());
+        assert_eq!(harness.local_store_events_tx.receiver_count(), 0);
+
+        // Enabling micro-batching on reload subscribes to the events
+        harness
+            .config_tx
+            .send(Config {
+                micro_batching: Some(MicroBatchingConfig::default()),
+                ..Default::default()
+            })
+            .unwrap();
+        harness.exporter.reload_config();
+        assert!(harness.exporter.local_store_events_rx.is_some());
+        assert_eq!(harness.local_store_events_tx.receiver_count(), 1);
+
+        // And disabling it again unsubscribes
+        harness.exporter.pending_updates.record(1);
+        harness.config_tx.send(Config::default()).unwrap();
+        harness.exporter.reload_config();
+        assert!(harness.exporter.local_store_events_rx.is_none());
+        assert_eq!(harness.local_store_events_tx.receiver_count(), 0);
+        assert_eq!(harness.exporter.pending_updates.count, 0);
+    }
+
+    /// A snapshot of the Global Store holding a product with a single price
+    /// of each symbol
+    fn snapshot(symbols: &[(&str, Pubkey)]) -> global::Snapshot {
+        let mut snapshot = global::Snapshot::default();
+        for (symbol, price_key) in symbols {
+            snapshot.account_metadata.insert_product(
+                Pubkey::new_unique(),
+                ProductAccountMetadata {
+                    attr_dict:      [("symbol".to_string(), symbol.to_string())]
+                        .into_iter()
+                        .collect(),
+                    price_accounts: vec![*price_key],
+                },
+            );
+        }
+        snapshot
+    }
+
+    fn conf_floor(min_conf: u64) -> ConfFloor {
+        ConfFloor {
+            min_conf,
+            min_conf_ratio: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_conf_floor_of_the_symbol_then_asset_class_then_default() {
+        let config = ConfFloorConfig {
+            default_floor: conf_floor(1),
+            asset_class_floors: HashMap::from([("Equity".to_string(), conf_floor(10))]),
+            symbol_floors: HashMap::from([("Equity.US.AAPL/USD".to_string(), conf_floor(100))]),
+            ..Default::default()
+        };
+
+        assert_eq!(config.floor(Some("Equity.US.AAPL/USD")).min_conf, 100);
+        assert_eq!(config.floor(Some("Equity.US.MSFT/USD")).min_conf, 10);
+        assert_eq!(config.floor(Some("Crypto.BTC/USD")).min_conf, 1);
+        assert_eq!(config.floor(Some("Equity")).min_conf, 1);
+        assert_eq!(config.floor(None).min_conf, 1);
+    }
+
+    #[test]
+    fn test_min_conf_is_the_highest_of_the_absolute_and_relative_bounds() {
+        let floor = ConfFloor {
+            min_conf:       5,
+            min_conf_ratio: 0.001,
+        };
+        assert_eq!(floor.min_conf(1000), 5);
+        // The relative bound is rounded up, and applies to negative prices
+        assert_eq!(floor.min_conf(10_001), 11);
+        assert_eq!(floor.min_conf(-100_000), 100);
+        assert_eq!(ConfFloor::default().min_conf(i64::MIN), 0);
+    }
+
+    #[tokio::test]
+    async fn test_conf_floor_clamps_or_rejects_the_published_prices() {
+        let mut harness = Harness::new(Config {
+            conf_floor: Some(ConfFloorConfig {
+                default_floor: conf_floor(5),
+                symbol_floors: HashMap::from([("Crypto.BTC/USD".to_string(), conf_floor(10))]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        })
+        .await;
+        let (btc, eth, sol) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        harness.exporter.global_store_reader = global::SnapshotReader::new(snapshot(&[
+            ("Crypto.BTC/USD", btc),
+            ("Crypto.ETH/USD", eth),
+            ("Crypto.SOL/USD", sol),
+        ]));
+        let (btc, eth, sol) = (
+            PriceIdentifier::new(btc.to_bytes()),
+            PriceIdentifier::new(eth.to_bytes()),
+            PriceIdentifier::new(sol.to_bytes()),
+        );
+        harness.permit(&[btc, eth, sol]).await;
+        let published = |harness: &Harness| {
+            let mut published = harness.published();
+            for (_, prices) in &mut published {
+                prices.sort_by_key(|(identifier, ..)| identifier.to_bytes());
+            }
+            published
+        };
+        let sorted = |mut prices: Vec<(PriceIdentifier, i64, u64)>| {
+            prices.sort_by_key(|(identifier, ..)| identifier.to_bytes());
+            prices
+        };
+
+        // The confidence intervals below their floors are raised to them in
+        // the published prices, and those of the prices which are not
+        // trading are left as they are
+        let timestamp = Utc::now().timestamp();
+        let at = |timestamp, info| PriceInfo { timestamp, ..info };
+        harness.update(btc, at(timestamp, price_info(100, 1)));
+        harness.update(eth, at(timestamp, price_info(100, 7)));
+        harness.update(
+            sol,
+            PriceInfo {
+                status: PriceStatus::Halted,
+                ..at(timestamp, price_info(100, 1))
+            },
+        );
+        harness.exporter.publish_updates().await.unwrap();
+        assert_eq!(
+            published(&harness),
+            vec![(
+                harness.publish_key(),
+                sorted(vec![(btc, 100, 10), (eth, 100, 7), (sol, 100, 1)])
+            )]
+        );
+
+        // Rejected updates are not published
+        harness.exporter.config.conf_floor.as_mut().unwrap().action = ConfFloorAction::Reject;
+        for (identifier, conf) in [(btc, 1), (eth, 8)] {
+            harness.update(identifier, at(timestamp + 1, price_info(200, conf)));
+        }
+        harness.exporter.publish_updates().await.unwrap();
+        assert_eq!(
+            published(&harness),
+            vec![(harness.publish_key(), vec![(eth, 200, 8)])]
+        );
+    }
+
+    #[test]
+    fn test_retries_escalate_the_compute_unit_price_up_to_the_cap() {
+        let config = RetryConfig {
+            initial_compute_unit_price_micro_lamports: 1000,
+            fee_multiplier: 2.0,
+            max_compute_unit_price_micro_lamports: 5000,
+            ..Default::default()
+        };
+
+        // The first attempt offers the base price, if any
+        assert_eq!(config.compute_unit_price(None, 1), None);
+        assert_eq!(config.compute_unit_price(Some(300), 1), Some(300));
+
+        // Retries escalate from the base price, or the initial one without it
+        assert_eq!(config.compute_unit_price(Some(300), 2), Some(600));
+        assert_eq!(config.compute_unit_price(Some(300), 3), Some(1200));
+        assert_eq!(config.compute_unit_price(None, 2), Some(2000));
+        assert_eq!(config.compute_unit_price(None, 3), Some(4000));
+        assert_eq!(config.compute_unit_price(None, 4), Some(5000));
+        assert_eq!(config.compute_unit_price(None, u32::MAX), Some(5000));
+    }
+
+    #[test]
+    fn test_retries_never_offer_less_than_the_base_price() {
+        let config = |fee_multiplier| RetryConfig {
+            initial_compute_unit_price_micro_lamports: 1000,
+            fee_multiplier,
+            ..Default::default()
+        };
+        assert_eq!(config(1.0).compute_unit_price(None, 3), Some(1000));
+        assert_eq!(config(0.5).compute_unit_price(None, 3), Some(1000));
+        assert_eq!(config(0.0).compute_unit_price(Some(300), 2), Some(300));
+        assert_eq!(config(f64::NAN).compute_unit_price(None, 2), Some(1000));
+        assert_eq!(
+            config(f64::INFINITY).compute_unit_price(None, 2),
+            Some(RetryConfig::default().max_compute_unit_price_micro_lamports)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_retries_skip_the_prices_published_again_since() {
+        let mut harness = Harness::new(Config {
+            retry: Some(RetryConfig::default()),
+            ..Default::default()
+        })
+        .await;
+        let (btc, eth) = (
+            PriceIdentifier::new(Pubkey::new_unique().to_bytes()),
+            PriceIdentifier::new(Pubkey::new_unique().to_bytes()),
+        );
+        harness.permit(&[btc, eth]).await;
+        for identifier in [btc, eth] {
+            harness.update(
+                identifier,
+                PriceInfo {
+                    timestamp: Utc::now().timestamp() - 2,
+                    ..price_info(10, 1)
+                },
+            );
+        }
+        harness.exporter.publish_updates().await.unwrap();
+        harness.published();
+        let unlanded = harness.inflight_transactions_rx.try_recv().unwrap().batch;
+        assert_eq!(unlanded.prices.len(), 2);
+
+        // ETH is published again with a newer update before the batch is retried
+        harness.update(eth, price_info(20, 1));
+        harness.exporter.publish_updates().await.unwrap();
+        assert_eq!(
+            harness.published(),
+            vec![(harness.publish_key(), vec![(eth, 20, 1)])]
+        );
+        harness.inflight_transactions_rx.try_recv().unwrap();
+
+        // So only BTC is published again by the retry
+        harness.exporter.retry_batch(unlanded).await.unwrap();
+        assert_eq!(
+            harness.published(),
+            vec![(harness.publish_key(), vec![(btc, 10, 1)])]
+        );
+        let retried = harness.inflight_transactions_rx.try_recv().unwrap().batch;
+        assert_eq!(retried.attempt, 2);
+    }
+
+    #[tokio::test]
+    async fn test_rotated_keypair_waits_for_the_transactions_of_the_old_one() {
+        let mut harness = Harness::new(Config::default()).await;
+        let old_key = harness.publish_key();
+        let identifier = PriceIdentifier::new(Pubkey::new_unique().to_bytes());
+        harness.update(
+            identifier,
+            PriceInfo {
+                timestamp: Utc::now().timestamp() - 1,
+                ..price_info(10, 1)
+            },
+        );
+
+        // Rotate the keypair while a transaction of the old one is in flight
+        let new_keypair = Keypair::new();
+        let new_key = new_keypair.pubkey();
+        harness
+            .publisher_permissions_tx
+            .send(HashMap::from([
+                (
+                    old_key,
+                    HashSet::from([Pubkey::new(&identifier.to_bytes())]),
+                ),
+                (
+                    new_key,
+                    HashSet::from([Pubkey::new(&identifier.to_bytes())]),
+                ),
+            ]))
+            .await
+            .unwrap();
+        harness.exporter.publish_updates().await.unwrap();
+        assert_eq!(
+            harness.published(),
+            vec![(old_key, vec![(identifier, 10, 1)])]
+        );
+        let signature = harness
+            .inflight_transactions_rx
+            .try_recv()
+            .unwrap()
+            .signature;
+
+        let root_path = env::temp_dir();
+        let publish_keypair_path = format!("publish-keypair-{}.json", new_key);
+        write_keypair_file(&new_keypair, root_path.join(&publish_keypair_path)).unwrap();
+        harness
+            .key_store_config_tx
+            .send(key_store::Config {
+                root_path,
+                publish_keypair_path: publish_keypair_path.into(),
+                ..Default::default()
+            })
+            .unwrap();
+        harness.exporter.reload_publish_keypair();
+        assert_eq!(harness.publish_key(), old_key);
+
+        // Publishing is held back until it lands
+        harness.update(identifier, price_info(20, 1));
+        harness.exporter.publish_updates().await.unwrap();
+        assert!(harness.published().is_empty());
+        harness
+            .destination
+            .statuses
+            .lock()
+            .insert(signature, TransactionStatus::Pending);
+        harness.exporter.publish_updates().await.unwrap();
+        assert!(harness.published().is_empty());
+
+        // Then the new keypair publishes the latest update
+        harness
+            .destination
+            .statuses
+            .lock()
+            .insert(signature, TransactionStatus::Confirmed);
+        harness.exporter.publish_updates().await.unwrap();
+        assert_eq!(harness.publish_key(), new_key);
+        assert_eq!(
+            harness.published(),
+            vec![(new_key, vec![(identifier, 20, 1)])]
+        );
+    }
+
+    #[test]
+    fn test_submit_prices_instruction_data() {
+        let publish_key = Pubkey::new_unique();
+        let price_store_key = Pubkey::new_unique();
+        let buffer_key = Pubkey::new_unique();
+        let prices = [
+            BufferedPrice::new(5, &price_info(42, 3)).unwrap(),
+            BufferedPrice::new(
+                (1 << 28) - 1,
+                &PriceInfo {
+                    status: PriceStatus::Halted,
+                    ..price_info(-7, 1)
+                },
+            )
+            .unwrap(),
+        ];
+        // Feed indexes beyond 28 bits can't be packed with the status
+        assert!(BufferedPrice::new(1 << 28, &price_info(42, 3)).is_none());
+
+        let instruction = SolanaExporter::create_submit_prices_instruction(
+            publish_key,
+            price_store_key,
+            buffer_key,
+            &prices,
+        )
+        .unwrap();
+
+        let (publisher_config_key, bump) = Pubkey::find_program_address(
+            &[PRICE_STORE_PUBLISHER_CONFIG_SEED, &publish_key.to_bytes()],
+            &price_store_key,
+        );
+        assert_eq!(instruction.program_id, price_store_key);
+        assert_eq!(
+            instruction
+                .accounts
+                .iter()
+                .map(|account| (account.pubkey, account.is_signer, account.is_writable))
+                .collect::<Vec<_>>(),
+            vec![
+                (publish_key, true, true),
+                (publisher_config_key, false, false),
+                (buffer_key, false, true),
+            ]
+        );
+        let mut data = vec![1, bump];
+        // Trading (1) in the high 4 bits, feed index 5 in the low 28 bits
+        data.extend([0x05, 0x00, 0x00, 0x10]);
+        data.extend([42, 0, 0, 0, 0, 0, 0, 0]);
+        data.extend([3, 0, 0, 0, 0, 0, 0, 0]);
+        // Halted (2) with the highest feed index
+        data.extend([0xff, 0xff, 0xff, 0x2f]);
+        data.extend([0xf9, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+        data.extend([1, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(instruction.data, data);
+    }
+
+    /// A Solana exporter of a network whose RPC node can't be reached
+    fn solana_exporter(
+        config: Config,
+        program_key: Pubkey,
+        price_store_key: Option<Pubkey>,
+        network_state: NetworkState,
+        snapshot: global::Snapshot,
+        simulated: bool,
+    ) -> SolanaExporter {
+        let (_, config_rx) = watch::channel(config);
+        let (_, network_state_rx) = watch::channel(network_state);
+        SolanaExporter::new(
+            config_rx,
+            "test",
+            "http://127.0.0.1:1",
+            Duration::from_secs(1),
+            &KeyStore {
+                publish_keypair: None,
+                program_key,
+                mapping_key: Pubkey::new_unique(),
+                accumulator_key: None,
+                price_store_key,
+                additional_publish_keypairs: HashMap::new(),
+            },
+            network_state_rx,
+            global::SnapshotReader::new(snapshot),
+            None,
+            simulated,
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_prices_without_a_feed_index_are_published_with_upd_price() {
+        let (migrated, unmigrated) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut snapshot = global::Snapshot::default();
+        for (price_key, feed_index) in [(migrated, 7), (unmigrated, 0)] {
+            let mut price_account = PriceEntry::default();
+            price_account.drv4 = feed_index;
+            snapshot
+                .account_data
+                .price_accounts
+                .insert(price_key, Arc::new(price_account));
+        }
+
+        let program_key = Pubkey::new_unique();
+        let price_store_key = Pubkey::new_unique();
+        let buffer_key = Pubkey::new_unique();
+        let solana_exporter = solana_exporter(
+            Config {
+                backend: Backend::PriceStore,
+                ..Default::default()
+            },
+            program_key,
+            Some(price_store_key),
+            NetworkState::default(),
+            snapshot,
+            // Without a blockhash
+            true,
+        );
+        let keypair = Keypair::new();
+        solana_exporter
+            .publisher_buffer_keys
+            .write()
+            .insert(keypair.pubkey(), buffer_key);
+
+        let prepared = solana_exporter
+            .prepare(&destination::Batch {
+                publisher: None,
+                prices:    &[
+                    (PriceIdentifier::new(migrated.to_bytes()), price_info(10, 1)),
+                    (
+                        PriceIdentifier::new(unmigrated.to_bytes()),
+                        price_info(20, 2),
+                    ),
+                ],
+                signer:    &KeypairSigner::new(&keypair).unwrap(),
+                attempt:   1,
+            })
+            .await
+            .unwrap();
+
+        // The unmigrated price is published with upd_price, and the migrated
+        // one is submitted to the buffer account
+        let message = &prepared.submission.transaction.message;
+        let instructions = message
+            .instructions
+            .iter()
+            .map(|instruction| {
+                (
+                    message.account_keys[instruction.program_id_index as usize],
+                    instruction
+                        .accounts
+                        .iter()
+                        .map(|index| message.account_keys[*index as usize])
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .filter(|(program_id, _)| [program_key, price_store_key].contains(program_id))
+            .collect::<Vec<_>>();
+        assert_eq!(instructions.len(), 2);
+        assert_eq!(instructions[0].0, program_key);
+        assert_eq!(instructions[0].1[1], unmigrated);
+        assert_eq!(instructions[1].0, price_store_key);
+        assert_eq!(instructions[1].1[2], buffer_key);
+        assert_eq!(prepared.submission.update_count, 2);
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_transactions_are_not_sent() {
+        let solana_exporter = solana_exporter(
+            Config {
+                dry_run: true,
+                ..Default::default()
+            },
+            Pubkey::new_unique(),
+            None,
+            NetworkState {
+                blockhash:            Hash::new_unique(),
+                current_slot:         1,
+                blockhash_fetched_at: Some(Instant::now()),
+            },
+            global::Snapshot::default(),
+            false,
+        );
+        let keypair = Keypair::new();
+        let prepared = solana_exporter
+            .prepare(&destination::Batch {
+                publisher: None,
+                prices:    &[(
+                    PriceIdentifier::new(Pubkey::new_unique().to_bytes()),
+                    price_info(10, 1),
+                )],
+                signer:    &KeypairSigner::new(&keypair).unwrap(),
+                attempt:   1,
+            })
+            .await
+            .unwrap();
+
+        // Sending would fail, as the RPC node can't be reached
+        assert!(!solana_exporter.submit(&prepared).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_batches_not_sent_are_not_monitored() {
+        let mut harness = Harness::new(Config::default()).await;
+        *harness.destination.dry_run.lock() = true;
+        let identifier = PriceIdentifier::new(Pubkey::new_unique().to_bytes());
+        harness.permit(&[identifier]).await;
+        harness.update(identifier, price_info(10, 1));
+
+        harness.exporter.publish_updates().await.unwrap();
+
+        assert_eq!(
+            harness.published(),
+            vec![(harness.publish_key(), vec![(identifier, 10, 1)])]
+        );
+        assert!(harness.inflight_transactions_rx.try_recv().is_err());
+        assert!(harness.exporter.sent_signatures.is_empty());
+    }
+}
@@ -0,0 +1,64 @@
+// The Exporter schedules the publishing of the updates: it conflates them to the latest
+// of each price, filters out the unchanged, stale and unpermissioned ones, batches them,
+// staggers the batches over the publish interval and publishes the batches which did
+// not land again. Where the batches go is up to its destination, which prepares each
+// batch into a submission, submits it and reports whether it was confirmed. Sending
+// transactions to the Oracle or price store program of a Solana network is the only
+// destination so far, and others only have to implement the Exporter trait.
+use {
+    super::super::signer::Signer,
+    crate::agent::store::{
+        local::{
+            PriceInfo,
+            Publisher,
+        },
+        transactions::TransactionStatus,
+        PriceIdentifier,
+    },
+    anyhow::Result,
+    async_trait::async_trait,
+    pyth_sdk::UnixTimestamp,
+    solana_sdk::signature::Signature,
+};
+
+/// A batch of the latest updates of a publisher, none of them stale
+pub struct Batch<'a> {
+    pub publisher: Publisher,
+    pub prices:    &'a [(PriceIdentifier, PriceInfo)],
+    /// Signs the submission on behalf of the publisher
+    pub signer:    &'a dyn Signer,
+    /// Attempt to publish the batch, starting from 1
+    pub attempt:   u32,
+}
+
+/// A batch prepared for submission
+pub struct Prepared<S> {
+    /// Identifies the submission, as the signature of its transaction
+    pub signature:             Signature,
+    /// Prices the submission publishes, with the timestamps of their updates
+    pub prices:                Vec<(PriceIdentifier, UnixTimestamp)>,
+    /// Slot of the network the batch was prepared in
+    pub slot:                  u64,
+    /// Priority fee offered for the submission, if any
+    pub priority_fee_lamports: Option<u64>,
+    /// The submission itself
+    pub submission:            S,
+}
+
+/// Destination of the batches published by the Exporter
+#[async_trait]
+pub trait Exporter: Send + Sync + 'static {
+    /// What a batch is prepared into, e.g. a signed transaction
+    type Submission: Send + Sync;
+
+    /// Prepare the batch for submission, signing it
+    async fn prepare(&self, batch: &Batch<'_>) -> Result<Prepared<Self::Submission>>;
+
+    /// Submit the prepared batch, returning whether it was actually sent, which
+    /// it is not in a dry run
+    async fn submit(&self, prepared: &Prepared<Self::Submission>) -> Result<bool>;
+
+    /// The statuses of the submissions with the signatures, None for those the
+    /// destination has not seen yet, and pending for those not settled yet
+    async fn confirm(&self, signatures: &[Signature]) -> Result<Vec<Option<TransactionStatus>>>;
+}
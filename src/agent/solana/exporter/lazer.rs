@@ -0,0 +1,718 @@
+// Pyth Lazer is fed by the publishers directly rather than through a Solana program.
+// When configured, the Exporter of a network publishes the updates of the configured
+// feeds to the Lazer relayers instead of sending transactions, while the Oracle still
+// reads the network for the symbols and permissions of the prices.
+//
+// The updates are published with the Lazer publisher protocol: each batch is a
+// protobuf `LazerTransaction` holding the `PublisherUpdate` of its feeds, signed with
+// the ed25519 publish key and wrapped in a `SignedLazerTransaction`, which is sent as
+// a binary message over a websocket connection to every relayer, authenticated with
+// the access token as bearer token. The relayers do not answer the transactions, but
+// they answer the pings in order, so a relayer has received a transaction once it
+// answered a ping sent after it. Only the transactions received by a relayer this way
+// are reported as confirmed.
+use {
+    super::{
+        destination,
+        Config as ExporterConfig,
+    },
+    crate::agent::{
+        dump::redacted,
+        metrics::DRY_RUN_METRICS,
+        store::{
+            global,
+            local::PriceInfo,
+            transactions::TransactionStatus,
+        },
+    },
+    anyhow::{
+        anyhow,
+        bail,
+        Context as _,
+        Result,
+    },
+    async_trait::async_trait,
+    futures_util::{
+        future::join_all,
+        SinkExt,
+        StreamExt,
+    },
+    parking_lot::Mutex,
+    protobuf::{
+        well_known_types::timestamp::Timestamp,
+        Message as _,
+        MessageField,
+    },
+    pyth_lazer_publisher_sdk::{
+        publisher_update::{
+            feed_update,
+            FeedUpdate,
+            PriceUpdate,
+            PublisherUpdate,
+        },
+        transaction::{
+            lazer_transaction,
+            signature_data,
+            Ed25519SignatureData,
+            LazerTransaction,
+            SignatureData,
+            SignedLazerTransaction,
+        },
+    },
+    pyth_sdk_solana::state::PriceStatus,
+    serde::{
+        Deserialize,
+        Serialize,
+    },
+    solana_sdk::signature::Signature,
+    std::{
+        collections::{
+            HashMap,
+            HashSet,
+            VecDeque,
+        },
+        fmt,
+        sync::atomic::{
+            AtomicU64,
+            Ordering,
+        },
+        time::{
+            Duration,
+            SystemTime,
+        },
+    },
+    tokio::{
+        net::TcpStream,
+        sync::watch,
+    },
+    tokio_tungstenite::{
+        connect_async,
+        tungstenite::{
+            client::IntoClientRequest,
+            http::{
+                header::AUTHORIZATION,
+                HeaderValue,
+            },
+            Message,
+        },
+        MaybeTlsStream,
+        WebSocketStream,
+    },
+};
+
+/// Number of acknowledged transactions remembered for their confirmation
+const MAX_ACKNOWLEDGED: usize = 10_000;
+
+#[derive(Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Websocket URLs of the relayers, e.g. "wss://<relayer>/v1/transaction".
+    /// Each update is sent to all of them.
+    pub relayer_urls:    Vec<String>,
+    /// Token the relayers authenticate the publisher with. Can be read from a
+    /// file with `access_token_file`.
+    pub access_token:    String,
+    /// Lazer feed ids of the published symbols. The updates of the other
+    /// symbols are not published.
+    pub feeds:           HashMap<String, u32>,
+    /// Timeout of the delivery of an update to a relayer, connecting included
+    #[serde(with = "humantime_serde")]
+    pub request_timeout: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            relayer_urls:    vec![],
+            access_token:    "".to_string(),
+            feeds:           HashMap::new(),
+            request_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+impl fmt::Debug for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Config")
+            .field("relayer_urls", &self.relayer_urls)
+            .field("access_token", &redacted(Some(self.access_token.as_str())))
+            .field("feeds", &self.feeds)
+            .field("request_timeout", &self.request_timeout)
+            .finish()
+    }
+}
+
+/// The websocket connection to a relayer, opened on the first update sent to
+/// it and reopened on the next one after it failed
+struct Relayer {
+    url:        String,
+    connection: tokio::sync::Mutex<Option<WebSocketStream<MaybeTlsStream<TcpStream>>>>,
+}
+
+/// The signatures of the transactions received by a relayer, the most recent
+/// `MAX_ACKNOWLEDGED` of them
+#[derive(Default)]
+struct Acknowledged {
+    order:      VecDeque<Signature>,
+    signatures: HashSet<Signature>,
+}
+
+impl Acknowledged {
+    fn insert(&mut self, signature: Signature) {
+        if !self.signatures.insert(signature) {
+            return;
+        }
+        self.order.push_back(signature);
+        if self.order.len() > MAX_ACKNOWLEDGED {
+            if let Some(oldest) = self.order.pop_front() {
+                self.signatures.remove(&oldest);
+            }
+        }
+    }
+
+    fn contains(&self, signature: &Signature) -> bool {
+        self.signatures.contains(signature)
+    }
+}
+
+/// Publishes the batches to the Pyth Lazer relayers
+pub struct LazerExporter {
+    config:              Config,
+    /// Watched for the dry run setting of the Exporter, and the reloaded feeds
+    exporter_config_rx:  watch::Receiver<ExporterConfig>,
+    /// Name of the network whose Exporter publishes to Lazer
+    network_name:        String,
+    /// Used to resolve the symbols of the prices
+    global_store_reader: global::SnapshotReader,
+    relayers:            Vec<Relayer>,
+    /// Payload of the next ping sent to a relayer
+    next_ping:           AtomicU64,
+    acknowledged:        Mutex<Acknowledged>,
+    /// Whether the updates are logged instead of sent, in simulation
+    simulated:           bool,
+}
+
+impl LazerExporter {
+    pub fn new(
+        config: Config,
+        exporter_config_rx: watch::Receiver<ExporterConfig>,
+        network_name: &str,
+        global_store_reader: global::SnapshotReader,
+        simulated: bool,
+    ) -> Result<Self> {
+        if config.relayer_urls.is_empty() {
+            return Err(anyhow!("exporter.lazer.relayer_urls is empty"));
+        }
+        let relayers = config
+            .relayer_urls
+            .iter()
+            .map(|url| Relayer {
+                url:        url.clone(),
+                connection: tokio::sync::Mutex::new(None),
+            })
+            .collect();
+        Ok(LazerExporter {
+            config,
+            exporter_config_rx,
+            network_name: network_name.to_string(),
+            global_store_reader,
+            relayers,
+            next_ping: AtomicU64::new(0),
+            acknowledged: Mutex::new(Acknowledged::default()),
+            simulated,
+        })
+    }
+
+    fn feed_update(feed_id: u32, price_info: &PriceInfo) -> FeedUpdate {
+        FeedUpdate {
+            feed_id: Some(feed_id),
+            source_timestamp: MessageField::some(Timestamp {
+                seconds: price_info.timestamp.max(0),
+                ..Default::default()
+            }),
+            update: Some(feed_update::Update::PriceUpdate(PriceUpdate {
+                // Not set while the price is not trading
+                price: (price_info.status == PriceStatus::Trading).then(|| price_info.price),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// Send the transaction to the relayer, returning once the relayer
+    /// received it. The connection is dropped if the delivery fails, to be
+    /// reopened by the next one.
+    async fn deliver(&self, relayer: &Relayer, transaction: &[u8]) -> Result<()> {
+        let mut connection = relayer.connection.lock().await;
+        let result = tokio::time::timeout(
+            self.config.request_timeout,
+            self.deliver_over(&relayer.url, &mut connection, transaction),
+        )
+        .await
+        .unwrap_or_else(|_| Err(anyhow!("timed out")));
+        if result.is_err() {
+            *connection = None;
+        }
+        result
+    }
+
+    async fn deliver_over(
+        &self,
+        relayer_url: &str,
+        connection: &mut Option<WebSocketStream<MaybeTlsStream<TcpStream>>>,
+        transaction: &[u8],
+    ) -> Result<()> {
+        if connection.is_none() {
+            let mut request = relayer_url.into_client_request()?;
+            request.headers_mut().insert(
+                AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {}", self.config.access_token))?,
+            );
+            let (stream, _) = connect_async(request)
+                .await
+                .context("connecting to the relayer")?;
+            *connection = Some(stream);
+        }
+        let stream = connection.as_mut().context("not connected")?;
+
+        stream.send(Message::Binary(transaction.to_vec())).await?;
+        let ping = self
+            .next_ping
+            .fetch_add(1, Ordering::Relaxed)
+            .to_le_bytes()
+            .to_vec();
+        stream.send(Message::Ping(ping.clone())).await?;
+        while let Some(message) = stream.next().await {
+            match message? {
+                Message::Pong(payload) if payload == ping => return Ok(()),
+                Message::Text(text) => {
+                    warn!(%relayer_url, "Exporter: Lazer relayer: {}", text)
+                }
+                Message::Close(frame) => bail!("closed by the relayer: {:?}", frame),
+                _ => {}
+            }
+        }
+        bail!("closed by the relayer")
+    }
+}
+
+#[async_trait]
+impl destination::Exporter for LazerExporter {
+    /// The protobuf-encoded `SignedLazerTransaction`
+    type Submission = Vec<u8>;
+
+    async fn prepare(
+        &self,
+        batch: &destination::Batch<'_>,
+    ) -> Result<destination::Prepared<Vec<u8>>> {
+        // The feeds are reloaded along with the config of the Exporter
+        let feeds = self
+            .exporter_config_rx
+            .borrow()
+            .lazer
+            .as_ref()
+            .map_or_else(|| self.config.feeds.clone(), |lazer| lazer.feeds.clone());
+        let snapshot = self.global_store_reader.load();
+        let symbol_index = &snapshot.account_metadata.symbol_index;
+        let mut prices = vec![];
+        let mut updates = vec![];
+        for (identifier, price_info) in batch.prices {
+            let feed_id = match symbol_index
+                .symbol_of_identifier(identifier)
+                .and_then(|symbol| feeds.get(symbol))
+            {
+                Some(feed_id) => *feed_id,
+                None => continue,
+            };
+            prices.push((*identifier, price_info.timestamp));
+            updates.push(Self::feed_update(feed_id, price_info));
+        }
+        // Nothing is signed when none of the prices has a Lazer feed
+        if updates.is_empty() {
+            return Ok(destination::Prepared {
+                signature: Signature::default(),
+                prices,
+                slot: 0,
+                priority_fee_lamports: None,
+                submission: vec![],
+            });
+        }
+
+        let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?;
+        let payload = LazerTransaction {
+            payload: Some(lazer_transaction::Payload::PublisherUpdate(
+                PublisherUpdate {
+                    updates,
+                    publisher_timestamp: MessageField::some(Timestamp {
+                        seconds: now.as_secs() as i64,
+                        nanos: now.subsec_nanos() as i32,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+            )),
+            ..Default::default()
+        }
+        .write_to_bytes()?;
+        let signature = batch.signer.sign_message(&payload).await?;
+        let submission = SignedLazerTransaction {
+            signature_data: MessageField::some(SignatureData {
+                data: Some(signature_data::Data::Ed25519(Ed25519SignatureData {
+                    signature: Some(signature.as_ref().to_vec()),
+                    public_key: Some(batch.signer.pubkey().to_bytes().to_vec()),
+                    ..Default::default()
+                })),
+                ..Default::default()
+            }),
+            payload: Some(payload),
+            ..Default::default()
+        }
+        .write_to_bytes()?;
+
+        Ok(destination::Prepared {
+            signature,
+            prices,
+            // Lazer has no slots
+            slot: 0,
+            priority_fee_lamports: None,
+            submission,
+        })
+    }
+
+    async fn submit(&self, prepared: &destination::Prepared<Vec<u8>>) -> Result<bool> {
+        if prepared.prices.is_empty() {
+            return Ok(false);
+        }
+        if self.simulated {
+            info!(
+                signature = %prepared.signature,
+                updates = prepared.prices.len(),
+                "simulation: Lazer update not sent"
+            );
+            return Ok(false);
+        }
+        if self.exporter_config_rx.borrow().dry_run {
+            info!(
+                signature = %prepared.signature,
+                updates = prepared.prices.len(),
+                "Exporter: dry run, Lazer update not sent"
+            );
+            DRY_RUN_METRICS.record(&self.network_name, prepared.prices.len());
+            return Ok(false);
+        }
+
+        // The update is published as long as any relayer received it
+        let results = join_all(
+            self.relayers
+                .iter()
+                .map(|relayer| self.deliver(relayer, &prepared.submission)),
+        )
+        .await;
+        let mut received = false;
+        let mut last_err = None;
+        for (relayer, result) in self.relayers.iter().zip(results) {
+            match result {
+                Ok(()) => received = true,
+                Err(err) => {
+                    warn!(relayer_url = %relayer.url, "Exporter: Lazer relayer did not receive the update: {:#}", err);
+                    last_err = Some(err);
+                }
+            }
+        }
+        if received {
+            self.acknowledged.lock().insert(prepared.signature);
+            debug!(
+                signature = %prepared.signature,
+                updates = prepared.prices.len(),
+                "sent Lazer update"
+            );
+            return Ok(true);
+        }
+        Err(last_err
+            .unwrap_or_else(|| anyhow!("no relayer"))
+            .context("no Lazer relayer received the update"))
+    }
+
+    async fn confirm(&self, signatures: &[Signature]) -> Result<Vec<Option<TransactionStatus>>> {
+        // The updates not received by any relayer are never confirmed, and
+        // expire as the transactions which did not land
+        let acknowledged = self.acknowledged.lock();
+        Ok(signatures
+            .iter()
+            .map(|signature| {
+                acknowledged
+                    .contains(signature)
+                    .then(|| TransactionStatus::Confirmed)
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            Config,
+            LazerExporter,
+        },
+        crate::agent::{
+            solana::{
+                exporter::destination::{
+                    self,
+                    Exporter as _,
+                },
+                signer::KeypairSigner,
+            },
+            store::{
+                global::{
+                    self,
+                    ProductAccountMetadata,
+                },
+                local::PriceInfo,
+                transactions::TransactionStatus,
+                PriceIdentifier,
+            },
+        },
+        futures_util::StreamExt,
+        protobuf::Message as _,
+        pyth_lazer_publisher_sdk::{
+            publisher_update::feed_update,
+            transaction::{
+                lazer_transaction,
+                signature_data,
+                LazerTransaction,
+                SignedLazerTransaction,
+            },
+        },
+        pyth_sdk_solana::state::PriceStatus,
+        solana_sdk::{
+            pubkey::Pubkey,
+            signature::{
+                Keypair,
+                Signature,
+            },
+            signer::Signer as _,
+        },
+        tokio::{
+            net::TcpListener,
+            sync::{
+                mpsc,
+                watch,
+            },
+        },
+        tokio_tungstenite::tungstenite::{
+            handshake::server::{
+                Request,
+                Response,
+            },
+            Message,
+        },
+    };
+
+    const BTC: &str = "Crypto.BTC/USD";
+
+    /// A snapshot with two prices, only the first of which has a Lazer feed
+    fn snapshot() -> (global::Snapshot, Pubkey, Pubkey) {
+        let mut snapshot = global::Snapshot::default();
+        let (published, unpublished) = (Pubkey::new_unique(), Pubkey::new_unique());
+        for (symbol, price_key) in [(BTC, published), ("Crypto.ETH/USD", unpublished)] {
+            snapshot.account_metadata.insert_product(
+                Pubkey::new_unique(),
+                ProductAccountMetadata {
+                    attr_dict:      [("symbol".to_string(), symbol.to_string())]
+                        .into_iter()
+                        .collect(),
+                    price_accounts: vec![price_key],
+                },
+            );
+        }
+        (snapshot, published, unpublished)
+    }
+
+    fn lazer_exporter(relayer_url: &str, snapshot: global::Snapshot) -> LazerExporter {
+        let (_, exporter_config_rx) = watch::channel(Default::default());
+        LazerExporter::new(
+            Config {
+                relayer_urls: vec![relayer_url.to_string()],
+                access_token: "lazer-token".to_string(),
+                feeds: [(BTC.to_string(), 1)].into_iter().collect(),
+                ..Default::default()
+            },
+            exporter_config_rx,
+            "primary",
+            global::SnapshotReader::new(snapshot),
+            false,
+        )
+        .unwrap()
+    }
+
+    fn price_info() -> PriceInfo {
+        PriceInfo {
+            status:    PriceStatus::Trading,
+            price:     42,
+            conf:      1,
+            timestamp: 100,
+        }
+    }
+
+    /// A relayer accepting a single connection, forwarding its authorization
+    /// header and the transactions it receives. The pings are answered while
+    /// the messages are read.
+    async fn relayer() -> (String, mpsc::UnboundedReceiver<Vec<u8>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}/v1/transaction", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let authorization_tx = tx.clone();
+            let mut websocket = tokio_tungstenite::accept_hdr_async(
+                stream,
+                move |request: &Request, response: Response| {
+                    let authorization = request.headers()["authorization"].as_bytes().to_vec();
+                    authorization_tx.send(authorization).unwrap();
+                    Ok(response)
+                },
+            )
+            .await
+            .unwrap();
+            while let Some(Ok(message)) = websocket.next().await {
+                if let Message::Binary(transaction) = message {
+                    let _ = tx.send(transaction);
+                }
+            }
+        });
+        (url, rx)
+    }
+
+    #[tokio::test]
+    async fn test_batch_is_signed_for_the_configured_feeds() {
+        let (snapshot, published, unpublished) = snapshot();
+        let lazer_exporter = lazer_exporter("ws://127.0.0.1:1/v1/transaction", snapshot);
+
+        let keypair = Keypair::new();
+        let signer = KeypairSigner::new(&keypair).unwrap();
+        let prepared = lazer_exporter
+            .prepare(&destination::Batch {
+                publisher: None,
+                prices:    &[
+                    (PriceIdentifier::new(published.to_bytes()), price_info()),
+                    (PriceIdentifier::new(unpublished.to_bytes()), price_info()),
+                ],
+                signer:    &signer,
+                attempt:   1,
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(
+            prepared.prices,
+            vec![(PriceIdentifier::new(published.to_bytes()), 100)]
+        );
+        let signed_transaction =
+            SignedLazerTransaction::parse_from_bytes(&prepared.submission).unwrap();
+        let payload = signed_transaction.payload.unwrap();
+        let ed25519 = match signed_transaction
+            .signature_data
+            .into_option()
+            .unwrap()
+            .data
+        {
+            Some(signature_data::Data::Ed25519(ed25519)) => ed25519,
+            data => panic!("unexpected signature data: {:?}", data),
+        };
+        assert_eq!(ed25519.public_key.unwrap(), keypair.pubkey().to_bytes());
+        let signature = Signature::new(&ed25519.signature.unwrap());
+        assert_eq!(signature, prepared.signature);
+        assert!(signature.verify(keypair.pubkey().as_ref(), &payload));
+
+        let publisher_update = match LazerTransaction::parse_from_bytes(&payload)
+            .unwrap()
+            .payload
+        {
+            Some(lazer_transaction::Payload::PublisherUpdate(publisher_update)) => publisher_update,
+            payload => panic!("unexpected payload: {:?}", payload),
+        };
+        assert_eq!(publisher_update.updates.len(), 1);
+        let feed_update = &publisher_update.updates[0];
+        assert_eq!(feed_update.feed_id, Some(1));
+        assert_eq!(feed_update.source_timestamp.seconds, 100);
+        match &feed_update.update {
+            Some(feed_update::Update::PriceUpdate(price_update)) => {
+                assert_eq!(price_update.price, Some(42))
+            }
+            update => panic!("unexpected update: {:?}", update),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_only_the_updates_received_by_a_relayer_are_confirmed() {
+        let (relayer_url, mut relayer_rx) = relayer().await;
+        let (snapshot, published, _) = snapshot();
+        let lazer_exporter = lazer_exporter(&relayer_url, snapshot);
+
+        let keypair = Keypair::new();
+        let signer = KeypairSigner::new(&keypair).unwrap();
+        let prepared = lazer_exporter
+            .prepare(&destination::Batch {
+                publisher: None,
+                prices:    &[(PriceIdentifier::new(published.to_bytes()), price_info())],
+                signer:    &signer,
+                attempt:   1,
+            })
+            .await
+            .unwrap();
+
+        let unsent = Signature::new_unique();
+        assert_eq!(
+            lazer_exporter
+                .confirm(&[prepared.signature, unsent])
+                .await
+                .unwrap(),
+            vec![None, None]
+        );
+
+        assert!(lazer_exporter.submit(&prepared).await.unwrap());
+        assert_eq!(relayer_rx.recv().await.unwrap(), b"Bearer lazer-token");
+        assert_eq!(relayer_rx.recv().await.unwrap(), prepared.submission);
+        assert_eq!(
+            lazer_exporter
+                .confirm(&[prepared.signature, unsent])
+                .await
+                .unwrap(),
+            vec![Some(TransactionStatus::Confirmed), None]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_update_not_received_by_any_relayer_is_not_confirmed() {
+        let (snapshot, published, _) = snapshot();
+        let lazer_exporter = lazer_exporter("ws://127.0.0.1:1/v1/transaction", snapshot);
+
+        let keypair = Keypair::new();
+        let signer = KeypairSigner::new(&keypair).unwrap();
+        let prepared = lazer_exporter
+            .prepare(&destination::Batch {
+                publisher: None,
+                prices:    &[(PriceIdentifier::new(published.to_bytes()), price_info())],
+                signer:    &signer,
+                attempt:   1,
+            })
+            .await
+            .unwrap();
+
+        assert!(lazer_exporter.submit(&prepared).await.is_err());
+        assert_eq!(
+            lazer_exporter.confirm(&[prepared.signature]).await.unwrap(),
+            vec![None]
+        );
+    }
+
+    #[test]
+    fn test_access_token_is_redacted() {
+        let config = Config {
+            access_token: "lazer-token".to_string(),
+            ..Default::default()
+        };
+        assert!(!format!("{:?}", config).contains("lazer-token"));
+    }
+}
@@ -0,0 +1,243 @@
+// Publishing on the ticks of the publish interval lands the updates at arbitrary points
+// within a slot, and those sent late in a slot are often only included in the next one.
+// With slot alignment, the Slot Clock subscribes to the slots over the network's
+// websocket endpoint and keeps track of when the latest slot started and of how long
+// the slots last, and the Exporter delays each tick of the publish interval until the
+// configured offset into the next slot. The Exporter publishes on the unaligned ticks
+// while the slots are not notified, e.g. while the subscription is down.
+use {
+    anyhow::{
+        anyhow,
+        Context as _,
+        Result,
+    },
+    futures_util::StreamExt,
+    serde::{
+        Deserialize,
+        Serialize,
+    },
+    solana_client::nonblocking::pubsub_client::PubsubClient,
+    std::time::{
+        Duration,
+        Instant,
+    },
+    tokio::{
+        sync::watch,
+        time,
+    },
+};
+
+/// Delay before subscribing to the slots again after the subscription failed
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(10);
+
+/// Weight of the latest slot in the estimate of the slot duration
+const SLOT_DURATION_SMOOTHING: f64 = 0.1;
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Offset into the slot at which the updates are published
+    #[serde(with = "humantime_serde")]
+    pub offset:                Duration,
+    /// Duration of the slots until it is estimated from the notified slots
+    #[serde(with = "humantime_serde")]
+    pub initial_slot_duration: Duration,
+    /// The ticks are not aligned once no slot was notified for this many slot
+    /// durations
+    pub max_missed_slots:      u32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            offset:                Duration::from_millis(50),
+            initial_slot_duration: Duration::from_millis(400),
+            max_missed_slots:      10,
+        }
+    }
+}
+
+/// Start of the latest slot notified, and the estimated duration of the slots
+#[derive(Clone, Copy, Debug)]
+pub struct SlotBoundary {
+    pub slot:          u64,
+    pub started_at:    Instant,
+    pub slot_duration: Duration,
+}
+
+impl SlotBoundary {
+    /// The first time, from now on, which is the offset into a slot, None if
+    /// no slot was notified for too long to extrapolate the boundaries
+    pub fn next_publish_time(&self, config: &Config, now: Instant) -> Option<Instant> {
+        let first = self.started_at + config.offset;
+        if now <= first {
+            return Some(first);
+        }
+        let slot_duration = self.slot_duration.max(Duration::from_millis(1));
+        let elapsed = now - self.started_at;
+        if elapsed > slot_duration * config.max_missed_slots {
+            return None;
+        }
+        let slots = ((now - first).as_secs_f64() / slot_duration.as_secs_f64()).ceil();
+        Some(first + slot_duration.mul_f64(slots))
+    }
+}
+
+/// Follows the slots of the network, sending the start of each one on the
+/// watch channel
+pub struct SlotClock {
+    network_name:     String,
+    wss_url:          String,
+    config:           Config,
+    slot_boundary_tx: watch::Sender<Option<SlotBoundary>>,
+}
+
+impl SlotClock {
+    pub fn new(
+        network_name: &str,
+        wss_url: &str,
+        config: Config,
+        slot_boundary_tx: watch::Sender<Option<SlotBoundary>>,
+    ) -> Self {
+        SlotClock {
+            network_name: network_name.to_string(),
+            wss_url: wss_url.to_string(),
+            config,
+            slot_boundary_tx,
+        }
+    }
+
+    pub async fn run(&mut self) {
+        loop {
+            if let Err(err) = self.follow_slots().await {
+                error!(
+                    error = ?err,
+                    network = %self.network_name,
+                    "Slot clock: slot subscription failed, publishing unaligned: {:#}",
+                    err
+                );
+            }
+            time::sleep(RESUBSCRIBE_DELAY).await;
+        }
+    }
+
+    /// Record the start of every new slot until the subscription ends
+    async fn follow_slots(&mut self) -> Result<()> {
+        let pubsub_client = PubsubClient::new(&self.wss_url)
+            .await
+            .context("connecting to the websocket endpoint")?;
+        let (mut slots, unsubscribe) = pubsub_client
+            .slot_subscribe()
+            .await
+            .context("subscribing to the slots")?;
+        while let Some(slot_info) = slots.next().await {
+            let previous = *self.slot_boundary_tx.borrow();
+            let boundary = observe_slot(
+                previous,
+                slot_info.slot,
+                Instant::now(),
+                self.config.initial_slot_duration,
+            );
+            if let Some(boundary) = boundary {
+                self.slot_boundary_tx.send_replace(Some(boundary));
+            }
+        }
+        unsubscribe().await;
+        Err(anyhow!("slot subscription closed"))
+    }
+}
+
+/// The boundary of a newly notified slot, None if it is not newer than the
+/// previous one. The slot duration is smoothed over the consecutive slots.
+fn observe_slot(
+    previous: Option<SlotBoundary>,
+    slot: u64,
+    now: Instant,
+    initial_slot_duration: Duration,
+) -> Option<SlotBoundary> {
+    let previous = match previous {
+        Some(previous) if slot <= previous.slot => return None,
+        Some(previous) => previous,
+        None => {
+            return Some(SlotBoundary {
+                slot,
+                started_at: now,
+                slot_duration: initial_slot_duration,
+            })
+        }
+    };
+    let slots = slot - previous.slot;
+    let measured = (now - previous.started_at).div_f64(slots as f64);
+    let slot_duration = if slots == 1 {
+        previous
+            .slot_duration
+            .mul_f64(1.0 - SLOT_DURATION_SMOOTHING)
+            + measured.mul_f64(SLOT_DURATION_SMOOTHING)
+    } else {
+        // Slots were skipped or missed, e.g. across a reconnection
+        previous.slot_duration
+    };
+    Some(SlotBoundary {
+        slot,
+        started_at: now,
+        slot_duration,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            observe_slot,
+            Config,
+        },
+        std::time::{
+            Duration,
+            Instant,
+        },
+    };
+
+    #[test]
+    fn test_publish_time_is_the_offset_into_the_next_slot() {
+        let config = Config {
+            offset:                Duration::from_millis(50),
+            initial_slot_duration: Duration::from_millis(400),
+            max_missed_slots:      10,
+        };
+        let start = Instant::now();
+        let boundary = observe_slot(None, 100, start, config.initial_slot_duration).unwrap();
+
+        // Within the offset of the slot, publish at the offset
+        assert_eq!(
+            boundary.next_publish_time(&config, start + Duration::from_millis(20)),
+            Some(start + Duration::from_millis(50))
+        );
+        // Past the offset, publish at the offset into the next slot
+        assert_eq!(
+            boundary.next_publish_time(&config, start + Duration::from_millis(60)),
+            Some(start + Duration::from_millis(450))
+        );
+        assert_eq!(
+            boundary.next_publish_time(&config, start + Duration::from_millis(900)),
+            Some(start + Duration::from_millis(1250))
+        );
+        // The boundaries are not extrapolated once the slots stopped being notified
+        assert_eq!(
+            boundary.next_publish_time(&config, start + Duration::from_secs(5)),
+            None
+        );
+
+        // Older slots are ignored, and the slot duration follows the new slots
+        assert!(observe_slot(Some(boundary), 99, start, config.initial_slot_duration).is_none());
+        let next = observe_slot(
+            Some(boundary),
+            101,
+            start + Duration::from_millis(500),
+            config.initial_slot_duration,
+        )
+        .unwrap();
+        // 90% of the previous estimate, 10% of the measured 500ms
+        assert!(next.slot_duration > Duration::from_millis(409));
+        assert!(next.slot_duration < Duration::from_millis(411));
+    }
+}
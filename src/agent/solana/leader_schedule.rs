@@ -0,0 +1,298 @@
+// The Leader Tracker follows the leader schedule and the slot progress of a network, so
+// that the Exporter can time its transactions around leader rotations. A transaction
+// sent during the last slot of a leader is usually forwarded to it too late, and then
+// has to be retried by the RPC node, so the Exporter holds it back until the next
+// leader's first slot. The transactions are also sent to the TPUs of the upcoming
+// leaders directly, in addition to the RPC node.
+use {
+    super::instrumented_rpc,
+    anyhow::{
+        Context,
+        Result,
+    },
+    serde::{
+        Deserialize,
+        Serialize,
+    },
+    solana_client::nonblocking::rpc_client::RpcClient,
+    solana_sdk::{
+        clock::Slot,
+        commitment_config::CommitmentConfig,
+        pubkey::Pubkey,
+    },
+    std::{
+        collections::HashMap,
+        net::SocketAddr,
+        str::FromStr,
+        sync::Arc,
+        time::Duration,
+    },
+    tokio::{
+        sync::watch,
+        time::{
+            self,
+            Instant,
+        },
+    },
+};
+
+/// Expected duration of a slot
+const SLOT_DURATION: Duration = Duration::from_millis(400);
+
+/// Number of upcoming slots whose leaders are fetched on each refresh, which
+/// must outlast the refresh interval
+const FETCHED_LEADER_SLOTS: u64 = 1000;
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Duration of the interval at which the leaders of the upcoming slots and
+    /// the TPU endpoints of the validators are refreshed
+    #[serde(with = "humantime_serde")]
+    pub refresh_interval_duration:   Duration,
+    /// Duration of the interval at which the current slot is polled
+    #[serde(with = "humantime_serde")]
+    pub slot_poll_interval_duration: Duration,
+    /// Longest a transaction is held back to avoid the last slot of a leader
+    #[serde(with = "humantime_serde")]
+    pub max_submission_delay:        Duration,
+    /// Transactions are also sent to the TPUs of the leaders of this many
+    /// upcoming slots. Disabled with 0.
+    pub tpu_fanout_slots:            u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            refresh_interval_duration:   Duration::from_secs(30),
+            slot_poll_interval_duration: Duration::from_millis(100),
+            max_submission_delay:        SLOT_DURATION,
+            tpu_fanout_slots:            8,
+        }
+    }
+}
+
+/// The leaders of the upcoming slots, as last seen by the Leader Tracker
+#[derive(Clone, Debug, Default)]
+pub struct LeaderSchedule {
+    /// First slot of the leaders
+    leaders_start_slot: Slot,
+    /// Leader of each slot from the start slot
+    leaders:            Arc<Vec<Pubkey>>,
+    /// TPU endpoint of each validator
+    tpu_addresses:      Arc<HashMap<Pubkey, SocketAddr>>,
+    /// Latest processed slot, with when it was first observed
+    current_slot:       Option<(Slot, Instant)>,
+}
+
+impl LeaderSchedule {
+    fn leader(&self, slot: Slot) -> Option<&Pubkey> {
+        let index = slot.checked_sub(self.leaders_start_slot)?;
+        self.leaders.get(index as usize)
+    }
+
+    /// The slot expected to be in progress, with when it started. The current
+    /// slot may have ended since it was observed.
+    fn estimated_slot(&self, now: Instant) -> Option<(Slot, Instant)> {
+        let (slot, observed_at) = self.current_slot?;
+        let elapsed_slots = (now.saturating_duration_since(observed_at).as_millis()
+            / SLOT_DURATION.as_millis()) as u32;
+        Some((
+            slot + elapsed_slots as u64,
+            observed_at + SLOT_DURATION * elapsed_slots,
+        ))
+    }
+
+    /// How long to hold back a transaction so that it does not reach the current
+    /// leader in its last slot. Zero when the next slot has the same leader, or
+    /// the schedule is not known.
+    pub fn submission_delay(&self, now: Instant, max_delay: Duration) -> Duration {
+        let (slot, slot_started_at) = match self.estimated_slot(now) {
+            Some(estimated_slot) => estimated_slot,
+            None => return Duration::ZERO,
+        };
+        match (self.leader(slot), self.leader(slot + 1)) {
+            (Some(leader), Some(next_leader)) if leader != next_leader => (slot_started_at
+                + SLOT_DURATION)
+                .saturating_duration_since(now)
+                .min(max_delay),
+            _ => Duration::ZERO,
+        }
+    }
+
+    /// TPU endpoints of the distinct leaders of the given number of upcoming slots
+    pub fn upcoming_tpu_addresses(&self, now: Instant, slots: u64) -> Vec<SocketAddr> {
+        let slot = match self.estimated_slot(now) {
+            Some((slot, _)) => slot,
+            None => return vec![],
+        };
+        let mut tpu_addresses = vec![];
+        for leader in (slot..slot + slots).filter_map(|slot| self.leader(slot)) {
+            if let Some(tpu_address) = self.tpu_addresses.get(leader) {
+                if !tpu_addresses.contains(tpu_address) {
+                    tpu_addresses.push(*tpu_address);
+                }
+            }
+        }
+        tpu_addresses
+    }
+}
+
+/// Polls the current slot and refreshes the leaders of the upcoming slots,
+/// publishing the schedule on a watch channel
+pub struct LeaderTracker {
+    rpc_client:  RpcClient,
+    config:      Config,
+    schedule:    LeaderSchedule,
+    schedule_tx: watch::Sender<LeaderSchedule>,
+}
+
+impl LeaderTracker {
+    pub fn new(
+        network_name: &str,
+        rpc_url: &str,
+        rpc_timeout: Duration,
+        config: Config,
+        schedule_tx: watch::Sender<LeaderSchedule>,
+    ) -> Self {
+        LeaderTracker {
+            rpc_client: instrumented_rpc::new_rpc_client(
+                network_name,
+                rpc_url,
+                rpc_timeout,
+                CommitmentConfig::processed(),
+            ),
+            config,
+            schedule: LeaderSchedule::default(),
+            schedule_tx,
+        }
+    }
+
+    pub async fn run(&mut self) {
+        let mut refresh_interval = time::interval(self.config.refresh_interval_duration);
+        let mut slot_poll_interval = time::interval(self.config.slot_poll_interval_duration);
+        loop {
+            let result = tokio::select! {
+                _ = refresh_interval.tick() => self.refresh_leaders().await,
+                _ = slot_poll_interval.tick() => self.poll_slot().await,
+            };
+            if let Err(err) = result {
+                error!(error = ?err, "Leader tracker: {:#}", err);
+            }
+        }
+    }
+
+    async fn poll_slot(&mut self) -> Result<()> {
+        let slot = self
+            .rpc_client
+            .get_slot()
+            .await
+            .context("fetching the current slot")?;
+        if self
+            .schedule
+            .current_slot
+            .map_or(true, |(current_slot, _)| slot > current_slot)
+        {
+            self.schedule.current_slot = Some((slot, Instant::now()));
+            let _ = self.schedule_tx.send(self.schedule.clone());
+        }
+        Ok(())
+    }
+
+    async fn refresh_leaders(&mut self) -> Result<()> {
+        let slot = self
+            .rpc_client
+            .get_slot()
+            .await
+            .context("fetching the current slot")?;
+        let leaders = self
+            .rpc_client
+            .get_slot_leaders(slot, FETCHED_LEADER_SLOTS)
+            .await
+            .context("fetching the slot leaders")?;
+        let tpu_addresses = self
+            .rpc_client
+            .get_cluster_nodes()
+            .await
+            .context("fetching the cluster nodes")?
+            .into_iter()
+            .filter_map(|node| Some((Pubkey::from_str(&node.pubkey).ok()?, node.tpu?)))
+            .collect();
+
+        self.schedule.leaders_start_slot = slot;
+        self.schedule.leaders = Arc::new(leaders);
+        self.schedule.tpu_addresses = Arc::new(tpu_addresses);
+        let _ = self.schedule_tx.send(self.schedule.clone());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            LeaderSchedule,
+            SLOT_DURATION,
+        },
+        solana_sdk::pubkey::Pubkey,
+        std::{
+            collections::HashMap,
+            net::SocketAddr,
+            sync::Arc,
+            time::Duration,
+        },
+        tokio::time::Instant,
+    };
+
+    #[test]
+    fn test_submissions_avoid_the_last_slot_of_a_leader() {
+        let (first_leader, second_leader) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let first_tpu: SocketAddr = "10.0.0.1:8003".parse().unwrap();
+        let second_tpu: SocketAddr = "10.0.0.2:8003".parse().unwrap();
+        let start = Instant::now();
+        let schedule = LeaderSchedule {
+            leaders_start_slot: 100,
+            leaders:            Arc::new(vec![
+                first_leader,
+                first_leader,
+                first_leader,
+                first_leader,
+                second_leader,
+                second_leader,
+            ]),
+            tpu_addresses:      Arc::new(HashMap::from([
+                (first_leader, first_tpu),
+                (second_leader, second_tpu),
+            ])),
+            current_slot:       Some((101, start)),
+        };
+        let max_delay = Duration::from_secs(1);
+
+        // Not held back before the leader's last slot
+        assert_eq!(schedule.submission_delay(start, max_delay), Duration::ZERO);
+
+        // Held back until the next leader's first slot, at most for the max delay
+        let in_last_slot = start + SLOT_DURATION * 2 + Duration::from_millis(100);
+        assert_eq!(
+            schedule.submission_delay(in_last_slot, max_delay),
+            Duration::from_millis(300)
+        );
+        assert_eq!(
+            schedule.submission_delay(in_last_slot, Duration::from_millis(50)),
+            Duration::from_millis(50)
+        );
+
+        // Sent to the distinct upcoming leaders
+        assert_eq!(
+            schedule.upcoming_tpu_addresses(start, 4),
+            vec![first_tpu, second_tpu]
+        );
+        assert_eq!(
+            schedule.upcoming_tpu_addresses(in_last_slot, 4),
+            vec![first_tpu, second_tpu]
+        );
+        assert!(LeaderSchedule::default()
+            .upcoming_tpu_addresses(start, 4)
+            .is_empty());
+    }
+}
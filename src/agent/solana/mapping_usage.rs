@@ -0,0 +1,138 @@
+// A mapping account lists a fixed number of products, and listing more products than
+// the mapping accounts of a network can hold takes creating another one. The Oracle of
+// each network reports how many products each of the mapping accounts it polls lists,
+// which is exported as metrics along with their remaining capacity, and the dashboard
+// flags the mapping accounts nearing their capacity.
+use {
+    super::oracle::MappingAccount,
+    crate::agent::metrics::{
+        MappingUsageMetrics,
+        PROMETHEUS_REGISTRY,
+    },
+    parking_lot::RwLock,
+    solana_sdk::pubkey::Pubkey,
+    std::{
+        collections::{
+            BTreeMap,
+            HashMap,
+        },
+        sync::Arc,
+    },
+};
+
+/// Share of its capacity from which a mapping account is flagged as nearly full
+pub const NEARLY_FULL_USAGE: f64 = 0.9;
+
+/// Products listed by a mapping account
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MappingUsage {
+    /// Number of products listed by the mapping account
+    pub products: u64,
+    /// Number of products the mapping account can list
+    pub capacity: u64,
+}
+
+impl MappingUsage {
+    pub fn new(mapping_account: &MappingAccount) -> Self {
+        MappingUsage {
+            products: mapping_account.num as u64,
+            capacity: mapping_account.products.len() as u64,
+        }
+    }
+
+    /// Number of products the mapping account can still list
+    pub fn remaining_capacity(&self) -> u64 {
+        self.capacity.saturating_sub(self.products)
+    }
+
+    pub fn nearly_full(&self) -> bool {
+        self.products as f64 >= self.capacity as f64 * NEARLY_FULL_USAGE
+    }
+}
+
+type Usages = Arc<RwLock<BTreeMap<String, BTreeMap<Pubkey, MappingUsage>>>>;
+
+/// Shared registry of the usage of the mapping accounts of each network
+#[derive(Clone)]
+pub struct MappingUsageReporter {
+    networks: Usages,
+    metrics:  Arc<MappingUsageMetrics>,
+}
+
+impl MappingUsageReporter {
+    pub async fn new() -> Self {
+        MappingUsageReporter {
+            networks: Default::default(),
+            metrics:  Arc::new(MappingUsageMetrics::new(
+                &mut &mut PROMETHEUS_REGISTRY.lock().await,
+            )),
+        }
+    }
+
+    /// Register a network, returning the handle the usage of its mapping
+    /// accounts is reported through
+    pub fn network(&self, name: impl Into<String>) -> NetworkMappingUsage {
+        let name = name.into();
+        self.networks.write().insert(name.clone(), BTreeMap::new());
+        NetworkMappingUsage {
+            name,
+            networks: self.networks.clone(),
+            metrics: self.metrics.clone(),
+        }
+    }
+
+    /// The latest usage of the mapping accounts of every registered network
+    pub fn usages(&self) -> BTreeMap<String, BTreeMap<Pubkey, MappingUsage>> {
+        self.networks.read().clone()
+    }
+}
+
+/// Handle through which the usage of the mapping accounts of a single network
+/// is reported
+#[derive(Clone)]
+pub struct NetworkMappingUsage {
+    name:     String,
+    networks: Usages,
+    metrics:  Arc<MappingUsageMetrics>,
+}
+
+impl NetworkMappingUsage {
+    /// Record the mapping accounts polled by the Oracle, updating the metrics
+    pub fn observe_mapping_accounts(&self, mapping_accounts: &HashMap<Pubkey, MappingAccount>) {
+        let usages: BTreeMap<Pubkey, MappingUsage> = mapping_accounts
+            .iter()
+            .map(|(mapping_key, mapping_account)| {
+                (*mapping_key, MappingUsage::new(mapping_account))
+            })
+            .collect();
+        for (mapping_key, usage) in &usages {
+            self.metrics.update(&self.name, mapping_key, usage);
+        }
+        self.networks.write().insert(self.name.clone(), usages);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::MappingUsage,
+        crate::agent::solana::oracle::MappingAccount,
+        bytemuck::Zeroable,
+    };
+
+    #[test]
+    fn test_mapping_account_nearing_capacity_is_flagged() {
+        let mut mapping_account = MappingAccount::zeroed();
+        let capacity = mapping_account.products.len() as u32;
+
+        mapping_account.num = capacity / 2;
+        let usage = MappingUsage::new(&mapping_account);
+        assert_eq!(usage.remaining_capacity(), (capacity - capacity / 2) as u64);
+        assert!(!usage.nearly_full());
+
+        mapping_account.num = capacity - 1;
+        let usage = MappingUsage::new(&mapping_account);
+        assert_eq!(usage.remaining_capacity(), 1);
+        assert!(usage.nearly_full());
+    }
+}
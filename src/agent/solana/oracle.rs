@@ -2,13 +2,58 @@
 // on-chain Oracle program accounts from Solana.
 use {
     self::subscriber::Subscriber,
-    super::key_store::KeyStore,
-    crate::agent::store::global,
+    super::{
+        account_check::{
+            self,
+            AccountKind,
+        },
+        accumulator::{
+            self,
+            AccumulatorUpdates,
+        },
+        instrumented_rpc,
+        key_store::KeyStore,
+        mapping_usage::NetworkMappingUsage,
+        recording::{
+            self,
+            Recorder,
+        },
+        simulation::SimulatedCluster,
+        slot_lag::{
+            self,
+            NetworkSlots,
+        },
+    },
+    crate::agent::{
+        channel_monitor::ChannelMonitor,
+        error::{
+            self,
+            Error,
+        },
+        health::{
+            ComponentHealth,
+            HealthReporter,
+        },
+        metrics::{
+            ACCOUNT_CHECK_METRICS,
+            DUPLICATE_UPDATE_METRICS,
+            GLOBAL_STORE_BACKPRESSURE_METRICS,
+        },
+        startup_gate::StartupGate,
+        store::global,
+        telemetry,
+    },
     anyhow::{
         anyhow,
-        Context,
+        Context as _,
         Result,
     },
+    futures_util::future,
+    opentelemetry::{
+        Context,
+        KeyValue,
+    },
+    parking_lot::Mutex,
     pyth_sdk_solana::state::{
         load_mapping_account,
         load_price_account,
@@ -18,8 +63,18 @@ use {
         Deserialize,
         Serialize,
     },
-    slog::Logger,
-    solana_client::nonblocking::rpc_client::RpcClient,
+    solana_account_decoder::UiAccountEncoding,
+    solana_client::{
+        client_error::{
+            ClientError,
+            ClientErrorKind,
+        },
+        nonblocking::rpc_client::RpcClient,
+        rpc_config::RpcAccountInfoConfig,
+        rpc_custom_error::JSON_RPC_SERVER_ERROR_MIN_CONTEXT_SLOT_NOT_REACHED,
+        rpc_request::RpcError,
+        rpc_response::Response,
+    },
     solana_sdk::{
         account::Account,
         commitment_config::{
@@ -30,23 +85,53 @@ use {
     },
     std::{
         collections::{
+            hash_map::DefaultHasher,
             HashMap,
             HashSet,
         },
-        time::Duration,
+        hash::{
+            Hash,
+            Hasher,
+        },
+        path::PathBuf,
+        sync::{
+            atomic::{
+                AtomicU64,
+                Ordering,
+            },
+            Arc,
+        },
+        time::{
+            Duration,
+            Instant,
+        },
     },
     tokio::{
-        sync::mpsc,
+        sync::{
+            mpsc::{
+                self,
+                error::{
+                    SendTimeoutError,
+                    TrySendError,
+                },
+            },
+            watch,
+        },
         task::JoinHandle,
-        time::Interval,
+        time::{
+            Interval,
+            MissedTickBehavior,
+        },
     },
+    tracing::Instrument,
 };
 
 #[derive(Default, Debug, Clone)]
 pub struct Data {
     pub mapping_accounts:      HashMap<Pubkey, MappingAccount>,
     pub product_accounts:      HashMap<Pubkey, ProductEntry>,
-    pub price_accounts:        HashMap<Pubkey, PriceEntry>,
+    /// Parsed price accounts, shared with the Global Store
+    pub price_accounts:        HashMap<Pubkey, Arc<PriceEntry>>,
     /// publisher => {their permissioned price accounts}
     pub publisher_permissions: HashMap<Pubkey, HashSet<Pubkey>>,
 }
@@ -55,7 +140,7 @@ impl Data {
     fn new(
         mapping_accounts: HashMap<Pubkey, MappingAccount>,
         product_accounts: HashMap<Pubkey, ProductEntry>,
-        price_accounts: HashMap<Pubkey, PriceEntry>,
+        price_accounts: HashMap<Pubkey, Arc<PriceEntry>>,
         publisher_permissions: HashMap<Pubkey, HashSet<Pubkey>>,
     ) -> Self {
         Data {
@@ -86,26 +171,278 @@ pub struct Oracle {
     /// Channel on which account updates are received from the Subscriber
     updates_rx: mpsc::Receiver<(Pubkey, solana_sdk::account::Account)>,
 
+    /// Price accounts the shards of the Subscriber subscribe to, if sharded
+    price_accounts_tx: Option<watch::Sender<HashSet<Pubkey>>>,
+
     /// Channel on which updates are sent to the global store
     global_store_tx: mpsc::Sender<global::Update>,
 
-    logger: Logger,
+    /// Name under which the global store channel is monitored
+    global_store_channel: String,
+
+    channel_monitor: ChannelMonitor,
+
+    /// Records the account updates received from the Subscriber, if enabled
+    recorder: Option<Arc<Recorder>>,
+
+    /// Slots of the observed price accounts, compared to the network slot
+    slots: NetworkSlots,
+
+    /// Usage of the polled mapping accounts
+    mapping_usage: NetworkMappingUsage,
+
+    /// Updates of our publishers in the observed price accounts, followed into
+    /// the accumulator, if tracked
+    accumulator_updates: Option<AccumulatorUpdates>,
+
+    /// Opened once the accounts of the first poll were handed to the Global
+    /// Store, on the primary network
+    startup_gate: Option<StartupGate>,
+
+    /// Drops the price account updates already handed to the Global Store,
+    /// if enabled
+    deduplicator: Option<Deduplicator>,
+
+    /// Bounds the wait for the Global Store to accept the updates, if enabled
+    backpressure: Option<Backpressure>,
+}
+
+/// Remembers the last update of each price account handed to the Global Store,
+/// so that the identical updates delivered by both the polls and the
+/// subscription are only handed over once
+pub struct Deduplicator {
+    network_name: String,
+    /// Slot and hash of the data of the last update of each price account
+    last_updates: HashMap<Pubkey, (u64, u64)>,
 }
 
-#[derive(Clone, Serialize, Deserialize, Debug)]
-#[serde(default)]
+impl Deduplicator {
+    pub fn new(network_name: &str) -> Self {
+        Deduplicator {
+            network_name: network_name.to_string(),
+            last_updates: HashMap::new(),
+        }
+    }
+
+    /// Whether the update is identical to the last one of the price account,
+    /// counting it as a duplicate of the source if so
+    fn is_duplicate(&mut self, account_key: &Pubkey, account: &PriceEntry, source: &str) -> bool {
+        let mut hasher = DefaultHasher::new();
+        bytemuck::bytes_of(account).hash(&mut hasher);
+        let update = (account.last_slot, hasher.finish());
+        if self.last_updates.insert(*account_key, update) == Some(update) {
+            DUPLICATE_UPDATE_METRICS.record(&self.network_name, source);
+            return true;
+        }
+        false
+    }
+}
+
+/// Holds back the price account updates the Global Store did not accept within
+/// the send timeout, so that a Global Store falling behind does not stall the
+/// ingestion of the account updates
+pub struct Backpressure {
+    config:         BackpressureConfig,
+    network_name:   String,
+    /// Latest update of each price account held back, handed over ahead of
+    /// the next updates once the Global Store accepts them
+    held_updates:   HashMap<Pubkey, global::Update>,
+    /// Ticks while updates are held back, so that they are handed over even
+    /// when no new price account update arrives
+    flush_interval: Interval,
+}
+
+impl Backpressure {
+    pub fn new(config: BackpressureConfig, network_name: &str) -> Self {
+        let mut flush_interval = tokio::time::interval(config.flush_interval);
+        flush_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        Backpressure {
+            config,
+            network_name: network_name.to_string(),
+            held_updates: HashMap::new(),
+            flush_interval,
+        }
+    }
+
+    /// Whether updates are held back, waiting to be flushed
+    fn is_holding(&self) -> bool {
+        !self.held_updates.is_empty()
+    }
+
+    /// Send the update of a price account, holding it back if the Global Store
+    /// does not accept it within the send timeout, or if other updates are
+    /// still held back
+    async fn send_price_account_update(
+        &mut self,
+        channel_monitor: &ChannelMonitor,
+        channel: &str,
+        global_store_tx: &mpsc::Sender<global::Update>,
+        account_key: &Pubkey,
+        update: global::Update,
+    ) -> Result<()> {
+        self.flush(global_store_tx)?;
+        if !self.held_updates.is_empty() {
+            self.hold(account_key, update);
+            return Ok(());
+        }
+        match channel_monitor
+            .send_timeout(channel, global_store_tx, update, self.config.send_timeout)
+            .await
+        {
+            Ok(()) => Ok(()),
+            Err(SendTimeoutError::Timeout(update)) => {
+                warn!("Oracle: global store is not keeping up, holding back price account updates");
+                self.hold(account_key, update);
+                Ok(())
+            }
+            Err(SendTimeoutError::Closed(_)) => Err(Error::ChannelClosed("global store").into()),
+        }
+    }
+
+    /// Send an update of a poll, dropping it if the Global Store does not
+    /// accept it within the send timeout, as the next poll sends it again
+    async fn send_poll_update(
+        &self,
+        channel_monitor: &ChannelMonitor,
+        channel: &str,
+        global_store_tx: &mpsc::Sender<global::Update>,
+        update: global::Update,
+        kind: &str,
+    ) -> Result<()> {
+        match channel_monitor
+            .send_timeout(channel, global_store_tx, update, self.config.send_timeout)
+            .await
+        {
+            Ok(()) => Ok(()),
+            Err(SendTimeoutError::Timeout(_)) => {
+                GLOBAL_STORE_BACKPRESSURE_METRICS.record_dropped(&self.network_name, kind);
+                Ok(())
+            }
+            Err(SendTimeoutError::Closed(_)) => Err(Error::ChannelClosed("global store").into()),
+        }
+    }
+
+    /// Hold the update back, replacing the held update of the same price
+    /// account. The update is dropped if too many are held back already.
+    fn hold(&mut self, account_key: &Pubkey, update: global::Update) {
+        if self.held_updates.len() >= self.config.max_held_updates
+            && !self.held_updates.contains_key(account_key)
+        {
+            GLOBAL_STORE_BACKPRESSURE_METRICS.record_dropped(&self.network_name, "price_account");
+            return;
+        }
+        self.held_updates.insert(*account_key, update);
+        GLOBAL_STORE_BACKPRESSURE_METRICS
+            .set_held_updates(&self.network_name, self.held_updates.len());
+    }
+
+    /// Hand the held updates over to the Global Store, as long as it accepts
+    /// them without waiting
+    fn flush(&mut self, global_store_tx: &mpsc::Sender<global::Update>) -> Result<()> {
+        if self.held_updates.is_empty() {
+            return Ok(());
+        }
+        let account_keys: Vec<Pubkey> = self.held_updates.keys().cloned().collect();
+        for account_key in account_keys {
+            let permit = match global_store_tx.try_reserve() {
+                Ok(permit) => permit,
+                Err(TrySendError::Full(())) => break,
+                Err(TrySendError::Closed(())) => {
+                    return Err(Error::ChannelClosed("global store").into())
+                }
+            };
+            if let Some(update) = self.held_updates.remove(&account_key) {
+                permit.send(update);
+            }
+        }
+        GLOBAL_STORE_BACKPRESSURE_METRICS
+            .set_held_updates(&self.network_name, self.held_updates.len());
+        if self.held_updates.is_empty() {
+            info!(
+                "Oracle: global store caught up, handed over the held back price account updates"
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Resolves to the backpressure on the next tick of its flush interval while
+/// it holds updates back, never otherwise
+async fn flush_tick(backpressure: &mut Option<Backpressure>) -> Option<&mut Backpressure> {
+    match backpressure {
+        Some(backpressure) if backpressure.is_holding() => {
+            backpressure.flush_interval.tick().await;
+            Some(backpressure)
+        }
+        _ => std::future::pending().await,
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct BackpressureConfig {
+    /// How long an update waits for the Global Store to accept it
+    #[serde(with = "humantime_serde")]
+    pub send_timeout:     Duration,
+    /// Number of price accounts whose latest update is held back when the
+    /// Global Store does not accept it in time. The updates of other price
+    /// accounts are dropped while that many are held back, and all of them
+    /// when 0.
+    pub max_held_updates: usize,
+    /// Interval at which the held updates are handed over to the Global Store
+    /// as it catches up, in between the price account updates
+    #[serde(with = "humantime_serde")]
+    pub flush_interval:   Duration,
+}
+
+impl Default for BackpressureConfig {
+    fn default() -> Self {
+        Self {
+            send_timeout:     Duration::from_millis(100),
+            max_held_updates: 10000,
+            flush_interval:   Duration::from_millis(100),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
 pub struct Config {
     /// The commitment level to use when reading data from the RPC node.
-    pub commitment:               CommitmentLevel,
+    pub commitment:                      CommitmentLevel,
     /// The interval with which to poll account information.
     #[serde(with = "humantime_serde")]
-    pub poll_interval_duration:   Duration,
+    pub poll_interval_duration:          Duration,
+    /// Polls the mapping and product accounts on this interval rather than on
+    /// every poll. The polls in between only fetch the price accounts found by
+    /// the last metadata poll, and a failed metadata poll is retried on the
+    /// next one. Disabled when not set.
+    #[serde(with = "humantime_serde")]
+    pub metadata_poll_interval_duration: Option<Duration>,
     /// Whether subscribing to account updates over websocket is enabled
-    pub subscriber_enabled:       bool,
+    pub subscriber_enabled:              bool,
+    /// Number of websocket connections the subscription is sharded across,
+    /// each subscribing to the price accounts whose key falls in its shard
+    /// rather than to the whole program. Disabled when not set. Read at
+    /// startup only.
+    pub subscriber_shards:               Option<usize>,
+    /// Whether the price account updates identical to the last one handed to
+    /// the Global Store, with the same slot and data, are dropped. Read at
+    /// startup only.
+    pub deduplicate_updates:             bool,
+    /// Bounds how long the updates wait for the Global Store to accept them,
+    /// holding back or dropping those it does not accept in time, so that a
+    /// Global Store falling behind does not stall the Oracle. The updates wait
+    /// indefinitely when not set. Read at startup only.
+    pub global_store_backpressure:       Option<BackpressureConfig>,
+    /// The interval with which to poll the slot of the cluster tip, to measure
+    /// how far behind the observed price accounts are
+    #[serde(with = "humantime_serde")]
+    pub slot_poll_interval_duration:     Duration,
     /// Capacity of the channel over which the Subscriber sends updates to the Oracle
-    pub updates_channel_capacity: usize,
+    pub updates_channel_capacity:        usize,
     /// Capacity of the channel over which the Poller sends data to the Oracle
-    pub data_channel_capacity:    usize,
+    pub data_channel_capacity:           usize,
 
     /// Ask the RPC for up to this many product/price accounts in a
     /// single request. Tune this setting if you're experiencing
@@ -113,66 +450,269 @@ pub struct Config {
     /// socket count at bay, the batches are looked up sequentially,
     /// trading off overall time it takes to fetch all symbols.
     pub max_lookup_batch_size: usize,
+
+    /// Encoding of the account data in the responses to the polls. Compressing
+    /// the accounts cuts the transfer size of the polls, at the cost of
+    /// decompressing them locally, but is not supported by every RPC provider.
+    pub account_encoding: AccountEncoding,
+
+    /// HTTP RPC endpoints polled in turn when the network's endpoint serves
+    /// accounts older than the latest slot already seen, e.g. after failing
+    /// over to a lagging RPC node, so that the polled data never moves
+    /// backwards. Read at startup only.
+    pub fallback_rpc_urls: Vec<String>,
+
+    /// How long an HTTP RPC endpoint is not polled for after it served
+    /// accounts which are not the oracle program accounts expected, e.g.
+    /// truncated ones
+    #[serde(with = "humantime_serde")]
+    pub rpc_quarantine_duration: Duration,
+
+    /// Path of the file the polled accounts and account updates are recorded
+    /// to, to be replayed in simulation. Read at startup only.
+    pub recording_path: Option<PathBuf>,
+
+    /// Tracks whether the updates of our publishers make it into the
+    /// accumulator, on networks whose key store sets the accumulator program.
+    /// Disabled when not set. Read at startup only.
+    pub accumulator: Option<accumulator::Config>,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
-            commitment:               CommitmentLevel::Confirmed,
-            poll_interval_duration:   Duration::from_secs(2 * 60),
-            subscriber_enabled:       true,
-            updates_channel_capacity: 10000,
-            data_channel_capacity:    10000,
-            max_lookup_batch_size:    100,
+            commitment:                      CommitmentLevel::Confirmed,
+            poll_interval_duration:          Duration::from_secs(2 * 60),
+            metadata_poll_interval_duration: None,
+            subscriber_enabled:              true,
+            subscriber_shards:               None,
+            deduplicate_updates:             false,
+            global_store_backpressure:       None,
+            slot_poll_interval_duration:     Duration::from_secs(1),
+            updates_channel_capacity:        10000,
+            data_channel_capacity:           10000,
+            max_lookup_batch_size:           100,
+            account_encoding:                AccountEncoding::Base64,
+            fallback_rpc_urls:               vec![],
+            rpc_quarantine_duration:         Duration::from_secs(60),
+            recording_path:                  None,
+            accumulator:                     None,
+        }
+    }
+}
+
+/// Encoding of the account data requested from the RPC node
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub enum AccountEncoding {
+    #[serde(rename = "base64")]
+    Base64,
+    /// Supported by most RPC nodes, but not all RPC providers
+    #[serde(rename = "base64+zstd")]
+    Base64Zstd,
+}
+
+impl From<AccountEncoding> for UiAccountEncoding {
+    fn from(encoding: AccountEncoding) -> Self {
+        match encoding {
+            AccountEncoding::Base64 => UiAccountEncoding::Base64,
+            AccountEncoding::Base64Zstd => UiAccountEncoding::Base64Zstd,
         }
     }
 }
 
 pub fn spawn_oracle(
-    config: Config,
+    config_rx: watch::Receiver<Config>,
+    network_name: &str,
     rpc_url: &str,
     wss_url: &str,
     rpc_timeout: Duration,
     global_store_update_tx: mpsc::Sender<global::Update>,
     publisher_permissions_tx: mpsc::Sender<HashMap<Pubkey, HashSet<Pubkey>>>,
+    keypair_loader_permissions_tx: watch::Sender<HashMap<Pubkey, HashSet<Pubkey>>>,
     key_store: KeyStore,
-    logger: Logger,
+    simulated_cluster: Option<Arc<SimulatedCluster>>,
+    startup_gate: Option<StartupGate>,
+    health: &HealthReporter,
+    channel_monitor: &ChannelMonitor,
+    slots: NetworkSlots,
+    mapping_usage: NetworkMappingUsage,
 ) -> Vec<JoinHandle<()>> {
+    let config = config_rx.borrow().clone();
     let mut jhs = vec![];
 
+    // Track the slot of the cluster tip. The simulated cluster has none.
+    if simulated_cluster.is_none() {
+        jhs.push(slot_lag::spawn_tracker(
+            rpc_url,
+            rpc_timeout,
+            config.commitment,
+            config.slot_poll_interval_duration,
+            slots.clone(),
+        ));
+    }
+
+    // Track the updates of our publishers into the accumulator, if enabled
+    let accumulator_updates = match (&config.accumulator, key_store.accumulator_key) {
+        (Some(accumulator_config), Some(accumulator_key)) if simulated_cluster.is_none() => {
+            let (accumulator_updates, jh) = accumulator::spawn_tracker(
+                accumulator_config.clone(),
+                network_name,
+                rpc_url,
+                rpc_timeout,
+                config.commitment,
+                &key_store,
+                accumulator_key,
+            );
+            jhs.push(jh);
+            Some(accumulator_updates)
+        }
+        (Some(_), None) => {
+            warn!("Oracle: not tracking the accumulator, as the key store sets no accumulator program");
+            None
+        }
+        _ => None,
+    };
+
+    // Record the accounts seen by the Poller and the Oracle, if enabled
+    let recorder = match &config.recording_path {
+        Some(path) => match Recorder::new(path) {
+            Ok(recorder) => Some(Arc::new(recorder)),
+            Err(err) => {
+                error!(error = ?err, kind = %error::record("oracle", &err), "{:#}", err);
+                None
+            }
+        },
+        None => None,
+    };
+
     // Create and spawn the account subscriber
     let (updates_tx, updates_rx) = mpsc::channel(config.updates_channel_capacity);
-    if config.subscriber_enabled {
-        let subscriber = Subscriber::new(
+    let updates_channel = format!("{}_subscriber_updates", network_name);
+    channel_monitor.register(
+        updates_channel.clone(),
+        &updates_tx,
+        config.updates_channel_capacity,
+    );
+    let new_subscriber = |health| {
+        Subscriber::new(
             rpc_url.to_string(),
             wss_url.to_string(),
             rpc_timeout,
             config.commitment,
             key_store.program_key.clone(),
-            updates_tx,
-            logger.clone(),
-        );
-        jhs.push(tokio::spawn(async move { subscriber.run().await }));
+            updates_tx.clone(),
+            updates_channel.clone(),
+            channel_monitor.clone(),
+            health,
+        )
+    };
+    let mut price_accounts_tx = None;
+    // The simulated cluster is only polled
+    if config.subscriber_enabled && simulated_cluster.is_none() {
+        match config.subscriber_shards {
+            // Each shard subscribes to its price accounts over its own connection
+            Some(shards) => {
+                let shards = shards.max(1);
+                let (tx, price_accounts_rx) = watch::channel(HashSet::new());
+                price_accounts_tx = Some(tx);
+                for shard in 0..shards {
+                    let subscriber = new_subscriber(
+                        health.component(format!("{}.subscriber.{}", network_name, shard)),
+                    );
+                    let price_accounts_rx = price_accounts_rx.clone();
+                    jhs.push(tokio::spawn(
+                        async move { subscriber.run_shard(shard, shards, price_accounts_rx).await }
+                            .instrument(info_span!("subscriber", shard)),
+                    ));
+                }
+            }
+            None => {
+                let subscriber =
+                    new_subscriber(health.component(format!("{}.subscriber", network_name)));
+                jhs.push(tokio::spawn(
+                    async move { subscriber.run().await }.instrument(info_span!("subscriber")),
+                ));
+            }
+        }
     }
 
+    // Replay a recording into the simulated cluster, if configured. The
+    // subscription updates are sent to the Oracle as the Subscriber would,
+    // and the Poller polls where the recorded polls ended.
+    let replay_polls_rx = match simulated_cluster.as_ref().and_then(|simulated_cluster| {
+        Some((simulated_cluster.clone(), simulated_cluster.replay()?))
+    }) {
+        Some((simulated_cluster, (path, speed))) => {
+            let (replay_polls_tx, replay_polls_rx) = mpsc::channel(1);
+            jhs.push(tokio::spawn(
+                async move {
+                    if let Err(err) = recording::replay(
+                        path,
+                        speed,
+                        simulated_cluster,
+                        updates_tx,
+                        replay_polls_tx,
+                    )
+                    .await
+                    {
+                        error!(error = ?err, kind = %error::record("oracle", &err), "{:#}", err);
+                    }
+                }
+                .instrument(info_span!("replay")),
+            ));
+            Some(replay_polls_rx)
+        }
+        None => None,
+    };
+
     // Create and spawn the Poller
     let (data_tx, data_rx) = mpsc::channel(config.data_channel_capacity);
     let mut poller = Poller::new(
         data_tx,
         publisher_permissions_tx,
+        keypair_loader_permissions_tx,
+        network_name,
         rpc_url,
         rpc_timeout,
         config.commitment,
-        config.poll_interval_duration,
+        config_rx,
         config.max_lookup_batch_size,
+        config.account_encoding,
         key_store.mapping_key,
-        logger.clone(),
+        key_store.program_key,
+        simulated_cluster,
+        replay_polls_rx,
+        recorder.clone(),
+        health.component(format!("{}.oracle", network_name)),
     );
-    jhs.push(tokio::spawn(async move { poller.run().await }));
+    jhs.push(tokio::spawn(
+        async move { poller.run().await }.instrument(info_span!("poller")),
+    ));
 
     // Create and spawn the Oracle
-    let mut oracle = Oracle::new(data_rx, updates_rx, global_store_update_tx, logger);
-    jhs.push(tokio::spawn(async move { oracle.run().await }));
+    let mut oracle = Oracle::new(
+        data_rx,
+        updates_rx,
+        price_accounts_tx,
+        global_store_update_tx,
+        format!("{}_oracle_updates", network_name),
+        channel_monitor.clone(),
+        recorder,
+        slots,
+        mapping_usage,
+        accumulator_updates,
+        startup_gate,
+        config
+            .deduplicate_updates
+            .then(|| Deduplicator::new(network_name)),
+        config
+            .global_store_backpressure
+            .clone()
+            .map(|backpressure_config| Backpressure::new(backpressure_config, network_name)),
+    );
+    jhs.push(tokio::spawn(
+        async move { oracle.run().await }.instrument(info_span!("oracle")),
+    ));
 
     jhs
 }
@@ -181,22 +721,40 @@ impl Oracle {
     pub fn new(
         data_rx: mpsc::Receiver<Data>,
         updates_rx: mpsc::Receiver<(Pubkey, solana_sdk::account::Account)>,
+        price_accounts_tx: Option<watch::Sender<HashSet<Pubkey>>>,
         global_store_tx: mpsc::Sender<global::Update>,
-        logger: Logger,
+        global_store_channel: String,
+        channel_monitor: ChannelMonitor,
+        recorder: Option<Arc<Recorder>>,
+        slots: NetworkSlots,
+        mapping_usage: NetworkMappingUsage,
+        accumulator_updates: Option<AccumulatorUpdates>,
+        startup_gate: Option<StartupGate>,
+        deduplicator: Option<Deduplicator>,
+        backpressure: Option<Backpressure>,
     ) -> Self {
         Oracle {
             data: Default::default(),
             data_rx,
             updates_rx,
+            price_accounts_tx,
             global_store_tx,
-            logger,
+            global_store_channel,
+            channel_monitor,
+            recorder,
+            slots,
+            mapping_usage,
+            accumulator_updates,
+            startup_gate,
+            deduplicator,
+            backpressure,
         }
     }
 
     pub async fn run(&mut self) {
         loop {
             if let Err(err) = self.handle_next().await {
-                error!(self.logger, "{:#}", err; "error" => format!("{:?}", err));
+                error!(error = ?err, kind = %error::record("oracle", &err), "{:#}", err);
             }
         }
     }
@@ -208,7 +766,14 @@ impl Oracle {
             }
             Some(data) = self.data_rx.recv() => {
                 self.handle_data_update(data);
-                self.send_all_data_to_global_store().await
+                self.send_all_data_to_global_store().await?;
+                if let Some(startup_gate) = &self.startup_gate {
+                    startup_gate.open("first poll of the oracle succeeded");
+                }
+                Ok(())
+            }
+            Some(backpressure) = flush_tick(&mut self.backpressure) => {
+                backpressure.flush(&self.global_store_tx)
             }
         }
     }
@@ -221,33 +786,48 @@ impl Oracle {
             .keys()
             .cloned()
             .collect::<HashSet<_>>();
-        info!(self.logger, "fetched mapping accounts"; "new" => format!("{:?}", data
+        info!(
+            new = ?data
                 .mapping_accounts
                 .keys()
                 .cloned()
-                .collect::<HashSet<_>>().difference(&previous_mapping_accounts)), "total" => data.mapping_accounts.len());
+                .collect::<HashSet<_>>()
+                .difference(&previous_mapping_accounts),
+            total = data.mapping_accounts.len(),
+            "fetched mapping accounts"
+        );
         let previous_product_accounts = self
             .data
             .product_accounts
             .keys()
             .cloned()
             .collect::<HashSet<_>>();
-        info!(self.logger, "fetched product accounts"; "new" => format!("{:?}", data
+        info!(
+            new = ?data
                 .product_accounts
                 .keys()
                 .cloned()
-                .collect::<HashSet<_>>().difference(&previous_product_accounts)), "total" => data.product_accounts.len());
+                .collect::<HashSet<_>>()
+                .difference(&previous_product_accounts),
+            total = data.product_accounts.len(),
+            "fetched product accounts"
+        );
         let previous_price_accounts = self
             .data
             .price_accounts
             .keys()
             .cloned()
             .collect::<HashSet<_>>();
-        info!(self.logger, "fetched price accounts"; "new" => format!("{:?}", data
+        info!(
+            new = ?data
                 .price_accounts
                 .keys()
                 .cloned()
-                .collect::<HashSet<_>>().difference(&previous_price_accounts)), "total" => data.price_accounts.len());
+                .collect::<HashSet<_>>()
+                .difference(&previous_price_accounts),
+            total = data.price_accounts.len(),
+            "fetched price accounts"
+        );
 
         let previous_publishers = self
             .data
@@ -256,14 +836,32 @@ impl Oracle {
             .collect::<HashSet<_>>();
         let new_publishers = data.publisher_permissions.keys().collect::<HashSet<_>>();
         info!(
-        self.logger,
-        "updated publisher permissions";
-        "new" => format!("{:?}", new_publishers.difference(&previous_publishers).collect::<HashSet<_>>()),
-        "total" => new_publishers.len(),
+            new = ?new_publishers
+                .difference(&previous_publishers)
+                .collect::<HashSet<_>>(),
+            total = new_publishers.len(),
+            "updated publisher permissions"
         );
 
+        self.mapping_usage
+            .observe_mapping_accounts(&data.mapping_accounts);
+        for (price_key, price_account) in &data.price_accounts {
+            self.slots.observe_price_account(price_account);
+            if let Some(accumulator_updates) = &self.accumulator_updates {
+                accumulator_updates.observe_price_account(price_key, price_account);
+            }
+        }
+
         // Update the data with the new data structs
         self.data = data;
+
+        // Have the shards of the Subscriber subscribe to the new price accounts
+        if let Some(price_accounts_tx) = &self.price_accounts_tx {
+            let price_accounts = self.data.price_accounts.keys().cloned().collect();
+            if *price_accounts_tx.borrow() != price_accounts {
+                let _ = price_accounts_tx.send(price_accounts);
+            }
+        }
     }
 
     async fn handle_account_update(
@@ -271,7 +869,12 @@ impl Oracle {
         account_key: &Pubkey,
         account: &Account,
     ) -> Result<()> {
-        debug!(self.logger, "handling account update");
+        debug!("handling account update");
+
+        if let Some(recorder) = &self.recorder {
+            let slot = load_price_account(&account.data).map_or(0, |price| price.last_slot);
+            recorder.record_account(slot, account_key, account, false);
+        }
 
         // We are only interested in price account updates, all other types of updates
         // will be fetched using polling.
@@ -287,31 +890,75 @@ impl Oracle {
         account_key: &Pubkey,
         account: &Account,
     ) -> Result<()> {
-        let price_account = *load_price_account(&account.data)
-            .with_context(|| format!("load price account {}", account_key))?;
+        // Parse the account once, sharing it with the Global Store and its
+        // subscribers rather than copying it for each of them.
+        let price_account = Arc::new(
+            *load_price_account(&account.data)
+                .with_context(|| format!("load price account {}", account_key))?,
+        );
 
-        debug!(self.logger, "observed on-chain price account update"; "pubkey" => account_key.to_string(), "price" => price_account.agg.price, "conf" => price_account.agg.conf, "status" => format!("{:?}", price_account.agg.status));
+        let symbol = self
+            .data
+            .product_accounts
+            .get(&price_account.prod)
+            .and_then(|product| {
+                product
+                    .account_data
+                    .iter()
+                    .find(|(key, _)| *key == "symbol")
+                    .map(|(_, symbol)| symbol.to_string())
+            });
+        debug!(
+            pubkey = %account_key,
+            symbol = symbol.as_deref().unwrap_or("unknown"),
+            price = price_account.agg.price,
+            conf = price_account.agg.conf,
+            status = ?price_account.agg.status,
+            "observed on-chain price account update"
+        );
 
-        self.data.price_accounts.insert(*account_key, price_account);
+        self.slots.observe_price_account(&price_account);
+        if let Some(accumulator_updates) = &self.accumulator_updates {
+            accumulator_updates.observe_price_account(account_key, &price_account);
+        }
+        self.data
+            .price_accounts
+            .insert(*account_key, price_account.clone());
 
+        if let Some(deduplicator) = &mut self.deduplicator {
+            if deduplicator.is_duplicate(account_key, &price_account, "subscription") {
+                return Ok(());
+            }
+        }
         self.notify_price_account_update(account_key, &price_account)
             .await?;
 
         Ok(())
     }
 
-    async fn send_all_data_to_global_store(&self) -> Result<()> {
+    async fn send_all_data_to_global_store(&mut self) -> Result<()> {
         for (product_account_key, product_account) in &self.data.product_accounts {
             self.notify_product_account_update(product_account_key, product_account)
                 .await?;
         }
 
-        for (price_account_key, price_account) in &self.data.price_accounts {
+        let price_accounts: Vec<(Pubkey, Arc<PriceEntry>)> = self
+            .data
+            .price_accounts
+            .iter()
+            .map(|(price_account_key, price_account)| (*price_account_key, price_account.clone()))
+            .collect();
+        for (price_account_key, price_account) in &price_accounts {
+            if let Some(deduplicator) = &mut self.deduplicator {
+                if deduplicator.is_duplicate(price_account_key, price_account, "poll") {
+                    continue;
+                }
+            }
             self.notify_price_account_update(price_account_key, price_account)
                 .await?;
         }
 
-        Ok(())
+        self.notify_listed_accounts().await
     }
 
     async fn notify_product_account_update(
@@ -319,27 +966,93 @@ impl Oracle {
         account_key: &Pubkey,
         account: &ProductEntry,
     ) -> Result<()> {
-        self.global_store_tx
-            .send(global::Update::ProductAccountUpdate {
+        self.send_poll_update(
+            global::Update::ProductAccountUpdate {
                 account_key: account_key.clone(),
                 account:     account.clone(),
-            })
-            .await
-            .map_err(|_| anyhow!("failed to notify product account update"))
+            },
+            "product_account",
+        )
+        .await
+        .context("failed to notify product account update")
+    }
+
+    /// Send the accounts found by the poll, so that the Global Store can tell
+    /// the delisted ones
+    async fn notify_listed_accounts(&self) -> Result<()> {
+        self.send_poll_update(
+            global::Update::ListedAccounts {
+                product_accounts: self.data.product_accounts.keys().cloned().collect(),
+                price_accounts:   self.data.price_accounts.keys().cloned().collect(),
+            },
+            "listed_accounts",
+        )
+        .await
+        .context("failed to notify listed accounts")
+    }
+
+    async fn send_poll_update(&self, update: global::Update, kind: &str) -> Result<()> {
+        match &self.backpressure {
+            Some(backpressure) => {
+                backpressure
+                    .send_poll_update(
+                        &self.channel_monitor,
+                        &self.global_store_channel,
+                        &self.global_store_tx,
+                        update,
+                        kind,
+                    )
+                    .await
+            }
+            None => self
+                .channel_monitor
+                .send(&self.global_store_channel, &self.global_store_tx, update)
+                .await
+                .map_err(|_| Error::ChannelClosed("global store").into()),
+        }
     }
 
     async fn notify_price_account_update(
-        &self,
+        &mut self,
         account_key: &Pubkey,
-        account: &PriceEntry,
+        account: &Arc<PriceEntry>,
     ) -> Result<()> {
-        self.global_store_tx
-            .send(global::Update::PriceAccountUpdate {
-                account_key: account_key.clone(),
-                account:     account.clone(),
-            })
-            .await
-            .map_err(|_| anyhow!("failed to notify price account update"))
+        // Updates observed on-chain start their own trace, which the Publish
+        // Latency Tracker links to the traces of our updates they include.
+        let trace_context = telemetry::start_span(
+            &Context::new(),
+            "oracle.price_account_update",
+            vec![
+                KeyValue::new("price_account", account_key.to_string()),
+                KeyValue::new("pub_slot", account.agg.pub_slot as i64),
+            ],
+        );
+        let update = global::Update::PriceAccountUpdate {
+            account_key:   account_key.clone(),
+            account:       account.clone(),
+            trace_context: trace_context.clone(),
+        };
+        let result = match &mut self.backpressure {
+            Some(backpressure) => {
+                backpressure
+                    .send_price_account_update(
+                        &self.channel_monitor,
+                        &self.global_store_channel,
+                        &self.global_store_tx,
+                        account_key,
+                        update,
+                    )
+                    .await
+            }
+            None => self
+                .channel_monitor
+                .send(&self.global_store_channel, &self.global_store_tx, update)
+                .await
+                .map_err(|_| Error::ChannelClosed("global store").into()),
+        }
+        .context("failed to notify price account update");
+        telemetry::end_span(&trace_context, &result);
+        result
     }
 }
 
@@ -350,68 +1063,247 @@ struct Poller {
     /// Updates about permissioned price accounts from oracle to exporter
     publisher_permissions_tx: mpsc::Sender<HashMap<Pubkey, HashSet<Pubkey>>>,
 
+    /// Permissioned price accounts, watched by the remote keypair loader to
+    /// validate the keypairs it loads
+    keypair_loader_permissions_tx: watch::Sender<HashMap<Pubkey, HashSet<Pubkey>>>,
+
+    /// Name of the network polled
+    network_name: String,
+
     /// The RPC client to use to poll data from the RPC node
-    rpc_client: RpcClient,
+    /// The network's RPC endpoint, followed by its fallbacks
+    rpc_endpoints: Vec<RpcEndpoint>,
+
+    /// Highest slot of the responses to the polls. The RPC nodes must have
+    /// reached it to serve the next ones.
+    min_context_slot: AtomicU64,
 
     /// The interval with which to poll for data
     poll_interval: Interval,
 
+    /// The interval with which to poll the mapping and product accounts, if
+    /// they are not polled on every poll
+    metadata_poll_interval: Option<Interval>,
+
+    /// The mapping and product accounts found by the last metadata poll, kept
+    /// if they are not polled on every poll
+    metadata: Option<PolledMetadata>,
+
+    /// Whether the next poll is a metadata poll, as the metadata poll interval
+    /// ticked or the last metadata poll failed
+    metadata_due: bool,
+
+    /// Oracle config, watched for changes to the poll interval
+    config_rx: watch::Receiver<Config>,
+
     /// Passed from Oracle config
     max_lookup_batch_size: usize,
 
+    /// Encoding of the polled account data
+    account_encoding: AccountEncoding,
+
     mapping_key: Pubkey,
 
-    /// Logger
-    logger: Logger,
+    /// Owner of the polled accounts
+    program_key: Pubkey,
+
+    /// Polled instead of the RPC node in simulation
+    simulated_cluster: Option<Arc<SimulatedCluster>>,
+
+    /// Signals the end of the recorded polls when replaying a recording, in
+    /// place of the poll interval
+    replay_polls_rx: Option<mpsc::Receiver<()>>,
+
+    /// Records the polled accounts, if enabled
+    recorder: Option<Arc<Recorder>>,
+
+    /// Reports whether the last poll succeeded
+    health: ComponentHealth,
+}
+
+/// The mapping and product accounts found by a metadata poll, the price
+/// accounts of which are polled until the next one
+struct PolledMetadata {
+    mapping_accounts: HashMap<Pubkey, MappingAccount>,
+    product_accounts: HashMap<Pubkey, ProductEntry>,
+}
+
+/// An HTTP RPC endpoint polled by the Poller
+struct RpcEndpoint {
+    rpc_client:        RpcClient,
+    /// Host of the endpoint, as labeled in the metrics
+    label:             String,
+    /// Until when the endpoint is not polled, after it served bad accounts
+    quarantined_until: Mutex<Option<Instant>>,
+}
+
+impl RpcEndpoint {
+    fn is_quarantined(&self) -> bool {
+        let mut quarantined_until = self.quarantined_until.lock();
+        match *quarantined_until {
+            Some(until) if until > Instant::now() => true,
+            Some(_) => {
+                info!(rpc_endpoint = %self.label, "Poller: RPC endpoint released from quarantine");
+                *quarantined_until = None;
+                false
+            }
+            None => false,
+        }
+    }
+
+    fn quarantine(&self, duration: Duration) {
+        *self.quarantined_until.lock() = Some(Instant::now() + duration);
+    }
 }
 
 impl Poller {
     pub fn new(
         data_tx: mpsc::Sender<Data>,
         publisher_permissions_tx: mpsc::Sender<HashMap<Pubkey, HashSet<Pubkey>>>,
+        keypair_loader_permissions_tx: watch::Sender<HashMap<Pubkey, HashSet<Pubkey>>>,
+        network_name: &str,
         rpc_url: &str,
         rpc_timeout: Duration,
         commitment: CommitmentLevel,
-        poll_interval_duration: Duration,
+        config_rx: watch::Receiver<Config>,
         max_lookup_batch_size: usize,
+        account_encoding: AccountEncoding,
         mapping_key: Pubkey,
-        logger: Logger,
+        program_key: Pubkey,
+        simulated_cluster: Option<Arc<SimulatedCluster>>,
+        replay_polls_rx: Option<mpsc::Receiver<()>>,
+        recorder: Option<Arc<Recorder>>,
+        health: ComponentHealth,
     ) -> Self {
-        let rpc_client = RpcClient::new_with_timeout_and_commitment(
-            rpc_url.to_string(),
-            rpc_timeout,
-            CommitmentConfig { commitment },
-        );
-        let poll_interval = tokio::time::interval(poll_interval_duration);
+        let rpc_endpoints = std::iter::once(rpc_url)
+            .chain(
+                config_rx
+                    .borrow()
+                    .fallback_rpc_urls
+                    .iter()
+                    .map(String::as_str),
+            )
+            .map(|url| RpcEndpoint {
+                rpc_client:        instrumented_rpc::new_rpc_client(
+                    network_name,
+                    url,
+                    rpc_timeout,
+                    CommitmentConfig { commitment },
+                ),
+                label:             instrumented_rpc::endpoint_label(url),
+                quarantined_until: Mutex::new(None),
+            })
+            .collect();
+        let poll_interval = tokio::time::interval(config_rx.borrow().poll_interval_duration);
+        let metadata_poll_interval = config_rx
+            .borrow()
+            .metadata_poll_interval_duration
+            .map(metadata_poll_interval);
 
         Poller {
             data_tx,
             publisher_permissions_tx,
-            rpc_client,
+            keypair_loader_permissions_tx,
+            network_name: network_name.to_string(),
+            rpc_endpoints,
+            min_context_slot: AtomicU64::new(0),
             poll_interval,
+            metadata_poll_interval,
+            metadata: None,
+            metadata_due: true,
+            config_rx,
             max_lookup_batch_size,
+            account_encoding,
             mapping_key,
-            logger,
+            program_key,
+            simulated_cluster,
+            replay_polls_rx,
+            recorder,
+            health,
         }
     }
 
     pub async fn run(&mut self) {
         loop {
-            self.poll_interval.tick().await;
-            info!(self.logger, "fetching all pyth account data");
-            if let Err(err) = self.poll_and_send().await {
-                error!(self.logger, "{:#}", err; "error" => format!("{:?}", err));
+            tokio::select! {
+                _ = self.poll_interval.tick(), if self.replay_polls_rx.is_none() => {}
+                _ = Self::next_metadata_poll(&mut self.metadata_poll_interval), if self.replay_polls_rx.is_none() => {
+                    self.metadata_due = true;
+                }
+                Some(()) = async {
+                    match &mut self.replay_polls_rx {
+                        Some(replay_polls_rx) => replay_polls_rx.recv().await,
+                        None => None,
+                    }
+                } => {}
+                Ok(()) = self.config_rx.changed() => {
+                    self.reload_poll_interval();
+                    continue;
+                }
+            }
+            match self.poll_and_send().await {
+                Ok(()) => self.health.healthy("last poll succeeded"),
+                Err(err) => {
+                    error!(error = ?err, kind = %error::record("oracle", &err), "{:#}", err);
+                    self.health
+                        .unhealthy(format!("last poll failed: {:#}", err));
+                }
+            }
+        }
+    }
+
+    /// Wait for the next tick of the metadata poll interval, or never if the
+    /// metadata is polled on every poll
+    async fn next_metadata_poll(metadata_poll_interval: &mut Option<Interval>) {
+        match metadata_poll_interval {
+            Some(metadata_poll_interval) => {
+                metadata_poll_interval.tick().await;
+            }
+            None => future::pending().await,
+        }
+    }
+
+    /// Restart the poll intervals if their durations changed in the reloaded config
+    fn reload_poll_interval(&mut self) {
+        let config = self.config_rx.borrow().clone();
+        if config.poll_interval_duration != self.poll_interval.period() {
+            let poll_interval_duration = config.poll_interval_duration;
+            info!(?poll_interval_duration, "Poller: poll interval changed");
+            self.poll_interval = tokio::time::interval(poll_interval_duration);
+        }
+        let metadata_poll_interval_duration = config.metadata_poll_interval_duration;
+        if metadata_poll_interval_duration
+            != self
+                .metadata_poll_interval
+                .as_ref()
+                .map(|metadata_poll_interval| metadata_poll_interval.period())
+        {
+            info!(
+                ?metadata_poll_interval_duration,
+                "Poller: metadata poll interval changed"
+            );
+            self.metadata_poll_interval =
+                metadata_poll_interval_duration.map(metadata_poll_interval);
+            if self.metadata_poll_interval.is_none() {
+                self.metadata = None;
             }
         }
     }
 
     async fn poll_and_send(&mut self) -> Result<()> {
         let fresh_data = self.poll().await?;
+        if let Some(recorder) = &self.recorder {
+            recorder.record_poll_end();
+        }
 
         self.publisher_permissions_tx
             .send(fresh_data.publisher_permissions.clone())
             .await
             .context("Updating permissioned price accounts for exporter")?;
+        // The remote keypair loader may not be watching
+        let _ = self
+            .keypair_loader_permissions_tx
+            .send(fresh_data.publisher_permissions.clone());
 
         self.data_tx
             .send(fresh_data)
@@ -421,12 +1313,49 @@ impl Poller {
         Ok(())
     }
 
-    async fn poll(&self) -> Result<Data> {
+    async fn poll(&mut self) -> Result<Data> {
+        // In between the metadata polls, only the known price accounts are polled
+        if let Some(metadata) = self.metadata.as_ref().filter(|_| !self.metadata_due) {
+            debug!("fetching pyth price accounts");
+            let price_accounts = self
+                .fetch_price_accounts(&metadata.product_accounts)
+                .await?;
+            return Ok(Self::with_publisher_permissions(
+                metadata.mapping_accounts.clone(),
+                metadata.product_accounts.clone(),
+                price_accounts,
+            ));
+        }
+
+        info!("fetching all pyth account data");
         let mapping_accounts = self.fetch_mapping_accounts(self.mapping_key).await?;
         let (product_accounts, price_accounts) = self
             .fetch_product_and_price_accounts(mapping_accounts.values())
             .await?;
 
+        // A failed metadata poll stays due, and is retried on the next poll
+        if self.metadata_poll_interval.is_some() {
+            self.metadata = Some(PolledMetadata {
+                mapping_accounts: mapping_accounts.clone(),
+                product_accounts: product_accounts.clone(),
+            });
+        }
+        self.metadata_due = false;
+
+        Ok(Self::with_publisher_permissions(
+            mapping_accounts,
+            product_accounts,
+            price_accounts,
+        ))
+    }
+
+    /// The polled accounts, with the publisher permissions listed by the
+    /// price accounts
+    fn with_publisher_permissions(
+        mapping_accounts: HashMap<Pubkey, MappingAccount>,
+        product_accounts: HashMap<Pubkey, ProductEntry>,
+        price_accounts: HashMap<Pubkey, Arc<PriceEntry>>,
+    ) -> Data {
         let mut publisher_permissions = HashMap::new();
 
         for (price_key, price_entry) in price_accounts.iter() {
@@ -439,12 +1368,44 @@ impl Poller {
             }
         }
 
-        Ok(Data::new(
+        Data::new(
             mapping_accounts,
             product_accounts,
             price_accounts,
             publisher_permissions,
-        ))
+        )
+    }
+
+    /// Fetch the price accounts of the products found by the last metadata
+    /// poll, using the configured batch size
+    async fn fetch_price_accounts(
+        &self,
+        product_accounts: &HashMap<Pubkey, ProductEntry>,
+    ) -> Result<HashMap<Pubkey, Arc<PriceEntry>>> {
+        let price_keys = product_accounts
+            .values()
+            .flat_map(|product| product.price_accounts.iter().cloned())
+            .collect::<Vec<_>>();
+
+        let mut price_entries = HashMap::new();
+        for price_key_batch in price_keys.chunks(self.max_lookup_batch_size) {
+            let price_accounts = self
+                .get_multiple_accounts(price_key_batch, AccountKind::Price)
+                .await?;
+            for (price_key, price_account) in price_key_batch.iter().zip(price_accounts) {
+                match price_account {
+                    Some(price_acc) => {
+                        let price = load_price_account(&price_acc.data)
+                            .context(format!("Could not parse price account at {}", price_key))?;
+                        price_entries.insert(*price_key, Arc::new(*price));
+                    }
+                    None => {
+                        warn!(%price_key, "Could not look up price account on chain, skipping")
+                    }
+                }
+            }
+        }
+        Ok(price_entries)
     }
 
     async fn fetch_mapping_accounts(
@@ -457,8 +1418,7 @@ impl Poller {
         while account_key != Pubkey::default() {
             let account = *load_mapping_account(
                 &self
-                    .rpc_client
-                    .get_account_data(&account_key)
+                    .get_account_data(&account_key, AccountKind::Mapping)
                     .await
                     .with_context(|| format!("load mapping account {}", account_key))?,
             )?;
@@ -473,7 +1433,10 @@ impl Poller {
     async fn fetch_product_and_price_accounts<'a, A>(
         &self,
         mapping_accounts: A,
-    ) -> Result<(HashMap<Pubkey, ProductEntry>, HashMap<Pubkey, PriceEntry>)>
+    ) -> Result<(
+        HashMap<Pubkey, ProductEntry>,
+        HashMap<Pubkey, Arc<PriceEntry>>,
+    )>
     where
         A: IntoIterator<Item = &'a MappingAccount>,
     {
@@ -509,13 +1472,18 @@ impl Poller {
     async fn fetch_batch_of_product_and_price_accounts(
         &self,
         product_key_batch: &[Pubkey],
-    ) -> Result<(HashMap<Pubkey, ProductEntry>, HashMap<Pubkey, PriceEntry>)> {
+    ) -> Result<(
+        HashMap<Pubkey, ProductEntry>,
+        HashMap<Pubkey, Arc<PriceEntry>>,
+    )> {
         let mut product_entries = HashMap::new();
 
         let product_keys = product_key_batch;
 
         // Look up the batch with a single request
-        let product_accounts = self.rpc_client.get_multiple_accounts(product_keys).await?;
+        let product_accounts = self
+            .get_multiple_accounts(product_keys, AccountKind::Product)
+            .await?;
 
         // Log missing products, fill the product entries with initial values
         for (product_key, product_account) in product_keys.iter().zip(product_accounts) {
@@ -531,8 +1499,7 @@ impl Poller {
                     },
                 );
             } else {
-                warn!(self.logger, "Oracle: Could not find product on chain, skipping";
-		      "product_key" => product_key.to_string(),);
+                warn!(%product_key, "Oracle: Could not find product on chain, skipping");
             }
         }
 
@@ -548,8 +1515,7 @@ impl Poller {
 
         while !todo.is_empty() {
             let price_accounts = self
-                .rpc_client
-                .get_multiple_accounts(todo.as_slice())
+                .get_multiple_accounts(todo.as_slice(), AccountKind::Price)
                 .await?;
 
             // Any non-zero price.next pubkey will be gathered here and looked up on next iteration
@@ -565,11 +1531,12 @@ impl Poller {
 
                     if let Some(prod) = product_entries.get_mut(&price.prod) {
                         prod.price_accounts.push(*price_key);
-                        price_entries.insert(*price_key, *price);
+                        price_entries.insert(*price_key, Arc::new(*price));
                     } else {
-                        warn!(self.logger, "Could not find product entry for price, listed in its prod field, skipping";
-                         "missing_product" => price.prod.to_string(),
-                         "price_key" => price_key.to_string(),
+                        warn!(
+                            missing_product = %price.prod,
+                            %price_key,
+                            "Could not find product entry for price, listed in its prod field, skipping"
                         );
 
                         continue;
@@ -579,7 +1546,7 @@ impl Poller {
                         next_todo.push(price.next.clone());
                     }
                 } else {
-                    warn!(self.logger, "Could not look up price account on chain, skipping"; "price_key" => price_key.to_string(),);
+                    warn!(%price_key, "Could not look up price account on chain, skipping");
                     continue;
                 }
             }
@@ -588,15 +1555,157 @@ impl Poller {
         }
         Ok((product_entries, price_entries))
     }
+
+    async fn get_account_data(&self, key: &Pubkey, kind: AccountKind) -> Result<Vec<u8>> {
+        self.get_multiple_accounts(&[*key], kind)
+            .await?
+            .pop()
+            .flatten()
+            .map(|account| account.data)
+            .ok_or_else(|| anyhow!("account {} not found", key))
+    }
+
+    /// Read the accounts from the RPC node, or the simulated cluster, recording
+    /// them if enabled
+    async fn get_multiple_accounts(
+        &self,
+        keys: &[Pubkey],
+        kind: AccountKind,
+    ) -> Result<Vec<Option<Account>>> {
+        let (slot, accounts) = match &self.simulated_cluster {
+            Some(simulated_cluster) => (0, simulated_cluster.get_multiple_accounts(keys)),
+            None => {
+                let response = self.get_multiple_accounts_from_rpc(keys, kind).await?;
+                (response.context.slot, response.value)
+            }
+        };
+
+        if let Some(recorder) = &self.recorder {
+            for (key, account) in keys.iter().zip(&accounts) {
+                if let Some(account) = account {
+                    recorder.record_account(slot, key, account, true);
+                }
+            }
+        }
+
+        Ok(accounts)
+    }
+
+    /// Read the accounts from the first RPC endpoint which has reached the
+    /// highest slot seen so far, raising it to the slot of the response. The
+    /// endpoints serving accounts other than those expected are quarantined.
+    async fn get_multiple_accounts_from_rpc(
+        &self,
+        keys: &[Pubkey],
+        kind: AccountKind,
+    ) -> Result<Response<Vec<Option<Account>>>> {
+        let min_context_slot = self.min_context_slot.load(Ordering::Relaxed);
+        for endpoint in &self.rpc_endpoints {
+            if endpoint.is_quarantined() {
+                continue;
+            }
+            let rpc_client = &endpoint.rpc_client;
+            // The accounts are decoded, and decompressed if needed, by the client
+            let result = rpc_client
+                .get_multiple_accounts_with_config(
+                    keys,
+                    RpcAccountInfoConfig {
+                        encoding: Some(self.account_encoding.into()),
+                        commitment: Some(rpc_client.commitment()),
+                        min_context_slot: Some(min_context_slot),
+                        ..RpcAccountInfoConfig::default()
+                    },
+                )
+                .await;
+            match result {
+                Ok(response) => {
+                    if let Some((key, mismatch)) = self.check_accounts(keys, &response.value, kind)
+                    {
+                        ACCOUNT_CHECK_METRICS.record(
+                            &self.network_name,
+                            &endpoint.label,
+                            mismatch.label(),
+                        );
+                        let quarantine_duration = self.config_rx.borrow().rpc_quarantine_duration;
+                        warn!(
+                            rpc_endpoint = %endpoint.label,
+                            account = %key,
+                            ?quarantine_duration,
+                            "Poller: RPC endpoint served a bad account ({}), quarantining it",
+                            mismatch,
+                        );
+                        endpoint.quarantine(quarantine_duration);
+                        continue;
+                    }
+                    self.min_context_slot
+                        .fetch_max(response.context.slot, Ordering::Relaxed);
+                    return Ok(response);
+                }
+                Err(err) if is_min_context_slot_not_reached(&err) => {
+                    warn!(
+                        rpc_endpoint = %endpoint.label,
+                        min_context_slot,
+                        "Poller: RPC node is behind the latest slot seen, trying the next endpoint",
+                    );
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Err(anyhow!(
+            "no RPC endpoint out of quarantine has reached slot {}",
+            min_context_slot
+        ))
+    }
+
+    /// The first of the accounts found which is not of the expected kind
+    fn check_accounts(
+        &self,
+        keys: &[Pubkey],
+        accounts: &[Option<Account>],
+        kind: AccountKind,
+    ) -> Option<(Pubkey, account_check::Mismatch)> {
+        keys.iter()
+            .zip(accounts)
+            .filter_map(|(key, account)| Some((key, account.as_ref()?)))
+            .find_map(|(key, account)| {
+                account_check::check(account, &self.program_key, kind)
+                    .err()
+                    .map(|mismatch| (*key, mismatch))
+            })
+    }
+}
+
+/// The metadata poll interval, which first ticks after its period, as the
+/// first poll already polls the metadata
+fn metadata_poll_interval(period: Duration) -> Interval {
+    tokio::time::interval_at(tokio::time::Instant::now() + period, period)
+}
+
+/// Whether the RPC node refused the request for not having reached its
+/// minimum context slot yet
+fn is_min_context_slot_not_reached(err: &ClientError) -> bool {
+    matches!(
+        err.kind(),
+        ClientErrorKind::RpcError(RpcError::RpcResponseError { code, .. })
+            if *code == JSON_RPC_SERVER_ERROR_MIN_CONTEXT_SLOT_NOT_REACHED
+    )
 }
 
 mod subscriber {
     use {
+        crate::agent::{
+            channel_monitor::ChannelMonitor,
+            error::{
+                self,
+                Error,
+            },
+            fault_injection,
+            health::ComponentHealth,
+        },
         anyhow::{
-            anyhow,
+            Context as _,
             Result,
         },
-        slog::Logger,
         solana_sdk::{
             account::Account,
             commitment_config::CommitmentLevel,
@@ -606,13 +1715,41 @@ mod subscriber {
             BlockchainShadow,
             SyncOptions,
         },
-        std::time::Duration,
-        tokio::sync::{
-            broadcast,
-            mpsc,
+        std::{
+            collections::HashSet,
+            time::Duration,
+        },
+        tokio::{
+            sync::{
+                broadcast,
+                mpsc,
+                watch,
+            },
+            time,
         },
     };
 
+    /// Delay before a shard first subscribes again after failing to, doubled
+    /// on each consecutive failure up to the maximum
+    const MIN_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+    const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+
+    /// The price accounts falling in the shard by the first byte of their key,
+    /// sorted
+    fn shard_accounts(
+        price_accounts: &HashSet<Pubkey>,
+        shard: usize,
+        shards: usize,
+    ) -> Vec<Pubkey> {
+        let mut account_keys: Vec<Pubkey> = price_accounts
+            .iter()
+            .filter(|account_key| account_key.as_ref()[0] as usize % shards == shard)
+            .cloned()
+            .collect();
+        account_keys.sort();
+        account_keys
+    }
+
     /// Subscriber subscribes to all changes on the given account, and sends those changes
     /// on updates_tx. This is a convenience wrapper around the Blockchain Shadow crate.
     pub struct Subscriber {
@@ -634,7 +1771,13 @@ mod subscriber {
         /// Channel on which updates are sent
         updates_tx: mpsc::Sender<(Pubkey, solana_sdk::account::Account)>,
 
-        logger: Logger,
+        /// Name under which the updates channel is monitored
+        updates_channel: String,
+
+        channel_monitor: ChannelMonitor,
+
+        /// Reports whether the subscription is connected
+        health: ComponentHealth,
     }
 
     impl Subscriber {
@@ -645,7 +1788,9 @@ mod subscriber {
             commitment: CommitmentLevel,
             account_key: Pubkey,
             updates_tx: mpsc::Sender<(Pubkey, solana_sdk::account::Account)>,
-            logger: Logger,
+            updates_channel: String,
+            channel_monitor: ChannelMonitor,
+            health: ComponentHealth,
         ) -> Self {
             Subscriber {
                 rpc_url,
@@ -654,21 +1799,122 @@ mod subscriber {
                 commitment,
                 account_key,
                 updates_tx,
-                logger,
+                updates_channel,
+                channel_monitor,
+                health,
             }
         }
 
         pub async fn run(&self) {
-            match self.start_shadow().await {
-                Ok(mut shadow_rx) => self.forward_updates(&mut shadow_rx).await,
-                Err(err) => error!(self.logger, "{:#}", err; "error" => format!("{:?}", err)),
+            loop {
+                match self.start_shadow().await {
+                    Ok((shadow, mut shadow_rx)) => {
+                        self.health.healthy("subscribed to account updates");
+                        // Only an injected disconnect stops forwarding the updates
+                        let reconnect_delay = tokio::select! {
+                            _ = self.forward_updates(&mut shadow_rx) => return,
+                            reconnect_delay = fault_injection::subscriber_disconnect() => reconnect_delay,
+                        };
+                        warn!(
+                            ?reconnect_delay,
+                            "fault injection: disconnecting the subscriber"
+                        );
+                        drop((shadow, shadow_rx));
+                        self.health.unhealthy("fault injection: disconnected");
+                        time::sleep(reconnect_delay).await;
+                    }
+                    Err(err) => {
+                        error!(error = ?err, kind = %error::record("oracle", &err), "{:#}", err);
+                        self.health
+                            .unhealthy(format!("could not subscribe: {:#}", err));
+                        return;
+                    }
+                }
+            }
+        }
+
+        /// Subscribe to the price accounts of the shard over a connection of
+        /// its own, subscribing again whenever the price accounts of the shard
+        /// change, and with an exponential backoff when subscribing fails
+        pub async fn run_shard(
+            &self,
+            shard: usize,
+            shards: usize,
+            mut price_accounts_rx: watch::Receiver<HashSet<Pubkey>>,
+        ) {
+            let mut reconnect_delay = MIN_RECONNECT_DELAY;
+            loop {
+                let account_keys = shard_accounts(&price_accounts_rx.borrow(), shard, shards);
+                // Wait for the polls to find the price accounts of the shard
+                if account_keys.is_empty() {
+                    if price_accounts_rx.changed().await.is_err() {
+                        return;
+                    }
+                    continue;
+                }
+
+                match self.start_shard_shadow(&account_keys).await {
+                    Ok((shadow, mut shadow_rx)) => {
+                        reconnect_delay = MIN_RECONNECT_DELAY;
+                        self.health.healthy(format!(
+                            "subscribed to the updates of {} accounts",
+                            account_keys.len()
+                        ));
+                        let disconnect_delay = loop {
+                            tokio::select! {
+                                _ = self.forward_updates(&mut shadow_rx) => return,
+                                changed = price_accounts_rx.changed() => {
+                                    if changed.is_err() {
+                                        return;
+                                    }
+                                    // Subscribe again to the new price accounts of the shard
+                                    if shard_accounts(&price_accounts_rx.borrow(), shard, shards)
+                                        != account_keys
+                                    {
+                                        break Duration::ZERO;
+                                    }
+                                }
+                                delay = fault_injection::subscriber_disconnect() => {
+                                    warn!(
+                                        reconnect_delay = ?delay,
+                                        "fault injection: disconnecting the subscriber"
+                                    );
+                                    self.health.unhealthy("fault injection: disconnected");
+                                    break delay;
+                                }
+                            }
+                        };
+                        drop((shadow, shadow_rx));
+                        time::sleep(disconnect_delay).await;
+                    }
+                    Err(err) => {
+                        error!(error = ?err, kind = %error::record("oracle", &err), "{:#}", err);
+                        self.health
+                            .unhealthy(format!("could not subscribe: {:#}", err));
+                        time::sleep(reconnect_delay).await;
+                        reconnect_delay = (reconnect_delay * 2).min(MAX_RECONNECT_DELAY);
+                    }
+                }
             }
         }
 
         async fn forward_updates(&self, shadow_rx: &mut broadcast::Receiver<(Pubkey, Account)>) {
+            // Whether the last forward failed, the subscriber being healthy again
+            // once an update is forwarded
+            let mut failed = false;
             loop {
-                if let Err(err) = self.forward_update(shadow_rx).await {
-                    error!(self.logger, "error forwarding updates: {:#}", err; "error" => format!("{:?}", err))
+                match self.forward_update(shadow_rx).await {
+                    Ok(()) if failed => {
+                        failed = false;
+                        self.health.healthy("forwarding account updates");
+                    }
+                    Ok(()) => {}
+                    Err(err) => {
+                        error!(error = ?err, kind = %error::record("oracle", &err), "error forwarding updates: {:#}", err);
+                        self.health
+                            .unhealthy(format!("error forwarding updates: {:#}", err));
+                        failed = true;
+                    }
                 }
             }
         }
@@ -677,16 +1923,21 @@ mod subscriber {
             &self,
             shadow_rx: &mut broadcast::Receiver<(Pubkey, Account)>,
         ) -> Result<()> {
-            self.updates_tx
-                .send(shadow_rx.recv().await?)
+            let update = shadow_rx.recv().await?;
+            self.channel_monitor
+                .send(&self.updates_channel, &self.updates_tx, update)
                 .await
-                .map_err(|_| anyhow!("failed to forward update"))
+                .map_err(|_| Error::ChannelClosed("oracle"))
+                .context("failed to forward update")
         }
 
         pub async fn start_shadow(
             &self,
-        ) -> Result<broadcast::Receiver<(Pubkey, solana_sdk::account::Account)>> {
-            debug!(self.logger, "subscribed to account updates"; "account" => self.account_key.to_string());
+        ) -> Result<(
+            BlockchainShadow,
+            broadcast::Receiver<(Pubkey, solana_sdk::account::Account)>,
+        )> {
+            debug!(account = %self.account_key, "subscribed to account updates");
 
             let shadow = BlockchainShadow::new_for_program(
                 &self.account_key,
@@ -703,7 +1954,221 @@ mod subscriber {
             )
             .await?;
 
-            Ok(shadow.updates_channel())
+            let shadow_rx = shadow.updates_channel();
+            Ok((shadow, shadow_rx))
+        }
+
+        async fn start_shard_shadow(
+            &self,
+            account_keys: &[Pubkey],
+        ) -> Result<(
+            BlockchainShadow,
+            broadcast::Receiver<(Pubkey, solana_sdk::account::Account)>,
+        )> {
+            debug!(
+                accounts = account_keys.len(),
+                "subscribed to account updates"
+            );
+
+            let shadow = BlockchainShadow::new_for_accounts(
+                &account_keys.to_vec(),
+                SyncOptions {
+                    network: solana_shadow::Network::Custom(
+                        self.rpc_url.clone(),
+                        self.wss_url.clone(),
+                    ),
+                    commitment: self.commitment,
+                    rpc_timeout: self.rpc_timeout,
+                    max_lag: Some(10000),
+                    ..SyncOptions::default()
+                },
+            )
+            .await?;
+
+            let shadow_rx = shadow.updates_channel();
+            Ok((shadow, shadow_rx))
+        }
+    }
+    #[cfg(test)]
+    mod tests {
+        use {
+            super::shard_accounts,
+            solana_sdk::pubkey::Pubkey,
+            std::collections::HashSet,
+        };
+
+        #[test]
+        fn test_every_price_account_is_in_exactly_one_shard() {
+            // Keys starting with every possible byte, and random ones
+            let price_accounts = (0..=u8::MAX)
+                .map(|first_byte| {
+                    let mut key = [0; 32];
+                    key[0] = first_byte;
+                    Pubkey::new_from_array(key)
+                })
+                .chain((0..100).map(|_| Pubkey::new_unique()))
+                .collect::<HashSet<_>>();
+            let mut all_accounts = price_accounts.iter().cloned().collect::<Vec<_>>();
+            all_accounts.sort();
+
+            assert_eq!(shard_accounts(&price_accounts, 0, 1), all_accounts);
+            for shards in 1..=16 {
+                let mut sharded_accounts = (0..shards)
+                    .flat_map(|shard| shard_accounts(&price_accounts, shard, shards))
+                    .collect::<Vec<_>>();
+                sharded_accounts.sort();
+                assert_eq!(sharded_accounts, all_accounts, "{} shards", shards);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            flush_tick,
+            Backpressure,
+            BackpressureConfig,
+            Deduplicator,
+            PriceEntry,
+        },
+        crate::agent::{
+            channel_monitor::{
+                self,
+                ChannelMonitor,
+            },
+            store::global,
+        },
+        opentelemetry::Context,
+        solana_sdk::pubkey::Pubkey,
+        std::{
+            sync::Arc,
+            time::Duration,
+        },
+        tokio::sync::mpsc,
+    };
+
+    #[test]
+    fn test_identical_updates_are_duplicates() {
+        let mut deduplicator = Deduplicator::new("test");
+        let (account_key, other_key) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut account = PriceEntry::default();
+        account.last_slot = 10;
+        account.agg.price = 100;
+
+        assert!(!deduplicator.is_duplicate(&account_key, &account, "poll"));
+        assert!(deduplicator.is_duplicate(&account_key, &account, "subscription"));
+        // The updates are remembered by price account
+        assert!(!deduplicator.is_duplicate(&other_key, &account, "poll"));
+
+        // Updates of a new slot, or with other data, are not duplicates
+        account.last_slot = 11;
+        assert!(!deduplicator.is_duplicate(&account_key, &account, "subscription"));
+        account.agg.price = 101;
+        assert!(!deduplicator.is_duplicate(&account_key, &account, "poll"));
+        assert!(deduplicator.is_duplicate(&account_key, &account, "subscription"));
+    }
+
+    fn price_account_update(account_key: Pubkey) -> global::Update {
+        global::Update::PriceAccountUpdate {
+            account_key,
+            account: Arc::new(PriceEntry::default()),
+            trace_context: Context::new(),
+        }
+    }
+
+    fn updated_account(update: global::Update) -> Pubkey {
+        match update {
+            global::Update::PriceAccountUpdate { account_key, .. } => account_key,
+            _ => panic!("not a price account update"),
         }
     }
+
+    async fn send(
+        backpressure: &mut Option<Backpressure>,
+        channel_monitor: &ChannelMonitor,
+        global_store_tx: &mpsc::Sender<global::Update>,
+        account_key: Pubkey,
+    ) {
+        backpressure
+            .as_mut()
+            .unwrap()
+            .send_price_account_update(
+                channel_monitor,
+                "global_store",
+                global_store_tx,
+                &account_key,
+                price_account_update(account_key),
+            )
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_held_back_update_is_flushed_once_the_global_store_drains() {
+        let channel_monitor = ChannelMonitor::new(channel_monitor::Config::default()).await;
+        let (global_store_tx, mut global_store_rx) = mpsc::channel(1);
+        let mut backpressure = Some(Backpressure::new(
+            BackpressureConfig {
+                send_timeout:     Duration::from_millis(10),
+                max_held_updates: 1,
+                flush_interval:   Duration::from_millis(10),
+            },
+            "test",
+        ));
+        let (first, second, third) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        // The first update fills the channel, and the second is held back as
+        // the Global Store does not accept it in time
+        send(&mut backpressure, &channel_monitor, &global_store_tx, first).await;
+        assert!(!backpressure.as_ref().unwrap().is_holding());
+        send(
+            &mut backpressure,
+            &channel_monitor,
+            &global_store_tx,
+            second,
+        )
+        .await;
+        assert!(backpressure.as_ref().unwrap().is_holding());
+        // Only one update is held back, so that of another account is dropped
+        send(&mut backpressure, &channel_monitor, &global_store_tx, third).await;
+        assert_eq!(
+            backpressure
+                .as_ref()
+                .unwrap()
+                .held_updates
+                .keys()
+                .collect::<Vec<_>>(),
+            vec![&second]
+        );
+
+        // Once the Global Store drains, the held update is handed over on the
+        // next tick, without waiting for another update
+        assert_eq!(
+            updated_account(global_store_rx.recv().await.unwrap()),
+            first
+        );
+        flush_tick(&mut backpressure)
+            .await
+            .unwrap()
+            .flush(&global_store_tx)
+            .unwrap();
+        assert!(!backpressure.as_ref().unwrap().is_holding());
+        assert_eq!(
+            updated_account(global_store_rx.recv().await.unwrap()),
+            second
+        );
+        assert!(global_store_rx.try_recv().is_err());
+
+        // And the flush interval no longer ticks
+        assert!(
+            tokio::time::timeout(Duration::from_millis(50), flush_tick(&mut backpressure))
+                .await
+                .is_err()
+        );
+    }
 }
@@ -0,0 +1,296 @@
+// The account updates seen by an Oracle can be recorded to a log file, and replayed
+// in simulation to reproduce the exact sequence of updates going through the Oracle
+// and the stores. The log is a sequence of bincode-encoded records: the accounts
+// read by each poll, followed by the end of the poll, and the accounts received
+// from the subscription. Replaying the log applies the polled accounts to the
+// simulated cluster and polls it where the recorded polls ended, and forwards the
+// subscription updates to the Oracle, at the original speed or faster.
+//
+// The records are written by a thread of the Recorder, so that the Oracle's tasks
+// never wait on the disk. It buffers the records, flushing them periodically and
+// when the recording ends.
+use {
+    super::simulation::SimulatedCluster,
+    anyhow::{
+        Context as _,
+        Result,
+    },
+    serde::{
+        Deserialize,
+        Serialize,
+    },
+    solana_sdk::{
+        account::Account,
+        pubkey::Pubkey,
+    },
+    std::{
+        fs::File,
+        io::{
+            self,
+            BufReader,
+            BufWriter,
+            Write,
+        },
+        path::{
+            Path,
+            PathBuf,
+        },
+        sync::{
+            mpsc as std_mpsc,
+            Arc,
+        },
+        thread,
+        time::Duration,
+    },
+    tokio::{
+        sync::mpsc,
+        time::{
+            self,
+            Instant,
+        },
+    },
+};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Record {
+    /// An account read by a poll, or received from the subscription. The slot
+    /// of subscription updates is the last slot of price accounts, and 0 for
+    /// other accounts.
+    Account {
+        elapsed_ms: u64,
+        slot:       u64,
+        key:        Pubkey,
+        account:    Account,
+        polled:     bool,
+    },
+    /// The end of a poll, after all the accounts it read
+    PollEnd { elapsed_ms: u64 },
+}
+
+impl Record {
+    /// Time since the start of the recording
+    fn elapsed(&self) -> Duration {
+        match self {
+            Record::Account { elapsed_ms, .. } | Record::PollEnd { elapsed_ms } => {
+                Duration::from_millis(*elapsed_ms)
+            }
+        }
+    }
+}
+
+/// Interval at which the buffered records are flushed to the recording
+const FLUSH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Writes the account updates seen by an Oracle to a log file
+pub struct Recorder {
+    /// Records to write, None once the recording ended
+    records_tx: Option<std_mpsc::Sender<Record>>,
+    writer:     Option<thread::JoinHandle<()>>,
+    start:      Instant,
+}
+
+impl Recorder {
+    /// Start a new recording, replacing the file at the given path
+    pub fn new(path: &Path) -> Result<Self> {
+        let file =
+            File::create(path).with_context(|| format!("creating recording {}", path.display()))?;
+        info!(path = %path.display(), "recording account updates");
+        let (records_tx, records_rx) = std_mpsc::channel();
+        let path = path.to_path_buf();
+        let writer = thread::Builder::new()
+            .name("recorder".to_string())
+            .spawn(move || write_records(path, BufWriter::new(file), records_rx))
+            .context("spawning the recording writer")?;
+        Ok(Recorder {
+            records_tx: Some(records_tx),
+            writer:     Some(writer),
+            start:      Instant::now(),
+        })
+    }
+
+    pub fn record_account(&self, slot: u64, key: &Pubkey, account: &Account, polled: bool) {
+        self.record(Record::Account {
+            elapsed_ms: self.elapsed_ms(),
+            slot,
+            key: *key,
+            account: account.clone(),
+            polled,
+        })
+    }
+
+    pub fn record_poll_end(&self) {
+        self.record(Record::PollEnd {
+            elapsed_ms: self.elapsed_ms(),
+        })
+    }
+
+    fn elapsed_ms(&self) -> u64 {
+        self.start.elapsed().as_millis() as u64
+    }
+
+    fn record(&self, record: Record) {
+        if let Some(records_tx) = &self.records_tx {
+            // The writer only stops once the recording ended
+            let _ = records_tx.send(record);
+        }
+    }
+}
+
+impl Drop for Recorder {
+    /// End the recording, waiting for the buffered records to be written
+    fn drop(&mut self) {
+        self.records_tx = None;
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+/// Write the records to the recording until the Recorder is dropped, flushing
+/// them every FLUSH_INTERVAL
+fn write_records(
+    path: PathBuf,
+    mut writer: BufWriter<File>,
+    records_rx: std_mpsc::Receiver<Record>,
+) {
+    let mut last_flush = std::time::Instant::now();
+    loop {
+        let (result, ended) = match records_rx.recv_timeout(FLUSH_INTERVAL) {
+            Ok(record) => (
+                bincode::serialize_into(&mut writer, &record).map_err(anyhow::Error::from),
+                false,
+            ),
+            Err(std_mpsc::RecvTimeoutError::Timeout) => (Ok(()), false),
+            Err(std_mpsc::RecvTimeoutError::Disconnected) => (Ok(()), true),
+        };
+        let result = result.and_then(|()| {
+            if ended || last_flush.elapsed() >= FLUSH_INTERVAL {
+                last_flush = std::time::Instant::now();
+                writer.flush()?;
+            }
+            Ok(())
+        });
+        if let Err(err) = result {
+            error!(
+                error = ?err,
+                path = %path.display(),
+                "could not write to recording: {:#}",
+                err
+            );
+        }
+        if ended {
+            return;
+        }
+    }
+}
+
+/// Replay a recording through the simulated cluster and the Oracle, with the
+/// delays between the records divided by `speed`
+pub async fn replay(
+    path: PathBuf,
+    speed: f64,
+    simulated_cluster: Arc<SimulatedCluster>,
+    updates_tx: mpsc::Sender<(Pubkey, Account)>,
+    polls_tx: mpsc::Sender<()>,
+) -> Result<()> {
+    let mut reader = BufReader::new(
+        File::open(&path).with_context(|| format!("opening recording {}", path.display()))?,
+    );
+    info!(path = %path.display(), speed, "replaying account updates");
+
+    let start = Instant::now();
+    let mut replayed = 0;
+    while let Some(record) = read_record(&mut reader)? {
+        time::sleep_until(start + record.elapsed().div_f64(speed)).await;
+        match record {
+            Record::Account {
+                key,
+                account,
+                polled,
+                ..
+            } => {
+                simulated_cluster.set_account(key, account.clone());
+                if !polled {
+                    updates_tx.send((key, account)).await?;
+                }
+            }
+            Record::PollEnd { .. } => polls_tx.send(()).await?,
+        }
+        replayed += 1;
+    }
+
+    info!(records = replayed, "replay complete");
+    Ok(())
+}
+
+/// Read the next record, or None at the end of the recording
+fn read_record(reader: &mut impl io::Read) -> Result<Option<Record>> {
+    match bincode::deserialize_from(reader) {
+        Ok(record) => Ok(Some(record)),
+        Err(err) => match *err {
+            bincode::ErrorKind::Io(ref io_err) if io_err.kind() == io::ErrorKind::UnexpectedEof => {
+                Ok(None)
+            }
+            _ => Err(err).context("reading recording"),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            read_record,
+            Record,
+            Recorder,
+        },
+        solana_sdk::{
+            account::Account,
+            pubkey::Pubkey,
+        },
+        std::{
+            fs::File,
+            io::BufReader,
+        },
+    };
+
+    #[tokio::test]
+    async fn test_recorded_updates_are_read_back_in_order() {
+        let path = std::env::temp_dir().join(format!("recording-{}.bin", Pubkey::new_unique()));
+        let key = Pubkey::new_unique();
+        let account = Account {
+            lamports: 42,
+            data: vec![1, 2, 3],
+            ..Default::default()
+        };
+
+        let recorder = Recorder::new(&path).unwrap();
+        recorder.record_account(10, &key, &account, true);
+        recorder.record_poll_end();
+        recorder.record_account(11, &key, &account, false);
+        drop(recorder);
+
+        let mut reader = BufReader::new(File::open(&path).unwrap());
+        let mut records = vec![];
+        while let Some(record) = read_record(&mut reader).unwrap() {
+            records.push(record);
+        }
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(records.len(), 3);
+        assert!(matches!(
+            &records[0],
+            Record::Account { slot: 10, key: recorded_key, account: recorded_account, polled: true, .. }
+                if *recorded_key == key && *recorded_account == account
+        ));
+        assert!(matches!(records[1], Record::PollEnd { .. }));
+        assert!(matches!(
+            records[2],
+            Record::Account {
+                slot: 11,
+                polled: false,
+                ..
+            }
+        ));
+    }
+}
@@ -0,0 +1,324 @@
+// Signers sign the transactions published by the Exporter. The local signer holds
+// the keypair in memory. The remote signer sends the serialized message of each
+// transaction to a remote signing service and receives the signature, so that the
+// private key never has to be present on the publishing host.
+pub mod kms;
+pub mod ledger;
+
+use {
+    super::instrumented_rpc::endpoint_label,
+    crate::agent::{
+        dump::redacted,
+        metrics::SIGNER_METRICS,
+    },
+    anyhow::{
+        anyhow,
+        Context as _,
+        Result,
+    },
+    async_trait::async_trait,
+    serde::{
+        Deserialize,
+        Serialize,
+    },
+    solana_sdk::{
+        pubkey::Pubkey,
+        signature::{
+            Keypair,
+            Signature,
+        },
+        signer::Signer as _,
+    },
+    std::{
+        fmt,
+        str::FromStr,
+        time::{
+            Duration,
+            Instant,
+        },
+    },
+    tokio::time,
+};
+
+/// Signs the messages of the transactions published on behalf of a single
+/// publish key
+#[async_trait]
+pub trait Signer: Send + Sync {
+    /// The publish key, which pays for and signs the transactions
+    fn pubkey(&self) -> Pubkey;
+
+    /// Sign the serialized message of a transaction
+    async fn sign_message(&self, message: &[u8]) -> Result<Signature>;
+}
+
+/// Signs with a keypair held in memory
+pub struct KeypairSigner {
+    keypair: Keypair,
+}
+
+impl KeypairSigner {
+    pub fn new(keypair: &Keypair) -> Result<Self> {
+        // Keypair is not Clone
+        let keypair = Keypair::from_bytes(&keypair.to_bytes())
+            .context("INTERNAL: Could not convert keypair to bytes and back")?;
+        Ok(KeypairSigner { keypair })
+    }
+}
+
+#[async_trait]
+impl Signer for KeypairSigner {
+    fn pubkey(&self) -> Pubkey {
+        self.keypair.pubkey()
+    }
+
+    async fn sign_message(&self, message: &[u8]) -> Result<Signature> {
+        Ok(self.keypair.sign_message(message))
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// URL of the signing endpoint of the remote signing service
+    pub url:         String,
+    /// Public key of the keypair held by the remote signing service, used
+    /// to publish the updates not submitted on behalf of a specific publisher
+    pub publish_key: String,
+    /// Bearer token authenticating the agent to the remote signing service.
+    /// Can be read from a file with `auth_token_file`.
+    pub auth_token:  Option<String>,
+    /// Timeout of a single signing request
+    #[serde(with = "humantime_serde")]
+    pub timeout:     Duration,
+    /// Number of times a failed signing request is retried
+    pub max_retries: u32,
+    /// Delay between the retries of a failed signing request
+    #[serde(with = "humantime_serde")]
+    pub retry_delay: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            url:         "http://127.0.0.1:8910/sign".to_string(),
+            publish_key: "".to_string(),
+            auth_token:  None,
+            timeout:     Duration::from_millis(500),
+            max_retries: 2,
+            retry_delay: Duration::from_millis(50),
+        }
+    }
+}
+
+impl fmt::Debug for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Config")
+            .field("url", &self.url)
+            .field("publish_key", &self.publish_key)
+            .field("auth_token", &redacted(self.auth_token.as_deref()))
+            .field("timeout", &self.timeout)
+            .field("max_retries", &self.max_retries)
+            .field("retry_delay", &self.retry_delay)
+            .finish()
+    }
+}
+
+#[derive(Serialize)]
+struct SignRequest {
+    /// Base58-encoded public key to sign with
+    pubkey:  String,
+    /// Base64-encoded serialized transaction message
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct SignResponse {
+    /// Base58-encoded signature of the message
+    signature: String,
+}
+
+/// Signs by sending the messages to a remote signing service over HTTP.
+///
+/// The service receives a POST request with a JSON body of the form
+/// `{"pubkey": "<base58>", "message": "<base64>"}` and responds with
+/// `{"signature": "<base58>"}`. The signature is verified before use.
+pub struct RemoteSigner {
+    config:      Config,
+    publish_key: Pubkey,
+    client:      reqwest::Client,
+    /// Network and endpoint labels of the signing metrics
+    network:     String,
+    endpoint:    String,
+}
+
+impl RemoteSigner {
+    pub fn new(config: Config, network: &str) -> Result<Self> {
+        let publish_key = Pubkey::from_str(&config.publish_key)
+            .with_context(|| format!("invalid remote signer publish key {}", config.publish_key))?;
+        let client = reqwest::Client::builder()
+            .timeout(config.timeout)
+            .build()
+            .context("building remote signer HTTP client")?;
+        Ok(RemoteSigner {
+            network: network.to_string(),
+            endpoint: endpoint_label(&config.url),
+            config,
+            publish_key,
+            client,
+        })
+    }
+
+    async fn request_signature(&self, message: &[u8]) -> Result<Signature> {
+        let mut request = self.client.post(&self.config.url).json(&SignRequest {
+            pubkey:  self.publish_key.to_string(),
+            message: base64::encode(message),
+        });
+        if let Some(auth_token) = &self.config.auth_token {
+            request = request.bearer_auth(auth_token);
+        }
+
+        let response = request
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<SignResponse>()
+            .await?;
+        let signature = Signature::from_str(&response.signature)
+            .with_context(|| format!("invalid signature {}", response.signature))?;
+
+        if !signature.verify(self.publish_key.as_ref(), message) {
+            return Err(anyhow!(
+                "signature {} does not match publish key {}",
+                signature,
+                self.publish_key
+            ));
+        }
+
+        Ok(signature)
+    }
+}
+
+#[async_trait]
+impl Signer for RemoteSigner {
+    fn pubkey(&self) -> Pubkey {
+        self.publish_key
+    }
+
+    async fn sign_message(&self, message: &[u8]) -> Result<Signature> {
+        let start = Instant::now();
+        let mut attempt = 0;
+        let result = loop {
+            match self.request_signature(message).await {
+                Ok(signature) => break Ok(signature),
+                Err(err) if attempt < self.config.max_retries => {
+                    attempt += 1;
+                    SIGNER_METRICS.retried(&self.network, &self.endpoint);
+                    debug!(
+                        attempt,
+                        "Remote signer: signing failed, retrying: {:#}", err
+                    );
+                    time::sleep(self.config.retry_delay).await;
+                }
+                Err(err) => break Err(err),
+            }
+        };
+        SIGNER_METRICS.observe(
+            &self.network,
+            &self.endpoint,
+            start.elapsed(),
+            result.is_ok(),
+        );
+
+        result.with_context(|| {
+            format!(
+                "remote signer {} failed after {} attempt(s)",
+                self.endpoint,
+                attempt + 1
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            Config,
+            RemoteSigner,
+            Signer,
+        },
+        serde_json::json,
+        solana_sdk::{
+            signature::Keypair,
+            signer::Signer as _,
+        },
+        std::{
+            collections::HashMap,
+            sync::{
+                atomic::{
+                    AtomicUsize,
+                    Ordering,
+                },
+                Arc,
+            },
+        },
+        warp::{
+            http::StatusCode,
+            Filter,
+        },
+    };
+
+    #[tokio::test]
+    async fn test_remote_signer_retries() {
+        let keypair = Arc::new(Keypair::new());
+        let publish_key = keypair.pubkey();
+        let requests = Arc::new(AtomicUsize::new(0));
+
+        // The signing service fails the first request
+        let route = warp::post().and(warp::body::json()).map({
+            let requests = requests.clone();
+            move |body: HashMap<String, String>| {
+                if requests.fetch_add(1, Ordering::SeqCst) == 0 {
+                    return warp::reply::with_status(
+                        warp::reply::json(&json!({})),
+                        StatusCode::SERVICE_UNAVAILABLE,
+                    );
+                }
+                assert_eq!(body["pubkey"], publish_key.to_string());
+                let message = base64::decode(&body["message"]).unwrap();
+                let signature = keypair.sign_message(&message);
+                warp::reply::with_status(
+                    warp::reply::json(&json!({ "signature": signature.to_string() })),
+                    StatusCode::OK,
+                )
+            }
+        });
+        let port = portpicker::pick_unused_port().unwrap();
+        tokio::spawn(warp::serve(route).bind(([127, 0, 0, 1], port)));
+
+        let signer = RemoteSigner::new(
+            Config {
+                url: format!("http://127.0.0.1:{}/sign", port),
+                publish_key: publish_key.to_string(),
+                ..Default::default()
+            },
+            "primary",
+        )
+        .unwrap();
+
+        let signature = signer.sign_message(b"message").await.unwrap();
+        assert!(signature.verify(publish_key.as_ref(), b"message"));
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_auth_token_is_redacted_from_debug() {
+        let config = Config {
+            auth_token: Some("hunter2".to_string()),
+            ..Default::default()
+        };
+        let debug = format!("{:?}", config);
+        assert!(!debug.contains("hunter2"));
+        assert!(debug.contains("<redacted>"));
+    }
+}
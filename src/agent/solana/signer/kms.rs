@@ -0,0 +1,500 @@
+// The KMS signer keeps the publish key in a cloud key management service rather
+// than on disk. With Google Cloud KMS, the key never leaves the service: each
+// message is signed by an Ed25519 key version through the asymmetricSign API.
+// AWS KMS is used for envelope encryption: the keypair is stored encrypted under
+// a KMS key, and decrypted into memory on first use. Every signing operation is
+// written to the audit log.
+use {
+    super::{
+        super::instrumented_rpc::endpoint_label,
+        Signer,
+    },
+    crate::agent::{
+        dump::redacted,
+        metrics::SIGNER_METRICS,
+    },
+    anyhow::{
+        anyhow,
+        Context as _,
+        Result,
+    },
+    async_trait::async_trait,
+    chrono::Utc,
+    hmac::{
+        Hmac,
+        Mac,
+    },
+    serde::{
+        Deserialize,
+        Serialize,
+    },
+    serde_json::json,
+    sha2::{
+        Digest,
+        Sha256,
+    },
+    solana_sdk::{
+        pubkey::Pubkey,
+        signature::{
+            Keypair,
+            Signature,
+        },
+        signer::Signer as _,
+    },
+    std::{
+        fmt,
+        str::FromStr,
+        time::{
+            Duration,
+            Instant,
+        },
+    },
+    tokio::sync::{
+        Mutex,
+        OnceCell,
+    },
+};
+
+/// Refresh the Google Cloud access token this long before it expires
+const ACCESS_TOKEN_EXPIRY_MARGIN: Duration = Duration::from_secs(60);
+
+const GCP_METADATA_TOKEN_URL: &str =
+    "http://metadata.google.internal/computeMetadata/v1/instance/service-accounts/default/token";
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Backend {
+    /// Sign with an Ed25519 key version of Google Cloud KMS
+    Gcp,
+    /// Decrypt a keypair encrypted under an AWS KMS key
+    AwsEnvelope,
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub backend:           Backend,
+    /// Public key of the publish keypair held in KMS, used to publish the
+    /// updates not submitted on behalf of a specific publisher
+    pub publish_key:       String,
+    /// Timeout of the requests to the KMS API
+    #[serde(with = "humantime_serde")]
+    pub timeout:           Duration,
+    /// Overrides the URL of the KMS API, e.g. for a private endpoint
+    pub endpoint:          Option<String>,
+    /// Google Cloud: resource name of the Ed25519 key version, of the form
+    /// "projects/<project>/locations/<location>/keyRings/<key ring>/cryptoKeys/<key>/cryptoKeyVersions/<version>"
+    pub key_version:       String,
+    /// Google Cloud: OAuth access token. Fetched from the metadata server of
+    /// the instance when not set.
+    pub access_token:      Option<String>,
+    /// AWS: region of the KMS key
+    pub region:            String,
+    /// AWS: base64-encoded ciphertext blob of the publish keypair, as returned
+    /// by `aws kms encrypt`. The plaintext is either the 64 bytes of the
+    /// keypair, or a keypair file.
+    pub encrypted_keypair: String,
+    /// AWS: credentials allowed to decrypt the keypair
+    pub access_key_id:     String,
+    pub secret_access_key: String,
+    pub session_token:     Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            backend:           Backend::Gcp,
+            publish_key:       "".to_string(),
+            timeout:           Duration::from_secs(2),
+            endpoint:          None,
+            key_version:       "".to_string(),
+            access_token:      None,
+            region:            "".to_string(),
+            encrypted_keypair: "".to_string(),
+            access_key_id:     "".to_string(),
+            secret_access_key: "".to_string(),
+            session_token:     None,
+        }
+    }
+}
+
+impl fmt::Debug for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Config")
+            .field("backend", &self.backend)
+            .field("publish_key", &self.publish_key)
+            .field("timeout", &self.timeout)
+            .field("endpoint", &self.endpoint)
+            .field("key_version", &self.key_version)
+            .field("access_token", &redacted(self.access_token.as_deref()))
+            .field("region", &self.region)
+            .field("encrypted_keypair", &self.encrypted_keypair)
+            .field("access_key_id", &self.access_key_id)
+            .field(
+                "secret_access_key",
+                &redacted(Some(self.secret_access_key.as_str())),
+            )
+            .field("session_token", &redacted(self.session_token.as_deref()))
+            .finish()
+    }
+}
+
+pub struct KmsSigner {
+    config:       Config,
+    publish_key:  Pubkey,
+    client:       reqwest::Client,
+    /// URL of the KMS API
+    endpoint:     String,
+    /// Network label of the signing metrics
+    network:      String,
+    /// Google Cloud access token fetched from the metadata server, and when it
+    /// expires
+    access_token: Mutex<Option<(String, Instant)>>,
+    /// Keypair decrypted with AWS KMS
+    keypair:      OnceCell<Keypair>,
+}
+
+impl KmsSigner {
+    pub fn new(config: Config, network: &str) -> Result<Self> {
+        let publish_key = Pubkey::from_str(&config.publish_key)
+            .with_context(|| format!("invalid KMS signer publish key {}", config.publish_key))?;
+        let client = reqwest::Client::builder()
+            .timeout(config.timeout)
+            .build()
+            .context("building KMS signer HTTP client")?;
+        let endpoint = match (&config.endpoint, config.backend) {
+            (Some(endpoint), _) => endpoint.clone(),
+            (None, Backend::Gcp) => "https://cloudkms.googleapis.com".to_string(),
+            (None, Backend::AwsEnvelope) => format!("https://kms.{}.amazonaws.com", config.region),
+        };
+        Ok(KmsSigner {
+            config,
+            publish_key,
+            client,
+            endpoint,
+            network: network.to_string(),
+            access_token: Mutex::new(None),
+            keypair: OnceCell::new(),
+        })
+    }
+
+    async fn sign_with_gcp(&self, message: &[u8]) -> Result<Signature> {
+        #[derive(Deserialize)]
+        struct AsymmetricSignResponse {
+            signature: String,
+        }
+
+        let response = self
+            .client
+            .post(format!(
+                "{}/v1/{}:asymmetricSign",
+                self.endpoint, self.config.key_version
+            ))
+            .bearer_auth(self.gcp_access_token().await?)
+            .json(&json!({ "data": base64::encode(message) }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<AsymmetricSignResponse>()
+            .await?;
+
+        let signature = base64::decode(&response.signature).context("decoding KMS signature")?;
+        if signature.len() != 64 {
+            return Err(anyhow!("KMS signature is not an Ed25519 signature"));
+        }
+        Ok(Signature::new(&signature))
+    }
+
+    async fn gcp_access_token(&self) -> Result<String> {
+        if let Some(access_token) = &self.config.access_token {
+            return Ok(access_token.clone());
+        }
+
+        #[derive(Deserialize)]
+        struct TokenResponse {
+            access_token: String,
+            expires_in:   u64,
+        }
+
+        let mut cached = self.access_token.lock().await;
+        if let Some((access_token, expiry)) = cached.as_ref() {
+            if Instant::now() < *expiry {
+                return Ok(access_token.clone());
+            }
+        }
+
+        let response = self
+            .client
+            .get(GCP_METADATA_TOKEN_URL)
+            .header("Metadata-Flavor", "Google")
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<TokenResponse>()
+            .await
+            .context("fetching access token from the metadata server")?;
+        let expiry = Instant::now()
+            + Duration::from_secs(response.expires_in).saturating_sub(ACCESS_TOKEN_EXPIRY_MARGIN);
+        *cached = Some((response.access_token.clone(), expiry));
+
+        Ok(response.access_token)
+    }
+
+    async fn sign_with_aws_envelope(&self, message: &[u8]) -> Result<Signature> {
+        let keypair = self
+            .keypair
+            .get_or_try_init(|| self.decrypt_keypair())
+            .await?;
+        Ok(keypair.sign_message(message))
+    }
+
+    async fn decrypt_keypair(&self) -> Result<Keypair> {
+        #[derive(Deserialize)]
+        struct DecryptResponse {
+            #[serde(rename = "Plaintext")]
+            plaintext: String,
+        }
+
+        let body = json!({ "CiphertextBlob": self.config.encrypted_keypair.trim() }).to_string();
+        let amz_date = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+        let mut headers = vec![
+            (
+                "content-type".to_string(),
+                "application/x-amz-json-1.1".to_string(),
+            ),
+            ("host".to_string(), endpoint_label(&self.endpoint)),
+            ("x-amz-date".to_string(), amz_date.clone()),
+            (
+                "x-amz-target".to_string(),
+                "TrentService.Decrypt".to_string(),
+            ),
+        ];
+        if let Some(session_token) = &self.config.session_token {
+            headers.push(("x-amz-security-token".to_string(), session_token.clone()));
+        }
+        let authorization = aws_authorization(
+            &self.config.access_key_id,
+            &self.config.secret_access_key,
+            &self.config.region,
+            &amz_date,
+            &headers,
+            body.as_bytes(),
+        );
+
+        let mut request = self
+            .client
+            .post(format!("{}/", self.endpoint))
+            .header("authorization", authorization)
+            .body(body);
+        for (name, value) in headers.into_iter().filter(|(name, _)| name != "host") {
+            request = request.header(name, value);
+        }
+        let response = request
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<DecryptResponse>()
+            .await
+            .context("decrypting the publish keypair with AWS KMS")?;
+
+        let plaintext =
+            base64::decode(&response.plaintext).context("decoding decrypted publish keypair")?;
+        let keypair_bytes = if plaintext.first() == Some(&b'[') {
+            // Keypair file, a JSON array of the keypair bytes
+            serde_json::from_slice::<Vec<u8>>(&plaintext)
+                .context("parsing decrypted publish keypair file")?
+        } else {
+            plaintext
+        };
+        let keypair = Keypair::from_bytes(&keypair_bytes)
+            .map_err(|_| anyhow!("decrypted publish keypair is not a valid keypair"))?;
+        if keypair.pubkey() != self.publish_key {
+            return Err(anyhow!(
+                "decrypted publish keypair is for {} rather than {}",
+                keypair.pubkey(),
+                self.publish_key
+            ));
+        }
+
+        info!(
+            audit = true,
+            publish_key = %self.publish_key,
+            "KMS signer: publish keypair decrypted"
+        );
+        Ok(keypair)
+    }
+
+    fn key_id(&self) -> &str {
+        match self.config.backend {
+            Backend::Gcp => &self.config.key_version,
+            Backend::AwsEnvelope => &self.endpoint,
+        }
+    }
+}
+
+#[async_trait]
+impl Signer for KmsSigner {
+    fn pubkey(&self) -> Pubkey {
+        self.publish_key
+    }
+
+    async fn sign_message(&self, message: &[u8]) -> Result<Signature> {
+        let start = Instant::now();
+        let result = match self.config.backend {
+            Backend::Gcp => self.sign_with_gcp(message).await,
+            Backend::AwsEnvelope => self.sign_with_aws_envelope(message).await,
+        }
+        .and_then(|signature| {
+            if signature.verify(self.publish_key.as_ref(), message) {
+                Ok(signature)
+            } else {
+                Err(anyhow!(
+                    "KMS signature {} does not match publish key {}",
+                    signature,
+                    self.publish_key
+                ))
+            }
+        });
+        let latency = start.elapsed();
+        SIGNER_METRICS.observe(
+            &self.network,
+            &endpoint_label(&self.endpoint),
+            latency,
+            result.is_ok(),
+        );
+
+        let message_hash = hex::encode(Sha256::digest(message));
+        match &result {
+            Ok(signature) => {
+                info!(
+                    audit = true,
+                    backend = ?self.config.backend,
+                    key = self.key_id(),
+                    publish_key = %self.publish_key,
+                    message_sha256 = %message_hash,
+                    %signature,
+                    ?latency,
+                    "KMS signer: message signed"
+                )
+            }
+            Err(err) => {
+                warn!(
+                    audit = true,
+                    backend = ?self.config.backend,
+                    key = self.key_id(),
+                    publish_key = %self.publish_key,
+                    message_sha256 = %message_hash,
+                    error = %format!("{:#}", err),
+                    "KMS signer: signing failed"
+                )
+            }
+        }
+
+        result
+    }
+}
+
+type HmacSha256 = Hmac<Sha256>;
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Derive the AWS Signature Version 4 signing key of the given day
+fn aws_signing_key(secret_access_key: &str, date: &str, region: &str, service: &str) -> Vec<u8> {
+    let date_key = hmac_sha256(
+        format!("AWS4{}", secret_access_key).as_bytes(),
+        date.as_bytes(),
+    );
+    let region_key = hmac_sha256(&date_key, region.as_bytes());
+    let service_key = hmac_sha256(&region_key, service.as_bytes());
+    hmac_sha256(&service_key, b"aws4_request")
+}
+
+/// Authorization header of a POST request to the root path of the AWS KMS
+/// API, signed with AWS Signature Version 4. The header names must be
+/// lowercase.
+fn aws_authorization(
+    access_key_id: &str,
+    secret_access_key: &str,
+    region: &str,
+    amz_date: &str,
+    headers: &[(String, String)],
+    body: &[u8],
+) -> String {
+    let mut headers = headers.to_vec();
+    headers.sort();
+    let canonical_headers = headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+        .collect::<String>();
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>()
+        .join(";");
+    let canonical_request = format!(
+        "POST\n/\n\n{}\n{}\n{}",
+        canonical_headers,
+        signed_headers,
+        hex::encode(Sha256::digest(body))
+    );
+
+    let date = &amz_date[..8];
+    let scope = format!("{}/{}/kms/aws4_request", date, region);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        hex::encode(Sha256::digest(canonical_request.as_bytes()))
+    );
+    let signature = hex::encode(hmac_sha256(
+        &aws_signing_key(secret_access_key, date, region, "kms"),
+        string_to_sign.as_bytes(),
+    ));
+
+    format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        access_key_id, scope, signed_headers, signature
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        aws_signing_key,
+        Config,
+    };
+
+    #[test]
+    fn test_aws_signing_key() {
+        // Example of the AWS Signature Version 4 documentation
+        assert_eq!(
+            hex::encode(aws_signing_key(
+                "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+                "20120215",
+                "us-east-1",
+                "iam"
+            )),
+            "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d"
+        );
+    }
+
+    #[test]
+    fn test_credentials_are_redacted_from_debug() {
+        let config = Config {
+            access_token: Some("gcp-token".to_string()),
+            access_key_id: "AKIDEXAMPLE".to_string(),
+            secret_access_key: "aws-secret".to_string(),
+            session_token: Some("aws-session".to_string()),
+            ..Default::default()
+        };
+        let debug = format!("{:?}", config);
+        for secret in ["gcp-token", "aws-secret", "aws-session"] {
+            assert!(!debug.contains(secret));
+        }
+        assert!(debug.contains("AKIDEXAMPLE"));
+    }
+}
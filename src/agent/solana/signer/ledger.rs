@@ -0,0 +1,194 @@
+// The Ledger signer signs with a key held on a Ledger hardware wallet. Each
+// signature may need to be approved on the device, so it only suits low publish
+// rates; it is selected with `exporter.ledger_signer`, in place of the remote and
+// KMS signers. The device handles are not Send, so they are owned by a dedicated
+// thread which serves the signing requests. The device support is only built with
+// the ledger feature.
+use {
+    super::Signer,
+    anyhow::Result,
+    serde::{
+        Deserialize,
+        Serialize,
+    },
+    std::{
+        sync::Arc,
+        time::Duration,
+    },
+};
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Locator of the wallet, e.g. "usb://ledger" for the first connected
+    /// Ledger, or "usb://ledger/<wallet pubkey>" for a specific one
+    pub locator:         String,
+    /// Derivation path of the key, e.g. "0/0"
+    pub derivation_path: String,
+    /// Whether the key must be confirmed on the device when the signer is
+    /// created
+    pub confirm_key:     bool,
+    /// How long to wait for a signature, including its approval on the device
+    #[serde(with = "humantime_serde")]
+    pub timeout:         Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            locator:         "usb://ledger".to_string(),
+            derivation_path: "0/0".to_string(),
+            confirm_key:     false,
+            timeout:         Duration::from_secs(120),
+        }
+    }
+}
+
+/// Connect to the wallet and resolve the key. Blocks until the key is
+/// confirmed on the device, if confirmation is required.
+#[cfg(feature = "ledger")]
+pub fn new_signer(config: Config) -> Result<Arc<dyn Signer>> {
+    Ok(Arc::new(device::LedgerSigner::new(config)?))
+}
+
+#[cfg(not(feature = "ledger"))]
+pub fn new_signer(_config: Config) -> Result<Arc<dyn Signer>> {
+    anyhow::bail!(
+        "exporter.ledger_signer is set, but the agent was built without the ledger feature"
+    )
+}
+
+#[cfg(feature = "ledger")]
+mod device {
+    use {
+        super::{
+            Config,
+            Signer,
+        },
+        anyhow::{
+            anyhow,
+            Context as _,
+            Result,
+        },
+        async_trait::async_trait,
+        solana_remote_wallet::{
+            locator::Locator,
+            remote_keypair::generate_remote_keypair,
+            remote_wallet::maybe_wallet_manager,
+        },
+        solana_sdk::{
+            derivation_path::DerivationPath,
+            pubkey::Pubkey,
+            signature::Signature,
+            signer::Signer as _,
+        },
+        std::{
+            sync::mpsc as std_mpsc,
+            thread,
+        },
+        tokio::{
+            sync::oneshot,
+            time,
+        },
+    };
+
+    struct SignRequest {
+        message:   Vec<u8>,
+        result_tx: oneshot::Sender<Result<Signature>>,
+    }
+
+    pub struct LedgerSigner {
+        config:     Config,
+        pubkey:     Pubkey,
+        /// Requests to the thread owning the device
+        request_tx: std_mpsc::Sender<SignRequest>,
+    }
+
+    impl LedgerSigner {
+        /// Connect to the wallet and resolve the key. Blocks until the key is
+        /// confirmed on the device, if confirmation is required.
+        pub fn new(config: Config) -> Result<Self> {
+            let locator = Locator::new_from_path(&config.locator)
+                .with_context(|| format!("invalid wallet locator {}", config.locator))?;
+            let derivation_path = DerivationPath::from_key_str(&config.derivation_path)
+                .with_context(|| format!("invalid derivation path {}", config.derivation_path))?;
+
+            let (request_tx, request_rx) = std_mpsc::channel::<SignRequest>();
+            let (pubkey_tx, pubkey_rx) = std_mpsc::channel();
+            let confirm_key = config.confirm_key;
+            thread::Builder::new()
+                .name("ledger-signer".to_string())
+                .spawn(move || {
+                    let keypair = maybe_wallet_manager()
+                        .map_err(|err| anyhow!("{}", err))
+                        .and_then(|wallet_manager| {
+                            wallet_manager.ok_or_else(|| anyhow!("no hardware wallet found"))
+                        })
+                        .and_then(|wallet_manager| {
+                            generate_remote_keypair(
+                                locator,
+                                derivation_path,
+                                &wallet_manager,
+                                confirm_key,
+                                "publish key",
+                            )
+                            .map_err(|err| anyhow!("{}", err))
+                        });
+                    let keypair = match keypair {
+                        Ok(keypair) => {
+                            let _ = pubkey_tx.send(Ok(keypair.pubkey()));
+                            keypair
+                        }
+                        Err(err) => {
+                            let _ = pubkey_tx.send(Err(err));
+                            return;
+                        }
+                    };
+
+                    // Serve the requests until the signer is dropped
+                    for request in request_rx {
+                        let result = keypair
+                            .try_sign_message(&request.message)
+                            .map_err(|err| anyhow!("{}", err));
+                        let _ = request.result_tx.send(result);
+                    }
+                })
+                .context("spawning Ledger signer thread")?;
+
+            let pubkey = pubkey_rx
+                .recv()
+                .map_err(|_| anyhow!("Ledger signer thread exited"))?
+                .context("connecting to the Ledger")?;
+            info!(%pubkey, "Ledger signer: connected");
+
+            Ok(LedgerSigner {
+                config,
+                pubkey,
+                request_tx,
+            })
+        }
+    }
+
+    #[async_trait]
+    impl Signer for LedgerSigner {
+        fn pubkey(&self) -> Pubkey {
+            self.pubkey
+        }
+
+        async fn sign_message(&self, message: &[u8]) -> Result<Signature> {
+            let (result_tx, result_rx) = oneshot::channel();
+            self.request_tx
+                .send(SignRequest {
+                    message: message.to_vec(),
+                    result_tx,
+                })
+                .map_err(|_| anyhow!("Ledger signer thread exited"))?;
+
+            info!(pubkey = %self.pubkey, "Ledger signer: waiting for approval on the device");
+            time::timeout(self.config.timeout, result_rx)
+                .await
+                .map_err(|_| anyhow!("timed out waiting for the Ledger to sign"))?
+                .map_err(|_| anyhow!("Ledger signer thread exited"))?
+        }
+    }
+}
@@ -0,0 +1,391 @@
+// In simulation, a network is replaced by an in-memory cluster, so that the agent
+// can be run offline during development. The Oracle reads the Pyth accounts from
+// the simulated cluster instead of the RPC node, and the Exporter logs the signed
+// transactions instead of sending them. The accounts are loaded from fixture files,
+// as written by `solana account <address> --output json`, or generated: a mapping
+// account at the mapping key of the key store, listing products on which the
+// publish keypair and the additional publishers are permissioned. The simulated
+// accounts are static, so published prices are not reflected in them, unless a
+// recording is replayed into the cluster.
+use {
+    super::key_store::KeyStore,
+    anyhow::{
+        anyhow,
+        bail,
+        Context as _,
+        Result,
+    },
+    bytemuck::Zeroable,
+    chrono::Utc,
+    parking_lot::RwLock,
+    pyth_sdk_solana::state::{
+        AccountType,
+        MappingAccount,
+        PriceAccount,
+        PriceStatus,
+        PriceType,
+        ProductAccount,
+        MAGIC,
+        PROD_HDR_SIZE,
+        VERSION_2,
+    },
+    serde::{
+        Deserialize,
+        Serialize,
+    },
+    solana_sdk::{
+        account::Account,
+        pubkey::Pubkey,
+        signer::Signer,
+    },
+    std::{
+        collections::HashMap,
+        fs,
+        mem::size_of,
+        path::{
+            Path,
+            PathBuf,
+        },
+        str::FromStr,
+    },
+};
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Directory of JSON files holding the accounts of the simulated cluster,
+    /// one account per file. The accounts are generated when not set.
+    pub fixtures_path: Option<PathBuf>,
+    /// Number of products generated when no fixtures are given
+    pub products:      usize,
+    /// Public keys of publishers permissioned on the generated prices, in
+    /// addition to the publish keypair and the additional publishers, e.g.
+    /// the key of a remote signer
+    pub publishers:    Vec<String>,
+    /// Recording of the account updates seen by an Oracle, replayed into the
+    /// simulated cluster in place of the fixtures or generated accounts
+    pub replay_path:   Option<PathBuf>,
+    /// How many times faster than recorded the updates are replayed. Must be
+    /// positive.
+    pub replay_speed:  f64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            fixtures_path: None,
+            products:      10,
+            publishers:    vec![],
+            replay_path:   None,
+            replay_speed:  1.0,
+        }
+    }
+}
+
+/// An account as written by `solana account <address> --output json`, and read
+/// by `solana-test-validator --account <address> <file>`
+#[derive(Serialize, Deserialize)]
+struct FixtureAccount {
+    pubkey:  String,
+    account: FixtureAccountData,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FixtureAccountData {
+    lamports:   u64,
+    /// The encoded data and its encoding, only "base64" being supported
+    data:       (String, String),
+    owner:      String,
+    executable: bool,
+    rent_epoch: u64,
+}
+
+/// The accounts of a simulated cluster, read in place of an RPC node
+pub struct SimulatedCluster {
+    config:   Config,
+    accounts: RwLock<HashMap<Pubkey, Account>>,
+}
+
+impl SimulatedCluster {
+    pub fn new(config: &Config, key_store: &KeyStore) -> Result<Self> {
+        if !(config.replay_speed.is_finite() && config.replay_speed > 0.0) {
+            bail!(
+                "simulation.replay_speed must be positive, got {}",
+                config.replay_speed
+            );
+        }
+
+        // A replayed cluster starts empty
+        let accounts = match (&config.replay_path, &config.fixtures_path) {
+            (Some(_), _) => HashMap::new(),
+            (None, Some(path)) => Self::load_fixtures(path)?,
+            (None, None) => Self::generate_accounts(config, key_store)?,
+        };
+        info!(
+            accounts = accounts.len(),
+            "simulation: using a simulated cluster instead of the RPC node"
+        );
+        Ok(SimulatedCluster {
+            config:   config.clone(),
+            accounts: RwLock::new(accounts),
+        })
+    }
+
+    /// The recording replayed into the cluster, if any, and its speed
+    pub fn replay(&self) -> Option<(PathBuf, f64)> {
+        self.config
+            .replay_path
+            .clone()
+            .map(|path| (path, self.config.replay_speed))
+    }
+
+    pub fn get_account_data(&self, key: &Pubkey) -> Result<Vec<u8>> {
+        self.accounts
+            .read()
+            .get(key)
+            .map(|account| account.data.clone())
+            .ok_or_else(|| anyhow!("account {} not found in the simulated cluster", key))
+    }
+
+    pub fn get_multiple_accounts(&self, keys: &[Pubkey]) -> Vec<Option<Account>> {
+        let accounts = self.accounts.read();
+        keys.iter().map(|key| accounts.get(key).cloned()).collect()
+    }
+
+    pub fn set_account(&self, key: Pubkey, account: Account) {
+        self.accounts.write().insert(key, account);
+    }
+
+    /// Write the accounts of the cluster to the given directory as fixtures,
+    /// one file per account, returning the path of each account's file
+    pub fn write_fixtures(&self, path: &Path) -> Result<Vec<(Pubkey, PathBuf)>> {
+        fs::create_dir_all(path)
+            .with_context(|| format!("creating fixtures directory {}", path.display()))?;
+        let mut fixtures = vec![];
+        for (key, account) in self.accounts.read().iter() {
+            let fixture = FixtureAccount {
+                pubkey:  key.to_string(),
+                account: FixtureAccountData {
+                    lamports:   account.lamports,
+                    data:       (base64::encode(&account.data), "base64".to_string()),
+                    owner:      account.owner.to_string(),
+                    executable: account.executable,
+                    rent_epoch: account.rent_epoch,
+                },
+            };
+            let fixture_path = path.join(format!("{}.json", key));
+            fs::write(&fixture_path, serde_json::to_string(&fixture)?)
+                .with_context(|| format!("writing fixture {}", fixture_path.display()))?;
+            fixtures.push((*key, fixture_path));
+        }
+        Ok(fixtures)
+    }
+
+    fn load_fixtures(path: &Path) -> Result<HashMap<Pubkey, Account>> {
+        let mut accounts = HashMap::new();
+        for entry in fs::read_dir(path)
+            .with_context(|| format!("reading fixtures directory {}", path.display()))?
+        {
+            let path = entry?.path();
+            if path
+                .extension()
+                .map_or(true, |extension| extension != "json")
+            {
+                continue;
+            }
+            let (key, account) = Self::load_fixture(&path)
+                .with_context(|| format!("loading fixture {}", path.display()))?;
+            accounts.insert(key, account);
+        }
+        Ok(accounts)
+    }
+
+    fn load_fixture(path: &Path) -> Result<(Pubkey, Account)> {
+        let fixture: FixtureAccount = serde_json::from_str(&fs::read_to_string(path)?)?;
+        let (data, encoding) = fixture.account.data;
+        if encoding != "base64" {
+            bail!("unsupported account data encoding {:?}", encoding);
+        }
+        Ok((
+            Pubkey::from_str(&fixture.pubkey)?,
+            Account {
+                lamports:   fixture.account.lamports,
+                data:       base64::decode(data)?,
+                owner:      Pubkey::from_str(&fixture.account.owner)?,
+                executable: fixture.account.executable,
+                rent_epoch: fixture.account.rent_epoch,
+            },
+        ))
+    }
+
+    /// Generate a mapping account listing the given number of products, each
+    /// with a single price
+    fn generate_accounts(
+        config: &Config,
+        key_store: &KeyStore,
+    ) -> Result<HashMap<Pubkey, Account>> {
+        let mut publishers = config
+            .publishers
+            .iter()
+            .map(|key| Pubkey::from_str(key).with_context(|| format!("invalid publisher {}", key)))
+            .collect::<Result<Vec<_>>>()?;
+        publishers.extend(
+            key_store
+                .publish_keypair
+                .iter()
+                .map(|keypair| keypair.pubkey()),
+        );
+        publishers.extend(key_store.additional_publish_keypairs.keys().copied());
+
+        let account = |data: &[u8]| Account {
+            lamports:   1_000_000_000,
+            data:       data.to_vec(),
+            owner:      key_store.program_key,
+            executable: false,
+            rent_epoch: 0,
+        };
+        let derive_key = |seed: String| {
+            Pubkey::create_with_seed(&key_store.mapping_key, &seed, &key_store.program_key)
+        };
+
+        let mut mapping = MappingAccount::zeroed();
+        if config.products > mapping.products.len() {
+            bail!(
+                "at most {} products can be simulated",
+                mapping.products.len()
+            );
+        }
+        mapping.magic = MAGIC;
+        mapping.ver = VERSION_2;
+        mapping.atype = AccountType::Mapping as u32;
+        mapping.num = config.products as u32;
+
+        let now = Utc::now().timestamp();
+        let mut accounts = HashMap::new();
+        for index in 0..config.products {
+            let product_key = derive_key(format!("product{}", index))?;
+            let price_key = derive_key(format!("price{}", index))?;
+            mapping.products[index] = product_key;
+
+            let mut product = ProductAccount::zeroed();
+            product.magic = MAGIC;
+            product.ver = VERSION_2;
+            product.atype = AccountType::Product as u32;
+            product.px_acc = price_key;
+            let base = format!("SIM{}", index);
+            let attributes = encode_attributes(&[
+                ("symbol", &format!("Crypto.{}/USD", base)),
+                ("asset_type", "Crypto"),
+                ("base", &base),
+                ("quote_currency", "USD"),
+                ("description", &format!("Simulated {}/USD", base)),
+            ]);
+            product.attr[..attributes.len()].copy_from_slice(&attributes);
+            product.size = (PROD_HDR_SIZE + attributes.len()) as u32;
+            accounts.insert(product_key, account(bytemuck::bytes_of(&product)));
+
+            let mut price = PriceAccount::zeroed();
+            price.magic = MAGIC;
+            price.ver = VERSION_2;
+            price.atype = AccountType::Price as u32;
+            price.size = size_of::<PriceAccount>() as u32;
+            price.ptype = PriceType::Price;
+            price.expo = -8;
+            price.prod = product_key;
+            price.timestamp = now;
+            price.agg.price = (index as i64 + 1) * 100 * 10_i64.pow(8);
+            price.agg.conf = 10_u64.pow(8);
+            price.agg.status = PriceStatus::Trading;
+            for (component, publisher) in price.comp.iter_mut().zip(&publishers) {
+                component.publisher = *publisher;
+            }
+            price.num = publishers.len().min(price.comp.len()) as u32;
+            accounts.insert(price_key, account(bytemuck::bytes_of(&price)));
+        }
+        accounts.insert(key_store.mapping_key, account(bytemuck::bytes_of(&mapping)));
+
+        Ok(accounts)
+    }
+}
+
+/// Encode the attributes of a product account, as length-prefixed strings
+fn encode_attributes(attributes: &[(&str, &str)]) -> Vec<u8> {
+    let mut encoded = vec![];
+    for (key, value) in attributes {
+        for string in [key, value] {
+            encoded.push(string.len() as u8);
+            encoded.extend_from_slice(string.as_bytes());
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            Config,
+            SimulatedCluster,
+        },
+        crate::agent::solana::key_store::KeyStore,
+        pyth_sdk_solana::state::{
+            load_mapping_account,
+            load_price_account,
+            load_product_account,
+        },
+        solana_sdk::{
+            pubkey::Pubkey,
+            signature::Keypair,
+            signer::Signer,
+        },
+        std::collections::HashMap,
+    };
+
+    #[test]
+    fn test_generated_accounts_are_valid() {
+        let publish_keypair = Keypair::new();
+        let publisher = publish_keypair.pubkey();
+        let key_store = KeyStore {
+            publish_keypair:             Some(publish_keypair),
+            program_key:                 Pubkey::new_unique(),
+            mapping_key:                 Pubkey::new_unique(),
+            accumulator_key:             None,
+            price_store_key:             None,
+            additional_publish_keypairs: HashMap::new(),
+        };
+        let cluster = SimulatedCluster::new(
+            &Config {
+                products: 2,
+                ..Default::default()
+            },
+            &key_store,
+        )
+        .unwrap();
+
+        let mapping_data = cluster.get_account_data(&key_store.mapping_key).unwrap();
+        let mapping = load_mapping_account(&mapping_data).unwrap();
+        assert_eq!(mapping.num, 2);
+
+        let product_data = cluster.get_account_data(&mapping.products[1]).unwrap();
+        let product = load_product_account(&product_data).unwrap();
+        assert!(product
+            .iter()
+            .any(|(key, value)| key == "symbol" && value == "Crypto.SIM1/USD"));
+
+        let price_data = cluster.get_account_data(&product.px_acc).unwrap();
+        let price = load_price_account(&price_data).unwrap();
+        assert_eq!(price.prod, mapping.products[1]);
+        assert_eq!(price.comp[0].publisher, publisher);
+
+        // The delays of a replay are divided by its speed
+        assert!(SimulatedCluster::new(
+            &Config {
+                replay_speed: 0.0,
+                ..Default::default()
+            },
+            &key_store,
+        )
+        .is_err());
+    }
+}
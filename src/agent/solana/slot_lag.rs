@@ -0,0 +1,266 @@
+// The Slot Tracker of each network periodically fetches the slot of the cluster tip,
+// so that the agent can tell how far behind its view of the network is. The Oracle
+// reports the slots of the price accounts it observes, and the lags between those
+// and the network slot are exported as metrics and shown on the dashboard. The time
+// since the network slot last advanced tells the Exporter whether the cluster halted.
+use {
+    super::{
+        instrumented_rpc,
+        oracle::PriceEntry,
+    },
+    crate::agent::metrics::{
+        SlotLagMetrics,
+        PROMETHEUS_REGISTRY,
+    },
+    parking_lot::{
+        Mutex,
+        RwLock,
+    },
+    solana_sdk::commitment_config::{
+        CommitmentConfig,
+        CommitmentLevel,
+    },
+    std::{
+        collections::BTreeMap,
+        sync::{
+            atomic::{
+                AtomicBool,
+                AtomicU64,
+                Ordering,
+            },
+            Arc,
+        },
+        time::{
+            Duration,
+            Instant,
+        },
+    },
+    tokio::{
+        task::JoinHandle,
+        time,
+    },
+    tracing::Instrument,
+};
+
+/// Lags of a network, in slots. Unknown until both slots were observed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SlotLag {
+    /// Latest slot of the cluster tip
+    pub network_slot:  Option<u64>,
+    /// Slots between the cluster tip and the latest price account update
+    /// observed by the Oracle
+    pub oracle_lag:    Option<u64>,
+    /// Slots between the cluster tip and the latest aggregate price observed
+    /// by the Oracle
+    pub aggregate_lag: Option<u64>,
+    /// Whether the Exporter paused publishing as the network slot stalled
+    pub stalled:       bool,
+}
+
+impl SlotLag {
+    /// The larger of the two lags, if any is known
+    pub fn worst(&self) -> Option<u64> {
+        self.oracle_lag.max(self.aggregate_lag)
+    }
+}
+
+/// Shared registry of the slots observed on each network
+#[derive(Clone)]
+pub struct SlotLagReporter {
+    networks: Arc<RwLock<BTreeMap<String, NetworkSlots>>>,
+    metrics:  Arc<SlotLagMetrics>,
+}
+
+impl SlotLagReporter {
+    pub async fn new() -> Self {
+        SlotLagReporter {
+            networks: Default::default(),
+            metrics:  Arc::new(SlotLagMetrics::new(
+                &mut &mut PROMETHEUS_REGISTRY.lock().await,
+            )),
+        }
+    }
+
+    /// Register a network, returning the handle its slots are reported through
+    pub fn network(&self, name: impl Into<String>) -> NetworkSlots {
+        let name = name.into();
+        let slots = NetworkSlots {
+            name:    name.clone(),
+            slots:   Default::default(),
+            metrics: self.metrics.clone(),
+        };
+        self.networks.write().insert(name, slots.clone());
+        slots
+    }
+
+    /// The latest lags of every registered network
+    pub fn lags(&self) -> BTreeMap<String, SlotLag> {
+        self.networks
+            .read()
+            .iter()
+            .map(|(name, slots)| (name.clone(), slots.lag()))
+            .collect()
+    }
+
+    /// The network lagging the most behind its cluster tip, with its lags
+    pub fn worst(&self) -> Option<(String, SlotLag)> {
+        self.lags()
+            .into_iter()
+            .filter(|(_, lag)| lag.worst().is_some())
+            .max_by_key(|(_, lag)| lag.worst())
+    }
+}
+
+#[derive(Default)]
+struct Slots {
+    network:     AtomicU64,
+    oracle:      AtomicU64,
+    aggregate:   AtomicU64,
+    /// When the network slot last advanced
+    advanced_at: Mutex<Option<Instant>>,
+    stalled:     AtomicBool,
+}
+
+/// Handle through which the slots of a single network are reported
+#[derive(Clone)]
+pub struct NetworkSlots {
+    name:    String,
+    slots:   Arc<Slots>,
+    metrics: Arc<SlotLagMetrics>,
+}
+
+impl NetworkSlots {
+    /// Record the slot of the cluster tip, updating the metrics
+    pub fn observe_network_slot(&self, slot: u64) {
+        if self.slots.network.fetch_max(slot, Ordering::Relaxed) < slot {
+            *self.slots.advanced_at.lock() = Some(Instant::now());
+        }
+        self.metrics.update(&self.name, &self.lag());
+    }
+
+    /// Time since the network slot last advanced, unknown until it was observed
+    pub fn stalled_for(&self) -> Option<Duration> {
+        self.slots
+            .advanced_at
+            .lock()
+            .map(|advanced_at| advanced_at.elapsed())
+    }
+
+    /// Record whether publishing is paused as the network slot stalled,
+    /// returning whether it was before
+    pub fn set_stalled(&self, stalled: bool) -> bool {
+        let was_stalled = self.slots.stalled.swap(stalled, Ordering::Relaxed);
+        if was_stalled != stalled {
+            self.metrics.update(&self.name, &self.lag());
+        }
+        was_stalled
+    }
+
+    /// Record the slots of a price account observed by the Oracle
+    pub fn observe_price_account(&self, price_account: &PriceEntry) {
+        self.slots
+            .oracle
+            .fetch_max(price_account.last_slot, Ordering::Relaxed);
+        self.slots
+            .aggregate
+            .fetch_max(price_account.agg.pub_slot, Ordering::Relaxed);
+    }
+
+    pub fn lag(&self) -> SlotLag {
+        let known = |slot: &AtomicU64| Some(slot.load(Ordering::Relaxed)).filter(|slot| *slot > 0);
+        let network_slot = known(&self.slots.network);
+        let lag_of = |slot: &AtomicU64| Some(network_slot?.saturating_sub(known(slot)?));
+        SlotLag {
+            network_slot,
+            oracle_lag: lag_of(&self.slots.oracle),
+            aggregate_lag: lag_of(&self.slots.aggregate),
+            stalled: self.slots.stalled.load(Ordering::Relaxed),
+        }
+    }
+}
+
+pub fn spawn_tracker(
+    rpc_url: &str,
+    rpc_timeout: Duration,
+    commitment: CommitmentLevel,
+    poll_interval_duration: Duration,
+    slots: NetworkSlots,
+) -> JoinHandle<()> {
+    let rpc_client = instrumented_rpc::new_rpc_client(
+        &slots.name,
+        rpc_url,
+        rpc_timeout,
+        CommitmentConfig { commitment },
+    );
+    tokio::spawn(
+        async move {
+            let mut poll_interval = time::interval(poll_interval_duration);
+            loop {
+                poll_interval.tick().await;
+                match rpc_client.get_slot().await {
+                    Ok(slot) => slots.observe_network_slot(slot),
+                    Err(err) => {
+                        warn!(error = ?err, "Slot tracker: could not fetch the network slot: {:#}", err)
+                    }
+                }
+            }
+        }
+        .instrument(info_span!("slot_tracker")),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            SlotLag,
+            SlotLagReporter,
+        },
+        crate::agent::solana::oracle::PriceEntry,
+    };
+
+    #[tokio::test]
+    async fn test_lags_are_relative_to_the_network_slot() {
+        let reporter = SlotLagReporter::new().await;
+        let primary = reporter.network("primary");
+        let secondary = reporter.network("secondary");
+
+        // Lags are unknown until the network slot is observed
+        let mut price_account = PriceEntry::default();
+        price_account.last_slot = 95;
+        price_account.agg.pub_slot = 90;
+        primary.observe_price_account(&price_account);
+        assert_eq!(primary.lag().oracle_lag, None);
+
+        primary.observe_network_slot(100);
+        assert_eq!(
+            primary.lag(),
+            SlotLag {
+                network_slot:  Some(100),
+                oracle_lag:    Some(5),
+                aggregate_lag: Some(10),
+                stalled:       false,
+            }
+        );
+
+        // Older price accounts don't move the observed slots back
+        price_account.last_slot = 50;
+        primary.observe_price_account(&price_account);
+        assert_eq!(primary.lag().oracle_lag, Some(5));
+
+        // The stall is timed from the last advance of the network slot
+        assert!(primary.stalled_for().is_some());
+        assert_eq!(secondary.stalled_for(), None);
+        assert!(!primary.set_stalled(true));
+        assert!(primary.lag().stalled);
+        assert!(primary.set_stalled(false));
+
+        secondary.observe_network_slot(200);
+        price_account.last_slot = 198;
+        price_account.agg.pub_slot = 198;
+        secondary.observe_price_account(&price_account);
+        let (worst_network, worst_lag) = reporter.worst().unwrap();
+        assert_eq!(worst_network, "primary");
+        assert_eq!(worst_lag.worst(), Some(10));
+    }
+}
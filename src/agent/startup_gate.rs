@@ -0,0 +1,125 @@
+// Publishers connecting to the pythd API before the Oracle loaded the products get
+// empty product lists, and some of them give up. When configured, the startup gate
+// keeps the API from accepting connections, and the /ready endpoint from reporting
+// the agent ready, until the first poll of the primary network's Oracle succeeded and
+// its accounts were handed to the Global Store. The gate opens anyway once the
+// timeout elapsed, or when opened through the Admin API.
+use {
+    crate::agent::health::{
+        ComponentHealth,
+        HealthReporter,
+    },
+    serde::{
+        Deserialize,
+        Serialize,
+    },
+    std::{
+        sync::{
+            atomic::{
+                AtomicBool,
+                Ordering,
+            },
+            Arc,
+        },
+        time::Duration,
+    },
+    tokio::{
+        task::JoinHandle,
+        time,
+    },
+};
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The gate opens at the latest this long after startup, whether or not
+    /// a poll succeeded
+    #[serde(with = "humantime_serde")]
+    pub timeout: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(5 * 60),
+        }
+    }
+}
+
+/// Gate shared between the primary Oracle, the API Server and the Admin API
+#[derive(Clone)]
+pub struct StartupGate {
+    open:   Arc<AtomicBool>,
+    /// Reports the gate as starting until it opens, if it is enabled
+    health: Option<ComponentHealth>,
+}
+
+impl StartupGate {
+    /// A gate which is closed if enabled, and open from the start otherwise
+    pub fn new(enabled: bool, health: &HealthReporter) -> Self {
+        StartupGate {
+            open:   Arc::new(AtomicBool::new(!enabled)),
+            health: enabled.then(|| health.component("startup_gate")),
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open.load(Ordering::Relaxed)
+    }
+
+    /// Open the gate, if it is still closed
+    pub fn open(&self, reason: &str) {
+        if self.open.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        info!(reason, "Startup gate: opened, serving the pythd API");
+        if let Some(health) = &self.health {
+            health.healthy(format!("opened: {}", reason));
+        }
+    }
+}
+
+/// Open the gate once the timeout elapsed, if it is still closed by then
+pub fn spawn_timeout(startup_gate: StartupGate, config: Config) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        time::sleep(config.timeout).await;
+        if !startup_gate.is_open() {
+            warn!(
+                timeout = ?config.timeout,
+                "Startup gate: no poll of the oracle succeeded in time, serving the pythd API anyway"
+            );
+            startup_gate.open("timed out waiting for the first poll");
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::StartupGate,
+        crate::agent::health::{
+            HealthReporter,
+            Status,
+        },
+    };
+
+    #[test]
+    fn test_gate_holds_readiness_until_opened() {
+        let health = HealthReporter::default();
+        assert!(StartupGate::new(false, &health).is_open());
+        assert_eq!(health.readiness().status, Status::Healthy);
+
+        let startup_gate = StartupGate::new(true, &health);
+        assert!(!startup_gate.is_open());
+        assert_eq!(health.readiness().status, Status::Starting);
+
+        startup_gate.open("first poll succeeded");
+        startup_gate.open("timed out waiting for the first poll");
+        assert!(startup_gate.is_open());
+        assert_eq!(health.readiness().status, Status::Healthy);
+        assert_eq!(
+            health.components()["startup_gate"].detail,
+            "opened: first poll succeeded"
+        );
+    }
+}
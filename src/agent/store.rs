@@ -1,4 +1,6 @@
 pub mod global;
 pub mod local;
+pub mod symbol_index;
+pub mod transactions;
 
 pub type PriceIdentifier = pyth_sdk::Identifier;
@@ -1,6 +1,12 @@
 // The Global Store stores a copy of all the product and price information held in the Pyth
 // on-chain aggregation contracts, across both the primary and secondary networks.
 // This enables this data to be easily queried by other components.
+//
+// After each poll of the primary network, the Oracle lists the product and price
+// accounts it found. When configured, the accounts missing from the listing, as once
+// delisted or closed, are removed from the store along with their metrics after the
+// configured TTL, unless they are listed again in the meantime, and their removal is
+// broadcast to the subscribers.
 use {
     super::super::solana::oracle::{
         self,
@@ -8,39 +14,103 @@ use {
         ProductEntry,
     },
     crate::agent::{
+        error::{
+            self,
+            Error,
+        },
         metrics::{
+            PriceAggregationMetrics,
             PriceGlobalMetrics,
             ProductGlobalMetrics,
             PROMETHEUS_REGISTRY,
         },
         pythd::adapter,
+        store::{
+            local::PriceInfo,
+            symbol_index::{
+                SymbolIndex,
+                SYMBOL_ATTRIBUTE,
+            },
+        },
+        telemetry,
     },
     anyhow::{
-        anyhow,
+        Context as _,
         Result,
     },
+    arc_swap::ArcSwap,
+    chrono::Utc,
+    opentelemetry::{
+        Context,
+        KeyValue,
+    },
     pyth_sdk::Identifier,
-    slog::Logger,
+    pyth_sdk_solana::state::PriceStatus,
+    serde::{
+        Deserialize,
+        Serialize,
+    },
     solana_sdk::pubkey::Pubkey,
-    std::collections::{
-        BTreeMap,
-        HashMap,
+    std::{
+        collections::{
+            BTreeMap,
+            HashMap,
+            HashSet,
+        },
+        iter,
+        sync::Arc,
+        time::Duration,
     },
     tokio::{
         sync::{
+            broadcast,
             mpsc,
-            oneshot,
         },
         task::JoinHandle,
+        time::{
+            self,
+            Instant,
+            Interval,
+        },
     },
+    tracing::Instrument,
 };
 
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Age of the on-chain publish timestamp after which a price is considered stale.
+    /// Subscribers to the Global Store events are notified when a price becomes stale
+    /// or fresh again.
+    #[serde(with = "humantime_serde")]
+    pub staleness_threshold:               Duration,
+    /// Duration of the interval at which prices are checked for staleness
+    #[serde(with = "humantime_serde")]
+    pub staleness_check_interval_duration: Duration,
+    /// How long the accounts missing from the listing of the latest poll of the
+    /// primary network are kept before they are removed. Delisted accounts are
+    /// kept indefinitely when not set.
+    #[serde(with = "humantime_serde")]
+    pub delisted_account_ttl:              Option<Duration>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            staleness_threshold:               Duration::from_secs(60),
+            staleness_check_interval_duration: Duration::from_secs(1),
+            delisted_account_ttl:              None,
+        }
+    }
+}
+
 /// AllAccountsData contains the full data for the price and product accounts, sourced
-/// from the primary network.
+/// from the primary network. Price accounts are shared with the Oracle and the events,
+/// so that publishing a snapshot does not copy them.
 #[derive(Debug, Clone, Default)]
 pub struct AllAccountsData {
     pub product_accounts: HashMap<Pubkey, oracle::ProductEntry>,
-    pub price_accounts:   HashMap<Pubkey, oracle::PriceEntry>,
+    pub price_accounts:   HashMap<Pubkey, Arc<oracle::PriceEntry>>,
 }
 
 /// AllAccountsMetadata contains the metadata for all the price and product accounts.
@@ -50,6 +120,55 @@ pub struct AllAccountsData {
 pub struct AllAccountsMetadata {
     pub product_accounts_metadata: HashMap<Pubkey, ProductAccountMetadata>,
     pub price_accounts_metadata:   HashMap<Pubkey, PriceAccountMetadata>,
+    /// Symbols of the price accounts, kept in sync with the product metadata
+    pub symbol_index:              SymbolIndex,
+}
+
+impl AllAccountsMetadata {
+    /// Store the metadata of the product and index its symbol, returning the
+    /// metadata previously stored for it
+    pub fn insert_product(
+        &mut self,
+        product_key: Pubkey,
+        metadata: ProductAccountMetadata,
+    ) -> Option<ProductAccountMetadata> {
+        self.symbol_index.update_product(&product_key, &metadata);
+        self.product_accounts_metadata.insert(product_key, metadata)
+    }
+
+    /// Remove the metadata of the product and stop indexing its symbol
+    pub fn remove_product(&mut self, product_key: &Pubkey) -> Option<ProductAccountMetadata> {
+        self.symbol_index.remove_product(product_key);
+        self.product_accounts_metadata.remove(product_key)
+    }
+
+    /// The products having all the given attributes. When filtering on the
+    /// symbol, the product is looked up in the symbol index rather than by
+    /// scanning all products.
+    pub fn products_with_attributes(
+        &self,
+        attributes: &BTreeMap<String, String>,
+    ) -> Vec<(&Pubkey, &ProductAccountMetadata)> {
+        let has_attributes = |metadata: &ProductAccountMetadata| {
+            attributes
+                .iter()
+                .all(|(key, value)| metadata.attr_dict.get(key) == Some(value))
+        };
+        match attributes.get(SYMBOL_ATTRIBUTE) {
+            Some(symbol) => self
+                .symbol_index
+                .product(symbol)
+                .and_then(|product_key| self.product_accounts_metadata.get_key_value(product_key))
+                .into_iter()
+                .filter(|(_, metadata)| has_attributes(metadata))
+                .collect(),
+            None => self
+                .product_accounts_metadata
+                .iter()
+                .filter(|(_, metadata)| has_attributes(metadata))
+                .collect(),
+        }
+    }
 }
 
 /// ProductAccountMetadata contains the metadata for a product account.
@@ -82,14 +201,42 @@ pub struct PriceAccountMetadata {
     pub expo: i32,
 }
 
-impl From<oracle::PriceEntry> for PriceAccountMetadata {
-    fn from(price_account: oracle::PriceEntry) -> Self {
+impl From<&oracle::PriceEntry> for PriceAccountMetadata {
+    fn from(price_account: &oracle::PriceEntry) -> Self {
         PriceAccountMetadata {
             expo: price_account.expo,
         }
     }
 }
 
+/// Event is broadcast by the Global Store to its subscribers whenever its
+/// contents change. Obtain a receiver with `broadcast::Sender::subscribe()`
+/// on the events channel passed to `spawn_store`.
+#[derive(Debug, Clone)]
+pub enum Event {
+    /// A price account was updated with more recent data from the primary network
+    PriceUpdated {
+        account_key:   Pubkey,
+        account:       Arc<PriceEntry>,
+        /// Trace context of the Global Store's handling of the update
+        trace_context: Context,
+    },
+    /// A product account was seen for the first time
+    NewProduct {
+        account_key: Pubkey,
+        metadata:    ProductAccountMetadata,
+    },
+    /// A price account became stale, or fresh again after being stale
+    StalenessChanged {
+        account_key: Pubkey,
+        stale:       bool,
+    },
+    /// A delisted product account was removed
+    ProductRemoved { account_key: Pubkey },
+    /// A delisted price account was removed
+    PriceRemoved { account_key: Pubkey },
+}
+
 #[derive(Debug)]
 pub enum Update {
     ProductAccountUpdate {
@@ -97,19 +244,143 @@ pub enum Update {
         account:     ProductEntry,
     },
     PriceAccountUpdate {
-        account_key: Pubkey,
-        account:     PriceEntry,
+        account_key:   Pubkey,
+        account:       Arc<PriceEntry>,
+        trace_context: Context,
+    },
+    /// The product and price accounts found by a poll, the accounts missing
+    /// from which were delisted or closed
+    ListedAccounts {
+        product_accounts: HashSet<Pubkey>,
+        price_accounts:   HashSet<Pubkey>,
     },
 }
 
-#[derive(Debug)]
-pub enum Lookup {
-    LookupAllAccountsMetadata {
-        result_tx: oneshot::Sender<Result<AllAccountsMetadata>>,
-    },
-    LookupAllAccountsData {
-        result_tx: oneshot::Sender<Result<AllAccountsData>>,
-    },
+/// Snapshot is an immutable copy of the Global Store contents, published
+/// by the store after every batch of writes.
+#[derive(Debug, Clone, Default)]
+pub struct Snapshot {
+    pub account_data:     AllAccountsData,
+    pub account_metadata: AllAccountsMetadata,
+    /// Incremented on every publish, so that the readers can tell whether
+    /// the contents changed, e.g. to validate the cached HTTP responses
+    pub version:          u64,
+}
+
+/// SnapshotReader gives readers lock-free access to the latest Snapshot
+/// published by the Global Store. Cloning the reader is cheap, and so is
+/// loading a snapshot: readers never wait on the store task.
+#[derive(Clone, Default)]
+pub struct SnapshotReader {
+    snapshot: Arc<ArcSwap<Snapshot>>,
+}
+
+impl SnapshotReader {
+    pub fn new(snapshot: Snapshot) -> Self {
+        SnapshotReader {
+            snapshot: Arc::new(ArcSwap::from_pointee(snapshot)),
+        }
+    }
+
+    /// Returns the most recently published snapshot
+    pub fn load(&self) -> Arc<Snapshot> {
+        self.snapshot.load_full()
+    }
+
+    fn publish(&self, mut snapshot: Snapshot) {
+        snapshot.version = self.snapshot.load().version + 1;
+        self.snapshot.store(Arc::new(snapshot));
+    }
+}
+
+/// Number of slots a component may be behind the latest slot of its price account
+/// and still be aggregated, as in the Oracle program
+const MAX_SEND_LATENCY: u64 = 25;
+
+/// AggregatePreview is an estimate of the next aggregate of a price account
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct AggregatePreview {
+    pub price:          i64,
+    pub conf:           u64,
+    /// Unknown when fewer publishers than the minimum of the price account are
+    /// expected to be aggregated, in which case the price and confidence are
+    /// those of the current aggregate
+    pub status:         PriceStatus,
+    /// Number of publishers whose components are expected to be aggregated
+    pub num_publishers: usize,
+}
+
+/// Estimate the next aggregate of the price account, once the pending update of
+/// the publisher lands as its component. The aggregation of the Oracle program is
+/// replayed on the latest components: each recent trading component votes for its
+/// price and both ends of its confidence interval, the aggregate price is the
+/// median of the votes, and its confidence the distance to the furthest of their
+/// 25th and 75th percentiles. The components the other publishers land in the
+/// meantime make the actual aggregate differ.
+pub fn preview_aggregate(
+    price_account: &PriceEntry,
+    publisher: &Pubkey,
+    pending_update: Option<&PriceInfo>,
+) -> AggregatePreview {
+    let components = price_account.comp.iter().take(price_account.num as usize);
+    let latest_slot = components
+        .clone()
+        .map(|component| component.latest.pub_slot)
+        .chain(iter::once(price_account.agg.pub_slot))
+        .max()
+        .unwrap_or_default();
+
+    // The pending update replaces the latest component of the publisher, and
+    // lands in the latest slot at the earliest
+    let mut latest = components
+        .filter(|component| pending_update.is_none() || component.publisher != *publisher)
+        .map(|component| {
+            let latest = &component.latest;
+            (latest.status, latest.price, latest.conf, latest.pub_slot)
+        })
+        .collect::<Vec<_>>();
+    if let Some(update) = pending_update {
+        latest.push((update.status, update.price, update.conf, latest_slot));
+    }
+
+    let mut votes = vec![];
+    for (status, price, conf, pub_slot) in latest {
+        let conf = match i64::try_from(conf) {
+            Ok(conf) if 0 < conf && conf < price && price.checked_add(conf).is_some() => conf,
+            _ => continue,
+        };
+        if status == PriceStatus::Trading && latest_slot - pub_slot <= MAX_SEND_LATENCY {
+            votes.extend([price - conf, price, price + conf]);
+        }
+    }
+
+    let num_publishers = votes.len() / 3;
+    if num_publishers == 0 || num_publishers < price_account.min_pub as usize {
+        return AggregatePreview {
+            price: price_account.agg.price,
+            conf: price_account.agg.conf,
+            status: PriceStatus::Unknown,
+            num_publishers,
+        };
+    }
+
+    // Interpolate the given quartile of the votes
+    votes.sort_unstable();
+    let quartile = |quartile: usize| {
+        let position = (votes.len() - 1) * quartile;
+        let (index, remainder) = (position / 4, position % 4);
+        let low = votes[index] as i128;
+        let high = votes[(index + 1).min(votes.len() - 1)] as i128;
+        (low + (high - low) * remainder as i128 / 4) as i64
+    };
+    let (p25, price, p75) = (quartile(1), quartile(2), quartile(3));
+
+    AggregatePreview {
+        price,
+        conf: (p75 - price).max(price - p25) as u64,
+        status: PriceStatus::Trading,
+        num_publishers,
+    }
 }
 
 pub struct Store {
@@ -123,8 +394,11 @@ pub struct Store {
     /// Prometheus metrics for prices
     price_metrics: PriceGlobalMetrics,
 
-    /// Channel on which lookup requests are received
-    lookup_rx: mpsc::Receiver<Lookup>,
+    /// Prometheus metrics for the aggregation state of the prices, by symbol
+    aggregation_metrics: PriceAggregationMetrics,
+
+    /// Handle through which snapshots of the data are published to readers
+    snapshot_reader: SnapshotReader,
 
     /// Channel on which account updates are received from the primary network
     primary_updates_rx: mpsc::Receiver<Update>,
@@ -135,37 +409,56 @@ pub struct Store {
     /// Channel on which to communicate with the pythd API adapter
     pythd_adapter_tx: mpsc::Sender<adapter::Message>,
 
-    logger: Logger,
+    /// Channel on which events are broadcast to subscribers
+    events_tx: broadcast::Sender<Event>,
+
+    /// Price accounts currently considered stale
+    stale_prices: HashSet<Pubkey>,
+
+    /// Interval at which prices are checked for staleness
+    staleness_check_interval: Interval,
+
+    /// Accounts missing from the latest listing of the primary network, with
+    /// the time they first went missing
+    delisted_since: HashMap<Pubkey, Instant>,
+
+    config: Config,
 }
 
 pub fn spawn_store(
-    lookup_rx: mpsc::Receiver<Lookup>,
+    config: Config,
+    snapshot_reader: SnapshotReader,
     primary_updates_rx: mpsc::Receiver<Update>,
     secondary_updates_rx: mpsc::Receiver<Update>,
     pythd_adapter_tx: mpsc::Sender<adapter::Message>,
-    logger: Logger,
+    events_tx: broadcast::Sender<Event>,
 ) -> JoinHandle<()> {
-    tokio::spawn(async move {
-        Store::new(
-            lookup_rx,
-            primary_updates_rx,
-            secondary_updates_rx,
-            pythd_adapter_tx,
-            logger,
-        )
-        .await
-        .run()
-        .await
-    })
+    tokio::spawn(
+        async move {
+            Store::new(
+                config,
+                snapshot_reader,
+                primary_updates_rx,
+                secondary_updates_rx,
+                pythd_adapter_tx,
+                events_tx,
+            )
+            .await
+            .run()
+            .await
+        }
+        .instrument(info_span!("global_store")),
+    )
 }
 
 impl Store {
     pub async fn new(
-        lookup_rx: mpsc::Receiver<Lookup>,
+        config: Config,
+        snapshot_reader: SnapshotReader,
         primary_updates_rx: mpsc::Receiver<Update>,
         secondary_updates_rx: mpsc::Receiver<Update>,
         pythd_adapter_tx: mpsc::Sender<adapter::Message>,
-        logger: Logger,
+        events_tx: broadcast::Sender<Event>,
     ) -> Self {
         let prom_registry_ref = &mut &mut PROMETHEUS_REGISTRY.lock().await;
 
@@ -174,42 +467,229 @@ impl Store {
             account_metadata: Default::default(),
             product_metrics: ProductGlobalMetrics::new(prom_registry_ref),
             price_metrics: PriceGlobalMetrics::new(prom_registry_ref),
-            lookup_rx,
+            aggregation_metrics: PriceAggregationMetrics::new(prom_registry_ref),
+            snapshot_reader,
             primary_updates_rx,
             secondary_updates_rx,
             pythd_adapter_tx,
-            logger,
+            events_tx,
+            stale_prices: HashSet::new(),
+            staleness_check_interval: time::interval(config.staleness_check_interval_duration),
+            delisted_since: HashMap::new(),
+            config,
         }
     }
 
     pub async fn run(&mut self) {
         loop {
             if let Err(err) = self.handle_next().await {
-                error!(self.logger, "{:#}", err; "error" => format!("{:?}", err));
+                error!(error = ?err, kind = %error::record("global_store", &err), "{:#}", err);
             }
         }
     }
 
     async fn handle_next(&mut self) -> Result<()> {
-        tokio::select! {
+        let result = tokio::select! {
             Some(update) = self.primary_updates_rx.recv() => {
-                self.update_data(&update).await?;
-                self.update_metadata(&update)?;
+                self.handle_primary_update(&update).await
             }
             Some(update) = self.secondary_updates_rx.recv() => {
-                // We only use the secondary store to update the metadata, which is
-                // the same between both networks. This is so that if one network is offline
-                // we still have the metadata available to us. We don't update the data
-                // itself, because the aggregate prices may diverge slightly between
-                // the two networks.
-                self.update_metadata(&update)?;
+                self.handle_secondary_update(&update)
             }
-            Some(lookup) = self.lookup_rx.recv() => {
-                self.handle_lookup(lookup).await?
+            _ = self.staleness_check_interval.tick() => {
+                self.check_staleness();
+                if self.remove_delisted_accounts() {
+                    self.publish_snapshot();
+                }
+                return Ok(());
             }
         };
 
-        Ok(())
+        // Apply any further updates which are already queued before
+        // publishing, so that a burst of updates results in a single
+        // snapshot copy.
+        while let Ok(update) = self.primary_updates_rx.try_recv() {
+            if let Err(err) = self.handle_primary_update(&update).await {
+                error!(error = ?err, kind = %error::record("global_store", &err), "{:#}", err);
+            }
+        }
+        while let Ok(update) = self.secondary_updates_rx.try_recv() {
+            if let Err(err) = self.handle_secondary_update(&update) {
+                error!(error = ?err, kind = %error::record("global_store", &err), "{:#}", err);
+            }
+        }
+
+        self.publish_snapshot();
+
+        result
+    }
+
+    async fn handle_primary_update(&mut self, update: &Update) -> Result<()> {
+        self.update_data(update).await?;
+        self.update_metadata(update)
+    }
+
+    fn handle_secondary_update(&mut self, update: &Update) -> Result<()> {
+        // We only use the secondary store to update the metadata, which is
+        // the same between both networks. This is so that if one network is offline
+        // we still have the metadata available to us. We don't update the data
+        // itself, because the aggregate prices may diverge slightly between
+        // the two networks.
+        self.update_metadata(update)
+    }
+
+    /// Broadcast an event to all subscribers. Having no subscribers is not an error.
+    fn broadcast(&self, event: Event) {
+        let _ = self.events_tx.send(event);
+    }
+
+    /// Compare the publish timestamp of each price against the staleness
+    /// threshold, notifying subscribers of any price whose staleness changed.
+    fn check_staleness(&mut self) {
+        let now = Utc::now().timestamp();
+        let threshold = self.config.staleness_threshold.as_secs() as i64;
+
+        let mut changes = vec![];
+        for (account_key, account) in &self.account_data.price_accounts {
+            let stale = (now - account.timestamp) > threshold;
+            if stale != self.stale_prices.contains(account_key) {
+                changes.push((*account_key, stale));
+            }
+        }
+
+        for (account_key, stale) in changes {
+            if stale {
+                self.stale_prices.insert(account_key);
+            } else {
+                self.stale_prices.remove(&account_key);
+            }
+
+            debug!(
+                price_key = %account_key,
+                stale,
+                "Global store: price staleness changed"
+            );
+            self.broadcast(Event::StalenessChanged { account_key, stale });
+        }
+    }
+
+    /// Mark the known accounts missing from the listing of the primary network
+    /// as delisted, and those listed again as no longer delisted
+    fn observe_listed_accounts(
+        &mut self,
+        product_accounts: &HashSet<Pubkey>,
+        price_accounts: &HashSet<Pubkey>,
+    ) {
+        if self.config.delisted_account_ttl.is_none() {
+            return;
+        }
+
+        let delisted: HashSet<Pubkey> = self
+            .account_metadata
+            .product_accounts_metadata
+            .keys()
+            .filter(|account_key| !product_accounts.contains(account_key))
+            .chain(
+                self.account_metadata
+                    .price_accounts_metadata
+                    .keys()
+                    .filter(|account_key| !price_accounts.contains(account_key)),
+            )
+            .cloned()
+            .collect();
+
+        self.delisted_since.retain(|account_key, _| {
+            let listed_again = !delisted.contains(account_key);
+            if listed_again {
+                info!(account_key = %account_key, "Global store: delisted account listed again");
+            }
+            !listed_again
+        });
+        let now = Instant::now();
+        for account_key in delisted {
+            self.delisted_since.entry(account_key).or_insert_with(|| {
+                info!(account_key = %account_key, "Global store: account delisted");
+                now
+            });
+        }
+    }
+
+    /// Remove the accounts delisted for longer than the TTL, returning whether
+    /// any was removed
+    fn remove_delisted_accounts(&mut self) -> bool {
+        let ttl = match self.config.delisted_account_ttl {
+            Some(ttl) => ttl,
+            None => return false,
+        };
+        let expired: Vec<Pubkey> = self
+            .delisted_since
+            .iter()
+            .filter(|(_, delisted_since)| delisted_since.elapsed() >= ttl)
+            .map(|(account_key, _)| *account_key)
+            .collect();
+        if expired.is_empty() {
+            return false;
+        }
+
+        // The prices are removed first, so that their metrics are removed
+        // under the symbol of their product
+        let (product_keys, price_keys): (Vec<Pubkey>, Vec<Pubkey>) =
+            expired.into_iter().partition(|account_key| {
+                self.account_metadata
+                    .product_accounts_metadata
+                    .contains_key(account_key)
+            });
+        for account_key in price_keys {
+            self.delisted_since.remove(&account_key);
+            self.remove_price_account(&account_key);
+        }
+        for account_key in product_keys {
+            self.delisted_since.remove(&account_key);
+            self.remove_product_account(&account_key);
+        }
+        true
+    }
+
+    fn remove_price_account(&mut self, account_key: &Pubkey) {
+        self.price_metrics.remove(account_key);
+        self.aggregation_metrics.remove(
+            account_key,
+            &self.account_metadata.symbol_index.price_name(account_key),
+        );
+        self.account_data.price_accounts.remove(account_key);
+        self.account_metadata
+            .price_accounts_metadata
+            .remove(account_key);
+        self.stale_prices.remove(account_key);
+
+        info!(price_key = %account_key, "Global store: removed delisted price account");
+        self.broadcast(Event::PriceRemoved {
+            account_key: *account_key,
+        });
+    }
+
+    fn remove_product_account(&mut self, account_key: &Pubkey) {
+        let maybe_symbol = self
+            .account_metadata
+            .product_accounts_metadata
+            .get(account_key)
+            .and_then(|metadata| metadata.attr_dict.get(SYMBOL_ATTRIBUTE).cloned());
+        self.product_metrics.remove(account_key, maybe_symbol);
+        self.account_data.product_accounts.remove(account_key);
+        self.account_metadata.remove_product(account_key);
+
+        info!(product_key = %account_key, "Global store: removed delisted product account");
+        self.broadcast(Event::ProductRemoved {
+            account_key: *account_key,
+        });
+    }
+
+    fn publish_snapshot(&self) {
+        self.snapshot_reader.publish(Snapshot {
+            account_data:     self.account_data.clone(),
+            account_metadata: self.account_metadata.clone(),
+            version:          0,
+        });
     }
 
     async fn update_data(&mut self, update: &Update) -> Result<()> {
@@ -220,7 +700,7 @@ impl Store {
             } => {
                 let attr_dict = ProductAccountMetadata::from(account.clone()).attr_dict;
 
-                let maybe_symbol = attr_dict.get("symbol").cloned();
+                let maybe_symbol = attr_dict.get(SYMBOL_ATTRIBUTE).cloned();
 
                 self.product_metrics.update(account_key, maybe_symbol);
 
@@ -232,46 +712,82 @@ impl Store {
             Update::PriceAccountUpdate {
                 account_key,
                 account,
+                trace_context,
             } => {
-                // Sanity-check that we are updating with more recent data
-                if let Some(existing_price) = self.account_data.price_accounts.get(account_key) {
-                    if existing_price.timestamp > account.timestamp {
-                        // This message is not an error. It is common
-                        // for primary and secondary network to have
-                        // slight difference in their timestamps.
-                        debug!(self.logger, "Global store: ignoring stale update of an existing newer price";
-                        "price_key" => account_key.to_string(),
-                        "existing_timestamp" => existing_price.timestamp,
-                        "new_timestamp" => account.timestamp,
-                                      );
-                        return Ok(());
-                    }
-                }
+                let trace_context = telemetry::start_span(
+                    trace_context,
+                    "global_store.price_update",
+                    vec![KeyValue::new("price_account", account_key.to_string())],
+                );
+                let result = self
+                    .update_price_account(account_key, account, &trace_context)
+                    .await;
+                telemetry::end_span(&trace_context, &result);
+                result?;
+            }
+            Update::ListedAccounts {
+                product_accounts,
+                price_accounts,
+            } => self.observe_listed_accounts(product_accounts, price_accounts),
+        }
 
-                // Update metrics
-                self.price_metrics.update(account_key, account);
+        Ok(())
+    }
 
-                // Update the stored data
-                self.account_data
-                    .price_accounts
-                    .insert(*account_key, *account);
-
-                // Notify the Pythd API adapter that this account has changed
-                self.pythd_adapter_tx
-                    .send(adapter::Message::GlobalStoreUpdate {
-                        price_identifier: Identifier::new(account_key.to_bytes()),
-                        price:            account.agg.price,
-                        conf:             account.agg.conf,
-                        status:           account.agg.status,
-                        valid_slot:       account.valid_slot,
-                        pub_slot:         account.agg.pub_slot,
-                    })
-                    .await
-                    .map_err(|_| anyhow!("failed to notify pythd adapter of account update"))?;
+    async fn update_price_account(
+        &mut self,
+        account_key: &Pubkey,
+        account: &Arc<PriceEntry>,
+        trace_context: &Context,
+    ) -> Result<()> {
+        // Sanity-check that we are updating with more recent data
+        if let Some(existing_price) = self.account_data.price_accounts.get(account_key) {
+            if existing_price.timestamp > account.timestamp {
+                // This message is not an error. It is common
+                // for primary and secondary network to have
+                // slight difference in their timestamps.
+                debug!(
+                    price_key = %account_key,
+                    existing_timestamp = existing_price.timestamp,
+                    new_timestamp = account.timestamp,
+                    "Global store: ignoring stale update of an existing newer price"
+                );
+                return Ok(());
             }
         }
 
-        Ok(())
+        // Update metrics
+        self.price_metrics.update(account_key, account);
+        self.aggregation_metrics.update(
+            account_key,
+            &self.account_metadata.symbol_index.price_name(account_key),
+            account,
+        );
+
+        // Update the stored data
+        self.account_data
+            .price_accounts
+            .insert(*account_key, account.clone());
+
+        self.broadcast(Event::PriceUpdated {
+            account_key:   *account_key,
+            account:       account.clone(),
+            trace_context: trace_context.clone(),
+        });
+
+        // Notify the Pythd API adapter that this account has changed
+        self.pythd_adapter_tx
+            .send(adapter::Message::GlobalStoreUpdate {
+                price_identifier: Identifier::new(account_key.to_bytes()),
+                price:            account.agg.price,
+                conf:             account.agg.conf,
+                status:           account.agg.status,
+                valid_slot:       account.valid_slot,
+                pub_slot:         account.agg.pub_slot,
+            })
+            .await
+            .map_err(|_| Error::ChannelClosed("adapter"))
+            .context("failed to notify pythd adapter of account update")
     }
 
     fn update_metadata(&mut self, update: &Update) -> Result<()> {
@@ -280,33 +796,206 @@ impl Store {
                 account_key,
                 account,
             } => {
-                self.account_metadata
-                    .product_accounts_metadata
-                    .insert(*account_key, account.clone().into());
+                let metadata: ProductAccountMetadata = account.clone().into();
+
+                let previous = self
+                    .account_metadata
+                    .insert_product(*account_key, metadata.clone());
+
+                if previous.is_none() {
+                    self.broadcast(Event::NewProduct {
+                        account_key: *account_key,
+                        metadata,
+                    });
+                }
 
                 Ok(())
             }
             Update::PriceAccountUpdate {
                 account_key,
                 account,
+                ..
             } => {
                 self.account_metadata
                     .price_accounts_metadata
-                    .insert(*account_key, (*account).into());
+                    .insert(*account_key, account.as_ref().into());
 
                 Ok(())
             }
+            // Only the listings of the primary network are followed, as part
+            // of the data
+            Update::ListedAccounts { .. } => Ok(()),
         }
     }
+}
 
-    async fn handle_lookup(&self, lookup: Lookup) -> Result<()> {
-        match lookup {
-            Lookup::LookupAllAccountsMetadata { result_tx } => result_tx
-                .send(Ok(self.account_metadata.clone()))
-                .map_err(|_| anyhow!("failed to send metadata to pythd adapter")),
-            Lookup::LookupAllAccountsData { result_tx } => result_tx
-                .send(Ok(self.account_data.clone()))
-                .map_err(|_| anyhow!("failed to send data to pythd adapter")),
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            preview_aggregate,
+            AggregatePreview,
+            Config,
+            Event,
+            SnapshotReader,
+            Store,
+            Update,
+        },
+        crate::agent::{
+            solana::oracle::{
+                PriceEntry,
+                ProductEntry,
+            },
+            store::local::PriceInfo,
+        },
+        bytemuck::Zeroable,
+        opentelemetry::Context,
+        pyth_sdk_solana::state::{
+            PriceStatus,
+            ProductAccount,
+            PROD_HDR_SIZE,
+        },
+        solana_sdk::pubkey::Pubkey,
+        std::{
+            collections::HashSet,
+            sync::Arc,
+            time::Duration,
+        },
+        tokio::sync::{
+            broadcast,
+            mpsc,
+        },
+    };
+
+    #[tokio::test]
+    async fn test_delisted_accounts_are_removed_after_the_ttl() {
+        let (_primary_updates_tx, primary_updates_rx) = mpsc::channel(10);
+        let (_secondary_updates_tx, secondary_updates_rx) = mpsc::channel(10);
+        let (pythd_adapter_tx, _pythd_adapter_rx) = mpsc::channel(10);
+        let (events_tx, mut events_rx) = broadcast::channel(10);
+        let mut store = Store::new(
+            Config {
+                delisted_account_ttl: Some(Duration::ZERO),
+                ..Default::default()
+            },
+            SnapshotReader::default(),
+            primary_updates_rx,
+            secondary_updates_rx,
+            pythd_adapter_tx,
+            events_tx,
+        )
+        .await;
+
+        let (product_key, price_key) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut product_account = ProductAccount::zeroed();
+        product_account.size = PROD_HDR_SIZE as u32;
+        let updates = [
+            Update::ProductAccountUpdate {
+                account_key: product_key,
+                account:     ProductEntry {
+                    account_data:   product_account,
+                    price_accounts: vec![price_key],
+                },
+            },
+            Update::PriceAccountUpdate {
+                account_key:   price_key,
+                account:       Arc::new(PriceEntry::default()),
+                trace_context: Context::new(),
+            },
+        ];
+        for update in &updates {
+            store.handle_primary_update(update).await.unwrap();
         }
+
+        // Accounts still listed are kept
+        let listed = Update::ListedAccounts {
+            product_accounts: HashSet::from([product_key]),
+            price_accounts:   HashSet::from([price_key]),
+        };
+        store.handle_primary_update(&listed).await.unwrap();
+        assert!(!store.remove_delisted_accounts());
+
+        // Delisted accounts are removed once the TTL elapsed
+        let delisted = Update::ListedAccounts {
+            product_accounts: HashSet::new(),
+            price_accounts:   HashSet::new(),
+        };
+        store.handle_primary_update(&delisted).await.unwrap();
+        assert!(store.remove_delisted_accounts());
+        assert!(store.account_data.price_accounts.is_empty());
+        assert!(store.account_data.product_accounts.is_empty());
+        assert!(store.account_metadata.product_accounts_metadata.is_empty());
+        assert!(store.account_metadata.price_accounts_metadata.is_empty());
+
+        let mut removed = vec![];
+        while let Ok(event) = events_rx.try_recv() {
+            match event {
+                Event::PriceRemoved { account_key } => removed.push(("price", account_key)),
+                Event::ProductRemoved { account_key } => removed.push(("product", account_key)),
+                _ => {}
+            }
+        }
+        assert_eq!(
+            removed,
+            vec![("price", price_key), ("product", product_key)]
+        );
+    }
+
+    #[test]
+    fn test_pending_update_moves_the_preview() {
+        let publishers = [
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        ];
+        let mut price_account = PriceEntry::default();
+        price_account.num = 3;
+        price_account.min_pub = 3;
+        for (component, (publisher, price)) in price_account
+            .comp
+            .iter_mut()
+            .zip(publishers.iter().zip([100, 102, 104]))
+        {
+            component.publisher = *publisher;
+            component.latest.status = PriceStatus::Trading;
+            component.latest.price = price;
+            component.latest.conf = 2;
+            component.latest.pub_slot = 50;
+        }
+
+        // Without a pending update, the latest components are aggregated
+        assert_eq!(
+            preview_aggregate(&price_account, &publishers[2], None),
+            AggregatePreview {
+                price:          102,
+                conf:           2,
+                status:         PriceStatus::Trading,
+                num_publishers: 3,
+            }
+        );
+
+        // The pending update replaces the component of the publisher
+        let update = PriceInfo {
+            status:    PriceStatus::Trading,
+            price:     110,
+            conf:      2,
+            timestamp: 0,
+        };
+        assert_eq!(
+            preview_aggregate(&price_account, &publishers[2], Some(&update)),
+            AggregatePreview {
+                price:          102,
+                conf:           6,
+                status:         PriceStatus::Trading,
+                num_publishers: 3,
+            }
+        );
+
+        // Stale components are left out, below the minimum number of publishers
+        price_account.comp[0].latest.pub_slot = 20;
+        assert_eq!(
+            preview_aggregate(&price_account, &publishers[2], Some(&update)).status,
+            PriceStatus::Unknown
+        );
     }
 }
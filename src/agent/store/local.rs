@@ -2,30 +2,195 @@
 // is contributing to the network. The Exporters will then take this data and publish
 // it to the networks.
 use {
-    super::PriceIdentifier,
-    crate::agent::metrics::{
-        PriceLocalMetrics,
-        PROMETHEUS_REGISTRY,
+    super::{
+        global,
+        PriceIdentifier,
+    },
+    crate::agent::{
+        error::{
+            self,
+            Error,
+        },
+        metrics::{
+            PriceLocalMetrics,
+            PROMETHEUS_REGISTRY,
+        },
+        publish_latency,
+        reference_prices::ReferencePrices,
+        shutdown,
+        telemetry,
+        update_status::{
+            Seq,
+            UpdateStatuses,
+        },
     },
     anyhow::{
         anyhow,
+        Context as _,
         Result,
     },
+    chrono::Utc,
+    opentelemetry::Context,
     pyth_sdk::UnixTimestamp,
     pyth_sdk_solana::state::PriceStatus,
-    slog::Logger,
-    solana_sdk::bs58,
-    std::collections::HashMap,
+    serde::{
+        Deserialize,
+        Serialize,
+    },
+    solana_sdk::{
+        bs58,
+        pubkey::Pubkey,
+    },
+    std::{
+        collections::{
+            HashMap,
+            HashSet,
+        },
+        fs,
+        path::PathBuf,
+        str::FromStr,
+        time::{
+            Duration,
+            Instant,
+        },
+    },
     tokio::{
         sync::{
+            broadcast,
             mpsc,
             oneshot,
+            watch,
         },
         task::JoinHandle,
+        time::{
+            self,
+            Interval,
+        },
     },
+    tracing::Instrument,
 };
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Path of the file the Local Store contents are persisted to, so that
+    /// prices submitted shortly before a restart can still be published
+    /// afterwards. Persistence is disabled when not set.
+    pub persistence_path:              Option<PathBuf>,
+    /// Duration of the interval at which changed contents are written to disk
+    #[serde(with = "humantime_serde")]
+    pub persistence_interval_duration: Duration,
+    /// Persisted prices older than this are discarded when restoring on startup
+    #[serde(with = "humantime_serde")]
+    pub persistence_max_age:           Duration,
+    /// Bounds every trading price update is validated against, unless
+    /// overridden for the symbol in `price_bounds`
+    pub default_price_bounds:          PriceBounds,
+    /// Per-symbol bounds, keyed by symbol, e.g. "Crypto.BTC/USD". These replace
+    /// `default_price_bounds` entirely for the price accounts of the symbol.
+    pub price_bounds:                  HashMap<String, PriceBounds>,
+    /// Updates repeating the price, confidence and status of the last accepted
+    /// update of a price within this window are dropped, and counted in the
+    /// `local_store_duplicate_update_count` metric. Disabled when not set.
+    #[serde(with = "humantime_serde")]
+    pub dedup_window:                  Option<Duration>,
+    /// Sheds the queued updates superseded by a later update of the same price
+    /// when the updates back up in the channel of the Local Store. Disabled
+    /// when not set.
+    pub load_shedding:                 Option<LoadSheddingConfig>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            persistence_path:              None,
+            persistence_interval_duration: Duration::from_secs(1),
+            persistence_max_age:           Duration::from_secs(60),
+            default_price_bounds:          Default::default(),
+            price_bounds:                  HashMap::new(),
+            dedup_window:                  None,
+            load_shedding:                 None,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoadSheddingConfig {
+    /// Queued messages beyond which the superseded updates are shed
+    pub backlog_threshold: usize,
+    /// Most queued messages taken off the channel at once
+    pub max_backlog:       usize,
+}
+
+impl Default for LoadSheddingConfig {
+    fn default() -> Self {
+        Self {
+            backlog_threshold: 1000,
+            max_backlog:       10000,
+        }
+    }
+}
+
+/// Sanity bounds on the price updates accepted into the Local Store. Prices are
+/// expressed in the same exponent-scaled integer units as the updates.
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct PriceBounds {
+    /// Updates with a lower price are rejected
+    pub min_price:      Option<i64>,
+    /// Updates with a higher price are rejected
+    pub max_price:      Option<i64>,
+    /// Updates with a higher ratio of confidence interval to absolute price are rejected
+    pub max_conf_ratio: Option<f64>,
+}
+
+impl PriceBounds {
+    /// Check the price update against the bounds. Only updates with a trading
+    /// status are checked, as other statuses do not carry a meaningful price.
+    fn validate(&self, price_info: &PriceInfo) -> Result<()> {
+        if price_info.status != PriceStatus::Trading {
+            return Ok(());
+        }
+
+        if let Some(min_price) = self.min_price {
+            if price_info.price < min_price {
+                return Err(anyhow!(
+                    "price {} below minimum of {}",
+                    price_info.price,
+                    min_price
+                ));
+            }
+        }
+
+        if let Some(max_price) = self.max_price {
+            if price_info.price > max_price {
+                return Err(anyhow!(
+                    "price {} above maximum of {}",
+                    price_info.price,
+                    max_price
+                ));
+            }
+        }
+
+        if let Some(max_conf_ratio) = self.max_conf_ratio {
+            let conf_ratio = price_info.conf as f64 / price_info.price.unsigned_abs() as f64;
+            // A zero price yields an infinite (or NaN, for a zero conf) ratio
+            if conf_ratio.is_nan() || conf_ratio > max_conf_ratio {
+                return Err(anyhow!(
+                    "confidence {} too wide for price {} (max ratio {})",
+                    price_info.conf,
+                    price_info.price,
+                    max_conf_ratio
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct PriceInfo {
     pub status:    PriceStatus,
     pub price:     i64,
@@ -49,86 +214,871 @@ impl PriceInfo {
     }
 }
 
+/// The publisher a price update is submitted on behalf of, identified by its
+/// publish public key. `None` designates the default publish keypair of each
+/// network (the one configured in its key store, or loaded remotely).
+pub type Publisher = Option<Pubkey>;
+
+/// The latest price information of every price, per publisher
+pub type AllPriceInfo = HashMap<Publisher, HashMap<PriceIdentifier, PriceInfo>>;
+
+/// Trace context of the latest accepted update of every price, per publisher
+pub type AllTraceContexts = HashMap<(Publisher, PriceIdentifier), Context>;
+
+/// Updates of a price rejected for being outside its bounds
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rejection {
+    pub count:       u64,
+    /// Why the latest of these updates was rejected
+    pub last_reason: String,
+}
+
 #[derive(Debug)]
 pub enum Message {
     Update {
+        publisher:        Publisher,
         price_identifier: PriceIdentifier,
         price_info:       PriceInfo,
+        /// Sequence id the API assigned to the update, if it came through it
+        seq:              Option<Seq>,
+        /// When the update arrived at the agent, e.g. at the pythd API, which
+        /// its publish latency is measured from
+        received_at:      Instant,
+        /// Whether the update was replicated from the peer agent, rather than
+        /// submitted to this one
+        replicated:       bool,
+        trace_context:    Context,
     },
     LookupAllPriceInfo {
-        result_tx: oneshot::Sender<HashMap<PriceIdentifier, PriceInfo>>,
+        result_tx: oneshot::Sender<AllPriceInfo>,
+    },
+    LookupAllTraceContexts {
+        result_tx: oneshot::Sender<AllTraceContexts>,
+    },
+    /// Look up the updates rejected since the previous lookup, by price
+    LookupRejections {
+        result_tx: oneshot::Sender<HashMap<PriceIdentifier, Rejection>>,
     },
 }
 
-pub fn spawn_store(rx: mpsc::Receiver<Message>, logger: Logger) -> JoinHandle<()> {
-    tokio::spawn(async move { Store::new(rx, logger).await.run().await })
+/// Event is broadcast by the Local Store to its subscribers whenever it accepts
+/// an update. Obtain a receiver with `broadcast::Sender::subscribe()` on the
+/// events channel passed to `spawn_store`.
+#[derive(Debug, Clone)]
+pub enum Event {
+    PriceUpdated {
+        publisher:        Publisher,
+        price_identifier: PriceIdentifier,
+        price_info:       PriceInfo,
+        /// Whether the update was replicated from the peer agent. Replicated
+        /// updates are not streamed back to the peer.
+        replicated:       bool,
+    },
+}
+
+/// On-disk representation of a single Local Store entry, also used to
+/// replicate the entries to a standby agent
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PersistedPriceInfo {
+    publisher:        Option<String>,
+    price_identifier: String,
+    price_info:       PriceInfo,
+}
+
+impl PersistedPriceInfo {
+    pub fn new(publisher: &Publisher, identifier: &PriceIdentifier, info: &PriceInfo) -> Self {
+        PersistedPriceInfo {
+            publisher:        publisher.map(|key| key.to_string()),
+            price_identifier: Pubkey::new_from_array(identifier.to_bytes()).to_string(),
+            price_info:       info.clone(),
+        }
+    }
+
+    pub fn parse(self) -> Result<(Publisher, PriceIdentifier, PriceInfo)> {
+        let publisher = self
+            .publisher
+            .map(|key| Pubkey::from_str(&key))
+            .transpose()?;
+        let identifier = PriceIdentifier::new(Pubkey::from_str(&self.price_identifier)?.to_bytes());
+        Ok((publisher, identifier, self.price_info))
+    }
+}
+
+pub fn spawn_store(
+    config_rx: watch::Receiver<Config>,
+    rx: mpsc::Receiver<Message>,
+    publish_latency_tx: mpsc::Sender<publish_latency::Message>,
+    events_tx: broadcast::Sender<Event>,
+    global_store_reader: global::SnapshotReader,
+    reference_prices: Option<ReferencePrices>,
+    update_statuses: UpdateStatuses,
+    shutdown: shutdown::Participant,
+) -> JoinHandle<()> {
+    tokio::spawn(
+        async move {
+            Store::new(
+                config_rx,
+                rx,
+                publish_latency_tx,
+                events_tx,
+                global_store_reader,
+                reference_prices,
+                update_statuses,
+            )
+            .await
+            .run(shutdown)
+            .await
+        }
+        .instrument(info_span!("local_store")),
+    )
 }
 
 pub struct Store {
-    prices:  HashMap<PriceIdentifier, PriceInfo>,
-    metrics: PriceLocalMetrics,
-    rx:      mpsc::Receiver<Message>,
-    logger:  Logger,
+    prices:               AllPriceInfo,
+    /// When the stored update of each price was accepted, for the dedup window
+    accepted_at:          HashMap<(Publisher, PriceIdentifier), Instant>,
+    /// Trace contexts of the accepted updates, which the Exporters link their spans to
+    trace_contexts:       AllTraceContexts,
+    metrics:              PriceLocalMetrics,
+    rx:                   mpsc::Receiver<Message>,
+    /// Channel on which accepted updates are stamped for the Publish Latency Tracker
+    publish_latency_tx:   mpsc::Sender<publish_latency::Message>,
+    /// Channel on which accepted updates are broadcast to subscribers
+    events_tx:            broadcast::Sender<Event>,
+    /// Interval at which the contents are persisted to disk
+    persistence_interval: Interval,
+    /// Whether the contents changed since they were last persisted
+    dirty:                bool,
+    /// Resolves the symbols of the prices, which the bounds are configured by
+    global_store_reader:  global::SnapshotReader,
+    /// Updates rejected since the last lookup
+    rejections:           HashMap<PriceIdentifier, Rejection>,
+    /// Reference prices the updates are cross-checked against, if configured
+    reference_prices:     Option<ReferencePrices>,
+    /// Whether the last backlog was shed
+    shedding:             bool,
+    /// Statuses of the updates accepted by the API, reported as they are
+    /// stored or rejected
+    update_statuses:      UpdateStatuses,
+    config:               Config,
+    /// Watched for the price bounds reloaded on SIGHUP
+    config_rx:            watch::Receiver<Config>,
 }
 
 impl Store {
-    pub async fn new(rx: mpsc::Receiver<Message>, logger: Logger) -> Self {
-        Store {
+    pub async fn new(
+        config_rx: watch::Receiver<Config>,
+        rx: mpsc::Receiver<Message>,
+        publish_latency_tx: mpsc::Sender<publish_latency::Message>,
+        events_tx: broadcast::Sender<Event>,
+        global_store_reader: global::SnapshotReader,
+        reference_prices: Option<ReferencePrices>,
+        update_statuses: UpdateStatuses,
+    ) -> Self {
+        let config = config_rx.borrow().clone();
+
+        let mut store = Store {
             prices: HashMap::new(),
+            accepted_at: HashMap::new(),
+            trace_contexts: HashMap::new(),
             metrics: PriceLocalMetrics::new(&mut &mut PROMETHEUS_REGISTRY.lock().await),
             rx,
-            logger,
+            publish_latency_tx,
+            events_tx,
+            persistence_interval: time::interval(config.persistence_interval_duration),
+            dirty: false,
+            global_store_reader,
+            rejections: HashMap::new(),
+            reference_prices,
+            shedding: false,
+            update_statuses,
+            config,
+            config_rx,
+        };
+
+        if let Err(err) = store.restore() {
+            error!(error = ?err, kind = %error::record("local_store", &err), "Local store: could not restore persisted prices: {:#}", err);
+        }
+
+        store
+    }
+
+    /// Handle the messages until the channel is closed or the shutdown asks to
+    /// persist the contents
+    pub async fn run(&mut self, mut shutdown: shutdown::Participant) {
+        loop {
+            tokio::select! {
+                message = self.rx.recv() => match message {
+                    Some(message) => {
+                        for message in self.take_backlog(message) {
+                            if let Err(err) = self.handle(message) {
+                                error!(error = ?err, kind = %error::record("local_store", &err), "{:#}", err)
+                            }
+                        }
+                    }
+                    None => break,
+                },
+                _ = self.persistence_interval.tick() => {
+                    if let Err(err) = self.persist() {
+                        error!(error = ?err, kind = %error::record("local_store", &err), "{:#}", err)
+                    }
+                }
+                Ok(()) = self.config_rx.changed() => self.reload_config(),
+                _ = shutdown.wait() => break,
+            }
+        }
+
+        // Persist whatever arrived since the last tick before exiting
+        if let Err(err) = self.persist() {
+            error!(error = ?err, kind = %error::record("local_store", &err), "{:#}", err)
         }
     }
 
-    pub async fn run(&mut self) {
-        while let Some(message) = self.rx.recv().await {
-            if let Err(err) = self.handle(message) {
-                error!(self.logger, "{:#}", err; "error" => format!("{:?}", err))
+    /// The message along with the messages queued behind it if load shedding is
+    /// enabled, without the updates superseded by a later one if they exceed the
+    /// backlog threshold
+    fn take_backlog(&mut self, message: Message) -> Vec<Message> {
+        let load_shedding = match &self.config.load_shedding {
+            Some(load_shedding) => load_shedding.clone(),
+            None => return vec![message],
+        };
+
+        let mut backlog = vec![message];
+        while backlog.len() < load_shedding.max_backlog {
+            match self.rx.try_recv() {
+                Ok(message) => backlog.push(message),
+                Err(_) => break,
+            }
+        }
+
+        let overloaded = backlog.len() > load_shedding.backlog_threshold;
+        let mut shed_count = 0;
+        if overloaded {
+            for (publisher, price_identifier) in shed(&mut backlog) {
+                self.metrics.shed(&publisher, &price_identifier);
+                shed_count += 1;
             }
         }
+        match (self.shedding, overloaded) {
+            (false, true) => warn!(
+                backlog = backlog.len() + shed_count,
+                shed_count, "Local store: updates are backing up, shedding superseded updates"
+            ),
+            (true, false) => info!("Local store: the backlog cleared, no longer shedding updates"),
+            _ => {}
+        }
+        self.shedding = overloaded;
+
+        backlog
+    }
+
+    /// Apply the price bounds of the reloaded config
+    fn reload_config(&mut self) {
+        self.config = self.config_rx.borrow().clone();
+        info!(
+            default_price_bounds = ?self.config.default_price_bounds,
+            price_bounds = self.config.price_bounds.len(),
+            "Local store: price bounds reloaded"
+        );
+    }
+
+    /// Write the store contents to the persistence file, if configured and
+    /// changed since last time. The file is replaced atomically.
+    fn persist(&mut self) -> Result<()> {
+        let path = match (&self.config.persistence_path, self.dirty) {
+            (Some(path), true) => path,
+            _ => return Ok(()),
+        };
+
+        let persisted = self
+            .prices
+            .iter()
+            .flat_map(|(publisher, prices)| {
+                prices
+                    .iter()
+                    .map(|(identifier, info)| PersistedPriceInfo::new(publisher, identifier, info))
+            })
+            .collect::<Vec<_>>();
+
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, serde_json::to_vec(&persisted)?)
+            .with_context(|| format!("writing local store to {}", tmp_path.display()))?;
+        fs::rename(&tmp_path, path)
+            .with_context(|| format!("replacing local store file {}", path.display()))?;
+
+        self.dirty = false;
+
+        Ok(())
+    }
+
+    /// Load previously persisted prices, skipping any older than the configured max age.
+    fn restore(&mut self) -> Result<()> {
+        let path = match &self.config.persistence_path {
+            Some(path) if path.exists() => path,
+            _ => return Ok(()),
+        };
+
+        let contents = fs::read(path)
+            .with_context(|| format!("reading local store from {}", path.display()))?;
+        let persisted: Vec<PersistedPriceInfo> = serde_json::from_slice(&contents)?;
+
+        let now = Utc::now().timestamp();
+        let max_age = self.config.persistence_max_age.as_secs() as i64;
+
+        let mut restored = 0;
+        for entry in persisted {
+            if now - entry.price_info.timestamp > max_age {
+                continue;
+            }
+
+            let (publisher, identifier, price_info) = entry.parse()?;
+            self.metrics.update(&publisher, &identifier, &price_info);
+            self.prices
+                .entry(publisher)
+                .or_default()
+                .insert(identifier, price_info);
+            restored += 1;
+        }
+
+        info!(
+            path = %path.display(),
+            restored,
+            "Local store: restored persisted prices"
+        );
+
+        Ok(())
     }
 
     fn handle(&mut self, message: Message) -> Result<()> {
         match message {
             Message::Update {
+                publisher,
                 price_identifier,
                 price_info,
+                seq,
+                received_at,
+                replicated,
+                trace_context,
             } => {
-                self.update(price_identifier, price_info)?;
+                let trace_context =
+                    telemetry::start_span(&trace_context, "local_store.update", vec![]);
+                let result = self.update(publisher, price_identifier, price_info.clone());
+                telemetry::end_span(&trace_context, &result);
+                match (&result, seq) {
+                    (Ok(true), Some(seq)) => self.update_statuses.stored(
+                        seq,
+                        publisher,
+                        price_identifier,
+                        price_info.timestamp,
+                    ),
+                    (Ok(false), Some(seq)) => self
+                        .update_statuses
+                        .reject(seq, "dropped as a duplicate of the last update"),
+                    (Err(err), Some(seq)) => self.update_statuses.reject(seq, format!("{:#}", err)),
+                    (_, None) => {}
+                }
+                if !result? {
+                    return Ok(());
+                }
+
+                // Sending only fails when there are no subscribers
+                let _ = self.events_tx.send(Event::PriceUpdated {
+                    publisher,
+                    price_identifier,
+                    price_info: price_info.clone(),
+                    replicated,
+                });
+                self.track_publish_latency(
+                    publisher,
+                    price_identifier,
+                    price_info,
+                    received_at,
+                    trace_context.clone(),
+                );
+                self.trace_contexts
+                    .insert((publisher, price_identifier), trace_context);
                 Ok(())
             }
             Message::LookupAllPriceInfo { result_tx } => result_tx
                 .send(self.get_all_price_infos())
-                .map_err(|_| anyhow!("failed to send LookupAllPriceInfo result")),
+                .map_err(|_| Error::ChannelClosed("requester"))
+                .context("failed to send LookupAllPriceInfo result"),
+            Message::LookupAllTraceContexts { result_tx } => result_tx
+                .send(self.trace_contexts.clone())
+                .map_err(|_| Error::ChannelClosed("requester"))
+                .context("failed to send LookupAllTraceContexts result"),
+            Message::LookupRejections { result_tx } => result_tx
+                .send(std::mem::take(&mut self.rejections))
+                .map_err(|_| Error::ChannelClosed("requester"))
+                .context("failed to send LookupRejections result"),
         }
     }
 
+    /// Store the update, returning whether it was stored rather than dropped
+    /// as a duplicate
     pub fn update(
         &mut self,
+        publisher: Publisher,
         price_identifier: PriceIdentifier,
         price_info: PriceInfo,
-    ) -> Result<()> {
-        debug!(self.logger, "local store received price update"; "identifier" => bs58::encode(price_identifier.to_bytes()).into_string());
+    ) -> Result<bool> {
+        debug!(
+            identifier = %bs58::encode(price_identifier.to_bytes()).into_string(),
+            ?publisher,
+            "local store received price update"
+        );
+
+        // Drop the update if it repeats the last accepted one within the dedup window
+        if self.is_duplicate(&publisher, &price_identifier, &price_info) {
+            self.metrics.drop_duplicate(&publisher, &price_identifier);
+            return Ok(false);
+        }
+
+        // Reject updates outside the sanity bounds configured for the symbol of
+        // the price, or deviating too far from its reference price
+        let snapshot = self.global_store_reader.load();
+        let bounds = snapshot
+            .account_metadata
+            .symbol_index
+            .symbol_of_identifier(&price_identifier)
+            .and_then(|symbol| self.config.price_bounds.get(symbol))
+            .unwrap_or(&self.config.default_price_bounds);
+        let validation = bounds.validate(&price_info).and_then(|()| {
+            self.reference_prices
+                .as_ref()
+                .map_or(Ok(()), |reference_prices| {
+                    reference_prices.check(&publisher, &price_identifier, &price_info)
+                })
+        });
+        if let Err(err) = validation {
+            self.metrics.reject(&publisher, &price_identifier);
+            let rejection = self
+                .rejections
+                .entry(price_identifier)
+                .or_insert_with(|| Rejection {
+                    count:       0,
+                    last_reason: String::new(),
+                });
+            rejection.count += 1;
+            rejection.last_reason = err.to_string();
+            return Err(err.context(Error::Store(format!(
+                "Rejected update for price {}",
+                price_identifier
+            ))));
+        }
+
+        let prices = self.prices.entry(publisher).or_default();
 
         // Drop the update if it is older than the current one stored for the price
-        if let Some(current_price_info) = self.prices.get(&price_identifier) {
+        if let Some(current_price_info) = prices.get(&price_identifier) {
             if current_price_info.timestamp > price_info.timestamp {
-                return Err(anyhow!(
+                return Err(Error::Store(format!(
                     "Received stale timestamp for price {}",
                     price_identifier
-                ));
+                ))
+                .into());
             }
         }
 
-        self.metrics.update(&price_identifier, &price_info);
+        self.metrics
+            .update(&publisher, &price_identifier, &price_info);
 
-        self.prices.insert(price_identifier, price_info);
+        prices.insert(price_identifier, price_info);
+        self.accepted_at
+            .insert((publisher, price_identifier), Instant::now());
+        self.dirty = true;
 
-        Ok(())
+        Ok(true)
+    }
+
+    /// Whether the update has the same price, confidence and status as the
+    /// stored update of the price, accepted within the dedup window
+    fn is_duplicate(
+        &self,
+        publisher: &Publisher,
+        price_identifier: &PriceIdentifier,
+        price_info: &PriceInfo,
+    ) -> bool {
+        let dedup_window = match self.config.dedup_window {
+            Some(dedup_window) => dedup_window,
+            None => return false,
+        };
+        let current_price_info = self
+            .prices
+            .get(publisher)
+            .and_then(|prices| prices.get(price_identifier));
+        match (
+            current_price_info,
+            self.accepted_at.get(&(*publisher, *price_identifier)),
+        ) {
+            (Some(current_price_info), Some(accepted_at)) => {
+                current_price_info.cmp_no_timestamp(price_info)
+                    && accepted_at.elapsed() < dedup_window
+            }
+            _ => false,
+        }
     }
 
-    pub fn get_all_price_infos(&self) -> HashMap<PriceIdentifier, PriceInfo> {
+    /// Hand an accepted update over to the Publish Latency Tracker, with the
+    /// time it arrived at the agent
+    fn track_publish_latency(
+        &self,
+        publisher: Publisher,
+        price_identifier: PriceIdentifier,
+        price_info: PriceInfo,
+        received_at: Instant,
+        trace_context: Context,
+    ) {
+        // Latency tracking must never hold up the store, so the update is not
+        // tracked if the tracker is behind.
+        if self
+            .publish_latency_tx
+            .try_send(publish_latency::Message::LocalUpdate {
+                publisher,
+                price_identifier,
+                price_info,
+                received_at,
+                trace_context,
+            })
+            .is_err()
+        {
+            debug!(
+                identifier = %bs58::encode(price_identifier.to_bytes()).into_string(),
+                "Local store: could not track publish latency of update"
+            );
+        }
+    }
+
+    pub fn get_all_price_infos(&self) -> AllPriceInfo {
         self.prices.clone()
     }
 }
+
+/// Drop the updates of the backlog which a later update of the same price from
+/// the same publisher supersedes, returning the publisher and price of each
+fn shed(backlog: &mut Vec<Message>) -> Vec<(Publisher, PriceIdentifier)> {
+    let mut latest = HashSet::new();
+    let mut shed = vec![];
+    let mut kept = backlog
+        .drain(..)
+        .rev()
+        .filter(|message| match message {
+            Message::Update {
+                publisher,
+                price_identifier,
+                ..
+            } => {
+                let is_latest = latest.insert((*publisher, *price_identifier));
+                if !is_latest {
+                    shed.push((*publisher, *price_identifier));
+                }
+                is_latest
+            }
+            _ => true,
+        })
+        .collect::<Vec<_>>();
+    kept.reverse();
+    *backlog = kept;
+    shed
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            Config,
+            LoadSheddingConfig,
+            Message,
+            PriceBounds,
+            PriceInfo,
+            Store,
+        },
+        crate::agent::store::{
+            global::{
+                ProductAccountMetadata,
+                Snapshot,
+                SnapshotReader,
+            },
+            PriceIdentifier,
+        },
+        chrono::Utc,
+        pyth_sdk_solana::state::PriceStatus,
+        rand::Rng,
+        solana_sdk::pubkey::Pubkey,
+        std::time::{
+            Duration,
+            Instant,
+        },
+        tokio::sync::{
+            broadcast,
+            mpsc,
+            watch,
+        },
+    };
+
+    #[tokio::test]
+    async fn test_persist_and_restore() {
+        let path = std::env::temp_dir().join(format!(
+            "pyth-agent-local-store-{}.json",
+            rand::thread_rng().gen::<u64>()
+        ));
+        let config = Config {
+            persistence_path: Some(path.clone()),
+            persistence_max_age: Duration::from_secs(60),
+            ..Default::default()
+        };
+        let now = Utc::now().timestamp();
+
+        let fresh_identifier = PriceIdentifier::new([1; 32]);
+        let fresh_info = PriceInfo {
+            status:    PriceStatus::Trading,
+            price:     42,
+            conf:      7,
+            timestamp: now,
+        };
+        let old_identifier = PriceIdentifier::new([2; 32]);
+        let old_info = PriceInfo {
+            status:    PriceStatus::Trading,
+            price:     43,
+            conf:      8,
+            timestamp: now - 3600,
+        };
+
+        // Store two prices and persist them
+        let (_tx, rx) = mpsc::channel(1);
+        let (publish_latency_tx, _publish_latency_rx) = mpsc::channel(10);
+        let mut store = Store::new(
+            watch::channel(config.clone()).1,
+            rx,
+            publish_latency_tx.clone(),
+            broadcast::channel(1).0,
+            Default::default(),
+            None,
+            Default::default(),
+        )
+        .await;
+        store
+            .update(None, fresh_identifier, fresh_info.clone())
+            .unwrap();
+        store.update(None, old_identifier, old_info).unwrap();
+        store.persist().unwrap();
+
+        // A new store restores only the price within the max age
+        let (_tx, rx) = mpsc::channel(1);
+        let restored = Store::new(
+            watch::channel(config).1,
+            rx,
+            publish_latency_tx,
+            broadcast::channel(1).0,
+            Default::default(),
+            None,
+            Default::default(),
+        )
+        .await
+        .get_all_price_infos();
+        std::fs::remove_file(&path).unwrap();
+
+        let restored = restored.get(&None).unwrap();
+        assert_eq!(restored.len(), 1);
+        let restored_info = restored.get(&fresh_identifier).unwrap();
+        assert!(restored_info.cmp_no_timestamp(&fresh_info));
+        assert_eq!(restored_info.timestamp, fresh_info.timestamp);
+    }
+
+    #[tokio::test]
+    async fn test_reject_out_of_bounds_update() {
+        // The bounds of a symbol apply to its price accounts
+        let bounded_identifier = PriceIdentifier::new([3; 32]);
+        let mut snapshot = Snapshot::default();
+        snapshot.account_metadata.insert_product(
+            Pubkey::new_unique(),
+            ProductAccountMetadata {
+                attr_dict:      [("symbol".to_string(), "Crypto.BTC/USD".to_string())]
+                    .into_iter()
+                    .collect(),
+                price_accounts: vec![Pubkey::new_from_array(bounded_identifier.to_bytes())],
+            },
+        );
+        let config = Config {
+            default_price_bounds: PriceBounds {
+                min_price:      Some(0),
+                max_price:      None,
+                max_conf_ratio: Some(0.1),
+            },
+            price_bounds: [(
+                "Crypto.BTC/USD".to_string(),
+                PriceBounds {
+                    max_price: Some(100),
+                    ..Default::default()
+                },
+            )]
+            .into_iter()
+            .collect(),
+            ..Default::default()
+        };
+        let (_tx, rx) = mpsc::channel(1);
+        let (publish_latency_tx, _publish_latency_rx) = mpsc::channel(10);
+        let mut store = Store::new(
+            watch::channel(config).1,
+            rx,
+            publish_latency_tx,
+            broadcast::channel(1).0,
+            SnapshotReader::new(snapshot),
+            None,
+            Default::default(),
+        )
+        .await;
+
+        let price_info = |status, price, conf| PriceInfo {
+            status,
+            price,
+            conf,
+            timestamp: Utc::now().timestamp(),
+        };
+        let identifier = PriceIdentifier::new([4; 32]);
+
+        // Default bounds apply to prices without their own bounds
+        assert!(store
+            .update(None, identifier, price_info(PriceStatus::Trading, -1, 0))
+            .is_err());
+        assert!(store
+            .update(None, identifier, price_info(PriceStatus::Trading, 100, 11))
+            .is_err());
+        assert!(store
+            .update(None, identifier, price_info(PriceStatus::Trading, 0, 1))
+            .is_err());
+        assert!(store
+            .update(None, identifier, price_info(PriceStatus::Unknown, 0, 1))
+            .is_ok());
+        assert!(store
+            .update(None, identifier, price_info(PriceStatus::Trading, 100, 10))
+            .is_ok());
+
+        // Per-symbol bounds replace the default bounds
+        assert!(store
+            .update(
+                None,
+                bounded_identifier,
+                price_info(PriceStatus::Trading, 101, 0)
+            )
+            .is_err());
+        assert!(store
+            .update(
+                None,
+                bounded_identifier,
+                price_info(PriceStatus::Trading, -5, 50)
+            )
+            .is_ok());
+
+        // The rejections are kept for the alerts, by price
+        assert_eq!(store.rejections[&identifier].count, 3);
+        assert_eq!(store.rejections[&bounded_identifier].count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_drop_duplicate_updates_within_dedup_window() {
+        let config = Config {
+            dedup_window: Some(Duration::from_secs(3600)),
+            ..Default::default()
+        };
+        let (_tx, rx) = mpsc::channel(1);
+        let (publish_latency_tx, _publish_latency_rx) = mpsc::channel(10);
+        let mut store = Store::new(
+            watch::channel(config).1,
+            rx,
+            publish_latency_tx,
+            broadcast::channel(1).0,
+            Default::default(),
+            None,
+            Default::default(),
+        )
+        .await;
+
+        let price_info = |price| PriceInfo {
+            status: PriceStatus::Trading,
+            price,
+            conf: 1,
+            timestamp: Utc::now().timestamp(),
+        };
+        let identifier = PriceIdentifier::new([5; 32]);
+        let other_identifier = PriceIdentifier::new([6; 32]);
+
+        assert!(store.update(None, identifier, price_info(42)).unwrap());
+        // The same price state is dropped, whatever its timestamp
+        assert!(!store.update(None, identifier, price_info(42)).unwrap());
+        // A changed price, or another price, is stored
+        assert!(store.update(None, identifier, price_info(43)).unwrap());
+        assert!(store
+            .update(None, other_identifier, price_info(43))
+            .unwrap());
+        assert_eq!(store.get_all_price_infos()[&None][&identifier].price, 43);
+    }
+
+    #[tokio::test]
+    async fn test_superseded_updates_are_shed_from_the_backlog() {
+        let config = Config {
+            load_shedding: Some(LoadSheddingConfig {
+                backlog_threshold: 3,
+                max_backlog:       100,
+            }),
+            ..Default::default()
+        };
+        let (tx, rx) = mpsc::channel(100);
+        let (publish_latency_tx, _publish_latency_rx) = mpsc::channel(10);
+        let mut store = Store::new(
+            watch::channel(config).1,
+            rx,
+            publish_latency_tx,
+            broadcast::channel(1).0,
+            Default::default(),
+            None,
+            Default::default(),
+        )
+        .await;
+
+        let update = |identifier: u8, price| Message::Update {
+            publisher:        None,
+            price_identifier: PriceIdentifier::new([identifier; 32]),
+            price_info:       PriceInfo {
+                status: PriceStatus::Trading,
+                price,
+                conf: 1,
+                timestamp: Utc::now().timestamp(),
+            },
+            seq:              None,
+            received_at:      Instant::now(),
+            replicated:       false,
+            trace_context:    Default::default(),
+        };
+        let prices = |backlog: Vec<Message>| {
+            backlog
+                .into_iter()
+                .map(|message| match message {
+                    Message::Update {
+                        price_identifier,
+                        price_info,
+                        ..
+                    } => (price_identifier.to_bytes()[0], price_info.price),
+                    _ => unreachable!(),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // Below the threshold, nothing is shed
+        tx.send(update(1, 11)).await.unwrap();
+        assert_eq!(
+            prices(store.take_backlog(update(1, 10))),
+            vec![(1, 10), (1, 11)]
+        );
+
+        // Beyond it, only the latest update of each price is kept, in order
+        for (identifier, price) in [(2, 20), (1, 12), (2, 21)] {
+            tx.send(update(identifier, price)).await.unwrap();
+        }
+        assert_eq!(
+            prices(store.take_backlog(update(1, 11))),
+            vec![(1, 12), (2, 21)]
+        );
+        assert!(store.shedding);
+    }
+}
@@ -0,0 +1,170 @@
+// The Symbol Index resolves price accounts to the symbols of their products, and
+// symbols back to their price accounts. It is maintained by the Global Store from
+// the product account metadata, and published with every snapshot so that all
+// components name prices the same way.
+use {
+    super::{
+        global::ProductAccountMetadata,
+        PriceIdentifier,
+    },
+    solana_sdk::pubkey::Pubkey,
+    std::collections::HashMap,
+};
+
+/// Key of the product attribute holding the symbol
+pub const SYMBOL_ATTRIBUTE: &str = "symbol";
+
+#[derive(Debug, Clone, Default)]
+pub struct SymbolIndex {
+    /// Symbol of each product account which has one
+    symbols_by_product: HashMap<Pubkey, String>,
+    /// Product account of each price account
+    products_by_price:  HashMap<Pubkey, Pubkey>,
+    /// Price accounts of each product account
+    prices_by_product:  HashMap<Pubkey, Vec<Pubkey>>,
+    /// Product account of each symbol
+    products_by_symbol: HashMap<String, Pubkey>,
+}
+
+impl SymbolIndex {
+    /// Index the symbol and price accounts of the product, replacing those
+    /// previously indexed for it
+    pub fn update_product(&mut self, product_key: &Pubkey, metadata: &ProductAccountMetadata) {
+        self.remove_product(product_key);
+
+        if let Some(symbol) = metadata.attr_dict.get(SYMBOL_ATTRIBUTE) {
+            self.symbols_by_product.insert(*product_key, symbol.clone());
+            self.products_by_symbol.insert(symbol.clone(), *product_key);
+        }
+        for price_key in &metadata.price_accounts {
+            self.products_by_price.insert(*price_key, *product_key);
+        }
+        self.prices_by_product
+            .insert(*product_key, metadata.price_accounts.clone());
+    }
+
+    /// Stop indexing the product and its price accounts
+    pub fn remove_product(&mut self, product_key: &Pubkey) {
+        if let Some(symbol) = self.symbols_by_product.remove(product_key) {
+            if self.products_by_symbol.get(&symbol) == Some(product_key) {
+                self.products_by_symbol.remove(&symbol);
+            }
+        }
+        for price_key in self
+            .prices_by_product
+            .remove(product_key)
+            .unwrap_or_default()
+        {
+            if self.products_by_price.get(&price_key) == Some(product_key) {
+                self.products_by_price.remove(&price_key);
+            }
+        }
+    }
+
+    /// The symbol of the product of the price account, if known
+    pub fn symbol(&self, price_key: &Pubkey) -> Option<&str> {
+        self.products_by_price
+            .get(price_key)
+            .and_then(|product_key| self.product_symbol(product_key))
+    }
+
+    /// The symbol of the product of the price, if known
+    pub fn symbol_of_identifier(&self, price_identifier: &PriceIdentifier) -> Option<&str> {
+        self.symbol(&Pubkey::new_from_array(price_identifier.to_bytes()))
+    }
+
+    /// The symbol of the product account, if it has one
+    pub fn product_symbol(&self, product_key: &Pubkey) -> Option<&str> {
+        self.symbols_by_product.get(product_key).map(String::as_str)
+    }
+
+    /// The symbol of the price account, or the price account itself if the
+    /// symbol is not known
+    pub fn price_name(&self, price_key: &Pubkey) -> String {
+        self.symbol(price_key)
+            .map(str::to_string)
+            .unwrap_or_else(|| price_key.to_string())
+    }
+
+    /// The symbol of the product account, or a name made of the product
+    /// account if it has none
+    pub fn product_name(&self, product_key: &Pubkey) -> String {
+        self.product_symbol(product_key)
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("unnamed product {}", product_key))
+    }
+
+    /// The product account with the given symbol
+    pub fn product(&self, symbol: &str) -> Option<&Pubkey> {
+        self.products_by_symbol.get(symbol)
+    }
+
+    /// The price accounts of the product with the given symbol
+    pub fn price_accounts(&self, symbol: &str) -> &[Pubkey] {
+        self.products_by_symbol
+            .get(symbol)
+            .and_then(|product_key| self.prices_by_product.get(product_key))
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::SymbolIndex,
+        crate::agent::store::{
+            global::ProductAccountMetadata,
+            PriceIdentifier,
+        },
+        solana_sdk::pubkey::Pubkey,
+    };
+
+    fn product(symbol: Option<&str>, price_accounts: Vec<Pubkey>) -> ProductAccountMetadata {
+        ProductAccountMetadata {
+            attr_dict: symbol
+                .map(|symbol| ("symbol".to_string(), symbol.to_string()))
+                .into_iter()
+                .collect(),
+            price_accounts,
+        }
+    }
+
+    #[test]
+    fn test_prices_are_resolved_to_the_symbols_of_their_products() {
+        let product_key = Pubkey::new_unique();
+        let price_key = Pubkey::new_unique();
+        let mut index = SymbolIndex::default();
+        index.update_product(
+            &product_key,
+            &product(Some("Crypto.BTC/USD"), vec![price_key]),
+        );
+
+        assert_eq!(index.symbol(&price_key), Some("Crypto.BTC/USD"));
+        assert_eq!(
+            index.symbol_of_identifier(&PriceIdentifier::new(price_key.to_bytes())),
+            Some("Crypto.BTC/USD")
+        );
+        assert_eq!(index.price_accounts("Crypto.BTC/USD"), &[price_key]);
+        assert_eq!(index.product_name(&product_key), "Crypto.BTC/USD");
+
+        // Updating a product replaces its symbol and price accounts
+        let new_price_key = Pubkey::new_unique();
+        index.update_product(
+            &product_key,
+            &product(Some("Crypto.ETH/USD"), vec![new_price_key]),
+        );
+        assert_eq!(index.symbol(&price_key), None);
+        assert_eq!(index.price_name(&price_key), price_key.to_string());
+        assert!(index.price_accounts("Crypto.BTC/USD").is_empty());
+        assert_eq!(index.price_accounts("Crypto.ETH/USD"), &[new_price_key]);
+
+        // Products without a symbol fall back to their account
+        let unnamed_key = Pubkey::new_unique();
+        index.update_product(&unnamed_key, &product(None, vec![Pubkey::new_unique()]));
+        assert_eq!(
+            index.product_name(&unnamed_key),
+            format!("unnamed product {}", unnamed_key)
+        );
+    }
+}
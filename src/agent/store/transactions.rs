@@ -0,0 +1,148 @@
+// The Transactions Store keeps a record of the transactions recently sent by the
+// Exporters, along with their confirmation status as observed by the Transaction
+// Monitors. It is used to display publishing health on the dashboard.
+use {
+    crate::agent::error::{
+        self,
+        Error,
+    },
+    anyhow::{
+        Context as _,
+        Result,
+    },
+    pyth_sdk::UnixTimestamp,
+    serde::{
+        Deserialize,
+        Serialize,
+    },
+    solana_sdk::{
+        pubkey::Pubkey,
+        signature::Signature,
+    },
+    std::collections::VecDeque,
+    tokio::{
+        sync::{
+            mpsc,
+            oneshot,
+        },
+        task::JoinHandle,
+    },
+    tracing::Instrument,
+};
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Maximum number of recent transactions to keep. When this number is exceeded,
+    /// the oldest transactions are forgotten.
+    pub max_transactions: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            max_transactions: 100,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TransactionStatus {
+    /// Sent, but not confirmed yet
+    Pending,
+    Confirmed,
+    Failed(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct TransactionRecord {
+    /// Name of the network the transaction was sent to
+    pub network:               String,
+    pub signature:             Signature,
+    /// Publish key the transaction was signed with
+    pub publish_key:           Pubkey,
+    /// Price accounts updated by the transaction
+    pub price_accounts:        Vec<Pubkey>,
+    pub submit_time:           UnixTimestamp,
+    /// Priority fee offered for the transaction, if any
+    pub priority_fee_lamports: Option<u64>,
+    pub status:                TransactionStatus,
+}
+
+#[derive(Debug)]
+pub enum Message {
+    /// A transaction was sent by an Exporter
+    Sent(TransactionRecord),
+    /// The status of a sent transaction changed
+    StatusUpdate {
+        signature: Signature,
+        status:    TransactionStatus,
+    },
+    /// Look up the recent transactions, most recent first
+    LookupRecent {
+        result_tx: oneshot::Sender<Vec<TransactionRecord>>,
+    },
+}
+
+pub fn spawn_store(config: Config, rx: mpsc::Receiver<Message>) -> JoinHandle<()> {
+    tokio::spawn(
+        async move { Store::new(config, rx).run().await }
+            .instrument(info_span!("transactions_store")),
+    )
+}
+
+pub struct Store {
+    transactions: VecDeque<TransactionRecord>,
+    rx:           mpsc::Receiver<Message>,
+    config:       Config,
+}
+
+impl Store {
+    pub fn new(config: Config, rx: mpsc::Receiver<Message>) -> Self {
+        Store {
+            transactions: VecDeque::new(),
+            rx,
+            config,
+        }
+    }
+
+    pub async fn run(&mut self) {
+        while let Some(message) = self.rx.recv().await {
+            if let Err(err) = self.handle(message) {
+                error!(error = ?err, kind = %error::record("transactions_store", &err), "{:#}", err)
+            }
+        }
+    }
+
+    fn handle(&mut self, message: Message) -> Result<()> {
+        match message {
+            Message::Sent(record) => {
+                self.transactions.push_back(record);
+
+                // Forget the oldest transaction if necessary
+                if self.transactions.len() > self.config.max_transactions {
+                    self.transactions.pop_front();
+                }
+
+                Ok(())
+            }
+            Message::StatusUpdate { signature, status } => {
+                // The transaction may have been forgotten already, which is fine
+                if let Some(record) = self
+                    .transactions
+                    .iter_mut()
+                    .rev()
+                    .find(|record| record.signature == signature)
+                {
+                    record.status = status;
+                }
+
+                Ok(())
+            }
+            Message::LookupRecent { result_tx } => result_tx
+                .send(self.transactions.iter().rev().cloned().collect())
+                .map_err(|_| Error::ChannelClosed("requester"))
+                .context("failed to send LookupRecent result"),
+        }
+    }
+}
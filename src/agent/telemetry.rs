@@ -0,0 +1,174 @@
+//! Optional OpenTelemetry tracing of price updates through the agent pipeline.
+//! Each component starts a span as a child of the trace context received along
+//! with its channel messages, and passes the context of its own span on. When no
+//! OTLP endpoint is configured, the global tracer is a no-op.
+use {
+    anyhow::Result,
+    opentelemetry::{
+        global,
+        sdk::{
+            trace::{
+                self as sdktrace,
+                Sampler,
+            },
+            Resource,
+        },
+        trace::{
+            Link,
+            SpanBuilder,
+            Status,
+            TraceContextExt,
+            Tracer,
+        },
+        Context,
+        KeyValue,
+    },
+    opentelemetry_otlp::WithExportConfig,
+    serde::{
+        Deserialize,
+        Serialize,
+    },
+    std::time::SystemTime,
+};
+
+const TRACER_NAME: &str = "pyth-agent";
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// gRPC endpoint of the OTLP collector spans are exported to, e.g.
+    /// "http://localhost:4317". Tracing is disabled when not set.
+    pub otlp_endpoint: Option<String>,
+    /// Service name the spans are reported under
+    pub service_name:  String,
+    /// Fraction of the traces to sample, between 0 and 1
+    pub sample_ratio:  f64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            otlp_endpoint: None,
+            service_name:  "pyth-agent".to_string(),
+            sample_ratio:  1.0,
+        }
+    }
+}
+
+/// Install the OTLP exporter as the global tracer, if an endpoint is configured.
+/// Must be called from within the Tokio runtime.
+pub fn init(config: &Config) -> Result<()> {
+    let endpoint = match &config.otlp_endpoint {
+        Some(endpoint) => endpoint,
+        None => return Ok(()),
+    };
+
+    opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(endpoint),
+        )
+        .with_trace_config(
+            sdktrace::config()
+                .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+                    config.sample_ratio,
+                ))))
+                .with_resource(Resource::new(vec![KeyValue::new(
+                    "service.name",
+                    config.service_name.clone(),
+                )])),
+        )
+        .install_batch(opentelemetry::runtime::Tokio)?;
+
+    Ok(())
+}
+
+/// Flush the spans which have not been exported yet
+pub fn shutdown() {
+    global::shutdown_tracer_provider();
+}
+
+/// Start a span as a child of the given context, returning the context carrying it
+pub fn start_span(parent: &Context, name: &'static str, attributes: Vec<KeyValue>) -> Context {
+    start_span_with(
+        parent,
+        SpanBuilder::from_name(name).with_attributes(attributes),
+    )
+}
+
+/// Start a span linked to the spans of the given contexts, for work which
+/// combines several traces (e.g. a batch of price updates)
+pub fn start_linked_span<'a>(
+    name: &'static str,
+    attributes: Vec<KeyValue>,
+    linked: impl IntoIterator<Item = &'a Context>,
+) -> Context {
+    start_span_with(
+        &Context::new(),
+        SpanBuilder::from_name(name)
+            .with_attributes(attributes)
+            .with_links(links(linked)),
+    )
+}
+
+/// Record a span which started at the given time and ends now
+pub fn record_span_since(
+    parent: &Context,
+    name: &'static str,
+    start_time: SystemTime,
+    attributes: Vec<KeyValue>,
+) {
+    start_span_with(
+        parent,
+        SpanBuilder::from_name(name)
+            .with_start_time(start_time)
+            .with_attributes(attributes),
+    )
+    .span()
+    .end();
+}
+
+/// Record a span which started at the given time and ends now, linked to the
+/// span of another trace which observed its end
+pub fn record_linked_span_since(
+    parent: &Context,
+    name: &'static str,
+    start_time: SystemTime,
+    attributes: Vec<KeyValue>,
+    linked: &Context,
+) {
+    start_span_with(
+        parent,
+        SpanBuilder::from_name(name)
+            .with_start_time(start_time)
+            .with_attributes(attributes)
+            .with_links(links([linked])),
+    )
+    .span()
+    .end();
+}
+
+/// End the span carried by the context, marking it as failed if the result is an error
+pub fn end_span<T>(context: &Context, result: &Result<T>) {
+    let span = context.span();
+    if let Err(err) = result {
+        span.set_status(Status::error(format!("{:#}", err)));
+    }
+    span.end();
+}
+
+fn links<'a>(linked: impl IntoIterator<Item = &'a Context>) -> Vec<Link> {
+    linked
+        .into_iter()
+        .map(|context| context.span().span_context().clone())
+        .filter(|span_context| span_context.is_valid())
+        .map(|span_context| Link::new(span_context, vec![]))
+        .collect()
+}
+
+fn start_span_with(parent: &Context, builder: SpanBuilder) -> Context {
+    let span = global::tracer(TRACER_NAME).build_with_context(builder, parent);
+    parent.with_span(span)
+}
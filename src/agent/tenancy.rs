@@ -0,0 +1,275 @@
+// Tenancy lets a single agent serve several independent publishers, sharing the Oracle
+// polling and the Global Store of each network between them. Each tenant has its own
+// pythd API credential, publish keypair and symbol permissions. A connection to the
+// pythd API authenticates as a tenant with its token, either as a bearer token or as
+// the `token` query parameter. The updates it submits are attributed to the tenant's
+// publish key, and rejected if they name another publisher or a symbol the tenant may
+// not publish.
+//
+// The Local Store keeps the updates of each publish key apart, and the Exporters batch
+// and sign the updates of each publish key separately, so the tenants don't see or
+// delay each other's updates. The tenants' keypairs are added to the additional publish
+// keypairs of the key store of each network.
+//
+// Without tenants, connections are not authenticated and may publish on behalf of any
+// publish key of the key store, as before.
+use {
+    crate::agent::{
+        dump::redacted,
+        metrics::TENANT_METRICS,
+        solana::key_store,
+    },
+    anyhow::{
+        anyhow,
+        Result,
+    },
+    serde::{
+        Deserialize,
+        Serialize,
+    },
+    solana_sdk::{
+        pubkey::Pubkey,
+        signature::read_keypair_file,
+        signer::Signer as _,
+    },
+    std::{
+        collections::HashSet,
+        fmt,
+        path::{
+            Path,
+            PathBuf,
+        },
+        sync::Arc,
+    },
+    subtle::ConstantTimeEq,
+};
+
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Tenancy is disabled when empty
+    pub tenants: Vec<TenantConfig>,
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct TenantConfig {
+    /// Name of the tenant, used as the `tenant` label of its metrics
+    pub name:                 String,
+    /// Token the tenant's connections authenticate with. Can be read from a
+    /// file with `auth_token_file`.
+    pub auth_token:           String,
+    /// Path to the tenant's publish keypair, relative to the key store root
+    /// of each network unless absolute
+    pub publish_keypair_path: PathBuf,
+    /// Symbols the tenant may publish, e.g. ["Crypto.BTC/USD"]. All the
+    /// symbols its publish key is permissioned for when empty.
+    #[serde(default)]
+    pub symbols:              Vec<String>,
+}
+
+impl fmt::Debug for TenantConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TenantConfig")
+            .field("name", &self.name)
+            .field("auth_token", &redacted(Some(self.auth_token.as_str())))
+            .field("publish_keypair_path", &self.publish_keypair_path)
+            .field("symbols", &self.symbols)
+            .finish()
+    }
+}
+
+impl Config {
+    /// Add the publish keypairs of the tenants to the key store of a network
+    pub fn extend_key_store(&self, key_store: &mut key_store::Config) {
+        for tenant in &self.tenants {
+            if !key_store
+                .additional_publish_keypair_paths
+                .contains(&tenant.publish_keypair_path)
+            {
+                key_store
+                    .additional_publish_keypair_paths
+                    .push(tenant.publish_keypair_path.clone());
+            }
+        }
+    }
+}
+
+/// A tenant the connection authenticated as
+#[derive(Clone)]
+pub struct Tenant {
+    pub name:        String,
+    pub publish_key: Pubkey,
+    auth_token:      String,
+    /// Symbols the tenant may publish, all when empty
+    symbols:         HashSet<String>,
+}
+
+impl fmt::Debug for Tenant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tenant")
+            .field("name", &self.name)
+            .field("publish_key", &self.publish_key)
+            .field("auth_token", &redacted(Some(self.auth_token.as_str())))
+            .field("symbols", &self.symbols)
+            .finish()
+    }
+}
+
+impl Tenant {
+    pub fn may_publish(&self, symbol: &str) -> bool {
+        self.symbols.is_empty() || self.symbols.contains(symbol)
+    }
+
+    /// Count an update of the tenant, accepted unless `rejected` says why
+    pub fn record_update(&self, rejected: Option<&str>) {
+        TENANT_METRICS.record_update(&self.name, rejected);
+    }
+}
+
+/// The configured tenants, shared by the connections of the pythd API
+#[derive(Clone, Debug, Default)]
+pub struct Tenants {
+    tenants: Arc<Vec<Tenant>>,
+}
+
+impl Tenants {
+    /// Read the public keys of the tenants from their keypairs, resolved
+    /// against the given key store root
+    pub fn new(config: &Config, key_store_root: &Path) -> Result<Self> {
+        let mut tenants = Vec::with_capacity(config.tenants.len());
+        for tenant in &config.tenants {
+            // An empty token would let in any connection passing one
+            if tenant.auth_token.trim().is_empty() {
+                return Err(anyhow!("tenant {} has an empty auth token", tenant.name));
+            }
+            if tenants
+                .iter()
+                .any(|other: &Tenant| other.auth_token == tenant.auth_token)
+            {
+                return Err(anyhow!(
+                    "tenant {} has the same auth token as another tenant",
+                    tenant.name
+                ));
+            }
+            let keypair_path = key_store_root.join(&tenant.publish_keypair_path);
+            let keypair = read_keypair_file(&keypair_path).map_err(|e| {
+                anyhow!(
+                    "reading the publish keypair of tenant {} from {}: {}",
+                    tenant.name,
+                    keypair_path.display(),
+                    e
+                )
+            })?;
+            tenants.push(Tenant {
+                name:        tenant.name.clone(),
+                publish_key: keypair.pubkey(),
+                auth_token:  tenant.auth_token.clone(),
+                symbols:     tenant.symbols.iter().cloned().collect(),
+            });
+        }
+        Ok(Tenants {
+            tenants: Arc::new(tenants),
+        })
+    }
+
+    /// Whether connections must authenticate as a tenant
+    pub fn enabled(&self) -> bool {
+        !self.tenants.is_empty()
+    }
+
+    /// The tenant authenticating with the token, if any. The token is
+    /// compared in constant time.
+    pub fn authenticate(&self, token: &str) -> Option<Tenant> {
+        self.tenants
+            .iter()
+            .find(|tenant| bool::from(tenant.auth_token.as_bytes().ct_eq(token.as_bytes())))
+            .cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            Config,
+            TenantConfig,
+            Tenants,
+        },
+        solana_sdk::{
+            signature::{
+                write_keypair_file,
+                Keypair,
+            },
+            signer::Signer,
+        },
+        std::env,
+    };
+
+    #[test]
+    fn test_tenants_authenticate_with_their_token() {
+        let root = env::temp_dir();
+        let keypair = Keypair::new();
+        let keypair_path = format!("tenant_{}.json", rand::random::<u64>());
+        write_keypair_file(&keypair, root.join(&keypair_path)).unwrap();
+
+        let tenant = |name: &str, auth_token: &str, symbols: Vec<String>| TenantConfig {
+            name: name.to_string(),
+            auth_token: auth_token.to_string(),
+            publish_keypair_path: keypair_path.clone().into(),
+            symbols,
+        };
+        let tenants = Tenants::new(
+            &Config {
+                tenants: vec![
+                    tenant("first", "first-token", vec!["Crypto.BTC/USD".to_string()]),
+                    tenant("second", "second-token", vec![]),
+                ],
+            },
+            &root,
+        )
+        .unwrap();
+        assert!(tenants.enabled());
+
+        let first = tenants.authenticate("first-token").unwrap();
+        assert_eq!(first.name, "first");
+        assert_eq!(first.publish_key, keypair.pubkey());
+        assert!(first.may_publish("Crypto.BTC/USD"));
+        assert!(!first.may_publish("Crypto.ETH/USD"));
+
+        let second = tenants.authenticate("second-token").unwrap();
+        assert!(second.may_publish("Crypto.ETH/USD"));
+
+        assert!(tenants.authenticate("unknown-token").is_none());
+        assert!(!Tenants::default().enabled());
+
+        // The tokens are not logged along with the config or the tenant
+        assert!(!format!("{:?}", tenant("first", "first-token", vec![])).contains("first-token"));
+        assert!(!format!("{:?}", first).contains("first-token"));
+
+        // Tokens must tell the tenants apart
+        assert!(Tenants::new(
+            &Config {
+                tenants: vec![
+                    tenant("first", "token", vec![]),
+                    tenant("second", "token", vec![]),
+                ],
+            },
+            &root,
+        )
+        .is_err());
+
+        // And must be set, also when read from an empty file
+        for auth_token in ["", "\n"] {
+            assert!(Tenants::new(
+                &Config {
+                    tenants: vec![tenant("first", auth_token, vec![])],
+                },
+                &root,
+            )
+            .is_err());
+        }
+
+        std::fs::remove_file(root.join(&keypair_path)).unwrap();
+    }
+}
@@ -0,0 +1,461 @@
+// Support for end-to-end tests of the agent, enabled with the "test-support" feature.
+// A TestCluster starts a solana-test-validator with the Oracle program deployed and
+// the mapping, product and price accounts of a simulated cluster preloaded, funds a
+// publish keypair permissioned on all the prices, and runs the full agent against
+// it. Tests then submit prices over the Pythd API with a TestClient, and read them
+// back from the price accounts and from the agent. Only one TestCluster can run at
+// a time, as the validator and the agent bind fixed ports besides the picked ones,
+// and the agent registers its metrics globally.
+use {
+    crate::agent::{
+        config::{
+            Config as AgentConfig,
+            ConfigSource,
+        },
+        pythd::api::ProductAccount,
+        solana::{
+            key_store::KeyStore,
+            simulation::{
+                self,
+                SimulatedCluster,
+            },
+        },
+        Agent,
+    },
+    anyhow::{
+        anyhow,
+        bail,
+        Context as _,
+        Result,
+    },
+    pyth_sdk_solana::state::{
+        load_price_account,
+        PriceAccount,
+    },
+    serde::Serialize,
+    serde_json::{
+        json,
+        Value,
+    },
+    solana_client::nonblocking::rpc_client::RpcClient,
+    solana_sdk::{
+        commitment_config::CommitmentConfig,
+        native_token::LAMPORTS_PER_SOL,
+        pubkey::Pubkey,
+        signature::{
+            write_keypair_file,
+            Keypair,
+        },
+        signer::Signer,
+    },
+    std::{
+        collections::HashMap,
+        fs::{
+            self,
+            File,
+        },
+        path::{
+            Path,
+            PathBuf,
+        },
+        process::{
+            Child,
+            Command,
+        },
+        str::{
+            from_utf8,
+            FromStr,
+        },
+        time::Duration,
+    },
+    tokio::{
+        net::TcpStream,
+        task::JoinHandle,
+        time::{
+            self,
+            Instant,
+        },
+    },
+    tokio_util::compat::{
+        Compat,
+        TokioAsyncReadCompatExt,
+    },
+};
+
+/// Address the Oracle program is deployed at, as on localnet
+pub const ORACLE_PROGRAM_KEY: &str = "BujGr9ChcuaCJhxeFEvGvaCFTxSV1CUCSVHL1SVFpU4i";
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// Path of the solana-test-validator binary, read from $SOLANA_TEST_VALIDATOR
+    /// by default, e.g. to test against a Pythnet validator
+    pub validator_path:  PathBuf,
+    /// Oracle program binary deployed to the validator
+    pub program_path:    PathBuf,
+    /// Number of products created on the validator, each with a single price
+    pub products:        usize,
+    /// Time allowed for the validator to start and fund the publish keypair
+    pub startup_timeout: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            validator_path:  std::env::var_os("SOLANA_TEST_VALIDATOR")
+                .unwrap_or_else(|| "solana-test-validator".into())
+                .into(),
+            program_path:    Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("integration-tests/program-binaries/oracle.so"),
+            products:        3,
+            startup_timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+/// A local cluster with the Oracle program and its accounts, and the agent
+/// publishing to it. The validator and the agent are stopped, and their files
+/// removed, when dropped.
+pub struct TestCluster {
+    /// Temporary directory holding the ledger, the key store, the fixtures, the
+    /// validator logs and the agent config
+    path:            PathBuf,
+    validator:       Child,
+    rpc_client:      RpcClient,
+    rpc_url:         String,
+    wss_url:         String,
+    publish_keypair: Keypair,
+    /// Price accounts, in the order of their products in the mapping account
+    price_keys:      Vec<Pubkey>,
+    api_port:        u16,
+    agent_jh:        Option<JoinHandle<()>>,
+}
+
+impl TestCluster {
+    /// Start the validator with the Oracle program and its accounts, and fund
+    /// the publish keypair. The agent is started with `spawn_agent`.
+    pub async fn start(config: Config) -> Result<Self> {
+        let path = std::env::temp_dir().join(format!("pyth-agent-test-{}", Pubkey::new_unique()));
+        let key_store_path = path.join("keystore");
+        fs::create_dir_all(&key_store_path)
+            .with_context(|| format!("creating {}", key_store_path.display()))?;
+
+        // Write the key store read by the agent
+        let publish_keypair = Keypair::new();
+        let program_key = Pubkey::from_str(ORACLE_PROGRAM_KEY)?;
+        let mapping_key = Pubkey::new_unique();
+        write_keypair_file(
+            &publish_keypair,
+            key_store_path.join("publish_key_pair.json"),
+        )
+        .map_err(|err| anyhow!("writing publish keypair: {}", err))?;
+        fs::write(
+            key_store_path.join("program_key.json"),
+            program_key.to_string(),
+        )?;
+        fs::write(
+            key_store_path.join("mapping_key.json"),
+            mapping_key.to_string(),
+        )?;
+
+        // Generate the Oracle accounts, on which the publish keypair is permissioned
+        let simulated_cluster = SimulatedCluster::new(
+            &simulation::Config {
+                products: config.products,
+                ..Default::default()
+            },
+            &KeyStore {
+                publish_keypair: Some(Keypair::from_bytes(&publish_keypair.to_bytes())?),
+                program_key,
+                mapping_key,
+                accumulator_key: None,
+                price_store_key: None,
+                additional_publish_keypairs: HashMap::new(),
+            },
+        )?;
+        let fixtures = simulated_cluster.write_fixtures(&path.join("fixtures"))?;
+        let price_keys = (0..config.products)
+            .map(|index| {
+                Pubkey::create_with_seed(&mapping_key, &format!("price{}", index), &program_key)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Start the validator, with the Oracle program deployed and its accounts
+        // preloaded. The websocket endpoint listens on the port after the RPC one.
+        let rpc_port = unused_port()?;
+        let mut command = Command::new(&config.validator_path);
+        command
+            .arg("--ledger")
+            .arg(path.join("ledger"))
+            .args(["--rpc-port", &rpc_port.to_string()])
+            .args(["--faucet-port", &unused_port()?.to_string()])
+            .args(["--gossip-port", &unused_port()?.to_string()])
+            .args(["--bpf-program", ORACLE_PROGRAM_KEY])
+            .arg(&config.program_path)
+            .arg("--quiet")
+            .stdout(File::create(path.join("validator.log"))?)
+            .stderr(File::create(path.join("validator.err"))?);
+        for (key, fixture_path) in &fixtures {
+            command
+                .args(["--account", &key.to_string()])
+                .arg(fixture_path);
+        }
+        let validator = command
+            .spawn()
+            .with_context(|| format!("starting {}", config.validator_path.display()))?;
+        info!(path = %path.display(), rpc_port, "started solana-test-validator");
+
+        let rpc_url = format!("http://127.0.0.1:{}", rpc_port);
+        let wss_url = format!("ws://127.0.0.1:{}", rpc_port + 1);
+        let cluster = TestCluster {
+            path,
+            validator,
+            rpc_client: RpcClient::new_with_commitment(
+                rpc_url.clone(),
+                CommitmentConfig::confirmed(),
+            ),
+            rpc_url,
+            wss_url,
+            publish_keypair,
+            price_keys,
+            api_port: unused_port()?,
+            agent_jh: None,
+        };
+
+        let deadline = Instant::now() + config.startup_timeout;
+        cluster.wait_until_healthy(deadline).await?;
+        cluster.fund_publish_keypair(deadline).await?;
+        Ok(cluster)
+    }
+
+    pub fn rpc_client(&self) -> &RpcClient {
+        &self.rpc_client
+    }
+
+    pub fn publish_key(&self) -> Pubkey {
+        self.publish_keypair.pubkey()
+    }
+
+    pub fn price_keys(&self) -> &[Pubkey] {
+        &self.price_keys
+    }
+
+    /// Run the full agent against the validator, in a task of the current runtime
+    pub async fn spawn_agent(&mut self) -> Result<()> {
+        if self.agent_jh.is_some() {
+            bail!("the agent is already running");
+        }
+
+        let config_path = self.path.join("agent.toml");
+        fs::write(
+            &config_path,
+            format!(
+                r#"
+[pythd_api_server]
+listen_address = "127.0.0.1:{api_port}"
+
+[primary_network]
+rpc_url = "{rpc_url}"
+wss_url = "{wss_url}"
+key_store.root_path = "{key_store_path}"
+oracle.poll_interval_duration = "1s"
+exporter.transaction_monitor.poll_interval_duration = "1s"
+
+[metrics_server]
+bind_address = "127.0.0.1:{metrics_port}"
+
+[remote_keypair_loader]
+bind_address = "127.0.0.1:{keypair_loader_port}"
+"#,
+                api_port = self.api_port,
+                rpc_url = self.rpc_url,
+                wss_url = self.wss_url,
+                key_store_path = self.path.join("keystore").display(),
+                metrics_port = unused_port()?,
+                keypair_loader_port = unused_port()?,
+            ),
+        )?;
+
+        let config_source = ConfigSource {
+            path:      config_path,
+            overrides: vec![],
+        };
+        let config = AgentConfig::new(&config_source).context("parsing the agent config")?;
+        self.agent_jh = Some(tokio::spawn(async move {
+            Agent::new(config, config_source, None).start().await
+        }));
+        Ok(())
+    }
+
+    /// Connect a client to the Pythd API of the agent, once it is listening
+    pub async fn client(&self) -> Result<TestClient> {
+        TestClient::connect(self.api_port, Duration::from_secs(10)).await
+    }
+
+    pub async fn get_price_account(&self, key: &Pubkey) -> Result<PriceAccount> {
+        let data = self.rpc_client.get_account_data(key).await?;
+        Ok(*load_price_account(&data)?)
+    }
+
+    /// Wait until the component of the publish keypair in the given price
+    /// account holds the given price
+    pub async fn wait_for_price(
+        &self,
+        key: &Pubkey,
+        price: i64,
+        timeout: Duration,
+    ) -> Result<PriceAccount> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let price_account = self.get_price_account(key).await?;
+            let published = price_account
+                .comp
+                .iter()
+                .find(|component| component.publisher == self.publish_key())
+                .map(|component| component.latest.price);
+            if published == Some(price) {
+                return Ok(price_account);
+            }
+            if Instant::now() > deadline {
+                bail!(
+                    "price {} not published to {} in time, found {:?}",
+                    price,
+                    key,
+                    published
+                );
+            }
+            time::sleep(Duration::from_millis(500)).await;
+        }
+    }
+
+    async fn wait_until_healthy(&self, deadline: Instant) -> Result<()> {
+        while let Err(err) = self.rpc_client.get_health().await {
+            if Instant::now() > deadline {
+                return Err(err).context("solana-test-validator did not start in time");
+            }
+            time::sleep(Duration::from_millis(500)).await;
+        }
+        Ok(())
+    }
+
+    async fn fund_publish_keypair(&self, deadline: Instant) -> Result<()> {
+        let signature = self
+            .rpc_client
+            .request_airdrop(&self.publish_key(), 1000 * LAMPORTS_PER_SOL)
+            .await
+            .context("airdropping to the publish keypair")?;
+        while !self.rpc_client.confirm_transaction(&signature).await? {
+            if Instant::now() > deadline {
+                bail!("airdrop to the publish keypair not confirmed in time");
+            }
+            time::sleep(Duration::from_millis(500)).await;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for TestCluster {
+    fn drop(&mut self) {
+        if let Some(agent_jh) = &self.agent_jh {
+            agent_jh.abort();
+        }
+        if let Err(err) = self.validator.kill().and_then(|()| self.validator.wait()) {
+            warn!(error = ?err, "could not stop solana-test-validator");
+        }
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// A JRPC client of the Pythd API
+pub struct TestClient {
+    sender:   soketto::Sender<Compat<TcpStream>>,
+    receiver: soketto::Receiver<Compat<TcpStream>>,
+    next_id:  u64,
+}
+
+impl TestClient {
+    /// Connect to the API at the given port, retrying until the timeout as the
+    /// agent may still be starting
+    pub async fn connect(port: u16, timeout: Duration) -> Result<Self> {
+        let deadline = Instant::now() + timeout;
+        let socket = loop {
+            match TcpStream::connect(("127.0.0.1", port)).await {
+                Ok(socket) => break socket,
+                Err(err) if Instant::now() > deadline => {
+                    return Err(err).context("connecting to the pythd API");
+                }
+                Err(_) => time::sleep(Duration::from_millis(100)).await,
+            }
+        };
+
+        let mut client = soketto::handshake::Client::new(socket.compat(), "127.0.0.1", "/");
+        if !matches!(
+            client.handshake().await?,
+            soketto::handshake::ServerResponse::Accepted { .. }
+        ) {
+            bail!("websocket handshake rejected by the pythd API");
+        }
+        let (sender, receiver) = client.into_builder().finish();
+        Ok(TestClient {
+            sender,
+            receiver,
+            next_id: 0,
+        })
+    }
+
+    pub async fn update_price(
+        &mut self,
+        account: &Pubkey,
+        price: i64,
+        conf: u64,
+        status: &str,
+    ) -> Result<()> {
+        self.request(
+            "update_price",
+            json!({
+                "account": account.to_string(),
+                "price": price,
+                "conf": conf,
+                "status": status,
+            }),
+        )
+        .await?;
+        Ok(())
+    }
+
+    pub async fn get_all_products(&mut self) -> Result<Vec<ProductAccount>> {
+        let result = self.request("get_all_products", json!({})).await?;
+        Ok(serde_json::from_value(result)?)
+    }
+
+    /// Send a request, and return the result of its response. Messages other
+    /// than the response, such as notifications, are skipped.
+    pub async fn request(&mut self, method: &str, params: impl Serialize) -> Result<Value> {
+        self.next_id += 1;
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": self.next_id,
+            "method": method,
+            "params": params,
+        });
+        self.sender.send_text(request.to_string()).await?;
+        self.sender.flush().await?;
+
+        loop {
+            let mut data = vec![];
+            self.receiver.receive_data(&mut data).await?;
+            let mut response: Value = serde_json::from_str(from_utf8(&data)?)?;
+            if response["id"] != json!(self.next_id) {
+                continue;
+            }
+            if !response["error"].is_null() {
+                bail!("{} failed: {}", method, response["error"]);
+            }
+            return Ok(response["result"].take());
+        }
+    }
+}
+
+/// A port which is currently unused, and likely to remain so until bound
+fn unused_port() -> Result<u16> {
+    portpicker::pick_unused_port().ok_or_else(|| anyhow!("no unused port"))
+}
@@ -0,0 +1,355 @@
+// Publishers cannot tell from the response of `update_price` whether their update was
+// published, as the API hands it over to the Adapter without waiting on it. The API
+// assigns a sequence id to each update it accepts, returned in the response, and the
+// update is followed as the Local Store stores it, and the Exporter of each network
+// packs it into a transaction, submits it and sees it land. Publishers look its status
+// up with the `get_update_status` method.
+//
+// Only the latest update of a price is published, so an update superseded by a later
+// one before any Exporter packed it fails. The statuses of the most recent updates
+// are kept, up to the configured number.
+use {
+    crate::agent::store::{
+        local::Publisher,
+        PriceIdentifier,
+    },
+    parking_lot::Mutex,
+    pyth_sdk::UnixTimestamp,
+    serde::{
+        Deserialize,
+        Serialize,
+    },
+    solana_sdk::signature::Signature,
+    std::{
+        collections::{
+            BTreeMap,
+            HashMap,
+            VecDeque,
+        },
+        sync::Arc,
+    },
+};
+
+/// Sequence id of an update accepted by the API, starting from 1
+pub type Seq = u64;
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Number of the most recent updates whose status is kept
+    pub max_tracked_updates: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            max_tracked_updates: 100_000,
+        }
+    }
+}
+
+/// Status of an update, overall or on a single network
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Status {
+    /// Accepted by the API, and not packed by any Exporter yet
+    Accepted,
+    /// Packed into a transaction by the Exporter
+    Packed,
+    /// Sent in the transaction with the signature
+    Submitted { signature: String },
+    /// Landed in the transaction with the signature
+    Confirmed { signature: String },
+    /// Rejected, superseded or not landed, for the reason
+    Failed { reason: String },
+}
+
+impl Status {
+    /// How far the update got, the furthest of the networks being its overall status
+    fn progress(&self) -> u8 {
+        match self {
+            Status::Accepted => 0,
+            Status::Failed { .. } => 1,
+            Status::Packed => 2,
+            Status::Submitted { .. } => 3,
+            Status::Confirmed { .. } => 4,
+        }
+    }
+}
+
+/// Status of an update, as served by the API
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct UpdateStatus {
+    pub seq:       Seq,
+    pub account:   String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub publisher: Option<String>,
+    #[serde(flatten)]
+    pub status:    Status,
+    /// Statuses on the networks whose Exporter packed the update
+    pub networks:  BTreeMap<String, Status>,
+}
+
+struct TrackedUpdate {
+    account:   String,
+    publisher: Option<String>,
+    /// Timestamp the Local Store stored the update with
+    timestamp: Option<UnixTimestamp>,
+    /// Why the update failed before reaching the Exporters
+    failure:   Option<String>,
+    networks:  BTreeMap<String, Status>,
+}
+
+struct Updates {
+    /// Sequence id of the oldest tracked update
+    first_seq: Seq,
+    updates:   VecDeque<TrackedUpdate>,
+    /// Sequence id of the latest update stored for each price, per publisher
+    latest:    HashMap<(Publisher, PriceIdentifier), Seq>,
+}
+
+impl Updates {
+    fn get_mut(&mut self, seq: Seq) -> Option<&mut TrackedUpdate> {
+        let index = seq.checked_sub(self.first_seq)?;
+        self.updates.get_mut(index as usize)
+    }
+}
+
+/// Shared registry of the statuses of the recent updates, reported to by the
+/// API, the Adapter, the Local Store and the Exporters
+#[derive(Clone)]
+pub struct UpdateStatuses {
+    max_tracked_updates: usize,
+    updates:             Arc<Mutex<Updates>>,
+}
+
+impl Default for UpdateStatuses {
+    fn default() -> Self {
+        Self::new(&Config::default())
+    }
+}
+
+impl UpdateStatuses {
+    pub fn new(config: &Config) -> Self {
+        UpdateStatuses {
+            max_tracked_updates: config.max_tracked_updates,
+            updates:             Arc::new(Mutex::new(Updates {
+                first_seq: 1,
+                updates:   VecDeque::new(),
+                latest:    HashMap::new(),
+            })),
+        }
+    }
+
+    /// Assign the next sequence id to an update accepted by the API
+    pub fn accept(&self, account: &str, publisher: Option<&str>) -> Seq {
+        let mut updates = self.updates.lock();
+        let seq = updates.first_seq + updates.updates.len() as Seq;
+        updates.updates.push_back(TrackedUpdate {
+            account:   account.to_string(),
+            publisher: publisher.map(str::to_string),
+            timestamp: None,
+            failure:   None,
+            networks:  BTreeMap::new(),
+        });
+        if updates.updates.len() > self.max_tracked_updates {
+            updates.updates.pop_front();
+            updates.first_seq += 1;
+        }
+        seq
+    }
+
+    /// Record that the update failed before reaching the Exporters
+    pub fn reject(&self, seq: Seq, reason: impl Into<String>) {
+        if let Some(update) = self.updates.lock().get_mut(seq) {
+            update.failure = Some(reason.into());
+        }
+    }
+
+    /// Record that the Local Store stored the update as the latest of its
+    /// price, failing the previous update of the price if no Exporter packed it
+    pub fn stored(
+        &self,
+        seq: Seq,
+        publisher: Publisher,
+        price_identifier: PriceIdentifier,
+        timestamp: UnixTimestamp,
+    ) {
+        let mut updates = self.updates.lock();
+        if let Some(update) = updates.get_mut(seq) {
+            update.timestamp = Some(timestamp);
+        }
+        let previous = updates.latest.insert((publisher, price_identifier), seq);
+        if let Some(previous) = previous.and_then(|previous| updates.get_mut(previous)) {
+            if previous.networks.is_empty() && previous.failure.is_none() {
+                previous.failure = Some(format!("superseded by update {}", seq));
+            }
+        }
+    }
+
+    /// Record that the Exporter of the network packed the prices of the
+    /// publisher, updated at the given timestamps, into a transaction,
+    /// returning the sequence ids of the updates it publishes
+    pub fn packed(
+        &self,
+        network: &str,
+        publisher: Publisher,
+        prices: &[(PriceIdentifier, UnixTimestamp)],
+    ) -> Vec<Seq> {
+        let mut updates = self.updates.lock();
+        let mut seqs = vec![];
+        for (price_identifier, timestamp) in prices {
+            let seq = match updates.latest.get(&(publisher, *price_identifier)) {
+                Some(seq) => *seq,
+                None => continue,
+            };
+            let update = match updates.get_mut(seq) {
+                Some(update) => update,
+                None => continue,
+            };
+            let published = update.failure.is_none()
+                && update
+                    .timestamp
+                    .map_or(false, |stored| stored <= *timestamp);
+            let confirmed = matches!(update.networks.get(network), Some(Status::Confirmed { .. }));
+            if published && !confirmed {
+                update.networks.insert(network.to_string(), Status::Packed);
+                seqs.push(seq);
+            }
+        }
+        seqs
+    }
+
+    /// Record that the Exporter of the network sent the updates in the
+    /// transaction with the signature
+    pub fn submitted(&self, network: &str, seqs: &[Seq], signature: &Signature) {
+        self.set(
+            network,
+            seqs,
+            Status::Submitted {
+                signature: signature.to_string(),
+            },
+        );
+    }
+
+    /// Record that the transaction with the signature landed the updates on
+    /// the network
+    pub fn confirmed(&self, network: &str, seqs: &[Seq], signature: &Signature) {
+        self.set(
+            network,
+            seqs,
+            Status::Confirmed {
+                signature: signature.to_string(),
+            },
+        );
+    }
+
+    /// Record that the updates were not published on the network
+    pub fn failed(&self, network: &str, seqs: &[Seq], reason: impl Into<String>) {
+        self.set(
+            network,
+            seqs,
+            Status::Failed {
+                reason: reason.into(),
+            },
+        );
+    }
+
+    fn set(&self, network: &str, seqs: &[Seq], status: Status) {
+        let mut updates = self.updates.lock();
+        for seq in seqs {
+            if let Some(update) = updates.get_mut(*seq) {
+                update.networks.insert(network.to_string(), status.clone());
+            }
+        }
+    }
+
+    /// The status of the update, unless it is unknown or no longer tracked
+    pub fn get(&self, seq: Seq) -> Option<UpdateStatus> {
+        let mut updates = self.updates.lock();
+        let update = updates.get_mut(seq)?;
+        let status = match &update.failure {
+            Some(reason) => Status::Failed {
+                reason: reason.clone(),
+            },
+            None => update
+                .networks
+                .values()
+                .max_by_key(|status| status.progress())
+                .cloned()
+                .unwrap_or(Status::Accepted),
+        };
+        Some(UpdateStatus {
+            seq,
+            account: update.account.clone(),
+            publisher: update.publisher.clone(),
+            status,
+            networks: update.networks.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            Config,
+            Status,
+            UpdateStatuses,
+        },
+        crate::agent::store::PriceIdentifier,
+        solana_sdk::signature::Signature,
+    };
+
+    #[test]
+    fn test_update_is_followed_until_it_lands() {
+        let statuses = UpdateStatuses::new(&Config {
+            max_tracked_updates: 3,
+        });
+        let price_identifier = PriceIdentifier::new([1; 32]);
+
+        // The first update is superseded before being packed
+        let superseded = statuses.accept("price", None);
+        statuses.stored(superseded, None, price_identifier, 100);
+        let published = statuses.accept("price", None);
+        statuses.stored(published, None, price_identifier, 101);
+        assert_eq!((superseded, published), (1, 2));
+        assert_eq!(
+            statuses.get(superseded).unwrap().status,
+            Status::Failed {
+                reason: "superseded by update 2".to_string(),
+            }
+        );
+
+        // The second lands on the primary network, but not on the secondary one
+        let seqs = statuses.packed("primary", None, &[(price_identifier, 101)]);
+        assert_eq!(seqs, vec![published]);
+        assert_eq!(
+            statuses.packed("secondary", None, &[(price_identifier, 101)]),
+            seqs
+        );
+        let signature = Signature::new_unique();
+        statuses.submitted("primary", &seqs, &signature);
+        statuses.confirmed("primary", &seqs, &signature);
+        statuses.failed("secondary", &seqs, "did not land");
+        let status = statuses.get(published).unwrap();
+        assert_eq!(
+            status.status,
+            Status::Confirmed {
+                signature: signature.to_string(),
+            }
+        );
+        assert_eq!(
+            status.networks["secondary"],
+            Status::Failed {
+                reason: "did not land".to_string(),
+            }
+        );
+
+        // The oldest updates are forgotten
+        statuses.accept("price", None);
+        statuses.accept("price", None);
+        assert!(statuses.get(superseded).is_none());
+        assert!(statuses.get(published).is_some());
+    }
+}
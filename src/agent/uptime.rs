@@ -0,0 +1,368 @@
+// The Uptime Tracker accounts for the publish uptime of our components over long
+// periods, for SLA reporting. Every slot the aggregate of a price advances by is a
+// slot our components were expected to publish in, and a component which published
+// since the previous aggregate observed by the Global Store is counted as having
+// published in one of them. The counts are kept in fixed-duration buckets, from which
+// the uptime over any window up to the retention is served as JSON by the metrics
+// server, e.g. at /api/uptime?window=7d.
+//
+// A component which published several times between two observations of its price is
+// only counted once, so the uptime is most accurate when the Oracle observes the price
+// accounts through its subscriber rather than by polling.
+use {
+    crate::agent::{
+        error::Error,
+        solana::oracle::PriceEntry,
+        store::global,
+    },
+    anyhow::{
+        Context as _,
+        Result,
+    },
+    serde::{
+        Deserialize,
+        Serialize,
+    },
+    solana_sdk::pubkey::Pubkey,
+    std::{
+        collections::{
+            HashMap,
+            HashSet,
+            VecDeque,
+        },
+        str::FromStr,
+        time::Duration,
+    },
+    tokio::{
+        sync::{
+            broadcast,
+            mpsc,
+            oneshot,
+        },
+        task::JoinHandle,
+        time::{
+            self,
+            Instant,
+            Interval,
+        },
+    },
+    tracing::Instrument,
+};
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Publish keys whose uptime is accounted for. The tracker is disabled
+    /// when empty.
+    pub publisher_keys:  Vec<String>,
+    /// Granularity of the windows the uptime is reported over
+    #[serde(with = "humantime_serde")]
+    pub bucket_duration: Duration,
+    /// How long the counts are kept for, which is the longest window the
+    /// uptime can be reported over
+    #[serde(with = "humantime_serde")]
+    pub retention:       Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            publisher_keys:  vec![],
+            bucket_duration: Duration::from_secs(60 * 60),
+            retention:       Duration::from_secs(31 * 24 * 60 * 60),
+        }
+    }
+}
+
+/// Window the uptime is reported over when the query sets none
+pub const DEFAULT_WINDOW: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Query of the uptime endpoint, e.g. ?window=7d
+#[derive(Debug, Default, Deserialize)]
+pub struct UptimeQuery {
+    #[serde(default, with = "humantime_serde")]
+    pub window: Option<Duration>,
+}
+
+#[derive(Debug)]
+pub enum Message {
+    /// Look up the uptime of all our components over the window ending now
+    LookupUptime {
+        window:    Duration,
+        result_tx: oneshot::Sender<Vec<SymbolUptime>>,
+    },
+}
+
+/// The uptime of one of our publishers for a price over a window
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct SymbolUptime {
+    /// The price account itself if its symbol is not known
+    pub symbol:          String,
+    pub price_account:   String,
+    pub publisher:       String,
+    /// Slots the aggregate advanced by during the window
+    pub expected_slots:  u64,
+    /// Slots the component was counted as having published in
+    pub published_slots: u64,
+    /// Percentage of the expected slots the component published in, not set
+    /// when no slot was expected
+    pub uptime_percent:  Option<f64>,
+}
+
+pub fn spawn_tracker(
+    config: Config,
+    rx: mpsc::Receiver<Message>,
+    global_store_events_rx: broadcast::Receiver<global::Event>,
+    global_store_reader: global::SnapshotReader,
+) -> JoinHandle<()> {
+    tokio::spawn(
+        async move {
+            Tracker::new(config, rx, global_store_events_rx, global_store_reader)
+                .run()
+                .await
+        }
+        .instrument(info_span!("uptime_tracker")),
+    )
+}
+
+/// The slots counted for a component during a bucket
+#[derive(Clone, Debug)]
+struct Bucket {
+    started_at: Instant,
+    expected:   u64,
+    published:  u64,
+}
+
+pub struct Tracker {
+    /// Buckets of each of our components, by price account and publisher, oldest first
+    buckets:                HashMap<(Pubkey, Pubkey), VecDeque<Bucket>>,
+    /// Slot of the last aggregate observed for each price account
+    last_aggregate_slots:   HashMap<Pubkey, u64>,
+    /// Publish keys parsed from the config
+    publisher_keys:         HashSet<Pubkey>,
+    /// Start of the current bucket
+    bucket_started_at:      Instant,
+    rx:                     mpsc::Receiver<Message>,
+    global_store_events_rx: broadcast::Receiver<global::Event>,
+    /// Used to look up the symbols of the prices
+    global_store_reader:    global::SnapshotReader,
+    bucket_interval:        Interval,
+    config:                 Config,
+}
+
+impl Tracker {
+    pub fn new(
+        config: Config,
+        rx: mpsc::Receiver<Message>,
+        global_store_events_rx: broadcast::Receiver<global::Event>,
+        global_store_reader: global::SnapshotReader,
+    ) -> Self {
+        let publisher_keys = config
+            .publisher_keys
+            .iter()
+            .filter_map(|key| match Pubkey::from_str(key) {
+                Ok(key) => Some(key),
+                Err(err) => {
+                    error!(%key, error = %err, "Uptime: ignoring invalid publisher key");
+                    None
+                }
+            })
+            .collect();
+
+        Tracker {
+            buckets: HashMap::new(),
+            last_aggregate_slots: HashMap::new(),
+            publisher_keys,
+            bucket_started_at: Instant::now(),
+            rx,
+            global_store_events_rx,
+            global_store_reader,
+            bucket_interval: time::interval(config.bucket_duration),
+            config,
+        }
+    }
+
+    pub async fn run(&mut self) {
+        loop {
+            tokio::select! {
+                message = self.rx.recv() => match message {
+                    Some(message) => {
+                        if let Err(err) = self.handle(message) {
+                            error!(error = ?err, "{:#}", err)
+                        }
+                    }
+                    None => break,
+                },
+                event = self.global_store_events_rx.recv() => match event {
+                    Ok(global::Event::PriceUpdated { account_key, account, .. }) => {
+                        self.observe(account_key, &account)
+                    }
+                    Ok(_) => {}
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!(skipped, "Uptime: missed global store events");
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                _ = self.bucket_interval.tick() => self.start_bucket(),
+            }
+        }
+    }
+
+    fn handle(&mut self, message: Message) -> Result<()> {
+        match message {
+            Message::LookupUptime { window, result_tx } => result_tx
+                .send(self.report(window))
+                .map_err(|_| Error::ChannelClosed("requester"))
+                .context("failed to send LookupUptime result"),
+        }
+    }
+
+    /// Count the slots the aggregate of the price account advanced by, and
+    /// those our components published in, if its aggregate is new
+    fn observe(&mut self, account_key: Pubkey, account: &PriceEntry) {
+        let slot = account.agg.pub_slot;
+        let last_slot = match self.last_aggregate_slots.insert(account_key, slot) {
+            Some(last_slot) if last_slot < slot => last_slot,
+            // The first aggregate observed only sets where the counts start from
+            _ => return,
+        };
+
+        for component in account
+            .comp
+            .iter()
+            .filter(|component| self.publisher_keys.contains(&component.publisher))
+        {
+            let buckets = self
+                .buckets
+                .entry((account_key, component.publisher))
+                .or_default();
+            if buckets.back().map(|bucket| bucket.started_at) != Some(self.bucket_started_at) {
+                buckets.push_back(Bucket {
+                    started_at: self.bucket_started_at,
+                    expected:   0,
+                    published:  0,
+                });
+            }
+            let bucket = buckets.back_mut().expect("a bucket was just pushed");
+
+            bucket.expected += slot - last_slot;
+            if component.latest.pub_slot > last_slot {
+                bucket.published += 1;
+            }
+        }
+    }
+
+    /// Start a new bucket, forgetting those older than the retention
+    fn start_bucket(&mut self) {
+        self.bucket_started_at = Instant::now();
+
+        let now = self.bucket_started_at;
+        let retention = self.config.retention;
+        self.buckets.retain(|_, buckets| {
+            buckets.retain(|bucket| now.duration_since(bucket.started_at) < retention);
+            !buckets.is_empty()
+        });
+    }
+
+    /// The uptime of all our components over the buckets overlapping the
+    /// window ending now, by symbol
+    fn report(&self, window: Duration) -> Vec<SymbolUptime> {
+        let snapshot = self.global_store_reader.load();
+        let now = Instant::now();
+        let mut report = self
+            .buckets
+            .iter()
+            .map(|((price_key, publisher), buckets)| {
+                let (expected_slots, published_slots) = buckets
+                    .iter()
+                    .filter(|bucket| {
+                        now.duration_since(bucket.started_at) < window + self.config.bucket_duration
+                    })
+                    .fold((0, 0), |(expected, published), bucket| {
+                        (expected + bucket.expected, published + bucket.published)
+                    });
+                SymbolUptime {
+                    symbol: snapshot.account_metadata.symbol_index.price_name(price_key),
+                    price_account: price_key.to_string(),
+                    publisher: publisher.to_string(),
+                    expected_slots,
+                    published_slots,
+                    uptime_percent: (expected_slots > 0)
+                        .then(|| published_slots as f64 * 100.0 / expected_slots as f64),
+                }
+            })
+            .collect::<Vec<_>>();
+        report.sort_by(|a, b| {
+            (&a.symbol, &a.price_account, &a.publisher).cmp(&(
+                &b.symbol,
+                &b.price_account,
+                &b.publisher,
+            ))
+        });
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            Config,
+            Tracker,
+            DEFAULT_WINDOW,
+        },
+        crate::agent::{
+            solana::oracle::PriceEntry,
+            store::global,
+        },
+        solana_sdk::pubkey::Pubkey,
+        tokio::sync::{
+            broadcast,
+            mpsc,
+        },
+    };
+
+    #[tokio::test]
+    async fn test_uptime_is_the_fraction_of_expected_slots_published_in() {
+        let publisher = Pubkey::new_unique();
+        let (_tx, rx) = mpsc::channel(1);
+        let (_events_tx, events_rx) = broadcast::channel(1);
+        let mut tracker = Tracker::new(
+            Config {
+                publisher_keys: vec![publisher.to_string()],
+                ..Default::default()
+            },
+            rx,
+            events_rx,
+            global::SnapshotReader::default(),
+        );
+
+        let account_key = Pubkey::new_unique();
+        let aggregate = |slot, published_slot| {
+            let mut account = PriceEntry::default();
+            account.agg.pub_slot = slot;
+            account.comp[0].publisher = publisher;
+            account.comp[0].latest.pub_slot = published_slot;
+            // Components of other publishers are not counted
+            account.comp[1].publisher = Pubkey::new_unique();
+            account
+        };
+
+        // The first aggregate only sets where the counts start from
+        tracker.observe(account_key, &aggregate(10, 9));
+        tracker.observe(account_key, &aggregate(11, 10));
+        tracker.observe(account_key, &aggregate(12, 11));
+        // Repeated observations of the same aggregate are not counted
+        tracker.observe(account_key, &aggregate(12, 11));
+        // The component did not publish since the previous aggregate
+        tracker.observe(account_key, &aggregate(14, 11));
+
+        let report = tracker.report(DEFAULT_WINDOW);
+        assert_eq!(report.len(), 1);
+        assert_eq!(report[0].symbol, account_key.to_string());
+        assert_eq!(report[0].publisher, publisher.to_string());
+        assert_eq!(report[0].expected_slots, 4);
+        assert_eq!(report[0].published_slots, 2);
+        assert_eq!(report[0].uptime_percent, Some(50.0));
+    }
+}
@@ -4,23 +4,37 @@ use {
         Context,
         Result,
     },
-    clap::Parser,
+    clap::{
+        Parser,
+        Subcommand,
+    },
     pyth_agent::agent::{
-        config::Config,
+        config::{
+            self,
+            Config,
+            ConfigSource,
+        },
+        config_check,
+        dump,
+        logging,
+        rpc_probe,
+        runtime::Runtimes,
+        snapshot_diff::{
+            self,
+            SnapshotDiff,
+        },
         Agent,
     },
-    slog::{
-        debug,
-        error,
-        o,
-        Drain,
-        Logger,
-    },
-    slog_async::Async,
-    slog_envlogger::LogBuilder,
+    solana_sdk::pubkey::Pubkey,
     std::{
-        env,
+        net::Ipv4Addr,
         path::PathBuf,
+        time::Duration,
+    },
+    tracing::{
+        debug,
+        error,
+        warn,
     },
 };
 
@@ -31,50 +45,297 @@ struct Arguments {
     #[clap(short, long, default_value = "config/config.toml")]
     /// Path to configuration file
     config: PathBuf,
+
+    #[clap(
+        long = "set",
+        value_name = "KEY=VALUE",
+        value_parser = config::parse_override,
+        global = true
+    )]
+    /// Override a config key, e.g. --set primary_network.oracle.poll_interval_duration=5s.
+    /// Can be repeated.
+    set: Vec<(String, String)>,
+
+    #[clap(long)]
+    /// Print every config key with its default value as JSON, and exit
+    dump_config_schema: bool,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Inspect the configuration file
+    #[clap(subcommand)]
+    Config(ConfigCommand),
+    /// Fetch a snapshot of the state of the running agent from its Admin API,
+    /// and print it as JSON for support tickets
+    Dump {
+        #[clap(long)]
+        /// URL of the Admin API, e.g. http://127.0.0.1:9002. Defaults to the
+        /// configured admin_api.bind_address.
+        admin_url: Option<String>,
+        #[clap(long)]
+        /// Write the dump to this file rather than to stdout
+        output:    Option<PathBuf>,
+    },
+    /// Measure the latency, slot lag and supported features of an RPC and WSS
+    /// endpoint pair, and print a scorecard. The config file is not read.
+    Probe {
+        #[clap(long)]
+        /// URL of the RPC endpoint
+        rpc:     String,
+        #[clap(long)]
+        /// URL of the websocket endpoint
+        wss:     String,
+        #[clap(long, default_value = "SysvarC1ock11111111111111111111111111111111")]
+        /// Account read to measure the latency
+        account: Pubkey,
+        #[clap(long, default_value_t = 10)]
+        /// Number of requests the latency is measured over
+        samples: usize,
+        #[clap(long, default_value = "5s", value_parser = humantime::parse_duration)]
+        /// Timeout of every request, and of the websocket subscription
+        timeout: Duration,
+        #[clap(long)]
+        /// Print the scorecard as JSON
+        json:    bool,
+    },
+    /// Fetch the snapshots of the Global Store of two agents from their
+    /// metrics servers, and print their differences. The config file is not read.
+    DiffSnapshots {
+        /// URL of the metrics server of the first agent, e.g. http://10.0.0.1:8888
+        left:           String,
+        /// URL of the metrics server of the second agent
+        right:          String,
+        #[clap(long)]
+        /// Bearer token of the metrics servers, if they require one
+        token:          Option<String>,
+        #[clap(long, default_value_t = 0)]
+        /// Number of slots the aggregate of a symbol may differ by between the
+        /// agents, in which case its other fields are not compared
+        slot_tolerance: u64,
+        #[clap(long)]
+        /// Print the diff as JSON
+        json:           bool,
+    },
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
+#[derive(Subcommand, Debug)]
+enum ConfigCommand {
+    /// Validate the configuration and print a report, without starting the agent
+    Check {
+        #[clap(long)]
+        /// Also probe the RPC and WSS endpoints and the mapping account of each network
+        probe: bool,
+        #[clap(long)]
+        /// Print the report as JSON
+        json:  bool,
+    },
+}
+
+fn main() -> Result<()> {
     let args = Arguments::parse();
 
+    if args.dump_config_schema {
+        println!("{}", serde_json::to_string_pretty(&Config::schema())?);
+        return Ok(());
+    }
+
+    if let Some(Command::Probe {
+        rpc,
+        wss,
+        account,
+        samples,
+        timeout,
+        json,
+    }) = args.command
+    {
+        let target = rpc_probe::Target {
+            rpc_url: rpc,
+            wss_url: wss,
+            account,
+            samples,
+            timeout,
+        };
+        return tokio::runtime::Runtime::new()?.block_on(probe_rpc(&target, json));
+    }
+
+    if let Some(Command::DiffSnapshots {
+        left,
+        right,
+        token,
+        slot_tolerance,
+        json,
+    }) = args.command
+    {
+        return tokio::runtime::Runtime::new()?.block_on(diff_snapshots(
+            &left,
+            &right,
+            token.as_deref(),
+            slot_tolerance,
+            json,
+        ));
+    }
+
     if !args.config.as_path().exists() {
         return Err(anyhow!("No config found under {:?}", args.config.to_str()));
     }
 
-    println!("Loading config from {:?}", args.config.display());
+    let config_source = ConfigSource {
+        path:      args.config,
+        overrides: args.set,
+    };
 
-    // Parse config early for logging channel capacity
-    let config = Config::new(args.config).context("Could not parse config")?;
+    if let Some(Command::Config(ConfigCommand::Check { probe, json })) = args.command {
+        return tokio::runtime::Runtime::new()?.block_on(check_config(&config_source, probe, json));
+    }
 
-    // A plain slog drain that sits inside an async drain instance
-    let inner_drain = LogBuilder::new(
-        slog_term::FullFormat::new(slog_term::TermDecorator::new().stdout().build())
-            .build()
-            .fuse(), // Yell loud on logger internal errors
-    )
-    .parse(&env::var("RUST_LOG").unwrap_or("info".to_string()))
-    .build();
+    if let Some(Command::Dump { admin_url, output }) = args.command {
+        return tokio::runtime::Runtime::new()?.block_on(dump_agent(
+            &config_source,
+            admin_url,
+            output,
+        ));
+    }
 
-    // The top level async drain
-    let async_drain = Async::new(inner_drain)
-        .chan_size(config.channel_capacities.logger_buffer)
-        .build()
-        .fuse();
-    let logger = slog::Logger::root(async_drain, o!());
+    // Parse config early for logging settings
+    let config = Config::new(&config_source).context("Could not parse config")?;
+
+    // Stdout is kept for the pythd API when it is served over stdio
+    let stdio = config.pythd_api_server.stdio;
+    if stdio {
+        eprintln!("Loaded config from {:?}", config_source.path.display());
+    } else {
+        println!("Loaded config from {:?}", config_source.path.display());
+    }
+
+    // The guard flushes the buffered log lines when main returns
+    let (log_level, _log_guard) = logging::init(
+        config.log_level()?,
+        config.log_format,
+        config.channel_capacities.logger_buffer,
+        stdio,
+    );
+
+    // Logged once the subscriber is installed
+    for key in config.deprecated_keys() {
+        warn!(
+            key,
+            "config key is deprecated and ignored, remove it from the config"
+        );
+    }
 
     let cwd = std::env::current_dir()?;
 
-    debug!(&logger, "Current working directory"; "cwd" => cwd.display());
+    debug!(cwd = %cwd.display(), "Current working directory");
+
+    // The runtimes are built from the config, so they can't be set up by #[tokio::main]
+    let runtimes = Runtimes::new(&config.runtime).context("Could not build the runtimes")?;
 
-    if let Err(err) = start(config, logger.clone()).await {
-        error!(logger, "{:#}", err; "error" => format!("{:?}", err));
+    if let Err(err) = runtimes.block_on(start(config, config_source, log_level, &runtimes)) {
+        error!(error = ?err, "{:#}", err);
         return Err(err);
     }
 
     Ok(())
 }
 
-async fn start(config: Config, logger: Logger) -> Result<()> {
-    Agent::new(config).start(logger).await;
+async fn start(
+    config: Config,
+    config_source: ConfigSource,
+    log_level: Option<logging::LogLevel>,
+    runtimes: &Runtimes,
+) -> Result<()> {
+    let mut agent = Agent::new(config, config_source, log_level);
+    if let Some(api_runtime) = runtimes.api_handle() {
+        agent = agent.with_api_runtime(api_runtime);
+    }
+    agent.start().await;
+    Ok(())
+}
+
+async fn check_config(config_source: &ConfigSource, probe: bool, json: bool) -> Result<()> {
+    // Problems are reported rather than logged, so no subscriber is installed
+    let report = config_check::check_config(config_source, probe).await;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print!("{}", report);
+    }
+
+    if report.has_errors() {
+        return Err(anyhow!("config check failed"));
+    }
+    Ok(())
+}
+
+async fn dump_agent(
+    config_source: &ConfigSource,
+    admin_url: Option<String>,
+    output: Option<PathBuf>,
+) -> Result<()> {
+    let config = Config::new(config_source).context("Could not parse config")?;
+    let auth_token =
+        config.admin_api.auth_token.as_deref().ok_or_else(|| {
+            anyhow!("the Admin API is disabled, as admin_api.auth_token is not set")
+        })?;
+    let admin_url = admin_url.unwrap_or_else(|| {
+        // An agent listening on all interfaces is reached on the loopback one
+        let mut bind_address = config.admin_api.bind_address;
+        if bind_address.ip().is_unspecified() {
+            bind_address.set_ip(Ipv4Addr::LOCALHOST.into());
+        }
+        format!("http://{}", bind_address)
+    });
+
+    let dump = serde_json::to_string_pretty(&dump::fetch(&admin_url, auth_token).await?)?;
+    match output {
+        Some(output) => std::fs::write(&output, dump)
+            .with_context(|| format!("writing the dump to {}", output.display()))?,
+        None => println!("{}", dump),
+    }
+    Ok(())
+}
+
+async fn diff_snapshots(
+    left_url: &str,
+    right_url: &str,
+    token: Option<&str>,
+    slot_tolerance: u64,
+    json: bool,
+) -> Result<()> {
+    let (left, right) = tokio::try_join!(
+        snapshot_diff::fetch(left_url, token),
+        snapshot_diff::fetch(right_url, token)
+    )?;
+    let diff = SnapshotDiff::new(&left, &right, slot_tolerance);
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&diff)?);
+    } else {
+        print!("{}", diff);
+    }
+
+    if !diff.is_empty() {
+        return Err(anyhow!("the snapshots differ"));
+    }
+    Ok(())
+}
+
+async fn probe_rpc(target: &rpc_probe::Target, json: bool) -> Result<()> {
+    let report = rpc_probe::probe(target).await;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print!("{}", report);
+    }
+
+    if report.has_errors() {
+        return Err(anyhow!("the endpoints failed the probe"));
+    }
     Ok(())
 }
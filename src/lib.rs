@@ -2,7 +2,6 @@
 // recursion limit compilation errors return for html!() calls.
 #![recursion_limit = "256"]
 #[macro_use]
-extern crate slog;
-extern crate slog_term;
+extern crate tracing;
 
 pub mod agent;
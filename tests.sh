@@ -3,6 +3,9 @@
 # Run Rust unit tests
 cargo test --workspace
 
+# Run Rust end-to-end tests against solana-test-validator
+cargo test --features test-support --test end_to_end
+
 # Run Python integration tests
 cd integration-tests
 poetry install
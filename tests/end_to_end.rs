@@ -0,0 +1,68 @@
+// End-to-end tests of the agent against solana-test-validator, run with
+// `cargo test --features test-support --test end_to_end`.
+#![cfg(feature = "test-support")]
+
+use {
+    pyth_agent::agent::test_support::{
+        Config,
+        TestCluster,
+    },
+    std::time::Duration,
+    tokio::time::{
+        self,
+        Instant,
+    },
+};
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_published_price_is_read_back() {
+    let mut cluster = TestCluster::start(Config::default()).await.unwrap();
+    cluster.spawn_agent().await.unwrap();
+    let mut client = cluster.client().await.unwrap();
+    let price_key = cluster.price_keys()[0];
+
+    // Submit the price until it lands on-chain, as the first updates are
+    // dropped until the Oracle has read the publisher's permissions
+    let deadline = Instant::now() + Duration::from_secs(60);
+    loop {
+        client
+            .update_price(&price_key, 42_000, 100, "trading")
+            .await
+            .unwrap();
+        if cluster
+            .wait_for_price(&price_key, 42_000, Duration::from_secs(2))
+            .await
+            .is_ok()
+        {
+            break;
+        }
+        assert!(Instant::now() < deadline, "price not published in time");
+    }
+
+    let price_account = cluster.get_price_account(&price_key).await.unwrap();
+    let component = price_account
+        .comp
+        .iter()
+        .find(|component| component.publisher == cluster.publish_key())
+        .unwrap();
+    assert_eq!(component.latest.conf, 100);
+
+    // The Oracle reads the published price back, and the agent serves it
+    let deadline = Instant::now() + Duration::from_secs(30);
+    loop {
+        let products = client.get_all_products().await.unwrap();
+        let read_back = products
+            .iter()
+            .flat_map(|product| &product.price_accounts)
+            .filter(|price| price.account == price_key.to_string())
+            .flat_map(|price| &price.publisher_accounts)
+            .any(|publisher| {
+                publisher.account == cluster.publish_key().to_string() && publisher.price == 42_000
+            });
+        if read_back {
+            break;
+        }
+        assert!(Instant::now() < deadline, "price not read back in time");
+        time::sleep(Duration::from_millis(500)).await;
+    }
+}